@@ -0,0 +1,126 @@
+#ifndef STARLIGHT_H
+#define STARLIGHT_H
+
+/* Generated by cbindgen from crates/starlight-capi/src/lib.rs, do not edit. */
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+typedef enum SlStatus {
+  SL_STATUS_OK = 0,
+  // JS code threw, the exception is stored in the context.
+  SL_STATUS_EXCEPTION = 1,
+  // A required pointer was NULL or a string was not valid UTF-8.
+  SL_STATUS_INVALID_ARGUMENT = 2,
+} SlStatus;
+
+// Result of `typeof`, with `null` and functions reported separately.
+typedef enum SlType {
+  SL_TYPE_UNDEFINED = 0,
+  SL_TYPE_NULL = 1,
+  SL_TYPE_BOOLEAN = 2,
+  SL_TYPE_NUMBER = 3,
+  SL_TYPE_STRING = 4,
+  SL_TYPE_SYMBOL = 5,
+  SL_TYPE_OBJECT = 6,
+  SL_TYPE_FUNCTION = 7,
+} SlType;
+
+// Runtime owning the heap shared by its contexts.
+typedef struct SlRuntime SlRuntime;
+
+// Realm with its own global object.
+typedef struct SlContext SlContext;
+
+// Rooted JS value.
+typedef struct SlValue SlValue;
+
+// Native function registered with `sl_new_function`. `this_value` and `args` are valid only during the call; the
+// function stores the return value or thrown exception in `result` and returns `SL_STATUS_OK` or
+// `SL_STATUS_EXCEPTION`. A NULL `result` means `undefined`, or the exception pending in `ctx` for
+// `SL_STATUS_EXCEPTION`.
+typedef SlStatus (*SlNativeFunction)(SlContext *ctx,
+                                     void *data,
+                                     const SlValue *this_value,
+                                     const SlValue *const *args,
+                                     uintptr_t argc,
+                                     SlValue **result);
+
+SlRuntime *sl_runtime_new(void);
+
+// Destroy the runtime. Contexts and values created from it must be freed before.
+void sl_runtime_free(SlRuntime *rt);
+
+SlContext *sl_context_new(SlRuntime *rt);
+
+// Destroy a context returned by `sl_context_new`. Contexts passed to native functions are owned by the engine.
+void sl_context_free(SlContext *ctx);
+
+// Evaluate `source` as a script. `file_name` may be NULL. On success the completion value is stored in `result`.
+SlStatus sl_eval(SlContext *ctx, const char *source, const char *file_name, SlValue **result);
+
+// Take the exception thrown by the last failed call, NULL when there is none.
+SlValue *sl_take_exception(SlContext *ctx);
+
+// Stack trace of the last uncaught exception, free with `sl_string_free`. Empty when there was no exception.
+char *sl_take_stacktrace(SlContext *ctx);
+
+SlValue *sl_global_object(SlContext *ctx);
+
+SlValue *sl_new_undefined(SlContext *ctx);
+
+SlValue *sl_new_null(SlContext *ctx);
+
+SlValue *sl_new_boolean(SlContext *ctx, bool value);
+
+SlValue *sl_new_number(SlContext *ctx, double value);
+
+// Create a string from UTF-8 `value`. Returns NULL when `value` is not valid UTF-8.
+SlValue *sl_new_string(SlContext *ctx, const char *value);
+
+SlValue *sl_new_object(SlContext *ctx);
+
+// Create an `Error` object, e.g. to throw it from a native function.
+SlValue *sl_new_error(SlContext *ctx, const char *message);
+
+// Create a function calling `callback` with `data`. `data` must stay valid as long as the function may be called.
+SlValue *sl_new_function(SlContext *ctx,
+                         const char *name,
+                         SlNativeFunction callback,
+                         void *data,
+                         uint32_t argc);
+
+// Call `function` with `argc` arguments. NULL `this_value` means `undefined`.
+SlStatus sl_call(SlContext *ctx,
+                 const SlValue *function,
+                 const SlValue *this_value,
+                 const SlValue *const *args,
+                 uintptr_t argc,
+                 SlValue **result);
+
+// Read property `name` of `object`, converting primitives to objects first.
+SlStatus sl_get_property(SlContext *ctx, const SlValue *object, const char *name, SlValue **result);
+
+SlStatus sl_set_property(SlContext *ctx,
+                         const SlValue *object,
+                         const char *name,
+                         const SlValue *value);
+
+SlType sl_value_type(const SlValue *value);
+
+// `ToBoolean`, never throws.
+bool sl_value_to_boolean(const SlValue *value);
+
+// `ToNumber`, may call `valueOf` of objects.
+SlStatus sl_value_to_number(SlContext *ctx, const SlValue *value, double *result);
+
+// `ToString` as a NUL terminated UTF-8 string, free it with `sl_string_free`. Interior NUL characters are dropped.
+SlStatus sl_value_to_string(SlContext *ctx, const SlValue *value, char **result);
+
+void sl_string_free(char *string);
+
+// Release the value, it may be collected afterwards.
+void sl_value_free(SlValue *value);
+
+#endif /* STARLIGHT_H */
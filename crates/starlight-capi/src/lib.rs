@@ -0,0 +1,615 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+//! C API of starlight, see `include/starlight.h` generated by the build script.
+//!
+//! All engine objects are exposed as opaque handles:
+//! - `SlRuntime` owns the heap and must outlive everything created from it.
+//! - `SlContext` is a realm with its own global object.
+//! - `SlValue` roots one JS value until it is released with `sl_value_free`.
+//!
+//! Functions that run JS code return [SlStatus]. On `SL_STATUS_EXCEPTION` the thrown value is kept by the context
+//! and can be taken with `sl_take_exception`.
+//!
+//! ```c
+//! SlRuntime *rt = sl_runtime_new();
+//! SlContext *ctx = sl_context_new(rt);
+//! SlValue *result;
+//! if (sl_eval(ctx, "1 + 2", "main.js", &result) == SL_STATUS_OK) {
+//!     double number;
+//!     sl_value_to_number(ctx, result, &number);
+//!     sl_value_free(result);
+//! }
+//! sl_context_free(ctx);
+//! sl_runtime_free(rt);
+//! ```
+#![allow(clippy::missing_safety_doc)]
+use starlight::{
+    prelude::*,
+    vm::{context::Context, PersistentRooted, VirtualMachineRef},
+};
+use std::{
+    ffi::{CStr, CString},
+    os::raw::{c_char, c_void},
+    ptr,
+};
+
+/// Runtime owning the heap shared by its contexts.
+pub struct SlRuntime {
+    vm: VirtualMachineRef,
+}
+
+/// Realm with its own global object.
+pub struct SlContext {
+    ctx: GcPointer<Context>,
+    exception: Option<PersistentRooted>,
+}
+
+/// Rooted JS value.
+pub struct SlValue {
+    value: PersistentRooted,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SlStatus {
+    Ok = 0,
+    /// JS code threw, the exception is stored in the context.
+    Exception = 1,
+    /// A required pointer was NULL or a string was not valid UTF-8.
+    InvalidArgument = 2,
+}
+
+/// Result of `typeof`, with `null` and functions reported separately.
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SlType {
+    Undefined = 0,
+    Null = 1,
+    Boolean = 2,
+    Number = 3,
+    String = 4,
+    Symbol = 5,
+    Object = 6,
+    Function = 7,
+}
+
+/// Native function registered with `sl_new_function`. `this_value` and `args` are valid only during the call; the
+/// function stores the return value or thrown exception in `result` and returns `SL_STATUS_OK` or
+/// `SL_STATUS_EXCEPTION`. A NULL `result` means `undefined`, or the exception pending in `ctx` for
+/// `SL_STATUS_EXCEPTION`.
+pub type SlNativeFunction = extern "C" fn(
+    ctx: *mut SlContext,
+    data: *mut c_void,
+    this_value: *const SlValue,
+    args: *const *const SlValue,
+    argc: usize,
+    result: *mut *mut SlValue,
+) -> SlStatus;
+
+fn root(ctx: GcPointer<Context>, value: JsValue) -> SlValue {
+    SlValue {
+        value: ctx.vm().add_persistent_root(value),
+    }
+}
+
+fn new_handle(ctx: GcPointer<Context>, value: JsValue) -> *mut SlValue {
+    Box::into_raw(Box::new(root(ctx, value)))
+}
+
+unsafe fn str_arg<'a>(s: *const c_char) -> Option<&'a str> {
+    if s.is_null() {
+        return None;
+    }
+    CStr::from_ptr(s).to_str().ok()
+}
+
+unsafe fn value_arg(value: *const SlValue) -> Option<JsValue> {
+    value.as_ref().map(|value| value.value.get_value())
+}
+
+/// Store `result` in `out` or the exception in `ctx`.
+unsafe fn complete(
+    ctx: &mut SlContext,
+    result: Result<JsValue, JsValue>,
+    out: *mut *mut SlValue,
+) -> SlStatus {
+    match result {
+        Ok(value) => {
+            if !out.is_null() {
+                *out = new_handle(ctx.ctx, value);
+            }
+            SlStatus::Ok
+        }
+        Err(exception) => {
+            ctx.exception = Some(ctx.ctx.vm().add_persistent_root(exception));
+            SlStatus::Exception
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn sl_runtime_new() -> *mut SlRuntime {
+    let vm = Platform::new_runtime(Options::default(), None);
+    Box::into_raw(Box::new(SlRuntime { vm }))
+}
+
+/// Destroy the runtime. Contexts and values created from it must be freed before.
+#[no_mangle]
+pub unsafe extern "C" fn sl_runtime_free(rt: *mut SlRuntime) {
+    if !rt.is_null() {
+        Box::from_raw(rt).vm.dispose();
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sl_context_new(rt: *mut SlRuntime) -> *mut SlContext {
+    match rt.as_mut() {
+        Some(rt) => Box::into_raw(Box::new(SlContext {
+            ctx: rt.vm.new_context(),
+            exception: None,
+        })),
+        None => ptr::null_mut(),
+    }
+}
+
+/// Destroy a context returned by `sl_context_new`. Contexts passed to native functions are owned by the engine.
+#[no_mangle]
+pub unsafe extern "C" fn sl_context_free(ctx: *mut SlContext) {
+    if !ctx.is_null() {
+        let ctx = Box::from_raw(ctx);
+        let context = ctx.ctx;
+        drop(ctx);
+        context.vm().remove_context(context);
+    }
+}
+
+/// Evaluate `source` as a script. `file_name` may be NULL. On success the completion value is stored in `result`.
+#[no_mangle]
+pub unsafe extern "C" fn sl_eval(
+    ctx: *mut SlContext,
+    source: *const c_char,
+    file_name: *const c_char,
+    result: *mut *mut SlValue,
+) -> SlStatus {
+    let (ctx, source) = match (ctx.as_mut(), str_arg(source)) {
+        (Some(ctx), Some(source)) => (ctx, source),
+        _ => return SlStatus::InvalidArgument,
+    };
+    let file_name = if file_name.is_null() {
+        None
+    } else {
+        match str_arg(file_name) {
+            Some(file_name) => Some(file_name),
+            None => return SlStatus::InvalidArgument,
+        }
+    };
+    let value = ctx.ctx.eval_internal(file_name, false, source, false);
+    complete(ctx, value, result)
+}
+
+/// Take the exception thrown by the last failed call, NULL when there is none.
+#[no_mangle]
+pub unsafe extern "C" fn sl_take_exception(ctx: *mut SlContext) -> *mut SlValue {
+    match ctx.as_mut().and_then(|ctx| ctx.exception.take()) {
+        Some(value) => Box::into_raw(Box::new(SlValue { value })),
+        None => ptr::null_mut(),
+    }
+}
+
+/// Stack trace of the last uncaught exception, free with `sl_string_free`. Empty when there was no exception.
+#[no_mangle]
+pub unsafe extern "C" fn sl_take_stacktrace(ctx: *mut SlContext) -> *mut c_char {
+    match ctx.as_mut() {
+        Some(ctx) => new_string(ctx.ctx.take_stacktrace()),
+        None => ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sl_global_object(ctx: *mut SlContext) -> *mut SlValue {
+    match ctx.as_mut() {
+        Some(ctx) => new_handle(ctx.ctx, JsValue::new(ctx.ctx.global_object())),
+        None => ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sl_new_undefined(ctx: *mut SlContext) -> *mut SlValue {
+    match ctx.as_mut() {
+        Some(ctx) => new_handle(ctx.ctx, JsValue::encode_undefined_value()),
+        None => ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sl_new_null(ctx: *mut SlContext) -> *mut SlValue {
+    match ctx.as_mut() {
+        Some(ctx) => new_handle(ctx.ctx, JsValue::encode_null_value()),
+        None => ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sl_new_boolean(ctx: *mut SlContext, value: bool) -> *mut SlValue {
+    match ctx.as_mut() {
+        Some(ctx) => new_handle(ctx.ctx, JsValue::new(value)),
+        None => ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sl_new_number(ctx: *mut SlContext, value: f64) -> *mut SlValue {
+    match ctx.as_mut() {
+        Some(ctx) => new_handle(ctx.ctx, JsValue::new(value)),
+        None => ptr::null_mut(),
+    }
+}
+
+/// Create a string from UTF-8 `value`. Returns NULL when `value` is not valid UTF-8.
+#[no_mangle]
+pub unsafe extern "C" fn sl_new_string(ctx: *mut SlContext, value: *const c_char) -> *mut SlValue {
+    match (ctx.as_mut(), str_arg(value)) {
+        (Some(ctx), Some(value)) => {
+            new_handle(ctx.ctx, JsValue::new(JsString::new(ctx.ctx, value)))
+        }
+        _ => ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sl_new_object(ctx: *mut SlContext) -> *mut SlValue {
+    match ctx.as_mut() {
+        Some(ctx) => new_handle(ctx.ctx, JsValue::new(JsObject::new_empty(ctx.ctx))),
+        None => ptr::null_mut(),
+    }
+}
+
+/// Create an `Error` object, e.g. to throw it from a native function.
+#[no_mangle]
+pub unsafe extern "C" fn sl_new_error(ctx: *mut SlContext, message: *const c_char) -> *mut SlValue {
+    match (ctx.as_mut(), str_arg(message)) {
+        (Some(ctx), Some(message)) => {
+            let message = JsString::new(ctx.ctx, message);
+            new_handle(ctx.ctx, JsValue::new(JsError::new(ctx.ctx, message, None)))
+        }
+        _ => ptr::null_mut(),
+    }
+}
+
+/// Create a function calling `callback` with `data`. `data` must stay valid as long as the function may be called.
+#[no_mangle]
+pub unsafe extern "C" fn sl_new_function(
+    ctx: *mut SlContext,
+    name: *const c_char,
+    callback: SlNativeFunction,
+    data: *mut c_void,
+    argc: u32,
+) -> *mut SlValue {
+    let (ctx, name) = match (ctx.as_mut(), str_arg(name)) {
+        (Some(ctx), Some(name)) => (ctx, name),
+        _ => return ptr::null_mut(),
+    };
+    let data = data as usize;
+    let function = JsClosureFunction::new(
+        ctx.ctx,
+        name.intern(),
+        move |ctx, args| {
+            let mut handle = SlContext {
+                ctx,
+                exception: None,
+            };
+            let this = root(ctx, args.this);
+            let values = (0..args.size())
+                .map(|i| root(ctx, args.at(i)))
+                .collect::<Vec<_>>();
+            let pointers = values
+                .iter()
+                .map(|value| value as *const SlValue)
+                .collect::<Vec<_>>();
+            let mut result = ptr::null_mut();
+            let status = callback(
+                &mut handle,
+                data as *mut c_void,
+                &this,
+                pointers.as_ptr(),
+                pointers.len(),
+                &mut result,
+            );
+            let value = if !result.is_null() {
+                Box::from_raw(result).value.get_value()
+            } else if status == SlStatus::Exception {
+                handle
+                    .exception
+                    .take()
+                    .map(|exception| exception.get_value())
+                    .unwrap_or_else(JsValue::encode_undefined_value)
+            } else {
+                JsValue::encode_undefined_value()
+            };
+            match status {
+                SlStatus::Ok => Ok(value),
+                _ => Err(value),
+            }
+        },
+        argc,
+    );
+    new_handle(ctx.ctx, JsValue::new(function))
+}
+
+/// Call `function` with `argc` arguments. NULL `this_value` means `undefined`.
+#[no_mangle]
+pub unsafe extern "C" fn sl_call(
+    ctx: *mut SlContext,
+    function: *const SlValue,
+    this_value: *const SlValue,
+    args: *const *const SlValue,
+    argc: usize,
+    result: *mut *mut SlValue,
+) -> SlStatus {
+    let (ctx, function) = match (ctx.as_mut(), value_arg(function)) {
+        (Some(ctx), Some(function)) => (ctx, function),
+        _ => return SlStatus::InvalidArgument,
+    };
+    if !function.is_callable() || (args.is_null() && argc != 0) {
+        return SlStatus::InvalidArgument;
+    }
+    let this = value_arg(this_value).unwrap_or_else(JsValue::encode_undefined_value);
+    let mut values = Vec::with_capacity(argc);
+    for i in 0..argc {
+        match value_arg(*args.add(i)) {
+            Some(value) => values.push(value),
+            None => return SlStatus::InvalidArgument,
+        }
+    }
+    let mut arguments = Arguments::new(this, &mut values);
+    let value = function
+        .get_jsobject()
+        .as_function_mut()
+        .call(ctx.ctx, &mut arguments, function);
+    complete(ctx, value, result)
+}
+
+/// Read property `name` of `object`, converting primitives to objects first.
+#[no_mangle]
+pub unsafe extern "C" fn sl_get_property(
+    ctx: *mut SlContext,
+    object: *const SlValue,
+    name: *const c_char,
+    result: *mut *mut SlValue,
+) -> SlStatus {
+    let (ctx, object, name) = match (ctx.as_mut(), value_arg(object), str_arg(name)) {
+        (Some(ctx), Some(object), Some(name)) => (ctx, object, name),
+        _ => return SlStatus::InvalidArgument,
+    };
+    let js_ctx = ctx.ctx;
+    let value = object
+        .to_object(js_ctx)
+        .and_then(|mut object| object.get(js_ctx, name));
+    complete(ctx, value, result)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sl_set_property(
+    ctx: *mut SlContext,
+    object: *const SlValue,
+    name: *const c_char,
+    value: *const SlValue,
+) -> SlStatus {
+    let (ctx, object, name, value) = match (
+        ctx.as_mut(),
+        value_arg(object),
+        str_arg(name),
+        value_arg(value),
+    ) {
+        (Some(ctx), Some(object), Some(name), Some(value)) => (ctx, object, name, value),
+        _ => return SlStatus::InvalidArgument,
+    };
+    let js_ctx = ctx.ctx;
+    let result = object
+        .to_object(js_ctx)
+        .and_then(|mut object| object.put(js_ctx, name, value, true))
+        .map(|_| JsValue::encode_undefined_value());
+    complete(ctx, result, ptr::null_mut())
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sl_value_type(value: *const SlValue) -> SlType {
+    let value = match value_arg(value) {
+        Some(value) => value,
+        None => return SlType::Undefined,
+    };
+    if value.is_null() {
+        return SlType::Null;
+    }
+    match value.type_of() {
+        "function" => SlType::Function,
+        "object" => SlType::Object,
+        "number" => SlType::Number,
+        "string" => SlType::String,
+        "boolean" => SlType::Boolean,
+        "symbol" => SlType::Symbol,
+        _ => SlType::Undefined,
+    }
+}
+
+/// `ToBoolean`, never throws.
+#[no_mangle]
+pub unsafe extern "C" fn sl_value_to_boolean(value: *const SlValue) -> bool {
+    value_arg(value).map_or(false, |value| value.to_boolean())
+}
+
+/// `ToNumber`, may call `valueOf` of objects.
+#[no_mangle]
+pub unsafe extern "C" fn sl_value_to_number(
+    ctx: *mut SlContext,
+    value: *const SlValue,
+    result: *mut f64,
+) -> SlStatus {
+    let (ctx, value) = match (ctx.as_mut(), value_arg(value)) {
+        (Some(ctx), Some(value)) if !result.is_null() => (ctx, value),
+        _ => return SlStatus::InvalidArgument,
+    };
+    match value.to_number(ctx.ctx) {
+        Ok(number) => {
+            *result = number;
+            SlStatus::Ok
+        }
+        Err(exception) => complete(ctx, Err(exception), ptr::null_mut()),
+    }
+}
+
+/// `ToString` as a NUL terminated UTF-8 string, free it with `sl_string_free`. Interior NUL characters are dropped.
+#[no_mangle]
+pub unsafe extern "C" fn sl_value_to_string(
+    ctx: *mut SlContext,
+    value: *const SlValue,
+    result: *mut *mut c_char,
+) -> SlStatus {
+    let (ctx, value) = match (ctx.as_mut(), value_arg(value)) {
+        (Some(ctx), Some(value)) if !result.is_null() => (ctx, value),
+        _ => return SlStatus::InvalidArgument,
+    };
+    match value.to_string(ctx.ctx) {
+        Ok(string) => {
+            *result = new_string(string);
+            SlStatus::Ok
+        }
+        Err(exception) => complete(ctx, Err(exception), ptr::null_mut()),
+    }
+}
+
+fn new_string(mut string: String) -> *mut c_char {
+    string.retain(|c| c != '\0');
+    CString::new(string).unwrap().into_raw()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sl_string_free(string: *mut c_char) {
+    if !string.is_null() {
+        drop(CString::from_raw(string));
+    }
+}
+
+/// Release the value, it may be collected afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn sl_value_free(value: *mut SlValue) {
+    if !value.is_null() {
+        drop(Box::from_raw(value));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    extern "C" fn add(
+        ctx: *mut SlContext,
+        data: *mut c_void,
+        _this_value: *const SlValue,
+        args: *const *const SlValue,
+        argc: usize,
+        result: *mut *mut SlValue,
+    ) -> SlStatus {
+        unsafe {
+            *(data as *mut u32) += 1;
+            let mut sum = 0.0;
+            for i in 0..argc {
+                let mut number = 0.0;
+                if sl_value_to_number(ctx, *args.add(i), &mut number) != SlStatus::Ok {
+                    return SlStatus::Exception;
+                }
+                sum += number;
+            }
+            if sum < 0.0 {
+                let message = CString::new("negative sum").unwrap();
+                *result = sl_new_error(ctx, message.as_ptr());
+                return SlStatus::Exception;
+            }
+            *result = sl_new_number(ctx, sum);
+            SlStatus::Ok
+        }
+    }
+
+    unsafe fn eval(ctx: *mut SlContext, source: &str) -> (SlStatus, *mut SlValue) {
+        let source = CString::new(source).unwrap();
+        let file = CString::new("test.js").unwrap();
+        let mut result = ptr::null_mut();
+        let status = sl_eval(ctx, source.as_ptr(), file.as_ptr(), &mut result);
+        (status, result)
+    }
+
+    unsafe fn to_string(ctx: *mut SlContext, value: *const SlValue) -> String {
+        let mut string = ptr::null_mut();
+        assert_eq!(sl_value_to_string(ctx, value, &mut string), SlStatus::Ok);
+        let result = CStr::from_ptr(string).to_str().unwrap().to_owned();
+        sl_string_free(string);
+        result
+    }
+
+    #[test]
+    fn test_capi() {
+        unsafe {
+            let rt = sl_runtime_new();
+            let ctx = sl_context_new(rt);
+
+            let (status, value) = eval(ctx, "'a' + 1");
+            assert_eq!(status, SlStatus::Ok);
+            assert_eq!(sl_value_type(value), SlType::String);
+            assert_eq!(to_string(ctx, value), "a1");
+            sl_value_free(value);
+
+            let mut calls = 0u32;
+            let name = CString::new("add").unwrap();
+            let function = sl_new_function(ctx, name.as_ptr(), add, &mut calls as *mut u32 as _, 2);
+            let global = sl_global_object(ctx);
+            assert_eq!(
+                sl_set_property(ctx, global, name.as_ptr(), function),
+                SlStatus::Ok
+            );
+
+            let (status, value) = eval(ctx, "add(1, 2) + add(3, 4)");
+            assert_eq!(status, SlStatus::Ok);
+            let mut number = 0.0;
+            assert_eq!(sl_value_to_number(ctx, value, &mut number), SlStatus::Ok);
+            assert_eq!(number, 10.0);
+            assert_eq!(calls, 2);
+            sl_value_free(value);
+
+            let (status, _) = eval(ctx, "try { add(-1) } catch (e) { e.message }");
+            assert_eq!(status, SlStatus::Ok);
+            let (status, _) = eval(ctx, "add(-1)");
+            assert_eq!(status, SlStatus::Exception);
+            let exception = sl_take_exception(ctx);
+            assert_eq!(to_string(ctx, exception), "Error: negative sum");
+            assert!(sl_take_exception(ctx).is_null());
+            sl_value_free(exception);
+
+            let one = sl_new_number(ctx, 1.0);
+            let mut value = ptr::null_mut();
+            let args = [one as *const SlValue, one as *const SlValue];
+            assert_eq!(
+                sl_call(
+                    ctx,
+                    function,
+                    ptr::null(),
+                    args.as_ptr(),
+                    args.len(),
+                    &mut value
+                ),
+                SlStatus::Ok
+            );
+            assert_eq!(to_string(ctx, value), "2");
+            assert_eq!(
+                sl_call(ctx, one, ptr::null(), ptr::null(), 0, &mut value),
+                SlStatus::InvalidArgument
+            );
+
+            for value in [value, one, function, global].iter() {
+                sl_value_free(*value);
+            }
+            sl_context_free(ctx);
+            sl_runtime_free(rt);
+        }
+    }
+}
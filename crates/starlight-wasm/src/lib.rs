@@ -0,0 +1,121 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+//! wasm-bindgen bindings for running starlight inside a browser or an edge sandbox.
+//!
+//! Built without the `mmap` and `threads` features of starlight, so the interpreter stack lives on the heap and
+//! the sampling profiler is not available.
+//!
+//! The conservative stack scan of the GC can not see wasm locals: they live in registers of the wasm engine that
+//! are neither addressable nor spilled to the shadow stack. Collections are therefore deferred while starlight
+//! code runs and only happen between two calls of [Runtime::eval], when no JS value is held outside of the
+//! runtime. A single evaluation that allocates more than the instance memory runs out of memory instead of
+//! collecting.
+//!
+//! ```js
+//! import init, { Runtime } from "./starlight_wasm.js";
+//! await init();
+//! const rt = new Runtime();
+//! rt.eval("1 + 2"); // "3"
+//! rt.free();
+//! ```
+use starlight::{
+    prelude::*,
+    vm::{context::Context, VirtualMachineRef},
+};
+use std::{cell::RefCell, collections::VecDeque};
+use wasm_bindgen::prelude::{wasm_bindgen, JsValue as HostValue};
+
+type Job = Box<dyn FnOnce(GcPointer<Context>)>;
+
+thread_local! {
+    static JOBS: RefCell<VecDeque<Job>> = RefCell::new(VecDeque::new());
+}
+
+fn schedule(job: Job) {
+    JOBS.with(|jobs| jobs.borrow_mut().push_back(job));
+}
+
+fn run_jobs(ctx: GcPointer<Context>) {
+    while let Some(job) = JOBS.with(|jobs| jobs.borrow_mut().pop_front()) {
+        job(ctx);
+    }
+}
+
+/// Runtime with a single context. Promise jobs are run after every evaluation.
+#[wasm_bindgen]
+pub struct Runtime {
+    vm: VirtualMachineRef,
+    ctx: GcPointer<Context>,
+}
+
+#[wasm_bindgen]
+impl Runtime {
+    #[wasm_bindgen(constructor)]
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Runtime {
+        let mut vm = Platform::new_runtime(Options::default(), None)
+            .with_async_scheduler(Box::new(schedule));
+        let defer = vm.heap().defer();
+        let ctx = vm.new_context();
+        drop(defer);
+        Runtime { vm, ctx }
+    }
+
+    /// Evaluate `source` as a script and return its completion value converted to a string. A JS exception is
+    /// thrown to the host as the string of the thrown value.
+    pub fn eval(&mut self, source: &str) -> Result<String, HostValue> {
+        let mut ctx = self.ctx;
+        let defer = ctx.heap().defer();
+        let result = ctx.eval_internal(None, false, source, false);
+        run_jobs(ctx);
+        let result = result
+            .and_then(|value| value.to_string(ctx))
+            .map_err(|exception| match exception.to_string(ctx) {
+                Ok(message) => HostValue::from_str(&message),
+                Err(_) => HostValue::from_str("uncaught exception"),
+            });
+        drop(defer);
+        // safe point: the result is converted and the job queue is empty, only the context refers to cells.
+        ctx.heap().collect_if_necessary();
+        result
+    }
+}
+
+impl Drop for Runtime {
+    fn drop(&mut self) {
+        self.vm.remove_context(self.ctx);
+        unsafe { self.vm.dispose() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_eval() {
+        let mut rt = Runtime::new();
+        assert_eq!(rt.eval("var x = 40; x + 2").ok(), Some("42".to_string()));
+        assert_eq!(
+            rt.eval("var log = []; Promise.resolve(1).then(v => log.push(v)); 0")
+                .ok(),
+            Some("0".to_string())
+        );
+        assert_eq!(rt.eval("log.join()").ok(), Some("1".to_string()));
+    }
+
+    #[test]
+    fn test_collect_between_evals() {
+        let mut rt = Runtime::new();
+        for _ in 0..4 {
+            assert_eq!(
+                rt.eval(
+                    "var a = []; for (var i = 0; i < 100000; i++) a.push({ i: i }); a[99999].i"
+                )
+                .ok(),
+                Some("99999".to_string())
+            );
+        }
+    }
+}
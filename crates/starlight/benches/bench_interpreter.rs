@@ -0,0 +1,39 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use starlight::{prelude::*, vm::context::Context};
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
+
+/// Dispatch-heavy scripts: short opcodes in tight loops where interpreter dispatch dominates.
+const SCRIPTS: &[(&str, &str)] = &[
+    (
+        "arith loop",
+        "var s = 0; for (var i = 0; i < 100000; i++) { s = (s + i * 3) | 0; }",
+    ),
+    (
+        "property loop",
+        "var o = { x: 1, y: 2 }; for (var i = 0; i < 100000; i++) { o.x = o.y + i; }",
+    ),
+    (
+        "array loop",
+        "var a = [1, 2, 3, 4, 5, 6, 7, 8]; var s = 0; for (var i = 0; i < 100000; i++) { s += a[i & 7]; }",
+    ),
+    (
+        "calls",
+        "function f(a, b) { return a + b; } var s = 0; for (var i = 0; i < 100000; i++) { s = f(s, i); }",
+    ),
+    (
+        "object as map",
+        "var m = {}; for (var i = 0; i < 100000; i++) { m['k' + (i & 63)] = i; if (i & 1) delete m['k' + ((i >> 1) & 63)]; }",
+    ),
+];
+
+pub fn criterion_benchmark(c: &mut Criterion) {
+    Platform::initialize();
+    let mut vm = VirtualMachine::new(Options::default(), None);
+    let mut ctx = Context::new(&mut vm);
+    for (name, script) in SCRIPTS {
+        c.bench_function(name, |b| {
+            b.iter(|| ctx.eval(script).unwrap_or_else(|_| panic!("{} failed", name)));
+        });
+    }
+}
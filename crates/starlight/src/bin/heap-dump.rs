@@ -0,0 +1,60 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! Runs a script and prints every reachable heap cell with its type, size and outgoing references.
+//!
+//! Usage: `heap-dump <script.js> [--summary | --check-natives]`
+//!
+//! `--check-natives` lists native references that a snapshot of the heap could not write instead.
+use starlight::{prelude::*, vm::context::Context};
+use std::io::{stdout, BufWriter, Write};
+
+fn main() {
+    Platform::initialize();
+    let mut args = std::env::args().skip(1);
+    let path = match args.next() {
+        Some(path) => path,
+        None => {
+            eprintln!("usage: heap-dump <script.js> [--summary | --check-natives]");
+            std::process::exit(1);
+        }
+    };
+    let flags = args.collect::<Vec<_>>();
+    let summary = flags.iter().any(|arg| arg == "--summary");
+    let check_natives = flags.iter().any(|arg| arg == "--check-natives");
+    let source = match std::fs::read_to_string(&path) {
+        Ok(source) => source,
+        Err(error) => {
+            eprintln!("Error while reading JS source: {}", error);
+            std::process::exit(1);
+        }
+    };
+
+    let mut vm = VirtualMachine::new(Options::default(), None);
+    let mut ctx = Context::new(&mut vm);
+    if let Err(e) = ctx.eval_internal(Some(&path), false, &source, false) {
+        let str = match e.to_string(ctx) {
+            Ok(s) => s,
+            Err(_) => "<unknown error>".to_owned(),
+        };
+        eprintln!("Uncaught exception: {}", str);
+        std::process::exit(1);
+    }
+
+    let out = stdout();
+    let mut out = BufWriter::new(out.lock());
+    let result = if summary {
+        writeln!(out, "{}", ctx.memory_usage())
+    } else if check_natives {
+        ctx.unregistered_natives()
+            .iter()
+            .try_for_each(|native| writeln!(out, "{}", native))
+    } else {
+        ctx.take_heap_snapshot().dump(&mut out)
+    };
+    if let Err(error) = result.and_then(|_| out.flush()) {
+        eprintln!("Failed to write heap dump: {}", error);
+        std::process::exit(1);
+    }
+}
@@ -6,7 +6,7 @@ use starlight::vm::context::Context;
 use starlight::{letroot, prelude::*};
 use structopt::*;
 
-#[cfg(not(debug_assertions))]
+#[cfg(all(not(debug_assertions), not(target_arch = "wasm32")))]
 #[global_allocator]
 static GLOBAL: mimalloc::MiMalloc = mimalloc::MiMalloc;
 
@@ -16,13 +16,21 @@ fn main() {
 
     let mut vm = VirtualMachine::new(options, None);
 
-    #[cfg(all(target_pointer_width = "64", feature = "ffi"))]
-    if vm.options().enable_ffi {
-        vm.add_ffi();
-    }
-
     let mut ctx = Context::new(&mut vm);
 
+    #[cfg(feature = "inspector")]
+    if let Some(addr) = vm.options().inspect.clone() {
+        let server = starlight::inspector::InspectorServer::bind(&addr).and_then(|server| {
+            eprintln!("Debugger listening on {}", server.websocket_url()?);
+            server.attach(ctx)?;
+            Ok(())
+        });
+        if let Err(error) = server {
+            eprintln!("Failed to start inspector: {}", error);
+            std::process::exit(1);
+        }
+    }
+
     let string = std::fs::read_to_string(&vm.options().file);
     match string {
         Ok(source) => {
@@ -56,6 +64,10 @@ fn main() {
             let mut args = [JsValue::new(module_object)];
             let mut args = Arguments::new(JsValue::encode_object_value(global), &mut args);
 
+            #[cfg(feature = "threads")]
+            if vm.options().cpu_profile.is_some() {
+                vm.start_profiling(std::time::Duration::from_millis(1));
+            }
             let start = std::time::Instant::now();
             let f = function;
             match function
@@ -75,6 +87,19 @@ fn main() {
                     eprintln!("Stacktrace: \n{}", ctx.take_stacktrace());
                 }
             }
+            if let Some(path) = vm.options().cpu_profile.clone() {
+                let profile = vm.stop_profiling().unwrap();
+                let written = std::fs::File::create(&path).and_then(|mut file| {
+                    if path.extension().map_or(false, |ext| ext == "json") {
+                        profile.write_chrome_trace(&mut file)
+                    } else {
+                        profile.write_collapsed(&mut file)
+                    }
+                });
+                if let Err(error) = written {
+                    eprintln!("Failed to write CPU profile: {}", error);
+                }
+            }
         }
         Err(error) => {
             eprintln!("Error while reading JS source: {}", error);
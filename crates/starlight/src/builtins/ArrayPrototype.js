@@ -93,63 +93,6 @@ Array.prototype.includes = function includes(searchElement, fromIndex_) {
     return false;
 }
 
-Array.prototype.map = function map(callback, thisArg) {
-    "use strict";
-    var array = ___toObject(this, "Array.prototype.map requires that |this| not be null or undefined");
-
-    var length = array.length;
-    if (length >= 4294967295)
-        throw new RangeError("Out of memory for array elements.")
-
-    var result = new Array(length);
-    for (var i = 0; i < length; i += 1) {
-
-        if (!(i in array)) {
-            continue;
-        }
-
-        var mappedValue = callback.___call(thisArg, array[i], i, array);
-        result[i] = mappedValue;
-    }
-    return result;
-}
-
-Array.prototype.forEach = function forEach(callback, thisArg) {
-    "use strict";
-    var array = ___toObject(this, "Array.prototype.forEach requires that |this| not be null or undefined");
-
-    var length = ___toLength(array.length);
-    if (length >= 4294967295)
-        throw new RangeError("Out of memory for array elements.")
-
-    for (var i = 0; i < length; i++) {
-        if (i in array) {
-            callback.___call(thisArg, array[i], i, array);
-        }
-    }
-}
-
-Array.prototype.filter = function filter(callback, thisArg) {
-    "use strict";
-    var array = ___toObject(this, "Array.prototype.filter requires that |this| not be null or undefined");
-    var length = ___toLength(array.length);
-
-    var result = [];
-    var nextIndex = 0;
-    for (var i = 0; i < length; i++) {
-        if (!(i in array)) {
-            continue;
-        }
-
-        var current = array[i];
-        if (callback.___call(thisArg, current, i, array)) {
-            result[nextIndex] = current;
-            ++nextIndex;
-        }
-    }
-    return result;
-}
-
 Array.prototype.fill = function fill(value, start, end) {
     "use strict";
     var array = ___toObject(this, "Array.prototype.fill requires that |this| not be null or undefined");
@@ -578,31 +521,3 @@ let values = function values() {
 }
 Array.prototype.values = values;
 Array.prototype[Symbol.iterator] = values;
-Array.prototype.reduce = function (...args) {
-    'use strict';
-    let callback = args[0];
-    if (this == null) {
-        throw new TypeError('Array.prototype.reduce called on null or undefined');
-    }
-    if (typeof callback !== 'function') {
-        throw new TypeError(callback + ' is not a function');
-    }
-    var t = Object(this), len = t.length , k = 0, value;
-    if (args.length >= 2) {
-        value = args[1];
-    } else {
-        while (k < len && !(k in t)) {
-            k++;
-        }
-        if (k >= len) {
-            throw new TypeError('Reduce of empty array with no initial value');
-        }
-        value = t[k++];
-    }
-    for (; k < len; k++) {
-        if (k in t) {
-            value = callback(value, t[k], k, t);
-        }
-    }
-    return value;
-};
\ No newline at end of file
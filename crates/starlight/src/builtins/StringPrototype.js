@@ -5,6 +5,7 @@ let RegExpCtor = RegExp;
 let RegExpReplace = RegExp.prototype[Symbol.replace];
 let symReplace = Symbol.replace;
 let __replace = String.___replace;
+let __replaceAll = String.___replaceAll;
 String.prototype.match = function match(regexp) {
     "use strict";
     if (this === null | this === undefined) {
@@ -70,6 +71,24 @@ String.prototype.replace = function replace(search, replace) {
 
     return __replace.___call(this, search, replace);
 }
+String.prototype.replaceAll = function replaceAll(search, replace) {
+    "use strict";
+
+    if (this == undefined | this == null)
+        throw new TypeError("String.prototype.replaceAll requires that |this| not be null or undefined");
+
+    if (search !== undefined & search !== null) {
+        if (search instanceof RegExpCtor && !search.flags.includes("g"))
+            throw new TypeError("String.prototype.replaceAll argument must not be a non-global regular expression");
+
+        var replacer = search[symReplace];
+        if (replacer) {
+            return replacer.___call(search, this, replace);
+        }
+    }
+
+    return __replaceAll.___call(this, search, replace);
+}
 let split_sym = Symbol.split;
 let fastSplit = String.prototype.___splitFast;
 
@@ -3,11 +3,15 @@
  * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
 use crate::{
     gc::cell::{GcPointer, Trace, Visitor},
-    vm::{object::JsObject, structure::Structure, structure_chain::StructureChain},
+    vm::{
+        global::GlobalPropertyCell, object::JsObject, structure::Structure,
+        structure_chain::StructureChain,
+    },
 };
 
 pub mod block;
 pub mod opcodes;
+pub mod peephole;
 pub mod profile;
 
 #[derive(Clone, Copy, PartialEq, Eq)]
@@ -31,6 +35,9 @@ pub enum TypeFeedBack {
         offset: u32,
         structure_chain: Option<GcPointer<StructureChain>>,
     },
+    GlobalCell {
+        cell: GcPointer<GlobalPropertyCell>,
+    },
     None,
 }
 
@@ -57,6 +64,7 @@ impl Trace for TypeFeedBack {
                 old_structure.trace(visitor);
                 structure_chain.trace(visitor);
             }
+            Self::GlobalCell { cell } => cell.trace(visitor),
             _ => (),
         }
     }
@@ -235,7 +235,7 @@ pub enum Opcode {
     OP_SET_ENV,
     OP_GET_LOCAL,
     OP_SET_LOCAL,
-    OP_SET_GLOBAL,
+    OP_PUT_GLOBAL,
     OP_GET_GLOBAL,
     OP_DECL_LET,
     OP_DECL_CONST,
@@ -281,6 +281,86 @@ pub enum Opcode {
     OP_AWAIT,
     OP_NEWGENERATOR,
     OP_IS_OBJECT,
+    /// push frame slot
+    OP_GET_SLOT,
+    /// pop into frame slot
+    OP_SET_SLOT,
+
+    // Superinstructions produced by the peephole pass (see `bytecode::peephole`). Each of them overwrites only
+    // the opcode byte of the first instruction in the pair so operands of both instructions stay in place.
+    /// push_int + add
+    OP_PUSH_INT_ADD,
+    /// ge0gl + get_by_id
+    OP_GE0GL_GET_BY_ID,
+    /// less + jmp_if_false
+    OP_LESS_JMP_IF_FALSE,
+    /// lesseq + jmp_if_false
+    OP_LESSEQ_JMP_IF_FALSE,
+    /// greater + jmp_if_false
+    OP_GREATER_JMP_IF_FALSE,
+    /// greatereq + jmp_if_false
+    OP_GREATEREQ_JMP_IF_FALSE,
+
+    /// increment frame slot in place
+    OP_INC_SLOT,
+    /// decrement frame slot in place
+    OP_DEC_SLOT,
+    // Int32 variants of arithmetic written over `add`/`sub` by `jit::specialize` once profile saw only int32
+    // operands. Operand is the profile of the original instruction, on the first miss the opcode is rewritten
+    // back to the generic one.
+    /// add of two int32 values
+    OP_ADD_INT,
+    /// sub of two int32 values
+    OP_SUB_INT,
+    /// Call of identifier `eval`. Operands are argument count and index of compile-time scope of the call in
+    /// `CodeBlock::eval_scopes`. Performs direct eval when the callee is the builtin `eval`, otherwise behaves
+    /// as `call`.
+    OP_CALL_EVAL,
+    /// push value of `let`/`const` binding that was not initialized yet
+    OP_PUSH_EMPTY,
+    /// Throw `ReferenceError` when the value on top of the stack is the uninitialized binding pushed by
+    /// `push_empty`. Operand is the name of the binding. The value stays on the stack.
+    OP_CHECK_TDZ,
+    /// Throw `TypeError` for assignment to `const` binding. Operand is the name of the binding.
+    OP_THROW_CONST_ASSIGN,
+    /// Debugger hook, see [debugger](crate::vm::debugger). Operand is the kind of the hook.
+    OP_DEBUG,
+}
+
+impl Opcode {
+    /// Number of operand bytes that follow this opcode in the bytecode stream.
+    pub fn operand_size(self) -> usize {
+        use Opcode::*;
+        match self {
+            OP_GET_BY_ID | OP_TRY_GET_BY_ID | OP_PUT_BY_ID | OP_GET_GLOBAL | OP_PUT_GLOBAL => 8,
+            OP_CALL_BUILTIN => 12,
+            OP_CALL_EVAL => 8,
+            OP_GE0GL | OP_GE0SL | OP_GET_LOCAL | OP_SET_LOCAL | OP_GET_ENV | OP_JMP
+            | OP_JMP_IF_TRUE | OP_JMP_IF_FALSE | OP_PUSH_LITERAL | OP_PUSH_INT | OP_ADD
+            | OP_SUB | OP_DIV | OP_MUL | OP_REM | OP_CALL | OP_TAILCALL | OP_NEW | OP_TAILNEW
+            | OP_PUT_BY_VAL | OP_GET_BY_VAL | OP_GET_BY_VAL_PUSH_OBJ | OP_FORIN_SETUP
+            | OP_FORIN_ENUMERATE | OP_PUSH_CATCH | OP_DECL_CONST | OP_DECL_LET
+            | OP_DELETE_BY_ID | OP_GET_FUNCTION | OP_NEWARRAY | OP_GET_SLOT | OP_SET_SLOT
+            | OP_INC_SLOT | OP_DEC_SLOT | OP_ADD_INT | OP_SUB_INT | OP_CHECK_TDZ
+            | OP_THROW_CONST_ASSIGN | OP_DEBUG => 4,
+            // fused opcodes own only the operands of the first instruction.
+            OP_PUSH_INT_ADD | OP_GE0GL_GET_BY_ID => 4,
+            _ => 0,
+        }
+    }
+
+    /// Returns true if the operand of this opcode is a jump offset relative to the end of the instruction.
+    pub fn is_jump(self) -> bool {
+        matches!(
+            self,
+            Opcode::OP_JMP
+                | Opcode::OP_JMP_IF_TRUE
+                | Opcode::OP_JMP_IF_FALSE
+                | Opcode::OP_FORIN_SETUP
+                | Opcode::OP_FORIN_ENUMERATE
+                | Opcode::OP_PUSH_CATCH
+        )
+    }
 }
 
 pub type RegisterId = u16;
@@ -0,0 +1,146 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! Peephole pass over finished bytecode.
+//!
+//! The pass never changes the size of code: a fused pair is produced by overwriting the opcode byte of
+//! the first instruction with a superinstruction while operands and the second instruction stay in
+//! place. Jump offsets, try/catch addresses and source locations therefore stay valid. When the fast path
+//! of a superinstruction fails it executes only the first instruction and continues at the second one.
+//!
+//! Pairs whose second instruction is a jump target are never rewritten since control can reach the second
+//! instruction without executing the first one.
+use super::opcodes::Opcode;
+use std::collections::HashSet;
+
+/// Decode instruction boundaries of `code` and collect every address that some jump can land on.
+fn scan(code: &[u8]) -> (Vec<usize>, HashSet<usize>) {
+    let mut starts = vec![];
+    let mut targets = HashSet::new();
+    let mut pc = 0;
+    while pc < code.len() {
+        let op = unsafe { std::mem::transmute::<u8, Opcode>(code[pc]) };
+        starts.push(pc);
+        let next = pc + 1 + op.operand_size();
+        if op.is_jump() {
+            let offset = i32::from_le_bytes([code[pc + 1], code[pc + 2], code[pc + 3], code[pc + 4]]);
+            targets.insert((next as isize + offset as isize) as usize);
+        }
+        pc = next;
+    }
+    (starts, targets)
+}
+
+fn fuse(first: Opcode, second: Opcode) -> Option<Opcode> {
+    use Opcode::*;
+    Some(match (first, second) {
+        (OP_PUSH_INT, OP_ADD) => OP_PUSH_INT_ADD,
+        (OP_GE0GL, OP_GET_BY_ID) => OP_GE0GL_GET_BY_ID,
+        (OP_LESS, OP_JMP_IF_FALSE) => OP_LESS_JMP_IF_FALSE,
+        (OP_LESSEQ, OP_JMP_IF_FALSE) => OP_LESSEQ_JMP_IF_FALSE,
+        (OP_GREATER, OP_JMP_IF_FALSE) => OP_GREATER_JMP_IF_FALSE,
+        (OP_GREATEREQ, OP_JMP_IF_FALSE) => OP_GREATEREQ_JMP_IF_FALSE,
+        _ => return None,
+    })
+}
+
+/// Returns true if `op` only pushes a value and has no other observable effect.
+fn is_pure_push(op: Opcode) -> bool {
+    use Opcode::*;
+    matches!(
+        op,
+        OP_PUSH_TRUE
+            | OP_PUSH_FALSE
+            | OP_PUSH_UNDEF
+            | OP_PUSH_NULL
+            | OP_PUSH_NAN
+            | OP_PUSH_THIS
+            | OP_DUP
+            | OP_PUSH_INT
+            | OP_PUSH_LITERAL
+            | OP_GE0GL
+            | OP_GET_SLOT
+    )
+}
+
+/// Rewrite `code` in place. Returns number of rewritten pairs.
+pub fn optimize(code: &mut [u8]) -> usize {
+    let (starts, targets) = scan(code);
+    let mut rewritten = 0;
+    let mut i = 0;
+    while i + 1 < starts.len() {
+        let (pc, next) = (starts[i], starts[i + 1]);
+        if targets.contains(&next) {
+            i += 1;
+            continue;
+        }
+        let first = unsafe { std::mem::transmute::<u8, Opcode>(code[pc]) };
+        let second = unsafe { std::mem::transmute::<u8, Opcode>(code[next]) };
+        if second == Opcode::OP_POP && is_pure_push(first) {
+            if first.operand_size() == 0 {
+                code[pc] = Opcode::OP_NOP as u8;
+                code[next] = Opcode::OP_NOP as u8;
+            } else {
+                // jump over the pop, operands are reused for the offset.
+                code[pc] = Opcode::OP_JMP as u8;
+                code[pc + 1..pc + 5].copy_from_slice(&1i32.to_le_bytes());
+            }
+            rewritten += 1;
+            // both instructions are consumed.
+            i += 2;
+            continue;
+        }
+        if let Some(fused) = fuse(first, second) {
+            code[pc] = fused as u8;
+            rewritten += 1;
+            i += 2;
+            continue;
+        }
+        i += 1;
+    }
+    rewritten
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn emit(code: &mut Vec<u8>, op: Opcode, operands: &[u32]) {
+        code.push(op as u8);
+        for operand in operands {
+            code.extend_from_slice(&operand.to_le_bytes());
+        }
+    }
+
+    #[test]
+    fn test_peephole() {
+        let mut code = vec![];
+        emit(&mut code, Opcode::OP_PUSH_INT, &[1]);
+        emit(&mut code, Opcode::OP_ADD, &[0]);
+        emit(&mut code, Opcode::OP_PUSH_TRUE, &[]);
+        emit(&mut code, Opcode::OP_POP, &[]);
+        // loop head is a jump target but it still may start a fused pair.
+        emit(&mut code, Opcode::OP_LESS, &[]);
+        emit(&mut code, Opcode::OP_JMP_IF_FALSE, &[0]);
+        emit(&mut code, Opcode::OP_JMP, &[(-11i32) as u32]);
+        let len = code.len();
+
+        assert_eq!(optimize(&mut code), 3);
+        assert_eq!(code.len(), len);
+        assert_eq!(code[0], Opcode::OP_PUSH_INT_ADD as u8);
+        assert_eq!(code[5], Opcode::OP_ADD as u8);
+        assert_eq!(code[10], Opcode::OP_NOP as u8);
+        assert_eq!(code[11], Opcode::OP_NOP as u8);
+        assert_eq!(code[12], Opcode::OP_LESS_JMP_IF_FALSE as u8);
+
+        // `jmp_if_false` is a jump target so `less` must run separately.
+        let mut code = vec![];
+        emit(&mut code, Opcode::OP_PUSH_TRUE, &[]);
+        emit(&mut code, Opcode::OP_LESS, &[]);
+        emit(&mut code, Opcode::OP_JMP_IF_FALSE, &[0]);
+        emit(&mut code, Opcode::OP_JMP, &[(-10i32) as u32]);
+        assert_eq!(optimize(&mut code), 0);
+        assert_eq!(code[1], Opcode::OP_LESS as u8);
+    }
+}
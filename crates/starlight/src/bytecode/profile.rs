@@ -445,6 +445,19 @@ pub enum ProfileState {
     Profiled,
     DoNotProfile,
 }
+/// Inline cache state of by-val access. Zeroed operand of freshly emitted opcode is `Uninitialized`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[repr(u8)]
+pub enum ByValMode {
+    Uninitialized = 0,
+    /// int32 key and object with dense `IndexedElements`.
+    DenseArray,
+    /// int32 key and string primitive.
+    StringIndex,
+    /// Any other access. Typed arrays end up here too since they are not backed by JS objects yet.
+    Generic,
+}
+
 #[repr(C, align(32))]
 pub struct ByValProfile {
     /// Value that is used as property name.
@@ -453,9 +466,32 @@ pub struct ByValProfile {
     /// When value type is int32/number and object type is dense array then JIT will emit fast path for dense array load.
     pub value_type: ObservedType,
     pub is_dense_array: ProfileState,
+    pub mode: ByValMode,
 }
 
 impl ByValProfile {
+    /// Update inline cache after fast path for current mode missed. Access that saw two different kinds of
+    /// keys or objects stays generic forever.
+    #[inline(never)]
+    pub fn update_mode(&mut self, key: JsValue, obj: JsValue) {
+        let mode = if key.is_int32() && key.get_int32() >= 0 {
+            if obj.is_jsobject() && obj.get_jsobject().indexed.dense() {
+                ByValMode::DenseArray
+            } else if obj.is_jsstring() {
+                ByValMode::StringIndex
+            } else {
+                ByValMode::Generic
+            }
+        } else {
+            ByValMode::Generic
+        };
+        self.mode = match self.mode {
+            ByValMode::Uninitialized => mode,
+            current if current == mode => current,
+            _ => ByValMode::Generic,
+        };
+    }
+
     #[inline]
     pub fn observe_key_and_object(&mut self, key: JsValue, obj: JsValue) {
         return;
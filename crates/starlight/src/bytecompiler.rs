@@ -1,24 +1,60 @@
 /* This Source Code Form is subject to the terms of the Mozilla Public
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
-use crate::vm::{code_block::FileLocation, *};
+use crate::vm::{
+    builtins::{BUILTIN_DECLARE_GLOBAL, BUILTIN_DEFINE_METHOD},
+    code_block::FileLocation,
+    debugger::{DEBUG_DEBUGGER_STATEMENT, DEBUG_FUNCTION_ENTRY, DEBUG_STATEMENT},
+    *,
+};
 use crate::{
     bytecode::{opcodes::Opcode, TypeFeedBack},
     prelude::*,
-    vm::{code_block::CodeBlock, context::Context},
+    vm::{
+        code_block::{CodeBlock, VariableNames},
+        context::Context,
+    },
 };
 use std::convert::TryInto;
+use std::sync::atomic::{AtomicU32, Ordering};
 use std::u16;
 use std::{cell::RefCell, collections::HashMap, ops::Range, rc::Rc};
-use swc_common::{errors::Handler, sync::Lrc};
-use swc_common::{FileName, SourceMap};
+use swc_common::sync::Lrc;
+use swc_common::{FileName, SourceFile, SourceMap, Span, Spanned};
 use swc_ecmascript::parser::*;
 pub struct LoopControlInfo {
     breaks: Vec<Box<dyn FnOnce(&mut ByteCompiler)>>,
     continues: Vec<Box<dyn FnOnce(&mut ByteCompiler)>>,
+    /// Values kept on the operand stack while the body runs, e.g. iterator of `for-in`.
+    stack_values: u32,
+}
+
+/// Completion kinds stored by code jumping into `finally` block.
+const COMPLETION_NORMAL: u32 = 0;
+const COMPLETION_THROW: u32 = 1;
+const COMPLETION_RETURN: u32 = 2;
+const COMPLETION_BREAK: u32 = 3;
+const COMPLETION_CONTINUE: u32 = 4;
+
+/// `try` statement being compiled. `return`, `break` and `continue` that leave it drop its exception
+/// handlers and go through its `finally` block.
+pub struct TryLevel {
+    /// Exception handlers of the statement active at the current point.
+    handlers: u32,
+    /// Length of `lci` when the statement started.
+    lci_depth: usize,
+    /// Hidden variables holding completion kind and value read at the end of `finally` block.
+    finally: Option<(Access, Access)>,
+    /// Jumps into `finally` block.
+    jumps: Vec<Box<dyn FnOnce(&mut ByteCompiler)>>,
+    /// Abrupt completions that jump into `finally` block.
+    kinds: Vec<u32>,
 }
+use super::codegen::uses_arguments;
 use super::codegen::BindingKind;
+use super::codegen::Captures;
 use super::codegen::Scope as Analyzer;
+use super::preparser::PreParse;
 use swc_common::DUMMY_SP;
 use swc_ecmascript::visit::Node;
 use swc_ecmascript::visit::Visit;
@@ -32,8 +68,17 @@ pub struct Scope {
     pub variables: HashMap<Symbol, Variable>,
 
     pub depth: u32,
+    /// Function the scope belongs to, from [Scope::new_function_id]. Closures and eval code read bindings of
+    /// other functions with temporal dead zone checks.
+    pub function: u32,
 }
 impl Scope {
+    /// Fresh id for scopes of a function compiled by its own [ByteCompiler].
+    pub fn new_function_id() -> u32 {
+        static NEXT: AtomicU32 = AtomicU32::new(0);
+        NEXT.fetch_add(1, Ordering::Relaxed)
+    }
+
     pub fn add_var(&mut self, name: Symbol, ix: u16) -> u16 {
         self.variables.insert(
             name,
@@ -42,6 +87,8 @@ impl Scope {
                 name,
                 index: ix,
                 dont_free: false,
+                slot: false,
+                initialized: true,
             },
         );
         ix
@@ -54,6 +101,23 @@ impl Scope {
                 name,
                 index: ix,
                 dont_free: true,
+                slot: false,
+                initialized: true,
+            },
+        );
+        ix
+    }
+
+    pub fn add_slot_var(&mut self, name: Symbol, ix: u16, kind: VariableKind) -> u16 {
+        self.variables.insert(
+            name,
+            Variable {
+                kind,
+                name,
+                index: ix,
+                dont_free: true,
+                slot: true,
+                initialized: true,
             },
         );
         ix
@@ -67,6 +131,8 @@ impl Scope {
                 name,
                 index: ix,
                 dont_free: true,
+                slot: false,
+                initialized: true,
             },
         );
         ix
@@ -78,6 +144,11 @@ pub struct Variable {
     pub index: u16,
     pub kind: VariableKind,
     pub dont_free: bool,
+    /// Variable is stored in call frame slot `index` instead of environment.
+    pub slot: bool,
+    /// Declaration of `let` or `const` binding was already evaluated in every path that reaches code compiled
+    /// now, reads from the same function skip temporal dead zone check.
+    pub initialized: bool,
 }
 
 pub enum VariableKind {
@@ -89,6 +160,11 @@ pub enum VariableKind {
 #[derive(Clone, Debug)]
 pub enum Access {
     Variable(u16, u32),
+    Slot(u16),
+    /// `let` or `const` binding that may be accessed before its declaration is evaluated.
+    Tdz(Symbol, Box<Access>),
+    /// `const` binding, assignment to it throws.
+    Const(Symbol, Box<Access>),
     Global(Symbol),
     ById(Symbol),
     ArrayPat(Vec<(usize, Access)>),
@@ -96,6 +172,13 @@ pub enum Access {
     This,
 }
 
+/// Key of a member expression stored by [ByteCompiler::member_ref].
+#[derive(Clone, Debug)]
+pub enum MemberKey {
+    Name(Symbol),
+    Value(Access),
+}
+
 impl Access {
     pub fn expects_this(&self) -> bool {
         match self {
@@ -112,10 +195,76 @@ pub enum Val {
     Str(String),
 }
 
+/// String literals shared by all code blocks of one compiled script, so a string that appears in many
+/// functions is allocated once. [ByteCompiler::val_map] still deduplicates within a single code block.
+///
+/// The pool does not keep its strings alive, every one of them is referenced by `literals` of a code block
+/// of the script. Functions compiled lazily get a pool of their own since other code blocks of the script
+/// may be collected by then.
+#[derive(Default)]
+pub struct LiteralPool {
+    strings: HashMap<String, GcPointer<JsString>>,
+}
+
+impl LiteralPool {
+    pub fn string(&mut self, ctx: GcPointer<Context>, string: String) -> GcPointer<JsString> {
+        if let Some(cell) = self.strings.get(&string) {
+            return *cell;
+        }
+        let cell = JsString::new(ctx, &string);
+        self.strings.insert(string, cell);
+        cell
+    }
+
+    /// Number of distinct strings in the pool.
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
+    }
+}
+
+/// Function skipped by the pre-parser, see [ByteCompiler::compile_lazy].
+pub struct LazyFunction {
+    /// Source file function is declared in.
+    pub source: Lrc<SourceFile>,
+    /// Range of the function in `source`, from `function` keyword to the end of its body.
+    pub span: Span,
+    /// Scope function is declared in.
+    pub scope: ScopeRef,
+    pub builtins: bool,
+}
+
 #[derive(Debug)]
 pub enum CompileError {
+    /// Syntax the compiler does not support yet, e.g. `async function`.
     NotYetImpl(String),
+    /// Exception thrown while compiling, e.g. by the `RegExp` constructor for an invalid literal.
     Val(JsValue),
+    /// Early error of valid syntax, e.g. strict mode restrictions the parser does not check.
+    Syntax(String),
+}
+
+impl std::fmt::Display for CompileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CompileError::NotYetImpl(what) => write!(f, "Unsupported syntax: {}", what),
+            CompileError::Val(_) => f.write_str("Exception while compiling"),
+            CompileError::Syntax(msg) => f.write_str(msg),
+        }
+    }
+}
+
+impl CompileError {
+    /// Value to throw for this error: `Val` is thrown as is, everything else becomes a `SyntaxError`.
+    pub fn into_exception(self, ctx: GcPointer<Context>) -> JsValue {
+        match self {
+            CompileError::Val(value) => value,
+            err => JsValue::new(ctx.new_syntax_error(err.to_string())),
+        }
+    }
 }
 
 pub struct ByteCompiler {
@@ -123,15 +272,29 @@ pub struct ByteCompiler {
     pub code: GcPointer<CodeBlock>,
     pub scope: Rc<RefCell<Scope>>,
     pub val_map: HashMap<Val, u32>,
+    /// String literals of the whole script, see [LiteralPool].
+    pub literal_pool: Rc<RefCell<LiteralPool>>,
     pub name_map: HashMap<Symbol, u32>,
     pub lci: Vec<LoopControlInfo>,
+    pub try_levels: Vec<TryLevel>,
     pub fmap: HashMap<Symbol, u32>,
     pub top_level: bool,
     pub tail_pos: bool,
     /// Freelist of unused variable indexes
     pub variable_freelist: Vec<u32>,
 
-    pub info: Option<Vec<(Range<usize>, FileLocation)>>,
+    /// Source file of compiled code, used to record line numbers of statements.
+    pub source_file: Option<Lrc<SourceFile>>,
+
+    /// Capture analysis of compiled function, `None` when every binding is stored in environment.
+    pub captures: Option<Captures>,
+
+    /// Pre-parsed source of compiled script. Functions whose bodies were skipped are compiled lazily.
+    pub preparse: Option<Rc<PreParse>>,
+
+    /// Environment variable that holds completion value of script or eval code, i.e. value of the last
+    /// evaluated expression statement. `None` when compiled code does not produce one.
+    pub completion: Option<u16>,
 
     pub is_try: bool,
 }
@@ -144,7 +307,7 @@ impl ByteCompiler {
 
         let val_ = match val.clone() {
             Val::Float(x) => JsValue::new(f64::from_bits(x)),
-            Val::Str(x) => JsValue::encode_object_value(JsString::new(ctx, x)),
+            Val::Str(x) => JsValue::new(self.literal_pool.borrow_mut().string(ctx, x)),
         };
         let ix = self.code.literals.len();
         self.code.literals.push(val_);
@@ -184,13 +347,42 @@ impl ByteCompiler {
 
     fn access_var(&self, var: Symbol) -> Access {
         if let Some((ix, scope)) = self.lookup_scope(var) {
-            let cur_depth = self.scope.borrow().depth;
-            let depth = cur_depth - scope.borrow().depth;
-            Access::Variable(ix, depth)
+            let scope = scope.borrow();
+            let variable = &scope.variables[&var];
+            let depth = self.scope.borrow().depth - scope.depth;
+            let access = if variable.slot {
+                // only declared for bindings no closure refers to, so it always belongs to this function.
+                Access::Slot(ix)
+            } else {
+                Access::Variable(ix, depth)
+            };
+            // closures may run before the declaration no matter where they are created, even when they
+            // don't have an environment of their own and see the binding at depth 0.
+            let foreign = scope.function != self.scope.borrow().function;
+            let access = match variable.kind {
+                VariableKind::Let | VariableKind::Const
+                    if foreign || depth != 0 || !variable.initialized =>
+                {
+                    Access::Tdz(var, Box::new(access))
+                }
+                _ => access,
+            };
+            match variable.kind {
+                VariableKind::Const => Access::Const(var, Box::new(access)),
+                _ => access,
+            }
         } else {
             Access::Global(var)
         }
     }
+
+    /// Mark `let` or `const` binding `name` of the current scope initialized once its declaration is emitted.
+    /// Bindings of enclosing scopes stay unchanged since the declaration may be skipped, e.g. by `switch`.
+    fn mark_initialized(&mut self, name: Symbol) {
+        if let Some(var) = self.scope.borrow_mut().variables.get_mut(&name) {
+            var.initialized = true;
+        }
+    }
     pub fn emit_get_local(&mut self, depth: u32, index: u32) {
         if depth == 0 {
             self.emit(Opcode::OP_GE0GL, &[index], false);
@@ -213,6 +405,7 @@ impl ByteCompiler {
             let cur_depth = self.scope.borrow().depth;
             let _depth = cur_depth - scope.borrow().depth;
             self.emit(Opcode::OP_DECL_CONST, &[ix as _], false);
+            self.mark_initialized(name);
             return ix;
         } else {
             unreachable!(
@@ -236,18 +429,50 @@ impl ByteCompiler {
         ix
     }
 
+    /// Returns true if binding `name` of compiled function can be stored in a stack slot.
+    fn is_slot_binding(&self, name: Symbol) -> bool {
+        match self.captures {
+            Some(ref captures) => !captures.is_captured(name),
+            None => false,
+        }
+    }
+
+    fn alloc_slot(&mut self) -> u16 {
+        if self.code.slot_count == 0 {
+            // arguments are copied to the first slots on call.
+            self.code.slot_count =
+                self.code.param_count + self.code.rest_at.map(|_| 1).unwrap_or(0);
+        }
+        self.code.slot_count += 1;
+        self.code.slot_count as u16 - 1
+    }
+
     pub fn decl_let(&mut self, name: Symbol) -> u16 {
-        let ix = if let Some(ix) = self.variable_freelist.pop() {
-            self.scope.borrow_mut().add_let_var(name, ix as _);
-            ix as u16
+        let ix = self.alloc_let(name);
+        let op = if self.scope.borrow().variables[&name].slot {
+            Opcode::OP_SET_SLOT
+        } else {
+            Opcode::OP_DECL_LET
+        };
+        self.emit(op, &[ix as _], false);
+        ix
+    }
+
+    /// Declare `let` binding `name` in the current scope without emitting code.
+    fn alloc_let(&mut self, name: Symbol) -> u16 {
+        if self.is_slot_binding(name) {
+            let ix = self.alloc_slot();
+            self.scope
+                .borrow_mut()
+                .add_slot_var(name, ix, VariableKind::Let)
+        } else if let Some(ix) = self.variable_freelist.pop() {
+            self.scope.borrow_mut().add_let_var(name, ix as _)
         } else {
             self.code.var_count += 1;
             self.scope
                 .borrow_mut()
                 .add_let_var(name, self.code.var_count as u16 - 1)
-        };
-        self.emit(Opcode::OP_DECL_LET, &[ix as _], false);
-        ix
+        }
     }
 
     pub fn ident_to_sym(id: &Ident) -> Symbol {
@@ -268,15 +493,14 @@ impl ByteCompiler {
                     let ix = if VarDeclKind::Var == var.kind || VarDeclKind::Const == var.kind {
                         None
                     } else {
-                        Some(if let Some(ix) = self.variable_freelist.pop() {
-                            self.scope.borrow_mut().add_let_var(name_, ix as _);
-                            ix as u16
-                        } else {
-                            self.code.var_count += 1;
-                            self.scope
-                                .borrow_mut()
-                                .add_let_var(name_, self.code.var_count as u16 - 1)
-                        })
+                        let hoisted = self
+                            .scope
+                            .borrow()
+                            .variables
+                            .get(&name_)
+                            .filter(|v| matches!(v.kind, VariableKind::Let))
+                            .map(|v| v.index);
+                        Some(hoisted.unwrap_or_else(|| self.alloc_let(name_)))
                     };
                     match &decl.init {
                         Some(ref init) => {
@@ -294,7 +518,13 @@ impl ByteCompiler {
                             self.decl_const(Self::ident_to_sym(&name.id));
                         }
                         VarDeclKind::Let => {
-                            self.emit(Opcode::OP_DECL_LET, &[ix.unwrap() as _], false);
+                            let op = if self.scope.borrow().variables[&name_].slot {
+                                Opcode::OP_SET_SLOT
+                            } else {
+                                Opcode::OP_DECL_LET
+                            };
+                            self.emit(op, &[ix.unwrap() as _], false);
+                            self.mark_initialized(name_);
                         }
                         VarDeclKind::Var => {
                             let acc = self.access_var(Self::ident_to_sym(&name.id));
@@ -315,7 +545,7 @@ impl ByteCompiler {
                 }
 
                 x => {
-                    return Err(CompileError::NotYetImpl(format!("NYI: {:?}", x)));
+                    return Err(nyi(x));
                 }
             }
         }
@@ -335,7 +565,7 @@ impl ByteCompiler {
                 let id = self.get_sym(x);
                 self.emit(Opcode::OP_DELETE_BY_ID, &[id], false);
             }
-            Access::Variable(_ix, _depth) => {
+            Access::Variable(..) | Access::Slot(_) | Access::Tdz(..) | Access::Const(..) => {
                 self.emit(Opcode::OP_PUSH_TRUE, &[], false);
                 // self.access_set()
             }
@@ -353,10 +583,23 @@ impl ByteCompiler {
                 self.emit_set_local(depth as _, index as _);
                 //self.emit_u16(index);
             }
+            Access::Slot(index) => self.emit(Opcode::OP_SET_SLOT, &[index as _], false),
+            Access::Tdz(name, access) => {
+                self.access_get(Access::Tdz(name, access.clone()))?;
+                self.emit(Opcode::OP_POP, &[], false);
+                self.access_set(*access)?;
+            }
+            Access::Const(name, access) => {
+                if let Access::Tdz(..) = *access {
+                    self.access_get(*access)?;
+                    self.emit(Opcode::OP_POP, &[], false);
+                }
+                let name = self.get_sym(name);
+                self.emit(Opcode::OP_THROW_CONST_ASSIGN, &[name], false);
+            }
             Access::Global(x) => {
                 let name = self.get_sym(x);
-                self.emit(Opcode::OP_GLOBALTHIS, &[], false);
-                self.emit(Opcode::OP_PUT_BY_ID, &[name], true);
+                self.emit(Opcode::OP_PUT_GLOBAL, &[name], true);
             }
             Access::ById(name) => {
                 let name = self.get_sym(name);
@@ -369,21 +612,45 @@ impl ByteCompiler {
                     self.access_set(acc)?;
                 }
             }
-            x => return Err(CompileError::NotYetImpl(format!("NYI: {:?}", x))),
+            x => return Err(nyi(x)),
         }
         Ok(())
     }
+    /// Store value on top of the stack into variable declared in the head of `for-in` or `for-of`. Each
+    /// iteration initializes `const` binding anew instead of assigning to it.
+    fn access_init(&mut self, acc: Access) -> Result<(), CompileError> {
+        match acc {
+            Access::Const(_, access) => match *access {
+                Access::Variable(index, 0) => {
+                    self.emit(Opcode::OP_DECL_CONST, &[index as _], false);
+                    Ok(())
+                }
+                access => self.access_init(access),
+            },
+            Access::Tdz(_, access) => self.access_init(*access),
+            acc => self.access_set(acc),
+        }
+    }
+
     pub fn access_get(&mut self, acc: Access) -> Result<(), CompileError> {
         match acc {
             Access::Variable(index, depth) => {
                 self.emit_get_local(depth as _, index as _);
             }
+            Access::Slot(index) => self.emit(Opcode::OP_GET_SLOT, &[index as _], false),
+            Access::Tdz(name, access) => {
+                self.access_get(*access)?;
+                let name = self.get_sym(name);
+                self.emit(Opcode::OP_CHECK_TDZ, &[name], false);
+            }
+            Access::Const(_, access) => self.access_get(*access)?,
             Access::Global(x) => {
                 let name = self.get_sym(x);
-                self.emit(Opcode::OP_GLOBALTHIS, &[], false);
                 if self.is_try {
-                    self.emit(Opcode::OP_TRY_GET_BY_ID, &[name], true);
+                    self.emit(Opcode::OP_GET_GLOBAL, &[name], true);
                 } else {
+                    // `typeof` of undeclared variable must not throw.
+                    self.emit(Opcode::OP_GLOBALTHIS, &[], false);
                     self.emit(Opcode::OP_GET_BY_ID, &[name], true);
                 }
             }
@@ -402,7 +669,7 @@ impl ByteCompiler {
                     self.access_get(access)?;
                 }
             }
-            x => return Err(CompileError::NotYetImpl(format!("NYI: {:?}", x))),
+            x => return Err(nyi(x)),
         }
         Ok(())
     }
@@ -418,18 +685,16 @@ impl ByteCompiler {
             Expr::Member(member) => {
                 match &member.obj {
                     ExprOrSuper::Expr(e) => self.expr(ctx, e, true, false)?,
-                    _ => return Err(CompileError::NotYetImpl("NYI: super access".to_string())),
+                    _ => {
+                        return Err(CompileError::NotYetImpl(
+                            "super property access".to_string(),
+                        ))
+                    }
                 }
                 if dup {
                     self.emit(Opcode::OP_DUP, &[], false);
                 }
-                let name = if member.computed {
-                    None
-                } else if let Expr::Ident(name) = &*member.prop {
-                    Some(Self::ident_to_sym(name))
-                } else {
-                    None
-                };
+                let name = Self::member_name(member);
                 if name.is_none() {
                     self.expr(ctx, &member.prop, true, false)?;
                     self.emit(Opcode::OP_SWAP, &[], false);
@@ -442,18 +707,116 @@ impl ByteCompiler {
                 })
             }
             Expr::This(_) => Ok(Access::This),
-            x => Err(CompileError::NotYetImpl(format!("NYI: Access {:?}", x))),
+            x => Err(nyi(x)),
+        }
+    }
+
+    /// Name of the property accessed by `member` when it is known at compile time. `o.x` and `o["x"]` are
+    /// compiled to by-id access, other computed keys to by-val access.
+    fn member_name(member: &MemberExpr) -> Option<Symbol> {
+        match &*member.prop {
+            Expr::Ident(id) if !member.computed => Some(Self::ident_to_sym(id)),
+            // "1" is an index and takes the element fast path of by-val access.
+            Expr::Lit(Lit::Str(s)) if member.computed && s.value.parse::<u32>().is_err() => {
+                Some(s.value.intern())
+            }
+            _ => None,
+        }
+    }
+
+    /// Remember text of `callee` for the call instruction emitted next, see [CodeBlock::callee_name].
+    fn record_callee(&mut self, callee: &Expr) {
+        if let Some(text) = callee_text(callee) {
+            let pc = self.code.code.len() as u32;
+            self.code.callee_names.push((pc, text.into_boxed_str()));
+        }
+    }
+
+    /// Evaluate object and key of `member` once and store them in hidden variables. Compound assignments and
+    /// updates read and write the property with [ByteCompiler::member_get] and [ByteCompiler::member_set]
+    /// without evaluating the operands again.
+    fn member_ref(
+        &mut self,
+        ctx: GcPointer<Context>,
+        member: &MemberExpr,
+    ) -> Result<(Access, MemberKey), CompileError> {
+        match &member.obj {
+            ExprOrSuper::Expr(e) => self.expr(ctx, e, true, false)?,
+            _ => {
+                return Err(CompileError::NotYetImpl(
+                    "super property access".to_string(),
+                ))
+            }
+        }
+        let object = self.alloc_hidden();
+        self.access_set(object.clone())?;
+        let key = match Self::member_name(member) {
+            Some(name) => MemberKey::Name(name),
+            None => {
+                self.expr(ctx, &member.prop, true, false)?;
+                let key = self.alloc_hidden();
+                self.access_set(key.clone())?;
+                MemberKey::Value(key)
+            }
+        };
+        Ok((object, key))
+    }
+
+    fn member_get(&mut self, object: &Access, key: &MemberKey) -> Result<(), CompileError> {
+        match key {
+            MemberKey::Name(name) => {
+                self.access_get(object.clone())?;
+                self.access_get(Access::ById(*name))
+            }
+            MemberKey::Value(key) => {
+                self.access_get(key.clone())?;
+                self.access_get(object.clone())?;
+                self.access_get(Access::ByVal)
+            }
+        }
+    }
+
+    fn member_set(&mut self, object: &Access, key: &MemberKey) -> Result<(), CompileError> {
+        match key {
+            MemberKey::Name(name) => {
+                self.access_get(object.clone())?;
+                self.access_set(Access::ById(*name))
+            }
+            MemberKey::Value(key) => {
+                self.access_get(key.clone())?;
+                self.access_get(object.clone())?;
+                self.access_set(Access::ByVal)
+            }
+        }
+    }
+
+    /// Returns member expression `target` of an assignment or update refers to.
+    fn assign_member(target: &PatOrExpr) -> Option<&MemberExpr> {
+        let expr = match target {
+            PatOrExpr::Expr(expr) => expr,
+            PatOrExpr::Pat(pat) => match &**pat {
+                Pat::Expr(expr) => expr,
+                _ => return None,
+            },
+        };
+        match &**expr {
+            Expr::Member(member) => Some(member),
+            _ => None,
         }
     }
     pub fn finish(&mut self, ctx: GcPointer<Context>) -> Result<GcPointer<CodeBlock>, JsValue> {
+        if !ctx.vm.options.disable_peephole {
+            crate::bytecode::peephole::optimize(&mut self.code.code);
+        }
         if ctx.vm.options.dump_bytecode {
             let mut buf = String::new();
-            let name = ctx.description(self.code.name);
-            self.code.display_to(&mut buf).unwrap();
-            eprintln!("Code block '{}' at {:p}: \n {}", name, self.code, buf);
+            self.code.disassemble_to(ctx, &mut buf, false).unwrap();
+            eprintln!("{}", buf);
         }
         // self.code.compute_stack_size(ctx)?;
         self.code.literals_ptr = self.code.literals.as_ptr();
+        let scope = self.scope.clone();
+        self.record_var_names(&scope.borrow());
 
         Ok(self.code)
     }
@@ -476,7 +839,8 @@ impl ByteCompiler {
             }
             None => false,
         };
-        self.code.strict = is_strict;
+        // functions nested in strict code are strict as well.
+        self.code.strict |= is_strict;
 
         match fun.body {
             Some(ref body) => {
@@ -506,21 +870,27 @@ impl ByteCompiler {
             variables: HashMap::new(),
             parent: None,
             depth: 0,
+            function: Scope::new_function_id(),
         }));
         let mut code = CodeBlock::new(ctx, "<anonymous>".intern(), false, rel_path.into());
         let mut compiler = ByteCompiler {
             lci: Vec::new(),
+            try_levels: Vec::new(),
             builtins,
             variable_freelist: Vec::with_capacity(4),
             code,
             tail_pos: false,
-            info: None,
+            source_file: None,
             fmap: HashMap::new(),
             val_map: HashMap::new(),
+            literal_pool: Default::default(),
             name_map: HashMap::new(),
             top_level: false,
             scope,
             is_try: true,
+            captures: None,
+            preparse: None,
+            completion: None,
         };
         let mut p = 0;
         for x in params_.iter() {
@@ -532,24 +902,10 @@ impl ByteCompiler {
         code.var_count = p as _;
         code.rest_at = rat;
         let cm: Lrc<SourceMap> = Default::default();
-        let _e = BufferedError::default();
-
-        let handler = Handler::with_emitter(true, false, Box::new(MyEmiter::default()));
-
         let fm = cm.new_source_file(FileName::Custom("<anonymous>".into()), body);
+        compiler.source_file = Some(fm.clone());
 
-        let mut parser = Parser::new(Syntax::Es(init_es_config()), StringInput::from(&*fm), None);
-
-        for e in parser.take_errors() {
-            e.into_diagnostic(&handler).emit();
-        }
-
-        let script = match parser.parse_script() {
-            Ok(script) => script,
-            Err(e) => {
-                return Err(CompileError::NotYetImpl(format!("{}", e.kind().msg())));
-            }
-        };
+        let script = frontend::parse_script(&*ctx.vm.frontend, &fm.src, fm.start_pos)?;
 
         let is_strict = if script.body.is_empty() {
             false
@@ -576,9 +932,6 @@ impl ByteCompiler {
         name: Symbol,
         expr: bool,
     ) -> Result<(), CompileError> {
-        let mut _rest = None;
-        let mut params = vec![];
-        let mut rat = None;
         let (mut code, ix) = if !expr {
             (
                 self.code.codes[self.fmap.get(&name).copied().unwrap() as usize],
@@ -591,28 +944,98 @@ impl ByteCompiler {
             (code, self.code.codes.len() - 1)
         };
         if function.is_async {
-            return Err(CompileError::NotYetImpl("NYI: async".to_string()));
+            return Err(CompileError::NotYetImpl("async function".to_string()));
         }
         code.is_generator = function.is_generator;
+        code.strict = self.code.strict;
+        code.source = self.source_of(function.span);
+        let skipped = match (&self.preparse, &self.source_file, &function.body) {
+            (Some(preparse), Some(source), Some(body)) => {
+                preparse.body(body.span.lo).map(|skipped| {
+                    (
+                        source.clone(),
+                        Span::new(skipped.start, skipped.end, Default::default()),
+                    )
+                })
+            }
+            _ => None,
+        };
+        if let Some((source, span)) = skipped {
+            code.lazy = Some(Box::new(LazyFunction {
+                source,
+                span,
+                scope: self.scope.clone(),
+                builtins: self.builtins,
+            }));
+        } else {
+            Self::compile_function(
+                ctx,
+                code,
+                function,
+                self.scope.clone(),
+                self.builtins,
+                self.source_file.clone(),
+                self.preparse.clone(),
+                self.literal_pool.clone(),
+            )?;
+        }
+        let ix = if expr {
+            ix as u32
+        } else {
+            *self.fmap.get(&name).unwrap()
+        };
+        self.emit(Opcode::OP_GET_FUNCTION, &[ix], false);
+        Ok(())
+    }
+
+    /// Compile parameters and body of `function` into `code`. `parent` is the scope function was declared in.
+    #[allow(clippy::too_many_arguments)]
+    fn compile_function(
+        ctx: GcPointer<Context>,
+        mut code: GcPointer<CodeBlock>,
+        function: &Function,
+        parent: ScopeRef,
+        builtins: bool,
+        source_file: Option<Lrc<SourceFile>>,
+        preparse: Option<Rc<PreParse>>,
+        literal_pool: Rc<RefCell<LiteralPool>>,
+    ) -> Result<(), CompileError> {
+        let mut _rest = None;
+        let mut params = vec![];
+        let mut rat = None;
+        if let Some(ref body) = function.body {
+            code.strict |= body
+                .stmts
+                .first()
+                .map_or(false, |stmt| stmt.is_use_strict());
+        }
+        let captures = Captures::analyze_function(function, preparse.as_deref());
+        code.needs_environment = captures.needs_environment();
         let scope = Rc::new(RefCell::new(Scope {
             variables: HashMap::new(),
-            parent: Some(self.scope.clone()),
-            depth: self.scope.borrow().depth + 1,
+            depth: parent.borrow().depth + code.needs_environment as u32,
+            parent: Some(parent),
+            function: Scope::new_function_id(),
         }));
 
         let mut compiler = ByteCompiler {
             lci: Vec::new(),
-            builtins: self.builtins,
+            try_levels: Vec::new(),
+            builtins,
             variable_freelist: Vec::with_capacity(4),
             code,
-            info: None,
+            source_file,
             tail_pos: false,
             fmap: HashMap::new(),
             val_map: HashMap::new(),
+            literal_pool,
             name_map: HashMap::new(),
             top_level: false,
             scope,
             is_try: true,
+            captures: Some(captures),
+            preparse,
+            completion: None,
         };
         let mut p = 0;
         for x in function.params.iter() {
@@ -620,10 +1043,7 @@ impl ByteCompiler {
                 Pat::Ident(ref x) => {
                     params.push(Self::ident_to_sym(&x.id));
                     p += 1;
-                    compiler
-                        .scope
-                        .borrow_mut()
-                        .add_var(Self::ident_to_sym(&x.id), p - 1);
+                    compiler.declare_param(Self::ident_to_sym(&x.id), p - 1);
                 }
                 Pat::Rest(ref r) => match &*r.arg {
                     Pat::Ident(ref id) => {
@@ -637,30 +1057,95 @@ impl ByteCompiler {
                                 as u32,
                         );
                     }
-                    ref x => return Err(CompileError::NotYetImpl(format!("NYI: {:?}", x))),
+                    ref x => return Err(nyi(x)),
                 },
                 ref x => {
-                    return Err(CompileError::NotYetImpl(format!("NYI: {:?}", x)));
+                    return Err(nyi(x));
                 }
             }
         }
 
+        check_duplicate_params(
+            &params
+                .iter()
+                .chain(_rest.iter())
+                .copied()
+                .collect::<Vec<_>>(),
+            !code.strict && _rest.is_none(),
+        )?;
         code.param_count = params.len() as _;
         code.var_count = p as _;
         code.rest_at = rat;
+        compiler.reserve_param_slots();
         if code.is_generator {
             compiler.emit(Opcode::OP_INITIAL_YIELD, &[], false);
         }
+        if ctx.vm.debugger.is_some() {
+            compiler.code.debug = true;
+            compiler.emit(Opcode::OP_DEBUG, &[DEBUG_FUNCTION_ENTRY], false);
+        }
         compiler.compile_fn(ctx, function)?;
         compiler.finish(ctx).map_err(CompileError::Val)?;
-        let ix = if expr {
-            ix as u32
-        } else {
-            *self.fmap.get(&name).unwrap()
+        Ok(())
+    }
+
+    /// Parse and compile function that was skipped by the pre-parser. Called before the first call of `code`,
+    /// does nothing when `code` is compiled already.
+    pub fn compile_lazy(
+        ctx: GcPointer<Context>,
+        mut code: GcPointer<CodeBlock>,
+    ) -> Result<(), JsValue> {
+        let lazy = match code.lazy.take() {
+            Some(lazy) => lazy,
+            None => return Ok(()),
         };
-        self.emit(Opcode::OP_GET_FUNCTION, &[ix], false);
+        let fm = &lazy.source;
+        let range =
+            (lazy.span.lo - fm.start_pos).0 as usize..(lazy.span.hi - fm.start_pos).0 as usize;
+        let input = StringInput::new(&fm.src[range], lazy.span.lo, lazy.span.hi);
+        let mut parser = Parser::new(Syntax::Es(init_es_config()), input, None);
+        let result = match parser.parse_expr() {
+            Ok(expr) => match *expr {
+                Expr::Fn(ref fun) => Self::compile_function(
+                    ctx,
+                    code,
+                    &fun.function,
+                    lazy.scope.clone(),
+                    lazy.builtins,
+                    Some(lazy.source.clone()),
+                    None,
+                    Default::default(),
+                )
+                .map_err(|e| e.into_exception(ctx)),
+                _ => unreachable!("pre-parser recorded something that is not a function"),
+            },
+            Err(e) => Err(JsValue::new(ctx.new_syntax_error(e.kind().msg()))),
+        };
+        if result.is_err() {
+            // drop partially emitted code, every following call reports the error again.
+            code.code.clear();
+            code.codes.clear();
+            code.literals.clear();
+            code.names.clear();
+            code.feedback.clear();
+            code.loc.clear();
+            code.lazy = Some(lazy);
+        }
+        result
+    }
+
+    /// Compile every function skipped by the pre-parser in `code` and in functions nested in it.
+    pub fn compile_all_lazy(
+        ctx: GcPointer<Context>,
+        code: GcPointer<CodeBlock>,
+    ) -> Result<(), JsValue> {
+        Self::compile_lazy(ctx, code)?;
+        for nested in code.codes.iter() {
+            Self::compile_all_lazy(ctx, *nested)?;
+        }
         Ok(())
     }
+
     pub fn fn_expr(
         &mut self,
         ctx: GcPointer<Context>,
@@ -686,6 +1171,199 @@ impl ByteCompiler {
         Ok(())
     }
 
+    /// Compile class and push its constructor.
+    pub fn class(
+        &mut self,
+        ctx: GcPointer<Context>,
+        class: &Class,
+        name: Symbol,
+    ) -> Result<(), CompileError> {
+        // class bodies are strict code, methods and field initializers inherit it from the enclosing code block.
+        let strict = std::mem::replace(&mut self.code.strict, true);
+        let result = self
+            .class_definition(ctx, class, name)
+            .and_then(|static_fields| self.class_static_init(ctx, class.span, static_fields));
+        self.code.strict = strict;
+        result
+    }
+
+    /// Push constructor of `class` with methods and accessors defined on its prototype or, when static, on the
+    /// constructor itself. Instance fields are initialized at the start of the constructor. Returns
+    /// initializers of static fields, which run after the class binding is initialized.
+    fn class_definition(
+        &mut self,
+        ctx: GcPointer<Context>,
+        class: &Class,
+        name: Symbol,
+    ) -> Result<Vec<Stmt>, CompileError> {
+        if class.super_class.is_some() {
+            return Err(CompileError::NotYetImpl("class extends".to_string()));
+        }
+        let mut constructor = None;
+        let mut fields = vec![];
+        let mut static_fields = vec![];
+        for member in class.body.iter() {
+            match member {
+                ClassMember::Constructor(ctor) => constructor = Some(ctor),
+                ClassMember::ClassProp(prop) if prop.is_static => {
+                    static_fields.push(Self::field_init(prop))
+                }
+                ClassMember::ClassProp(prop) => {
+                    if prop.computed {
+                        return Err(CompileError::NotYetImpl(
+                            "computed instance field".to_string(),
+                        ));
+                    }
+                    fields.push(Self::field_init(prop));
+                }
+                ClassMember::Method(_) | ClassMember::Empty(_) => {}
+                x => return Err(nyi(x)),
+            }
+        }
+
+        let mut params = vec![];
+        let mut body = vec![];
+        let mut span = class.span;
+        if let Some(ctor) = constructor {
+            for param in ctor.params.iter() {
+                match param {
+                    ParamOrTsParamProp::Param(param) => params.push(param.clone()),
+                    x => return Err(nyi(x)),
+                }
+            }
+            if let Some(ref block) = ctor.body {
+                body = block.stmts.clone();
+                span = block.span;
+            }
+        }
+        fields.append(&mut body);
+        self.function(
+            ctx,
+            &Self::synthetic_function(span, params, fields),
+            name,
+            true,
+        )?;
+        // `toString` of a class returns the whole class
+        let mut code = *self.code.codes.last().unwrap();
+        code.source = self.source_of(class.span);
+
+        for member in class.body.iter() {
+            if let ClassMember::Method(method) = member {
+                self.emit(Opcode::OP_DUP, &[], false);
+                if !method.is_static {
+                    let prototype = self.get_sym(Symbol::PROTOTYPE);
+                    self.emit(Opcode::OP_GET_BY_ID, &[prototype], true);
+                }
+                let name = self.prop_name(ctx, &method.key)?;
+                self.function(ctx, &method.function, name, true)?;
+                let effect = match method.kind {
+                    MethodKind::Method => 0,
+                    MethodKind::Getter => 1,
+                    MethodKind::Setter => 2,
+                };
+                self.emit(
+                    Opcode::OP_CALL_BUILTIN,
+                    &[0, BUILTIN_DEFINE_METHOD, effect],
+                    false,
+                );
+                self.emit(Opcode::OP_POP, &[], false);
+            }
+        }
+
+        Ok(static_fields)
+    }
+
+    /// Run initializers of static fields in a function called with the constructor on top of the stack as
+    /// `this`.
+    fn class_static_init(
+        &mut self,
+        ctx: GcPointer<Context>,
+        span: Span,
+        static_fields: Vec<Stmt>,
+    ) -> Result<(), CompileError> {
+        if static_fields.is_empty() {
+            return Ok(());
+        }
+        self.emit(Opcode::OP_DUP, &[], false);
+        self.function(
+            ctx,
+            &Self::synthetic_function(span, vec![], static_fields),
+            "<static_initializer>".intern(),
+            true,
+        )?;
+        self.emit(Opcode::OP_CALL, &[0], false);
+        self.emit(Opcode::OP_POP, &[], false);
+        Ok(())
+    }
+
+    /// `this.key = value;` statement initializing class field `prop`.
+    fn field_init(prop: &ClassProp) -> Stmt {
+        let span = prop.span;
+        let value = prop.value.clone().unwrap_or_else(|| {
+            Box::new(Expr::Unary(UnaryExpr {
+                span,
+                op: UnaryOp::Void,
+                arg: Box::new(Expr::Lit(Lit::Num(Number { span, value: 0.0 }))),
+            }))
+        });
+        let target = Expr::Member(MemberExpr {
+            span,
+            obj: ExprOrSuper::Expr(Box::new(Expr::This(ThisExpr { span }))),
+            prop: prop.key.clone(),
+            computed: prop.computed || !matches!(*prop.key, Expr::Ident(_)),
+        });
+        Stmt::Expr(ExprStmt {
+            span,
+            expr: Box::new(Expr::Assign(AssignExpr {
+                span,
+                op: AssignOp::Assign,
+                left: PatOrExpr::Expr(Box::new(target)),
+                right: value,
+            })),
+        })
+    }
+
+    fn synthetic_function(span: Span, params: Vec<Param>, stmts: Vec<Stmt>) -> Function {
+        Function {
+            params,
+            decorators: vec![],
+            span,
+            body: Some(BlockStmt { span, stmts }),
+            is_generator: false,
+            is_async: false,
+            type_params: None,
+            return_type: None,
+        }
+    }
+
+    /// Push key of class element and return name of the function defined with it.
+    fn prop_name(
+        &mut self,
+        ctx: GcPointer<Context>,
+        key: &PropName,
+    ) -> Result<Symbol, CompileError> {
+        let name = match key {
+            PropName::Ident(id) => id.sym.to_string(),
+            PropName::Str(s) => s.value.to_string(),
+            PropName::Num(n) => {
+                let ix = self.get_val(ctx, Val::Float(n.value.to_bits()));
+                self.emit(Opcode::OP_PUSH_LITERAL, &[ix], false);
+                let name = JsValue::new(n.value)
+                    .to_string(ctx)
+                    .map_err(CompileError::Val)?;
+                return Ok(name.intern());
+            }
+            PropName::Computed(computed) => {
+                self.expr(ctx, &computed.expr, true, false)?;
+                return Ok("<anonymous>".intern());
+            }
+            x => return Err(nyi(x)),
+        };
+        let ix = self.get_val(ctx, Val::Str(name.clone()));
+        self.emit(Opcode::OP_PUSH_LITERAL, &[ix], false);
+        Ok(name.intern())
+    }
+
     pub fn analyze_module(
         &mut self,
         ctx: GcPointer<Context>,
@@ -728,7 +1406,9 @@ impl ByteCompiler {
             }
             // self.emit(Opcode::OP_GET_FUNCTION, &[ix as _], false);
             let var = self.access_var(name);
-            self.access_set(var).unwrap_or_else(|_| panic!("wtf"));
+            if let Err(e) = self.access_set(var) {
+                res = Err(e);
+            }
         });
         res
     }
@@ -739,6 +1419,7 @@ impl ByteCompiler {
         path: &str,
         name: &str,
         module: &Module,
+        source_file: Option<Lrc<SourceFile>>,
     ) -> Result<GcPointer<CodeBlock>, CompileError> {
         let name = name.intern();
 
@@ -746,21 +1427,27 @@ impl ByteCompiler {
         code.file_name = file.to_string();
         let mut compiler = ByteCompiler {
             lci: Vec::new(),
+            try_levels: Vec::new(),
             top_level: true,
-            info: None,
+            source_file,
             tail_pos: false,
             builtins: false,
             scope: Rc::new(RefCell::new(Scope {
                 parent: None,
                 variables: Default::default(),
                 depth: 0,
+                function: Scope::new_function_id(),
             })),
             variable_freelist: vec![],
             code,
             val_map: Default::default(),
+            literal_pool: Default::default(),
             name_map: Default::default(),
             fmap: Default::default(),
             is_try: true,
+            captures: None,
+            preparse: None,
+            completion: None,
         };
         code.var_count = 1;
         code.param_count = 1;
@@ -839,8 +1526,15 @@ impl ByteCompiler {
                             DefaultDecl::Fn(ref fun) => {
                                 compiler.fn_expr(ctx, fun, true)?;
                             }
+                            DefaultDecl::Class(ref class) => {
+                                let name = match class.ident {
+                                    Some(ref id) => Self::ident_to_sym(id),
+                                    None => "default".intern(),
+                                };
+                                compiler.class(ctx, &class.class, name)?;
+                            }
                             ref x => {
-                                return Err(CompileError::NotYetImpl(format!("NYI: {:?}", x)));
+                                return Err(nyi(x));
                             }
                         }
 
@@ -858,9 +1552,7 @@ impl ByteCompiler {
                     }
                     ModuleDecl::ExportNamed(named_export) => {
                         if named_export.src.is_some() {
-                            return Err(CompileError::NotYetImpl(
-                                "NYI: export * from mod".to_string(),
-                            ));
+                            return Err(CompileError::NotYetImpl("export * from".to_string()));
                         }
 
                         for specifier in named_export.specifiers.iter() {
@@ -880,15 +1572,12 @@ impl ByteCompiler {
                                     compiler.emit(Opcode::OP_PUT_BY_ID, &[sym], true);
                                 }
                                 _ => {
-                                    return Err(CompileError::NotYetImpl(format!(
-                                        "NYI: {:?}",
-                                        specifier
-                                    )));
+                                    return Err(nyi(specifier));
                                 }
                             }
                         }
                     }
-                    x => return Err(CompileError::NotYetImpl(format!("NYI: {:?}", x))),
+                    x => return Err(nyi(x)),
                 },
             }
         }
@@ -903,27 +1592,34 @@ impl ByteCompiler {
         path: &str,
         fname: String,
         builtins: bool,
+        source_file: Option<Lrc<SourceFile>>,
     ) -> Result<GcPointer<CodeBlock>, CompileError> {
         let name = "<script>".intern();
         let mut code = CodeBlock::new(ctx, name, false, path.into());
         code.file_name = fname;
         let mut compiler = ByteCompiler {
             lci: Vec::new(),
+            try_levels: Vec::new(),
             top_level: true,
-            info: None,
+            source_file,
             tail_pos: false,
             builtins,
             scope: Rc::new(RefCell::new(Scope {
                 parent: None,
                 variables: Default::default(),
                 depth: 0,
+                function: Scope::new_function_id(),
             })),
             variable_freelist: vec![],
             code,
             val_map: Default::default(),
+            literal_pool: Default::default(),
             name_map: Default::default(),
             fmap: Default::default(),
             is_try: true,
+            captures: None,
+            preparse: None,
+            completion: None,
         };
 
         let is_strict = match p.body.get(0) {
@@ -947,27 +1643,35 @@ impl ByteCompiler {
         path: &str,
         fname: String,
         builtins: bool,
+        source_file: Option<Lrc<SourceFile>>,
+        preparse: Option<Rc<PreParse>>,
     ) -> Result<GcPointer<CodeBlock>, CompileError> {
         let name = "<script>".intern();
         let mut code = CodeBlock::new(ctx, name, false, path.into());
         code.file_name = fname;
         let mut compiler = ByteCompiler {
             lci: Vec::new(),
+            try_levels: Vec::new(),
             top_level: true,
-            info: None,
+            source_file,
             tail_pos: false,
             builtins,
             scope: Rc::new(RefCell::new(Scope {
                 parent: None,
                 variables: Default::default(),
                 depth: 0,
+                function: Scope::new_function_id(),
             })),
             variable_freelist: vec![],
             code,
             val_map: Default::default(),
+            literal_pool: Default::default(),
             name_map: Default::default(),
             fmap: Default::default(),
             is_try: true,
+            captures: None,
+            preparse,
+            completion: None,
         };
 
         let is_strict = match p.body.get(0) {
@@ -979,30 +1683,77 @@ impl ByteCompiler {
         compiler.push_scope();
         compiler.compile(ctx, &p.body, true)?;
         compiler.pop_scope();
-        compiler.emit(Opcode::OP_PUSH_UNDEF, &[], false);
-        compiler.emit(Opcode::OP_RET, &[], false);
         let mut result = compiler.finish(ctx).map_err(CompileError::Val)?;
         Ok(result)
     }
 
+    /// Compile code of direct `eval` call. Names are resolved through `scope`, the compile-time scope of the
+    /// call site. Variables declared by eval code stay local to it except for sloppy eval at the top level of a
+    /// script, which declares globals.
+    pub fn compile_direct_eval(
+        ctx: GcPointer<Context>,
+        p: &Script,
+        scope: ScopeRef,
+        strict: bool,
+        source_file: Option<Lrc<SourceFile>>,
+    ) -> Result<GcPointer<CodeBlock>, CompileError> {
+        let mut code = CodeBlock::new(ctx, "<eval>".intern(), false, "".into());
+        let strict = strict
+            || match p.body.get(0) {
+                Some(body) => body.is_use_strict(),
+                None => false,
+            };
+        let depth = scope.borrow().depth;
+        let mut compiler = ByteCompiler {
+            lci: Vec::new(),
+            try_levels: Vec::new(),
+            top_level: depth == 0 && !strict,
+            source_file,
+            tail_pos: false,
+            builtins: false,
+            scope: Rc::new(RefCell::new(Scope {
+                parent: Some(scope),
+                variables: Default::default(),
+                depth: depth + 1,
+                function: Scope::new_function_id(),
+            })),
+            variable_freelist: vec![],
+            code,
+            val_map: Default::default(),
+            literal_pool: Default::default(),
+            name_map: Default::default(),
+            fmap: Default::default(),
+            is_try: true,
+            captures: None,
+            preparse: None,
+            completion: None,
+        };
+        code.strict = strict;
+        compiler.compile(ctx, &p.body, true)?;
+        compiler.finish(ctx).map_err(CompileError::Val)
+    }
+
     pub fn analyze(&mut self, ctx: GcPointer<Context>, body: &[Stmt]) -> Result<(), CompileError> {
         let scopea = Analyzer::analyze_stmts(body);
-
+        let mut lexical = vec![];
         for var in scopea.vars.iter() {
             match var.1.kind() {
-                BindingKind::Var if !self.top_level => {
+                BindingKind::Var | BindingKind::Function if !self.top_level => {
                     let s: &str = &(var.0).0;
                     let name = s.intern();
-                    let c = self.code.var_count;
-                    self.scope.borrow_mut().add_var(name, c as _);
-                    self.code.var_count += 1;
+                    self.declare_var(name);
                 }
-                BindingKind::Function if !self.top_level => {
+                BindingKind::Var | BindingKind::Function => {
+                    // script level bindings are properties of the global object that exist before code runs,
+                    // so strict code can assign them.
                     let s: &str = &(var.0).0;
-                    let name = s.intern();
-                    let c = self.code.var_count;
-                    self.scope.borrow_mut().add_var(name, c as _);
-                    self.code.var_count += 1;
+                    let name = self.get_sym(s.intern());
+                    self.emit(
+                        Opcode::OP_CALL_BUILTIN,
+                        &[name, BUILTIN_DECLARE_GLOBAL, 0],
+                        false,
+                    );
+                    self.emit(Opcode::OP_POP, &[], false);
                 }
                 BindingKind::Const => {
                     let s: &str = &(var.0).0;
@@ -1010,10 +1761,48 @@ impl ByteCompiler {
                     let c = self.code.var_count;
                     self.scope.borrow_mut().add_const_var(name, c as _);
                     self.code.var_count += 1;
+                    lexical.push(name);
                 }
                 _ => {}
             }
         }
+        // `let` declared directly in `body` is visible to the whole scope, including functions declared below.
+        for stmt in body.iter() {
+            match stmt {
+                Stmt::Decl(Decl::Var(ref var)) if var.kind == VarDeclKind::Let => {
+                    for decl in var.decls.iter() {
+                        if let Pat::Ident(ref id) = decl.name {
+                            let name = Self::ident_to_sym(&id.id);
+                            self.alloc_let(name);
+                            lexical.push(name);
+                        }
+                    }
+                }
+                // class declarations are bound like `let`.
+                Stmt::Decl(Decl::Class(ref class)) => {
+                    let name = Self::ident_to_sym(&class.ident);
+                    self.alloc_let(name);
+                    lexical.push(name);
+                }
+                _ => {}
+            }
+        }
+        // scope is entered again on every iteration of a loop, bindings are reset to uninitialized each time.
+        for name in lexical {
+            let (ix, slot) = {
+                let mut scope = self.scope.borrow_mut();
+                let var = scope.variables.get_mut(&name).unwrap();
+                var.initialized = false;
+                (var.index, var.slot)
+            };
+            self.emit(Opcode::OP_PUSH_EMPTY, &[], false);
+            let op = if slot {
+                Opcode::OP_SET_SLOT
+            } else {
+                Opcode::OP_DECL_LET
+            };
+            self.emit(op, &[ix as _], false);
+        }
         let mut res = Ok(());
         VisitFnDecl::visit(body, &mut |decl: &FnDecl| {
             let name = Self::ident_to_sym(&decl.ident);
@@ -1032,41 +1821,90 @@ impl ByteCompiler {
 
             // self.emit(Opcode::OP_GET_FUNCTION, &[ix as _], false);
             let var = self.access_var(name);
-            self.access_set(var).unwrap_or_else(|_| panic!("wtf"));
+            if let Err(e) = self.access_set(var) {
+                res = Err(e);
+            }
         });
 
-        for stmt in body.iter() {
-            if contains_ident(stmt, "arguments") {
-                self.code.use_arguments = true;
-                let c = self.code.var_count;
-                self.code.args_at = self
-                    .scope
-                    .borrow_mut()
-                    .add_var("arguments".intern(), c as _) as _;
-                break;
-            }
+        if uses_arguments(body) {
+            self.code.use_arguments = true;
+            let c = self.code.var_count;
+            self.code.args_at = self
+                .scope
+                .borrow_mut()
+                .add_var("arguments".intern(), c as _) as _;
+            self.code.var_count += 1;
         }
         res
     }
 
+    /// Declare parameter at position `ix`. Parameter keeps its position whether it is stored in environment or
+    /// in a stack slot.
+    fn declare_param(&mut self, name: Symbol, ix: u16) {
+        if self.is_slot_binding(name) {
+            self.scope
+                .borrow_mut()
+                .add_slot_var(name, ix, VariableKind::Var);
+        } else {
+            self.scope.borrow_mut().add_var(name, ix);
+        }
+    }
+
+    /// Reserve slots for parameters declared by [ByteCompiler::declare_param] once parameter list is known.
+    fn reserve_param_slots(&mut self) {
+        let slots = self
+            .scope
+            .borrow()
+            .variables
+            .values()
+            .filter(|var| var.slot)
+            .count();
+        if slots != 0 {
+            self.code.slot_count =
+                self.code.param_count + self.code.rest_at.map(|_| 1).unwrap_or(0);
+        }
+        // duplicate parameter names share a binding, such lists are copied to the environment as before.
+        self.code.params_in_slots = slots == self.code.param_count as usize;
+    }
+
+    /// Declare function level variable in current scope.
+    fn declare_var(&mut self, name: Symbol) {
+        if self.is_slot_binding(name) {
+            let ix = self.alloc_slot();
+            self.scope
+                .borrow_mut()
+                .add_slot_var(name, ix, VariableKind::Var);
+        } else {
+            let c = self.code.var_count;
+            self.scope.borrow_mut().add_var(name, c as _);
+            self.code.var_count += 1;
+        }
+    }
+
+    /// Compile `body`. When `completion` is true compiled code returns completion value of `body` instead of
+    /// falling through, see [ByteCompiler::completion].
     pub fn compile(
         &mut self,
         ctx: GcPointer<Context>,
         body: &[Stmt],
-        _last_val_ret: bool,
+        completion: bool,
     ) -> Result<(), CompileError> {
         self.analyze(ctx, body)?;
-        for (index, stmt) in body.iter().enumerate() {
-            if index == body.len() - 1 && _last_val_ret {
-                if let Stmt::Expr(ref expr) = stmt {
-                    self.expr(ctx, &expr.expr, true, false)?;
-                    self.emit(Opcode::OP_RET, &[], false);
-                    break;
-                }
-            }
-
+        if completion {
+            let ix = self.code.var_count as u16;
+            self.code.var_count += 1;
+            self.scope.borrow_mut().add_var("@completion".intern(), ix);
+            self.completion = Some(ix);
+            self.emit(Opcode::OP_PUSH_UNDEF, &[], false);
+            self.emit(Opcode::OP_GE0SL, &[ix as _], false);
+        }
+        for stmt in body.iter() {
             self.stmt(ctx, stmt)?;
         }
+        if let Some(ix) = self.completion.take() {
+            self.emit(Opcode::OP_GE0GL, &[ix as _], false);
+            self.emit(Opcode::OP_RET, &[], false);
+        }
         Ok(())
     }
 
@@ -1077,6 +1915,7 @@ impl ByteCompiler {
             parent: Some(self.scope.clone()),
             depth: self.scope.borrow().depth,
             variables: Default::default(),
+            function: self.scope.borrow().function,
         }));
         self.scope = new_scope;
         d
@@ -1084,19 +1923,128 @@ impl ByteCompiler {
     pub fn pop_scope(&mut self) {
         let scope = self.scope.clone();
         self.scope = scope.borrow().parent.clone().expect("No scopes left");
+        self.record_var_names(&scope.borrow());
+        if self.code.var_names.is_some() {
+            // every index keeps a single name.
+            return;
+        }
         for var in scope.borrow().variables.iter() {
             if !var.1.dont_free {
                 self.variable_freelist.push(var.1.index as u32);
             }
         }
     }
+
+    /// Record names of bindings declared in `scope` when the code block keeps [CodeBlock::var_names].
+    fn record_var_names(&mut self, scope: &Scope) {
+        if let Some(ref mut names) = self.code.var_names {
+            for var in scope.variables.values() {
+                let table = if var.slot {
+                    &mut names.slots
+                } else {
+                    &mut names.env
+                };
+                VariableNames::set(table, var.index as _, var.name);
+            }
+        }
+    }
     pub fn push_lci(&mut self, _continue_target: u32, _depth: u32) {
         self.lci.push(LoopControlInfo {
             continues: vec![],
             breaks: vec![],
+            stack_values: 0,
         });
     }
 
+    /// Drop exception handlers of `try` statements left by completion of `kind` and jump into the
+    /// first `finally` block on the way, which continues the completion after it runs. `return` leaves
+    /// every statement, `break` and `continue` only the ones inside the innermost loop or switch.
+    ///
+    /// Returns `false` if there is no `finally` block on the way.
+    fn leave_try_levels(&mut self, kind: u32) -> Result<bool, CompileError> {
+        for i in (0..self.try_levels.len()).rev() {
+            let lci_depth = self.try_levels[i].lci_depth;
+            if kind != COMPLETION_RETURN && lci_depth < self.lci.len() {
+                break;
+            }
+            for _ in 0..self.try_levels[i].handlers {
+                self.emit(Opcode::OP_POP_CATCH, &[], false);
+            }
+            if let Some((kind_var, _)) = self.try_levels[i].finally.clone() {
+                let values = self.lci[lci_depth..]
+                    .iter()
+                    .map(|lci| lci.stack_values)
+                    .sum::<u32>();
+                for _ in 0..values {
+                    self.emit(Opcode::OP_POP, &[], false);
+                }
+                self.emit(Opcode::OP_PUSH_INT, &[kind], false);
+                self.access_set(kind_var)?;
+                let jfinally = self.jmp();
+                let level = &mut self.try_levels[i];
+                level.jumps.push(Box::new(jfinally));
+                if !level.kinds.contains(&kind) {
+                    level.kinds.push(kind);
+                }
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Return value on top of the stack.
+    fn emit_return(&mut self) -> Result<(), CompileError> {
+        let finally = self
+            .try_levels
+            .iter()
+            .rev()
+            .find_map(|level| level.finally.clone());
+        match finally {
+            Some((_, value_var)) => {
+                self.access_set(value_var)?;
+                self.leave_try_levels(COMPLETION_RETURN)?;
+            }
+            None => self.emit(Opcode::OP_RET, &[], false),
+        }
+        Ok(())
+    }
+
+    fn emit_break(&mut self, kind: u32) -> Result<(), CompileError> {
+        if self.leave_try_levels(kind)? {
+            return Ok(());
+        }
+        let j = Box::new(self.jmp());
+        let lci = self.lci.last_mut().unwrap();
+        if kind == COMPLETION_BREAK {
+            lci.breaks.push(j);
+        } else {
+            lci.continues.push(j);
+        }
+        Ok(())
+    }
+
+    /// Compile block statement in its own scope.
+    fn block(&mut self, ctx: GcPointer<Context>, block: &BlockStmt) -> Result<(), CompileError> {
+        self.push_scope();
+        self.analyze(ctx, &block.stmts)?;
+        for stmt in block.stmts.iter() {
+            self.stmt(ctx, stmt)?;
+        }
+        self.pop_scope();
+        Ok(())
+    }
+
+    /// Allocate variable that is not visible to JS code. It lives in a stack slot when the function
+    /// may run without an environment.
+    fn alloc_hidden(&mut self) -> Access {
+        if self.captures.is_some() {
+            return Access::Slot(self.alloc_slot());
+        }
+        let ix = self.code.var_count as u16;
+        self.code.var_count += 1;
+        Access::Variable(ix, 0)
+    }
+
     pub fn pop_lci(&mut self) {
         let mut lci = self.lci.pop().unwrap();
         while let Some(break_) = lci.breaks.pop() {
@@ -1128,17 +2076,152 @@ impl ByteCompiler {
                 }
             }
 
+            Decl::Class(class) => {
+                let name = Self::ident_to_sym(&class.ident);
+                let hoisted = self
+                    .scope
+                    .borrow()
+                    .variables
+                    .get(&name)
+                    .filter(|v| matches!(v.kind, VariableKind::Let))
+                    .map(|v| v.index);
+                let ix = hoisted.unwrap_or_else(|| self.alloc_let(name));
+                let static_fields = self.class_definition(ctx, &class.class, name)?;
+                self.emit(Opcode::OP_DUP, &[], false);
+                let op = if self.scope.borrow().variables[&name].slot {
+                    Opcode::OP_SET_SLOT
+                } else {
+                    Opcode::OP_DECL_LET
+                };
+                self.emit(op, &[ix as _], false);
+                self.mark_initialized(name);
+                // static initializers may refer to the class by name.
+                self.class_static_init(ctx, class.class.span, static_fields)?;
+                self.emit(Opcode::OP_POP, &[], false);
+                if export {
+                    let var = self.access_var(name);
+                    self.access_get(var)?;
+                    let module = self.access_var("@module".intern());
+                    self.access_get(module)?;
+                    let exports = self.get_sym("@exports".intern());
+                    self.emit(Opcode::OP_GET_BY_ID, &[exports], true);
+                    let sym = self.get_sym(name);
+                    self.emit(Opcode::OP_PUT_BY_ID, &[sym], true);
+                }
+            }
+
             x => {
-                return Err(CompileError::NotYetImpl(format!("NYI Decl: {:?}", x)));
+                return Err(nyi(x));
             }
         }
         Ok(())
     }
     pub fn stmt(&mut self, ctx: GcPointer<Context>, stmt: &Stmt) -> Result<(), CompileError> {
+        let start = self.code.code.len();
+        if ctx.vm.debugger.is_some()
+            && !matches!(
+                stmt,
+                Stmt::Block(_) | Stmt::Empty(_) | Stmt::Debugger(_) | Stmt::Decl(Decl::Fn(_))
+            )
+        {
+            self.emit(Opcode::OP_DEBUG, &[DEBUG_STATEMENT], false);
+        }
+        if let Some(ix) = self.completion {
+            // statements that may complete without evaluating any expression produce `undefined`,
+            // `1; if (false) 2` evaluates to `undefined`.
+            if matches!(
+                stmt,
+                Stmt::If(_)
+                    | Stmt::Switch(_)
+                    | Stmt::Try(_)
+                    | Stmt::While(_)
+                    | Stmt::DoWhile(_)
+                    | Stmt::For(_)
+                    | Stmt::ForIn(_)
+                    | Stmt::ForOf(_)
+                    | Stmt::With(_)
+            ) {
+                self.emit(Opcode::OP_PUSH_UNDEF, &[], false);
+                self.emit(Opcode::OP_GE0SL, &[ix as _], false);
+            }
+        }
+        self.stmt_body(ctx, stmt)?;
+        self.record_location(start, stmt.span());
+        Ok(())
+    }
+
+    /// Script text and range of `span` in it for [CodeBlock::source].
+    fn source_of(&self, span: Span) -> Option<(Lrc<String>, Range<usize>)> {
+        match self.source_file {
+            Some(ref file) if !span.is_dummy() => Some((
+                file.src.clone(),
+                (span.lo - file.start_pos).0 as usize..(span.hi - file.start_pos).0 as usize,
+            )),
+            _ => None,
+        }
+    }
+
+    /// Source text of `span`, `None` when compiled code has no source file.
+    fn source_text(&self, span: Span) -> Option<&str> {
+        let file = self.source_file.as_ref()?;
+        if span.is_dummy() {
+            return None;
+        }
+        file.src
+            .get((span.lo - file.start_pos).0 as usize..(span.hi - file.start_pos).0 as usize)
+    }
+
+    /// Reject legacy octal numbers (`010`, `08`) and octal escapes in strings (`"\01"`) in strict code.
+    fn check_strict_literal(&self, lit: &Lit) -> Result<(), CompileError> {
+        if !self.code.strict {
+            return Ok(());
+        }
+        let error = match lit {
+            Lit::Num(num) => match self.source_text(num.span).map(str::as_bytes) {
+                Some([b'0', digit, ..]) if digit.is_ascii_digit() => {
+                    "Octal literals are not allowed in strict mode"
+                }
+                _ => return Ok(()),
+            },
+            Lit::Str(str) if str.has_escape => match self.source_text(str.span) {
+                Some(raw) if has_octal_escape(raw) => {
+                    "Octal escape sequences are not allowed in strict mode"
+                }
+                _ => return Ok(()),
+            },
+            _ => return Ok(()),
+        };
+        Err(CompileError::Syntax(error.to_string()))
+    }
+
+    /// Map bytecode emitted since `start` to the line of `span`.
+    fn record_location(&mut self, start: usize, span: Span) {
+        let end = self.code.code.len();
+        if start == end || span.is_dummy() {
+            return;
+        }
+        if let Some(loc) = self.location(span) {
+            self.code.loc.push((start..end, loc));
+        }
+    }
+
+    /// One-based line and column of the start of `span`.
+    fn location(&self, span: Span) -> Option<FileLocation> {
+        let file = self.source_file.as_ref()?;
+        let line = file.lookup_line(span.lo)?;
+        Some(FileLocation {
+            line: line as u32 + 1,
+            col: span.lo.0 - file.lines[line].0 + 1,
+        })
+    }
+
+    fn stmt_body(&mut self, ctx: GcPointer<Context>, stmt: &Stmt) -> Result<(), CompileError> {
         match stmt {
             Stmt::Switch(switch) => {
-                let d = self.scope.borrow().depth;
+                // cases share one block scope.
+                let d = self.push_scope();
                 self.push_lci(0, d);
+                self.lci.last_mut().unwrap().stack_values = 1;
                 self.expr(ctx, &switch.discriminant, true, false)?;
 
                 let mut last_jump: Option<Box<dyn FnOnce(&mut ByteCompiler)>> = None;
@@ -1171,39 +2254,30 @@ impl ByteCompiler {
                     }
                 }
                 self.pop_lci();
+                self.pop_scope();
                 self.emit(Opcode::OP_POP, &[], false);
             }
-            Stmt::Expr(expr) => {
-                self.expr(ctx, &expr.expr, false, false)?;
-            }
-            Stmt::Block(block) => {
-                let _prev = self.push_scope();
-                self.analyze(ctx, &block.stmts)?;
-                // self.emit(Opcode::OP_PUSH_ENV, &[], false);
-                for stmt in block.stmts.iter() {
-                    self.stmt(ctx, stmt)?;
+            Stmt::Expr(expr) => match self.completion {
+                Some(ix) => {
+                    self.expr(ctx, &expr.expr, true, false)?;
+                    self.emit(Opcode::OP_GE0SL, &[ix as _], false);
                 }
-                self.pop_scope();
-                //self.emit(Opcode::OP_POP_ENV, &[], false);
-                //self.emit(Opcode::OP_SET_ENV, &[prev], false);
-            }
+                None => self.expr(ctx, &expr.expr, false, false)?,
+            },
+            Stmt::Block(block) => self.block(ctx, block)?,
             Stmt::Return(ret) => {
-                self.tail_pos = true;
+                // call inside `try` with `finally` block is not in tail position.
+                let tail = self.try_levels.iter().all(|level| level.finally.is_none());
+                self.tail_pos = tail;
                 match ret.arg {
-                    Some(ref arg) => self.expr(ctx, arg, true, true)?,
+                    Some(ref arg) => self.expr(ctx, arg, true, tail)?,
                     None => self.emit(Opcode::OP_PUSH_UNDEF, &[], false),
                 };
                 self.tail_pos = false;
-                self.emit(Opcode::OP_RET, &[], false);
-            }
-            Stmt::Break(_) => {
-                let br = self.jmp();
-                self.lci.last_mut().unwrap().breaks.push(Box::new(br));
-            }
-            Stmt::Continue(_) => {
-                let j = self.jmp();
-                self.lci.last_mut().unwrap().continues.push(Box::new(j));
+                self.emit_return()?;
             }
+            Stmt::Break(_) => self.emit_break(COMPLETION_BREAK)?,
+            Stmt::Continue(_) => self.emit_break(COMPLETION_CONTINUE)?,
             Stmt::ForIn(for_in) => {
                 let depth = self.push_scope();
 
@@ -1219,16 +2293,17 @@ impl ByteCompiler {
                         self.decl_let(sym);
                         sym
                     }
-                    ref x => return Err(CompileError::NotYetImpl(format!("{:?}", x))),
+                    ref x => return Err(nyi(x)),
                 };
 
                 self.expr(ctx, &for_in.right, true, false)?;
                 let for_in_setup = self.jmp_custom(Opcode::OP_FORIN_SETUP);
                 let head = self.code.code.len();
                 self.push_lci(head as _, depth);
+                self.lci.last_mut().unwrap().stack_values = 1;
                 let for_in_enumerate = self.jmp_custom(Opcode::OP_FORIN_ENUMERATE);
                 let acc = self.access_var(name);
-                self.access_set(acc)?;
+                self.access_init(acc)?;
                 //self.emit(Opcode::OP_SET_LOCAL, &[name], true);
                 self.stmt(ctx, &for_in.body)?;
                 while let Some(c) = self.lci.last_mut().unwrap().continues.pop() {
@@ -1243,8 +2318,9 @@ impl ByteCompiler {
                 // self.emit(Opcode::OP_POP_ENV, &[], false);
                 self.pop_scope();
 
-                self.emit(Opcode::OP_FORIN_LEAVE, &[], false);
+                // `break` leaves iterator on the stack as well.
                 self.pop_lci();
+                self.emit(Opcode::OP_FORIN_LEAVE, &[], false);
             }
             Stmt::ForOf(for_of) => {
                 let depth = self.push_scope();
@@ -1260,7 +2336,7 @@ impl ByteCompiler {
                         self.decl_let(sym);
                         sym
                     }
-                    ref x => return Err(CompileError::NotYetImpl(format!("NYI: {:?}", x))),
+                    ref x => return Err(nyi(x)),
                 };
                 let iterator_id = "Symbol.iterator".intern().private();
                 let iterator = self.get_sym(iterator_id);
@@ -1274,6 +2350,7 @@ impl ByteCompiler {
 
                 let head = self.code.code.len();
                 self.push_lci(head as _, depth);
+                self.lci.last_mut().unwrap().stack_values = 1;
                 // iterator is on stack, dup it twice to invoke `next` on it.
                 self.emit(Opcode::OP_DUP, &[], false);
                 self.emit(Opcode::OP_DUP, &[], false);
@@ -1284,7 +2361,7 @@ impl ByteCompiler {
                 let end = self.cjmp(true);
                 self.emit(Opcode::OP_GET_BY_ID, &[value], true);
                 let acc = self.access_var(name);
-                self.access_set(acc)?;
+                self.access_init(acc)?;
                 self.stmt(ctx, &for_of.body)?;
                 while let Some(c) = self.lci.last_mut().unwrap().continues.pop() {
                     c(self);
@@ -1360,58 +2437,89 @@ impl ByteCompiler {
             }
             Stmt::Decl(decl) => self.decl(ctx, decl, false)?,
             Stmt::Empty(_) => {}
+            Stmt::Debugger(_) => self.emit(Opcode::OP_DEBUG, &[DEBUG_DEBUGGER_STATEMENT], false),
             Stmt::Throw(throw) => {
                 self.expr(ctx, &throw.arg, true, false)?;
                 self.emit(Opcode::OP_THROW, &[], false);
             }
             Stmt::Try(try_stmt) => {
-                let try_push = self.try_();
-
-                for stmt in try_stmt.block.stmts.iter() {
-                    self.stmt(ctx, stmt)?;
-                }
-                self.emit(Opcode::OP_POP_CATCH, &[], false);
-                let jfinally = self.jmp();
-                try_push(self);
-                self.emit(Opcode::OP_ENTER_CATCH, &[], false);
-                let jcatch_finally = match try_stmt.handler {
-                    Some(ref catch) => {
-                        self.push_scope();
-
-                        match catch.param {
-                            Some(ref pat) => {
-                                let acc = self.compile_access_pat(ctx, pat, false)?;
-                                self.access_set(acc)?;
-                            }
-                            None => {
-                                self.emit(Opcode::OP_POP, &[], false);
-                            }
-                        }
-                        for stmt in catch.body.stmts.iter() {
-                            self.stmt(ctx, stmt)?;
-                        }
-                        self.pop_scope();
-                        self.jmp()
+                // Every way out of `try` and `catch` blocks goes through `finally` block: it is entered with
+                // completion kind and value stored in hidden variables and continues the completion
+                // once it completes normally.
+                let finally = try_stmt
+                    .finalizer
+                    .as_ref()
+                    .map(|_| (self.alloc_hidden(), self.alloc_hidden()));
+                let finally_push = finally.as_ref().map(|_| self.try_());
+                let catch_push = try_stmt.handler.as_ref().map(|_| self.try_());
+                self.try_levels.push(TryLevel {
+                    handlers: finally.is_some() as u32 + try_stmt.handler.is_some() as u32,
+                    lci_depth: self.lci.len(),
+                    finally: finally.clone(),
+                    jumps: vec![],
+                    kinds: vec![],
+                });
+                self.block(ctx, &try_stmt.block)?;
+                if let (Some(catch), Some(catch_push)) = (&try_stmt.handler, catch_push) {
+                    self.emit(Opcode::OP_POP_CATCH, &[], false);
+                    let jend = self.jmp();
+                    catch_push(self);
+                    self.emit(Opcode::OP_ENTER_CATCH, &[], false);
+                    self.try_levels.last_mut().unwrap().handlers -= 1;
+                    // catch parameter gets its own scope around the scope of the body.
+                    self.push_scope();
+                    match catch.param {
+                        Some(ref pat) => self.bind_pat(ctx, pat)?,
+                        None => self.emit(Opcode::OP_POP, &[], false),
                     }
-                    None => {
-                        self.emit(Opcode::OP_POP, &[], false);
-                        self.jmp()
+                    self.block(ctx, &catch.body)?;
+                    self.pop_scope();
+                    jend(self);
+                }
+                let level = self.try_levels.pop().unwrap();
+
+                if let (Some(block), Some((kind_var, value_var)), Some(finally_push)) =
+                    (&try_stmt.finalizer, finally, finally_push)
+                {
+                    self.emit(Opcode::OP_POP_CATCH, &[], false);
+                    self.emit(Opcode::OP_PUSH_INT, &[COMPLETION_NORMAL], false);
+                    self.access_set(kind_var.clone())?;
+                    let jfinally = self.jmp();
+                    finally_push(self);
+                    self.emit(Opcode::OP_ENTER_CATCH, &[], false);
+                    self.access_set(value_var.clone())?;
+                    self.emit(Opcode::OP_PUSH_INT, &[COMPLETION_THROW], false);
+                    self.access_set(kind_var.clone())?;
+                    jfinally(self);
+                    for jump in level.jumps {
+                        jump(self);
                     }
-                };
-
-                jfinally(self);
-                jcatch_finally(self);
-                match try_stmt.finalizer {
-                    Some(ref block) => {
-                        self.push_scope();
 
-                        for stmt in block.stmts.iter() {
-                            self.stmt(ctx, stmt)?;
+                    // normal completion of `finally` does not replace completion value of `try`.
+                    let completion = self.completion.take();
+                    self.block(ctx, block)?;
+                    self.completion = completion;
+
+                    let mut kinds = vec![COMPLETION_THROW];
+                    kinds.extend(level.kinds);
+                    for kind in kinds {
+                        self.access_get(kind_var.clone())?;
+                        self.emit(Opcode::OP_PUSH_INT, &[kind], false);
+                        self.emit(Opcode::OP_STRICTEQ, &[], false);
+                        let skip = self.cjmp(false);
+                        match kind {
+                            COMPLETION_THROW => {
+                                self.access_get(value_var.clone())?;
+                                self.emit(Opcode::OP_THROW, &[], false);
+                            }
+                            COMPLETION_RETURN => {
+                                self.access_get(value_var.clone())?;
+                                self.emit_return()?;
+                            }
+                            _ => self.emit_break(kind)?,
                         }
-
-                        self.pop_scope();
+                        skip(self);
                     }
-                    None => {}
                 }
             }
             Stmt::While(while_stmt) => {
@@ -1444,8 +2552,19 @@ impl ByteCompiler {
                 jend(self);
                 self.pop_lci();
             }
+            Stmt::With(with) => {
+                let mut msg = if self.code.strict {
+                    "Strict mode code may not include a with statement".to_string()
+                } else {
+                    "with statement is not supported".to_string()
+                };
+                if let Some(loc) = self.location(with.span) {
+                    msg.push_str(&format!(" ({}:{})", loc.line, loc.col));
+                }
+                return Err(CompileError::Syntax(msg));
+            }
             x => {
-                return Err(CompileError::NotYetImpl(format!("NYI: {:?}", x)));
+                return Err(nyi(x));
             }
         }
         Ok(())
@@ -1492,11 +2611,104 @@ impl ByteCompiler {
                 self.compile_pat_decl(&x.left)?;
             }
             x => {
-                return Err(CompileError::NotYetImpl(format!("NYI: {:?}", x)));
+                return Err(nyi(x));
+            }
+        }
+        Ok(())
+    }
+    /// Declare `let` bindings of `pat` in the current scope and initialize them by destructuring value on
+    /// top of the stack.
+    fn bind_pat(&mut self, ctx: GcPointer<Context>, pat: &Pat) -> Result<(), CompileError> {
+        match pat {
+            Pat::Ident(id) => {
+                let name = Self::ident_to_sym(&id.id);
+                self.decl_let(name);
+                self.mark_initialized(name);
+            }
+            Pat::Assign(assign) => {
+                self.default_value(ctx, &assign.right)?;
+                self.bind_pat(ctx, &assign.left)?;
+            }
+            Pat::Object(object) => {
+                for prop in object.props.iter() {
+                    self.emit(Opcode::OP_DUP, &[], false);
+                    match prop {
+                        ObjectPatProp::KeyValue(keyvalue) => {
+                            self.get_prop_name(ctx, &keyvalue.key)?;
+                            self.bind_pat(ctx, &keyvalue.value)?;
+                        }
+                        ObjectPatProp::Assign(assign) => {
+                            let name = Self::ident_to_sym(&assign.key);
+                            let sym = self.get_sym(name);
+                            self.emit(Opcode::OP_GET_BY_ID, &[sym], true);
+                            if let Some(ref value) = assign.value {
+                                self.default_value(ctx, value)?;
+                            }
+                            self.decl_let(name);
+                            self.mark_initialized(name);
+                        }
+                        ObjectPatProp::Rest(x) => {
+                            return Err(nyi(x));
+                        }
+                    }
+                }
+                self.emit(Opcode::OP_POP, &[], false);
+            }
+            x => return Err(nyi(x)),
+        }
+        Ok(())
+    }
+
+    /// Replace `undefined` on top of the stack with value of `default`.
+    fn default_value(
+        &mut self,
+        ctx: GcPointer<Context>,
+        default: &Expr,
+    ) -> Result<(), CompileError> {
+        self.emit(Opcode::OP_DUP, &[], false);
+        self.emit(Opcode::OP_PUSH_UNDEF, &[], false);
+        self.emit(Opcode::OP_STRICTEQ, &[], false);
+        let skip = self.cjmp(false);
+        self.emit(Opcode::OP_POP, &[], false);
+        self.expr(ctx, default, true, false)?;
+        skip(self);
+        Ok(())
+    }
+
+    /// Replace object on top of the stack with value of its property `key`.
+    fn get_prop_name(
+        &mut self,
+        ctx: GcPointer<Context>,
+        key: &PropName,
+    ) -> Result<(), CompileError> {
+        match key {
+            PropName::Ident(id) => {
+                let sym = Self::ident_to_sym(id);
+                let sym = self.get_sym(sym);
+                self.emit(Opcode::OP_GET_BY_ID, &[sym], true);
+                return Ok(());
+            }
+            PropName::Str(s) => {
+                let ix = self.get_val(ctx, Val::Str(s.value.to_string()));
+                self.emit(Opcode::OP_PUSH_LITERAL, &[ix], false);
+            }
+            PropName::Num(n) => {
+                let val = n.value;
+                if val as i32 as f64 == val {
+                    self.emit(Opcode::OP_PUSH_INT, &[val as i32 as u32], false);
+                } else {
+                    let ix = self.get_val(ctx, Val::Float(val.to_bits()));
+                    self.emit(Opcode::OP_PUSH_LITERAL, &[ix], false);
+                }
             }
+            PropName::Computed(computed) => self.expr(ctx, &computed.expr, true, false)?,
+            x => return Err(nyi(x)),
         }
+        self.emit(Opcode::OP_SWAP, &[], false);
+        self.emit(Opcode::OP_GET_BY_VAL, &[0], false);
         Ok(())
     }
+
     pub fn compile_access_pat(
         &mut self,
         ctx: GcPointer<Context>,
@@ -1517,12 +2729,9 @@ impl ByteCompiler {
                         _ => (),
                     }
                 }
-                Err(CompileError::NotYetImpl(format!(
-                    "NYI: Array access: {:?}",
-                    array
-                )))
+                Err(nyi(array))
             }
-            x => Err(CompileError::NotYetImpl(format!("NYI:  {:?}", x))),
+            x => Err(nyi(x)),
         }
     }
 
@@ -1536,7 +2745,7 @@ impl ByteCompiler {
         match expr {
             Expr::Yield(yield_expr) => {
                 if yield_expr.delegate {
-                    return Err(CompileError::NotYetImpl("NYI: yield*".to_string()));
+                    return Err(CompileError::NotYetImpl("yield*".to_string()));
                 }
                 match yield_expr.arg {
                     Some(ref expr) => {
@@ -1575,6 +2784,7 @@ impl ByteCompiler {
                     }
                     Lit::Null(_) => self.emit(Opcode::OP_PUSH_NULL, &[], false),
                     Lit::Num(num) => {
+                        self.check_strict_literal(lit)?;
                         if num.value as i32 as f64 == num.value {
                             self.emit(Opcode::OP_PUSH_INT, &[num.value as i32 as u32], false)
                         } else {
@@ -1583,6 +2793,7 @@ impl ByteCompiler {
                         }
                     }
                     Lit::Str(str) => {
+                        self.check_strict_literal(lit)?;
                         let str = self.get_val(ctx, Val::Str(str.value.to_string()));
                         self.emit(Opcode::OP_PUSH_LITERAL, &[str], false);
                     }
@@ -1593,33 +2804,19 @@ impl ByteCompiler {
                         let flags = JsString::new(ctx, flags);
                         let mut args = [JsValue::new(exp), JsValue::new(flags)];
                         let args = Arguments::new(JsValue::encode_undefined_value(), &mut args);
-                        let regexp =
-                            crate::jsrt::regexp::regexp_constructor(ctx, &args).map_err(|e| {
-                                CompileError::NotYetImpl(format!(
-                                    "Regexp constructor error {}",
-                                    e.to_string(ctx).unwrap()
-                                ))
-                            })?;
+                        let regexp = crate::jsrt::regexp::regexp_constructor(ctx, &args)
+                            .map_err(CompileError::Val)?;
                         let val = self.get_val2(regexp);
                         self.emit(Opcode::OP_PUSH_LITERAL, &[val], false);
                     }
                     Lit::BigInt(_) => {
-                        return Err(CompileError::NotYetImpl(
-                            "Unimplemented JS literal: BigInt".to_string(),
-                        ))
+                        return Err(CompileError::NotYetImpl("BigInt literal".to_string()))
                     }
                     Lit::JSXText(_) => {
-                        return Err(CompileError::NotYetImpl(
-                            "Unimplemented JS literal: JSXText".to_string(),
-                        ))
+                        return Err(CompileError::NotYetImpl("JSX text".to_string()))
                     } //todo!("{:?}", x),
                     #[allow(unreachable_patterns)]
-                    x => {
-                        return Err(CompileError::NotYetImpl(format!(
-                            "Unimplemented JS literal: {:?}",
-                            x
-                        )))
-                    }
+                    x => return Err(nyi(x)),
                 }
                 if !used {
                     self.emit(Opcode::OP_POP, &[], false);
@@ -1689,19 +2886,16 @@ impl ByteCompiler {
                                         }
                                     }
                                     ref x => {
-                                        return Err(CompileError::NotYetImpl(format!(
-                                            "NYI: {:?}",
-                                            x
-                                        )));
+                                        return Err(nyi(x));
                                     }
                                 }
                             }
                             p => {
-                                return Err(CompileError::NotYetImpl(format!("NYI: {:?}", p)));
+                                return Err(nyi(p));
                             }
                         },
                         x => {
-                            return Err(CompileError::NotYetImpl(format!("NYI: {:?}", x)));
+                            return Err(nyi(x));
                         }
                     }
                 }
@@ -1725,35 +2919,29 @@ impl ByteCompiler {
             Expr::Call(call) if !is_builtin_call(expr, self.builtins) => {
                 match call.callee {
                     ExprOrSuper::Super(_) => {
-                        return Err(CompileError::NotYetImpl("NYI: super call".to_string()));
+                        return Err(CompileError::NotYetImpl("super call".to_string()));
                     } // todo super call
                     ExprOrSuper::Expr(ref expr) => match &**expr {
                         Expr::Member(member) => {
-                            let name = if let Expr::Ident(id) = &*member.prop {
-                                let s: &str = &id.sym;
-                                let name = s.intern();
-                                Some(self.get_sym(name))
-                            } else {
-                                self.expr(ctx, &member.prop, true, false)?;
-                                None
-                            };
                             match member.obj {
                                 ExprOrSuper::Expr(ref expr) => {
                                     self.expr(ctx, expr, true, false)?;
-                                    if name.is_some() {
-                                        self.emit(Opcode::OP_DUP, &[], false);
-                                    }
                                 }
                                 ExprOrSuper::Super(_super) => {
-                                    return Err(CompileError::NotYetImpl(
-                                        "NYI: super call".to_string(),
-                                    ));
+                                    return Err(CompileError::NotYetImpl("super call".to_string()));
                                 }
                             }
-                            if let Some(name) = name {
-                                self.emit(Opcode::OP_GET_BY_ID, &[name], true);
-                            } else {
-                                self.emit(Opcode::OP_GET_BY_VAL_PUSH_OBJ, &[0], false);
+                            match Self::member_name(member) {
+                                Some(name) => {
+                                    self.emit(Opcode::OP_DUP, &[], false);
+                                    let name = self.get_sym(name);
+                                    self.emit(Opcode::OP_GET_BY_ID, &[name], true);
+                                }
+                                None => {
+                                    self.expr(ctx, &member.prop, true, false)?;
+                                    self.emit(Opcode::OP_SWAP, &[], false);
+                                    self.emit(Opcode::OP_GET_BY_VAL_PUSH_OBJ, &[0], false);
+                                }
                             }
                         }
                         _ => {
@@ -1779,14 +2967,31 @@ impl ByteCompiler {
                     }
                 }
 
-                if !has_spread {
+                let callee = match call.callee {
+                    ExprOrSuper::Expr(ref callee) => &**callee,
+                    ExprOrSuper::Super(_) => unreachable!("super call is rejected above"),
+                };
+                let direct_eval = matches!(callee, Expr::Ident(id) if &id.sym == "eval");
+                if !has_spread && direct_eval {
+                    // eval code resolves names through the scope of the call.
+                    let scope = self.code.eval_scopes.len() as u32;
+                    self.code.eval_scopes.push(self.scope.clone());
+                    self.record_callee(callee);
+                    self.emit(
+                        Opcode::OP_CALL_EVAL,
+                        &[call.args.len() as u32, scope],
+                        false,
+                    );
+                } else if !has_spread {
                     let op = if tail {
                         Opcode::OP_TAILCALL
                     } else {
                         Opcode::OP_CALL
                     };
+                    self.record_callee(callee);
                     self.emit(op, &[call.args.len() as u32], false);
                 } else {
+                    self.record_callee(callee);
                     self.emit(
                         Opcode::OP_CALL_BUILTIN,
                         &[call.args.len() as _, 0, 0],
@@ -1799,6 +3004,11 @@ impl ByteCompiler {
             }
             Expr::Unary(unary) => {
                 if let UnaryOp::Delete = unary.op {
+                    if self.code.strict && matches!(unparen(&unary.arg), Expr::Ident(_)) {
+                        return Err(CompileError::Syntax(
+                            "Delete of an unqualified identifier in strict mode".to_string(),
+                        ));
+                    }
                     let acc = self.compile_access(ctx, &*unary.arg, false)?;
                     self.access_delete(acc);
                     if !used {
@@ -1830,7 +3040,7 @@ impl ByteCompiler {
                         self.emit(Opcode::OP_PUSH_UNDEF, &[], false);
                     }
                     x => {
-                        return Err(CompileError::NotYetImpl(format!("NYI Unary Op: {:?}", x)));
+                        return Err(nyi(x));
                     }
                 }
                 if !used {
@@ -1838,11 +3048,42 @@ impl ByteCompiler {
                 }
             }
             Expr::Update(update) => {
+                if let Expr::Ident(ref id) = &*update.arg {
+                    if let Access::Slot(ix) = self.access_var(Self::ident_to_sym(id)) {
+                        // old value of postfix update has to be converted to number, leave it to generic code.
+                        if update.prefix || !used {
+                            let op = match update.op {
+                                UpdateOp::PlusPlus => Opcode::OP_INC_SLOT,
+                                UpdateOp::MinusMinus => Opcode::OP_DEC_SLOT,
+                            };
+                            self.emit(op, &[ix as _], false);
+                            if used {
+                                self.emit(Opcode::OP_GET_SLOT, &[ix as _], false);
+                            }
+                            return Ok(());
+                        }
+                    }
+                }
                 let op = match update.op {
                     UpdateOp::PlusPlus => Opcode::OP_ADD,
                     UpdateOp::MinusMinus => Opcode::OP_SUB,
                 };
-                if update.prefix {
+                if let Expr::Member(ref member) = *update.arg {
+                    let (object, key) = self.member_ref(ctx, member)?;
+                    self.member_get(&object, &key)?;
+                    if used && !update.prefix {
+                        self.emit(Opcode::OP_DUP, &[], false);
+                    }
+                    self.emit(Opcode::OP_PUSH_INT, &[1i32 as u32], false);
+                    self.emit(op, &[0], false);
+                    if op == Opcode::OP_SUB {
+                        self.emit(Opcode::OP_NEG, &[], false);
+                    }
+                    if used && update.prefix {
+                        self.emit(Opcode::OP_DUP, &[], false);
+                    }
+                    self.member_set(&object, &key)?;
+                } else if update.prefix {
                     self.expr(ctx, &update.arg, true, false)?;
                     self.emit(Opcode::OP_PUSH_INT, &[1i32 as u32], false);
                     self.emit(op, &[0], false);
@@ -1896,6 +3137,7 @@ impl ByteCompiler {
                     }
                 }
 
+                self.record_callee(&call.callee);
                 if !has_spread {
                     let op = if tail {
                         Opcode::OP_TAILNEW
@@ -1923,12 +3165,22 @@ impl ByteCompiler {
 
                     self.access_set(acc)?;
                 } else {
-                    self.expr(ctx, &assign.right, true, false)?;
-                    let left = match &assign.left {
-                        PatOrExpr::Expr(e) => self.compile_access(ctx, e, false)?,
-                        PatOrExpr::Pat(p) => self.compile_access_pat(ctx, p, false)?,
+                    let member = match Self::assign_member(&assign.left) {
+                        Some(member) => Some(self.member_ref(ctx, member)?),
+                        None => None,
                     };
-                    self.access_get(left)?;
+                    if let Some((ref object, ref key)) = member {
+                        self.member_get(object, key)?;
+                        self.expr(ctx, &assign.right, true, false)?;
+                        self.emit(Opcode::OP_SWAP, &[], false);
+                    } else {
+                        self.expr(ctx, &assign.right, true, false)?;
+                        let left = match &assign.left {
+                            PatOrExpr::Expr(e) => self.compile_access(ctx, e, false)?,
+                            PatOrExpr::Pat(p) => self.compile_access_pat(ctx, p, false)?,
+                        };
+                        self.access_get(left)?;
+                    }
 
                     let op = match assign.op {
                         AssignOp::AddAssign => Opcode::OP_ADD,
@@ -1942,7 +3194,7 @@ impl ByteCompiler {
                         AssignOp::RShiftAssign => Opcode::OP_SHR,
                         AssignOp::LShiftAssign => Opcode::OP_SHL,
                         x => {
-                            return Err(CompileError::NotYetImpl(format!("NYI: {:?}", x)));
+                            return Err(nyi(x));
                         }
                     };
                     let additional: &'static [u32] = if op == Opcode::OP_ADD
@@ -1959,11 +3211,15 @@ impl ByteCompiler {
                     if used {
                         self.emit(Opcode::OP_DUP, &[], false);
                     }
-                    let left = match &assign.left {
-                        PatOrExpr::Expr(e) => self.compile_access(ctx, e, false)?,
-                        PatOrExpr::Pat(p) => self.compile_access_pat(ctx, p, false)?,
-                    };
-                    self.access_set(left)?;
+                    if let Some((ref object, ref key)) = member {
+                        self.member_set(object, key)?;
+                    } else {
+                        let left = match &assign.left {
+                            PatOrExpr::Expr(e) => self.compile_access(ctx, e, false)?,
+                            PatOrExpr::Pat(p) => self.compile_access_pat(ctx, p, false)?,
+                        };
+                        self.access_set(left)?;
+                    }
                 }
             }
             Expr::Bin(binary) => {
@@ -2035,7 +3291,7 @@ impl ByteCompiler {
                     BinaryOp::LtEq => self.emit(Opcode::OP_LESSEQ, &[], false),
                     BinaryOp::In => self.emit(Opcode::OP_IN, &[], false),
                     BinaryOp::InstanceOf => self.emit(Opcode::OP_INSTANCEOF, &[], false),
-                    x => return Err(CompileError::NotYetImpl(format!("NYI: {:?}", x))),
+                    x => return Err(nyi(x)),
                 }
 
                 if !used {
@@ -2057,26 +3313,36 @@ impl ByteCompiler {
                 let p = self.code.path.clone();
                 let mut code = CodeBlock::new(ctx, name, false, p);
                 code.file_name = self.code.file_name.clone();
+                code.source = self.source_of(fun.span);
+                let captures = Captures::analyze_arrow(fun, self.preparse.as_deref());
+                code.needs_environment = captures.needs_environment();
                 let mut compiler = ByteCompiler {
                     lci: Vec::new(),
+                    try_levels: Vec::new(),
                     top_level: false,
                     tail_pos: false,
                     builtins: self.builtins,
                     code,
                     variable_freelist: vec![],
                     val_map: Default::default(),
+                    literal_pool: self.literal_pool.clone(),
                     name_map: Default::default(),
-                    info: None,
+                    source_file: self.source_file.clone(),
                     fmap: Default::default(),
                     scope: Rc::new(RefCell::new(Scope {
                         parent: Some(self.scope.clone()),
-                        depth: self.scope.borrow().depth + 1,
+                        depth: self.scope.borrow().depth + code.needs_environment as u32,
                         variables: HashMap::new(),
+                        function: Scope::new_function_id(),
                     })),
                     is_try: true,
+                    captures: Some(captures),
+                    preparse: self.preparse.clone(),
+                    completion: None,
                 };
-                code.strict = is_strict;
+                code.strict = is_strict || self.code.strict;
                 let mut params = vec![];
+                let mut rest = None;
                 let mut rest_at = None;
                 let mut p = 0;
                 for x in fun.params.iter() {
@@ -2084,14 +3350,12 @@ impl ByteCompiler {
                         Pat::Ident(ref x) => {
                             params.push(Self::ident_to_sym(&x.id));
                             p += 1;
-                            compiler
-                                .scope
-                                .borrow_mut()
-                                .add_var(Self::ident_to_sym(&x.id), p - 1);
+                            compiler.declare_param(Self::ident_to_sym(&x.id), p - 1);
                         }
                         Pat::Rest(ref r) => match &*r.arg {
                             Pat::Ident(ref id) => {
                                 p += 1;
+                                rest = Some(Self::ident_to_sym(&id.id));
                                 rest_at = Some(
                                     compiler
                                         .scope
@@ -2100,16 +3364,19 @@ impl ByteCompiler {
                                         as u32,
                                 );
                             }
-                            ref x => return Err(CompileError::NotYetImpl(format!("NYI: {:?}", x))),
+                            ref x => return Err(nyi(x)),
                         },
                         x => {
-                            return Err(CompileError::NotYetImpl(format!("NYI: {:?}", x)));
+                            return Err(nyi(x));
                         }
                     }
                 }
                 code.rest_at = rest_at;
                 code.param_count = params.len() as _;
+                params.extend(rest);
+                check_duplicate_params(&params, false)?;
                 code.var_count = p as _;
+                compiler.reserve_param_slots();
                 match &fun.body {
                     BlockStmtOrExpr::BlockStmt(block) => {
                         compiler.compile(ctx, &block.stmts, false)?;
@@ -2137,6 +3404,16 @@ impl ByteCompiler {
             Expr::Fn(fun) => {
                 self.fn_expr(ctx, fun, used)?;
             }
+            Expr::Class(class) => {
+                let name = match class.ident {
+                    Some(ref id) => Self::ident_to_sym(id),
+                    None => "<anonymous>".intern(),
+                };
+                self.class(ctx, &class.class, name)?;
+                if !used {
+                    self.emit(Opcode::OP_POP, &[], false);
+                }
+            }
 
             Expr::Array(array_lit) => {
                 for expr in array_lit.elems.iter().rev() {
@@ -2170,7 +3447,7 @@ impl ByteCompiler {
                 self.expr(ctx, &p.expr, used, false)?;
             }
             x => {
-                return Err(CompileError::NotYetImpl(format!("NYI: {:?}", x)));
+                return Err(nyi(x));
             }
         }
         Ok(())
@@ -2370,6 +3647,87 @@ impl Visit for IdentFinder<'_> {
     }
 }
 
+/// [CompileError::NotYetImpl] naming the variant of unsupported `node`, e.g. `Labeled` for a labeled statement.
+/// The AST itself is not included, it may be as large as the script.
+fn nyi(node: impl std::fmt::Debug) -> CompileError {
+    let text = format!("{:?}", node);
+    let end = text
+        .find(|c: char| !c.is_alphanumeric() && c != '_')
+        .unwrap_or(text.len());
+    CompileError::NotYetImpl(text[..end].to_string())
+}
+
+/// Does raw string literal `raw` contain a legacy octal escape like `\01` or `\7`, or `\8`/`\9`? `\0` not
+/// followed by a digit is the null character and allowed.
+fn has_octal_escape(raw: &str) -> bool {
+    let mut chars = raw.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            continue;
+        }
+        match chars.next() {
+            Some('0') => {
+                if chars.peek().map_or(false, |c| c.is_ascii_digit()) {
+                    return true;
+                }
+            }
+            Some('1'..='9') => return true,
+            _ => {}
+        }
+    }
+    false
+}
+
+fn unparen(expr: &Expr) -> &Expr {
+    match expr {
+        Expr::Paren(paren) => unparen(&paren.expr),
+        _ => expr,
+    }
+}
+
+/// Early error for duplicate parameter names. They are allowed only in sloppy functions with a simple
+/// parameter list, so `simple_sloppy` is false for strict code, arrow functions and rest parameters.
+fn check_duplicate_params(names: &[Symbol], simple_sloppy: bool) -> Result<(), CompileError> {
+    if simple_sloppy {
+        return Ok(());
+    }
+    for (i, name) in names.iter().enumerate() {
+        if names[..i].contains(name) {
+            return Err(CompileError::Syntax(
+                "Duplicate parameter name not allowed in this context".to_string(),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Short text of callee `expr` for error messages, e.g. `a.b[0].c` or `f(...)`. `None` for expressions that
+/// do not read well in a message, like function literals.
+fn callee_text(expr: &Expr) -> Option<String> {
+    Some(match expr {
+        Expr::Ident(id) => id.sym.to_string(),
+        Expr::This(_) => "this".to_string(),
+        Expr::Paren(paren) => return callee_text(&paren.expr),
+        Expr::Member(member) => {
+            let object = match member.obj {
+                ExprOrSuper::Expr(ref obj) => callee_text(obj)?,
+                ExprOrSuper::Super(_) => "super".to_string(),
+            };
+            match &*member.prop {
+                Expr::Ident(id) if !member.computed => format!("{}.{}", object, id.sym),
+                Expr::Lit(Lit::Str(s)) => format!("{}[\"{}\"]", object, s.value),
+                Expr::Lit(Lit::Num(n)) => format!("{}[{}]", object, n.value),
+                prop => format!("{}[{}]", object, callee_text(prop)?),
+            }
+        }
+        Expr::Call(call) => match call.callee {
+            ExprOrSuper::Expr(ref callee) => format!("{}(...)", callee_text(callee)?),
+            ExprOrSuper::Super(_) => return None,
+        },
+        _ => return None,
+    })
+}
+
 fn is_codegen_plugin_call(ctx: GcPointer<Context>, e: &Expr, builtins: bool) -> bool {
     if !builtins && !ctx.vm.options.codegen_plugins {
         return false;
@@ -2405,7 +3763,7 @@ fn is_builtin_call(e: &Expr, builtin_compilation: bool) -> bool {
                 }
                 // foo.___call(x,y)
                 // now first support foo.___call
-                Expr::Member(m) => {
+                Expr::Member(m) if !m.computed => {
                     if let Expr::Ident(x) = &*m.prop {
                         let str = &*x.sym;
                         return str == "___call";
@@ -2429,12 +3787,12 @@ impl ByteCompiler {
             if let Expr::Ident(x) = &**expr {
                 &*x.sym
             } else {
-                return Err(CompileError::NotYetImpl(
+                return Err(CompileError::Syntax(
                     "Incorrect codegen plugin syntax".to_string(),
                 ));
             }
         } else {
-            return Err(CompileError::NotYetImpl(
+            return Err(CompileError::Syntax(
                 "Incorrect codegen plugin syntax".to_string(),
             ));
         };
@@ -2526,17 +3884,348 @@ impl ByteCompiler {
                             let operands: u32 = (call.args.len() - 1).try_into().unwrap();
                             self.emit(Opcode::OP_CALL, &[operands], false);
                         } else {
-                            todo!()
+                            return Err(CompileError::NotYetImpl(
+                                "___call on a non-identifier".to_string(),
+                            ));
                         }
                     } else {
-                        todo!()
+                        return Err(CompileError::NotYetImpl("super.___call".to_string()));
                     }
                 } else {
                     unreachable!()
                 }
             }
-            _ => todo!("{}", nstr),
+            _ => return Err(CompileError::Syntax(format!("Unknown intrinsic {}", nstr))),
         }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        gc::cell::GcPointer,
+        options::Options,
+        vm::{
+            context::Context,
+            symbol_table::Internable,
+            tests::{new_context, new_context_with},
+        },
+    };
+
+    #[test]
+    fn test_temporal_dead_zone() {
+        let (_vm, mut ctx) = new_context();
+
+        let result = ctx
+            .eval(
+                "function early() { return late; }
+                 var sum = 0;
+                 for (let i = 0; i < 3; i++) { let x = i; sum += x; }
+                 for (const v of [1, 2]) { sum += v; }
+                 let late = 10;
+                 early() + sum",
+            )
+            .ok()
+            .unwrap();
+        assert_eq!(result.get_number(), 16.0);
+
+        let message = |ctx: &mut GcPointer<Context>, src: &str| {
+            let err = ctx.eval(src).err().unwrap();
+            err.to_string(*ctx).ok().unwrap()
+        };
+        let err = message(&mut ctx, "function f() { return y; } f(); let y = 1;");
+        assert!(err.contains("ReferenceError"), "{}", err);
+        assert!(
+            err.contains("Cannot access 'y' before initialization"),
+            "{}",
+            err
+        );
+        let err = message(&mut ctx, "{ z = 2; let z = 1; }");
+        assert!(err.contains("'z'"), "{}", err);
+        let err = message(&mut ctx, "{ const c = 1; c += 1; }");
+        assert!(err.contains("TypeError"), "{}", err);
+        assert!(
+            err.contains("Assignment to constant variable 'c'"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_temporal_dead_zone_in_closures() {
+        let message = |ctx: &mut GcPointer<Context>, src: &str| {
+            let err = ctx.eval(src).err().unwrap();
+            err.to_string(*ctx).ok().unwrap()
+        };
+        // the closure has no environment of its own and reads `x` after the next iteration reset it.
+        let (_vm, mut ctx) = new_context();
+        let err = message(
+            &mut ctx,
+            "var f; for (var i = 0; i < 2; i++) { if (f) f(); let x = i; f = () => x; }",
+        );
+        assert!(
+            err.contains("Cannot access 'x' before initialization"),
+            "{}",
+            err
+        );
+
+        // `early` is compiled on its first call, after the declaration of `late` was compiled.
+        let (_vm, mut ctx) = new_context_with(Options::default().with_lazy_compilation(true));
+        let err = message(
+            &mut ctx,
+            "function early() { return late; } early(); let late = 1;",
+        );
+        assert!(
+            err.contains("Cannot access 'late' before initialization"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_error_messages_name_callee() {
+        let (_vm, mut ctx) = new_context();
+
+        let message = |ctx: &mut GcPointer<Context>, src: &str| {
+            let err = ctx.eval(src).err().unwrap();
+            err.to_string(*ctx).ok().unwrap()
+        };
+        let cases = [
+            ("var o = {}; o.foo();", "TypeError: o.foo is not a function"),
+            ("var x = 1; x();", "TypeError: x is not a function"),
+            ("var a = [1]; a[0]();", "TypeError: a[0] is not a function"),
+            (
+                "var m = { m: function () { return {}; } }; m.m().n();",
+                "TypeError: m.m(...).n is not a function",
+            ),
+            (
+                "var s = {}; s.bar(...[1]);",
+                "TypeError: s.bar is not a function",
+            ),
+            (
+                "var k = {}; new k.C();",
+                "TypeError: k.C is not a constructor",
+            ),
+            ("var n = 5; new n();", "TypeError: n is not a constructor"),
+            ("missing;", "ReferenceError: missing is not defined"),
+        ];
+        for (src, expected) in cases.iter() {
+            let err = message(&mut ctx, src);
+            assert!(err.contains(expected), "{}: {}", src, err);
+        }
+    }
+
+    #[test]
+    fn test_strict_mode_rules() {
+        let (_vm, mut ctx) = new_context();
+
+        let mut eval = |src: &str| match ctx.eval(src) {
+            Ok(value) => value.to_string(ctx).ok().unwrap(),
+            Err(err) => err.to_string(ctx).ok().unwrap(),
+        };
+        let cases = [
+            ("'use strict'; undeclared = 1;", "ReferenceError: undeclared"),
+            ("'use strict'; var declared = 1; declared = 2; declared", "2"),
+            ("'use strict'; function declaredFn() {} declaredFn = 1", "1"),
+            ("implicit = 3; implicit", "3"),
+            ("var read = hoisted; var hoisted = 1; typeof read", "undefined"),
+            ("'use strict'; var v; delete v;", "Delete of an unqualified identifier"),
+            ("delete implicit", "true"),
+            ("'use strict'; function g(a, a) {}", "SyntaxError"),
+            ("function h(a, a) { return a; } h(1, 2)", "2"),
+            ("function r(a, ...a) {}", "SyntaxError"),
+            ("'use strict'; 010", "SyntaxError"),
+            ("'use strict'; '\\01'", "SyntaxError"),
+            ("010 + '\\0'.length", "9"),
+            (
+                "'use strict'; function outer() { return (function () { return this; })(); } outer()",
+                "undefined",
+            ),
+            (
+                "'use strict'; function t() { return typeof this; } t.call(5)",
+                "number",
+            ),
+            ("function s() { return this === globalThis; } s.call(null)", "true"),
+            (
+                "class C { m() { inClass = 1; } } new C().m()",
+                "ReferenceError: inClass",
+            ),
+        ];
+        for (src, expected) in cases.iter() {
+            let result = eval(src);
+            assert!(result.contains(expected), "{}: {}", src, result);
+        }
+    }
+
+    #[test]
+    fn test_with_statement_is_syntax_error() {
+        let (_vm, mut ctx) = new_context();
+        let message = |ctx: &mut GcPointer<Context>, src: &str| {
+            let err = ctx.eval(src).err().unwrap();
+            err.to_string(*ctx).ok().unwrap()
+        };
+        let err = message(&mut ctx, "var o = {};\n  with (o) { x; }");
+        assert!(err.contains("SyntaxError"), "{}", err);
+        assert!(
+            err.contains("with statement is not supported (2:3)"),
+            "{}",
+            err
+        );
+        let err = message(&mut ctx, "function f() { 'use strict'; with ({}) {} }");
+        assert!(
+            err.contains("Strict mode code may not include a with statement"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_unsupported_syntax_is_syntax_error() {
+        let (_vm, mut ctx) = new_context();
+        let message = |ctx: &mut GcPointer<Context>, src: &str| {
+            let err = ctx.eval(src).err().unwrap();
+            err.to_string(*ctx).ok().unwrap()
+        };
+        let err = message(&mut ctx, "outer: for (;;) { break outer; }");
+        assert_eq!(err, "SyntaxError: Unsupported syntax: Labeled");
+        let err = message(&mut ctx, "1n");
+        assert_eq!(err, "SyntaxError: Unsupported syntax: BigInt literal");
+        let err = message(&mut ctx, "new Function('x: 1')");
+        assert_eq!(err, "SyntaxError: Unsupported syntax: Labeled");
+        let err = message(&mut ctx, "function f() { return async function () {}; }");
+        assert_eq!(err, "SyntaxError: Unsupported syntax: async function");
+        // the context stays usable after a failed compilation.
+        let result = ctx.eval("1 + 1").ok().unwrap();
+        assert_eq!(result.get_int32(), 2);
+    }
+
+    #[test]
+    fn test_catch_binding() {
+        let (_vm, mut ctx) = new_context();
+
+        let result = ctx
+            .eval(
+                "var e = 'outer';
+                 function nested() {
+                     try { throw 1; } catch (e) {
+                         try { throw 2; } catch (e) { var inner = e; }
+                         return [e, inner];
+                     }
+                 }
+                 function destructure() {
+                     try { throw new TypeError('bad'); }
+                     catch ({ name, message: msg, code = 'none' }) { return [name, msg, code]; }
+                 }
+                 function closures() {
+                     var fns = [];
+                     try { throw 'captured'; } catch (x) { fns.push(function () { return x; }); }
+                     return fns[0]();
+                 }
+                 [nested(), destructure(), closures(), e].join()",
+            )
+            .ok()
+            .unwrap();
+        assert_eq!(
+            result.to_string(ctx).ok().unwrap(),
+            "1,2,TypeError,bad,none,captured,outer"
+        );
+
+        // rethrown error keeps stacktrace of the place it was created at.
+        let err = ctx
+            .eval(
+                "function thrower() { throw new Error('x'); }
+                 function rethrower() { try { thrower(); } catch (e) { throw e; } }
+                 rethrower();",
+            )
+            .err()
+            .unwrap();
+        assert!(err.is_jsobject());
+        assert!(ctx.take_stacktrace().contains("'thrower'"));
+    }
+
+    #[test]
+    fn test_lazy_compilation() {
+        let (_vm, mut ctx) = new_context_with(Options::default().with_lazy_compilation(true));
+
+        let result = ctx
+            .eval(
+                "function outer(a) { var b = 2; function inner() { return a + b; } return inner; }
+                 function broken() { return ( }
+                 var f = outer(40);
+                 f() + 1",
+            )
+            .ok()
+            .unwrap();
+        assert_eq!(result.get_number(), 43.0);
+
+        let outer = ctx.eval("outer").ok().unwrap().get_jsobject();
+        assert!(outer.as_function().as_vm().code.lazy.is_none());
+        let broken = ctx.eval("broken").ok().unwrap().get_jsobject();
+        assert!(broken.as_function().as_vm().code.lazy.is_some());
+        // syntax error is reported by every call of the function, not by the script that declares it.
+        assert!(ctx.eval("broken()").is_err());
+        assert!(ctx.eval("broken()").is_err());
+    }
+
+    #[test]
+    fn test_class_members() {
+        let (_vm, mut ctx) = new_context();
+
+        let result = ctx
+            .eval(
+                "class Point {
+                     z = 3;
+                     static count = 0;
+                     static origin = new this(0, 0);
+                     constructor(x, y) { this.x = x; this.y = y; Point.count++; }
+                     get sum() { return this.x + this.y + this.z }
+                     set sum(v) { this.x = v - this.y - this.z }
+                     ['to' + 'Array']() { return [this.x, this.y] }
+                     static create(x) { return new Point(x, x) }
+                 }
+                 var p = Point.create(2);
+                 p.sum = 10;
+                 var keys = [];
+                 for (var k in p) keys.push(k);
+                 [p.sum, p.toArray(), Point.count, Point.origin.sum, keys].join()",
+            )
+            .ok()
+            .unwrap();
+        assert_eq!(result.get_jsstring().as_str(), "10,5,2,2,3,z,x,y");
+        assert!(ctx.eval("class A extends Object {}").is_err());
+    }
+
+    #[test]
+    fn test_variable_names() {
+        let (_vm, mut ctx) = new_context_with(Options::default().with_debug_info(true));
+        let source = "function f(a, b) {
+                          { let p = a; var g = () => p; }
+                          { let q = b; var h = () => q; }
+                          return () => b + g() + h();
+                      }
+                      f(1, 2)";
+
+        let code = ctx
+            .compile_eval_code(None, false, source, false)
+            .ok()
+            .unwrap();
+        let names = code.codes[0].var_names.as_ref().unwrap();
+        assert_eq!(names.slot(0), Some("a".intern()));
+        assert_eq!(names.env(1), Some("b".intern()));
+        // block scoped bindings do not share an index with debug info.
+        let index = |name: &str| names.env.iter().position(|x| *x == Some(name.intern()));
+        assert!(index("p").is_some() && index("q").is_some());
+        assert_ne!(index("p"), index("q"));
+
+        let closure = ctx.eval(source).ok().unwrap().get_jsobject();
+        let scope = closure.as_function().as_vm().scope;
+        assert_eq!(scope.name(1), Some("b".intern()));
+
+        let (_vm, mut ctx) = new_context();
+        let closure = ctx.eval(source).ok().unwrap().get_jsobject();
+        let scope = closure.as_function().as_vm().scope;
+        assert!(scope.code.is_none());
+        assert_eq!(scope.name(1), None);
+    }
+}
@@ -1,7 +1,9 @@
 /* This Source Code Form is subject to the terms of the Mozilla Public
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
-use std::collections::HashMap;
+use crate::preparser::PreParse;
+use crate::vm::symbol_table::{Internable, Symbol};
+use std::collections::{HashMap, HashSet};
 use swc_atoms::JsWord;
 use swc_common::DUMMY_SP;
 use swc_ecmascript::utils::find_ids;
@@ -323,6 +325,214 @@ impl Visit for Analyzer<'_> {
     }
 }
 
+/// Closure capture analysis of a single function.
+///
+/// Bindings of a function that are never referenced by nested functions do not have to outlive the call and
+/// are stored in stack slots of the call frame. Analysis works on names: any identifier that appears inside of
+/// a nested function, class or accessor is treated as captured even if it refers to an unrelated binding.
+#[derive(Debug, Default)]
+pub struct Captures {
+    /// Names referenced by nested functions.
+    captured: HashSet<Symbol>,
+    /// Names bound directly in the analyzed function.
+    declared: HashSet<Symbol>,
+    /// `eval`, `with` or `arguments` may observe any binding.
+    dynamic: bool,
+    /// Function declares `const` bindings, immutability of those is checked by environment.
+    has_const: bool,
+    /// Rest parameter array is stored in environment.
+    has_rest: bool,
+}
+
+impl Captures {
+    /// Analyze `function`. Nested functions skipped by the pre-parser have empty bodies in AST, names recorded
+    /// in `preparse` are used for them instead.
+    pub fn analyze_function(function: &Function, preparse: Option<&PreParse>) -> Self {
+        let mut finder = CaptureFinder {
+            preparse,
+            ..Default::default()
+        };
+        function.visit_children_with(&mut finder);
+        finder.captures
+    }
+
+    pub fn analyze_arrow(arrow: &ArrowExpr, preparse: Option<&PreParse>) -> Self {
+        let mut finder = CaptureFinder {
+            preparse,
+            ..Default::default()
+        };
+        arrow.visit_children_with(&mut finder);
+        finder.captures
+    }
+
+    /// Returns true if binding `name` must be stored in heap environment.
+    pub fn is_captured(&self, name: Symbol) -> bool {
+        self.dynamic || self.captured.contains(&name)
+    }
+
+    /// Returns false when every binding of the function can be stored in stack slots and the function does not
+    /// need environment of its own.
+    pub fn needs_environment(&self) -> bool {
+        self.dynamic
+            || self.has_const
+            || self.has_rest
+            || self
+                .declared
+                .iter()
+                .any(|name| self.captured.contains(name))
+    }
+}
+
+#[derive(Default)]
+struct CaptureFinder<'a> {
+    captures: Captures,
+    depth: u32,
+    /// Nesting of functions that have `arguments` of their own, arrow functions are not counted.
+    functions: u32,
+    preparse: Option<&'a PreParse>,
+}
+
+impl CaptureFinder<'_> {
+    fn nested<F: FnOnce(&mut Self)>(&mut self, op: F) {
+        self.depth += 1;
+        op(self);
+        self.depth -= 1;
+    }
+
+    fn nested_function<F: FnOnce(&mut Self)>(&mut self, op: F) {
+        self.functions += 1;
+        self.nested(op);
+        self.functions -= 1;
+    }
+
+    fn declare(&mut self, name: &JsWord) {
+        if self.depth == 0 {
+            let name: &str = name;
+            self.captures.declared.insert(name.intern());
+        }
+    }
+}
+
+impl Visit for CaptureFinder<'_> {
+    noop_visit_type!();
+
+    fn visit_function(&mut self, n: &Function, _: &dyn Node) {
+        let skipped = match (self.preparse, &n.body) {
+            (Some(preparse), Some(body)) => preparse.body(body.span.lo),
+            _ => None,
+        };
+        if let Some(skipped) = skipped {
+            self.captures.dynamic |= skipped.dynamic;
+            self.captures.captured.extend(skipped.names.iter().copied());
+        }
+        self.nested_function(|finder| n.visit_children_with(finder))
+    }
+
+    fn visit_arrow_expr(&mut self, n: &ArrowExpr, _: &dyn Node) {
+        self.nested(|finder| n.visit_children_with(finder))
+    }
+
+    fn visit_class(&mut self, n: &Class, _: &dyn Node) {
+        self.nested(|finder| n.visit_children_with(finder))
+    }
+
+    fn visit_getter_prop(&mut self, n: &GetterProp, _: &dyn Node) {
+        self.nested_function(|finder| n.visit_children_with(finder))
+    }
+
+    fn visit_setter_prop(&mut self, n: &SetterProp, _: &dyn Node) {
+        self.nested_function(|finder| n.visit_children_with(finder))
+    }
+
+    fn visit_constructor(&mut self, n: &Constructor, _: &dyn Node) {
+        self.nested_function(|finder| n.visit_children_with(finder))
+    }
+
+    fn visit_fn_decl(&mut self, n: &FnDecl, _: &dyn Node) {
+        self.declare(&n.ident.sym);
+        n.visit_children_with(self)
+    }
+
+    fn visit_class_decl(&mut self, n: &ClassDecl, _: &dyn Node) {
+        self.declare(&n.ident.sym);
+        n.visit_children_with(self)
+    }
+
+    fn visit_binding_ident(&mut self, n: &BindingIdent, _: &dyn Node) {
+        self.declare(&n.id.sym);
+        n.visit_children_with(self)
+    }
+
+    /// Shorthand property of object pattern binds its key, which is not a `BindingIdent`.
+    fn visit_assign_pat_prop(&mut self, n: &AssignPatProp, _: &dyn Node) {
+        self.declare(&n.key.sym);
+        n.visit_children_with(self)
+    }
+
+    fn visit_var_decl(&mut self, n: &VarDecl, _: &dyn Node) {
+        if self.depth == 0 && n.kind == VarDeclKind::Const {
+            self.captures.has_const = true;
+        }
+        n.visit_children_with(self)
+    }
+
+    fn visit_rest_pat(&mut self, n: &RestPat, _: &dyn Node) {
+        if self.depth == 0 {
+            self.captures.has_rest = true;
+        }
+        n.visit_children_with(self)
+    }
+
+    fn visit_with_stmt(&mut self, n: &WithStmt, _: &dyn Node) {
+        self.captures.dynamic = true;
+        n.visit_children_with(self)
+    }
+
+    fn visit_ident(&mut self, n: &Ident, _: &dyn Node) {
+        // mapped `arguments` aliases parameters through environment, see `JsArguments`.
+        if &n.sym == "eval" || (&n.sym == "arguments" && self.functions == 0) {
+            self.captures.dynamic = true;
+        } else if self.depth != 0 {
+            let name: &str = &n.sym;
+            self.captures.captured.insert(name.intern());
+        }
+    }
+}
+
+/// Returns true if `body` refers to `arguments` of its own function. References inside of nested functions,
+/// accessors and constructors are not counted since those have `arguments` of their own, arrow functions
+/// share it with the enclosing function.
+pub fn uses_arguments(body: &[Stmt]) -> bool {
+    let mut finder = ArgumentsFinder { found: false };
+    for stmt in body.iter() {
+        stmt.visit_with(&Invalid { span: DUMMY_SP } as _, &mut finder);
+    }
+    finder.found
+}
+
+struct ArgumentsFinder {
+    found: bool,
+}
+
+impl Visit for ArgumentsFinder {
+    noop_visit_type!();
+
+    fn visit_function(&mut self, _n: &Function, _: &dyn Node) {}
+
+    fn visit_constructor(&mut self, _n: &Constructor, _: &dyn Node) {}
+
+    fn visit_getter_prop(&mut self, _n: &GetterProp, _: &dyn Node) {}
+
+    fn visit_setter_prop(&mut self, _n: &SetterProp, _: &dyn Node) {}
+
+    fn visit_expr(&mut self, e: &Expr, _: &dyn Node) {
+        match *e {
+            Expr::Ident(ref i) if &i.sym == "arguments" => self.found = true,
+            _ => e.visit_children_with(self),
+        }
+    }
+}
+
 pub struct VisitFnDecl<'a> {
     cb: &'a mut dyn FnMut(&FnDecl),
 }
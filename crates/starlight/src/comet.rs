@@ -1,22 +1,36 @@
 pub use comet as cometgc;
 use comet::gcref::UntypedGcRef;
 use comet::header::HeapObjectHeader;
-use comet::heap::{DeferPoint, Heap as CometHeap, MarkingConstraint};
+use comet::heap::{DeferPoint as CometDeferPoint, Heap as CometHeap, MarkingConstraint};
 pub use comet::internal::finalize_trait::FinalizeTrait as Finalize;
 use comet::internal::gc_info::{GCInfoIndex, GCInfoTrait};
 pub use comet::internal::trace_trait::TraceTrait as Trace;
 pub use comet::visitor::Visitor;
 use cometgc::gcref::GcRef;
 use mopa::mopafy;
+use std::cell::Cell;
 use std::collections::HashMap;
 use std::intrinsics::{size_of, transmute};
 use std::marker::PhantomData;
 use std::ops::{Deref, DerefMut};
 use std::ptr::NonNull;
+use std::rc::Rc;
 
 use crate::options::Options;
+
+#[cfg(feature = "gc-debug")]
+pub mod debug;
+
 pub struct Heap {
     heap: Box<CometHeap>,
+    /// Number of live [DeferPoint]s, stress collections are skipped while there are any.
+    defers: Rc<Cell<usize>>,
+    stress: bool,
+    /// Called after every stress collection, set by the VM owning the heap to verify its roots.
+    pub(crate) verifier: Option<Box<dyn FnMut()>>,
+    // declared after `heap` so it is dropped after all cells are finalized.
+    #[cfg(feature = "gc-debug")]
+    debug: debug::HeapDebug,
 }
 #[allow(dead_code)]
 pub struct SimpleMarkingConstraint {
@@ -38,11 +52,33 @@ impl MarkingConstraint for SimpleMarkingConstraint {
     }
 }
 
+/// Postpones collections until it is dropped.
+pub struct DeferPoint {
+    _point: CometDeferPoint,
+    defers: Rc<Cell<usize>>,
+}
+
+impl Drop for DeferPoint {
+    fn drop(&mut self) {
+        self.defers.set(self.defers.get() - 1);
+    }
+}
+
 impl Heap {
     pub fn defer(&self) -> DeferPoint {
-        DeferPoint::new(&self.heap)
+        self.defers.set(self.defers.get() + 1);
+        DeferPoint {
+            _point: CometDeferPoint::new(&self.heap),
+            defers: self.defers.clone(),
+        }
     }
     pub fn new(opts: &Options) -> Self {
+        if opts.parallel_marking {
+            eprintln!(
+                "warning: parallel marking is not supported, ignoring parallel_marking and gc_threads ({})",
+                opts.gc_threads
+            );
+        }
         let mut configs = comet::Config::default();
         configs.heap_size = opts.heap_size;
 
@@ -51,9 +87,38 @@ impl Heap {
 
         let mut heap = CometHeap::new(configs);
         heap.add_core_constraints();
-        Self { heap }
+        #[cfg(feature = "gc-debug")]
+        let debug = debug::HeapDebug::new(&mut heap);
+        Self {
+            heap,
+            defers: Rc::new(Cell::new(0)),
+            stress: opts.gc_stress,
+            verifier: None,
+            #[cfg(feature = "gc-debug")]
+            debug,
+        }
+    }
+
+    /// Collect garbage before every allocation that is not deferred and verify the heap after each
+    /// collection, see [Options::gc_stress].
+    pub fn set_stress(&mut self, enable: bool) {
+        self.stress = enable;
+    }
+
+    fn stress_collect(&mut self) {
+        self.gc();
+        // taken out so the verifier can use the heap
+        if let Some(mut verify) = self.verifier.take() {
+            verify();
+            self.verifier = Some(verify);
+        }
     }
     pub fn gc(&mut self) {
+        #[cfg(feature = "gc-debug")]
+        return self
+            .debug
+            .enter(&mut self.heap, |heap| heap.collect_garbage(), |_| None);
+        #[cfg(not(feature = "gc-debug"))]
         self.heap.collect_garbage();
     }
     pub fn allocate_(
@@ -62,10 +127,24 @@ impl Heap {
         vtable: usize,
         idx: GCInfoIndex,
     ) -> Option<NonNull<GcPointerBase>> {
+        if self.stress && self.defers.get() == 0 {
+            self.stress_collect();
+        }
         unsafe {
-            let ptr = self
-                .heap
-                .allocate_raw(size + size_of::<GcPointerBase>(), idx);
+            let total = size + size_of::<GcPointerBase>();
+            #[cfg(feature = "gc-debug")]
+            let ptr = self.debug.enter(
+                &mut self.heap,
+                |heap| heap.allocate_raw(total, idx),
+                |ptr| {
+                    ptr.as_ref().map(|ptr| {
+                        let base = HeapObjectHeader::from_object(ptr.get()) as usize;
+                        (base, total)
+                    })
+                },
+            );
+            #[cfg(not(feature = "gc-debug"))]
+            let ptr = self.heap.allocate_raw(total, idx);
             match ptr {
                 Some(ptr) => {
                     let raw = HeapObjectHeader::from_object(ptr.get()).cast::<GcPointerBase>();
@@ -113,6 +192,13 @@ impl Heap {
         self.heap.add_constraint(constraint);
     }
     pub fn make_weak<T: GcCell>(&mut self, target: GcPointer<T>) -> WeakRef<T> {
+        #[cfg(feature = "gc-debug")]
+        let weak = self.debug.enter(
+            &mut self.heap,
+            |heap| unsafe { heap.allocate_weak(std::mem::transmute(target)) },
+            |_| None,
+        );
+        #[cfg(not(feature = "gc-debug"))]
         let weak = unsafe { self.heap.allocate_weak(std::mem::transmute(target)) };
         WeakRef {
             ref_: weak,
@@ -121,6 +207,13 @@ impl Heap {
     }
 
     pub fn collect_if_necessary(&mut self) {
+        #[cfg(feature = "gc-debug")]
+        return self.debug.enter(
+            &mut self.heap,
+            |heap| heap.collect_if_necessary_or_defer(),
+            |_| None,
+        );
+        #[cfg(not(feature = "gc-debug"))]
         self.heap.collect_if_necessary_or_defer();
     }
 }
@@ -159,6 +252,19 @@ pub struct GcPointerBase {
 }
 
 impl<T: GcCell + ?Sized> GcPointer<T> {
+    /// Address of the cell. Cells never move so address is stable for the cell lifetime.
+    pub fn address(&self) -> usize {
+        self.base.as_ptr() as usize
+    }
+    /// Size of the heap allocation backing this cell including GC header.
+    pub fn allocation_size(&self) -> usize {
+        unsafe { (*self.base.as_ptr()).allocation_size() }
+    }
+    /// Whether the header of the cell refers to type information. Freed cells lose it once their memory is
+    /// handed back to the allocator.
+    pub fn has_type_info(&self) -> bool {
+        unsafe { (*self.base.as_ptr()).hdr.get_gc_info_index().get().vtable != 0 }
+    }
     pub fn untyped(self) -> UntypedGcRef {
         unsafe { std::mem::transmute(self) }
     }
@@ -180,11 +286,15 @@ impl<T: GcCell + ?Sized> GcPointer<T> {
 
     #[inline]
     pub fn get_dyn(&self) -> &dyn GcCell {
+        #[cfg(feature = "gc-debug")]
+        debug::check_deref(self.address());
         unsafe { (*self.base.as_ptr()).get_dyn() }
     }
 
     #[inline]
     pub fn get_dyn_mut(&mut self) -> &mut dyn GcCell {
+        #[cfg(feature = "gc-debug")]
+        debug::check_deref(self.address());
         unsafe { (*self.base.as_ptr()).get_dyn() }
     }
 
@@ -248,11 +358,15 @@ impl<T: GcCell + ?Sized> Clone for GcPointer<T> {
 impl<T: GcCell> Deref for GcPointer<T> {
     type Target = T;
     fn deref(&self) -> &Self::Target {
+        #[cfg(feature = "gc-debug")]
+        debug::check_deref(self.address());
         unsafe { &*(&*self.base.as_ptr()).data::<T>() }
     }
 }
 impl<T: GcCell> DerefMut for GcPointer<T> {
     fn deref_mut(&mut self) -> &mut Self::Target {
+        #[cfg(feature = "gc-debug")]
+        debug::check_deref(self.address());
         unsafe { &mut *(&*self.base.as_ptr()).data::<T>() }
     }
 }
@@ -317,6 +431,8 @@ impl<T: GcCell> GcCell for Vec<T> {}
 
 impl<T: GcCell + Trace> Trace for GcPointer<T> {
     fn trace(&self, vis: &mut Visitor) {
+        #[cfg(feature = "gc-debug")]
+        debug::check_traced(self.address());
         unsafe {
             vis.trace_gcref(transmute::<_, GcRef<T>>(*self));
         }
@@ -0,0 +1,217 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! Checks enabled by the `gc-debug` feature.
+//!
+//! Every cell allocated by a [Heap](super::Heap) is registered with its owning heap and a weak reference to it.
+//! After each heap operation that ran a collection the cells whose weak reference was cleared are marked as
+//! freed and their payload is overwritten with [POISON]. Dereferencing a [GcPointer](super::GcPointer) then
+//! panics when the cell was freed, belongs to a dropped heap or was never allocated by a heap, and marking
+//! panics when a cell of the collected heap points into another heap.
+//!
+//! Memory of a freed cell is reused by later allocations, a stale pointer to it is not detected after that.
+use super::SimpleMarkingConstraint;
+use comet::gcref::WeakGcRef;
+use comet::heap::Heap as CometHeap;
+use once_cell::sync::Lazy;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::mem::size_of;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Mutex;
+
+/// Byte that payloads of freed cells are filled with.
+pub const POISON: u8 = 0xdb;
+/// Cells larger than this come from malloc and are returned to it when freed, they are never poisoned.
+const LARGE_CELL: usize = 8 * 1024;
+/// Leading payload words that the allocator keeps free-list links in, they are not poisoned.
+const FREE_LIST_WORDS: usize = 2;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum State {
+    Live,
+    Freed,
+    Disposed,
+}
+
+/// Owning heap and state of every cell, by address of its `GcPointerBase`.
+static CELLS: Lazy<Mutex<HashMap<usize, (u32, State)>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+static NEXT_HEAP: AtomicU32 = AtomicU32::new(1);
+
+thread_local! {
+    /// Heap that runs an operation on this thread, `0` outside of heap operations.
+    static ACTIVE: Cell<u32> = Cell::new(0);
+}
+
+struct Tracked {
+    weak: WeakGcRef,
+    /// Allocation size including the header.
+    size: usize,
+}
+
+/// Per heap registry, see the module documentation.
+pub(super) struct HeapDebug {
+    id: u32,
+    /// Cells whose weak reference is created at the start of the next heap operation, once they are initialized.
+    pending: RefCell<Vec<(usize, usize)>>,
+    tracked: Rc<RefCell<HashMap<usize, Tracked>>>,
+    collected: Rc<Cell<bool>>,
+}
+
+impl HeapDebug {
+    pub(super) fn new(heap: &mut CometHeap) -> Self {
+        let tracked = Rc::new(RefCell::new(HashMap::<usize, Tracked>::new()));
+        let collected = Rc::new(Cell::new(false));
+        let (slots, marked) = (tracked.clone(), collected.clone());
+        // weak references are held by the registry only, their slots have to be kept alive.
+        heap.add_constraint(SimpleMarkingConstraint::new(
+            "Mark gc-debug slots",
+            move |vis| {
+                marked.set(true);
+                for cell in slots.borrow().values() {
+                    vis.trace_gcref(cell.weak.slot());
+                }
+            },
+        ));
+        Self {
+            id: NEXT_HEAP.fetch_add(1, Ordering::Relaxed),
+            pending: RefCell::new(vec![]),
+            tracked,
+            collected,
+        }
+    }
+
+    /// Run heap operation `op`. `allocated` returns the cell `op` allocated, if any.
+    pub(super) fn enter<R>(
+        &self,
+        heap: &mut CometHeap,
+        op: impl FnOnce(&mut CometHeap) -> R,
+        allocated: impl FnOnce(&R) -> Option<(usize, usize)>,
+    ) -> R {
+        let previous = ACTIVE.with(|active| active.replace(self.id));
+        for (address, size) in self.pending.take() {
+            let weak = unsafe { heap.allocate_weak(std::mem::transmute(address)) };
+            self.tracked
+                .borrow_mut()
+                .insert(address, Tracked { weak, size });
+        }
+        let result = op(heap);
+        ACTIVE.with(|active| active.set(previous));
+        let allocated = allocated(&result);
+        if self.collected.replace(false) {
+            self.poison_freed(allocated);
+        }
+        if let Some((address, size)) = allocated {
+            CELLS
+                .lock()
+                .unwrap()
+                .insert(address, (self.id, State::Live));
+            self.pending.borrow_mut().push((address, size));
+        }
+        result
+    }
+
+    /// Mark cells whose weak reference was cleared as freed and poison them. A cell overlapping `allocated`, which
+    /// may reuse its memory already, is not poisoned.
+    fn poison_freed(&self, allocated: Option<(usize, usize)>) {
+        let mut freed = vec![];
+        self.tracked.borrow_mut().retain(|address, cell| {
+            if cell.weak.upgrade().is_some() {
+                return true;
+            }
+            freed.push((*address, cell.size));
+            false
+        });
+        let mut cells = CELLS.lock().unwrap();
+        for (address, size) in freed {
+            cells.insert(address, (self.id, State::Freed));
+            let overlaps = allocated.map_or(false, |(start, len)| {
+                address < start + len && start < address + size
+            });
+            let skip = size_of::<super::GcPointerBase>() + FREE_LIST_WORDS * size_of::<usize>();
+            if !overlaps && size <= LARGE_CELL && size > skip {
+                unsafe {
+                    std::ptr::write_bytes((address + skip) as *mut u8, POISON, size - skip);
+                }
+            }
+        }
+    }
+}
+
+impl Drop for HeapDebug {
+    // runs after the comet heap is dropped, so finalizers still see their cells as live.
+    fn drop(&mut self) {
+        for (heap, state) in CELLS.lock().unwrap().values_mut() {
+            if *heap == self.id {
+                *state = State::Disposed;
+            }
+        }
+    }
+}
+
+/// Panic unless `address` is a live cell.
+pub(super) fn check_deref(address: usize) {
+    let state = CELLS.lock().unwrap().get(&address).copied();
+    match state {
+        Some((_, State::Live)) => (),
+        Some((heap, State::Freed)) => panic!(
+            "use after free: GcPointer {:#x} points to a cell freed by a collection of heap #{}",
+            address, heap
+        ),
+        Some((heap, State::Disposed)) => panic!(
+            "GcPointer {:#x} points to a cell of heap #{} that was dropped",
+            address, heap
+        ),
+        None => panic!(
+            "GcPointer {:#x} does not point to a cell allocated by a heap",
+            address
+        ),
+    }
+}
+
+/// Panic when a cell traced while a heap is collecting belongs to another heap.
+pub(super) fn check_traced(address: usize) {
+    let active = ACTIVE.with(|active| active.get());
+    if active == 0 {
+        return;
+    }
+    check_deref(address);
+    let heap = CELLS.lock().unwrap()[&address].0;
+    if heap != active {
+        panic!(
+            "cross-heap pointer: cell {:#x} of heap #{} is referenced from heap #{}",
+            address, heap, active
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::GcPointer;
+    use crate::{prelude::*, vm::tests::new_context};
+    use std::{marker::PhantomData, mem::MaybeUninit, ptr::NonNull};
+
+    #[test]
+    #[should_panic(expected = "does not point to a cell allocated by a heap")]
+    fn test_foreign_pointer() {
+        let mut memory = Box::new(MaybeUninit::<[usize; 8]>::uninit());
+        let pointer = GcPointer::<String> {
+            base: NonNull::new(memory.as_mut_ptr().cast()).unwrap(),
+            marker: PhantomData,
+        };
+        let _ = pointer.len();
+    }
+
+    #[test]
+    #[should_panic(expected = "that was dropped")]
+    fn test_deref_after_drop() {
+        let (mut vm, mut ctx) = new_context();
+        let string = JsString::new(ctx, "cell");
+        unsafe {
+            vm.dispose();
+        }
+        let _ = string.len();
+    }
+}
@@ -59,7 +59,8 @@ use crate::{
         serializer::{Serializable, SnapshotSerializer},
     },
 };
-use std::intrinsics::{copy_nonoverlapping, unlikely};
+use crate::utils::unlikely;
+use std::intrinsics::copy_nonoverlapping;
 use std::ops::Deref;
 use std::{any::TypeId, cmp::Ordering, fmt, marker::PhantomData};
 use std::{
@@ -431,6 +432,7 @@ pub fn formatted_size(size: usize) -> FormattedSize {
 
 #[cfg(test)]
 mod tests {
+    use crate::vm::tests::new_context;
     #[test]
     fn test_offsetof() {
         #[repr(C)]
@@ -442,6 +444,23 @@ mod tests {
         assert_eq!(offsetof!(Point.x), 0);
         assert_eq!(offsetof!(Point.y), 4);
     }
+
+    #[test]
+    fn test_gc_stress() {
+        let (mut vm, mut ctx) = new_context();
+        vm.heap().set_stress(true);
+        let result = ctx
+            .eval(
+                "var list = [];
+                 for (var i = 0; i < 10; i++) list.push({ index: i, name: 'item' + i });
+                 list.filter(item => item.index % 3 == 0).map(item => item.name).join()",
+            )
+            .ok()
+            .unwrap();
+        vm.heap().set_stress(false);
+        assert_eq!(result.get_jsstring().as_str(), "item0,item3,item6,item9");
+        vm.verify_heap();
+    }
 }
 
 pub mod allocation;
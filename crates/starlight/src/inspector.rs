@@ -0,0 +1,827 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+//! Chrome DevTools Protocol server built on [debugger](crate::vm::debugger) hooks.
+//!
+//! [InspectorServer] accepts a single DevTools session over WebSocket and attaches it as the debugger of a
+//! runtime. Supported subset of the protocol:
+//! - `Runtime`: `evaluate`, `getProperties`, object release and `consoleAPICalled` events;
+//! - `Debugger`: scripts, breakpoints by url and line, stepping, pause on exceptions and evaluation while paused;
+//! - `Profiler`: CPU profile sampled at statement boundaries;
+//! - `HeapProfiler`: `takeHeapSnapshot` and `collectGarbage`.
+//!
+//! Messages are processed while execution is paused and periodically at statement boundaries otherwise.
+//! Expressions evaluated on a call frame run in the global scope, locals are shown through scope objects.
+//! Variables are named by [DebugFrame::slot_name] and
+//! [Environment::name](crate::vm::environment::Environment::name) when the code block records names (always
+//! while a debugger is attached), `slot N`/`var N` otherwise.
+//!
+//! ```rust,ignore
+//! let server = InspectorServer::bind("127.0.0.1:9229")?;
+//! eprintln!("Debugger listening on {}", server.websocket_url()?);
+//! server.attach(ctx)?;
+//! ```
+use crate::{
+    prelude::*,
+    vm::{
+        code_block::CodeBlock,
+        context::Context,
+        debugger::{
+            self, DebugFrame, DebuggerDelegate, PauseOnExceptions, PauseReason, ResumeAction,
+        },
+        inspect::InspectOptions,
+        PersistentRooted,
+    },
+};
+use serde_json::{json, Value};
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    io::{self, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    rc::Rc,
+    time::{Duration, Instant},
+};
+use tungstenite::{Message, WebSocket};
+
+/// Path of the WebSocket endpoint, also used as target id.
+const TARGET_ID: &str = "starlight";
+/// Minimal time between polls for messages while JS code runs.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// Time a new connection has to send its HTTP request or WebSocket handshake before it is dropped.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+/// Maximal size of `HeapProfiler.addHeapSnapshotChunk` message.
+const SNAPSHOT_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Listening socket waiting for DevTools to connect.
+pub struct InspectorServer {
+    listener: TcpListener,
+}
+
+impl InspectorServer {
+    pub fn bind(addr: impl ToSocketAddrs) -> io::Result<Self> {
+        Ok(Self {
+            listener: TcpListener::bind(addr)?,
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    pub fn websocket_url(&self) -> io::Result<String> {
+        Ok(format!("ws://{}/{}", self.local_addr()?, TARGET_ID))
+    }
+
+    /// Wait for DevTools session, attach it as debugger of `ctx`'s runtime and serve it until DevTools sends
+    /// `Runtime.runIfWaitingForDebugger`. Only code compiled after this call can be stepped through.
+    /// Connections that don't send a request within 5 seconds or fail the handshake are dropped.
+    pub fn attach(self, ctx: GcPointer<Context>) -> io::Result<()> {
+        let addr = self.local_addr()?;
+        let socket = loop {
+            let (stream, _) = self.listener.accept()?;
+            // a client that connects and stays silent must not keep DevTools from connecting.
+            if stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT)).is_err() {
+                continue;
+            }
+            let stream = match serve_http(stream, addr) {
+                Ok(Some(stream)) => stream,
+                Ok(None) | Err(_) => continue,
+            };
+            if let Ok(socket) = tungstenite::accept(stream) {
+                if socket.get_ref().set_read_timeout(None).is_ok() {
+                    break socket;
+                }
+            }
+        };
+        let session = Rc::new(RefCell::new(Session::new(socket)));
+        ctx.vm().set_debugger(Inspector {
+            session: session.clone(),
+        });
+        let mut session = session.borrow_mut();
+        session.waiting = true;
+        while session.waiting && !session.closed {
+            session.receive(ctx, true);
+        }
+        Ok(())
+    }
+}
+
+/// Answer DevTools discovery requests (`/json`, `/json/list`, `/json/version`). Returns `stream` back when
+/// it carries a WebSocket handshake.
+fn serve_http(mut stream: TcpStream, addr: SocketAddr) -> io::Result<Option<TcpStream>> {
+    let mut buf = [0u8; 1024];
+    let len = stream.peek(&mut buf)?;
+    let request = String::from_utf8_lossy(&buf[..len]);
+    let path = request.split_whitespace().nth(1).unwrap_or("");
+    let body = match path {
+        "/json" | "/json/list" => json!([{
+            "description": "starlight instance",
+            "devtoolsFrontendUrl": format!(
+                "devtools://devtools/bundled/js_app.html?experiments=true&v8only=true&ws={}/{}",
+                addr, TARGET_ID
+            ),
+            "id": TARGET_ID,
+            "title": "starlight",
+            "type": "node",
+            "url": "file://",
+            "webSocketDebuggerUrl": format!("ws://{}/{}", addr, TARGET_ID),
+        }]),
+        "/json/version" => json!({
+            "Browser": concat!("starlight/", env!("CARGO_PKG_VERSION")),
+            "Protocol-Version": "1.3",
+        }),
+        _ => return Ok(Some(stream)),
+    };
+    // consume the request before answering it.
+    let _ = stream.read(&mut buf)?;
+    let body = body.to_string();
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: application/json; charset=UTF-8\r\nContent-Length: {}\r\n\r\n{}",
+        body.len(),
+        body
+    )?;
+    Ok(None)
+}
+
+struct Script {
+    url: String,
+    source: String,
+}
+
+/// CPU profile in the format of `Profiler.Profile`.
+struct Profile {
+    start: Instant,
+    last_sample: Instant,
+    /// Nodes of the call tree: call frame and children. Node `i` has id `i + 1`, the first one is the root.
+    nodes: Vec<(Value, Vec<usize>)>,
+    /// Child node of a node for a code block address.
+    children: HashMap<(usize, usize), usize>,
+    hit_counts: Vec<u32>,
+    samples: Vec<usize>,
+    time_deltas: Vec<u64>,
+}
+
+struct Session {
+    socket: WebSocket<TcpStream>,
+    closed: bool,
+    /// Waiting for `Runtime.runIfWaitingForDebugger`.
+    waiting: bool,
+    /// Frames of the current pause.
+    frames: Option<Vec<DebugFrame>>,
+    resume: Option<ResumeAction>,
+    runtime_enabled: bool,
+    debugger_enabled: bool,
+    scripts: Vec<Script>,
+    /// Objects referenced by remote object ids together with their object group.
+    objects: HashMap<u64, (PersistentRooted, String)>,
+    next_object_id: u64,
+    profile: Option<Profile>,
+    sampling_interval: Duration,
+    last_poll: Instant,
+}
+
+impl Session {
+    fn new(socket: WebSocket<TcpStream>) -> Self {
+        Self {
+            socket,
+            closed: false,
+            waiting: false,
+            frames: None,
+            resume: None,
+            runtime_enabled: false,
+            debugger_enabled: false,
+            scripts: vec![],
+            objects: HashMap::new(),
+            next_object_id: 1,
+            profile: None,
+            sampling_interval: Duration::from_micros(1000),
+            last_poll: Instant::now(),
+        }
+    }
+
+    fn send(&mut self, message: Value) {
+        if self.closed {
+            return;
+        }
+        if self
+            .socket
+            .write_message(Message::Text(message.to_string()))
+            .is_err()
+        {
+            self.closed = true;
+        }
+    }
+
+    fn event(&mut self, method: &str, params: Value) {
+        self.send(json!({ "method": method, "params": params }));
+    }
+
+    /// Read and dispatch one message. Returns false when nothing was read.
+    fn receive(&mut self, ctx: GcPointer<Context>, block: bool) -> bool {
+        if self.closed {
+            return false;
+        }
+        if self.socket.get_mut().set_nonblocking(!block).is_err() {
+            self.closed = true;
+            return false;
+        }
+        let message = self.socket.read_message();
+        let _ = self.socket.get_mut().set_nonblocking(false);
+        let text = match message {
+            Ok(Message::Text(text)) => text,
+            Ok(Message::Close(_)) => {
+                self.closed = true;
+                return false;
+            }
+            Ok(_) => return true,
+            Err(tungstenite::Error::Io(ref e)) if e.kind() == io::ErrorKind::WouldBlock => {
+                return false
+            }
+            Err(_) => {
+                self.closed = true;
+                return false;
+            }
+        };
+        let request: Value = match serde_json::from_str(&text) {
+            Ok(request) => request,
+            Err(_) => return true,
+        };
+        let id = request["id"].clone();
+        let method = request["method"].as_str().unwrap_or("");
+        let response = match self.dispatch(ctx, method, &request["params"]) {
+            Ok(result) => json!({ "id": id, "result": result }),
+            Err(message) => json!({ "id": id, "error": { "code": -32000, "message": message } }),
+        };
+        self.send(response);
+        true
+    }
+
+    /// Process messages that arrived while JS code was running.
+    fn poll(&mut self, ctx: GcPointer<Context>) {
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return;
+        }
+        self.last_poll = Instant::now();
+        while self.receive(ctx, false) {}
+    }
+
+    fn dispatch(
+        &mut self,
+        mut ctx: GcPointer<Context>,
+        method: &str,
+        params: &Value,
+    ) -> Result<Value, String> {
+        let mut vm = ctx.vm();
+        let debugger = vm.debugger().ok_or("debugger is detached")?;
+        match method {
+            "Runtime.enable" => {
+                self.runtime_enabled = true;
+                self.event(
+                    "Runtime.executionContextCreated",
+                    json!({ "context": { "id": 1, "origin": "", "name": "starlight" } }),
+                );
+            }
+            "Runtime.disable" => self.runtime_enabled = false,
+            "Runtime.runIfWaitingForDebugger" => self.waiting = false,
+            "Runtime.evaluate" => {
+                let expression = params["expression"].as_str().ok_or("missing expression")?;
+                let group = params["objectGroup"].as_str().unwrap_or("console");
+                return Ok(self.evaluate(ctx, expression, group));
+            }
+            "Runtime.getProperties" => return self.get_properties(ctx, params),
+            "Runtime.releaseObject" => {
+                let id = object_id(params)?;
+                self.objects.remove(&id);
+            }
+            "Runtime.releaseObjectGroup" => {
+                let group = params["objectGroup"].as_str().unwrap_or("");
+                self.objects.retain(|_, (_, g)| g != group);
+            }
+            "Debugger.enable" => {
+                self.debugger_enabled = true;
+                for id in 0..self.scripts.len() {
+                    self.script_parsed_event(id);
+                }
+                return Ok(json!({ "debuggerId": TARGET_ID }));
+            }
+            "Debugger.disable" => self.debugger_enabled = false,
+            "Debugger.setBreakpointByUrl" => {
+                let url = params["url"]
+                    .as_str()
+                    .ok_or("only breakpoints by url are supported")?;
+                let line = params["lineNumber"].as_u64().ok_or("missing lineNumber")? as u32;
+                debugger.set_breakpoint(url, line + 1);
+                let locations = self
+                    .scripts
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, script)| script.url == url)
+                    .map(|(id, _)| {
+                        json!({ "scriptId": id.to_string(), "lineNumber": line, "columnNumber": 0 })
+                    })
+                    .collect::<Vec<_>>();
+                return Ok(json!({
+                    "breakpointId": breakpoint_id(url, line + 1),
+                    "locations": locations,
+                }));
+            }
+            "Debugger.removeBreakpoint" => {
+                let id = params["breakpointId"]
+                    .as_str()
+                    .ok_or("missing breakpointId")?;
+                let at = id.rfind(':').ok_or("invalid breakpointId")?;
+                let line = id[at + 1..].parse().map_err(|_| "invalid breakpointId")?;
+                debugger.remove_breakpoint(&id[..at], line);
+            }
+            "Debugger.setPauseOnExceptions" => {
+                debugger.set_pause_on_exceptions(match params["state"].as_str() {
+                    Some("uncaught") => PauseOnExceptions::Uncaught,
+                    Some("all") => PauseOnExceptions::All,
+                    _ => PauseOnExceptions::None,
+                });
+            }
+            "Debugger.getScriptSource" => {
+                let script = self.script(params)?;
+                return Ok(json!({ "scriptSource": script.source }));
+            }
+            "Debugger.pause" => debugger.pause(),
+            "Debugger.resume" => self.resume = Some(ResumeAction::Continue),
+            "Debugger.stepInto" => self.resume = Some(ResumeAction::StepIn),
+            "Debugger.stepOver" => self.resume = Some(ResumeAction::StepOver),
+            "Debugger.stepOut" => self.resume = Some(ResumeAction::StepOut),
+            "Debugger.evaluateOnCallFrame" => {
+                if self.frames.is_none() {
+                    return Err("not paused".to_owned());
+                }
+                let expression = params["expression"].as_str().ok_or("missing expression")?;
+                let group = params["objectGroup"].as_str().unwrap_or("backtrace");
+                return Ok(self.evaluate(ctx, expression, group));
+            }
+            "Profiler.enable"
+            | "Profiler.disable"
+            | "HeapProfiler.enable"
+            | "HeapProfiler.disable" => {}
+            "Profiler.setSamplingInterval" => {
+                let interval = params["interval"].as_u64().ok_or("missing interval")?;
+                self.sampling_interval = Duration::from_micros(interval);
+            }
+            "Profiler.start" => {
+                let now = Instant::now();
+                self.profile = Some(Profile {
+                    start: now,
+                    last_sample: now,
+                    nodes: vec![(call_frame("(root)", "", "", 0, 0), vec![])],
+                    children: HashMap::new(),
+                    hit_counts: vec![0],
+                    samples: vec![],
+                    time_deltas: vec![],
+                });
+            }
+            "Profiler.stop" => {
+                let profile = self.profile.take().ok_or("profiler is not started")?;
+                return Ok(json!({ "profile": profile.to_json() }));
+            }
+            "HeapProfiler.collectGarbage" => ctx.heap().gc(),
+            "HeapProfiler.takeHeapSnapshot" => {
+                let mut buf = vec![];
+                ctx.take_heap_snapshot()
+                    .write_to(&mut buf)
+                    .map_err(|e| e.to_string())?;
+                let snapshot = String::from_utf8(buf).map_err(|e| e.to_string())?;
+                let mut rest = snapshot.as_str();
+                while !rest.is_empty() {
+                    let mut end = rest.len().min(SNAPSHOT_CHUNK_SIZE);
+                    while !rest.is_char_boundary(end) {
+                        end -= 1;
+                    }
+                    self.event(
+                        "HeapProfiler.addHeapSnapshotChunk",
+                        json!({ "chunk": &rest[..end] }),
+                    );
+                    rest = &rest[end..];
+                }
+            }
+            _ => return Err(format!("'{}' wasn't found", method)),
+        }
+        Ok(json!({}))
+    }
+
+    fn evaluate(&mut self, ctx: GcPointer<Context>, expression: &str, group: &str) -> Value {
+        match ctx.eval(expression) {
+            Ok(value) => json!({ "result": self.remote_object(ctx, value, group) }),
+            Err(error) => {
+                let text = error
+                    .to_string(ctx)
+                    .unwrap_or_else(|_| "Uncaught".to_owned());
+                json!({
+                    "result": self.remote_object(ctx, error, group),
+                    "exceptionDetails": {
+                        "exceptionId": 1,
+                        "text": text,
+                        "lineNumber": 0,
+                        "columnNumber": 0,
+                        "exception": self.remote_object(ctx, error, group),
+                    },
+                })
+            }
+        }
+    }
+
+    fn get_properties(&mut self, ctx: GcPointer<Context>, params: &Value) -> Result<Value, String> {
+        let id = object_id(params)?;
+        let value = match self.objects.get(&id) {
+            Some((object, _)) => object.get_value(),
+            None => return Err("could not find object with given id".to_owned()),
+        };
+        let group = self.objects[&id].1.clone();
+        let mut object = value.get_jsobject();
+        let mut names = vec![];
+        object.get_own_property_names(
+            ctx,
+            &mut |name, _| names.push(name),
+            EnumerationMode::IncludeNotEnumerable,
+        );
+        let mut result = vec![];
+        for name in names {
+            let desc = match object.get_own_property(ctx, name) {
+                Some(desc) => desc,
+                None => continue,
+            };
+            let mut property = json!({
+                "name": ctx.description(name),
+                "configurable": desc.is_configurable(),
+                "enumerable": desc.is_enumerable(),
+                "isOwn": true,
+            });
+            if desc.is_data() {
+                property["value"] = self.remote_object(ctx, desc.value(), &group);
+                property["writable"] = json!(desc.is_writable());
+            } else {
+                property["get"] = self.remote_object(ctx, desc.getter(), &group);
+                property["set"] = self.remote_object(ctx, desc.setter(), &group);
+            }
+            result.push(property);
+        }
+        Ok(json!({ "result": result }))
+    }
+
+    /// Describe `value` as `Runtime.RemoteObject`, objects get an id valid until their group is released.
+    fn remote_object(&mut self, ctx: GcPointer<Context>, value: JsValue, group: &str) -> Value {
+        if value.is_undefined() {
+            return json!({ "type": "undefined" });
+        }
+        if value.is_null() {
+            return json!({ "type": "object", "subtype": "null", "value": null });
+        }
+        if value.is_bool() {
+            return json!({ "type": "boolean", "value": value.get_bool() });
+        }
+        if value.is_number() {
+            let number = value.get_number();
+            let description = JsValue::new(number).to_string(ctx).unwrap_or_default();
+            if number.is_finite() && !(number == 0.0 && number.is_sign_negative()) {
+                return json!({ "type": "number", "value": number, "description": description });
+            }
+            return json!({
+                "type": "number",
+                "unserializableValue": description,
+                "description": description,
+            });
+        }
+        if value.is_jsstring() {
+            return json!({ "type": "string", "value": value.get_string().as_str() });
+        }
+        let options = InspectOptions {
+            depth: 0,
+            ..Default::default()
+        };
+        let description = ctx.inspect(value, &options);
+        if !value.is_jsobject() {
+            return json!({ "type": "symbol", "description": description });
+        }
+        let object = value.get_jsobject();
+        let id = self.next_object_id;
+        self.next_object_id += 1;
+        let root = ctx.vm().add_persistent_root(value);
+        self.objects.insert(id, (root, group.to_owned()));
+        let kind = if object.is_callable() {
+            "function"
+        } else {
+            "object"
+        };
+        let mut remote = json!({
+            "type": kind,
+            "className": object.class.name,
+            "description": description,
+            "objectId": id.to_string(),
+        });
+        if object.tag() == ObjectTag::Array {
+            remote["subtype"] = json!("array");
+        } else if object.tag() == ObjectTag::Error {
+            remote["subtype"] = json!("error");
+        }
+        remote
+    }
+
+    fn script(&self, params: &Value) -> Result<&Script, String> {
+        params["scriptId"]
+            .as_str()
+            .and_then(|id| id.parse::<usize>().ok())
+            .and_then(|id| self.scripts.get(id))
+            .ok_or_else(|| "no script for id".to_owned())
+    }
+
+    fn script_id(&self, url: &str) -> String {
+        self.scripts
+            .iter()
+            .rposition(|script| script.url == url)
+            .map(|id| id.to_string())
+            .unwrap_or_default()
+    }
+
+    fn script_parsed_event(&mut self, id: usize) {
+        let script = &self.scripts[id];
+        let lines = script.source.lines().count();
+        let params = json!({
+            "scriptId": id.to_string(),
+            "url": script.url,
+            "startLine": 0,
+            "startColumn": 0,
+            "endLine": lines,
+            "endColumn": 0,
+            "executionContextId": 1,
+            "hash": "",
+        });
+        self.event("Debugger.scriptParsed", params);
+    }
+
+    /// Describe `frame` as `Debugger.CallFrame`.
+    fn call_frame(
+        &mut self,
+        mut ctx: GcPointer<Context>,
+        index: usize,
+        frame: &DebugFrame,
+    ) -> Value {
+        let (line, column) = frame
+            .location()
+            .map(|loc| (loc.line - 1, loc.col - 1))
+            .unwrap_or((0, 0));
+        let url = frame.file_name().to_owned();
+        let mut scopes = vec![];
+        if !frame.slots().is_empty() {
+            let mut object = JsObject::new_empty(ctx);
+            for (i, value) in frame.slots().iter().enumerate() {
+                if !value.is_empty() {
+                    let name = frame
+                        .slot_name(i)
+                        .unwrap_or_else(|| format!("slot {}", i).intern());
+                    let _ = object.put(ctx, name, *value, false);
+                }
+            }
+            scopes.push(("local", JsValue::new(object)));
+        }
+        for (depth, env) in frame.scopes().enumerate() {
+            let mut object = JsObject::new_empty(ctx);
+            for (i, var) in env.as_slice().iter().enumerate() {
+                if var.is_initialized() {
+                    let name = env.name(i).unwrap_or_else(|| format!("var {}", i).intern());
+                    let _ = object.put(ctx, name, var.value, false);
+                }
+            }
+            let kind = if depth == 0 && frame.code_block().needs_environment && scopes.is_empty() {
+                "local"
+            } else {
+                "closure"
+            };
+            scopes.push((kind, JsValue::new(object)));
+        }
+        scopes.push(("global", JsValue::new(ctx.global_object())));
+        let scope_chain = scopes
+            .into_iter()
+            .map(|(kind, object)| {
+                json!({ "type": kind, "object": self.remote_object(ctx, object, "backtrace") })
+            })
+            .collect::<Vec<_>>();
+        json!({
+            "callFrameId": index.to_string(),
+            "functionName": frame.function_name(ctx),
+            "location": {
+                "scriptId": self.script_id(&url),
+                "lineNumber": line,
+                "columnNumber": column,
+            },
+            "url": url,
+            "scopeChain": scope_chain,
+            "this": self.remote_object(ctx, frame.this(), "backtrace"),
+        })
+    }
+
+    /// Add sample of `frames` to the running profile unless the previous one was taken less than sampling
+    /// interval ago.
+    fn sample(&mut self, ctx: GcPointer<Context>, frames: &[DebugFrame]) {
+        let mut profile = match self.profile.take() {
+            Some(profile) => profile,
+            None => return,
+        };
+        let now = Instant::now();
+        if now - profile.last_sample >= self.sampling_interval {
+            let mut node = 0;
+            for frame in frames.iter().rev() {
+                let code = frame.code_block();
+                let key = (node, &*code as *const CodeBlock as usize);
+                node = match profile.children.get(&key) {
+                    Some(&child) => child,
+                    None => {
+                        // functions are identified by the line of their first statement.
+                        let line = code.loc.iter().map(|(_, loc)| loc.line).min().unwrap_or(1);
+                        let frame = call_frame(
+                            &frame.function_name(ctx),
+                            &self.script_id(&code.file_name),
+                            &code.file_name,
+                            line - 1,
+                            0,
+                        );
+                        let child = profile.nodes.len();
+                        profile.nodes.push((frame, vec![]));
+                        profile.hit_counts.push(0);
+                        profile.nodes[node].1.push(child);
+                        profile.children.insert(key, child);
+                        child
+                    }
+                };
+            }
+            profile.hit_counts[node] += 1;
+            profile.samples.push(node);
+            profile
+                .time_deltas
+                .push((now - profile.last_sample).as_micros() as u64);
+            profile.last_sample = now;
+        }
+        self.profile = Some(profile);
+    }
+}
+
+impl Profile {
+    fn to_json(&self) -> Value {
+        let nodes = self
+            .nodes
+            .iter()
+            .enumerate()
+            .map(|(i, (frame, children))| {
+                json!({
+                    "id": i + 1,
+                    "callFrame": frame,
+                    "hitCount": self.hit_counts[i],
+                    "children": children.iter().map(|child| child + 1).collect::<Vec<_>>(),
+                })
+            })
+            .collect::<Vec<_>>();
+        json!({
+            "nodes": nodes,
+            "startTime": 0,
+            "endTime": (self.last_sample - self.start).as_micros() as u64,
+            "samples": self.samples.iter().map(|node| node + 1).collect::<Vec<_>>(),
+            "timeDeltas": self.time_deltas,
+        })
+    }
+}
+
+fn call_frame(name: &str, script_id: &str, url: &str, line: u32, column: u32) -> Value {
+    json!({
+        "functionName": name,
+        "scriptId": script_id,
+        "url": url,
+        "lineNumber": line,
+        "columnNumber": column,
+    })
+}
+
+fn object_id(params: &Value) -> Result<u64, String> {
+    params["objectId"]
+        .as_str()
+        .and_then(|id| id.parse().ok())
+        .ok_or_else(|| "invalid objectId".to_owned())
+}
+
+fn breakpoint_id(url: &str, line: u32) -> String {
+    format!("{}:{}", url, line)
+}
+
+/// Debugger delegate forwarding events to the DevTools session.
+struct Inspector {
+    session: Rc<RefCell<Session>>,
+}
+
+impl Inspector {
+    /// Run `f` unless the session is busy, i.e. JS code runs on behalf of [InspectorServer::attach].
+    fn with_session(&self, ctx: GcPointer<Context>, f: impl FnOnce(&mut Session)) {
+        if let Ok(mut session) = self.session.try_borrow_mut() {
+            if session.closed {
+                ctx.vm().remove_debugger();
+                return;
+            }
+            f(&mut session);
+        }
+    }
+}
+
+impl DebuggerDelegate for Inspector {
+    fn paused(
+        &mut self,
+        ctx: GcPointer<Context>,
+        reason: PauseReason,
+        frames: &[DebugFrame],
+    ) -> ResumeAction {
+        let mut action = ResumeAction::Continue;
+        self.with_session(ctx, |session| {
+            let (reason, data, hit) = match reason {
+                PauseReason::Breakpoint => {
+                    let hit = frames[0]
+                        .location()
+                        .map(|loc| vec![breakpoint_id(frames[0].file_name(), loc.line)])
+                        .unwrap_or_default();
+                    ("other", Value::Null, hit)
+                }
+                PauseReason::Step | PauseReason::DebuggerStatement => {
+                    ("other", Value::Null, vec![])
+                }
+                PauseReason::Exception { value, .. } => {
+                    let data = session.remote_object(ctx, value, "backtrace");
+                    ("exception", data, vec![])
+                }
+            };
+            let call_frames = frames
+                .iter()
+                .enumerate()
+                .map(|(i, frame)| session.call_frame(ctx, i, frame))
+                .collect::<Vec<_>>();
+            session.event(
+                "Debugger.paused",
+                json!({
+                    "callFrames": call_frames,
+                    "reason": reason,
+                    "data": data,
+                    "hitBreakpoints": hit,
+                }),
+            );
+            session.frames = Some(frames.to_vec());
+            session.resume = None;
+            while session.resume.is_none() && !session.closed {
+                session.receive(ctx, true);
+            }
+            session.frames = None;
+            session.objects.retain(|_, (_, group)| group != "backtrace");
+            action = session.resume.take().unwrap_or(ResumeAction::Continue);
+            session.event("Debugger.resumed", json!({}));
+        });
+        action
+    }
+
+    fn statement(&mut self, ctx: GcPointer<Context>, _frame: &DebugFrame) {
+        self.with_session(ctx, |session| {
+            if session.profile.is_some() {
+                session.sample(ctx, &debugger::frames(ctx));
+            }
+            session.poll(ctx);
+        });
+    }
+
+    fn script_parsed(&mut self, ctx: GcPointer<Context>, file: &str, source: &str) {
+        self.with_session(ctx, |session| {
+            session.scripts.push(Script {
+                url: file.to_owned(),
+                source: source.to_owned(),
+            });
+            if session.debugger_enabled {
+                session.script_parsed_event(session.scripts.len() - 1);
+            }
+        });
+    }
+
+    fn console_message(&mut self, ctx: GcPointer<Context>, level: &str, args: &[JsValue]) {
+        self.with_session(ctx, |session| {
+            if !session.runtime_enabled {
+                return;
+            }
+            let args = args
+                .iter()
+                .map(|arg| session.remote_object(ctx, *arg, "console"))
+                .collect::<Vec<_>>();
+            let timestamp = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|time| time.as_secs_f64() * 1000.0)
+                .unwrap_or(0.0);
+            session.event(
+                "Runtime.consoleAPICalled",
+                json!({
+                    "type": level,
+                    "args": args,
+                    "executionContextId": 1,
+                    "timestamp": timestamp,
+                }),
+            );
+        });
+    }
+}
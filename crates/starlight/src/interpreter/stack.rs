@@ -1,3 +1,4 @@
+#[cfg(feature = "mmap")]
 use comet::mmap::Mmap;
 use std::ptr::null_mut;
 
@@ -5,13 +6,44 @@ use crate::vm::VirtualMachineRef;
 
 use super::{callframe::CallFrame, register::Register};
 
+#[cfg(feature = "mmap")]
+type Reservation = Mmap;
+
+/// Heap allocated stand-in for [Mmap] on targets without virtual memory. The whole reservation is committed up
+/// front.
+#[cfg(not(feature = "mmap"))]
+struct Reservation {
+    memory: Box<[u8]>,
+}
+
+#[cfg(not(feature = "mmap"))]
+impl Reservation {
+    fn new(size: usize) -> Self {
+        Self {
+            memory: vec![0; size].into_boxed_slice(),
+        }
+    }
+
+    fn start(&self) -> *mut u8 {
+        self.memory.as_ptr() as *mut u8
+    }
+
+    fn size(&self) -> usize {
+        self.memory.len()
+    }
+
+    fn commit(&self, _page: *mut u8, _size: usize) {}
+
+    fn dontneed(&self, _page: *mut u8, _size: usize) {}
+}
+
 /// Allow 8k of excess registers before we start trying to reap the stack
 pub const MAX_EXCESS_CAPACITY: usize = 8 * 1024;
 pub struct InterpreterLoopStack {
     top_call_frame: &'static mut *mut CallFrame,
     end: *mut Register,
     commit_top: *mut Register,
-    reservation: Mmap,
+    reservation: Reservation,
     last_stack_pointer: *mut u8,
     current_stack_pointer: *mut u8,
     soft_reserved_zone_in_registers: usize,
@@ -47,7 +79,7 @@ impl InterpreterLoopStack {
     }
 
     pub fn new(mut vm: VirtualMachineRef) -> Self {
-        let reservation = Mmap::new(4 * 1024 * 1024);
+        let reservation = Reservation::new(4 * 1024 * 1024);
 
         let mut this = Self {
             vm,
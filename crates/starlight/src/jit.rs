@@ -0,0 +1,266 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! Speculation analysis for a future baseline JIT.
+//!
+//! With the `jit` feature every [CodeBlock] counts function entries and loop back edges in `hotness`. Once
+//! the counter reaches [TIER_UP_THRESHOLD] the code block is handed to [analyze] which turns type feedback
+//! collected by the interpreter into a list of [Speculation]s: guarded fast paths that a baseline compiler
+//! would emit in place of the generic bytecode handler.
+//!
+//! There is no machine code backend and no deoptimization. The only consumer of the analysis is
+//! [specialize], which rewrites int32 arithmetic into specialized opcodes; the code block is then marked
+//! [JitState::Analyzed] and keeps running in the interpreter. Counting costs every call and backward jump,
+//! so the feature is off by default. See `docs/Internals.md`.
+use crate::{
+    bytecode::{
+        opcodes::Opcode,
+        profile::{ArithProfile, ByValMode, ByValProfile},
+        GetByIdMode, TypeFeedBack,
+    },
+    gc::cell::GcPointer,
+    vm::{code_block::CodeBlock, structure::Structure},
+};
+
+/// Number of calls plus loop iterations after which code block is analyzed.
+pub const TIER_UP_THRESHOLD: u32 = 1000;
+
+/// Tiering state of a single code block.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum JitState {
+    /// Code block runs in the interpreter and counts hotness.
+    Interpreted,
+    /// Feedback was analyzed and arithmetic specialized, code block stops counting.
+    Analyzed,
+}
+
+/// Fast path that a baseline compiler could emit for instruction at `pc` instead of generic handler. Every
+/// speculation is guarded and would deoptimize back to `pc` on guard failure.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Speculation {
+    /// Both operands were always int32 and result never overflowed. Guards: operand tags, overflow flag.
+    Int32Arith { pc: u32, op: Opcode },
+    /// Operands were always numbers. Guards: operand tags.
+    NumberArith { pc: u32, op: Opcode },
+    /// Property load from a single structure. Guard: structure of the object.
+    MonomorphicLoad {
+        pc: u32,
+        structure: GcPointer<Structure>,
+        offset: u32,
+    },
+    /// `length` of array. Guard: class of the object.
+    ArrayLength { pc: u32 },
+    /// Store into existing property of a single structure. Guard: structure of the object.
+    MonomorphicStore {
+        pc: u32,
+        structure: GcPointer<Structure>,
+        offset: u32,
+    },
+    /// Indexed access of dense object with int32 key. Guards: key tag, dense storage, bounds.
+    DenseIndexed { pc: u32, op: Opcode },
+    /// Indexed load from string primitive. Guards: key tag, string tag, bounds.
+    StringIndexed { pc: u32 },
+}
+
+impl Speculation {
+    /// Address of guarded instruction, interpreter resumes here after deoptimization.
+    pub fn deopt_pc(&self) -> u32 {
+        match *self {
+            Self::Int32Arith { pc, .. }
+            | Self::NumberArith { pc, .. }
+            | Self::MonomorphicLoad { pc, .. }
+            | Self::ArrayLength { pc }
+            | Self::MonomorphicStore { pc, .. }
+            | Self::DenseIndexed { pc, .. }
+            | Self::StringIndexed { pc } => pc,
+        }
+    }
+}
+
+fn arith_speculation(pc: u32, op: Opcode, profile: ArithProfile) -> Option<Speculation> {
+    let (lhs, rhs) = (profile.lhs_observed_type(), profile.rhs_observed_type());
+    if lhs.is_empty() || rhs.is_empty() {
+        // never executed, compiled code keeps generic call.
+        return None;
+    }
+    let int_op = matches!(op, Opcode::OP_ADD | Opcode::OP_SUB | Opcode::OP_MUL);
+    if int_op
+        && lhs.is_only_int32()
+        && rhs.is_only_int32()
+        && !profile.did_observe_int32_overflow()
+    {
+        Some(Speculation::Int32Arith { pc, op })
+    } else if !lhs.saw_non_number() && !rhs.saw_non_number() {
+        Some(Speculation::NumberArith { pc, op })
+    } else {
+        None
+    }
+}
+
+/// Collect fast paths that are justified by feedback of `code`. Nested code blocks are not visited, each of
+/// them tiers up on its own.
+pub fn speculations(code: &CodeBlock) -> Vec<Speculation> {
+    let mut result = vec![];
+    let bytes = &code.code;
+    let operand = |at: usize| {
+        u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+    };
+    let mut pc = 0;
+    while pc < bytes.len() {
+        let op = unsafe { std::mem::transmute::<u8, Opcode>(bytes[pc]) };
+        let at = pc as u32;
+        match op {
+            Opcode::OP_ADD | Opcode::OP_SUB | Opcode::OP_MUL | Opcode::OP_DIV | Opcode::OP_REM => {
+                let profile = unsafe { bytes.as_ptr().add(pc + 1).cast::<ArithProfile>().read_unaligned() };
+                result.extend(arith_speculation(at, op, profile));
+            }
+            // already specialized by a previous tier-up, guards are the same.
+            Opcode::OP_ADD_INT => result.push(Speculation::Int32Arith {
+                pc: at,
+                op: Opcode::OP_ADD,
+            }),
+            Opcode::OP_SUB_INT => result.push(Speculation::Int32Arith {
+                pc: at,
+                op: Opcode::OP_SUB,
+            }),
+            // the fused fast path does not update the profile of `add`, but constant operand is int32 and
+            // profile still records every miss.
+            Opcode::OP_PUSH_INT_ADD => {
+                let add = pc + 5;
+                let profile = unsafe { bytes.as_ptr().add(add + 1).cast::<ArithProfile>().read_unaligned() };
+                if !profile.did_observe_int32_overflow() && !profile.rhs_observed_type().saw_non_number() {
+                    result.push(Speculation::Int32Arith {
+                        pc: add as u32,
+                        op: Opcode::OP_ADD,
+                    });
+                }
+            }
+            Opcode::OP_GET_BY_ID | Opcode::OP_TRY_GET_BY_ID | Opcode::OP_PUT_BY_ID => {
+                let fdbk = operand(pc + 5) as usize;
+                match &code.feedback[fdbk] {
+                    TypeFeedBack::PropertyCache {
+                        structure,
+                        offset,
+                        mode: GetByIdMode::Default,
+                    } => result.push(Speculation::MonomorphicLoad {
+                        pc: at,
+                        structure: *structure,
+                        offset: *offset,
+                    }),
+                    TypeFeedBack::PropertyCache {
+                        mode: GetByIdMode::ArrayLength,
+                        ..
+                    } => result.push(Speculation::ArrayLength { pc: at }),
+                    TypeFeedBack::PutByIdFeedBack {
+                        new_structure: None,
+                        old_structure: Some(structure),
+                        offset,
+                        ..
+                    } => result.push(Speculation::MonomorphicStore {
+                        pc: at,
+                        structure: *structure,
+                        offset: *offset,
+                    }),
+                    _ => (),
+                }
+            }
+            Opcode::OP_GET_BY_VAL | Opcode::OP_GET_BY_VAL_PUSH_OBJ | Opcode::OP_PUT_BY_VAL => {
+                let profile = unsafe { &*bytes.as_ptr().add(pc + 1).cast::<ByValProfile>() };
+                match profile.mode {
+                    ByValMode::DenseArray => result.push(Speculation::DenseIndexed { pc: at, op }),
+                    ByValMode::StringIndex if op != Opcode::OP_PUT_BY_VAL => {
+                        result.push(Speculation::StringIndexed { pc: at })
+                    }
+                    _ => (),
+                }
+            }
+            _ => (),
+        }
+        pc += 1 + op.operand_size();
+    }
+    result
+}
+
+/// Rewrite `add`/`sub` instructions covered by [Speculation::Int32Arith] into their int32 variants that skip
+/// profiling and number decoding. Rewrite keeps the size of code and the interpreter reverts it on the first
+/// guard failure. Returns number of rewritten instructions.
+pub fn specialize(code: &mut CodeBlock, speculations: &[Speculation]) -> usize {
+    let mut rewritten = 0;
+    for speculation in speculations {
+        if let Speculation::Int32Arith { pc, op } = *speculation {
+            let specialized = match op {
+                Opcode::OP_ADD => Opcode::OP_ADD_INT,
+                Opcode::OP_SUB => Opcode::OP_SUB_INT,
+                _ => continue,
+            };
+            let byte = &mut code.code[pc as usize];
+            if *byte == op as u8 {
+                *byte = specialized as u8;
+                rewritten += 1;
+            }
+        }
+    }
+    rewritten
+}
+
+/// Analyze feedback of `code` and specialize its arithmetic. Called by the interpreter once code block
+/// becomes hot. Returns number of rewritten instructions.
+pub fn analyze(mut code: GcPointer<CodeBlock>) -> usize {
+    let speculations = speculations(&code);
+    code.jit_state = JitState::Analyzed;
+    specialize(&mut code, &speculations)
+}
+
+/// Count one function entry or loop iteration of `code` and analyze it when it becomes hot.
+#[cfg(feature = "jit")]
+#[inline]
+pub(crate) fn count(mut code: GcPointer<CodeBlock>) {
+    if code.jit_state != JitState::Interpreted {
+        return;
+    }
+    code.hotness += 1;
+    if code.hotness >= TIER_UP_THRESHOLD {
+        #[cold]
+        #[inline(never)]
+        fn tier_up(code: GcPointer<CodeBlock>) {
+            analyze(code);
+        }
+        tier_up(code);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::vm::tests::new_context;
+
+    #[test]
+    fn test_int_specialization() {
+        let (_vm, mut ctx) = new_context();
+
+        let listing = ctx
+            .disassemble_script("function f(n) { for (var i = 0; i < n; i++) {} return --n }")
+            .ok()
+            .unwrap();
+        assert!(listing.contains("inc_slot"));
+        assert!(listing.contains("dec_slot"));
+
+        let g = ctx
+            .eval("function g(a, b) { return a + b } for (var i = 0; i < 2000; i++) g(i, 1); g")
+            .ok()
+            .unwrap();
+        let code = g.get_jsobject().as_function().as_vm().code;
+        // without the `jit` feature nothing counts calls, tier up by hand.
+        #[cfg(not(feature = "jit"))]
+        crate::jit::analyze(code);
+        assert!(code.disassemble(ctx).contains("add_int"));
+
+        // failed guard restores generic add.
+        let result = ctx
+            .eval("g(1, 2) + g('a', 'b') + g(2147483647, 1)")
+            .ok()
+            .unwrap();
+        assert_eq!(result.get_jsstring().as_str(), "3ab2147483648");
+        assert!(!code.disassemble(ctx).contains("add_int"));
+    }
+}
@@ -16,14 +16,20 @@ use std::{collections::HashMap, rc::Rc};
 pub mod array;
 pub mod array_buffer;
 pub mod boolean;
+pub mod commonjs;
+pub mod console;
 pub mod data_view;
 pub mod date;
 pub mod error;
+#[cfg(feature = "fetch")]
+pub mod fetch;
 #[cfg(all(target_pointer_width = "64", feature = "ffi"))]
 pub mod ffi;
 pub mod function;
 pub mod generator;
 pub mod global;
+#[cfg(feature = "intl")]
+pub mod intl;
 pub mod js262;
 pub mod jsstd;
 pub mod math;
@@ -187,6 +193,16 @@ pub static VM_NATIVE_REFERENCES: Lazy<Vec<usize>> = Lazy::new(|| {
         jsstd::file::std_file_read_bytes_to_end as _,
         jsstd::file::std_file_close as _,
         jsstd::std_args as _,
+        // assert module
+        jsstd::assert::init_assert as _,
+        jsstd::assert::assert_ok as _,
+        jsstd::assert::assert_equal as _,
+        jsstd::assert::assert_strict_equal as _,
+        jsstd::assert::assert_deep_equal as _,
+        jsstd::assert::assert_throws as _,
+        jsstd::assert::assert_rejects as _,
+        jsstd::assert::assert_rejects_fulfilled as _,
+        jsstd::assert::assert_rejects_rejected as _,
         // Misc
         JsArrayBuffer::class() as *const _ as usize,
         js262::_262_create_realm as _,
@@ -195,9 +211,17 @@ pub static VM_NATIVE_REFERENCES: Lazy<Vec<usize>> = Lazy::new(|| {
 
     #[cfg(all(target_pointer_width = "64", feature = "ffi"))]
     {
+        refs.push(ffi::ffi_open as _);
+        refs.push(ffi::ffi_callback as _);
+        refs.push(ffi::ffi_callback_close as _);
+        refs.push(ffi::ffi_read as _);
+        refs.push(ffi::ffi_write as _);
+        refs.push(ffi::ffi_cstring as _);
+        refs.push(ffi::ffi_size_of as _);
+        refs.push(ffi::ffi_library_symbol as _);
+        refs.push(ffi::ffi_library_close as _);
         refs.push(ffi::ffi_function_attach as _);
         refs.push(ffi::ffi_function_call as _);
-        refs.push(ffi::ffi_library_open as _);
     }
 
     macro_rules! define_register_native_reference {
@@ -221,6 +245,44 @@ pub fn get_length(ctx: GcPointer<Context>, val: &mut GcPointer<JsObject>) -> Res
     len.to_length(ctx)
 }
 
+/// Parse JSON `text` into JS values, syntax errors are reported as `SyntaxError` prefixed with `what`.
+pub(crate) fn parse_json(
+    mut ctx: GcPointer<Context>,
+    text: &[u8],
+    what: &str,
+) -> Result<JsValue, JsValue> {
+    fn convert(ctx: GcPointer<Context>, json: &serde_json::Value) -> Result<JsValue, JsValue> {
+        use serde_json::Value;
+        Ok(match json {
+            Value::Null => JsValue::encode_null_value(),
+            Value::Bool(x) => JsValue::new(*x),
+            Value::Number(x) => JsValue::new(x.as_f64().unwrap_or(f64::NAN)),
+            Value::String(x) => JsValue::new(JsString::new(ctx, x)),
+            Value::Array(values) => {
+                let values = values
+                    .iter()
+                    .map(|value| convert(ctx, value))
+                    .collect::<Result<Vec<_>, _>>()?;
+                JsValue::new(JsArray::from_slice(ctx, &values))
+            }
+            Value::Object(entries) => {
+                let mut object = JsObject::new_empty(ctx);
+                for (key, value) in entries.iter() {
+                    let value = convert(ctx, value)?;
+                    object.put(ctx, key.intern(), value, false)?;
+                }
+                JsValue::new(object)
+            }
+        })
+    }
+    let json = serde_json::from_slice::<serde_json::Value>(text)
+        .map_err(|e| JsValue::new(ctx.new_syntax_error(format!("{}: {}", what, e))))?;
+    let defer = ctx.heap().defer();
+    let value = convert(ctx, &json);
+    drop(defer);
+    value
+}
+
 /// Convert JS object to JS property descriptor
 pub fn to_property_descriptor(
     ctx: GcPointer<Context>,
@@ -335,82 +397,70 @@ pub fn to_property_descriptor(
     }
 }
 
+fn new_module_object(ctx: GcPointer<Context>) -> Result<GcPointer<JsObject>, JsValue> {
+    let mut module_object = JsObject::new_empty(ctx);
+    let exports = JsObject::new_empty(ctx);
+    module_object.put(ctx, S_EXPORTS.intern(), JsValue::new(exports), false)?;
+    Ok(module_object)
+}
+
+fn load_registered_module(mut ctx: GcPointer<Context>, key: &str) -> Result<JsValue, JsValue> {
+    match ctx.modules()[key] {
+        ModuleKind::Initialized(x) => Ok(JsValue::new(x)),
+        ModuleKind::NativeUninit(init) => {
+            let module = new_module_object(ctx)?;
+            init(ctx, module)?;
+            ctx.modules()
+                .insert(key.to_string(), ModuleKind::Initialized(module));
+
+            Ok(JsValue::new(module))
+        }
+    }
+}
+
 pub(crate) fn module_load(
     mut ctx: GcPointer<Context>,
     args: &Arguments,
 ) -> Result<JsValue, JsValue> {
     let name = args.at(0).to_string(ctx)?;
-    let rel_path = unsafe { (*ctx.stack.current).code_block.unwrap().path.clone() };
-    let _is_js_load = (name.starts_with("./")
-        || name.starts_with("../")
-        || name.starts_with('/')
-        || name.ends_with(".js"))
-        && name.ends_with(".js");
-    let spath = name;
-    let mut spath = if rel_path.is_empty() {
-        spath
-    } else {
-        format!("{}/{}", rel_path, spath)
-    };
-    if cfg!(windows) {
-        spath = spath.replace("/", "\\");
+    // Native modules such as "std" are registered by bare name and not resolved by the loader.
+    if ctx.modules().contains_key(&name) {
+        return load_registered_module(ctx, &name);
     }
-    let path = std::path::Path::new(&spath);
-    let path = match path.canonicalize() {
-        Err(e) => {
-            return Err(JsValue::new(ctx.new_reference_error(format!(
-                "Module '{}' not found: '{}'",
-                path.display(),
-                e
-            ))))
-        }
-        Ok(path) => path,
-    };
-
-    let mut module_object = JsObject::new_empty(ctx);
-    let mut exports = JsObject::new_empty(ctx);
-    module_object.put(ctx, S_EXPORTS.intern(), JsValue::new(exports), false)?;
+    let referrer = unsafe { (*ctx.stack.current).code_block.unwrap().path.clone() };
+    let loader = ctx.vm().loader.clone();
+    let key = loader.resolve(&name, &referrer).map_err(|e| {
+        JsValue::new(ctx.new_reference_error(format!("Module '{}' not found: {}", name, e)))
+    })?;
+    if ctx.modules().contains_key(&key) {
+        return load_registered_module(ctx, &key);
+    }
+    let source = loader
+        .load(&key)
+        .and_then(|source| loader.transform(&key, source))
+        .map_err(|e| {
+            JsValue::new(ctx.new_type_error(format!("Failed to load module '{}': {}", key, e)))
+        })?;
+    let file_name = std::path::Path::new(&key)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| key.clone());
+    let module_fun = ctx.compile_module(&key, &file_name, &source)?;
+    let module_object = new_module_object(ctx)?;
+    // Cache the module before it runs so circular imports get the partially initialized module.
+    ctx.modules()
+        .insert(key.clone(), ModuleKind::Initialized(module_object));
     let mut args = [JsValue::new(module_object)];
     let mut args = Arguments::new(JsValue::encode_undefined_value(), &mut args);
-
-    if let Some(module) = ctx.modules().get(&spath).copied() {
-        match module {
-            ModuleKind::Initialized(x) => {
-                return Ok(JsValue::new(x));
-            }
-            ModuleKind::NativeUninit(init) => {
-                let mut module = module_object;
-                init(ctx, module)?;
-                ctx.modules()
-                    .insert(spath.clone(), ModuleKind::Initialized(module));
-
-                return Ok(JsValue::new(module));
-            }
-        }
-    }
-    if !path.exists() {
-        return Err(JsValue::new(
-            ctx.new_type_error(format!("Module '{}' not found", spath)),
-        ));
-    }
-    let source = match std::fs::read_to_string(&path) {
-        Ok(source) => source,
-        Err(e) => {
-            return Err(JsValue::new(ctx.new_type_error(format!(
-                "Failed to read module '{}': {}",
-                spath,
-                e.to_string()
-            ))));
-        }
-    };
-    let name = path.file_name().unwrap().to_str().unwrap().to_string();
-    let module_fun = ctx.compile_module(&spath, &name, &source)?;
     let mut module_fun = module_fun.get_jsobject();
-    module_fun
-        .as_function_mut()
-        .call(ctx, &mut args, JsValue::encode_undefined_value())?;
-    ctx.modules()
-        .insert(spath.clone(), ModuleKind::Initialized(module_object));
+    if let Err(e) =
+        module_fun
+            .as_function_mut()
+            .call(ctx, &mut args, JsValue::encode_undefined_value())
+    {
+        ctx.modules().remove(&key);
+        return Err(e);
+    }
     Ok(JsValue::new(module_object))
 }
 
@@ -497,6 +547,11 @@ macro_rules! define_op_builtins {
         $op!(JsWeakRef);
         $op!(JsDate);
         $op!(JsBoolean);
+        $op!(JsConsole);
+        #[cfg(feature = "fetch")]
+        $op!(JsFetch);
+        #[cfg(feature = "intl")]
+        $op!(JsIntl);
         $op!(SelfHost);
     };
 }
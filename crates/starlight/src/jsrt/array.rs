@@ -1,17 +1,17 @@
 /* This Source Code Form is subject to the terms of the Mozilla Public
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
-use std::{intrinsics::unlikely, u32};
+use crate::utils::unlikely;
+use std::{fmt::Write, u32};
 
 use super::object::object_to_string;
 use crate::{
-    constant::S_CONSTURCTOR,
     gc::cell::GcPointer,
     jsrt::{array, get_length},
     vm::{
         arguments::*, array::*, attributes::*, builder::Builtin, class::JsClass, context::Context,
-        error::*, function::JsNativeFunction, object::*, property_descriptor::DataDescriptor,
-        string::*, structure::Structure, symbol_table::*, value::*,
+        error::*, function::JsNativeFunction, object::*, operations::array_species_create,
+        property_descriptor::*, string::*, structure::Structure, symbol_table::*, value::*,
     },
 };
 pub fn array_ctor(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, JsValue> {
@@ -77,7 +77,7 @@ pub fn array_of(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, Js
 pub fn array_from(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, JsValue> {
     
     letroot!(arg1 = stack, args.at(0).to_object(ctx)?);
-    let len = arg1.get(ctx, "length".intern())?;
+    let len = arg1.get(ctx, Symbol::LENGTH)?;
     let len = if len.is_number() {
         let n = len.to_number(ctx)?;
         if n as u32 as f64 == n {
@@ -101,7 +101,7 @@ pub fn array_from(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue,
 pub fn array_join(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, JsValue> {
     
     letroot!(obj = stack, args.this.to_object(ctx)?);
-    let len = obj.get(ctx, "length".intern())?.to_number(ctx)?;
+    let len = obj.get(ctx, Symbol::LENGTH)?.to_number(ctx)?;
     let len = if len as u32 as f64 == len {
         len as u32
     } else {
@@ -113,27 +113,86 @@ pub fn array_join(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue,
         ",".to_string()
     };
 
-    let mut fmt = String::new();
+    if let Some(string) = join_dense(ctx, &obj, len, &separator) {
+        return Ok(JsValue::new(string));
+    }
+
+    let mut builder = StringBuilder::new();
     {
         let element0 = obj.get(ctx, Symbol::Index(0))?;
         if !(element0.is_undefined() || element0.is_null()) {
-            let str = element0.to_string(ctx)?;
-            fmt.push_str(&str);
+            builder.append_value(ctx, element0)?;
         }
     }
 
     let mut k: u32 = 1;
     while k < len {
-        fmt.push_str(&separator);
+        builder.append_str(&separator);
         let element = obj.get(ctx, Symbol::Index(k))?;
         if !(element.is_undefined() || element.is_null()) {
-            let str = element.to_string(ctx)?;
-            fmt.push_str(&str);
+            builder.append_value(ctx, element)?;
         }
         k += 1;
     }
-    Ok(JsValue::encode_object_value(JsString::new(ctx, fmt)))
+    Ok(JsValue::encode_object_value(builder.build(ctx)))
+}
+/// Join elements of a dense array directly from its element vector. Returns `None` when an element is a
+/// hole or an object, whose conversion could run user code, the generic path of [array_join] handles those.
+fn join_dense(
+    ctx: GcPointer<Context>,
+    obj: &GcPointer<JsObject>,
+    len: u32,
+    separator: &str,
+) -> Option<GcPointer<JsString>> {
+    if !obj.indexed.dense() || len > obj.indexed.vector.size() {
+        return None;
+    }
+    // measure first so that the output is allocated once.
+    let mut capacity = separator.len() * len.saturating_sub(1) as usize;
+    for index in 0..len {
+        let element = *obj.indexed.vector.at(index);
+        capacity += if element.is_empty() {
+            return None;
+        } else if element.is_jsstring() {
+            element.get_jsstring().len() as usize
+        } else if element.is_int32() {
+            let value = element.get_int32();
+            let mut digits = value.unsigned_abs();
+            let mut width = 1 + (value < 0) as usize;
+            while digits >= 10 {
+                digits /= 10;
+                width += 1;
+            }
+            width
+        } else if element.is_number() {
+            // longest shortest representation of a double, e.g. `-2.2250738585072014e-308`.
+            24
+        } else if element.is_bool() {
+            5
+        } else if element.is_undefined() || element.is_null() {
+            0
+        } else {
+            return None;
+        };
+    }
+
+    let mut builder = StringBuilder::with_capacity(capacity);
+    for index in 0..len {
+        if index != 0 {
+            builder.append_str(separator);
+        }
+        let element = *obj.indexed.vector.at(index);
+        if element.is_jsstring() {
+            builder.append(element.get_jsstring());
+        } else if element.is_int32() {
+            let _ = write!(builder, "{}", element.get_int32());
+        } else if !(element.is_undefined() || element.is_null()) {
+            builder.append_value(ctx, element).ok()?;
+        }
+    }
+    Some(builder.build(ctx))
 }
+
 pub fn array_to_string(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, JsValue> {
     
     letroot!(this = stack, args.this.to_object(ctx)?);
@@ -154,7 +213,7 @@ pub fn array_to_string(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsVa
 // TODO(playX): Allow to push up to 2^53-1 values
 pub fn array_push(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, JsValue> {
     let mut obj = args.this.to_object(ctx)?;
-    let n = obj.get(ctx, "length".intern())?.to_number(ctx)?;
+    let n = obj.get(ctx, Symbol::LENGTH)?.to_number(ctx)?;
     let mut n = if n as u32 as f64 == n {
         n as u32 as u64
     } else {
@@ -180,13 +239,13 @@ pub fn array_push(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue,
         )));
     }
     let len = n as f64;
-    obj.put(ctx, "length".intern(), JsValue::new(len), false)?;
+    obj.put(ctx, Symbol::LENGTH, JsValue::new(len), false)?;
     Ok(JsValue::new(n as f64))
 }
 
 pub fn array_pop(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, JsValue> {
     let mut obj = args.this.to_object(ctx)?;
-    let n = obj.get(ctx, "length".intern())?.to_number(ctx)?;
+    let n = obj.get(ctx, Symbol::LENGTH)?.to_number(ctx)?;
     let len = if n as u32 as f64 == n {
         n as u32
     } else {
@@ -196,13 +255,13 @@ pub fn array_pop(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, J
         )));
     };
     if len == 0 {
-        obj.put(ctx, "length".intern(), JsValue::new(0.0), true)?;
+        obj.put(ctx, Symbol::LENGTH, JsValue::new(0.0), true)?;
         return Ok(JsValue::encode_undefined_value());
     } else {
         let index = len - 1;
         let element = obj.get(ctx, Symbol::Index(index))?;
         obj.delete(ctx, Symbol::Index(index), true)?;
-        obj.put(ctx, "length".intern(), JsValue::new(index as i32), true)?;
+        obj.put(ctx, Symbol::LENGTH, JsValue::new(index as i32), true)?;
         Ok(element)
     }
 }
@@ -241,9 +300,9 @@ pub fn array_reduce(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue
     } else {
         let mut k_present = false;
         while k < len {
-            if obj.has_property(ctx, Symbol::Index(k)) {
+            if let Some(element) = JsArray::element(ctx, obj, k)? {
                 k_present = true;
-                acc = obj.get(ctx, Symbol::Index(k))?;
+                acc = element;
                 k += 1;
                 break;
             }
@@ -262,14 +321,14 @@ pub fn array_reduce(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue
     }
 
     while k < len {
-        if obj.has_property(ctx, Symbol::Index(k)) {
+        if let Some(element) = JsArray::element(ctx, obj, k)? {
             let mut tmp = [JsValue::encode_undefined_value(); 4];
             letroot!(
                 args = stack,
                 Arguments::new(JsValue::encode_undefined_value(), &mut tmp)
             );
             *args.at_mut(0) = acc;
-            *args.at_mut(1) = obj.get(ctx, Symbol::Index(k))?;
+            *args.at_mut(1) = element;
             *args.at_mut(2) = JsValue::new(k as i32);
             *args.at_mut(3) = JsValue::encode_object_value(obj);
             acc = callback.call(ctx, &mut args, JsValue::new(cb))?;
@@ -295,10 +354,11 @@ pub fn array_concat(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue
     letroot!(this = stack, args.this.get_jsobject());
     let this_length = super::get_length(ctx, &mut this)?;
 
-    let mut new_values = JsArray::new(ctx, this_length);
+    letroot!(new_values = stack, array_species_create(ctx, this, 0)?);
     for n in 0..this_length {
-        let val = this.get(ctx, Symbol::Index(n))?;
-        new_values.put(ctx, Symbol::Index(ix), val, false)?;
+        if let Some(val) = JsArray::element(ctx, this, n)? {
+            new_values.put(ctx, Symbol::Index(ix), val, false)?;
+        }
         ix += 1;
     }
 
@@ -318,11 +378,14 @@ pub fn array_concat(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue
             )));
         }
         for n in 0..len {
-            let val = arg.get(ctx, Symbol::Index(n))?;
-            new_values.put(ctx, Symbol::Index(ix), val, false)?;
+            if let Some(val) = JsArray::element(ctx, arg, n)? {
+                new_values.put(ctx, Symbol::Index(ix), val, false)?;
+            }
             ix += 1;
         }
     }
+    // trailing holes are not written
+    new_values.put(ctx, Symbol::LENGTH, JsValue::new(ix), false)?;
 
     Ok(JsValue::encode_object_value(new_values))
 }
@@ -344,8 +407,7 @@ pub fn array_for_each(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsVal
     let this_arg = args.at(1);
     let mut buf: [JsValue; 3] = [JsValue::encode_undefined_value(); 3];
     for i in 0..length {
-        if array.has_property(ctx, Symbol::Index(i)) {
-            let element = array.get(ctx, Symbol::Index(i))?;
+        if let Some(element) = JsArray::element(ctx, array, i)? {
             buf[0] = element;
             buf[1] = JsValue::new(i);
             buf[2] = JsValue::new(array);
@@ -367,22 +429,22 @@ pub fn array_filter(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue
     let callback = args.at(0);
     if !callback.is_callable() {
         return Err(JsValue::new(ctx.new_type_error(
-            "Array.prototype.forEach callback must be a function",
+            "Array.prototype.filter callback must be a function",
         )));
     }
 
     letroot!(callback = stack, callback.to_object(ctx)?);
     letroot!(cb2 = stack, callback);
-    letroot!(result = stack, JsArray::new(ctx, 0));
+    letroot!(result = stack, array_species_create(ctx, array, 0)?);
     letroot!(this_arg = stack, args.at(1));
 
     let mut next_index = 0;
     let mut buf = [JsValue::encode_undefined_value(); 3];
     for i in 0..length {
-        if !array.has_own_property(ctx, Symbol::Index(i)) {
-            continue;
-        }
-        let current = array.get(ctx, Symbol::Index(i))?;
+        let current = match JsArray::element(ctx, array, i)? {
+            Some(current) => current,
+            None => continue,
+        };
         buf[0] = current;
         buf[1] = JsValue::new(i);
         buf[2] = JsValue::new(array);
@@ -405,22 +467,22 @@ pub fn array_map(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, J
 
     let callback = args.at(0);
     if !callback.is_callable() {
-        return Err(JsValue::new(ctx.new_type_error(
-            "Array.prototype.forEach callback must be a function",
-        )));
+        return Err(JsValue::new(
+            ctx.new_type_error("Array.prototype.map callback must be a function"),
+        ));
     }
 
     letroot!(callback = stack, callback.to_object(ctx)?);
     letroot!(cb2 = stack, callback);
-    letroot!(result = stack, JsArray::new(ctx, 0));
+    // holes of `array` stay holes in the result
+    letroot!(result = stack, array_species_create(ctx, array, length)?);
     letroot!(this_arg = stack, args.at(1));
     let mut buf = [JsValue::encode_undefined_value(); 3];
     for i in 0..length {
-        if !array.has_own_property(ctx, Symbol::Index(i)) {
-            continue;
-        }
-
-        buf[0] = array.get(ctx, Symbol::Index(i))?;
+        buf[0] = match JsArray::element(ctx, array, i)? {
+            Some(element) => element,
+            None => continue,
+        };
         buf[1] = JsValue::new(i);
         buf[2] = JsValue::new(array);
         let mut args = Arguments::new(this_arg, &mut buf);
@@ -450,13 +512,10 @@ pub fn array_index_of(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsVal
     let from_index = from_index as u32;
 
     for i in from_index..length {
-        if !array.has_own_property(ctx, Symbol::Index(i)) {
-            continue;
-        }
-
-        let elem = array.get(ctx, Symbol::Index(i))?;
-        if elem == target {
-            return Ok(JsValue::new(i));
+        if let Some(elem) = JsArray::element(ctx, array, i)? {
+            if elem == target {
+                return Ok(JsValue::new(i));
+            }
         }
     }
     Ok(JsValue::new(-1))
@@ -505,41 +564,48 @@ pub fn array_slice(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue,
         return Err(JsValue::new(JsRangeError::new(ctx, msg, None)));
     }
     if result_len > (1024 << 6) {
-        letroot!(ary = stack, JsArray::new(ctx, result_len));
+        letroot!(ary = stack, array_species_create(ctx, obj, result_len)?);
 
         let mut n = 0;
         while k < fin {
-            let kval = obj.get(ctx, Symbol::Index(k))?;
-            ary.define_own_property(
-                ctx,
-                Symbol::Index(n),
-                &*DataDescriptor::new(kval, W | E | C),
-                false,
-            )?;
+            if let Some(kval) = JsArray::element(ctx, obj, k)? {
+                ary.define_own_property(
+                    ctx,
+                    Symbol::Index(n),
+                    &*DataDescriptor::new(kval, W | E | C),
+                    false,
+                )?;
+            }
             k += 1;
             n += 1;
         }
+        ary.put(ctx, Symbol::LENGTH, JsValue::new(n), true)?;
         return Ok(JsValue::new(ary));
     }
-    letroot!(ary = stack, JsArray::new(ctx, result_len));
+    letroot!(ary = stack, array_species_create(ctx, obj, result_len)?);
     let mut n = 0;
     while k < fin {
-        if obj.has_property(ctx, Symbol::Index(k)) {
-            let val = obj.get(ctx, Symbol::Index(k))?;
+        if let Some(val) = JsArray::element(ctx, obj, k)? {
             ary.put(ctx, Symbol::Index(n), val, false)?;
         }
         k += 1;
         n += 1;
     }
+    ary.put(ctx, Symbol::LENGTH, JsValue::new(n), true)?;
     return Ok(JsValue::new(ary));
 }
 
+/// `get Array[Symbol.species]`, the constructor used for arrays derived from `this`.
+pub fn array_get_species(_ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, JsValue> {
+    Ok(args.this)
+}
+
 pub fn array_shift(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, JsValue> {
     let mut obj = args.this.to_object(ctx)?;
 
     let length = super::get_length(ctx, &mut obj)?;
     if length == 0 {
-        obj.put(ctx, "length".intern(), JsValue::new(0), false)?;
+        obj.put(ctx, Symbol::LENGTH, JsValue::new(0), false)?;
         return Ok(JsValue::encode_undefined_value());
     }
 
@@ -547,22 +613,17 @@ pub fn array_shift(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue,
     for k in 1..length {
         let from = k;
         let to = k.wrapping_sub(1);
-        let from_value = obj.get(ctx, Symbol::Index(from as u32))?;
-        if from_value.is_undefined() {
-            obj.delete(ctx, Symbol::Index(to as _), false)?;
-        } else {
-            obj.put(ctx, Symbol::Index(to as _), from_value, false)?;
+        match JsArray::element(ctx, obj, from as u32)? {
+            Some(from_value) => obj.put(ctx, Symbol::Index(to as _), from_value, false)?,
+            None => {
+                obj.delete(ctx, Symbol::Index(to as _), false)?;
+            }
         }
     }
 
     let final_index = length.wrapping_sub(1);
     obj.delete(ctx, Symbol::Index(final_index as _), false)?;
-    obj.put(
-        ctx,
-        "length".intern(),
-        JsValue::new(final_index as u32),
-        false,
-    )?;
+    obj.put(ctx, Symbol::LENGTH, JsValue::new(final_index as u32), false)?;
     Ok(first)
 }
 
@@ -586,6 +647,7 @@ impl Builtin for JsArray {
             array::array_shift as _,
             array::array_slice as _,
             array::array_index_of as _,
+            array::array_get_species as _,
         ]
     }
 
@@ -599,12 +661,21 @@ impl Builtin for JsArray {
             .array_structure
             .unwrap()
             .change_prototype_with_no_transition(prototype);
-        let mut constructor = JsNativeFunction::new(ctx, S_CONSTURCTOR.intern(), array_ctor, 1);
+        let mut constructor = JsNativeFunction::new(ctx, Symbol::CONSTRUCTOR, array_ctor, 1);
 
         def_native_property!(ctx, constructor, prototype, prototype, NONE)?;
         def_native_method!(ctx, constructor, isArray, array_is_array, 1)?;
         def_native_method!(ctx, constructor, of, array_of, 1)?;
         def_native_method!(ctx, constructor, from, array_from, 1)?;
+        let species =
+            JsNativeFunction::new(ctx, "get [Symbol.species]".intern(), array_get_species, 0);
+        def_native_getter!(
+            ctx,
+            constructor,
+            "Symbol.species".intern().private(),
+            species,
+            C
+        )?;
         def_native_property!(ctx, prototype, constructor, constructor, W | C)?;
         def_native_method!(ctx, prototype, join, array_join, 1, W | C | E)?;
         def_native_method!(ctx, prototype, toString, array_to_string, 1, W | C | E)?;
@@ -619,6 +690,7 @@ impl Builtin for JsArray {
         def_native_method!(ctx, prototype, shift, array::array_shift, 0)?;
         def_native_method!(ctx, prototype, indexOf, array_index_of, 1, W | C | E)?;
         ctx.global_data.array_prototype = Some(prototype);
+        ctx.global_data.array_constructor = Some(constructor);
 
         let mut global_object = ctx.global_object();
 
@@ -627,3 +699,87 @@ impl Builtin for JsArray {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::vm::tests::new_context;
+
+    #[test]
+    fn test_array_join() {
+        let (_vm, mut ctx) = new_context();
+
+        let result = ctx
+            .eval(
+                "var a = [-12, 1.5, true, null, 'x', undefined, -0, 2147483647];
+                 var holes = [1, 2, 3]; delete holes[1]; Array.prototype[1] = 'p';
+                 var objects = [1, { toString: function () { objects.length = 0; return 'o'; } }, 3];
+                 [a.join(), a.join(' '), holes.join('-'), objects.join('-')].join('|')",
+            )
+            .ok()
+            .unwrap();
+        assert_eq!(
+            result.get_jsstring().as_str(),
+            "-12,1.5,true,,x,,0,2147483647|-12 1.5 true  x  0 2147483647|1-p-3|1-o-"
+        );
+
+        let result = ctx
+            .eval("var big = []; for (var i = 0; i < 1000000; i++) big.push(i % 10); big.join('').length")
+            .ok()
+            .unwrap();
+        assert_eq!(result.get_number(), 1000000.0);
+    }
+
+    #[test]
+    fn test_sparse_array_builtins() {
+        let (_vm, mut ctx) = new_context();
+
+        let result = ctx
+            .eval(
+                "var a = [1, , 3]; a[6] = 7;
+                 var seen = []; a.forEach(function (v, i) { seen.push(i); });
+                 var m = a.map(function (v) { return v * 2; });
+                 var sparse = []; sparse[100000] = 1;
+                 var s = [1, , 2]; s.shift();
+                 [seen.join(' '), m.length, 1 in m, m[6], a.filter(function () { return true; }).length,
+                  a.reduce(function (x, y) { return x + y; }), a.indexOf(undefined), a.concat([, 2]).length,
+                  1 in a.concat([]), a.slice(1, 3).length, 0 in a.slice(1, 3),
+                  sparse.map(function (v) { return v + 1; })[100000], 0 in s].join()",
+            )
+            .ok()
+            .unwrap();
+        assert_eq!(
+            result.get_jsstring().as_str(),
+            "0 2 6,7,false,14,3,11,-1,9,false,2,false,2,false"
+        );
+    }
+
+    #[test]
+    fn test_species() {
+        let (_vm, mut ctx) = new_context();
+
+        let result = ctx
+            .eval(
+                "function MyArray(length) { var a = new Array(length); a.tag = 'array'; return a; }
+                 function MyPromise(executor) { var p = new Promise(executor); p.tag = 'promise'; return p; }
+                 var a = [1, 2, 3];
+                 a.constructor = {};
+                 a.constructor[Symbol.species] = MyArray;
+                 var p = Promise.resolve(1);
+                 p.constructor = {};
+                 p.constructor[Symbol.species] = MyPromise;
+                 var id = function (v) { return v; };
+                 [a.map(id).tag, a.filter(id).tag, a.slice(1).tag, a.concat([4]).tag, a.map(id).join(' '),
+                  [1].map(id).tag, Array[Symbol.species] === Array, p.then(id).tag, p.catch(id).tag,
+                  Promise.all.call(MyPromise, [p]).tag, Promise[Symbol.species] === Promise].join()",
+            )
+            .ok()
+            .unwrap();
+        assert_eq!(
+            result.get_jsstring().as_str(),
+            "array,array,array,array,1 2 3,,true,promise,promise,promise,true"
+        );
+        assert!(ctx
+            .eval("var b = []; b.constructor = { [Symbol.species]: 1 }; b.map(function () {});")
+            .is_err());
+    }
+}
@@ -127,6 +127,7 @@ impl Builtin for JsArrayBuffer {
             data: std::ptr::null_mut(),
 
             attached: false,
+            deallocator: None,
         });
 
         ctx.global_data.array_buffer_prototype = Some(prototype);
@@ -138,6 +139,13 @@ impl Builtin for JsArrayBuffer {
         def_native_property!(ctx, constructor, prototype, prototype)?;
         def_native_property!(ctx, prototype, constructor, constructor)?;
         def_native_method!(ctx, prototype, slice, array_buffer_slice, 2)?;
+        def_native_property!(
+            ctx,
+            prototype,
+            "Symbol.toStringTag".intern().private(),
+            JsString::new(ctx, "ArrayBuffer"),
+            C
+        )?;
 
         ctx.global_object().put(
             ctx,
@@ -0,0 +1,223 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+//! CommonJS modules: global `require` enabled by [Options::commonjs](crate::options::Options::commonjs).
+//!
+//! `require` resolves specifiers with
+//! [ModuleLoader::resolve_require](crate::vm::module_loader::ModuleLoader::resolve_require) relative to the
+//! directory of the calling code, so it works from scripts, ES modules and CommonJS modules alike. Names of native modules such as `fs` return their exports. Other modules run once in a function
+//! with `exports`, `require`, `module`, `__filename` and `__dirname` parameters and `require` returns
+//! `module.exports`; `.json` files are parsed instead.
+//!
+//! Modules are cached in `require.cache` by key before they run. A circular `require` gets the `exports` of
+//! the module as far as it has been initialized, as in Node.js. Deleting an entry from the cache makes the
+//! next `require` load the module again.
+use super::load_registered_module;
+use crate::{
+    bytecompiler::{ByteCompiler, CompileError},
+    prelude::*,
+    vm::context::Context,
+};
+use std::path::Path;
+
+pub fn init_commonjs(mut ctx: GcPointer<Context>) -> Result<(), JsValue> {
+    let mut require = JsNativeFunction::new(ctx, "require".intern(), commonjs_require, 1);
+    def_native_method!(ctx, require, resolve, commonjs_require_resolve, 1)?;
+    let cache = JsObject::new_empty(ctx);
+    require.put(ctx, "cache".intern(), JsValue::new(cache), false)?;
+    let mut global_object = ctx.global_object();
+    global_object.put(
+        ctx,
+        "@@requireCache".intern().private(),
+        JsValue::new(cache),
+        false,
+    )?;
+    global_object.put(ctx, "require".intern(), JsValue::new(require), false)?;
+    Ok(())
+}
+
+/// Directory of the code calling `require`.
+fn referrer(ctx: GcPointer<Context>) -> String {
+    unsafe {
+        (*ctx.stack.current)
+            .code_block
+            .map(|code| code.path.clone())
+            .unwrap_or_default()
+    }
+}
+
+fn resolve(ctx: GcPointer<Context>, specifier: &str) -> Result<String, JsValue> {
+    ctx.vm()
+        .loader
+        .resolve_require(specifier, &referrer(ctx))
+        .map_err(|e| {
+            JsValue::new(
+                ctx.new_reference_error(format!("Cannot find module '{}': {}", specifier, e)),
+            )
+        })
+}
+
+/// `require.resolve(specifier)` returns the key `require` would load `specifier` from.
+pub fn commonjs_require_resolve(
+    ctx: GcPointer<Context>,
+    args: &Arguments,
+) -> Result<JsValue, JsValue> {
+    let specifier = args.at(0).to_string(ctx)?;
+    let key = resolve(ctx, &specifier)?;
+    Ok(JsValue::new(JsString::new(ctx, key)))
+}
+
+pub fn commonjs_require(mut ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, JsValue> {
+    let specifier = args.at(0).to_string(ctx)?;
+    if ctx.modules().contains_key(&specifier) {
+        let mut module = load_registered_module(ctx, &specifier)?.get_jsobject();
+        return module.get(ctx, S_EXPORTS.intern());
+    }
+    let key = resolve(ctx, &specifier)?;
+    let mut cache = ctx
+        .global_object()
+        .get(ctx, "@@requireCache".intern().private())?
+        .to_object(ctx)?;
+    let cached = cache.get(ctx, key.as_str().intern())?;
+    if cached.is_jsobject() {
+        return cached.get_jsobject().get(ctx, "exports".intern());
+    }
+
+    let loader = ctx.vm().loader.clone();
+    let source = loader
+        .load(&key)
+        .and_then(|source| loader.transform(&key, source))
+        .map_err(|e| {
+            JsValue::new(ctx.new_type_error(format!("Failed to load module '{}': {}", key, e)))
+        })?;
+    let mut module = JsObject::new_empty(ctx);
+    let exports = JsObject::new_empty(ctx);
+    let filename = JsValue::new(JsString::new(ctx, &key));
+    module.put(ctx, "exports".intern(), JsValue::new(exports), false)?;
+    module.put(ctx, "id".intern(), filename, false)?;
+    module.put(ctx, "filename".intern(), filename, false)?;
+    module.put(ctx, "loaded".intern(), JsValue::new(false), false)?;
+    cache.put(ctx, key.as_str().intern(), JsValue::new(module), false)?;
+
+    if let Err(e) = run_module(ctx, &key, &source, module, exports) {
+        cache.delete(ctx, key.as_str().intern(), false)?;
+        return Err(e);
+    }
+    module.put(ctx, "loaded".intern(), JsValue::new(true), false)?;
+    module.get(ctx, "exports".intern())
+}
+
+fn run_module(
+    ctx: GcPointer<Context>,
+    key: &str,
+    source: &str,
+    mut module: GcPointer<JsObject>,
+    exports: GcPointer<JsObject>,
+) -> Result<(), JsValue> {
+    if key.ends_with(".json") {
+        let value = super::parse_json(ctx, source.as_bytes(), key)?;
+        return module.put(ctx, "exports".intern(), value, false);
+    }
+    let dirname = Path::new(key)
+        .parent()
+        .map(|dir| dir.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let params = ["exports", "require", "module", "__filename", "__dirname"]
+        .iter()
+        .map(|param| param.to_string())
+        .collect::<Vec<_>>();
+    let function = ByteCompiler::compile_code(ctx, &params, &dirname, source.to_owned(), false)
+        .map_err(|e| match e {
+            CompileError::Val(value) => value,
+            e => JsValue::new(ctx.new_syntax_error(format!("{} in '{}'", e, key))),
+        })?;
+    let mut function = function.get_jsobject();
+    let code = &mut function.as_function_mut().as_vm_mut().code;
+    code.file_name = key.to_owned();
+    code.name = Path::new(key)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+        .intern();
+
+    let require = ctx.global_object().get(ctx, "require".intern())?;
+    let mut args = [
+        JsValue::new(exports),
+        require,
+        JsValue::new(module),
+        JsValue::new(JsString::new(ctx, key)),
+        JsValue::new(JsString::new(ctx, dirname)),
+    ];
+    let mut args = Arguments::new(JsValue::new(exports), &mut args);
+    function
+        .as_function_mut()
+        .call(ctx, &mut args, JsValue::new(function))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{options::Options, prelude::*, vm::tests::new_context_with};
+    use std::fs;
+
+    #[test]
+    fn test_require() {
+        let root = std::env::temp_dir().join(format!("starlight-cjs-{}", std::process::id()));
+        fs::create_dir_all(root.join("node_modules/dep")).unwrap();
+        fs::write(
+            root.join("a.js"),
+            "exports.early = 'a';
+             const b = require('./b');
+             exports.fromB = b.sawA;
+             exports.done = true;",
+        )
+        .unwrap();
+        fs::write(
+            root.join("b.js"),
+            "const a = require('./a.js');
+             module.exports = { sawA: a.early + ':' + a.done };",
+        )
+        .unwrap();
+        fs::write(root.join("data.json"), r#"{ "list": [1, 2, 3] }"#).unwrap();
+        fs::write(
+            root.join("node_modules/dep/package.json"),
+            r#"{ "exports": { "import": "./esm.js", "require": "./cjs.js" } }"#,
+        )
+        .unwrap();
+        fs::write(
+            root.join("node_modules/dep/cjs.js"),
+            "module.exports = function () { return __filename.endsWith('cjs.js'); };",
+        )
+        .unwrap();
+        let main = root.join("main.js");
+        fs::write(
+            &main,
+            "const a = require('./a');
+             globalThis.result = [
+                 a.fromB,
+                 require('./a') === a,
+                 require('./data').list.length,
+                 require('dep')(),
+                 typeof require('path').join,
+             ].join(',');",
+        )
+        .unwrap();
+
+        let options = Options::default()
+            .with_commonjs(true)
+            .with_host_modules(true);
+        let (_vm, mut ctx) = new_context_with(options);
+        let source = fs::read_to_string(&main).unwrap();
+        ctx.evalm(Some(main.to_str().unwrap()), false, &source)
+            .unwrap_or_else(|_| panic!());
+        let result = ctx
+            .global_object()
+            .get(ctx, "result".intern())
+            .unwrap_or_else(|_| panic!());
+        assert_eq!(
+            result.to_string(ctx).unwrap_or_else(|_| panic!()),
+            "a:undefined,true,3,true,function"
+        );
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
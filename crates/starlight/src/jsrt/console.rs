@@ -0,0 +1,70 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+use std::io::Write;
+
+use crate::{
+    prelude::*,
+    vm::{builder::Builtin, context::Context, debugger, inspect::InspectOptions},
+};
+
+pub struct JsConsole;
+
+/// Join `args` with spaces. Strings are printed as is, other values through [Context::inspect].
+fn format_args(ctx: GcPointer<Context>, args: &Arguments) -> String {
+    let options = InspectOptions::default();
+    let mut result = String::new();
+    for i in 0..args.size() {
+        if i != 0 {
+            result.push(' ');
+        }
+        let value = args.at(i);
+        if value.is_jsstring() {
+            result.push_str(value.get_string().as_str());
+        } else {
+            result.push_str(&ctx.inspect(value, &options));
+        }
+    }
+    result
+}
+
+/// Forward console call to the attached debugger, see
+/// [DebuggerDelegate::console_message](crate::vm::debugger::DebuggerDelegate::console_message).
+fn notify_debugger(ctx: GcPointer<Context>, level: &str, args: &Arguments) {
+    if ctx.vm().debugger().is_some() {
+        let args = (0..args.size()).map(|i| args.at(i)).collect::<Vec<_>>();
+        debugger::console_message(ctx, level, &args);
+    }
+}
+
+pub fn console_log(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, JsValue> {
+    notify_debugger(ctx, "log", args);
+    let _ = writeln!(std::io::stdout(), "{}", format_args(ctx, args));
+    Ok(JsValue::encode_undefined_value())
+}
+
+pub fn console_error(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, JsValue> {
+    notify_debugger(ctx, "error", args);
+    let _ = writeln!(std::io::stderr(), "{}", format_args(ctx, args));
+    Ok(JsValue::encode_undefined_value())
+}
+
+impl Builtin for JsConsole {
+    fn native_references() -> Vec<usize> {
+        vec![console_log as _, console_error as _]
+    }
+
+    fn init(mut ctx: GcPointer<Context>) -> Result<(), JsValue> {
+        let mut console = JsObject::new_empty(ctx);
+
+        def_native_method!(ctx, console, log, console_log, 0)?;
+        def_native_method!(ctx, console, info, console_log, 0)?;
+        def_native_method!(ctx, console, debug, console_log, 0)?;
+        def_native_method!(ctx, console, warn, console_error, 0)?;
+        def_native_method!(ctx, console, error, console_error, 0)?;
+
+        let mut global_object = ctx.global_object();
+        def_native_property!(ctx, global_object, console, console, W | C)?;
+        Ok(())
+    }
+}
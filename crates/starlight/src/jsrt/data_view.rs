@@ -277,6 +277,13 @@ impl Builtin for JsDataView {
 
         let buffer = JsNativeFunction::new(ctx, "buffer".intern(), data_view_prototype_buffer, 0);
         def_native_getter!(ctx, prototype, buffer, buffer, NONE)?;
+        def_native_property!(
+            ctx,
+            prototype,
+            "Symbol.toStringTag".intern().private(),
+            JsString::new(ctx, "DataView"),
+            C
+        )?;
 
         ctx.global_data.data_view_prototype = Some(prototype);
 
@@ -0,0 +1,387 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+//! Global `fetch(url, options)` backed by a [HttpTransport] installed by the embedder.
+//!
+//! Starlight does not open connections itself: every request goes through the transport set with
+//! [VirtualMachine::with_http_transport](crate::vm::VirtualMachine::with_http_transport), so the embedder
+//! decides which hosts are reachable, how TLS and proxies work and whether the network is available at all.
+//! Without a transport `fetch` rejects with `TypeError`.
+//!
+//! When the runtime has an async scheduler the request runs in a scheduled job, otherwise `fetch` calls the
+//! transport immediately and returns a settled promise.
+//!
+//! ```js
+//! fetch("https://example.com/data.json", { method: "POST", headers: { "x-token": "1" }, body: "{}" })
+//!     .then(response => response.json())
+//!     .then(data => print(data.name));
+//! ```
+use crate::{
+    define_jsclass,
+    prelude::*,
+    vm::{
+        array_buffer::JsArrayBuffer, builder::Builtin, context::Context, object::TypedJsObject,
+        promise::JsPromise,
+    },
+    JsTryFrom,
+};
+use std::mem::{size_of, ManuallyDrop};
+
+/// Request passed to [HttpTransport::send].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HttpRequest {
+    pub method: String,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Option<Vec<u8>>,
+}
+
+/// Response returned by [HttpTransport::send]. Header names are matched case-insensitively.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub status_text: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+/// Performs HTTP requests for `fetch`.
+pub trait HttpTransport {
+    /// Send `request` and wait for the whole response. `Err` rejects the promise returned by `fetch` with
+    /// `TypeError` carrying the message, HTTP error statuses are successful responses.
+    fn send(&self, request: &HttpRequest) -> Result<HttpResponse, String>;
+}
+
+pub struct JsFetch;
+
+pub struct ResponseObject {
+    response: HttpResponse,
+    body_used: bool,
+}
+
+extern "C" fn drop_response_fn(obj: GcPointer<JsObject>) {
+    unsafe { ManuallyDrop::drop(obj.data::<ResponseObject>()) }
+}
+
+extern "C" fn response_size() -> usize {
+    size_of::<ResponseObject>()
+}
+
+impl JsClass for ResponseObject {
+    fn class() -> &'static Class {
+        define_jsclass!(
+            ResponseObject,
+            Response,
+            Some(drop_response_fn),
+            None,
+            Some(response_size)
+        )
+    }
+}
+
+impl Builtin for JsFetch {
+    fn native_references() -> Vec<usize> {
+        vec![
+            fetch as _,
+            response_text as _,
+            response_json as _,
+            response_array_buffer as _,
+            ResponseObject::class() as *const _ as usize,
+        ]
+    }
+
+    fn init(mut ctx: GcPointer<Context>) -> Result<(), JsValue> {
+        let mut proto = JsObject::new_empty(ctx);
+        def_native_method!(ctx, proto, text, response_text, 0)?;
+        def_native_method!(ctx, proto, json, response_json, 0)?;
+        def_native_method!(ctx, proto, arrayBuffer, response_array_buffer, 0)?;
+        let mut global_object = ctx.global_object();
+        global_object.put(
+            ctx,
+            "@@Response".intern().private(),
+            JsValue::new(proto),
+            false,
+        )?;
+        def_native_method!(ctx, global_object, fetch, fetch, 2)?;
+        Ok(())
+    }
+}
+
+fn settle(
+    ctx: GcPointer<Context>,
+    promise: JsValue,
+    result: Result<JsValue, JsValue>,
+) -> Result<(), JsValue> {
+    let mut object = promise.get_jsobject();
+    match result {
+        Ok(value) => object.as_promise_mut().resolve(ctx, promise, value),
+        Err(error) => object.as_promise_mut().reject(ctx, promise, error),
+    }
+}
+
+fn settled(ctx: GcPointer<Context>, result: Result<JsValue, JsValue>) -> Result<JsValue, JsValue> {
+    let promise = JsPromise::new_unresolving(ctx)?;
+    settle(ctx, promise, result)?;
+    Ok(promise)
+}
+
+fn body_bytes(ctx: GcPointer<Context>, body: JsValue) -> Result<Option<Vec<u8>>, JsValue> {
+    if body.is_undefined() || body.is_null() {
+        return Ok(None);
+    }
+    if body.is_jsobject() && body.get_jsobject().is_class(JsArrayBuffer::class()) {
+        let buffer = TypedJsObject::<JsArrayBuffer>::new(body.get_jsobject());
+        return Ok(Some(buffer.data().to_vec()));
+    }
+    Ok(Some(body.to_string(ctx)?.into_bytes()))
+}
+
+/// Read `url` and the `method`, `headers` and `body` options of `fetch`.
+fn new_request(ctx: GcPointer<Context>, args: &Arguments) -> Result<HttpRequest, JsValue> {
+    let mut request = HttpRequest {
+        method: "GET".to_owned(),
+        url: args.at(0).to_string(ctx)?,
+        headers: vec![],
+        body: None,
+    };
+    if !args.at(1).is_jsobject() {
+        return Ok(request);
+    }
+    let mut options = args.at(1).get_jsobject();
+    let method = options.get(ctx, "method".intern())?;
+    if !method.is_undefined() {
+        request.method = method.to_string(ctx)?.to_uppercase();
+    }
+    let headers = options.get(ctx, "headers".intern())?;
+    if headers.is_jsobject() {
+        let mut headers = headers.get_jsobject();
+        let mut names = vec![];
+        headers.get_own_property_names(
+            ctx,
+            &mut |name, _| names.push(name),
+            EnumerationMode::Default,
+        );
+        for name in names {
+            let value = headers.get(ctx, name)?.to_string(ctx)?;
+            request.headers.push((ctx.description(name), value));
+        }
+    }
+    request.body = body_bytes(ctx, options.get(ctx, "body".intern())?)?;
+    if request.body.is_some() && (request.method == "GET" || request.method == "HEAD") {
+        return Err(JsValue::new(ctx.new_type_error(format!(
+            "fetch: {} request cannot have body",
+            request.method
+        ))));
+    }
+    Ok(request)
+}
+
+fn send(ctx: GcPointer<Context>, request: &HttpRequest) -> Result<JsValue, JsValue> {
+    let transport = match ctx.vm().http_transport.clone() {
+        Some(transport) => transport,
+        None => {
+            return Err(JsValue::new(
+                ctx.new_type_error("fetch: no HTTP transport is installed"),
+            ))
+        }
+    };
+    let response = transport.send(request).map_err(|e| {
+        JsValue::new(ctx.new_type_error(format!("fetch: '{}': {}", request.url, e)))
+    })?;
+    new_response(ctx, &request.url, response)
+}
+
+fn new_response(
+    ctx: GcPointer<Context>,
+    url: &str,
+    response: HttpResponse,
+) -> Result<JsValue, JsValue> {
+    let proto = ctx
+        .global_object()
+        .get(ctx, "@@Response".intern().private())?
+        .to_object(ctx)?;
+    let structure = Structure::new_indexed(ctx, Some(proto), false);
+    let mut obj = JsObject::new(
+        ctx,
+        &structure,
+        ResponseObject::class(),
+        ObjectTag::Ordinary,
+    );
+    let status = response.status;
+    let status_text = JsString::new(ctx, &response.status_text);
+    let mut headers = JsObject::new_empty(ctx);
+    for (name, value) in response.headers.iter() {
+        let name = name.to_lowercase().intern();
+        let previous = headers.get(ctx, name)?;
+        let value = if previous.is_undefined() {
+            value.clone()
+        } else {
+            format!("{}, {}", previous.to_string(ctx)?, value)
+        };
+        headers.put(ctx, name, JsValue::new(JsString::new(ctx, value)), false)?;
+    }
+    *obj.data::<ResponseObject>() = ManuallyDrop::new(ResponseObject {
+        response,
+        body_used: false,
+    });
+    obj.put(ctx, "status".intern(), JsValue::new(status as i32), false)?;
+    obj.put(ctx, "statusText".intern(), JsValue::new(status_text), false)?;
+    obj.put(
+        ctx,
+        "ok".intern(),
+        JsValue::new((200..300).contains(&status)),
+        false,
+    )?;
+    obj.put(
+        ctx,
+        "url".intern(),
+        JsValue::new(JsString::new(ctx, url)),
+        false,
+    )?;
+    obj.put(ctx, "headers".intern(), JsValue::new(headers), false)?;
+    Ok(JsValue::new(obj))
+}
+
+/// `fetch(url, options?)` returns a promise of a `Response` with `status`, `statusText`, `ok`, `url` and
+/// `headers` properties. Header names are lowercased and repeated headers are joined with `, `.
+pub fn fetch(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, JsValue> {
+    let request = match new_request(ctx, args) {
+        Ok(request) => request,
+        Err(error) => return settled(ctx, Err(error)),
+    };
+    if ctx.vm().sched_async_func.is_none() {
+        return settled(ctx, send(ctx, &request));
+    }
+    let promise = JsPromise::new_unresolving(ctx)?;
+    let root = ctx.vm().add_persistent_root(promise);
+    ctx.schedule_async(move |ctx| {
+        let result = send(ctx, &request);
+        let _ = settle(ctx, root.get_value(), result);
+    })?;
+    Ok(promise)
+}
+
+/// Take the body of `Response` in `this`, a body can be read only once.
+fn take_body(ctx: GcPointer<Context>, args: &Arguments, method: &str) -> Result<Vec<u8>, JsValue> {
+    let mut response = TypedJsObject::<ResponseObject>::try_from(ctx, args.this)?;
+    if response.body_used {
+        return Err(JsValue::new(ctx.new_type_error(format!(
+            "Response.prototype.{}: body has already been read",
+            method
+        ))));
+    }
+    response.body_used = true;
+    Ok(std::mem::take(&mut response.response.body))
+}
+
+pub fn response_text(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, JsValue> {
+    let result = take_body(ctx, args, "text").map(|body| {
+        let text = String::from_utf8_lossy(&body).into_owned();
+        JsValue::new(JsString::new(ctx, text))
+    });
+    settled(ctx, result)
+}
+
+pub fn response_json(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, JsValue> {
+    let result = take_body(ctx, args, "json")
+        .and_then(|body| super::parse_json(ctx, &body, "Response.prototype.json"));
+    settled(ctx, result)
+}
+
+pub fn response_array_buffer(
+    ctx: GcPointer<Context>,
+    args: &Arguments,
+) -> Result<JsValue, JsValue> {
+    let result = take_body(ctx, args, "arrayBuffer")
+        .and_then(|body| Ok(JsValue::new(JsArrayBuffer::from_vec(ctx, body)?)));
+    settled(ctx, result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{options::Options, Platform};
+    use std::{cell::RefCell, rc::Rc};
+
+    struct Recorder {
+        requests: Rc<RefCell<Vec<HttpRequest>>>,
+    }
+
+    impl HttpTransport for Recorder {
+        fn send(&self, request: &HttpRequest) -> Result<HttpResponse, String> {
+            self.requests.borrow_mut().push(request.clone());
+            if request.url.starts_with("http://offline") {
+                return Err("connection refused".to_owned());
+            }
+            Ok(HttpResponse {
+                status: 201,
+                status_text: "Created".to_owned(),
+                headers: vec![
+                    ("Content-Type".to_owned(), "application/json".to_owned()),
+                    ("Set-Cookie".to_owned(), "a=1".to_owned()),
+                    ("set-cookie".to_owned(), "b=2".to_owned()),
+                ],
+                body: br#"{"name": "starlight", "list": [1, true, null]}"#.to_vec(),
+            })
+        }
+    }
+
+    #[test]
+    fn test_fetch() {
+        Platform::initialize();
+        let jobs = Rc::new(RefCell::new(vec![]));
+        let queue = jobs.clone();
+        let requests = Rc::new(RefCell::new(vec![]));
+        let mut vm = Platform::new_runtime(Options::default(), None)
+            .with_async_scheduler(Box::new(move |job| queue.borrow_mut().push(job)))
+            .with_http_transport(Recorder {
+                requests: requests.clone(),
+            });
+        let mut ctx = vm.new_context();
+        let result = ctx
+            .eval_internal(
+                None,
+                false,
+                r#"
+                var log = [];
+                fetch("http://example.com/items", { method: "post", headers: { "X-Token": "1" }, body: "{}" })
+                    .then(response => {
+                        log.push(response.status, response.ok, response.headers["set-cookie"]);
+                        return response.json();
+                    })
+                    .then(data => log.push(data.name, data.list.length));
+                fetch("http://offline").catch(error => log.push(error instanceof TypeError));
+                log
+                "#,
+                false,
+            )
+            .unwrap_or_else(|_| panic!());
+        loop {
+            let job = {
+                let mut jobs = jobs.borrow_mut();
+                if jobs.is_empty() {
+                    break;
+                }
+                jobs.remove(0)
+            };
+            job(ctx);
+        }
+        let mut log = result.get_jsobject();
+        let log = (0..6)
+            .map(|i| {
+                let value = log.get(ctx, Symbol::Index(i)).unwrap_or_else(|_| panic!());
+                value.to_string(ctx).unwrap_or_else(|_| panic!())
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(log, ["201", "true", "a=1, b=2", "true", "starlight", "3"]);
+
+        let requests = requests.borrow();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].method, "POST");
+        assert_eq!(
+            requests[0].headers,
+            [("X-Token".to_owned(), "1".to_owned())]
+        );
+        assert_eq!(requests[0].body.as_deref(), Some(&b"{}"[..]));
+    }
+}
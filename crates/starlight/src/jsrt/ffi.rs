@@ -1,28 +1,45 @@
 /* This Source Code Form is subject to the terms of the Mozilla Public
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
-#![allow(dead_code)]
+//! `ffi` module: load shared libraries and call C functions from JS.
+//!
+//! ```js
+//! import ffi from "ffi";
+//! let libc = ffi.open(["libc.so.6", "libc.dylib"]);
+//! let strlen = libc.func("strlen", "usize", ["string"]);
+//! strlen("hello"); // 5
+//! ```
+//!
+//! Types are named by strings: `void`, `bool`, `i8`..`i64`, `u8`..`u64`, `isize`, `usize`, `f32`, `f64`,
+//! `pointer`, `string` and `buffer`. Ownership rules:
+//! - `string` arguments are copied into a temporary NUL terminated string that lives until the call returns.
+//!   `string` return values are copied into a JS string and never freed.
+//! - `buffer` arguments pass the data pointer of an `ArrayBuffer` without copying, so C may write into it.
+//!   The buffer must not be kept by C after the call returns.
+//! - `pointer` accepts a number, `null`, an `ArrayBuffer` or a callback and is returned as a number.
+//! - Functions keep their library loaded until they are collected, even after `Library.prototype.close`.
+//! - Callbacks created by `ffi.callback` stay valid until `close()` is called or the callback object is
+//!   collected. C code must not call them after that.
+//! - An exception thrown by a callback is rethrown when the foreign call that invoked it returns. C sees a
+//!   zero return value.
 use crate::{
-    define_jsclass_with_symbol,
-    gc::{
-        cell::GcPointer,
-        snapshot::{deserializer::Deserializer, serializer::SnapshotSerializer},
-    },
+    define_jsclass,
     prelude::*,
+    vm::{array_buffer::JsArrayBuffer, context::Context, object::TypedJsObject, PersistentRooted},
+    JsTryFrom,
 };
 use libffi::low::{
-    call as ffi_call, ffi_abi_FFI_DEFAULT_ABI as ABI, ffi_cif, ffi_type, prep_cif, types, CodePtr,
-    Error as FFIError,
+    call as ffi_call, closure_alloc, closure_free, ffi_abi_FFI_DEFAULT_ABI as ABI, ffi_cif,
+    ffi_closure, ffi_type, prep_cif, prep_closure, types, CodePtr, Error as FFIError,
 };
-use std::ffi::{CStr, OsStr};
-use std::fmt::{Debug, Display};
-use std::mem;
-use std::mem::ManuallyDrop;
-use std::os::raw::{
-    c_char, c_double, c_float, c_int, c_long, c_short, c_uchar, c_uint, c_ulong, c_ushort, c_void,
+use std::{
+    cell::RefCell,
+    ffi::{CStr, CString},
+    mem::{size_of, ManuallyDrop},
+    os::raw::{c_char, c_void},
+    ptr::null_mut,
+    rc::Rc,
 };
-use std::ptr;
-use std::{convert::Into, mem::size_of};
 
 /// A pointer to an FFI type.
 pub type TypePointer = *mut ffi_type;
@@ -30,321 +47,244 @@ pub type TypePointer = *mut ffi_type;
 /// A raw C pointer.
 pub type RawPointer = *mut c_void;
 
-pub fn initialize_ffi(ctx: GcPointer<Context>) {
-    vm.heap().defer();
-    let structure =
-        Structure::new_indexed(vm, Some(vm.global_data.object_prototype.unwrap()), false);
-    let mut init = || -> Result<(), JsValue> {
-        let mut proto = JsObject::new(vm, &structure, JsObject::get_class(), ObjectTag::Ordinary);
-        let func = JsNativeFunction::new(vm, "open".intern(), ffi_library_open, 1);
-        proto.define_own_property(
-            vm,
-            "open".intern(),
-            &*DataDescriptor::new(JsValue::new(func), NONE),
-            false,
-        )?;
-        vm.global_object().define_own_property(
-            vm,
-            "FFI".intern(),
-            &*DataDescriptor::new(JsValue::new(proto), E),
-            false,
-        )?;
-        let func_s =
-            Structure::new_indexed(vm, Some(vm.global_data.object_prototype.unwrap()), false);
-        let mut fproto = JsObject::new(vm, &func_s, JsObject::get_class(), ObjectTag::Ordinary);
-        let func = JsNativeFunction::new(vm, "attach".intern(), ffi_function_attach, 1);
-        fproto.define_own_property(
-            vm,
-            "attach".intern(),
-            &*DataDescriptor::new(JsValue::new(func), E),
-            false,
-        )?;
-        let func = JsNativeFunction::new(vm, "call".intern(), ffi_function_call, 1);
-        fproto.define_own_property(
-            vm,
-            "call".intern(),
-            &*DataDescriptor::new(JsValue::new(func), E),
-            false,
-        )?;
-
-        vm.global_object().define_own_property(
-            vm,
-            "CFunction".intern(),
-            &*DataDescriptor::new(JsValue::new(fproto), E),
-            false,
-        )?;
-
-        vm.eval_internal(None, false, include_str!("../builtins/FFI.js"), true)?;
-        Ok(())
-    };
-
-    match init() {
-        Ok(_) => (),
-        Err(_) => {
-            unreachable!()
-        }
-    }
-    vm.heap().undefer();
-}
-/// A wrapper around a C pointer.
-#[derive(Clone, Copy)]
-pub struct Pointer {
-    inner: RawPointer,
+thread_local! {
+    /// Exception thrown by a callback, rethrown once the foreign call returns.
+    static PENDING_EXCEPTION: RefCell<Option<PersistentRooted>> = RefCell::new(None);
 }
 
-unsafe impl Send for Pointer {}
-
-/// The numeric identifier of the C `void` type.
-const TYPE_VOID: i64 = 0;
-
-/// The numeric identifier of the C `void*` type.
-const TYPE_POINTER: i64 = 1;
-
-/// The numeric identifier of the C `double` type.
-const TYPE_DOUBLE: i64 = 2;
-
-/// The numeric identifier of the C `float` type.
-const TYPE_FLOAT: i64 = 3;
-
-/// The numeric identifier of the C `signed char` type.
-const TYPE_I8: i64 = 4;
-
-/// The numeric identifier of the C `short` type.
-const TYPE_I16: i64 = 5;
-
-/// The numeric identifier of the C `int` type.
-const TYPE_I32: i64 = 6;
-
-/// The numeric identifier of the C `long` type.
-const TYPE_I64: i64 = 7;
-
-/// The numeric identifier of the C `unsigned char` type.
-const TYPE_U8: i64 = 8;
-
-/// The numeric identifier of the C `unsigned short` type.
-const TYPE_U16: i64 = 9;
-
-/// The numeric identifier of the C `unsigned int` type.
-const TYPE_U32: i64 = 10;
-
-/// The numeric identifier of the C `unsigned long` type.
-const TYPE_U64: i64 = 11;
-
-/// The numeric identifier for the C `const char*` type.
-const TYPE_STRING: i64 = 12;
-
-/// The numeric identifier for a C `const char*` type that should be read into a
-/// byte array..
-const TYPE_BYTE_ARRAY: i64 = 13;
-
-/// The numeric identifier of the C `size_t` type.
-const TYPE_SIZE_T: i64 = 14;
-pub struct FFIFunction {
-    /// The pointer to the function to call.
-    pointer: Pointer,
-
-    /// The CIF (Call Interface) to use for this function.
-    cif: ffi_cif,
-
-    /// The argument types of the function.
-    arguments: Vec<TypePointer>,
-
-    /// The return type of the function.
-    return_type: TypePointer,
+pub fn init_ffi(
+    mut ctx: GcPointer<Context>,
+    mut module: GcPointer<JsObject>,
+) -> Result<(), JsValue> {
+    let defer = ctx.heap().defer();
+    let mut ffi = JsObject::new_empty(ctx);
+    module.put(ctx, "@exports".intern(), JsValue::new(ffi), false)?;
+    module.put(ctx, "@default".intern(), JsValue::new(ffi), false)?;
+    def_native_method!(ctx, ffi, open, ffi_open, 1)?;
+    def_native_method!(ctx, ffi, callback, ffi_callback, 3)?;
+    def_native_method!(ctx, ffi, read, ffi_read, 3)?;
+    def_native_method!(ctx, ffi, write, ffi_write, 4)?;
+    def_native_method!(ctx, ffi, cstring, ffi_cstring, 1)?;
+    def_native_method!(ctx, ffi, sizeOf, ffi_size_of, 1)?;
+
+    let mut library = JsObject::new_empty(ctx);
+    def_native_method!(ctx, library, symbol, ffi_library_symbol, 1)?;
+    def_native_method!(ctx, library, close, ffi_library_close, 0)?;
+    // `func` wraps the foreign function object in a JS function that keeps it reachable.
+    let func = ctx
+        .compile_function(
+            "func",
+            include_str!("../builtins/FFI.js"),
+            &["attach".to_string(), "call".to_string()],
+        )
+        .unwrap_or_else(|_| panic!("Failed to compile FFI builtins"));
+    let attach = JsNativeFunction::new(ctx, "attach".intern(), ffi_function_attach, 4);
+    let call = JsNativeFunction::new(ctx, "call".intern(), ffi_function_call, 2);
+    let mut args = [JsValue::new(attach), JsValue::new(call)];
+    let mut args = Arguments::new(JsValue::encode_undefined_value(), &mut args);
+    let func = func
+        .get_jsobject()
+        .as_function_mut()
+        .call(ctx, &mut args, func)?;
+    library.put(ctx, "func".intern(), func, false)?;
+
+    let mut callback = JsObject::new_empty(ctx);
+    def_native_method!(ctx, callback, close, ffi_callback_close, 0)?;
+
+    let mut global = ctx.global_object();
+    global.put(
+        ctx,
+        "@@FFILibrary".intern().private(),
+        JsValue::new(library),
+        false,
+    )?;
+    global.put(
+        ctx,
+        "@@FFICallback".intern().private(),
+        JsValue::new(callback),
+        false,
+    )?;
+    drop(defer);
+    Ok(())
 }
 
-extern "C" fn drop_ffi_fn(obj: &mut JsObject) {
-    unsafe { ManuallyDrop::drop(obj.data::<FFIFunction>()) }
-}
-
-extern "C" fn deser(_: &mut JsObject, _: &mut Deserializer, _: &mut VirtualMachine) {
-    unreachable!("Cannot deserialize FFI function");
-}
-
-extern "C" fn ser(_: &JsObject, _: &mut SnapshotSerializer) {
-    unreachable!("Cannot serialize FFI function");
-}
-extern "C" fn fsz() -> usize {
-    size_of::<FFIFunction>()
-}
-impl FFIFunction {
-    define_jsclass_with_symbol!(
-        JsObject,
-        FFIFunction,
-        Object,
-        Some(drop_ffi_fn),
-        None,
-        Some(deser),
-        Some(ser),
-        Some(fsz)
-    );
-}
-
-pub struct FFILibrary {
-    library: Option<libloading::Library>,
-}
-
-extern "C" fn drop_ffi_lib(obj: &mut JsObject) {
-    obj.data::<FFILibrary>().close();
-    unsafe { ManuallyDrop::drop(obj.data::<FFILibrary>()) }
+/// C type of a parameter, return value or memory location.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Kind {
+    Void,
+    Bool,
+    I8,
+    I16,
+    I32,
+    I64,
+    Isize,
+    U8,
+    U16,
+    U32,
+    U64,
+    Usize,
+    F32,
+    F64,
+    Pointer,
+    String,
+    Buffer,
 }
 
-extern "C" fn deser_lib(_: &mut JsObject, _: &mut Deserializer, _: &mut VirtualMachine) {
-    unreachable!("Cannot deserialize FFI library");
-}
+impl Kind {
+    pub fn parse(ctx: GcPointer<Context>, value: JsValue) -> Result<Self, JsValue> {
+        let name = value.to_string(ctx)?;
+        Ok(match name.as_str() {
+            "void" => Kind::Void,
+            "bool" => Kind::Bool,
+            "i8" => Kind::I8,
+            "i16" => Kind::I16,
+            "i32" => Kind::I32,
+            "i64" => Kind::I64,
+            "isize" => Kind::Isize,
+            "u8" => Kind::U8,
+            "u16" => Kind::U16,
+            "u32" => Kind::U32,
+            "u64" => Kind::U64,
+            "usize" => Kind::Usize,
+            "f32" => Kind::F32,
+            "f64" => Kind::F64,
+            "pointer" => Kind::Pointer,
+            "string" => Kind::String,
+            "buffer" => Kind::Buffer,
+            _ => {
+                return Err(JsValue::new(
+                    ctx.new_type_error(format!("ffi: unknown type '{}'", name)),
+                ))
+            }
+        })
+    }
 
-extern "C" fn ser_lib(_: &JsObject, _: &mut SnapshotSerializer) {
-    unreachable!("Cannot serialize FFI library");
-}
+    fn ffi_type(self) -> TypePointer {
+        unsafe {
+            let ty: *const ffi_type = match self {
+                Kind::Void => &types::void,
+                Kind::Bool | Kind::U8 => &types::uint8,
+                Kind::I8 => &types::sint8,
+                Kind::I16 => &types::sint16,
+                Kind::I32 => &types::sint32,
+                Kind::I64 | Kind::Isize => &types::sint64,
+                Kind::U16 => &types::uint16,
+                Kind::U32 => &types::uint32,
+                Kind::U64 | Kind::Usize => &types::uint64,
+                Kind::F32 => &types::float,
+                Kind::F64 => &types::double,
+                Kind::Pointer | Kind::String | Kind::Buffer => &types::pointer,
+            };
+            ty as TypePointer
+        }
+    }
 
-extern "C" fn sz() -> usize {
-    size_of::<FFILibrary>()
-}
+    pub fn size(self) -> usize {
+        unsafe { (*self.ffi_type()).size }
+    }
 
-impl FFILibrary {
-    define_jsclass_with_symbol!(
-        JsObject,
-        FFILibrary,
-        Object,
-        Some(drop_ffi_lib),
-        None,
-        Some(deser_lib),
-        Some(ser_lib),
-        Some(sz)
-    );
-}
-
-/// Returns a pointer to a statically allocated FFI type.
-macro_rules! ffi_type {
-    ($name: ident) => {
-        &types::$name as *const ffi_type as *mut ffi_type
-    };
-}
+    fn is_integral(self) -> bool {
+        !matches!(
+            self,
+            Kind::Void | Kind::F32 | Kind::F64 | Kind::Pointer | Kind::String | Kind::Buffer
+        )
+    }
 
-/// Converts a &T to a *mut c_void pointer.
-macro_rules! raw_pointer {
-    ($value: expr) => {
-        $value as *mut _ as RawPointer
-    };
-}
+    /// Read a value of this type from `ptr`.
+    unsafe fn read(self, ctx: GcPointer<Context>, ptr: *const u8) -> JsValue {
+        match self {
+            Kind::Void => JsValue::encode_undefined_value(),
+            Kind::Bool => JsValue::new(ptr.read() != 0),
+            Kind::I8 => JsValue::new(ptr.cast::<i8>().read()),
+            Kind::I16 => JsValue::new(ptr.cast::<i16>().read_unaligned()),
+            Kind::I32 => JsValue::new(ptr.cast::<i32>().read_unaligned()),
+            Kind::I64 | Kind::Isize => JsValue::new(ptr.cast::<i64>().read_unaligned()),
+            Kind::U8 => JsValue::new(ptr.read()),
+            Kind::U16 => JsValue::new(ptr.cast::<u16>().read_unaligned()),
+            Kind::U32 => JsValue::new(ptr.cast::<u32>().read_unaligned()),
+            Kind::U64 | Kind::Usize => JsValue::new(ptr.cast::<u64>().read_unaligned()),
+            Kind::F32 => JsValue::new(ptr.cast::<f32>().read_unaligned()),
+            Kind::F64 => JsValue::new(ptr.cast::<f64>().read_unaligned()),
+            Kind::Pointer | Kind::Buffer => {
+                pointer_to_value(ptr.cast::<RawPointer>().read_unaligned())
+            }
+            Kind::String => {
+                let string = ptr.cast::<*const c_char>().read_unaligned();
+                if string.is_null() {
+                    JsValue::encode_null_value()
+                } else {
+                    let string = CStr::from_ptr(string).to_string_lossy();
+                    JsValue::new(JsString::new(ctx, string))
+                }
+            }
+        }
+    }
 
-/// Generates a "match" that can be used for pattern matching a pointer to an
-/// FFI type.
-///
-/// For example, this macro call:
-///
-///     match_ffi_type!(
-///       some_variable,
-///       pointer => { 10 }
-///       void => { 20 }
-///     );
-///
-/// Would compile into:
-///
-///     match some_variable {
-///         t if t == ffi_type!(pointer) => { 10 }
-///         t if t == ffi_type!(void) => { 20 }
-///         _ => unreachable!()
-///     }
-///
-/// Just like a regular `match`, `match_ffi_type!` supports OR conditions:
-///
-///     match_ffi_type!(
-///       some_variable,
-///       pointer => { 10 }
-///       void => { 20 }
-///       sint8 | sint16 | sint32 | sint64 => { 30 }
-///     );
-///
-/// This would compile into the following:
-///
-///     match some_variable {
-///         t if t == ffi_type!(pointer) => { 10 }
-///         t if t == ffi_type!(void) => { 20 }
-///         t if t == ffi_type!(sint8) => { 30 }
-///         t if t == ffi_type!(sint16) => { 30 }
-///         t if t == ffi_type!(sint32) => { 30 }
-///         t if t == ffi_type!(sint64) => { 30 }
-///         _ => unreachable!()
-///     }
-macro_rules! match_ffi_type {
-    (
-        $pointer: expr,
-
-        $(
-            $($type: ident)|+ => $body: expr
-        )+
-    ) => {
-        match $pointer {
-            $(
-                $(
-                    t if t == ffi_type!($type) => { $body }
-                )+
-            )+
-            _ => unreachable!()
+    /// Read a return value of this type from the buffer filled by libffi. Integral values are widened to a
+    /// full register there, so narrow ones are at the end of it on big endian targets.
+    unsafe fn read_return(self, ctx: GcPointer<Context>, slot: *const u64) -> JsValue {
+        let mut ptr = slot.cast::<u8>();
+        if cfg!(target_endian = "big") && self.is_integral() {
+            ptr = ptr.add(size_of::<u64>() - self.size());
         }
+        self.read(ctx, ptr)
     }
 }
 
-macro_rules! ffi_type_error {
-    ($vm: expr,$type: expr) => {
-        return Err(JsValue::new(JsString::new(
-            $vm,
-            format!("Invalid FFI type: {}", $type),
-        )));
-    };
+fn pointer_to_value(pointer: RawPointer) -> JsValue {
+    if pointer.is_null() {
+        JsValue::encode_null_value()
+    } else {
+        JsValue::new(pointer as usize as f64)
+    }
 }
 
-/// Returns the size of a type ID.
-///
-/// The size of the type is returned as a tagged integer.
-pub fn type_size(ctx: GcPointer<Context>, id: i64) -> Result<JsValue, JsValue> {
-    let size = unsafe {
-        match id {
-            TYPE_VOID => types::void.size,
-            TYPE_POINTER | TYPE_STRING | TYPE_BYTE_ARRAY => types::pointer.size,
-            TYPE_DOUBLE => types::double.size,
-            TYPE_FLOAT => types::float.size,
-            TYPE_I8 => types::sint8.size,
-            TYPE_I16 => types::sint16.size,
-            TYPE_I32 => types::sint32.size,
-            TYPE_I64 => types::sint64.size,
-            TYPE_U8 => types::uint8.size,
-            TYPE_U16 => types::uint16.size,
-            TYPE_U32 => types::uint32.size,
-            TYPE_U64 => types::uint64.size,
-            TYPE_SIZE_T => mem::size_of::<usize>(),
-            _ => ffi_type_error!(vm, id),
+/// Convert `value` to a C pointer. Accepts numbers, `null`, `undefined`, `ArrayBuffer`s and callbacks.
+fn value_to_pointer(ctx: GcPointer<Context>, value: JsValue) -> Result<RawPointer, JsValue> {
+    if value.is_null() || value.is_undefined() {
+        return Ok(null_mut());
+    }
+    if value.is_number() {
+        let address = value.get_number();
+        if address < 0.0 || address.fract() != 0.0 || address > u64::MAX as f64 {
+            return Err(JsValue::new(ctx.new_type_error(format!(
+                "ffi: {} is not a valid address",
+                address
+            ))));
         }
-    };
-
-    Ok(JsValue::new(size as u32))
-}
-
-/// Returns the alignment of a type ID.
-///
-/// The alignment of the type is returned as a tagged integer.
-pub fn type_alignment(ctx: GcPointer<Context>, id: i64) -> Result<JsValue, JsValue> {
-    let size = unsafe {
-        match id {
-            TYPE_VOID => types::void.alignment,
-            TYPE_POINTER | TYPE_STRING | TYPE_BYTE_ARRAY => types::pointer.alignment,
-            TYPE_DOUBLE => types::double.alignment,
-            TYPE_FLOAT => types::float.alignment,
-            TYPE_I8 => types::sint8.alignment,
-            TYPE_I16 => types::sint16.alignment,
-            TYPE_I32 => types::sint32.alignment,
-            TYPE_I64 => types::sint64.alignment,
-            TYPE_U8 => types::uint8.alignment,
-            TYPE_U16 => types::uint16.alignment,
-            TYPE_U32 => types::uint32.alignment,
-            TYPE_U64 => types::uint64.alignment,
-            TYPE_SIZE_T => mem::align_of::<usize>() as u16,
-            _ => ffi_type_error!(vm, id),
+        return Ok(address as usize as RawPointer);
+    }
+    if value.is_jsobject() {
+        let object = value.get_jsobject();
+        if object.is_class(JsArrayBuffer::class()) {
+            return buffer_pointer(ctx, value);
         }
-    };
+        if object.is_class(FFICallback::class()) {
+            let callback = object.data::<FFICallback>();
+            if callback.closure.is_null() {
+                return Err(JsValue::new(ctx.new_type_error("ffi: callback is closed")));
+            }
+            return Ok(callback.code.as_mut_ptr());
+        }
+    }
+    Err(JsValue::new(ctx.new_type_error(format!(
+        "ffi: cannot pass value of type '{}' as pointer",
+        value.type_of()
+    ))))
+}
 
-    Ok(JsValue::new(size as u32))
+/// Data pointer of an `ArrayBuffer` or null.
+fn buffer_pointer(ctx: GcPointer<Context>, value: JsValue) -> Result<RawPointer, JsValue> {
+    if value.is_null() || value.is_undefined() {
+        return Ok(null_mut());
+    }
+    let buffer = TypedJsObject::<JsArrayBuffer>::try_from(ctx, value)?;
+    if !buffer.attached() {
+        return Err(JsValue::new(
+            ctx.new_type_error("ffi: ArrayBuffer is detached"),
+        ));
+    }
+    Ok(buffer.get_data_block().cast())
 }
 
 /// A value of some sort to be passed to a C function.
@@ -364,558 +304,603 @@ pub enum Argument {
 }
 
 impl Argument {
-    unsafe fn wrap(
-        ffi_type: *mut ffi_type,
-        val: JsValue,
+    /// Convert `value` to `kind`. Strings are copied into `strings` which must outlive the C call.
+    pub fn new(
         ctx: GcPointer<Context>,
-    ) -> Result<Argument, JsValue> {
-        match_ffi_type!(
-            ffi_type,
-            pointer => {
-                if val.is_number() {
-                    let n = val.get_number();
-                    if n as i32 as f64 == n
-                    {
-                        return Ok(Argument::I32(n as i32));
-                    } else {
-                        return Ok(Argument::F64(n));
-                    }
-
-                } else if val.is_jsstring() {
-                     return Ok(Argument::Pointer(val.get_jsstring().as_str().as_ptr() as *mut _));
-                } else {
-                    let val_str = val.to_string(vm);
-                    let val_str = if let Ok(val_str) = val_str {
-                        val_str
-                    } else {
-                        "<unknown>".to_owned()
-                    };
-                    return Err(JsValue::new(JsString::new(vm,format!("Cannot passs value '{}' as pointer",val_str))));
+        kind: Kind,
+        value: JsValue,
+        strings: &mut Vec<CString>,
+    ) -> Result<Self, JsValue> {
+        Ok(match kind {
+            Kind::Void => Argument::Void,
+            Kind::Bool => Argument::U8(value.to_boolean() as u8),
+            Kind::I8 => Argument::I8(value.to_int32(ctx)? as _),
+            Kind::I16 => Argument::I16(value.to_int32(ctx)? as _),
+            Kind::I32 => Argument::I32(value.to_int32(ctx)?),
+            Kind::I64 | Kind::Isize => Argument::I64(value.to_number(ctx)? as _),
+            Kind::U8 => Argument::U8(value.to_uint32(ctx)? as _),
+            Kind::U16 => Argument::U16(value.to_uint32(ctx)? as _),
+            Kind::U32 => Argument::U32(value.to_uint32(ctx)?),
+            Kind::U64 | Kind::Usize => Argument::U64(value.to_number(ctx)? as _),
+            Kind::F32 => Argument::F32(value.to_number(ctx)? as _),
+            Kind::F64 => Argument::F64(value.to_number(ctx)?),
+            Kind::Pointer => Argument::Pointer(value_to_pointer(ctx, value)?),
+            Kind::Buffer => Argument::Pointer(buffer_pointer(ctx, value)?),
+            Kind::String => {
+                if value.is_null() || value.is_undefined() {
+                    return Ok(Argument::Pointer(null_mut()));
                 }
+                let string = match CString::new(value.to_string(ctx)?) {
+                    Ok(string) => string,
+                    Err(_) => {
+                        return Err(JsValue::new(
+                            ctx.new_type_error("ffi: string contains NUL character"),
+                        ))
+                    }
+                };
+                // The heap buffer of a CString does not move when the vector grows.
+                let pointer = string.as_ptr() as RawPointer;
+                strings.push(string);
+                Argument::Pointer(pointer)
             }
-            void => return Ok(Argument::Void)
-            float => return Ok(Argument::F32(val.to_number(vm)? as f32))
-            double => return Ok(Argument::F64(val.to_number(vm)?))
-            sint8 => return Ok(Argument::I8(val.to_int32(vm)? as _))
-            sint16 => return Ok(Argument::I16(val.to_int32(vm)? as _))
-            sint32 => return Ok(Argument::I32(val.to_int32(vm)? as _))
-            sint64 => return Ok(Argument::I64(val.to_int32(vm)? as _))
-            uint8 => return Ok(Argument::U8(val.to_uint32(vm)? as _))
-            uint16 => return Ok(Argument::U16(val.to_uint32(vm)? as _))
-            uint32 => return Ok(Argument::U32(val.to_uint32(vm)? as _))
-            uint64 => return Ok(Argument::U64(val.to_uint32(vm)? as _))
-
-        );
+        })
     }
 
     /// Returns a C pointer to the wrapped value.
     fn as_c_pointer(&mut self) -> RawPointer {
         match self {
-            Argument::Pointer(ref mut val) => {
-                // When passing a pointer we shouldn't pass the pointer
-                // directly, instead we want a pointer to the pointer to pass to
-                // the underlying C function.
-                val as *mut RawPointer as RawPointer
-            }
-            Argument::Void => ptr::null_mut() as RawPointer,
-            Argument::F32(ref mut val) => raw_pointer!(val),
-            Argument::F64(ref mut val) => raw_pointer!(val),
-            Argument::I8(ref mut val) => raw_pointer!(val),
-            Argument::I16(ref mut val) => raw_pointer!(val),
-            Argument::I32(ref mut val) => raw_pointer!(val),
-            Argument::I64(ref mut val) => raw_pointer!(val),
-            Argument::U8(ref mut val) => raw_pointer!(val),
-            Argument::U16(ref mut val) => raw_pointer!(val),
-            Argument::U32(ref mut val) => raw_pointer!(val),
-            Argument::U64(ref mut val) => raw_pointer!(val),
+            // libffi takes a pointer to the pointer argument, not the pointer itself.
+            Argument::Pointer(ref mut val) => val as *mut RawPointer as RawPointer,
+            Argument::Void => null_mut(),
+            Argument::F32(ref mut val) => val as *mut _ as RawPointer,
+            Argument::F64(ref mut val) => val as *mut _ as RawPointer,
+            Argument::I8(ref mut val) => val as *mut _ as RawPointer,
+            Argument::I16(ref mut val) => val as *mut _ as RawPointer,
+            Argument::I32(ref mut val) => val as *mut _ as RawPointer,
+            Argument::I64(ref mut val) => val as *mut _ as RawPointer,
+            Argument::U8(ref mut val) => val as *mut _ as RawPointer,
+            Argument::U16(ref mut val) => val as *mut _ as RawPointer,
+            Argument::U32(ref mut val) => val as *mut _ as RawPointer,
+            Argument::U64(ref mut val) => val as *mut _ as RawPointer,
+        }
+    }
+
+    /// Store the value at `ptr`.
+    unsafe fn write(self, ptr: *mut u8) {
+        match self {
+            Argument::Pointer(val) => ptr.cast::<RawPointer>().write_unaligned(val),
+            Argument::Void => {}
+            Argument::F32(val) => ptr.cast::<f32>().write_unaligned(val),
+            Argument::F64(val) => ptr.cast::<f64>().write_unaligned(val),
+            Argument::I8(val) => ptr.cast::<i8>().write(val),
+            Argument::I16(val) => ptr.cast::<i16>().write_unaligned(val),
+            Argument::I32(val) => ptr.cast::<i32>().write_unaligned(val),
+            Argument::I64(val) => ptr.cast::<i64>().write_unaligned(val),
+            Argument::U8(val) => ptr.write(val),
+            Argument::U16(val) => ptr.cast::<u16>().write_unaligned(val),
+            Argument::U32(val) => ptr.cast::<u32>().write_unaligned(val),
+            Argument::U64(val) => ptr.cast::<u64>().write_unaligned(val),
+        }
+    }
+
+    /// Store the value as the return value of a callback. libffi expects integral values widened to a
+    /// full register.
+    unsafe fn write_return(self, slot: *mut u64) {
+        match self {
+            Argument::I8(val) => slot.write(val as i64 as u64),
+            Argument::I16(val) => slot.write(val as i64 as u64),
+            Argument::I32(val) => slot.write(val as i64 as u64),
+            Argument::U8(val) => slot.write(val as u64),
+            Argument::U16(val) => slot.write(val as u64),
+            Argument::U32(val) => slot.write(val as u64),
+            other => other.write(slot.cast()),
         }
     }
 }
-/// Returns an FFI type for an integer pointer.
-unsafe fn ffi_type_for(pointer: JsValue, ctx: GcPointer<Context>) -> Result<TypePointer, JsValue> {
-    let int = pointer.to_int32(vm)?;
-    let typ = match int as i64 {
-        TYPE_VOID => ffi_type!(void),
-        TYPE_POINTER | TYPE_STRING | TYPE_BYTE_ARRAY => ffi_type!(pointer),
-        TYPE_DOUBLE => ffi_type!(double),
-        TYPE_FLOAT => ffi_type!(float),
-        TYPE_I8 => ffi_type!(sint8),
-        TYPE_I16 => ffi_type!(sint16),
-        TYPE_I32 => ffi_type!(sint32),
-        TYPE_I64 => ffi_type!(sint64),
-        TYPE_U8 => ffi_type!(uint8),
-        TYPE_U16 => ffi_type!(uint16),
-        TYPE_U32 => ffi_type!(uint32),
-        TYPE_U64 => ffi_type!(uint64),
-        TYPE_SIZE_T => {
-            match mem::size_of::<usize>() {
-                64 => ffi_type!(uint64),
-                32 => ffi_type!(uint32),
-                8 => ffi_type!(uint8),
-
-                // The C spec states that `size_t` is at least 16 bits, so we
-                // can use this as the default.
-                _ => ffi_type!(uint16),
+
+/// Parameter and return types of a foreign function or callback with the prepared call interface.
+pub struct Signature {
+    cif: ffi_cif,
+    returns: Kind,
+    params: Vec<Kind>,
+    types: Vec<TypePointer>,
+}
+
+impl Signature {
+    pub fn new(
+        ctx: GcPointer<Context>,
+        returns: JsValue,
+        params: JsValue,
+    ) -> Result<Self, JsValue> {
+        let returns = Kind::parse(ctx, returns)?;
+        if returns == Kind::Buffer {
+            return Err(JsValue::new(ctx.new_type_error(
+                "ffi: 'buffer' is not a valid return type, use 'pointer'",
+            )));
+        }
+        if !params.is_jsobject() {
+            return Err(JsValue::new(
+                ctx.new_type_error("ffi: parameter types must be an array"),
+            ));
+        }
+        let mut object = params.get_jsobject();
+        let len = super::get_length(ctx, &mut object)?;
+        let mut params = Vec::with_capacity(len as usize);
+        for i in 0..len {
+            let kind = Kind::parse(ctx, object.get(ctx, Symbol::Index(i))?)?;
+            if kind == Kind::Void {
+                return Err(JsValue::new(
+                    ctx.new_type_error("ffi: 'void' is not a valid parameter type"),
+                ));
             }
+            params.push(kind);
         }
-        _ => ffi_type_error!(vm, int),
-    };
+        let mut signature = Signature {
+            cif: Default::default(),
+            returns,
+            types: params.iter().map(|kind| kind.ffi_type()).collect(),
+            params,
+        };
+        // cif keeps a pointer to the heap buffer of `types` which stays put when the signature is moved.
+        let result = unsafe {
+            prep_cif(
+                &mut signature.cif,
+                ABI,
+                signature.types.len(),
+                returns.ffi_type(),
+                signature.types.as_mut_ptr(),
+            )
+        };
+        match result {
+            Ok(_) => Ok(signature),
+            Err(FFIError::Typedef) => Err(JsValue::new(
+                ctx.new_type_error("ffi: the type representation is invalid or unsupported"),
+            )),
+            Err(FFIError::Abi) => Err(JsValue::new(
+                ctx.new_type_error("ffi: the ABI is invalid or unsupported"),
+            )),
+        }
+    }
+}
 
-    Ok(typ as TypePointer)
+pub struct FFILibrary {
+    library: Option<Rc<libloading::Library>>,
 }
 
-impl FFILibrary {
-    /// Opens a library using one or more possible names, stored as pointers to
-    /// heap allocated objects.
-    pub fn from_pointers(
-        ctx: GcPointer<Context>,
-        search_for: &[JsValue],
-    ) -> Result<FFILibrary, JsValue> {
-        let mut names = Vec::with_capacity(search_for.len());
+extern "C" fn drop_ffi_lib(obj: GcPointer<JsObject>) {
+    unsafe { ManuallyDrop::drop(obj.data::<FFILibrary>()) }
+}
 
-        for name in search_for {
-            names.push(name.to_string(vm)?);
-        }
+extern "C" fn lib_size() -> usize {
+    size_of::<FFILibrary>()
+}
 
-        Self::open(&names).map_err(|err| JsValue::new(JsString::new(vm, err)))
+impl JsClass for FFILibrary {
+    fn class() -> &'static Class {
+        define_jsclass!(
+            FFILibrary,
+            Library,
+            Some(drop_ffi_lib),
+            None,
+            Some(lib_size)
+        )
     }
+}
 
+impl FFILibrary {
     /// Opens a library using one or more possible names.
-    pub fn open<P: AsRef<OsStr> + Debug + Display>(search_for: &[P]) -> Result<FFILibrary, String> {
-        let mut errors = Vec::new();
-
-        unsafe {
-            for name in search_for {
-                match libloading::Library::new(name).map(|raw| FFILibrary { library: Some(raw) }) {
-                    Ok(library) => return Ok(library),
-                    Err(err) => {
-                        errors.push(format!("\n{}: {}", name, err));
-                    }
+    pub fn open(search_for: &[String]) -> Result<Self, String> {
+        let mut error_message = "Unable to open the supplied libraries:".to_string();
+        for name in search_for {
+            match unsafe { libloading::Library::new(name) } {
+                Ok(library) => {
+                    return Ok(FFILibrary {
+                        library: Some(Rc::new(library)),
+                    })
                 }
+                Err(err) => error_message.push_str(&format!("\n{}: {}", name, err)),
             }
-
-            let mut error_message = "Unable to open the supplied libraries:\n".to_string();
-
-            for error in errors {
-                error_message.push_str(&error);
-            }
-
-            Err(error_message)
         }
+        Err(error_message)
     }
 
-    /// Obtains a pointer to a symbol.
-    ///
-    /// This method is unsafe because the pointer could be of any type, thus it
-    /// is up to the caller to make sure the result is used appropriately.
-    pub unsafe fn get(&self, name: &str) -> Result<Pointer, String> {
-        let inner = if let Some(ref inner) = self.library {
-            inner
-        } else {
-            return Err("The library has been closed".to_string());
+    /// Obtains the address of a symbol.
+    pub fn get(&self, name: &str) -> Result<(Rc<libloading::Library>, RawPointer), String> {
+        let library = match self.library {
+            Some(ref library) => library.clone(),
+            None => return Err("The library has been closed".to_string()),
         };
-
-        inner
-            .get(name.as_bytes())
-            .map(|sym: libloading::Symbol<RawPointer>| Pointer::new(*sym))
-            .map_err(|err| err.to_string())
-    }
-
-    pub fn close(&mut self) {
-        drop(self.library.take());
+        let pointer = unsafe {
+            library
+                .get::<RawPointer>(name.as_bytes())
+                .map(|symbol| *symbol)
+                .map_err(|err| err.to_string())?
+        };
+        Ok((library, pointer))
     }
 }
 
-impl Pointer {
-    pub fn new(inner: RawPointer) -> Self {
-        Pointer { inner }
-    }
-
-    /// Returns the address of this pointer.
-    pub fn address(self) -> usize {
-        self.inner as usize
-    }
+pub struct FFIFunction {
+    /// Keeps the library loaded while the function is reachable.
+    _library: Rc<libloading::Library>,
+    pointer: RawPointer,
+    signature: Signature,
+}
 
-    /// Reads the value of this pointer into a particular type, based on the
-    /// integer specified in `kind`.
-    pub unsafe fn read_as(
-        self,
-        ctx: GcPointer<Context>,
-        kind: JsValue,
-    ) -> Result<JsValue, JsValue> {
-        let int = kind.to_int32(vm)? as i64;
-        let pointer = match int {
-            TYPE_POINTER => {
-                todo!()
-            }
-            TYPE_STRING => {
-                let string = self.read_cstr().to_string_lossy().into_owned();
+extern "C" fn drop_ffi_fn(obj: GcPointer<JsObject>) {
+    unsafe { ManuallyDrop::drop(obj.data::<FFIFunction>()) }
+}
 
-                JsValue::new(JsString::new(vm, string))
-            }
-            TYPE_BYTE_ARRAY => {
-                todo!()
-            }
-            TYPE_DOUBLE => self.read_float::<c_double>(),
-            TYPE_FLOAT => self.read_float::<c_float>(),
-            TYPE_I8 => self.read_signed_integer::<c_char>(),
-            TYPE_I16 => self.read_signed_integer::<c_short>(),
-            TYPE_I32 => self.read_signed_integer::<c_int>(),
-            TYPE_I64 => self.read_signed_integer::<c_long>(),
-            TYPE_U8 => self.read_unsigned_integer::<c_uchar>(),
-            TYPE_U16 => self.read_unsigned_integer::<c_ushort>(),
-            TYPE_U32 => self.read_unsigned_integer::<c_uint>(),
-            TYPE_U64 => self.read_unsigned_integer::<c_ulong>(),
-            TYPE_SIZE_T => match mem::size_of::<usize>() {
-                64 => self.read_unsigned_integer::<c_ulong>(),
-                32 => self.read_unsigned_integer::<c_uint>(),
-                16 => self.read_unsigned_integer::<c_ushort>(),
-                8 => self.read_unsigned_integer::<c_uchar>(),
-                _ => unreachable!(),
-            },
-            _ => ffi_type_error!(vm, int),
-        };
+extern "C" fn fn_size() -> usize {
+    size_of::<FFIFunction>()
+}
 
-        Ok(pointer)
+impl JsClass for FFIFunction {
+    fn class() -> &'static Class {
+        define_jsclass!(
+            FFIFunction,
+            ForeignFunction,
+            Some(drop_ffi_fn),
+            None,
+            Some(fn_size)
+        )
     }
+}
 
-    /// Writes a value to the underlying pointer.
-    pub unsafe fn write_as(
-        self,
-        ctx: GcPointer<Context>,
-        kind: JsValue,
-        value: JsValue,
-    ) -> Result<(), JsValue> {
-        let int = kind.to_int32(vm)? as i64;
-
-        match int {
-            TYPE_STRING => {
-                let string = value.to_string(vm)?;
-
-                ptr::copy(
-                    string.as_ptr() as *mut c_char,
-                    self.inner as *mut c_char,
-                    string.len(),
-                );
-            }
-            TYPE_BYTE_ARRAY => {
-                todo!("byte array");
+impl FFIFunction {
+    /// Calls the function with the given arguments.
+    pub fn call(&self, ctx: GcPointer<Context>, args: &[JsValue]) -> Result<JsValue, JsValue> {
+        if args.len() != self.signature.params.len() {
+            return Err(JsValue::new(ctx.new_type_error(format!(
+                "ffi: expected {} arguments but got {}",
+                self.signature.params.len(),
+                args.len()
+            ))));
+        }
+        let mut strings = vec![];
+        let mut arguments = Vec::with_capacity(args.len());
+        for (kind, value) in self.signature.params.iter().zip(args.iter()) {
+            arguments.push(Argument::new(ctx, *kind, *value, &mut strings)?);
+        }
+        // libffi takes pointers to the arguments, so `arguments` and `strings` must outlive the call.
+        let mut argument_pointers = arguments
+            .iter_mut()
+            .map(Argument::as_c_pointer)
+            .collect::<Vec<_>>();
+        // libffi requires a mutable pointer to the CIF but never modifies it once prepared.
+        let cif = &self.signature.cif as *const ffi_cif as *mut ffi_cif;
+        let returns = self.signature.returns;
+        let result = unsafe {
+            let fun = CodePtr::from_ptr(self.pointer);
+            let args = argument_pointers.as_mut_ptr();
+            match returns {
+                Kind::F32 => JsValue::new(ffi_call::<f32>(cif, fun, args)),
+                Kind::F64 => JsValue::new(ffi_call::<f64>(cif, fun, args)),
+                _ => {
+                    let slot = ffi_call::<u64>(cif, fun, args);
+                    returns.read_return(ctx, &slot)
+                }
             }
-            TYPE_POINTER => todo!(),
-            TYPE_DOUBLE => self.write(value.to_number(vm)?),
-            TYPE_FLOAT => self.write(value.to_number(vm)? as f32),
-            TYPE_I8 => self.write(value.to_int32(vm)? as i8),
-            TYPE_I16 => self.write(value.to_int32(vm)? as i16),
-            TYPE_I32 => self.write(value.to_int32(vm)?),
-            TYPE_I64 => self.write(value.to_int32(vm)? as i64),
-            TYPE_U8 => self.write(value.to_uint32(vm)? as u8),
-            TYPE_U16 => self.write(value.to_uint32(vm)? as u16),
-            TYPE_U32 => self.write(value.to_uint32(vm)?),
-            TYPE_U64 => self.write(value.to_uint32(vm)? as u64),
-            TYPE_SIZE_T => self.write(value.to_uint32(vm)? as usize),
-            _ => ffi_type_error!(vm, int),
         };
-
-        Ok(())
+        match PENDING_EXCEPTION.with(|pending| pending.borrow_mut().take()) {
+            Some(exception) => Err(exception.get_value()),
+            None => Ok(result),
+        }
     }
+}
 
-    /// Returns a new Pointer, optionally starting at the given offset.
-    ///
-    /// The `offset` argument is the offset in _bytes_, not the number of
-    /// elements (unlike Rust's `pointer::offset`).
-    pub fn with_offset(self, offset_bytes: usize) -> Self {
-        let inner = (self.inner as usize + offset_bytes) as RawPointer;
-
-        Pointer::new(inner)
-    }
+struct CallbackData {
+    ctx: GcPointer<Context>,
+    function: JsValue,
+    signature: Signature,
+}
 
-    /// Returns the underlying C pointer.
-    fn as_c_pointer(self) -> RawPointer {
-        self.inner
+impl CallbackData {
+    unsafe fn invoke(&self, args: *const *const c_void, result: *mut u64) -> Result<(), JsValue> {
+        let ctx = self.ctx;
+        let mut values = self
+            .signature
+            .params
+            .iter()
+            .enumerate()
+            .map(|(i, kind)| kind.read(ctx, (*args.add(i)).cast()))
+            .collect::<Vec<_>>();
+        let mut arguments = Arguments::new(JsValue::encode_undefined_value(), &mut values);
+        let value = self.function.get_jsobject().as_function_mut().call(
+            ctx,
+            &mut arguments,
+            self.function,
+        )?;
+        let mut strings = vec![];
+        Argument::new(ctx, self.signature.returns, value, &mut strings)?.write_return(result);
+        Ok(())
     }
+}
 
-    unsafe fn read<R>(self) -> R {
-        ptr::read(self.inner as *mut R)
+unsafe extern "C" fn callback_trampoline(
+    _cif: &ffi_cif,
+    result: &mut u64,
+    args: *const *const c_void,
+    data: &CallbackData,
+) {
+    // C sees zero when the callback throws.
+    *result = 0;
+    // Do not run more JS once an exception is waiting to be rethrown.
+    if PENDING_EXCEPTION.with(|pending| pending.borrow().is_some()) {
+        return;
     }
-
-    unsafe fn write<T>(self, value: T) {
-        ptr::write(self.inner as *mut T, value);
+    if let Err(exception) = data.invoke(args, result) {
+        *result = 0;
+        let exception = data.ctx.vm().add_persistent_root(exception);
+        PENDING_EXCEPTION.with(|pending| *pending.borrow_mut() = Some(exception));
     }
+}
 
-    unsafe fn read_signed_integer<T: Into<i64>>(self) -> JsValue {
-        JsValue::new(self.read::<T>().into())
-    }
+pub struct FFICallback {
+    closure: *mut ffi_closure,
+    code: CodePtr,
+    data: Box<CallbackData>,
+}
 
-    unsafe fn read_unsigned_integer<T: Into<u64>>(self) -> JsValue {
-        JsValue::new(self.read::<T>().into())
+impl FFICallback {
+    fn close(&mut self) {
+        if !self.closure.is_null() {
+            unsafe { closure_free(self.closure) };
+            self.closure = null_mut();
+        }
     }
+}
 
-    unsafe fn read_float<T: Into<f64>>(self) -> JsValue {
-        JsValue::new(self.read::<T>().into())
-    }
+extern "C" fn drop_ffi_callback(obj: GcPointer<JsObject>) {
+    obj.data::<FFICallback>().close();
+    unsafe { ManuallyDrop::drop(obj.data::<FFICallback>()) }
+}
 
-    unsafe fn read_cstr<'a>(self) -> &'a CStr {
-        CStr::from_ptr(self.inner as *mut c_char)
-    }
+#[allow(improper_ctypes_definitions)]
+extern "C" fn trace_ffi_callback(tracer: &mut Visitor, obj: &JsObject) {
+    obj.data::<FFICallback>().data.function.trace(tracer);
 }
 
-impl FFIFunction {
-    /// Creates a new function using object pointers.
-    pub unsafe fn attach(
-        ctx: GcPointer<Context>,
-        library: &FFILibrary,
-        name: &str,
-        arguments: &[JsValue],
-        return_type: JsValue,
-    ) -> Result<GcPointer<JsObject>, JsValue> {
-        let func_ptr = library
-            .get(name)
-            .map_err(|x| JsValue::new(JsString::new(vm, x)))?;
-        let ffi_rtype = ffi_type_for(return_type, vm)?;
-        let mut ffi_arg_types = Vec::with_capacity(arguments.len());
-
-        for ptr in arguments {
-            ffi_arg_types.push(ffi_type_for(*ptr, vm)?);
-        }
+extern "C" fn callback_size() -> usize {
+    size_of::<FFICallback>()
+}
 
-        Self::create(vm, func_ptr, ffi_arg_types, ffi_rtype).map_err(|e| e)
+impl JsClass for FFICallback {
+    fn class() -> &'static Class {
+        define_jsclass!(
+            FFICallback,
+            Callback,
+            Some(drop_ffi_callback),
+            Some(trace_ffi_callback),
+            Some(callback_size)
+        )
     }
+}
 
-    /// Creates a new prepared function.
-    unsafe fn create(
-        ctx: GcPointer<Context>,
-        pointer: Pointer,
-        arguments: Vec<TypePointer>,
-        return_type: TypePointer,
-    ) -> Result<GcPointer<JsObject>, JsValue> {
-        let mut func = FFIFunction {
-            pointer,
-            cif: Default::default(),
-            arguments,
-            return_type,
-        };
+fn new_with_prototype(
+    ctx: GcPointer<Context>,
+    prototype: &str,
+    class: &'static Class,
+) -> Result<GcPointer<JsObject>, JsValue> {
+    let proto = ctx
+        .global_object()
+        .get(ctx, prototype.intern().private())?
+        .to_object(ctx)?;
+    let structure = Structure::new_indexed(ctx, Some(proto), false);
+    Ok(JsObject::new(ctx, &structure, class, ObjectTag::Ordinary))
+}
 
-        let result = prep_cif(
-            &mut func.cif,
-            ABI,
-            func.arguments.len(),
-            func.return_type,
-            func.arguments.as_mut_ptr(),
-        );
-
-        let f = result
-            .map(|_| func)
-            .map_err(|err| match err {
-                FFIError::Typedef => {
-                    "The type representation is invalid or unsupported".to_string()
-                }
-                FFIError::Abi => "The ABI is invalid or unsupported".to_string(),
-            })
-            .map_err(|x| JsValue::new(JsString::new(vm, x)))?;
-
-        let ffi_object = vm.global_object().get(vm, "CFunction".intern())?;
-        let structure = Structure::new_indexed(vm, Some(ffi_object.get_jsobject()), false);
-        let mut object = JsObject::new(
-            vm,
-            &structure,
-            FFIFunction::class(),
-            ObjectTag::Ordinary,
-        );
-        unsafe {
-            (object.data::<FFIFunction>() as *mut ManuallyDrop<Self> as *mut Self).write(f);
+/// `ffi.open(names)` loads the first library of a name or an array of names that can be opened.
+pub fn ffi_open(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, JsValue> {
+    let names = args.at(0);
+    let mut search_for = vec![];
+    if names.is_jsobject() {
+        let mut names = names.get_jsobject();
+        let len = super::get_length(ctx, &mut names)?;
+        for i in 0..len {
+            search_for.push(names.get(ctx, Symbol::Index(i))?.to_string(ctx)?);
         }
-        Ok(object)
+    } else {
+        search_for.push(names.to_string(ctx)?);
     }
+    let library =
+        FFILibrary::open(&search_for).map_err(|err| JsValue::new(ctx.new_reference_error(err)))?;
+    let mut object = new_with_prototype(ctx, "@@FFILibrary", FFILibrary::class())?;
+    *object.data::<FFILibrary>() = ManuallyDrop::new(library);
+    Ok(JsValue::new(object))
+}
 
-    /// Calls the function with the given arguments.
-    pub unsafe fn call(
-        &self,
-        ctx: GcPointer<Context>,
-        arg_ptrs: &[JsValue],
-    ) -> Result<JsValue, JsValue> {
-        if arg_ptrs.len() != self.arguments.len() {
-            return Err(JsValue::new(JsString::new(
-                vm,
-                format!(
-                    "Invalid number of arguments, expected {} but got {}",
-                    self.arguments.len(),
-                    arg_ptrs.len()
-                ),
-            )));
-        }
-
-        let mut arguments = Vec::with_capacity(arg_ptrs.len());
-
-        for (index, arg) in arg_ptrs.iter().enumerate() {
-            arguments.push(Argument::wrap(self.arguments[index], *arg, vm)?);
-        }
-
-        // libffi expects an array of _pointers_ to the arguments to pass,
-        // instead of an array containing the arguments directly. The pointers
-        // and the values they point to must outlive the FFI call, otherwise we
-        // may end up passing pointers to invalid memory.
-        let mut argument_pointers: Vec<RawPointer> =
-            arguments.iter_mut().map(Argument::as_c_pointer).collect();
-
-        // libffi requires a mutable pointer to the CIF, but "self" is immutable
-        // since we never actually modify the current function. To work around
-        // this we manually cast to a mutable pointer.
-        let cif_ptr = &self.cif as *const _ as *mut _;
-        let fun_ptr = CodePtr::from_ptr(self.pointer.inner);
-        let args_ptr = argument_pointers.as_mut_ptr();
-
-        // Instead of reading the result into some kind of generic pointer (*mut
-        // c_void for example) and trying to cast that to the right type, we'll
-        // immediately read the call's return value into the right type. This
-        // requires a bit more code, but is much less unsafe than trying to cast
-        // types from X to Y without knowing if this even works reliably.
-        let pointer = match_ffi_type!(
-            self.return_type,
-            pointer => {
-                let _result: RawPointer = ffi_call(cif_ptr, fun_ptr, args_ptr);
-
-                todo!()
-            }
-            void => {
-                ffi_call::<c_void>(cif_ptr, fun_ptr, args_ptr);
-
-                JsValue::encode_undefined_value()
-            }
-            double | float => {
-                let result: c_double = ffi_call(cif_ptr, fun_ptr, args_ptr);
-
-                JsValue::new(result as f64)
-                            }
-            sint8 | sint16 | sint32 | sint64 => {
-                let result: c_long = ffi_call(cif_ptr, fun_ptr, args_ptr);
-
-               JsValue::new(result as i32)
-            }
-            uint8 | uint16 | uint32 | uint64 => {
-                let result: c_ulong = ffi_call(cif_ptr, fun_ptr, args_ptr);
-
-                JsValue::new(result as u32)
-            }
-        );
-
-        Ok(pointer)
-    }
+/// `Library.prototype.symbol(name)` returns the address of a symbol.
+pub fn ffi_library_symbol(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, JsValue> {
+    let library = TypedJsObject::<FFILibrary>::try_from(ctx, args.this)?;
+    let name = args.at(0).to_string(ctx)?;
+    let (_, pointer) = library
+        .get(&name)
+        .map_err(|err| JsValue::new(ctx.new_reference_error(err)))?;
+    Ok(pointer_to_value(pointer))
 }
 
-pub fn ffi_library_open(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, JsValue> {
-    let names = args.at(0);
-    if !names.is_jsobject() {
-        let msg = JsString::new(
-            vm,
-            "library_open requires array-like object of library names",
-        );
-        return Err(JsValue::new(JsTypeError::new(vm, msg, None)));
-    }
-    
+/// `Library.prototype.close()` releases the library. It is unloaded once all its functions are collected.
+pub fn ffi_library_close(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, JsValue> {
+    let library = TypedJsObject::<FFILibrary>::try_from(ctx, args.this)?;
+    library.object().data::<FFILibrary>().library = None;
+    Ok(JsValue::encode_undefined_value())
+}
 
-    letroot!(rnames = stack, vec![]);
-    letroot!(names = stack, names.get_jsobject());
-    let len = super::get_length(vm, &mut names)?;
+/// `attach(library, name, returns, params)` used by `Library.prototype.func`.
+pub fn ffi_function_attach(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, JsValue> {
+    let library = TypedJsObject::<FFILibrary>::try_from(ctx, args.at(0))?;
+    let name = args.at(1).to_string(ctx)?;
+    let (library, pointer) = library
+        .get(&name)
+        .map_err(|err| JsValue::new(ctx.new_reference_error(err)))?;
+    let signature = Signature::new(ctx, args.at(2), args.at(3))?;
+    let structure = Structure::new_indexed(ctx, None, false);
+    let mut object = JsObject::new(ctx, &structure, FFIFunction::class(), ObjectTag::Ordinary);
+    *object.data::<FFIFunction>() = ManuallyDrop::new(FFIFunction {
+        _library: library,
+        pointer,
+        signature,
+    });
+    Ok(JsValue::new(object))
+}
 
+/// `call(function, args)` used by the wrappers returned from `Library.prototype.func`.
+pub fn ffi_function_call(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, JsValue> {
+    let function = TypedJsObject::<FFIFunction>::try_from(ctx, args.at(0))?;
+    let mut array = args.at(1).to_object(ctx)?;
+    let len = super::get_length(ctx, &mut array)?;
+    let mut values = Vec::with_capacity(len as usize);
     for i in 0..len {
-        rnames.push(names.get(vm, Symbol::Index(i))?);
+        values.push(array.get(ctx, Symbol::Index(i))?);
     }
+    function.call(ctx, &values)
+}
 
-    let lib = FFILibrary::from_pointers(vm, &rnames)?;
-    let proto = vm.global_object().get(vm, "FFI".intern())?.get_jsobject();
-    let structure = Structure::new_indexed(vm, Some(proto), false);
-    let mut obj = JsObject::new(vm, &structure, FFILibrary::get_class(), ObjectTag::Ordinary);
-    unsafe {
-        (obj.data::<FFILibrary>() as *mut ManuallyDrop<FFILibrary> as *mut FFILibrary).write(lib);
+/// `ffi.callback(returns, params, fn)` creates a C function pointer that calls `fn`.
+pub fn ffi_callback(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, JsValue> {
+    let signature = Signature::new(ctx, args.at(0), args.at(1))?;
+    if signature.returns == Kind::String {
+        return Err(JsValue::new(ctx.new_type_error(
+            "ffi: callbacks cannot return 'string', use 'pointer' to memory owned by C",
+        )));
     }
-    Ok(JsValue::new(obj))
+    let function = args.at(2);
+    if !function.is_callable() {
+        return Err(JsValue::new(
+            ctx.new_type_error("ffi: callback requires a function"),
+        ));
+    }
+    let mut data = Box::new(CallbackData {
+        ctx,
+        function,
+        signature,
+    });
+    let (closure, code) = unsafe {
+        let (closure, code) = closure_alloc();
+        let cif = &mut data.signature.cif as *mut ffi_cif;
+        let result = prep_closure(closure, cif, callback_trampoline, &*data, code);
+        if result.is_err() {
+            closure_free(closure);
+            return Err(JsValue::new(
+                ctx.new_type_error("ffi: failed to prepare callback"),
+            ));
+        }
+        (closure, code)
+    };
+    let mut object = new_with_prototype(ctx, "@@FFICallback", FFICallback::class())?;
+    let pointer = code.as_mut_ptr();
+    *object.data::<FFICallback>() = ManuallyDrop::new(FFICallback {
+        closure,
+        code,
+        data,
+    });
+    object.put(ctx, "pointer".intern(), pointer_to_value(pointer), false)?;
+    Ok(JsValue::new(object))
 }
 
-pub fn ffi_function_attach(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, JsValue> {
-    
-    let func = unsafe {
-        let lib = {
-            let val = args.at(0);
-            if !val.is_jsobject() {
-                let msg = JsString::new(vm, "function_attach requires library object");
-                return Err(JsValue::new(JsTypeError::new(vm, msg, None)));
-            }
-            let val = val.get_jsobject();
-            if !val.is_class(FFILibrary::get_class()) {
-                let msg = JsString::new(vm, "function_attach requires library object");
-                return Err(JsValue::new(JsTypeError::new(vm, msg, None)));
-            }
-            val
-        };
-
-        let name = { args.at(1).to_string(vm)? };
-        letroot!(rnames = stack, vec![]);
-        let args_ = {
-            let names = args.at(2);
-            if !names.is_jsobject() {
-                let msg = JsString::new(
-                    vm,
-                    "function_attach requires array-like object of arguments",
-                );
-                return Err(JsValue::new(JsTypeError::new(vm, msg, None)));
-            }
-
-            letroot!(names = stack, names.get_jsobject());
-            let len = super::get_length(vm, &mut names)?;
-
-            for i in 0..len {
-                rnames.push(names.get(vm, Symbol::Index(i))?);
-            }
-            rnames
-        };
+/// `Callback.prototype.close()` frees the C function pointer.
+pub fn ffi_callback_close(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, JsValue> {
+    let callback = TypedJsObject::<FFICallback>::try_from(ctx, args.this)?;
+    callback.object().data::<FFICallback>().close();
+    Ok(JsValue::encode_undefined_value())
+}
 
-        FFIFunction::attach(vm, lib.data::<FFILibrary>(), &name, &args_, args.at(3))?
+fn offset_pointer(
+    ctx: GcPointer<Context>,
+    args: &Arguments,
+    offset: usize,
+) -> Result<*mut u8, JsValue> {
+    let pointer = value_to_pointer(ctx, args.at(0))?;
+    if pointer.is_null() {
+        return Err(JsValue::new(
+            ctx.new_type_error("ffi: cannot access memory at null pointer"),
+        ));
+    }
+    let offset = if args.at(offset).is_undefined() {
+        0
+    } else {
+        args.at(offset).to_int32(ctx)? as isize
     };
-
-    Ok(JsValue::new(func))
+    Ok(pointer.cast::<u8>().wrapping_offset(offset))
 }
 
-pub fn ffi_function_call(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, JsValue> {
-    
-    vm.heap().defer();
-    let func = unsafe {
-        let val = args.this;
-        if !val.is_jsobject() {
-            let msg = JsString::new(vm, "call requires function object");
-            return Err(JsValue::new(JsTypeError::new(vm, msg, None)));
-        }
-        let val = val.get_jsobject();
-        if !val.is_class(FFIFunction::get_class()) {
-            let msg = JsString::new(vm, "CALL requires FFIFunction object");
-            return Err(JsValue::new(JsTypeError::new(vm, msg, None)));
-        }
-        val
-    };
-    letroot!(rnames = stack, vec![]);
+/// `ffi.read(pointer, type, offset = 0)` reads a value from memory.
+pub fn ffi_read(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, JsValue> {
+    let kind = Kind::parse(ctx, args.at(1))?;
+    let pointer = offset_pointer(ctx, args, 2)?;
+    Ok(unsafe { kind.read(ctx, pointer) })
+}
 
-    let args = {
-        let names = args.at(0);
-        if !names.is_jsobject() {
-            let msg = JsString::new(vm, "function call requires array-like object of arguments");
-            return Err(JsValue::new(JsTypeError::new(vm, msg, None)));
-        }
+/// `ffi.write(pointer, type, value, offset = 0)` writes a value to memory. `string` values are not copied to
+/// the memory, only a pointer to a temporary copy would be, so they are rejected.
+pub fn ffi_write(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, JsValue> {
+    let kind = Kind::parse(ctx, args.at(1))?;
+    if kind == Kind::String || kind == Kind::Void {
+        return Err(JsValue::new(ctx.new_type_error(format!(
+            "ffi: cannot write value of type '{:?}'",
+            kind
+        ))));
+    }
+    let pointer = offset_pointer(ctx, args, 3)?;
+    let mut strings = vec![];
+    let argument = Argument::new(ctx, kind, args.at(2), &mut strings)?;
+    unsafe { argument.write(pointer) };
+    Ok(JsValue::encode_undefined_value())
+}
 
-        letroot!(names = stack, names.get_jsobject());
-        let len = super::get_length(vm, &mut names)?;
+/// `ffi.cstring(pointer)` copies a NUL terminated string from memory.
+pub fn ffi_cstring(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, JsValue> {
+    let pointer = value_to_pointer(ctx, args.at(0))?;
+    if pointer.is_null() {
+        return Ok(JsValue::encode_null_value());
+    }
+    let string = unsafe { CStr::from_ptr(pointer as *const c_char) }.to_string_lossy();
+    Ok(JsValue::new(JsString::new(ctx, string)))
+}
 
-        for i in 0..len {
-            rnames.push(names.get(vm, Symbol::Index(i))?);
-        }
-        rnames
-    };
-    letroot!(res = stack, unsafe {
-        func.data::<FFIFunction>().call(vm, &args)
-    });
+/// `ffi.sizeOf(type)` returns the size of a type in bytes.
+pub fn ffi_size_of(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, JsValue> {
+    Ok(JsValue::new(Kind::parse(ctx, args.at(0))?.size() as u32))
+}
 
-    vm.heap().undefer();
-    // can't just do `*res` since it is internally Pin<&mut Result<JsValue,JsValue>>`
-    match &*res {
-        Ok(val) => Ok(*val),
-        Err(e) => Err(*e),
+#[cfg(test)]
+mod tests {
+    use crate::{options::Options, vm::tests::new_context_with};
+
+    #[test]
+    fn test_ffi() {
+        let (_vm, mut ctx) = new_context_with(Options::default().with_enable_ffi(true));
+        let result = ctx
+            .evalm(
+                None,
+                false,
+                r#"
+                import ffi from "ffi";
+                let libc = ffi.open(["libc.so.6", "libc.dylib", "msvcrt.dll"]);
+                let strlen = libc.func("strlen", "usize", ["string"]);
+                let compare = ffi.callback("i32", ["pointer", "pointer"], function (a, b) {
+                    return ffi.read(a, "i32") - ffi.read(b, "i32");
+                });
+                let qsort = libc.func("qsort", "void", ["buffer", "usize", "usize", "pointer"]);
+                let buffer = new ArrayBuffer(12);
+                ffi.write(buffer, "i32", 3, 0);
+                ffi.write(buffer, "i32", 1, 4);
+                ffi.write(buffer, "i32", 2, 8);
+                qsort(buffer, 3, ffi.sizeOf("i32"), compare);
+                let thrown = ffi.callback("i32", ["pointer", "pointer"], function () { throw "cmp"; });
+                let error;
+                try { qsort(buffer, 3, 4, thrown); } catch (e) { error = e; }
+                globalThis.result = strlen("hello") + ":" + ffi.read(buffer, "i32", 0)
+                    + ffi.read(buffer, "i32", 4) + ffi.read(buffer, "i32", 8) + ":" + error;
+                "#,
+            )
+            .and_then(|_| {
+                let mut global = ctx.global_object();
+                global.get(ctx, "result")
+            })
+            .and_then(|result| result.to_string(ctx))
+            .unwrap_or_else(|_| panic!("ffi test failed"));
+        assert_eq!(result, "5:123:cmp");
     }
 }
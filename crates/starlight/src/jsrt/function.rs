@@ -13,7 +13,6 @@ use crate::{
     vm::context::Context,
     vm::{
         arguments::Arguments,
-        array_storage::ArrayStorage,
         builder::Builtin,
         error::JsTypeError,
         function::*,
@@ -24,12 +23,21 @@ use crate::{
         value::JsValue,
     },
 };
+use swc_common::sync::Lrc;
 
 pub fn function_to_string(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, JsValue> {
     
     let obj = &args.this;
     if obj.is_callable() {
         letroot!(func = stack, obj.to_object(ctx)?);
+        let code = match func.as_function().ty {
+            FuncType::User(ref x) => Some(x.code),
+            FuncType::Generator(ref x) => Some(x.function.as_function().as_vm().code),
+            _ => None,
+        };
+        if let Some(source) = code.as_ref().and_then(|code| code.source_text()) {
+            return Ok(JsValue::new(JsString::new(ctx, source)));
+        }
         let mut slot = Slot::new();
         let mut fmt = "function ".to_string();
         if func.get_own_property_slot(ctx, "name".intern(), &mut slot) {
@@ -61,13 +69,22 @@ pub fn function_prototype(ctx: GcPointer<Context>, args: &Arguments) -> Result<J
         }
     }
     let body = if args.size() == 0 {
-        "{ }".to_owned()
+        String::new()
     } else {
-        format!("{{ {} }}", args.at(args.size() - 1).to_string(ctx)?)
+        args.at(args.size() - 1).to_string(ctx)?
     };
+    let source = format!(
+        "function anonymous({}\n) {{\n{}\n}}",
+        params.join(","),
+        body
+    );
     //let rel_path = unsafe { (*ctx.stack.current).code_block.unwrap().path.clone() };
-    ByteCompiler::compile_code(ctx, &params, ".", body, false)
-        .map_err(|e| JsValue::from(ctx.new_syntax_error(format!("Compile Error {:?}", e))))
+    let function = ByteCompiler::compile_code(ctx, &params, ".", format!("{{ {} }}", body), false)
+        .map_err(|e| e.into_exception(ctx))?;
+    let mut code = function.get_jsobject().as_function().as_vm().code;
+    let len = source.len();
+    code.source = Some((Lrc::new(source), 0..len));
+    Ok(function)
 }
 
 pub fn function_bind(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, JsValue> {
@@ -75,35 +92,8 @@ pub fn function_bind(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValu
     letroot!(obj = stack, args.this);
 
     if obj.is_callable() {
-        letroot!(
-            vals = stack,
-            ArrayStorage::with_size(
-                ctx,
-                if args.size() == 0 {
-                    0
-                } else {
-                    args.size() as u32 - 1
-                },
-                if args.size() == 0 {
-                    0
-                } else {
-                    args.size() as u32 - 1
-                },
-            )
-        );
-        for i in 1..args.size() as u32 {
-            *vals.at_mut(i - 1) = args.at(i as _);
-        }
-        let f = JsFunction::new(
-            ctx,
-            FuncType::Bound(JsBoundFunction {
-                args: vals,
-                this: args.at(0),
-                target: unsafe { obj.get_object().downcast_unchecked() },
-            }),
-            false,
-        );
-
+        let bound_args = args.values.get(1..).unwrap_or(&[]);
+        let f = JsBoundFunction::new(ctx, obj.get_jsobject(), args.at(0), bound_args)?;
         return Ok(JsValue::encode_object_value(f));
     }
     let msg = JsString::new(ctx, "Function.prototype.bind is not generic");
@@ -181,6 +171,22 @@ pub fn function_call(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValu
     )))
 }
 
+/// `Function.prototype[Symbol.hasInstance]`: the prototype chain walk of `instanceof` with `this` as
+/// the constructor.
+pub fn function_has_instance(
+    ctx: GcPointer<Context>,
+    args: &Arguments,
+) -> Result<JsValue, JsValue> {
+    if !args.this.is_callable() {
+        return Ok(JsValue::new(false));
+    }
+    letroot!(func = stack, args.this.get_jsobject());
+    letroot!(func2 = stack, func);
+    func.as_function()
+        .has_instance(&mut func2, ctx, args.at(0))
+        .map(JsValue::new)
+}
+
 impl Builtin for JsFunction {
     fn native_references() -> Vec<usize> {
         vec![
@@ -190,6 +196,7 @@ impl Builtin for JsFunction {
             function_to_string as usize,
             function_apply as usize,
             function_call as usize,
+            function_has_instance as usize,
         ]
     }
     fn init(mut ctx: GcPointer<Context>) -> Result<(), JsValue> {
@@ -226,9 +233,76 @@ impl Builtin for JsFunction {
         def_native_method!(ctx, prototype, apply, function_apply, 0, W | C)?;
         def_native_method!(ctx, prototype, call, function_call, 0, W | C)?;
         def_native_method!(ctx, prototype, toString, function_to_string, 0, W | C)?;
+        let has_instance = JsNativeFunction::new(
+            ctx,
+            "[Symbol.hasInstance]".intern(),
+            function_has_instance,
+            1,
+        );
+        prototype.define_own_property(
+            ctx,
+            "Symbol.hasInstance".intern().private(),
+            &*DataDescriptor::new(JsValue::new(has_instance), NONE),
+            false,
+        )?;
 
         ctx.global_object().put(ctx, name, constructor, false)?;
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::vm::tests::new_context;
+
+    #[test]
+    fn test_bound_function() {
+        let (_vm, mut ctx) = new_context();
+
+        let result = ctx
+            .eval(
+                "function Point(x, y) { this.x = x; this.y = y; }
+                 function sum(a, b, c) { return this.base + a + b + c; }
+                 var BoundPoint = Point.bind(null, 1);
+                 var p = new BoundPoint(2);
+                 var add = sum.bind({ base: 10 }, 1);
+                 [p.x, p.y, p instanceof Point, p instanceof BoundPoint, BoundPoint.name, BoundPoint.length,
+                  add(2, 3), add.name, add.length, sum.bind(null, 1, 2, 3, 4).length,
+                  add.bind(null, 2).name, add.bind(null, 2)(3)].join()",
+            )
+            .ok()
+            .unwrap();
+        assert_eq!(
+            result.get_jsstring().as_str(),
+            "1,2,true,true,bound Point,1,16,bound sum,2,0,bound bound sum,16"
+        );
+        assert!(ctx
+            .eval("function* gen() {} var g = gen.bind(null); new g();")
+            .is_err());
+    }
+
+    #[test]
+    fn test_function_to_string() {
+        let (_vm, mut ctx) = new_context();
+
+        let result = ctx
+            .eval(
+                "function add(a, b) { return a + b; }
+                 var arrow = (x) => x * 2;
+                 var obj = { method() { return 1; } };
+                 class Point { constructor(x) { this.x = x; } }
+                 function* gen() { yield 1; }
+                 [add, arrow, obj.method, Point, gen, new Function('a', 'b', 'return a'), Math.max]
+                     .map(function (f) { return f.toString(); }).join('|')",
+            )
+            .ok()
+            .unwrap();
+        assert_eq!(
+            result.get_jsstring().as_str(),
+            "function add(a, b) { return a + b; }|(x) => x * 2|method() { return 1; }|\
+             class Point { constructor(x) { this.x = x; } }|function* gen() { yield 1; }|\
+             function anonymous(a,b\n) {\nreturn a\n}|function max() { [native code] }"
+        );
+    }
+}
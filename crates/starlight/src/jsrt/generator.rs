@@ -1,4 +1,4 @@
-use std::intrinsics::unlikely;
+use crate::utils::unlikely;
 
 use cometgc::gc_size;
 
@@ -43,6 +43,13 @@ impl Builtin for JsGeneratorFunction {
             JsValue::new(iter),
             false,
         )?;
+        def_native_property!(
+            ctx,
+            generator,
+            "Symbol.toStringTag".intern().private(),
+            JsString::new(ctx, "Generator"),
+            C
+        )?;
         ctx.global_data.generator_prototype = Some(generator);
 
         ctx.global_data.generator_structure =
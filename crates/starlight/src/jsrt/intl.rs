@@ -0,0 +1,305 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+//! Locale aware builtins backed by ICU4X, enabled by the `intl` feature.
+//!
+//! Provides `String.prototype.localeCompare`, `Number.prototype.toLocaleString` and an `Intl` object with
+//! `NumberFormat` and `DateTimeFormat` constructors whose instances have a `format` method. Only the locale
+//! argument is supported, options are ignored. A locale is a BCP 47 tag or an array whose first element is
+//! used, no locale selects the root locale and malformed tags throw `RangeError`.
+//!
+//! ```js
+//! ["b", "a", "ä"].sort((x, y) => x.localeCompare(y, "de"));   // ["a", "ä", "b"]
+//! new Intl.NumberFormat("de").format(1234.5);                  // "1.234,5"
+//! new Intl.DateTimeFormat("en").format(new Date(2020, 5, 1));  // "6/1/20"
+//! ```
+use crate::{
+    jsrt::date::JsDate,
+    prelude::*,
+    vm::{builder::Builtin, context::Context, number::JsNumber},
+};
+use chrono::{DateTime as LocalDateTime, Datelike, Local, TimeZone, Timelike, Utc};
+use fixed_decimal::{FixedDecimal, FloatPrecision};
+use icu::{
+    calendar::DateTime,
+    collator::{Collator, CollatorOptions},
+    datetime::{options::length, DateTimeFormatter},
+    decimal::FixedDecimalFormatter,
+    locid::Locale,
+};
+use std::cmp::Ordering;
+
+pub struct JsIntl;
+
+impl Builtin for JsIntl {
+    fn native_references() -> Vec<usize> {
+        vec![
+            string_locale_compare as _,
+            number_to_locale_string as _,
+            number_format_constructor as _,
+            number_format_format as _,
+            date_time_format_constructor as _,
+            date_time_format_format as _,
+        ]
+    }
+
+    fn init(mut ctx: GcPointer<Context>) -> Result<(), JsValue> {
+        let mut string_prototype = ctx.global_data.string_prototype.unwrap();
+        def_native_method!(
+            ctx,
+            string_prototype,
+            localeCompare,
+            string_locale_compare,
+            1
+        )?;
+        let mut number_prototype = ctx.global_data.number_prototype.unwrap();
+        def_native_method!(
+            ctx,
+            number_prototype,
+            toLocaleString,
+            number_to_locale_string,
+            0
+        )?;
+
+        let mut intl = JsObject::new_empty(ctx);
+        let mut global_object = ctx.global_object();
+        let mut number_format =
+            JsNativeFunction::new(ctx, "NumberFormat".intern(), number_format_constructor, 0);
+        let mut proto = JsObject::new_empty(ctx);
+        def_native_method!(ctx, proto, format, number_format_format, 1)?;
+        number_format.put(ctx, Symbol::PROTOTYPE, JsValue::new(proto), false)?;
+        global_object.put(
+            ctx,
+            "@@NumberFormat".intern().private(),
+            JsValue::new(proto),
+            false,
+        )?;
+        intl.put(
+            ctx,
+            "NumberFormat".intern(),
+            JsValue::new(number_format),
+            false,
+        )?;
+
+        let mut date_time_format = JsNativeFunction::new(
+            ctx,
+            "DateTimeFormat".intern(),
+            date_time_format_constructor,
+            0,
+        );
+        let mut proto = JsObject::new_empty(ctx);
+        def_native_method!(ctx, proto, format, date_time_format_format, 1)?;
+        date_time_format.put(ctx, Symbol::PROTOTYPE, JsValue::new(proto), false)?;
+        global_object.put(
+            ctx,
+            "@@DateTimeFormat".intern().private(),
+            JsValue::new(proto),
+            false,
+        )?;
+        intl.put(
+            ctx,
+            "DateTimeFormat".intern(),
+            JsValue::new(date_time_format),
+            false,
+        )?;
+
+        global_object.put(ctx, "Intl".intern(), JsValue::new(intl), false)?;
+        Ok(())
+    }
+}
+
+/// Read the locale argument of `localeCompare`, `toLocaleString` and the `Intl` constructors.
+fn locale_of(ctx: GcPointer<Context>, value: JsValue) -> Result<Locale, JsValue> {
+    let value = if value.is_jsobject() && value.get_jsobject().tag() == ObjectTag::Array {
+        value.get_jsobject().get(ctx, Symbol::Index(0))?
+    } else {
+        value
+    };
+    if value.is_undefined() {
+        return Ok(Locale::UND);
+    }
+    let tag = value.to_string(ctx)?;
+    tag.parse::<Locale>().map_err(|_| {
+        JsValue::new(ctx.new_range_error(format!("Incorrect locale information provided: {}", tag)))
+    })
+}
+
+fn format_number(ctx: GcPointer<Context>, locale: &Locale, num: f64) -> Result<String, JsValue> {
+    if num.is_nan() {
+        return Ok("NaN".to_string());
+    }
+    if num.is_infinite() {
+        return Ok(if num > 0.0 { "∞" } else { "-∞" }.to_string());
+    }
+    let formatter = FixedDecimalFormatter::try_new(&locale.into(), Default::default())
+        .map_err(|e| JsValue::new(ctx.new_range_error(e.to_string())))?;
+    let mut decimal = FixedDecimal::try_from_f64(num, FloatPrecision::Floating)
+        .map_err(|e| JsValue::new(ctx.new_range_error(e.to_string())))?;
+    // default maximum of fraction digits of `Intl.NumberFormat`
+    decimal.half_even(-3);
+    decimal.trim_end();
+    Ok(formatter.format_to_string(&decimal))
+}
+
+fn format_date(
+    ctx: GcPointer<Context>,
+    locale: &Locale,
+    date: Option<LocalDateTime<Local>>,
+) -> Result<String, JsValue> {
+    let local = match date {
+        Some(local) => local,
+        None => return Err(JsValue::new(ctx.new_range_error("Invalid time value"))),
+    };
+    let date_time = DateTime::try_new_iso_datetime(
+        local.year(),
+        local.month() as u8,
+        local.day() as u8,
+        local.hour() as u8,
+        local.minute() as u8,
+        local.second() as u8,
+    )
+    .map_err(|e| JsValue::new(ctx.new_range_error(e.to_string())))?
+    .to_any();
+    let options = length::Bag::from_date_style(length::Date::Short).into();
+    DateTimeFormatter::try_new(&locale.into(), options)
+        .and_then(|formatter| formatter.format_to_string(&date_time))
+        .map_err(|e| JsValue::new(ctx.new_range_error(e.to_string())))
+}
+
+pub fn string_locale_compare(
+    ctx: GcPointer<Context>,
+    args: &Arguments,
+) -> Result<JsValue, JsValue> {
+    args.this.check_object_coercible(ctx)?;
+    let this = args.this.to_string(ctx)?;
+    let that = args.at(0).to_string(ctx)?;
+    let locale = locale_of(ctx, args.at(1))?;
+    let collator = Collator::try_new(&(&locale).into(), CollatorOptions::new())
+        .map_err(|e| JsValue::new(ctx.new_range_error(e.to_string())))?;
+    Ok(JsValue::new(match collator.compare(&this, &that) {
+        Ordering::Less => -1,
+        Ordering::Equal => 0,
+        Ordering::Greater => 1,
+    }))
+}
+
+pub fn number_to_locale_string(
+    ctx: GcPointer<Context>,
+    args: &Arguments,
+) -> Result<JsValue, JsValue> {
+    let obj = args.this;
+    let num = if obj.is_number() {
+        obj.get_number()
+    } else if obj.is_jsobject() && obj.get_jsobject().is_class(JsNumber::class()) {
+        JsNumber::to_ref(&obj.get_jsobject()).get()
+    } else {
+        return Err(JsValue::new(ctx.new_type_error(
+            "Number.prototype.toLocaleString is not generic function",
+        )));
+    };
+    let locale = locale_of(ctx, args.at(0))?;
+    let string = format_number(ctx, &locale, num)?;
+    Ok(JsValue::new(JsString::new(ctx, string)))
+}
+
+/// Create an object with the prototype stored in private global `name` that remembers the locale argument.
+fn new_formatter(
+    ctx: GcPointer<Context>,
+    args: &Arguments,
+    name: &str,
+) -> Result<JsValue, JsValue> {
+    let locale = locale_of(ctx, args.at(0))?;
+    let proto = ctx
+        .global_object()
+        .get(ctx, name.intern().private())?
+        .to_object(ctx)?;
+    let structure = Structure::new_indexed(ctx, Some(proto), false);
+    let mut formatter = JsObject::new(ctx, &structure, JsObject::class(), ObjectTag::Ordinary);
+    let tag = JsString::new(ctx, locale.to_string());
+    formatter.put(ctx, "@@locale".intern().private(), JsValue::new(tag), false)?;
+    Ok(JsValue::new(formatter))
+}
+
+/// Read the locale remembered by [new_formatter].
+fn formatter_locale(ctx: GcPointer<Context>, this: JsValue, what: &str) -> Result<Locale, JsValue> {
+    if this.is_jsobject() {
+        let locale = this
+            .get_jsobject()
+            .get(ctx, "@@locale".intern().private())?;
+        if locale.is_jsstring() {
+            return locale_of(ctx, locale);
+        }
+    }
+    Err(JsValue::new(ctx.new_type_error(format!(
+        "{}.prototype.format called on incompatible receiver",
+        what
+    ))))
+}
+
+pub fn number_format_constructor(
+    ctx: GcPointer<Context>,
+    args: &Arguments,
+) -> Result<JsValue, JsValue> {
+    new_formatter(ctx, args, "@@NumberFormat")
+}
+
+pub fn number_format_format(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, JsValue> {
+    let locale = formatter_locale(ctx, args.this, "Intl.NumberFormat")?;
+    let num = args.at(0).to_number(ctx)?;
+    let string = format_number(ctx, &locale, num)?;
+    Ok(JsValue::new(JsString::new(ctx, string)))
+}
+
+pub fn date_time_format_constructor(
+    ctx: GcPointer<Context>,
+    args: &Arguments,
+) -> Result<JsValue, JsValue> {
+    new_formatter(ctx, args, "@@DateTimeFormat")
+}
+
+pub fn date_time_format_format(
+    ctx: GcPointer<Context>,
+    args: &Arguments,
+) -> Result<JsValue, JsValue> {
+    let locale = formatter_locale(ctx, args.this, "Intl.DateTimeFormat")?;
+    let date = args.at(0);
+    let date = if date.is_undefined() {
+        Some(Local::now())
+    } else if date.is_jsobject() && date.get_jsobject().is_class(JsDate::class()) {
+        date.get_jsobject().data::<JsDate>().to_local()
+    } else {
+        let time = date.to_number(ctx)?;
+        if JsDate::time_clip(time).is_some() && time.is_finite() {
+            Utc.timestamp_millis_opt(time as i64)
+                .single()
+                .map(|utc| utc.with_timezone(&Local))
+        } else {
+            None
+        }
+    };
+    let string = format_date(ctx, &locale, date)?;
+    Ok(JsValue::new(JsString::new(ctx, string)))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::vm::tests::new_context;
+
+    #[test]
+    fn test_intl() {
+        let (_vm, mut ctx) = new_context();
+        let result = ctx
+            .eval(
+                "[['b', 'a', '\u{e4}'].sort(function (x, y) { return x.localeCompare(y, 'de'); }).join(''),
+                  (1234.5678).toLocaleString('en'), new Intl.NumberFormat(['de']).format(1234.5),
+                  new Intl.DateTimeFormat('en').format(new Date(2020, 5, 1))].join(' ')",
+            )
+            .ok()
+            .unwrap();
+        assert_eq!(
+            result.get_jsstring().as_str(),
+            "a\u{e4}b 1,234.568 1.234,5 6/1/20"
+        );
+        assert!(ctx.eval("'a'.localeCompare('b', 'not a locale!')").is_err());
+    }
+}
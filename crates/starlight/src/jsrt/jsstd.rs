@@ -1,8 +1,12 @@
 //! Small standard library for JS featuring IO and other useful stuff.
 use crate::prelude::*;
-use crate::vm::context::Context;
 use crate::vm::object::JsObject;
+use crate::vm::{context::Context, ModuleKind};
+pub mod assert;
 pub mod file;
+pub mod fs;
+pub mod path;
+pub mod process;
 
 /// Initialize JS std.
 pub fn init_js_std(
@@ -25,3 +29,14 @@ pub fn std_args(ctx: GcPointer<Context>, _args: &Arguments) -> Result<JsValue, J
         .collect::<Vec<_>>();
     Ok(JsValue::new(JsArray::from_slice(ctx, &args)))
 }
+
+/// Register the `fs`, `path` and `process` host modules. They give scripts access to the file system and
+/// environment of the host, so contexts only get them when [Options::host_modules] is set.
+pub fn register_host_modules(mut ctx: GcPointer<Context>) {
+    ctx.add_module("fs", ModuleKind::NativeUninit(fs::init_fs))
+        .unwrap();
+    ctx.add_module("path", ModuleKind::NativeUninit(path::init_path))
+        .unwrap();
+    ctx.add_module("process", ModuleKind::NativeUninit(process::init_process))
+        .unwrap();
+}
@@ -0,0 +1,405 @@
+//! `assert` module: a minimal testing primitive for scripts and the JS-level tests of the engine.
+//!
+//! ```js
+//! import assert from "assert";
+//! assert(1 + 1 === 2);
+//! assert.deepEqual({ a: [1, 2] }, { a: [1, 2] });
+//! assert.throws(() => null.x, TypeError);
+//! await assert.rejects(Promise.reject(new Error("no")), /no/);
+//! ```
+//!
+//! Failed assertions throw an `Error` named `AssertionError` with `actual`, `expected` and `operator`
+//! properties. A `message` argument replaces the generated message, or is thrown itself when it is an error.
+use crate::jsrt::regexp::JsRegExp;
+use crate::prelude::*;
+use crate::vm::{
+    context::Context,
+    error::JsError,
+    function::JsBoundFunction,
+    inspect::{inspect, InspectOptions},
+    operations::{
+        equality::{abstract_equal, deep_equal, same_value},
+        instance_of,
+    },
+    promise::JsPromise,
+};
+
+pub fn init_assert(
+    mut ctx: GcPointer<Context>,
+    mut module: GcPointer<JsObject>,
+) -> Result<(), JsValue> {
+    let defer = ctx.heap().defer();
+    let mut assert = JsNativeFunction::new(ctx, "assert".intern(), assert_ok, 2);
+    module.put(ctx, "@exports".intern(), JsValue::new(assert), false)?;
+    module.put(ctx, "@default".intern(), JsValue::new(assert), false)?;
+    def_native_method!(ctx, assert, ok, assert_ok, 2)?;
+    def_native_method!(ctx, assert, equal, assert_equal, 3)?;
+    def_native_method!(ctx, assert, strictEqual, assert_strict_equal, 3)?;
+    def_native_method!(ctx, assert, deepEqual, assert_deep_equal, 3)?;
+    def_native_method!(ctx, assert, throws, assert_throws, 3)?;
+    def_native_method!(ctx, assert, rejects, assert_rejects, 3)?;
+    drop(defer);
+    Ok(())
+}
+
+fn describe(ctx: GcPointer<Context>, value: JsValue) -> String {
+    inspect(ctx, value, &InspectOptions::default())
+}
+
+/// Create the error thrown by a failed assertion, `message` replaces `generated` unless it is undefined.
+fn assertion_error(
+    ctx: GcPointer<Context>,
+    message: JsValue,
+    generated: String,
+    actual: JsValue,
+    expected: JsValue,
+    operator: &str,
+) -> Result<JsValue, JsValue> {
+    if message.is_jsobject() && message.get_jsobject().is_class(JsError::class()) {
+        return Ok(message);
+    }
+    let message = if message.is_undefined() {
+        generated
+    } else {
+        message.to_string(ctx)?
+    };
+    let msg = JsString::new(ctx, message);
+    let mut error = JsError::new(ctx, msg, None);
+    let name = JsString::new(ctx, "AssertionError");
+    error.put(ctx, "name".intern(), JsValue::new(name), false)?;
+    error.put(ctx, "actual".intern(), actual, false)?;
+    error.put(ctx, "expected".intern(), expected, false)?;
+    let operator = JsString::new(ctx, operator);
+    error.put(ctx, "operator".intern(), JsValue::new(operator), false)?;
+    Ok(JsValue::new(error))
+}
+
+/// `assert(value, message)` and `assert.ok(value, message)` check that `value` is truthy.
+pub fn assert_ok(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, JsValue> {
+    let value = args.at(0);
+    if value.to_boolean() {
+        return Ok(JsValue::encode_undefined_value());
+    }
+    let generated = format!(
+        "The expression evaluated to a falsy value: {}",
+        describe(ctx, value)
+    );
+    Err(assertion_error(
+        ctx,
+        args.at(1),
+        generated,
+        value,
+        JsValue::new(true),
+        "==",
+    )?)
+}
+
+/// `assert.equal(actual, expected, message)` compares with `==`.
+pub fn assert_equal(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, JsValue> {
+    let (actual, expected) = (args.at(0), args.at(1));
+    if abstract_equal(ctx, actual, expected)? {
+        return Ok(JsValue::encode_undefined_value());
+    }
+    let generated = format!(
+        "Expected values to be loosely equal: {} != {}",
+        describe(ctx, actual),
+        describe(ctx, expected)
+    );
+    Err(assertion_error(
+        ctx,
+        args.at(2),
+        generated,
+        actual,
+        expected,
+        "==",
+    )?)
+}
+
+/// `assert.strictEqual(actual, expected, message)` compares with `Object.is`.
+pub fn assert_strict_equal(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, JsValue> {
+    let (actual, expected) = (args.at(0), args.at(1));
+    if same_value(actual, expected) {
+        return Ok(JsValue::encode_undefined_value());
+    }
+    let generated = format!(
+        "Expected values to be strictly equal: {} !== {}",
+        describe(ctx, actual),
+        describe(ctx, expected)
+    );
+    Err(assertion_error(
+        ctx,
+        args.at(2),
+        generated,
+        actual,
+        expected,
+        "strictEqual",
+    )?)
+}
+
+/// `assert.deepEqual(actual, expected, message)` compares structurally, see [deep_equal].
+pub fn assert_deep_equal(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, JsValue> {
+    let (actual, expected) = (args.at(0), args.at(1));
+    if deep_equal(ctx, actual, expected) {
+        return Ok(JsValue::encode_undefined_value());
+    }
+    let generated = format!(
+        "Expected values to be deeply equal: {} and {}",
+        describe(ctx, actual),
+        describe(ctx, expected)
+    );
+    Err(assertion_error(
+        ctx,
+        args.at(2),
+        generated,
+        actual,
+        expected,
+        "deepEqual",
+    )?)
+}
+
+/// Check `error` against `expected` of `throws` and `rejects`: a regular expression that must match the
+/// string form of the error or a constructor the error must be an instance of. Returns failure message.
+fn check_error(
+    ctx: GcPointer<Context>,
+    error: JsValue,
+    expected: JsValue,
+) -> Result<Option<String>, JsValue> {
+    if expected.is_undefined() {
+        return Ok(None);
+    }
+    if expected.is_jsobject() && expected.get_jsobject().is_class(JsRegExp::class()) {
+        let test = expected.get_jsobject().get(ctx, "test".intern())?;
+        if !test.is_callable() {
+            return Err(JsValue::new(
+                ctx.new_type_error("RegExp.prototype.test is not a function"),
+            ));
+        }
+        let string = JsString::new(ctx, error.to_string(ctx)?);
+        let mut argv = [JsValue::new(string)];
+        letroot!(args = stack, Arguments::new(expected, &mut argv));
+        let matched = test
+            .get_jsobject()
+            .as_function_mut()
+            .call(ctx, &mut args, test)?;
+        if matched.to_boolean() {
+            return Ok(None);
+        }
+        return Ok(Some(format!(
+            "The error did not match the regular expression {}",
+            describe(ctx, expected)
+        )));
+    }
+    if expected.is_callable() {
+        if instance_of(ctx, error, expected)? {
+            return Ok(None);
+        }
+        return Ok(Some(format!(
+            "The error is expected to be an instance of {}, got {}",
+            describe(ctx, expected),
+            describe(ctx, error)
+        )));
+    }
+    Err(JsValue::new(ctx.new_type_error(
+        "expected error must be a regular expression or a constructor",
+    )))
+}
+
+/// `assert.throws(fn, expected, message)` checks that calling `fn` throws an error matching `expected`.
+pub fn assert_throws(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, JsValue> {
+    let func = args.at(0);
+    if !func.is_callable() {
+        return Err(JsValue::new(
+            ctx.new_type_error("assert.throws requires a function"),
+        ));
+    }
+    let mut argv = [];
+    letroot!(
+        call_args = stack,
+        Arguments::new(JsValue::encode_undefined_value(), &mut argv)
+    );
+    let error = match func
+        .get_jsobject()
+        .as_function_mut()
+        .call(ctx, &mut call_args, func)
+    {
+        Ok(_) => {
+            return Err(assertion_error(
+                ctx,
+                args.at(2),
+                "Missing expected exception".to_string(),
+                JsValue::encode_undefined_value(),
+                args.at(1),
+                "throws",
+            )?)
+        }
+        Err(error) => error,
+    };
+    match check_error(ctx, error, args.at(1))? {
+        None => Ok(JsValue::encode_undefined_value()),
+        Some(generated) => Err(assertion_error(
+            ctx,
+            args.at(2),
+            generated,
+            error,
+            args.at(1),
+            "throws",
+        )?),
+    }
+}
+
+/// `assert.rejects(promise, expected, message)` returns a promise that is fulfilled when `promise` (or the
+/// promise returned by calling it when it is a function) is rejected with an error matching `expected`.
+pub fn assert_rejects(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, JsValue> {
+    let defer = ctx.heap().defer();
+    let result = JsPromise::new_unresolving(ctx)?;
+    let mut promise = args.at(0);
+    if promise.is_callable() {
+        let mut argv = [];
+        letroot!(
+            call_args = stack,
+            Arguments::new(JsValue::encode_undefined_value(), &mut argv)
+        );
+        promise = match promise
+            .get_jsobject()
+            .as_function_mut()
+            .call(ctx, &mut call_args, promise)
+        {
+            Ok(promise) => promise,
+            Err(error) => {
+                result
+                    .get_jsobject()
+                    .as_promise_mut()
+                    .reject(ctx, result, error)?;
+                return Ok(result);
+            }
+        };
+    }
+    if !promise.is_jsobject() || !promise.get_jsobject().is_class(JsPromise::class()) {
+        let error =
+            ctx.new_type_error("assert.rejects requires a promise or a function returning one");
+        result
+            .get_jsobject()
+            .as_promise_mut()
+            .reject(ctx, result, JsValue::new(error))?;
+        return Ok(result);
+    }
+
+    // reactions get the state through their bound `this`
+    let mut state = JsObject::new_empty(ctx);
+    state.put(ctx, "promise".intern(), result, false)?;
+    state.put(ctx, "expected".intern(), args.at(1), false)?;
+    state.put(ctx, "message".intern(), args.at(2), false)?;
+    let fulfilled = JsNativeFunction::new(ctx, "rejects".intern(), assert_rejects_fulfilled, 1);
+    let fulfilled = JsBoundFunction::new(ctx, fulfilled, JsValue::new(state), &[])?;
+    let rejected = JsNativeFunction::new(ctx, "rejects".intern(), assert_rejects_rejected, 1);
+    let rejected = JsBoundFunction::new(ctx, rejected, JsValue::new(state), &[])?;
+    promise.get_jsobject().as_promise_mut().then(
+        ctx,
+        Some(JsValue::new(fulfilled)),
+        Some(JsValue::new(rejected)),
+        None,
+    )?;
+    drop(defer);
+    Ok(result)
+}
+
+pub fn assert_rejects_fulfilled(
+    ctx: GcPointer<Context>,
+    args: &Arguments,
+) -> Result<JsValue, JsValue> {
+    let mut state = args.this.to_object(ctx)?;
+    let result = state.get(ctx, "promise".intern())?;
+    let expected = state.get(ctx, "expected".intern())?;
+    let message = state.get(ctx, "message".intern())?;
+    let error = assertion_error(
+        ctx,
+        message,
+        "Missing expected rejection".to_string(),
+        JsValue::encode_undefined_value(),
+        expected,
+        "rejects",
+    )?;
+    result
+        .get_jsobject()
+        .as_promise_mut()
+        .reject(ctx, result, error)?;
+    Ok(JsValue::encode_undefined_value())
+}
+
+pub fn assert_rejects_rejected(
+    ctx: GcPointer<Context>,
+    args: &Arguments,
+) -> Result<JsValue, JsValue> {
+    let mut state = args.this.to_object(ctx)?;
+    let result = state.get(ctx, "promise".intern())?;
+    let expected = state.get(ctx, "expected".intern())?;
+    let message = state.get(ctx, "message".intern())?;
+    let error = args.at(0);
+    let mut promise = result.get_jsobject();
+    match check_error(ctx, error, expected)? {
+        None => promise
+            .as_promise_mut()
+            .resolve(ctx, result, JsValue::encode_undefined_value())?,
+        Some(generated) => {
+            let error = assertion_error(ctx, message, generated, error, expected, "rejects")?;
+            promise.as_promise_mut().reject(ctx, result, error)?
+        }
+    }
+    Ok(JsValue::encode_undefined_value())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{options::Options, prelude::*, Platform};
+    use std::{cell::RefCell, rc::Rc};
+
+    #[test]
+    fn test_assert() {
+        Platform::initialize();
+        let jobs = Rc::new(RefCell::new(vec![]));
+        let queue = jobs.clone();
+        let mut vm = Platform::new_runtime(Options::default(), None)
+            .with_async_scheduler(Box::new(move |job| queue.borrow_mut().push(job)));
+        let mut ctx = vm.new_context();
+        ctx.evalm(
+            None,
+            false,
+            r#"
+            import assert from "assert";
+            let log = [];
+            assert(1);
+            assert.ok(true);
+            assert.equal(1, "1");
+            assert.strictEqual(NaN, NaN);
+            assert.deepEqual({ a: [1, 2], b: "c" }, { a: [1, 2], b: "c" });
+            assert.throws(() => null.x, TypeError);
+            assert.throws(() => { throw new Error("boom"); }, /bo+m/);
+            try { assert.strictEqual(1, 2); } catch (e) { log.push(e.name, e.operator); }
+            try { assert.deepEqual([1], [2], "custom"); } catch (e) { log.push(e.message); }
+            try { assert.throws(() => {}); } catch (e) { log.push(e.message); }
+            assert.rejects(Promise.reject(new RangeError("no")), RangeError).then(() => log.push("rejected"));
+            assert.rejects(Promise.resolve(1)).catch(e => log.push(e.message));
+            globalThis.log = log;
+            "#,
+        )
+        .unwrap_or_else(|_| panic!("assert test failed"));
+        loop {
+            let job = {
+                let mut jobs = jobs.borrow_mut();
+                if jobs.is_empty() {
+                    break;
+                }
+                jobs.remove(0)
+            };
+            job(ctx);
+        }
+        let result = ctx
+            .eval("log.join()")
+            .and_then(|log| log.to_string(ctx))
+            .unwrap_or_else(|_| panic!("assert test failed"));
+        assert_eq!(
+            result,
+            "AssertionError,strictEqual,custom,Missing expected exception,rejected,Missing expected rejection"
+        );
+    }
+}
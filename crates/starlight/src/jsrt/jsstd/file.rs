@@ -1,10 +1,10 @@
 use crate::define_jsclass;
 use crate::prelude::*;
+use crate::utils::unlikely;
 use crate::vm::context::Context;
 use crate::{gc::cell::GcPointer, vm::object::JsObject};
 use std::{
     fs::{File, OpenOptions},
-    intrinsics::unlikely,
     io::{Read, Write},
     mem::ManuallyDrop,
 };
@@ -25,7 +25,7 @@ pub(super) fn std_init_file(
     def_native_method!(ctx, proto, close, std_file_close, 0)?;
     ctx.global_object()
         .put(ctx, "@@File".intern().private(), JsValue::new(proto), false)?;
-    ctor.put(ctx, "prototype".intern(), JsValue::new(proto), false)?;
+    ctor.put(ctx, Symbol::PROTOTYPE, JsValue::new(proto), false)?;
     std.put(ctx, "File".intern(), JsValue::new(ctor), false)?;
     Ok(())
 }
@@ -0,0 +1,173 @@
+//! `fs` host module: file system access over `std::fs`, which maps to WASI on `wasm32-wasi`.
+//!
+//! Every function has a synchronous form and one in `fs.promises` that returns a settled promise:
+//!
+//! ```js
+//! import { readFile, promises } from "fs";
+//! let text = readFile("a.txt", "utf8");
+//! promises.readdir(".").then(names => print(names));
+//! ```
+use crate::prelude::*;
+use crate::vm::{
+    array_buffer::JsArrayBuffer, context::Context, object::TypedJsObject, promise::JsPromise,
+};
+use std::{fs, io, time::UNIX_EPOCH};
+
+pub fn init_fs(
+    mut ctx: GcPointer<Context>,
+    mut module: GcPointer<JsObject>,
+) -> Result<(), JsValue> {
+    let defer = ctx.heap().defer();
+    let mut fs = JsObject::new_empty(ctx);
+    module.put(ctx, "@exports".intern(), JsValue::new(fs), false)?;
+    module.put(ctx, "@default".intern(), JsValue::new(fs), false)?;
+    def_native_method!(ctx, fs, readFile, fs_read_file, 2)?;
+    def_native_method!(ctx, fs, writeFile, fs_write_file, 2)?;
+    def_native_method!(ctx, fs, readdir, fs_readdir, 1)?;
+    def_native_method!(ctx, fs, stat, fs_stat, 1)?;
+    def_native_method!(ctx, fs, exists, fs_exists, 1)?;
+
+    let mut promises = JsObject::new_empty(ctx);
+    def_native_method!(ctx, promises, readFile, fs_promises_read_file, 2)?;
+    def_native_method!(ctx, promises, writeFile, fs_promises_write_file, 2)?;
+    def_native_method!(ctx, promises, readdir, fs_promises_readdir, 1)?;
+    def_native_method!(ctx, promises, stat, fs_promises_stat, 1)?;
+    fs.put(ctx, "promises".intern(), JsValue::new(promises), false)?;
+    drop(defer);
+    Ok(())
+}
+
+fn io_error(ctx: GcPointer<Context>, function: &str, path: &str, error: io::Error) -> JsValue {
+    let message = JsString::new(ctx, format!("fs.{}: '{}': {}", function, path, error));
+    JsValue::new(JsError::new(ctx, message, None))
+}
+
+/// Promise already settled with `result`. IO is done synchronously, the promise only delays reactions to the
+/// job queue.
+fn settled(ctx: GcPointer<Context>, result: Result<JsValue, JsValue>) -> Result<JsValue, JsValue> {
+    let promise = JsPromise::new_unresolving(ctx)?;
+    let mut object = promise.get_jsobject();
+    match result {
+        Ok(value) => object.as_promise_mut().resolve(ctx, promise, value)?,
+        Err(error) => object.as_promise_mut().reject(ctx, promise, error)?,
+    }
+    Ok(promise)
+}
+
+/// `fs.readFile(path, encoding?)` returns a string when `encoding` is `"utf8"`, an ArrayBuffer otherwise.
+pub fn fs_read_file(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, JsValue> {
+    let path = args.at(0).to_string(ctx)?;
+    let utf8 = if args.at(1).is_undefined() {
+        false
+    } else {
+        match args.at(1).to_string(ctx)?.to_lowercase().as_str() {
+            "utf8" | "utf-8" => true,
+            encoding => {
+                return Err(JsValue::new(ctx.new_type_error(format!(
+                    "fs.readFile: unsupported encoding '{}'",
+                    encoding
+                ))))
+            }
+        }
+    };
+    let bytes = fs::read(&path).map_err(|e| io_error(ctx, "readFile", &path, e))?;
+    if utf8 {
+        let text = String::from_utf8_lossy(&bytes).into_owned();
+        Ok(JsValue::new(JsString::new(ctx, text)))
+    } else {
+        Ok(JsValue::new(JsArrayBuffer::from_vec(ctx, bytes)?))
+    }
+}
+
+/// `fs.writeFile(path, data)` writes an ArrayBuffer or the UTF-8 encoding of `data` converted to string.
+pub fn fs_write_file(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, JsValue> {
+    let path = args.at(0).to_string(ctx)?;
+    let data = args.at(1);
+    let result = if data.is_jsobject() && data.get_jsobject().is_class(JsArrayBuffer::class()) {
+        let buffer = TypedJsObject::<JsArrayBuffer>::new(data.get_jsobject());
+        fs::write(&path, buffer.data())
+    } else {
+        fs::write(&path, data.to_string(ctx)?)
+    };
+    result.map_err(|e| io_error(ctx, "writeFile", &path, e))?;
+    Ok(JsValue::encode_undefined_value())
+}
+
+/// `fs.readdir(path)` returns sorted names of directory entries.
+pub fn fs_readdir(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, JsValue> {
+    let path = args.at(0).to_string(ctx)?;
+    let mut names = fs::read_dir(&path)
+        .and_then(|entries| {
+            entries
+                .map(|entry| Ok(entry?.file_name().to_string_lossy().into_owned()))
+                .collect::<io::Result<Vec<_>>>()
+        })
+        .map_err(|e| io_error(ctx, "readdir", &path, e))?;
+    names.sort();
+    let names = names
+        .into_iter()
+        .map(|name| JsValue::new(JsString::new(ctx, name)))
+        .collect::<Vec<_>>();
+    Ok(JsValue::new(JsArray::from_slice(ctx, &names)))
+}
+
+/// `fs.stat(path)` returns `{ type, size, mtimeMs }` where `type` is `"file"`, `"directory"` or `"other"`.
+pub fn fs_stat(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, JsValue> {
+    let path = args.at(0).to_string(ctx)?;
+    let metadata = fs::metadata(&path).map_err(|e| io_error(ctx, "stat", &path, e))?;
+    let kind = if metadata.is_file() {
+        "file"
+    } else if metadata.is_dir() {
+        "directory"
+    } else {
+        "other"
+    };
+    let mtime = metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map(|duration| duration.as_secs_f64() * 1000.0)
+        .unwrap_or(f64::NAN);
+    let mut stat = JsObject::new_empty(ctx);
+    stat.put(
+        ctx,
+        "type".intern(),
+        JsValue::new(JsString::new(ctx, kind)),
+        false,
+    )?;
+    stat.put(
+        ctx,
+        "size".intern(),
+        JsValue::new(metadata.len() as f64),
+        false,
+    )?;
+    stat.put(ctx, "mtimeMs".intern(), JsValue::new(mtime), false)?;
+    Ok(JsValue::new(stat))
+}
+
+pub fn fs_exists(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, JsValue> {
+    let path = args.at(0).to_string(ctx)?;
+    Ok(JsValue::new(std::path::Path::new(&path).exists()))
+}
+
+pub fn fs_promises_read_file(
+    ctx: GcPointer<Context>,
+    args: &Arguments,
+) -> Result<JsValue, JsValue> {
+    settled(ctx, fs_read_file(ctx, args))
+}
+
+pub fn fs_promises_write_file(
+    ctx: GcPointer<Context>,
+    args: &Arguments,
+) -> Result<JsValue, JsValue> {
+    settled(ctx, fs_write_file(ctx, args))
+}
+
+pub fn fs_promises_readdir(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, JsValue> {
+    settled(ctx, fs_readdir(ctx, args))
+}
+
+pub fn fs_promises_stat(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, JsValue> {
+    settled(ctx, fs_stat(ctx, args))
+}
@@ -0,0 +1,153 @@
+//! `path` host module: manipulation of paths in the format of the host.
+//!
+//! All functions except `resolve` are lexical and never touch the file system.
+use crate::prelude::*;
+use crate::vm::context::Context;
+use std::path::{Component, Path, PathBuf, MAIN_SEPARATOR};
+
+pub fn init_path(
+    mut ctx: GcPointer<Context>,
+    mut module: GcPointer<JsObject>,
+) -> Result<(), JsValue> {
+    let defer = ctx.heap().defer();
+    let mut path = JsObject::new_empty(ctx);
+    module.put(ctx, "@exports".intern(), JsValue::new(path), false)?;
+    module.put(ctx, "@default".intern(), JsValue::new(path), false)?;
+    def_native_method!(ctx, path, join, path_join, 2)?;
+    def_native_method!(ctx, path, resolve, path_resolve, 1)?;
+    def_native_method!(ctx, path, normalize, path_normalize, 1)?;
+    def_native_method!(ctx, path, dirname, path_dirname, 1)?;
+    def_native_method!(ctx, path, basename, path_basename, 2)?;
+    def_native_method!(ctx, path, extname, path_extname, 1)?;
+    def_native_method!(ctx, path, isAbsolute, path_is_absolute, 1)?;
+    let sep = JsString::new(ctx, MAIN_SEPARATOR.to_string());
+    path.put(ctx, "sep".intern(), JsValue::new(sep), false)?;
+    let delimiter = JsString::new(ctx, if cfg!(windows) { ";" } else { ":" });
+    path.put(ctx, "delimiter".intern(), JsValue::new(delimiter), false)?;
+    drop(defer);
+    Ok(())
+}
+
+/// Remove `.` components and resolve `..` against preceding components. `..` is kept at the start of relative
+/// paths and dropped after the root.
+fn normalize(path: &Path) -> PathBuf {
+    let mut result = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match result.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    result.pop();
+                }
+                Some(Component::RootDir) | Some(Component::Prefix(_)) => {}
+                _ => result.push(".."),
+            },
+            component => result.push(component.as_os_str()),
+        }
+    }
+    if result.as_os_str().is_empty() {
+        result.push(".");
+    }
+    result
+}
+
+fn path_arguments(ctx: GcPointer<Context>, args: &Arguments) -> Result<Vec<String>, JsValue> {
+    (0..args.size())
+        .map(|i| args.at(i).to_string(ctx))
+        .collect()
+}
+
+fn path_value(ctx: GcPointer<Context>, path: impl AsRef<Path>) -> JsValue {
+    JsValue::new(JsString::new(ctx, path.as_ref().to_string_lossy()))
+}
+
+/// `path.join(...parts)` concatenates all parts with the separator and normalizes the result.
+pub fn path_join(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, JsValue> {
+    let parts = path_arguments(ctx, args)?;
+    let joined = parts
+        .iter()
+        .filter(|part| !part.is_empty())
+        .cloned()
+        .collect::<Vec<_>>()
+        .join(&MAIN_SEPARATOR.to_string());
+    Ok(path_value(ctx, normalize(Path::new(&joined))))
+}
+
+/// `path.resolve(...parts)` applies parts from left to right to the current directory, an absolute part replaces
+/// everything before it.
+pub fn path_resolve(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, JsValue> {
+    let mut path = std::env::current_dir()
+        .map_err(|e| JsValue::new(ctx.new_reference_error(format!("path.resolve: {}", e))))?;
+    for part in path_arguments(ctx, args)? {
+        path.push(part);
+    }
+    Ok(path_value(ctx, normalize(&path)))
+}
+
+pub fn path_normalize(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, JsValue> {
+    let path = args.at(0).to_string(ctx)?;
+    Ok(path_value(ctx, normalize(Path::new(&path))))
+}
+
+pub fn path_dirname(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, JsValue> {
+    let path = args.at(0).to_string(ctx)?;
+    let path = Path::new(&path);
+    let dirname = match path.parent() {
+        Some(parent) if parent.as_os_str().is_empty() => Path::new("."),
+        Some(parent) => parent,
+        None if path.has_root() => path,
+        None => Path::new("."),
+    };
+    Ok(path_value(ctx, dirname))
+}
+
+/// `path.basename(path, ext?)` returns the last component of `path` without `ext` suffix.
+pub fn path_basename(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, JsValue> {
+    let path = args.at(0).to_string(ctx)?;
+    let mut basename = Path::new(&path)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    if !args.at(1).is_undefined() {
+        let ext = args.at(1).to_string(ctx)?;
+        if basename.len() > ext.len() && basename.ends_with(&ext) {
+            basename.truncate(basename.len() - ext.len());
+        }
+    }
+    Ok(JsValue::new(JsString::new(ctx, basename)))
+}
+
+/// `path.extname(path)` returns the extension of the last component including the dot, or an empty string.
+pub fn path_extname(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, JsValue> {
+    let path = args.at(0).to_string(ctx)?;
+    let extname = Path::new(&path)
+        .extension()
+        .map(|ext| format!(".{}", ext.to_string_lossy()))
+        .unwrap_or_default();
+    Ok(JsValue::new(JsString::new(ctx, extname)))
+}
+
+pub fn path_is_absolute(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, JsValue> {
+    let path = args.at(0).to_string(ctx)?;
+    Ok(JsValue::new(Path::new(&path).is_absolute()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::normalize;
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn test_normalize() {
+        let cases = [
+            ("a/./b/../c", "a/c"),
+            ("/a/../../b", "/b"),
+            ("../a/..", ".."),
+            ("a/..", "."),
+            ("", "."),
+        ];
+        for (path, expected) in cases.iter() {
+            assert_eq!(normalize(Path::new(path)), PathBuf::from(expected));
+        }
+    }
+}
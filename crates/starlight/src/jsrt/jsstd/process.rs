@@ -0,0 +1,54 @@
+//! `process` host module: arguments, environment and working directory of the host process.
+use crate::prelude::*;
+use crate::vm::context::Context;
+
+pub fn init_process(
+    mut ctx: GcPointer<Context>,
+    mut module: GcPointer<JsObject>,
+) -> Result<(), JsValue> {
+    let defer = ctx.heap().defer();
+    let mut process = JsObject::new_empty(ctx);
+    module.put(ctx, "@exports".intern(), JsValue::new(process), false)?;
+    module.put(ctx, "@default".intern(), JsValue::new(process), false)?;
+    def_native_method!(ctx, process, exit, process_exit, 1)?;
+    def_native_method!(ctx, process, cwd, process_cwd, 0)?;
+
+    let argv = std::env::args()
+        .map(|arg| JsValue::new(JsString::new(ctx, arg)))
+        .collect::<Vec<_>>();
+    let argv = JsArray::from_slice(ctx, &argv);
+    process.put(ctx, "argv".intern(), JsValue::new(argv), false)?;
+
+    // Snapshot taken when the module is first imported, later changes of the environment are not visible.
+    let mut env = JsObject::new_empty(ctx);
+    for (key, value) in std::env::vars_os() {
+        let value = JsString::new(ctx, value.to_string_lossy());
+        env.put(
+            ctx,
+            key.to_string_lossy().intern(),
+            JsValue::new(value),
+            false,
+        )?;
+    }
+    process.put(ctx, "env".intern(), JsValue::new(env), false)?;
+
+    let platform = JsString::new(ctx, std::env::consts::OS);
+    process.put(ctx, "platform".intern(), JsValue::new(platform), false)?;
+    drop(defer);
+    Ok(())
+}
+
+/// `process.exit(code?)` terminates the host process without running pending jobs.
+pub fn process_exit(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, JsValue> {
+    let code = if args.at(0).is_undefined() {
+        0
+    } else {
+        args.at(0).to_int32(ctx)?
+    };
+    std::process::exit(code)
+}
+
+pub fn process_cwd(ctx: GcPointer<Context>, _args: &Arguments) -> Result<JsValue, JsValue> {
+    let dir = std::env::current_dir().or_throw(ctx)?;
+    Ok(JsValue::new(JsString::new(ctx, dir.to_string_lossy())))
+}
@@ -1,5 +1,5 @@
+use crate::utils::unlikely;
 use core::f64;
-use std::intrinsics::unlikely;
 
 use crate::{prelude::*, vm::{builder::{Builtin}, context::Context}};
 pub fn math_abs(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, JsValue> {
@@ -309,6 +309,13 @@ impl Builtin for JsMath {
         def_native_property!(ctx, math, SQRT1_2, f64::consts::FRAC_1_SQRT_2)?;
         def_native_property!(ctx, math, SQRT2, f64::consts::SQRT_2)?;
         def_native_property!(ctx, math, PI, std::f64::consts::PI)?;
+        def_native_property!(
+            ctx,
+            math,
+            "Symbol.toStringTag".intern().private(),
+            JsString::new(ctx, "Math"),
+            C
+        )?;
 
         let mut global_object = ctx.global_object();
 
@@ -3,7 +3,7 @@ use num::traits::float::FloatCore;
 use crate::{
     jsrt::global,
     prelude::*,
-    vm::{builder::Builtin, context::Context, number::JsNumber},
+    vm::{builder::Builtin, context::Context, number::JsNumber, value::number_to_string},
 };
 pub fn number_value_of(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, JsValue> {
     let obj = args.this;
@@ -235,7 +235,7 @@ pub fn number_to_string(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsV
             radix = first.to_int32(ctx)?;
         }
         if radix == 10 {
-            return Ok(JsValue::new(JsString::new(ctx, num.to_string())));
+            return Ok(JsValue::new(JsString::new(ctx, number_to_string(num))));
         }
         if (2..=36).contains(&radix) {
             if radix != 10 {
@@ -258,7 +258,7 @@ pub fn number_to_string(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsV
             return Err(JsValue::new(JsRangeError::new(ctx, msg, None)));
         }
     } else {
-        return Ok(JsValue::new(JsString::new(ctx, num.to_string())));
+        return Ok(JsValue::new(JsString::new(ctx, number_to_string(num))));
     }
 }
 
@@ -279,7 +279,8 @@ pub(crate) fn to_native_string_radix(mut value: f64, radix: u8) -> String {
     let mut buffer: [u8; BUF_SIZE] = [0; BUF_SIZE];
     let (int_buf, frac_buf) = buffer.split_at_mut(BUF_SIZE / 2);
     let mut fraction_cursor = 0;
-    let negative = value.is_sign_negative();
+    // `-0` prints as `0` in every radix.
+    let negative = value < 0.0;
     if negative {
         value = -value
     }
@@ -322,7 +323,7 @@ pub(crate) fn to_native_string_radix(mut value: f64, radix: u8) -> String {
                     } else {
                         let c: u8 = frac_buf[fraction_cursor];
                         // Reconstruct digit.
-                        let digit_0 = (c as char).to_digit(10).unwrap();
+                        let digit_0 = (c as char).to_digit(radix as u32).unwrap();
                         if digit_0 + 1 >= radix as u32 {
                             continue;
                         }
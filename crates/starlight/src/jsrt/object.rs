@@ -1,4 +1,4 @@
-use std::intrinsics::unlikely;
+use crate::utils::unlikely;
 
 use crate::{
     constant::S_OBJECT,
@@ -39,6 +39,7 @@ pub fn object_get_prototype_of(
     })
 }
 
+/// Object.prototype.toString: https://tc39.es/ecma262/#sec-object.prototype.tostring
 pub fn object_to_string(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, JsValue> {
     let this_binding = args.this;
 
@@ -53,9 +54,24 @@ pub fn object_to_string(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsV
             "[object Null]",
         )));
     }
-    let obj = this_binding.to_object(ctx)?;
-
-    let s = format!("[object {}]", obj.class.name);
+    letroot!(obj = stack, this_binding.to_object(ctx)?);
+
+    let builtin_tag = match obj.tag() {
+        ObjectTag::Array => "Array",
+        ObjectTag::NormalArguments | ObjectTag::StrictArguments => "Arguments",
+        ObjectTag::Number => "Number",
+        _ if obj.is_callable() => "Function",
+        _ => match obj.class.name {
+            "Error" | "Boolean" | "String" | "Date" | "RegExp" => obj.class.name,
+            _ => "Object",
+        },
+    };
+    let tag = obj.get(ctx, "Symbol.toStringTag".intern().private())?;
+    let s = if tag.is_jsstring() {
+        format!("[object {}]", tag.get_string().as_str())
+    } else {
+        format!("[object {}]", builtin_tag)
+    };
     Ok(JsValue::encode_object_value(JsString::new(ctx, s)))
 }
 
@@ -262,8 +278,7 @@ pub fn object_keys(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue,
             letroot!(arr = stack, JsArray::new(ctx, names.len() as _));
 
             for (i, name) in names.iter().enumerate() {
-                let desc = ctx.description(*name);
-                let name = JsString::new(ctx, desc);
+                let name = JsString::from_key(ctx, *name);
                 arr.put(ctx, Symbol::Index(i as _), JsValue::new(name), false)?;
             }
             return Ok(JsValue::new(arr));
@@ -518,3 +533,33 @@ impl Builtin for JsObject {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::vm::tests::new_context;
+
+    #[test]
+    fn test_object_prototype_to_string() {
+        let (_vm, mut ctx) = new_context();
+
+        let result = ctx
+            .eval(
+                "var tagged = {};
+                 Object.defineProperty(tagged, Symbol.toStringTag, { value: 'Custom' });
+                 var toString = Object.prototype.toString;
+                 [toString.call([]), toString.call(function () {}), toString.call(null),
+                  toString.call(undefined), toString.call(1), toString.call('s'),
+                  toString.call(true), toString.call(new TypeError('x')), toString.call(tagged),
+                  toString.call({}), toString.call(Math),
+                  (function () { return toString.call(arguments); })()].join()",
+            )
+            .ok()
+            .unwrap();
+        assert_eq!(
+            result.to_string(ctx).ok().unwrap(),
+            "[object Array],[object Function],[object Null],[object Undefined],[object Number],\
+             [object String],[object Boolean],[object Error],[object Custom],[object Object],\
+             [object Math],[object Arguments]"
+        );
+    }
+}
@@ -2,15 +2,19 @@ use crate::constant::S_PROMISE;
 use crate::gc::cell::GcPointer;
 use crate::prelude::JsArray;
 use crate::vm::arguments::Arguments;
+use crate::vm::attributes::*;
 use crate::vm::builder::Builtin;
 use crate::vm::class::JsClass;
 use crate::vm::context::Context;
-use crate::vm::function::JsNativeFunction;
+use crate::vm::function::{JsClosureFunction, JsNativeFunction};
 use crate::vm::object::JsObject;
+use crate::vm::operations::{construct, species_constructor};
 use crate::vm::promise::{JsPromise, TrackingMode};
+use crate::vm::property_descriptor::{AccessorDescriptor, DataDescriptor};
 use crate::vm::string::JsString;
 use crate::vm::symbol_table::Internable;
 use crate::vm::value::JsValue;
+use std::{cell::Cell, rc::Rc};
 
 pub fn promise_constructor(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, JsValue> {
     let func = args.at(0);
@@ -51,6 +55,66 @@ fn with_prom<
     }
 }
 
+/// Create a promise with `constructor` that settles like `promise`. `promise` is returned as is when
+/// `constructor` is `Promise` or not a function, e.g. when a static method is called without receiver.
+fn promise_of(
+    ctx: GcPointer<Context>,
+    constructor: JsValue,
+    promise: JsValue,
+) -> Result<JsValue, JsValue> {
+    if !constructor.is_callable()
+        || ctx.global_data.promise_constructor.map(JsValue::new) == Some(constructor)
+    {
+        return Ok(promise);
+    }
+    let functions = Rc::new(Cell::new(None));
+    let slot = functions.clone();
+    let executor = JsClosureFunction::new(
+        ctx,
+        "executor".intern(),
+        move |ctx, args| {
+            if slot.get().is_some() {
+                return Err(JsValue::new(
+                    ctx.new_type_error("Promise executor has already been invoked"),
+                ));
+            }
+            slot.set(Some((args.at(0), args.at(1))));
+            Ok(JsValue::encode_undefined_value())
+        },
+        2,
+    );
+    // the resolving functions are only held by `functions` until they are passed to `then`
+    let defer = ctx.heap().defer();
+    let result = construct(ctx, constructor, &mut [JsValue::new(executor)])?;
+    let (resolve, reject) = match functions.take() {
+        Some((resolve, reject)) if resolve.is_callable() && reject.is_callable() => {
+            (resolve, reject)
+        }
+        _ => {
+            return Err(JsValue::new(ctx.new_type_error(
+                "Promise resolve or reject function is not callable",
+            )))
+        }
+    };
+    promise
+        .get_jsobject()
+        .as_promise_mut()
+        .then(ctx, Some(resolve), Some(reject), None)?;
+    drop(defer);
+    Ok(result)
+}
+
+/// The constructor for promises derived from `this` by `then`, `catch` and `finally`.
+fn promise_species(ctx: GcPointer<Context>, this: JsValue) -> Result<JsValue, JsValue> {
+    let default = JsValue::new(ctx.global_data.promise_constructor.unwrap());
+    species_constructor(ctx, this.get_jsobject(), default)
+}
+
+/// `get Promise[Symbol.species]`, the constructor used for promises derived from `this`.
+pub fn promise_get_species(_ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, JsValue> {
+    Ok(args.this)
+}
+
 pub fn promise_then(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, JsValue> {
     // onResolved and onRejected arg, both optional but callable if has val
     with_prom(ctx, args, |ctx, args, prom| {
@@ -78,7 +142,9 @@ pub fn promise_then(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue
             on_rejected_opt = Some(rejected);
         }
 
-        prom.then(ctx, on_resolved_opt, on_rejected_opt, None)
+        let constructor = promise_species(ctx, args.this)?;
+        let promise = prom.then(ctx, on_resolved_opt, on_rejected_opt, None)?;
+        promise_of(ctx, constructor, promise)
     })
 }
 
@@ -92,7 +158,9 @@ pub fn promise_catch(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValu
                     "rejected argument is not a Function",
                 )))
             } else {
-                prom.then(ctx, None, Some(rejected), None)
+                let constructor = promise_species(ctx, args.this)?;
+                let promise = prom.then(ctx, None, Some(rejected), None)?;
+                promise_of(ctx, constructor, promise)
             }
         } else {
             Err(JsValue::encode_object_value(JsString::new(
@@ -113,7 +181,9 @@ pub fn promise_finally(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsVa
                     "finally argument is not a Function",
                 )))
             } else {
-                prom.then(ctx, None, None, Some(finally))
+                let constructor = promise_species(ctx, args.this)?;
+                let promise = prom.then(ctx, None, None, Some(finally))?;
+                promise_of(ctx, constructor, promise)
             }
         } else {
             Err(JsValue::encode_object_value(JsString::new(
@@ -205,7 +275,8 @@ pub fn promise_static_race(ctx: GcPointer<Context>, args: &Arguments) -> Result<
         )));
     }
 
-    JsPromise::new_tracking(ctx, TrackingMode::Race, args.at(0))
+    let promise = JsPromise::new_tracking(ctx, TrackingMode::Race, args.at(0))?;
+    promise_of(ctx, args.this, promise)
 }
 
 pub fn promise_static_all(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, JsValue> {
@@ -219,7 +290,8 @@ pub fn promise_static_all(ctx: GcPointer<Context>, args: &Arguments) -> Result<J
         )));
     }
 
-    JsPromise::new_tracking(ctx, TrackingMode::All, args.at(0))
+    let promise = JsPromise::new_tracking(ctx, TrackingMode::All, args.at(0))?;
+    promise_of(ctx, args.this, promise)
 }
 
 pub fn promise_static_all_settled(
@@ -236,7 +308,8 @@ pub fn promise_static_all_settled(
         )));
     }
 
-    JsPromise::new_tracking(ctx, TrackingMode::AllSettled, args.at(0))
+    let promise = JsPromise::new_tracking(ctx, TrackingMode::AllSettled, args.at(0))?;
+    promise_of(ctx, args.this, promise)
 }
 
 pub fn promise_static_any(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, JsValue> {
@@ -250,7 +323,8 @@ pub fn promise_static_any(ctx: GcPointer<Context>, args: &Arguments) -> Result<J
         )));
     }
 
-    JsPromise::new_tracking(ctx, TrackingMode::Any, args.at(0))
+    let promise = JsPromise::new_tracking(ctx, TrackingMode::Any, args.at(0))?;
+    promise_of(ctx, args.this, promise)
 }
 
 impl Builtin for JsPromise {
@@ -268,6 +342,7 @@ impl Builtin for JsPromise {
             promise_static_all as _,
             promise_static_all_settled as _,
             promise_static_any as _,
+            promise_get_species as _,
         ]
     }
     fn init(mut ctx: GcPointer<Context>) -> Result<(), JsValue> {
@@ -283,6 +358,13 @@ impl Builtin for JsPromise {
         def_native_method!(ctx, prototype, finally, promise_finally, 1)?;
         def_native_method!(ctx, prototype, resolve, promise_resolve, 1)?;
         def_native_method!(ctx, prototype, reject, promise_reject, 1)?;
+        def_native_property!(
+            ctx,
+            prototype,
+            "Symbol.toStringTag".intern().private(),
+            JsString::new(ctx, "Promise"),
+            C
+        )?;
         // statics
         def_native_method!(ctx, constructor, all, promise_static_all, 1)?;
         def_native_method!(ctx, constructor, allSettled, promise_static_all_settled, 1)?;
@@ -290,10 +372,20 @@ impl Builtin for JsPromise {
         def_native_method!(ctx, constructor, race, promise_static_race, 1)?;
         def_native_method!(ctx, constructor, reject, promise_static_reject, 1)?;
         def_native_method!(ctx, constructor, resolve, promise_static_resolve, 1)?;
+        let species =
+            JsNativeFunction::new(ctx, "get [Symbol.species]".intern(), promise_get_species, 0);
+        def_native_getter!(
+            ctx,
+            constructor,
+            "Symbol.species".intern().private(),
+            species,
+            C
+        )?;
 
         def_native_property!(ctx, constructor, prototype, prototype)?;
         def_native_property!(ctx, prototype, constructor, constructor)?;
         def_native_property!(ctx, global_object, Promise, constructor)?;
+        ctx.global_data.promise_constructor = Some(constructor);
 
         Ok(())
     }
@@ -1,12 +1,10 @@
 use crate::constant::S_CONSTURCTOR;
 use crate::js_method_table;
+use crate::utils::unlikely;
 use crate::vm::builder::Builtin;
 use crate::{define_jsclass, prelude::*, vm::context::Context};
 use regress::Regex;
-use std::{
-    intrinsics::unlikely,
-    mem::{size_of, ManuallyDrop},
-};
+use std::mem::{size_of, ManuallyDrop};
 
 /// The internal representation on a `RegExp` object.
 #[derive(Debug, Clone)]
@@ -141,14 +139,9 @@ impl Builtin for JsRegExp {
 
         let mut constructor = JsNativeFunction::new(ctx, "RegExp".intern(), regexp_constructor, 2);
 
-        constructor.put(ctx, "prototype".intern(), JsValue::new(prototype), false)?;
+        constructor.put(ctx, Symbol::PROTOTYPE, JsValue::new(prototype), false)?;
 
-        prototype.put(
-            ctx,
-            S_CONSTURCTOR.intern(),
-            JsValue::new(constructor),
-            false,
-        )?;
+        prototype.put(ctx, Symbol::CONSTRUCTOR, JsValue::new(constructor), false)?;
         def_native_method!(ctx, constructor, ___splitFast, regexp_split_fast, 3)?;
         def_native_method!(ctx, prototype, exec, regexp_exec, 1)?;
         def_native_method!(ctx, prototype, test, regexp_test, 1)?;
@@ -171,53 +164,93 @@ impl Builtin for JsRegExp {
     }
 }
 
-pub fn regexp_split_fast(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, JsValue> {
+pub fn regexp_split_fast(
+    mut ctx: GcPointer<Context>,
+    args: &Arguments,
+) -> Result<JsValue, JsValue> {
     if unlikely(!args.at(0).is_jsobject()) {
         return Err(JsValue::new(ctx.new_type_error(
             "Regex.@@splitFast requires regexp object as first argument",
         )));
     }
     let re = args.at(0).get_jsobject();
-    let regexp = re.data::<JsRegExp>();
     if unlikely(!re.is_class(JsRegExp::class())) {
         return Err(JsValue::new(ctx.new_type_error(
             "Regex.@@splitFast requires regexp object as first argument",
         )));
     }
+    let regexp = re.data::<JsRegExp>();
     let input = args.at(1).to_string(ctx)?;
     let limit = if args.at(2).is_undefined() {
-        u32::MAX - 1
+        u32::MAX
     } else {
         args.at(2).to_uint32(ctx)?
     };
 
-    let mut result = JsArray::new(ctx, 0);
-    //let mut result_length = 0;
-    // let input_size = input.len();
+    // pieces are only referenced from the vector until the array is created
+    let defer = ctx.heap().defer();
+    let values = split_with_matcher(ctx, &regexp.matcher, &input, limit as usize);
+    let result = JsArray::from_slice(ctx, &values);
+    drop(defer);
+    Ok(JsValue::new(result))
+}
 
-    //let mut position = 0;
+/// Split `input` at matches of `matcher` as `RegExp.prototype[Symbol.split]` does: captures of each match
+/// are included after the piece preceding it, empty matches at the end of the previous piece are skipped and
+/// at most `limit` values are returned.
+fn split_with_matcher(
+    ctx: GcPointer<Context>,
+    matcher: &Regex,
+    input: &str,
+    limit: usize,
+) -> Vec<JsValue> {
+    let mut values = vec![];
     if limit == 0 {
-        return Ok(JsValue::new(result));
+        return values;
     }
-
     if input.is_empty() {
-        let match_result = regexp.matcher.find(&input);
-        if match_result.is_none() {
-            let str = JsString::new(ctx, input);
-            result.put(ctx, Symbol::Index(0), JsValue::new(str), false)?;
+        if matcher.find(input).is_none() {
+            values.push(JsValue::new(JsString::new(ctx, input)));
         }
-        return Ok(JsValue::new(result));
+        return values;
     }
-    //let mut match_position = position;
-    // let regexp_is_sticky = regexp.sticky;
-    //let regexp_is_unicode = regexp.unicode;
-    let iter = input.splitn(limit as _, RegexPattern(&regexp.matcher));
-    for (i, r) in iter.enumerate() {
-        let str = JsString::new(ctx, r);
-        result.put(ctx, Symbol::Index(i as _), JsValue::new(str), false)?;
+
+    let size = input.len();
+    let mut position = 0;
+    let mut match_position = 0;
+    while match_position < size {
+        let m = match matcher.find_from(input, match_position).next() {
+            Some(m) if m.start() < size => m,
+            _ => break,
+        };
+        if m.end() == position {
+            match_position =
+                m.start() + input[m.start()..].chars().next().map_or(1, char::len_utf8);
+            continue;
+        }
+        values.push(JsValue::new(JsString::new(
+            ctx,
+            &input[position..m.start()],
+        )));
+        if values.len() == limit {
+            return values;
+        }
+        position = m.end();
+        for i in 1..=m.captures.len() {
+            values.push(match m.group(i) {
+                Some(range) => JsValue::new(JsString::new(ctx, &input[range])),
+                None => JsValue::encode_undefined_value(),
+            });
+            if values.len() == limit {
+                return values;
+            }
+        }
+        match_position = position;
     }
-    Ok(JsValue::new(result))
+    values.push(JsValue::new(JsString::new(ctx, &input[position..])));
+    values
 }
+
 pub fn regexp_constructor(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, JsValue> {
     let proto = ctx.global_data.regexp_prototype.unwrap();
     let structure = Structure::new_indexed(ctx, Some(proto), false);
@@ -1,5 +1,4 @@
-use regress::Regex;
-
+use crate::utils::unlikely;
 use crate::{
     gc::cell::GcPointer,
     vm::{
@@ -12,16 +11,15 @@ use crate::{
         error::{JsRangeError, JsTypeError},
         function::JsNativeFunction,
         property_descriptor::DataDescriptor,
-        string::{JsString, JsStringObject},
+        string::{JsString, JsStringObject, StringBuilder},
         structure::Structure,
         symbol_table::{Internable, Symbol},
         value::*,
     },
 };
 use std::{
-    char::{decode_utf16, from_u32},
+    char::decode_utf16,
     cmp::{max, min},
-    intrinsics::unlikely,
 };
 
 use super::regexp::JsRegExp;
@@ -33,177 +31,165 @@ pub fn string_to_string(_ctx: GcPointer<Context>, args: &Arguments) -> Result<Js
 pub fn string_concat(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, JsValue> {
     let val = args.this;
     val.check_object_coercible(ctx)?;
-    let mut str = val.to_string(ctx)?;
+    let mut builder = StringBuilder::new();
+    builder.append_value(ctx, val)?;
     for i in 0..args.size() {
-        let arg = args.at(i);
-        let r = arg.to_string(ctx)?;
-        str.push_str(&r);
+        builder.append_value(ctx, args.at(i))?;
     }
-    Ok(JsValue::encode_object_value(JsString::new(ctx, str)))
+    Ok(JsValue::encode_object_value(builder.build(ctx)))
 }
 
 pub fn string_value_of(_ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, JsValue> {
     Ok(args.this)
 }
 
+/// `this` of String.prototype methods converted to a string cell.
+fn this_string(ctx: GcPointer<Context>, args: &Arguments) -> Result<GcPointer<JsString>, JsValue> {
+    let this = args.this;
+    this.check_object_coercible(ctx)?;
+    if this.is_jsstring() {
+        return Ok(this.get_jsstring());
+    }
+    Ok(JsString::new(ctx, this.to_string(ctx)?))
+}
+
 pub fn string_char_at(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, JsValue> {
-    let primitive_val = args.this.to_string(ctx)?;
+    let string = this_string(ctx, args)?;
     let pos = args.at(0).to_int32(ctx)?;
-    if pos < 0 || pos >= primitive_val.len() as i32 {
-        return Ok(JsValue::encode_undefined_value());
+    if pos < 0 {
+        return Ok(JsValue::new(JsString::new(ctx, "")));
     }
-
-    if let Some(utf16_val) = primitive_val.encode_utf16().nth(pos as usize) {
-        Ok(JsValue::new(JsString::new(
-            ctx,
-            from_u32(utf16_val as u32).unwrap().to_string(),
-        )))
-    } else {
-        Ok(JsValue::new(JsString::new(ctx, "")))
+    match string.char_at(ctx, pos as _) {
+        Some(ch) => Ok(JsValue::new(ch)),
+        None => Ok(JsValue::new(JsString::new(ctx, ""))),
     }
 }
 
 pub fn string_code_point_at(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, JsValue> {
-    let primitive_val = args.this.to_string(ctx)?;
+    let string = this_string(ctx, args)?;
     let pos = args.at(0).to_int32(ctx)?;
-    if pos < 0 || pos >= primitive_val.len() as i32 {
+    if pos < 0 || pos as u32 >= string.utf16_len() {
         return Ok(JsValue::encode_undefined_value());
     }
-    if let Some((code_point, _, _)) = code_point_at(&primitive_val, pos as _) {
-        Ok(JsValue::new(code_point))
-    } else {
-        Ok(JsValue::encode_undefined_value())
+    let first = string.code_unit_at(pos as _).unwrap();
+    if is_leading_surrogate(first) {
+        if let Some(second) = string.code_unit_at(pos as u32 + 1) {
+            if is_trailing_surrogate(second) {
+                let cp = (first as u32 - 0xD800) * 0x400 + (second as u32 - 0xDC00) + 0x10000;
+                return Ok(JsValue::new(cp));
+            }
+        }
     }
+    Ok(JsValue::new(first as u32))
 }
 
 pub fn string_char_code_at(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, JsValue> {
-    let primitive_val = args.this.to_string(ctx)?;
+    let string = this_string(ctx, args)?;
     let pos = args.at(0).to_int32(ctx)?;
-    if pos < 0 || pos >= primitive_val.len() as i32 {
+    if pos < 0 {
         return Ok(JsValue::encode_nan_value());
     }
-
-    if let Some(utf16_val) = primitive_val.encode_utf16().nth(pos as _) {
-        return Ok(JsValue::new(utf16_val));
-    } else {
-        return Ok(JsValue::encode_nan_value());
+    match string.code_unit_at(pos as _) {
+        Some(unit) => Ok(JsValue::new(unit)),
+        None => Ok(JsValue::encode_nan_value()),
     }
 }
 
+/// `String.___replace(search, replace)`, the string pattern part of `String.prototype.replace`. Regular
+/// expressions are handled by their `Symbol.replace` method before this is called.
 pub fn string_replace(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, JsValue> {
-    let primitive_val = args.this.to_string(ctx)?;
-    if args.size() == 0 {
-        return Ok(JsValue::new(JsString::new(ctx, primitive_val)));
-    }
+    replace_string_pattern(ctx, args, false)
+}
 
-    let (regex_body, flags) = get_regex_string(ctx, args.at(0))?;
-    let re = Regex::with_flags(&regex_body, flags.as_str())
-        .expect("unable to convectx regex to regex object");
+/// `String.___replaceAll(search, replace)`, the string pattern part of `String.prototype.replaceAll`.
+pub fn string_replace_all(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, JsValue> {
+    replace_string_pattern(ctx, args, true)
+}
 
-    let mat = match re.find(&primitive_val) {
-        Some(mat) => mat,
-        None => return Ok(JsValue::new(JsString::new(ctx, primitive_val))),
+fn replace_string_pattern(
+    ctx: GcPointer<Context>,
+    args: &Arguments,
+    all: bool,
+) -> Result<JsValue, JsValue> {
+    args.this.check_object_coercible(ctx)?;
+    let string = args.this.to_string(ctx)?;
+    let search = args.at(0).to_string(ctx)?;
+    let replace = args.at(1);
+    let functional = replace.is_callable();
+    let replace_value = if functional {
+        String::new()
+    } else {
+        replace.to_string(ctx)?
     };
-    let caps = re
-        .find(&primitive_val)
-        .expect("unable to get capture groups from text")
-        .captures;
-
-    let replace_value = if args.size() > 1 {
-        let val = args.at(1).to_string(ctx)?;
-        let mut result = String::new();
-        let mut chars = val.chars().peekable();
-
-        let m = caps.len();
-
-        while let Some(first) = chars.next() {
-            if first == '$' {
-                let second = chars.next();
-                let second_is_digit = second.map_or(false, |ch| ch.is_digit(10));
-                // we use peek so that it is still in the iterator if not used
-                let third = if second_is_digit { chars.peek() } else { None };
-                let third_is_digit = third.map_or(false, |ch| ch.is_digit(10));
-
-                match (second, third) {
-                    (Some('$'), _) => {
-                        // $$
-                        result.push('$');
-                    }
-                    (Some('&'), _) => {
-                        // $&
-                        result.push_str(&primitive_val[mat.range()]);
-                    }
-                    (Some('`'), _) => {
-                        // $`
-                        let start_of_match = mat.start();
-                        result.push_str(&primitive_val[..start_of_match]);
-                    }
-                    (Some('\''), _) => {
-                        // $'
-                        let end_of_match = mat.end();
-                        result.push_str(&primitive_val[end_of_match..]);
-                    }
-                    (Some(second), Some(third)) if second_is_digit && third_is_digit => {
-                        // $nn
-                        let tens = second.to_digit(10).unwrap() as usize;
-                        let units = third.to_digit(10).unwrap() as usize;
-                        let nn = 10 * tens + units;
-                        if nn == 0 || nn > m {
-                            result.push(first);
-                            result.push(second);
-                            if let Some(ch) = chars.next() {
-                                result.push(ch);
-                            }
-                        } else {
-                            let group = match mat.group(nn) {
-                                Some(range) => &primitive_val[range.clone()],
-                                _ => "",
-                            };
-                            result.push_str(group);
-                            chars.next(); // consume third
-                        }
-                    }
-                    (Some(second), _) if second_is_digit => {
-                        // $n
-                        let n = second.to_digit(10).unwrap() as usize;
-                        if n == 0 || n > m {
-                            result.push(first);
-                            result.push(second);
-                        } else {
-                            let group = match mat.group(n) {
-                                Some(range) => &primitive_val[range.clone()],
-                                _ => "",
-                            };
-                            result.push_str(group);
-                        }
-                    }
-                    (Some('<'), _) => {
-                        // $<
-                        // TODO: named capture groups
-                        result.push_str("$<");
-                    }
-                    _ => {
-                        // $?, ? is none of the above
-                        // we can consume second because it isn't $
-                        result.push(first);
-                        if let Some(second) = second {
-                            result.push(second);
-                        }
-                    }
-                }
-            } else {
-                result.push(first);
-            }
-        }
-
-        result
+    // an empty pattern matches before every character and at the end
+    let positions = if all {
+        string
+            .match_indices(search.as_str())
+            .map(|(position, _)| position)
+            .collect::<Vec<_>>()
     } else {
-        "undefined".to_string()
+        string.find(search.as_str()).into_iter().collect()
     };
-    Ok(JsValue::new(JsString::new(
-        ctx,
-        primitive_val.replace(&primitive_val[mat.range()], &replace_value),
-    )))
+    if positions.is_empty() {
+        return Ok(JsValue::new(JsString::new(ctx, string)));
+    }
+
+    let string_value = JsValue::new(JsString::new(ctx, &string));
+    let mut result = String::with_capacity(string.len());
+    let mut end_of_last_match = 0;
+    let mut utf16_position = 0;
+    for position in positions {
+        let preceding = &string[end_of_last_match..position];
+        result.push_str(preceding);
+        utf16_position += preceding.encode_utf16().count() as u32;
+        let replacement = if functional {
+            letroot!(func = stack, replace.get_jsobject());
+            let mut buf = [
+                JsValue::new(JsString::new(ctx, &search)),
+                JsValue::new(utf16_position),
+                string_value,
+            ];
+            letroot!(
+                args = stack,
+                Arguments::new(JsValue::encode_undefined_value(), &mut buf)
+            );
+            func.as_function_mut()
+                .call(ctx, &mut args, JsValue::new(func))?
+                .to_string(ctx)?
+        } else {
+            get_substitution(&search, &string, position, &replace_value)
+        };
+        result.push_str(&replacement);
+        end_of_last_match = position + search.len();
+        utf16_position += search.encode_utf16().count() as u32;
+    }
+    result.push_str(&string[end_of_last_match..]);
+    Ok(JsValue::new(JsString::new(ctx, result)))
+}
+
+/// Expand `$$`, `$&`, `` $` `` and `$'` in `replacement` for `matched` found at byte offset `position` of
+/// `string`. String patterns have no capture groups, so `$1` and `$<name>` are kept as they are.
+fn get_substitution(matched: &str, string: &str, position: usize, replacement: &str) -> String {
+    let mut result = String::with_capacity(replacement.len());
+    let mut chars = replacement.chars().peekable();
+    while let Some(ch) = chars.next() {
+        if ch != '$' {
+            result.push(ch);
+            continue;
+        }
+        match chars.peek() {
+            Some('$') => result.push('$'),
+            Some('&') => result.push_str(matched),
+            Some('`') => result.push_str(&string[..position]),
+            Some('\'') => result.push_str(&string[position + matched.len()..]),
+            _ => {
+                result.push('$');
+                continue;
+            }
+        }
+        chars.next();
+    }
+    result
 }
 
 pub fn string_index_of(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, JsValue> {
@@ -278,14 +264,89 @@ pub fn string_repeat(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValu
         Ok(JsValue::new(JsString::new(ctx, "")))
     }
 }
+/// Apply `map` to `value` converted to a string. Lone surrogates are kept as they are and the well formed
+/// parts between them are mapped separately.
+fn map_string(
+    ctx: GcPointer<Context>,
+    value: JsValue,
+    map: impl Fn(&str) -> String,
+) -> Result<JsValue, JsValue> {
+    value.check_object_coercible(ctx)?;
+    if value.is_jsstring() && !value.get_jsstring().is_well_formed() {
+        let mut units = vec![];
+        let mut part = String::new();
+        for ch in decode_utf16(value.get_jsstring().code_units().to_vec()) {
+            match ch {
+                Ok(ch) => part.push(ch),
+                Err(error) => {
+                    units.extend(map(&part).encode_utf16());
+                    units.push(error.unpaired_surrogate());
+                    part.clear();
+                }
+            }
+        }
+        units.extend(map(&part).encode_utf16());
+        return Ok(JsValue::new(JsString::from_utf16(ctx, &units)));
+    }
+    let string = value.to_string(ctx)?;
+    Ok(JsValue::new(JsString::new(ctx, map(&string))))
+}
+
+/// `String.prototype.toLowerCase()`, uses the full case mapping of Unicode including the final sigma rule.
 pub fn string_to_lowercase(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, JsValue> {
-    let this = args.this.to_string(ctx)?;
-    Ok(JsValue::new(JsString::new(ctx, this.to_lowercase())))
+    map_string(ctx, args.this, str::to_lowercase)
 }
 
+/// `String.prototype.toUpperCase()`, uses the full case mapping of Unicode, so `"\u{df}"` becomes `"SS"`.
 pub fn string_to_uppercase(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, JsValue> {
-    let this = args.this.to_string(ctx)?;
-    Ok(JsValue::new(JsString::new(ctx, this.to_uppercase())))
+    map_string(ctx, args.this, str::to_uppercase)
+}
+
+/// `String.prototype.normalize(form)`. Without the `normalization` feature only strings of ASCII characters,
+/// which every form leaves unchanged, can be normalized.
+pub fn string_normalize(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, JsValue> {
+    args.this.check_object_coercible(ctx)?;
+    let form = if args.at(0).is_undefined() {
+        "NFC".to_string()
+    } else {
+        args.at(0).to_string(ctx)?
+    };
+    let normalize = match normalizer(&form) {
+        Some(normalize) => normalize,
+        None => {
+            return Err(JsValue::new(ctx.new_range_error(
+                "The normalization form should be one of NFC, NFD, NFKC, NFKD.",
+            )))
+        }
+    };
+    if !cfg!(feature = "normalization") && !args.this.to_string(ctx)?.is_ascii() {
+        return Err(JsValue::new(ctx.new_type_error(
+            "String.prototype.normalize requires the normalization feature for non-ASCII strings",
+        )));
+    }
+    map_string(ctx, args.this, normalize)
+}
+
+#[cfg(feature = "normalization")]
+fn normalizer(form: &str) -> Option<fn(&str) -> String> {
+    use unicode_normalization::UnicodeNormalization;
+    let normalize: fn(&str) -> String = match form {
+        "NFC" => |string| string.nfc().collect(),
+        "NFD" => |string| string.nfd().collect(),
+        "NFKC" => |string| string.nfkc().collect(),
+        "NFKD" => |string| string.nfkd().collect(),
+        _ => return None,
+    };
+    Some(normalize)
+}
+
+#[cfg(not(feature = "normalization"))]
+fn normalizer(form: &str) -> Option<fn(&str) -> String> {
+    let unchanged: fn(&str) -> String = str::to_owned;
+    match form {
+        "NFC" | "NFD" | "NFKC" | "NFKD" => Some(unchanged),
+        _ => None,
+    }
 }
 pub fn string_starts_with(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, JsValue> {
     let primitive_val = args.this.to_string(ctx)?;
@@ -478,43 +539,50 @@ pub fn string_substr(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValu
     Ok(JsValue::new(JsString::new(ctx, extracted_string)))
 }
 
-pub fn string_split(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, JsValue> {
+/// `String.prototype.___splitFast(separator, limit)`, the string separator part of `String.prototype.split`.
+/// Regular expressions are handled by their `Symbol.split` method before this is called.
+pub fn string_split(mut ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, JsValue> {
     let val = args.this;
     val.check_object_coercible(ctx)?;
     let str = val.to_string(ctx)?;
 
     let argc = args.size();
     let lim = if argc < 2 || args.at(1).is_undefined() {
-        4294967295u32
+        u32::MAX
     } else {
         args.at(1).to_uint32(ctx)?
     };
-    let separator = if args.at(0).is_undefined() || args.at(0).is_null() {
+    let separator = if args.at(0).is_undefined() {
         None
     } else {
         Some(args.at(0).to_string(ctx)?)
     };
-    let values = match separator {
-        None if lim == 0 => vec![],
+    // pieces are only referenced from the vector until the array is created
+    let defer = ctx.heap().defer();
+    let values: Vec<JsValue> = match separator {
+        _ if lim == 0 => vec![],
         None => vec![JsValue::encode_object_value(JsString::new(ctx, str))],
-        Some(separator) if separator.is_empty() => str
-            .encode_utf16()
-            .map(|cp| {
-                JsValue::encode_object_value(JsString::new(ctx, String::from_utf16_lossy(&[cp])))
-            })
-            .take(lim as _)
-            .collect(),
+        // an empty separator splits into code units, lone surrogates included
+        Some(separator) if separator.is_empty() => {
+            let units = if val.is_jsstring() {
+                val.get_jsstring().code_units().to_vec()
+            } else {
+                str.encode_utf16().collect()
+            };
+            units
+                .iter()
+                .take(lim as _)
+                .map(|unit| JsValue::new(JsString::from_utf16(ctx, &[*unit])))
+                .collect()
+        }
         Some(separator) => str
             .split(separator.as_str())
             .map(|x| JsValue::encode_object_value(JsString::new(ctx, x)))
             .take(lim as _)
             .collect(),
     };
-
-    let mut arr = JsArray::new(ctx, values.len() as _);
-    for (ix, item) in values.iter().enumerate() {
-        arr.put(ctx, Symbol::Index(ix as _), *item, false)?;
-    }
+    let arr = JsArray::from_slice(ctx, &values);
+    drop(defer);
     Ok(JsValue::encode_object_value(arr))
 }
 
@@ -562,6 +630,7 @@ impl Builtin for JsStringObject {
             string_substr as _,
             string_substring as _,
             string_replace as _,
+            string_replace_all as _,
             string_value_of as _,
             string_char_at as _,
             string_char_code_at as _,
@@ -571,6 +640,7 @@ impl Builtin for JsStringObject {
             string_repeat as _,
             string_to_lowercase as _,
             string_to_uppercase as _,
+            string_normalize as _,
             string_includes as _,
             string_slice as _,
         ]
@@ -597,6 +667,7 @@ impl Builtin for JsStringObject {
         def_native_method!(ctx, proto, charCodeAt, string_char_code_at, 1)?;
         def_native_method!(ctx, proto, toUpperCase, string_to_uppercase, 0)?;
         def_native_method!(ctx, proto, toLowerCase, string_to_lowercase, 0)?;
+        def_native_method!(ctx, proto, normalize, string_normalize, 0)?;
         def_native_method!(ctx, proto, indexOf, string_index_of, 2)?;
         def_native_method!(ctx, proto, lastIndexOf, string_last_index_of, 2)?;
         def_native_method!(ctx, proto, substr, string_substr, 2)?;
@@ -608,6 +679,7 @@ impl Builtin for JsStringObject {
         def_native_method!(ctx, proto, includes, string_includes, 1)?;
         def_native_method!(ctx, proto, slice, string_slice, 1)?;
         def_native_method!(ctx, constructor, ___replace, string_replace, 2)?;
+        def_native_method!(ctx, constructor, ___replaceAll, string_replace_all, 2)?;
         def_native_method!(ctx, proto, trim, string_trim, 0)?;
         def_native_method!(ctx, proto, trimStactx, string_trim_start, 0)?;
         def_native_method!(ctx, proto, trimEnd, string_trim_end, 0)?;
@@ -623,27 +695,6 @@ impl Builtin for JsStringObject {
     }
 }
 
-pub(crate) fn code_point_at(string: &str, position: i32) -> Option<(u32, u8, bool)> {
-    let size = string.encode_utf16().count() as i32;
-    if position < 0 || position >= size {
-        return None;
-    }
-    let mut encoded = string.encode_utf16();
-    let first = encoded.nth(position as usize)?;
-    if !is_leading_surrogate(first) && !is_trailing_surrogate(first) {
-        return Some((first as u32, 1, false));
-    }
-    if is_trailing_surrogate(first) || position + 1 == size {
-        return Some((first as u32, 1, true));
-    }
-    let second = encoded.next()?;
-    if !is_trailing_surrogate(second) {
-        return Some((first as u32, 1, true));
-    }
-    let cp = (first as u32 - 0xD800) * 0x400 + (second as u32 - 0xDC00) + 0x10000;
-    Some((cp, 2, false))
-}
-
 /// Helper function to check if a `char` is trimmable.
 #[inline]
 #[allow(dead_code)]
@@ -674,22 +725,6 @@ fn is_trailing_surrogate(value: u16) -> bool {
     (0xDC00..=0xDFFF).contains(&value)
 }
 
-fn get_regex_string(_ctx: GcPointer<Context>, val: JsValue) -> Result<(String, String), JsValue> {
-    if val.is_jsstring() {
-        return Ok((val.get_jsstring().string.clone(), String::new()));
-    }
-    if val.is_jsobject() {
-        let obj = val.get_jsobject();
-        if obj.is_class(JsRegExp::class()) {
-            return Ok((
-                obj.data::<JsRegExp>().original_source.to_string(),
-                obj.data::<JsRegExp>().original_flags.to_string(),
-            ));
-        }
-    }
-    return Ok(("undefined".to_string(), "".to_string()));
-}
-
 pub fn string_trim(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, JsValue> {
     let prim = args.this.to_string(ctx)?;
     Ok(JsValue::new(JsString::new(ctx, prim.trim())))
@@ -762,3 +797,80 @@ pub fn string_pad_end(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsVal
 pub fn string_pad_start(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, JsValue> {
     string_pad(ctx, args, Alignment::Stactx)
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::vm::symbol_table::Internable;
+    use crate::vm::tests::new_context;
+    use crate::vm::value::JsValue;
+
+    #[test]
+    fn test_string_replace() {
+        let (_vm, mut ctx) = new_context();
+
+        let result = ctx
+            .eval(
+                "var s = 'a.b.c';
+                 [s.replace('.', '-'), s.replaceAll('.', '-'), s.replace('.', '[$&|$`|$\\'|$$|$1]'),
+                  s.replaceAll('.', function (m, i, str) { return i + str.length; }),
+                  'ab'.replaceAll('', '_'), '\u{e9}\u{1F600}x'.replace('x', function (m, i) { return i; }),
+                  s.replace('z', 'y'), 'aXbX'.replaceAll(/x/gi, '$&$&')].join(' ')",
+            )
+            .ok()
+            .unwrap();
+        assert_eq!(
+            result.get_jsstring().as_str(),
+            "a-b.c a-b-c a[.|a|b.c|$|$1]b.c a6b8c _a_b_ \u{e9}\u{1F600}3 a.b.c aXXbXX"
+        );
+        assert!(ctx.eval("'a'.replaceAll(/a/, 'b')").is_err());
+    }
+
+    #[test]
+    fn test_string_split() {
+        let (_vm, mut ctx) = new_context();
+
+        let result = ctx
+            .eval(
+                "function show(list) {
+                     return '[' + list.map(function (v) { return v === undefined ? 'u' : v; }).join('|') + ']';
+                 }
+                 [show('a,b,c'.split(',', 2)), show('a,b'.split(',', 0)), show('abc'.split('')),
+                  show('\u{1F600}'.split('')).length, show('a1b2c'.split(/(\\d)/)), show('a1b2c'.split(/(\\d)/, 3)),
+                  show('abc'.split(/(?:)/)), show('ab'.split(/(x)?b/)), show(''.split(/a/)), show(''.split(/(?:)/)),
+                  show('anullb'.split(null)), show('ab'.split())].join(' ')",
+            )
+            .ok()
+            .unwrap();
+        assert_eq!(
+            result.get_jsstring().as_str(),
+            "[a|b] [] [a|b|c] 5 [a|1|b|2|c] [a|1|b] [a|b|c] [a|u|] [] [] [a|b] [ab]"
+        );
+    }
+
+    #[test]
+    fn test_string_case_and_normalize() {
+        use crate::vm::string::JsString;
+        let (_vm, mut ctx) = new_context();
+
+        let lone = JsString::from_utf16(ctx, &[0x61, 0xD800, 0x3A3, 0x391, 0x3A3]);
+        ctx.global_object()
+            .put(ctx, "lone".intern(), JsValue::new(lone), false)
+            .ok()
+            .unwrap();
+        let result = ctx
+            .eval(
+                "var lower = lone.toUpperCase().toLowerCase();
+                 ['stra\u{df}e'.toUpperCase(), '\u{130}'.toLowerCase().length, '\u{3a3}\u{391}\u{3a3}'.toLowerCase(),
+                  lower.length, lower.charCodeAt(1), lower.charCodeAt(0),
+                  'e\u{301}'.normalize() === '\u{e9}', '\u{e9}'.normalize('NFD').length,
+                  '\u{fb01}'.normalize('NFKC'), 'abc'.normalize('NFKD')].join()",
+            )
+            .ok()
+            .unwrap();
+        assert_eq!(
+            result.get_jsstring().as_str(),
+            "STRASSE,2,\u{3c3}\u{3b1}\u{3c2},5,55296,97,true,2,fi,abc"
+        );
+        assert!(ctx.eval("'a'.normalize('NFX')").is_err());
+    }
+}
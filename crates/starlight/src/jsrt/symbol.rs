@@ -1,9 +1,9 @@
+use crate::utils::unlikely;
 use crate::{
     prelude::*,
     vm::{builder::Builtin, context::Context, object::TypedJsObject},
     JsTryFrom,
 };
-use std::intrinsics::unlikely;
 
 macro_rules! builtin_symbols {
     ($ctx: expr,$ctor: expr,$m: ident) => {
@@ -58,6 +58,13 @@ impl Builtin for JsSymbolObject {
         ctx.global_data.symbol_prototype = Some(prototype);
         def_native_method!(ctx, prototype, toString, symbol_to_string, 0)?;
         def_native_method!(ctx, prototype, valueOf, symbol_value_of, 0)?;
+        def_native_property!(
+            ctx,
+            prototype,
+            "Symbol.toStringTag".intern().private(),
+            JsString::new(ctx, "Symbol"),
+            C
+        )?;
 
         let mut constructor = JsNativeFunction::new(ctx, "Symbol".intern(), symbol_ctor, 1);
 
@@ -1,4 +1,4 @@
-use std::intrinsics::unlikely;
+use crate::utils::unlikely;
 use std::mem::ManuallyDrop;
 
 use crate::define_jsclass;
@@ -90,6 +90,13 @@ impl Builtin for JsWeakRef {
         def_native_property!(ctx, constructor, prototype, prototype)?;
 
         def_native_method!(ctx, prototype, deref, weak_ref::weak_ref_prototype_deref, 0)?;
+        def_native_property!(
+            ctx,
+            prototype,
+            "Symbol.toStringTag".intern().private(),
+            JsString::new(ctx, "WeakRef"),
+            C
+        )?;
 
         ctx.global_data.weak_ref_prototype = Some(prototype);
 
@@ -4,10 +4,7 @@
 #![allow(incomplete_features)]
 #![feature(
     core_intrinsics,
-    llvm_asm,
     linked_list_cursors,
-    destructuring_assignment,
-    const_raw_ptr_deref,
     stmt_expr_attributes,
     const_type_id,
     pattern,
@@ -199,9 +196,13 @@ pub mod codegen;
 pub mod comet;
 mod constant;
 pub mod generator;
+#[cfg(feature = "inspector")]
+pub mod inspector;
 pub mod interpreter;
+pub mod jit;
 pub mod jsrt;
 pub mod options;
+pub mod preparser;
 pub mod vm;
 pub struct Platform;
 use std::sync::atomic::Ordering;
@@ -241,6 +242,7 @@ pub mod prelude {
         class::{Class, JsClass},
         error::*,
         function::*,
+        handle_scope::{EscapableHandleScope, Handle, HandleScope},
         method_table::MethodTable,
         object::{EnumerationMode, JsHint, JsObject, ObjectTag},
         property_descriptor::*,
@@ -18,23 +18,53 @@ pub struct Options {
         default_value="2GB",
         parse(try_from_str=parse_size_from_str))]
     pub heap_size: usize,
+    /// Number of marker threads. Ignored by the comet-backed heap, which marks on a single thread, see
+    /// `docs/gc/gc.md`.
     #[structopt(
         long = "gc-threads",
         default_value = "4",
         help = "Set number of GC marker threads"
     )]
     pub gc_threads: u32,
+    /// Not supported by the comet-backed heap, enabling it only prints a warning when the heap is created.
     #[structopt(long = "parallelMarking", help = "Enable parallel marking GC")]
     pub parallel_marking: bool,
     #[structopt(parse(from_os_str), help = "Input JS file")]
     pub file: PathBuf,
-    #[structopt(short = "d", long = "dumpBytecode", help = "Dump bytecode")]
+    #[structopt(
+        short = "d",
+        long = "dumpBytecode",
+        visible_alias = "dump-bytecode",
+        help = "Dump disassembly of every compiled code block"
+    )]
     pub dump_bytecode: bool,
     #[structopt(long = "disableIC", help = "Disable inline caching")]
     pub disable_ic: bool,
+    #[structopt(
+        long = "disablePeephole",
+        help = "Disable fusing of bytecode pairs into superinstructions"
+    )]
+    pub disable_peephole: bool,
+    /// Record names of environment variables and stack slots in every code block. Always on while a debugger
+    /// is attached.
+    #[structopt(
+        long = "debugInfo",
+        help = "Keep variable names for the debugger and error messages"
+    )]
+    pub debug_info: bool,
 
-    #[structopt(long = "enable-ffi", help = "Enable FFI and CFunction objects for use")]
+    #[structopt(long = "enable-ffi", help = "Enable the ffi module for loading native libraries")]
     pub enable_ffi: bool,
+    /// Make the `fs`, `path` and `process` modules importable. Off by default so embedders running untrusted
+    /// code get a sandboxed context.
+    #[structopt(
+        long = "host-modules",
+        help = "Enable the fs, path and process modules"
+    )]
+    pub host_modules: bool,
+    /// Define global `require` for CommonJS modules, see [commonjs](crate::jsrt::commonjs).
+    #[structopt(long = "commonjs", help = "Enable CommonJS require()")]
+    pub commonjs: bool,
     #[structopt(
         long = "dumpStats",
         help = "Dump various statistics at the end of execution"
@@ -44,6 +74,73 @@ pub struct Options {
     pub codegen_plugins: bool,
     #[structopt(long = "verboseGC", help = "Verbose GC cycle")]
     pub verbose_gc: bool,
+    /// Collect garbage before every allocation and verify the heap after each collection. Catches cells that
+    /// are reachable only through untraced memory at the point they are lost instead of long after. Very slow,
+    /// meant for tests.
+    #[structopt(
+        long = "gcStress",
+        help = "Collect garbage on every allocation and verify the heap"
+    )]
+    pub gc_stress: bool,
+    /// Size of the interpreter stack of every context in `JsValue`s. Operand stacks, stack slots and call
+    /// arguments of all active frames share it.
+    #[structopt(
+        long = "stackSize",
+        default_value = "16384",
+        help = "Set size of interpreter stack in values"
+    )]
+    pub stack_size: usize,
+    #[structopt(
+        long = "maxStackDepth",
+        default_value = "4096",
+        help = "Set maximal number of nested calls before RangeError is thrown"
+    )]
+    pub max_stack_depth: usize,
+    /// Space of the native stack in bytes that is kept free for Rust code. Calls entered with less space left
+    /// throw a `RangeError`, so recursion through builtins like `toString` calling back into JS can not
+    /// overflow the thread stack.
+    #[structopt(
+        long = "stackReserve",
+        default_value = "256KB",
+        help = "Set free native stack space below which calls throw RangeError",
+        parse(try_from_str = parse_size_from_str)
+    )]
+    pub stack_reserve: usize,
+    /// Skip bodies of inner functions when a script is compiled and compile them on first call. Syntax errors
+    /// inside of skipped bodies are reported only when the function is called.
+    #[structopt(
+        long = "lazyCompilation",
+        help = "Compile inner functions on their first call"
+    )]
+    pub lazy_compilation: bool,
+    /// Address the DevTools inspector listens at, `sl` waits for DevTools to attach before running the script.
+    /// Requires `inspector` feature.
+    #[structopt(
+        long = "inspect",
+        help = "Wait for Chrome DevTools to attach at address (e.g. 127.0.0.1:9229)"
+    )]
+    pub inspect: Option<String>,
+    /// Where `sl` writes a CPU profile of the script. `.json` files get Chrome trace format, other files
+    /// collapsed stacks for flamegraph tools.
+    #[structopt(
+        long = "cpuProfile",
+        help = "Sample the script and write a CPU profile to file",
+        parse(from_os_str)
+    )]
+    pub cpu_profile: Option<PathBuf>,
+    /// Function JSX elements compile to, JSX is a syntax error without it. Requires `jsx` feature.
+    #[structopt(
+        long = "jsx",
+        help = "Enable JSX compiled to calls of function (e.g. React.createElement or h)"
+    )]
+    pub jsx: Option<String>,
+    /// Value passed to the [Options::jsx] function for fragments `<></>`.
+    #[structopt(
+        long = "jsxFragment",
+        default_value = "React.Fragment",
+        help = "Set what JSX fragments compile to"
+    )]
+    pub jsx_fragment: String,
 }
 
 impl Default for Options {
@@ -52,15 +149,28 @@ impl Default for Options {
             parallel_marking: false,
             dump_bytecode: false,
             disable_ic: false,
+            disable_peephole: false,
+            debug_info: false,
             dump_size_classes: false,
             dump_stats: false,
             enable_ffi: false,
+            host_modules: false,
+            commonjs: false,
             size_class_progression: 1.4,
             heap_size: 2 * 1024 * 1024 * 1024,
             file: PathBuf::new(),
             gc_threads: 4,
             verbose_gc: false,
+            gc_stress: false,
             codegen_plugins: false,
+            stack_size: crate::vm::interpreter::stack::STACK_SIZE,
+            max_stack_depth: crate::vm::interpreter::stack::MAX_STACK_DEPTH,
+            stack_reserve: crate::vm::interpreter::stack::STACK_RESERVE,
+            lazy_compilation: false,
+            inspect: None,
+            cpu_profile: None,
+            jsx: None,
+            jsx_fragment: "React.Fragment".to_owned(),
         }
     }
 }
@@ -77,6 +187,7 @@ impl Options {
         self
     }
 
+    #[deprecated = "Parallel marking is not supported by the comet-backed heap."]
     pub fn with_parallel_marking(mut self, enable: bool) -> Self {
         self.parallel_marking = enable;
         self
@@ -92,6 +203,12 @@ impl Options {
         self
     }
 
+    pub fn with_gc_stress(mut self, enable: bool) -> Self {
+        self.gc_stress = enable;
+        self
+    }
+
+    #[deprecated = "Parallel marking is not supported by the comet-backed heap."]
     pub fn with_gc_threads(mut self, threads: u32) -> Self {
         self.gc_threads = threads;
         self
@@ -107,15 +224,117 @@ impl Options {
         self
     }
 
+    pub fn with_disable_peephole(mut self, disable: bool) -> Self {
+        self.disable_peephole = disable;
+        self
+    }
+
+    pub fn with_debug_info(mut self, enable: bool) -> Self {
+        self.debug_info = enable;
+        self
+    }
+
     pub fn with_enable_ffi(mut self, enable: bool) -> Self {
         self.enable_ffi = enable;
         self
     }
 
+    pub fn with_host_modules(mut self, enable: bool) -> Self {
+        self.host_modules = enable;
+        self
+    }
+
+    pub fn with_commonjs(mut self, enable: bool) -> Self {
+        self.commonjs = enable;
+        self
+    }
+
     pub fn with_dump_stats(mut self, enable: bool) -> Self {
         self.dump_stats = enable;
         self
     }
+
+    pub fn with_stack_size(mut self, size: usize) -> Self {
+        self.stack_size = size;
+        self
+    }
+
+    pub fn with_max_stack_depth(mut self, depth: usize) -> Self {
+        self.max_stack_depth = depth;
+        self
+    }
+
+    pub fn with_stack_reserve(mut self, bytes: usize) -> Self {
+        self.stack_reserve = bytes;
+        self
+    }
+
+    pub fn with_lazy_compilation(mut self, enable: bool) -> Self {
+        self.lazy_compilation = enable;
+        self
+    }
+
+    /// Enable JSX compiled to calls of `pragma` with `fragment` for fragments, e.g. `h` and `Fragment`.
+    pub fn with_jsx(mut self, pragma: &str, fragment: &str) -> Self {
+        self.jsx = Some(pragma.to_owned());
+        self.jsx_fragment = fragment.to_owned();
+        self
+    }
+}
+
+/// Options of a single [Context::eval_with](crate::vm::context::Context::eval_with).
+///
+/// ```rust,ignore
+/// let options = EvalOptions::default()
+///     .with_filename("index.html")
+///     .with_offset(12, 8)
+///     .with_strict(true);
+/// ctx.eval_with(inline_script, &options)?;
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EvalOptions {
+    /// Evaluate source as an ES module instead of a script.
+    pub module: bool,
+    /// Run top-level code as strict mode code even without `"use strict"`, like `force_strict` of
+    /// [Context::eval_internal](crate::vm::context::Context::eval_internal). Functions declared in it follow
+    /// their own directives.
+    pub strict: bool,
+    /// Path or URL of the source shown in stack traces. Relative imports of modules are resolved against it.
+    pub filename: Option<String>,
+    /// Zero-based line of the source in its file, e.g. of a script embedded in an HTML page.
+    pub line_offset: u32,
+    /// Zero-based column the first line of the source starts at.
+    pub column_offset: u32,
+    /// Overrides `Options::debug_info` for this source.
+    pub debug_info: Option<bool>,
+}
+
+impl EvalOptions {
+    pub fn with_module(mut self, enable: bool) -> Self {
+        self.module = enable;
+        self
+    }
+
+    pub fn with_strict(mut self, enable: bool) -> Self {
+        self.strict = enable;
+        self
+    }
+
+    pub fn with_filename(mut self, filename: &str) -> Self {
+        self.filename = Some(filename.to_owned());
+        self
+    }
+
+    pub fn with_offset(mut self, line: u32, column: u32) -> Self {
+        self.line_offset = line;
+        self.column_offset = column;
+        self
+    }
+
+    pub fn with_debug_info(mut self, enable: bool) -> Self {
+        self.debug_info = Some(enable);
+        self
+    }
 }
 
 fn parse_size_from_str(s: &str) -> Result<usize, ParseIntError> {
@@ -0,0 +1,250 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! Pre-parser used by lazy compilation.
+//!
+//! Before a script is parsed the pre-parser runs the tokenizer over it and records boundaries of every
+//! outermost `function` together with the identifiers used inside of its body. Bodies are then replaced with
+//! whitespace so that swc builds AST only for the code that runs immediately. Newlines and byte length are
+//! kept, spans of the remaining code stay valid for the original source file.
+//!
+//! The byte compiler emits such functions without compiling them and parses and compiles the recorded range
+//! on the first call, see [ByteCompiler::compile_lazy](crate::bytecompiler::ByteCompiler::compile_lazy).
+use crate::vm::{
+    init_es_config,
+    symbol_table::{Internable, Symbol},
+};
+use std::collections::{HashMap, HashSet};
+use swc_common::{BytePos, SourceFile};
+use swc_ecmascript::parser::{
+    lexer::Lexer,
+    token::{BinOpToken, Keyword, Token, Word},
+    JscTarget, StringInput, Syntax,
+};
+
+/// Function whose body was skipped by the pre-parser.
+#[derive(Debug)]
+pub struct LazyBody {
+    /// Position of `function` keyword.
+    pub start: BytePos,
+    /// Position right after closing brace of the body.
+    pub end: BytePos,
+    /// Identifiers that appear in the body. Like [Captures](crate::codegen::Captures) this is a superset of
+    /// the names body refers to.
+    pub names: HashSet<Symbol>,
+    /// Body uses `eval` or `with`. `arguments` of the skipped function never refers to the enclosing one.
+    pub dynamic: bool,
+}
+
+/// Result of pre-parsing a source file.
+pub struct PreParse {
+    /// Source text with skipped bodies blanked out.
+    pub source: String,
+    /// Skipped functions keyed by position of their opening brace.
+    bodies: HashMap<BytePos, LazyBody>,
+}
+
+impl PreParse {
+    /// Returns skipped function whose body starts at `body_start`.
+    pub fn body(&self, body_start: BytePos) -> Option<&LazyBody> {
+        self.bodies.get(&body_start)
+    }
+}
+
+enum State {
+    Normal,
+    /// Between `function` keyword and the body.
+    Head {
+        start: BytePos,
+        parens: u32,
+        params: bool,
+    },
+    Body {
+        start: BytePos,
+        lo: BytePos,
+        depth: u32,
+        names: HashSet<Symbol>,
+        dynamic: bool,
+    },
+}
+
+/// Pre-parse `fm`. Returns `None` when there is nothing to skip or the source can't be tokenized, the caller
+/// then parses the whole file as usual so syntax errors are reported eagerly.
+pub fn preparse(fm: &SourceFile) -> Option<PreParse> {
+    let lexer = Lexer::new(
+        Syntax::Es(init_es_config()),
+        JscTarget::Es2020,
+        StringInput::from(fm),
+        None,
+    );
+    let mut bodies = HashMap::new();
+    let mut state = State::Normal;
+    let mut prev: Option<Token> = None;
+    for item in lexer {
+        let token = item.token;
+        if let Token::Error(_) = token {
+            return None;
+        }
+        state = match state {
+            State::Normal => {
+                let method = matches!(prev, Some(Token::Dot));
+                let is_async =
+                    matches!(prev, Some(Token::Word(Word::Ident(ref name))) if name == "async");
+                if matches!(token, Token::Word(Word::Keyword(Keyword::Function)))
+                    && !method
+                    && !is_async
+                {
+                    State::Head {
+                        start: item.span.lo,
+                        parens: 0,
+                        params: false,
+                    }
+                } else {
+                    State::Normal
+                }
+            }
+            State::Head {
+                start,
+                parens,
+                params,
+            } => match token {
+                Token::LParen => State::Head {
+                    start,
+                    parens: parens + 1,
+                    params: true,
+                },
+                Token::RParen if parens > 0 => State::Head {
+                    start,
+                    parens: parens - 1,
+                    params,
+                },
+                Token::LBrace if parens == 0 && params => State::Body {
+                    start,
+                    lo: item.span.lo,
+                    depth: 1,
+                    names: HashSet::new(),
+                    dynamic: false,
+                },
+                // name and `*` of generators.
+                Token::Word(_) | Token::BinOp(BinOpToken::Mul) if !params => State::Head {
+                    start,
+                    parens,
+                    params,
+                },
+                _ if parens > 0 => State::Head {
+                    start,
+                    parens,
+                    params,
+                },
+                // `function` used as a property name or something the parser is going to reject.
+                _ => State::Normal,
+            },
+            State::Body {
+                start,
+                lo,
+                depth,
+                mut names,
+                mut dynamic,
+            } => match token {
+                Token::LBrace | Token::DollarLBrace => State::Body {
+                    start,
+                    lo,
+                    depth: depth + 1,
+                    names,
+                    dynamic,
+                },
+                Token::RBrace if depth == 1 => {
+                    bodies.insert(
+                        lo,
+                        LazyBody {
+                            start,
+                            end: item.span.hi,
+                            names,
+                            dynamic,
+                        },
+                    );
+                    State::Normal
+                }
+                Token::RBrace => State::Body {
+                    start,
+                    lo,
+                    depth: depth - 1,
+                    names,
+                    dynamic,
+                },
+                _ => {
+                    match token {
+                        Token::Word(Word::Ident(ref name)) => {
+                            if name == "eval" {
+                                dynamic = true;
+                            } else {
+                                let name: &str = name;
+                                names.insert(name.intern());
+                            }
+                        }
+                        Token::Word(Word::Keyword(Keyword::With)) => dynamic = true,
+                        _ => (),
+                    }
+                    State::Body {
+                        start,
+                        lo,
+                        depth,
+                        names,
+                        dynamic,
+                    }
+                }
+            },
+        };
+        prev = Some(token);
+    }
+    if bodies.is_empty() || !matches!(state, State::Normal) {
+        return None;
+    }
+    let mut source = fm.src.as_bytes().to_vec();
+    for (lo, body) in bodies.iter() {
+        let from = (lo.0 - fm.start_pos.0) as usize + 1;
+        let to = (body.end.0 - fm.start_pos.0) as usize - 1;
+        if source[from - 1] != b'{' || source[to] != b'}' {
+            return None;
+        }
+        for byte in source[from..to].iter_mut() {
+            if *byte != b'\n' && *byte != b'\r' {
+                *byte = b' ';
+            }
+        }
+    }
+    Some(PreParse {
+        // every byte of a multi-byte character is replaced, the result is still valid UTF-8.
+        source: String::from_utf8(source).ok()?,
+        bodies,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use swc_common::{sync::Lrc, FileName, SourceMap};
+
+    #[test]
+    fn test_preparse() {
+        let cm: Lrc<SourceMap> = Default::default();
+        let src = "var x = 1;\nfunction f(a = {}) {\n  return `${a}` + x; }\no.function(1);\nfunction* g() { function h() { eval(s) } }";
+        let fm = cm.new_source_file(FileName::Anon, src.to_string());
+        let pre = preparse(&fm).unwrap();
+        assert_eq!(pre.source.len(), src.len());
+        assert_eq!(pre.source.lines().count(), src.lines().count());
+        assert_eq!(pre.bodies.len(), 2);
+        assert!(pre.source.contains("function f(a = {}) {\n"));
+        assert!(!pre.source.contains("return"));
+        assert!(pre.source.contains("o.function(1);"));
+        assert!(!pre.source.contains("function h"));
+
+        let f = pre
+            .body(fm.start_pos + BytePos(src.find(") {").unwrap() as u32 + 2))
+            .unwrap();
+        assert!(f.names.contains(&"x".intern()));
+        assert!(!f.dynamic);
+        assert!(pre.bodies.values().any(|body| body.dynamic));
+    }
+}
@@ -2,6 +2,7 @@ use self::{frame::CallFrame, stack::Stack};
 use super::ir::{BinaryOp, Trace as Ir};
 use crate::letroot;
 use crate::prelude::*;
+use crate::utils::{likely, unlikely};
 use crate::vm::function::*;
 use crate::vm::interpreter::*;
 
@@ -19,7 +20,6 @@ use crate::{
 };
 use crate::{bytecode::*, gc::cell::Visitor};
 use profile::{ArithProfile, ByValProfile};
-use std::intrinsics::{likely, unlikely};
 use wtf_rs::unwrap_unchecked;
 
 pub enum RecordResult {
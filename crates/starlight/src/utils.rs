@@ -2,7 +2,25 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
 pub mod align_as;
+pub mod malloc;
 pub mod ordered_set;
+
+/// Branch prediction hints. These are `std::intrinsics` with the `intrinsics` feature and plain functions on
+/// toolchains and targets without them.
+#[cfg(feature = "intrinsics")]
+pub use std::intrinsics::{likely, unlikely};
+
+#[cfg(not(feature = "intrinsics"))]
+#[inline(always)]
+pub fn likely(b: bool) -> bool {
+    b
+}
+
+#[cfg(not(feature = "intrinsics"))]
+#[inline(always)]
+pub fn unlikely(b: bool) -> bool {
+    b
+}
 #[macro_export]
 macro_rules! log_if {
     ($cond: expr, $($fmt:tt)*) => {
@@ -0,0 +1,68 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+//! `malloc`, `calloc` and `free` for raw blocks such as `ArrayBuffer` data and the interpreter stack.
+//!
+//! These are libc functions where libc exists. wasm32-unknown-unknown has none, there blocks come from the Rust
+//! global allocator with their size stored in a header in front of the block.
+
+#[cfg(not(target_arch = "wasm32"))]
+pub use libc::{calloc, free, malloc};
+
+#[cfg(target_arch = "wasm32")]
+pub use self::wasm::{calloc, free, malloc};
+
+#[cfg(target_arch = "wasm32")]
+mod wasm {
+    use std::{
+        alloc::{alloc, alloc_zeroed, dealloc, Layout},
+        os::raw::c_void,
+        ptr::null_mut,
+    };
+
+    /// Size of the header, also the alignment of returned blocks like `max_align_t`.
+    const HEADER: usize = 16;
+
+    unsafe fn allocate(size: usize, zeroed: bool) -> *mut c_void {
+        let layout = match size
+            .checked_add(HEADER)
+            .and_then(|total| Layout::from_size_align(total, HEADER).ok())
+        {
+            Some(layout) => layout,
+            None => return null_mut(),
+        };
+        let base = if zeroed {
+            alloc_zeroed(layout)
+        } else {
+            alloc(layout)
+        };
+        if base.is_null() {
+            return null_mut();
+        }
+        base.cast::<usize>().write(size);
+        base.add(HEADER).cast()
+    }
+
+    pub unsafe fn malloc(size: usize) -> *mut c_void {
+        allocate(size, false)
+    }
+
+    pub unsafe fn calloc(count: usize, size: usize) -> *mut c_void {
+        match count.checked_mul(size) {
+            Some(size) => allocate(size, true),
+            None => null_mut(),
+        }
+    }
+
+    pub unsafe fn free(ptr: *mut c_void) {
+        if ptr.is_null() {
+            return;
+        }
+        let base = ptr.cast::<u8>().sub(HEADER);
+        let size = base.cast::<usize>().read();
+        dealloc(
+            base,
+            Layout::from_size_align_unchecked(size + HEADER, HEADER),
+        );
+    }
+}
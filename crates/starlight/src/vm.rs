@@ -2,7 +2,18 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
 use self::{
-    attributes::*, context::Context, object::JsObject, structure::Structure, symbol_table::Symbol,
+    attributes::*,
+    context::Context,
+    debugger::{Debugger, DebuggerDelegate},
+    frontend::{Frontend, SwcFrontend},
+    future::{FutureTask, FutureWakeHook},
+    global::GlobalResolver,
+    module_loader::{FsModuleLoader, ModuleLoader},
+    object::JsObject,
+    profiler::{CpuProfile, SamplingProfiler},
+    references::ReferenceMap,
+    structure::Structure,
+    symbol_table::Symbol,
 };
 use crate::{
     bytecompiler::{ByteCompiler, CompileError},
@@ -42,22 +53,36 @@ pub mod bigint;
 pub mod builder;
 pub mod builtins;
 pub mod code_block;
+pub mod code_cache;
 pub mod context;
 pub mod data_view;
+pub mod debugger;
+pub mod disassembler;
 pub mod environment;
 pub mod error;
+pub mod frontend;
 pub mod function;
+pub mod future;
 pub mod global;
+pub mod handle_scope;
+pub mod heap_snapshot;
 pub mod indexed_elements;
+pub mod inspect;
 pub mod interpreter;
 pub mod map;
+pub mod memory_usage;
+pub mod module_loader;
 pub mod native_iterator;
 pub mod number;
+pub mod numeric;
 pub mod object;
 pub mod operations;
 pub mod perf;
+pub mod profiler;
 pub mod property_descriptor;
+pub mod references;
 pub mod slot;
+pub mod snapshot_check;
 pub mod stack_alignment;
 pub mod string;
 pub mod structure;
@@ -65,6 +90,7 @@ pub mod structure_builder;
 pub mod structure_chain;
 pub mod symbol_table;
 pub mod thread;
+pub mod transfer;
 pub mod typedarray;
 pub mod value;
 
@@ -125,7 +151,11 @@ impl Deserializable for ModuleKind {
 /// JavaScript runtime instance.
 pub struct VirtualMachine {
     pub(crate) gc: Heap,
-    pub(crate) external_references: Vec<usize>,
+    /// Positional native references, see [ReferenceMap].
+    pub(crate) external_references: ReferenceMap,
+    /// Native references registered by stable key, see [VirtualMachine::register_native].
+    pub(crate) named_references: HashMap<String, usize>,
+    pub(crate) reference_keys: HashMap<usize, String>,
     pub(crate) options: Options,
     pub(crate) top_call_frame: *mut CallFrame,
     pub(crate) codegen_plugins: HashMap<
@@ -144,7 +174,19 @@ pub struct VirtualMachine {
     /// String that contains all the source code passed to [VirtualMachine::eval] and [VirtualMachine::evalm]
     pub(crate) eval_history: String,
     pub(crate) persistent_roots: Rc<RefCell<HashMap<usize, JsValue>>>,
+    /// Values rooted by [handle_scope::HandleScope]s, innermost scope at the end.
+    pub(crate) handles: Vec<JsValue>,
     pub(crate) sched_async_func: Option<Box<dyn Fn(Box<dyn FnOnce(GcPointer<Context>)>)>>,
+    /// Futures passed to [VirtualMachine::promise_from_future] that are not completed yet.
+    pub(crate) futures: Vec<FutureTask>,
+    pub(crate) future_wake_hook: Option<FutureWakeHook>,
+    pub(crate) loader: Rc<dyn ModuleLoader>,
+    pub(crate) frontend: Rc<dyn Frontend>,
+    pub(crate) global_resolver: Option<Rc<GlobalResolver>>,
+    #[cfg(feature = "fetch")]
+    pub(crate) http_transport: Option<Rc<dyn crate::jsrt::fetch::HttpTransport>>,
+    pub(crate) debugger: Option<Box<Debugger>>,
+    pub(crate) profiler: Option<Box<SamplingProfiler>>,
 
     pub(crate) contexts: Vec<GcPointer<Context>>,
 
@@ -180,6 +222,52 @@ impl VirtualMachine {
         self.sched_async_func = Some(scheduler);
         self
     }
+
+    /// Install the transport `fetch` sends requests with. `fetch` rejects every request until a transport is
+    /// installed, see [fetch](crate::jsrt::fetch).
+    #[cfg(feature = "fetch")]
+    pub fn with_http_transport(
+        mut self: VirtualMachineRef,
+        transport: impl crate::jsrt::fetch::HttpTransport + 'static,
+    ) -> VirtualMachineRef {
+        self.http_transport = Some(Rc::new(transport));
+        self
+    }
+    /// Replace the loader `import` resolves and reads modules with, [FsModuleLoader] by default.
+    pub fn with_module_loader(
+        mut self: VirtualMachineRef,
+        loader: impl ModuleLoader + 'static,
+    ) -> VirtualMachineRef {
+        self.loader = Rc::new(loader);
+        self
+    }
+
+    /// Replace the parser of scripts, modules, `eval` code and `Function` bodies, [SwcFrontend] configured by
+    /// [Options] by default.
+    pub fn with_frontend(
+        mut self: VirtualMachineRef,
+        frontend: impl Frontend + 'static,
+    ) -> VirtualMachineRef {
+        self.frontend = Rc::new(frontend);
+        self
+    }
+
+    /// Install a resolver called when lookup of a global property misses in any context of the runtime,
+    /// which allows defining globals lazily. See [GlobalResolver].
+    ///
+    /// ```rust,ignore
+    /// vm.set_global_resolver(|ctx, name| match ctx.description(name).as_str() {
+    ///     "answer" => Some(JsValue::new(42)),
+    ///     _ => None,
+    /// });
+    /// ```
+    pub fn set_global_resolver(
+        &mut self,
+        resolver: impl Fn(GcPointer<Context>, Symbol) -> Option<JsValue> + 'static,
+    ) {
+        self.global_resolver = Some(Rc::new(resolver));
+    }
+
     pub fn add_persistent_root(&mut self, obj: JsValue) -> PersistentRooted {
         // for PoC only, todo use something like AutoIdMap for persistent_roots
 
@@ -196,22 +284,123 @@ impl VirtualMachine {
         }
     }
 
+    /// Register native `reference` (function, class or any other static address) under stable `key`.
+    ///
+    /// Unlike positional external references keyed references are serialized by name so registration order
+    /// may change between snapshot and restore.
+    ///
+    /// # Panics
+    /// Panics if `key` is already registered for another reference or `reference` is already registered under
+    /// another key.
+    pub fn register_native(&mut self, key: impl Into<String>, reference: usize) {
+        let key = key.into();
+        if let Some(previous) = self.named_references.get(&key) {
+            assert_eq!(
+                *previous, reference,
+                "native reference '{}' is already registered",
+                key
+            );
+            return;
+        }
+        if let Some(previous) = self.reference_keys.get(&reference) {
+            panic!(
+                "native reference {:#x} is already registered as '{}', can't register it as '{}'",
+                reference, previous, key
+            );
+        }
+        self.reference_keys.insert(reference, key.clone());
+        self.named_references.insert(key, reference);
+    }
+
+    /// Find native reference registered with `key`.
+    pub fn native_reference(&self, key: &str) -> Option<usize> {
+        self.named_references.get(key).copied()
+    }
+
+    /// Find key that `reference` was registered with.
+    pub fn native_reference_key(&self, reference: usize) -> Option<&str> {
+        self.reference_keys.get(&reference).map(|key| &**key)
+    }
+
     pub fn options(&self) -> &Options {
         &self.options
     }
 
+    /// Structural equality of `a` and `b` that may come from different contexts of this runtime, see
+    /// [deep_equal](operations::equality::deep_equal).
+    pub fn deep_equal(&self, a: JsValue, b: JsValue) -> bool {
+        match self.contexts.first() {
+            Some(ctx) => operations::equality::deep_equal(*ctx, a, b),
+            // objects can't exist before the first context is created
+            None => operations::equality::same_value(a, b),
+        }
+    }
+
+    /// Attach debugger, see [debugger]. Only code compiled after this call stops at statements and
+    /// breakpoints. Replaces previously attached debugger together with its breakpoints.
+    pub fn set_debugger(&mut self, delegate: impl DebuggerDelegate + 'static) -> &mut Debugger {
+        self.debugger = Some(Box::new(Debugger::new(Box::new(delegate))));
+        self.debugger.as_mut().unwrap()
+    }
+
+    pub fn remove_debugger(&mut self) {
+        self.debugger = None;
+    }
+
+    /// Attached debugger, used to manage breakpoints.
+    pub fn debugger(&mut self) -> Option<&mut Debugger> {
+        self.debugger.as_deref_mut()
+    }
+
+    /// Opcode counters collected since the VM was created or since [VirtualMachine::reset_perf_stats].
+    #[cfg(feature = "perf")]
+    pub fn perf_stats(&self) -> perf::PerfStats {
+        self.perf.stats()
+    }
+
+    /// Reset opcode counters returned by [VirtualMachine::perf_stats].
+    #[cfg(feature = "perf")]
+    pub fn reset_perf_stats(&mut self) {
+        self.perf.reset();
+    }
+
+    /// Start sampling call stacks every `interval`, see [profiler]. Restarts profiling if it is running already.
+    /// Requires the `threads` feature for the sampling timer.
+    #[cfg(feature = "threads")]
+    pub fn start_profiling(&mut self, interval: std::time::Duration) {
+        self.profiler = Some(Box::new(SamplingProfiler::start(interval)));
+    }
+
+    /// Stop profiling started by [VirtualMachine::start_profiling] and return collected samples.
+    pub fn stop_profiling(&mut self) -> Option<CpuProfile> {
+        self.profiler.take().map(|profiler| profiler.finish())
+    }
+
     pub fn new_raw(gc: Heap, options: Options, external_references: Option<Vec<usize>>) -> VM {
+        let frontend = Rc::new(SwcFrontend::for_options(&options));
         VirtualMachineRef(Box::into_raw(Box::new(Self {
             gc,
             options,
 
-            external_references: external_references.unwrap_or(vec![]),
+            external_references: ReferenceMap::from(external_references.unwrap_or(vec![])),
+            named_references: HashMap::new(),
+            reference_keys: HashMap::new(),
             #[cfg(feature = "perf")]
             perf: perf::Perf::new(),
             eval_history: String::new(),
             top_call_frame: null_mut(),
             persistent_roots: Default::default(),
+            handles: vec![],
             sched_async_func: None,
+            futures: vec![],
+            future_wake_hook: None,
+            loader: Rc::new(FsModuleLoader::default()),
+            frontend,
+            global_resolver: None,
+            #[cfg(feature = "fetch")]
+            http_transport: None,
+            debugger: None,
+            profiler: None,
             codegen_plugins: HashMap::new(),
             contexts: vec![],
             context_snapshot: Rc::new(Box::new([])),
@@ -227,6 +416,13 @@ impl VirtualMachine {
         &mut self.gc
     }
 
+    /// Verify cells reachable from every context, see [Context::verify_heap](GcPointer::verify_heap).
+    pub fn verify_heap(&mut self) {
+        for ctx in self.contexts.iter() {
+            ctx.verify_heap();
+        }
+    }
+
     /// Construct runtime instance with specific GC heap.
     pub fn with_heap(gc: Heap, options: Options, external_references: Option<Vec<usize>>) -> VM {
         let mut this = VirtualMachine::new_raw(gc, options, external_references);
@@ -250,6 +446,8 @@ impl VirtualMachine {
     }
 
     fn register_gc_constraints(&mut self) {
+        let this = self as *mut Self;
+        self.gc.verifier = Some(Box::new(move || unsafe { (*this).verify_heap() }));
         let vm = unsafe { &mut *(self as *mut Self) };
         self.gc.add_constraint(SimpleMarkingConstraint::new(
             "Mark VM roots",
@@ -265,6 +463,9 @@ impl VirtualMachine {
                 pr.iter_mut().for_each(|entry| {
                     entry.1.trace(visitor);
                 });
+                vm.handles.iter_mut().for_each(|value| {
+                    value.trace(visitor);
+                });
             },
         ));
     }
@@ -279,13 +480,13 @@ impl VirtualMachine {
         this
     }
 
-    /// Enable FFI builtin object.
+    /// Enable the `ffi` module in contexts created after this call.
     ///
     ///
-    /// FFI object allows to load arbitrary dynamic library and then load functions from it.
+    /// `ffi` allows to load arbitrary dynamic library and then call functions from it.
     #[cfg(all(target_pointer_width = "64", feature = "ffi"))]
     pub fn add_ffi(&mut self) {
-        crate::jsrt::ffi::initialize_ffi(self);
+        self.options.enable_ffi = true;
     }
 
     pub fn register_codegen_plugin(
@@ -314,6 +515,7 @@ impl VirtualMachine {
             .position(|x| *x == ctx)
             .expect("context not found");
         self.contexts.remove(index);
+        self.drop_futures_of(ctx);
     }
 
     pub fn context(&mut self, index: usize) -> GcPointer<Context> {
@@ -368,6 +570,8 @@ pub struct GlobalData {
     pub(crate) function_struct: Option<GcPointer<Structure>>,
     pub(crate) object_prototype: Option<GcPointer<JsObject>>,
     pub(crate) object_constructor: Option<GcPointer<JsObject>>,
+    pub(crate) array_constructor: Option<GcPointer<JsObject>>,
+    pub(crate) promise_constructor: Option<GcPointer<JsObject>>,
     pub(crate) number_prototype: Option<GcPointer<JsObject>>,
     pub(crate) string_prototype: Option<GcPointer<JsObject>>,
     pub(crate) boolean_prototype: Option<GcPointer<JsObject>>,
@@ -420,6 +624,8 @@ impl Trace for GlobalData {
         self.function_struct.trace(vis);
         self.object_prototype.trace(vis);
         self.object_constructor.trace(vis);
+        self.array_constructor.trace(vis);
+        self.promise_constructor.trace(vis);
         self.number_prototype.trace(vis);
         self.string_prototype.trace(vis);
         self.boolean_prototype.trace(vis);
@@ -584,15 +790,31 @@ pub(crate) fn init_es_config() -> EsConfig {
 
 #[cfg(test)]
 pub mod tests {
+    use crate::bytecode::opcodes::Opcode;
+    use crate::bytecompiler::ByteCompiler;
     use crate::gc::cell::GcPointer;
     use crate::gc::Heap;
     use crate::options::Options;
     use crate::vm::symbol_table::Internable;
     use crate::vm::value::JsValue;
-    use crate::vm::{arguments, context::Context, VirtualMachine};
+    use crate::vm::{arguments, context::Context, VirtualMachine, VM};
     use crate::Platform;
     use std::cell::RefCell;
     use std::rc::Rc;
+    use swc_ecmascript::ast::ExprOrSpread;
+
+    /// Initialize the platform and create a runtime with one context.
+    pub(crate) fn new_context() -> (VM, GcPointer<Context>) {
+        new_context_with(Options::default())
+    }
+
+    /// Initialize the platform and create a runtime with `options` and one context.
+    pub(crate) fn new_context_with(options: Options) -> (VM, GcPointer<Context>) {
+        Platform::initialize();
+        let mut vm = VirtualMachine::new(options, None);
+        let ctx = Context::new(&mut vm);
+        (vm, ctx)
+    }
 
     #[test]
     fn test_simple_async() {
@@ -695,10 +917,6 @@ pub mod tests {
         }
     }
 
-    use swc_ecmascript::ast::ExprOrSpread;
-
-    use crate::{bytecode::opcodes::Opcode, bytecompiler::ByteCompiler};
-
     #[test]
     fn test_register_codegen_plugin() {
         Platform::initialize();
@@ -751,6 +969,41 @@ pub mod tests {
         assert!(result.is_err(), "Should return JsValue error");
         //
     }
+
+    fn native_a(_: GcPointer<Context>, _: &arguments::Arguments) -> Result<JsValue, JsValue> {
+        Ok(JsValue::encode_undefined_value())
+    }
+
+    fn native_b(_: GcPointer<Context>, _: &arguments::Arguments) -> Result<JsValue, JsValue> {
+        Ok(JsValue::encode_null_value())
+    }
+
+    #[test]
+    fn test_register_native() {
+        let (mut vm, _ctx) = new_context();
+        vm.register_native("native_a", native_a as usize);
+        // registering the same pair again is allowed
+        vm.register_native("native_a", native_a as usize);
+        assert_eq!(vm.native_reference("native_a"), Some(native_a as usize));
+        assert_eq!(vm.native_reference_key(native_a as usize), Some("native_a"));
+        assert_eq!(vm.native_reference("native_b"), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "native reference 'native_a' is already registered")]
+    fn test_register_native_duplicate_key() {
+        let (mut vm, _ctx) = new_context();
+        vm.register_native("native_a", native_a as usize);
+        vm.register_native("native_a", native_b as usize);
+    }
+
+    #[test]
+    #[should_panic(expected = "can't register it as 'alias'")]
+    fn test_register_native_duplicate_reference() {
+        let (mut vm, _ctx) = new_context();
+        vm.register_native("native_a", native_a as usize);
+        vm.register_native("alias", native_a as usize);
+    }
 }
 
 pub type VM = VirtualMachineRef;
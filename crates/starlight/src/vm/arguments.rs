@@ -182,6 +182,23 @@ impl JsArguments {
         true
     }
 
+    pub fn DeleteIndexedMethod(
+        obj: &mut GcPointer<JsObject>,
+        ctx: GcPointer<Context>,
+        index: u32,
+        throwable: bool,
+    ) -> Result<bool, JsValue> {
+        let deleted = JsObject::DeleteIndexedMethod(obj, ctx, index, throwable)?;
+        if deleted {
+            // property defined again later is not an alias of the parameter anymore.
+            let arg = obj.as_arguments_mut();
+            if arg.mapping.len() > index as usize {
+                arg.mapping[index as usize] = DUMMY_SYMBOL;
+            }
+        }
+        Ok(deleted)
+    }
+
     pub fn GetNonIndexedSlotMethod(
         obj: &mut GcPointer<JsObject>,
         ctx: GcPointer<Context>,
@@ -250,7 +267,7 @@ impl JsArguments {
 
             mapping.push(*param);
         }
-        let _ = obj.put(ctx, "length".intern(), JsValue::new(len as i32), false);
+        let _ = obj.put(ctx, Symbol::LENGTH, JsValue::new(len as i32), false);
         obj.as_arguments_mut().mapping = mapping.into_boxed_slice();
         obj
     }
@@ -261,3 +278,29 @@ impl Trace for JsArguments {
         self.env.trace(tracer);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::vm::tests::new_context;
+
+    #[test]
+    fn test_arguments() {
+        let (_vm, mut ctx) = new_context();
+
+        let result = ctx
+            .eval(
+                "function mapped(a) { arguments[0] = 2; var r = a; a = 3; return r + arguments[0]; }
+                 function unmapped(a) { 'use strict'; arguments[0] = 2; var r = a; a = 3; return r + arguments[0]; }
+                 function deleted(a) { delete arguments[0]; arguments[0] = 2; return a; }
+                 function extra(a) { let x = 7; return x + arguments.length + arguments[1]; }
+                 function outer() { return function () { return arguments.length; }; }
+                 mapped(1) + ',' + unmapped(1) + ',' + deleted(1) + ',' + extra(1, 10) + ',' + outer()(1, 2)",
+            )
+            .ok()
+            .unwrap();
+        assert_eq!(result.get_jsstring().as_str(), "5,3,1,19,2");
+
+        let outer = ctx.eval("outer").ok().unwrap().get_jsobject();
+        assert!(!outer.as_function().as_vm().code.use_arguments);
+    }
+}
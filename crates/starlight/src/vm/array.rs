@@ -1,7 +1,7 @@
 /* This Source Code Form is subject to the terms of the Mozilla Public
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
-use std::intrinsics::unlikely;
+use crate::utils::unlikely;
 
 use super::{
     attributes::*,
@@ -12,16 +12,47 @@ use super::{
     string::JsString,
     Context,
 };
-use super::{
-    method_table::*,
-    object::EnumerationMode,
-    symbol_table::{Internable, Symbol},
-};
+use super::{method_table::*, object::EnumerationMode, symbol_table::Symbol};
 use super::{property_descriptor::PropertyDescriptor, slot::*, value::*};
 use crate::gc::cell::GcPointer;
 use crate::prelude::*;
 pub struct JsArray;
 
+/// Iterator over elements of an array returned by [JsArray::iter].
+pub struct ArrayIter {
+    ctx: GcPointer<Context>,
+    array: GcPointer<JsObject>,
+    index: u32,
+    length: u32,
+}
+
+impl Iterator for ArrayIter {
+    type Item = Result<JsValue, JsValue>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.index >= self.length {
+            return None;
+        }
+        let index = self.index;
+        self.index += 1;
+        let indexed = &self.array.indexed;
+        if indexed.dense() && index < indexed.vector.size() {
+            let val = *indexed.vector.at(index);
+            if !val.is_empty() {
+                return Some(Ok(val));
+            }
+        }
+        Some(self.array.get(self.ctx, Symbol::Index(index)))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = (self.length - self.index) as usize;
+        (remaining, Some(remaining))
+    }
+}
+
+impl ExactSizeIterator for ArrayIter {}
+
 impl JsClass for JsArray {
     fn class() -> &'static Class {
         define_jsclass!(JsArray, Array)
@@ -31,15 +62,80 @@ impl JsClass for JsArray {
 #[allow(non_snake_case)]
 impl JsArray {
     pub fn from_slice(ctx: GcPointer<Context>, slice: &[JsValue]) -> GcPointer<JsObject> {
-        let mut this = Self::new(ctx, slice.len() as _);
+        Self::from_iter(ctx, slice.iter().copied())
+    }
 
-        for i in 0..slice.len() {
-            let val = slice[i];
-            let _ = this.put(ctx, Symbol::Index(i as _), val, false);
+    /// Create an array of `values`. Elements are written to the dense storage directly, only elements past
+    /// [MAX_VECTOR_SIZE] go through `put`.
+    pub fn from_iter(
+        mut ctx: GcPointer<Context>,
+        values: impl IntoIterator<Item = JsValue>,
+    ) -> GcPointer<JsObject> {
+        let values = values.into_iter();
+        letroot!(this = stack, Self::new(ctx, 0));
+        let capacity = values.size_hint().0.min(MAX_VECTOR_SIZE);
+        this.indexed
+            .vector
+            .ensure_capacity(ctx.heap(), capacity as _);
+        let mut length = 0u32;
+        for val in values {
+            if length < MAX_VECTOR_SIZE as u32 {
+                this.indexed.non_gc &= !val.is_object();
+                this.indexed.vector.push_back(ctx.heap(), val);
+                length += 1;
+                this.indexed.set_length(length);
+            } else {
+                let _ = this.put(ctx, Symbol::Index(length), val, false);
+                length += 1;
+            }
+        }
+        if length != 0 && !this.structure.is_indexed() {
+            this.structure = this.structure.change_indexed_transition(ctx);
         }
-
         this
     }
+
+    /// Read elements of `array` up to its length into a vector. Holes are read with `get` so they see the
+    /// prototype chain and getters.
+    pub fn to_vec(
+        ctx: GcPointer<Context>,
+        array: GcPointer<JsObject>,
+    ) -> Result<Vec<JsValue>, JsValue> {
+        Self::iter(ctx, array).collect()
+    }
+
+    /// Read element `index` of `obj`, `None` if it is a hole. Present dense elements are read from the
+    /// storage directly, anything else goes through `has_property` and `get` so holes see the prototype chain.
+    pub fn element(
+        ctx: GcPointer<Context>,
+        mut obj: GcPointer<JsObject>,
+        index: u32,
+    ) -> Result<Option<JsValue>, JsValue> {
+        let indexed = &obj.indexed;
+        if indexed.dense() && index < indexed.vector.size() {
+            let val = *indexed.vector.at(index);
+            if !val.is_empty() {
+                return Ok(Some(val));
+            }
+        }
+        if obj.has_property(ctx, Symbol::Index(index)) {
+            obj.get(ctx, Symbol::Index(index)).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Iterate elements of `array` up to the length it had when the iterator was created. Dense elements are
+    /// read from the storage directly. The iterator does not root the array.
+    pub fn iter(ctx: GcPointer<Context>, array: GcPointer<JsObject>) -> ArrayIter {
+        ArrayIter {
+            ctx,
+            array,
+            index: 0,
+            length: array.indexed.length(),
+        }
+    }
+
     pub fn new(ctx: GcPointer<Context>, n: u32) -> GcPointer<JsObject> {
         let mut arr = JsObject::new(
             ctx,
@@ -57,7 +153,7 @@ impl JsArray {
         mode: EnumerationMode,
     ) {
         if mode == EnumerationMode::IncludeNotEnumerable {
-            collector("length".intern(), 0);
+            collector(Symbol::LENGTH, 0);
         }
         JsObject::GetOwnPropertyNamesMethod(obj, ctx, collector, mode)
     }
@@ -68,7 +164,7 @@ impl JsArray {
         name: Symbol,
         throwable: bool,
     ) -> Result<bool, JsValue> {
-        if name == "length".intern() {
+        if name == Symbol::LENGTH {
             if throwable {
                 let msg = JsString::new(ctx, "delete failed");
                 return Err(JsValue::encode_object_value(JsTypeError::new(
@@ -86,7 +182,7 @@ impl JsArray {
         name: Symbol,
         slot: &mut Slot,
     ) -> bool {
-        if name == "length".intern() {
+        if name == Symbol::LENGTH {
             slot.set_1(
                 JsValue::new(obj.indexed.length() as f64),
                 if obj.indexed.writable() {
@@ -109,7 +205,7 @@ impl JsArray {
         slot: &mut Slot,
         throwable: bool,
     ) -> Result<bool, JsValue> {
-        if name == "length".intern() {
+        if name == Symbol::LENGTH {
             return obj.define_length_property(ctx, desc, throwable);
         }
         JsObject::DefineOwnNonIndexedPropertySlotMethod(obj, ctx, name, desc, slot, throwable)
@@ -322,3 +418,38 @@ impl GcPointer<JsObject> {
         Ok(true)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::vm::array::JsArray;
+    use crate::vm::symbol_table::Internable;
+    use crate::vm::tests::new_context;
+    use crate::vm::value::JsValue;
+
+    #[test]
+    fn test_array_from_iter() {
+        let (_vm, mut ctx) = new_context();
+
+        let array = JsArray::from_iter(ctx, (0..1000).map(JsValue::new));
+        ctx.global_object()
+            .put(ctx, "array".intern(), JsValue::new(array), false)
+            .ok()
+            .unwrap();
+        let result = ctx
+            .eval("array.push('x'); [array.length, array[999], Array.isArray(array)].join()")
+            .ok()
+            .unwrap();
+        assert_eq!(result.get_jsstring().as_str(), "1001,999,true");
+
+        let holes = ctx
+            .eval("Array.prototype[1] = 'proto'; var a = [1, , 3]; a.length = 4; a")
+            .ok()
+            .unwrap()
+            .get_jsobject();
+        let values = JsArray::to_vec(ctx, holes).ok().unwrap();
+        assert_eq!(values.len(), 4);
+        assert_eq!(values[1].get_jsstring().as_str(), "proto");
+        assert!(values[3].is_undefined());
+        assert_eq!(JsArray::iter(ctx, array).len(), 1001);
+    }
+}
@@ -1,15 +1,21 @@
 // TODO: Use mimalloc there?
 use crate::prelude::*;
+use crate::utils::{malloc, unlikely};
 use std::{
-    intrinsics::unlikely,
     mem::{size_of, ManuallyDrop},
     ptr::null_mut,
 };
 
 use super::{class::JsClass, object::TypedJsObject, Context};
+
+/// Callback that releases externally owned ArrayBuffer memory. Receives data pointer and byte length.
+pub type ArrayBufferDeallocator = Box<dyn FnOnce(*mut u8, usize)>;
+
 pub struct JsArrayBuffer {
     pub(crate) data: *mut u8,
     pub(crate) attached: bool,
+    /// Set when data block is not allocated by `malloc::malloc`. Invoked on detach.
+    pub(crate) deallocator: Option<ArrayBufferDeallocator>,
 }
 
 extern "C" fn drop_array_buffer(x: GcPointer<JsObject>) {
@@ -80,12 +86,53 @@ impl JsArrayBuffer {
         *this.data::<Self>() = ManuallyDrop::new(Self {
             data: null_mut(),
             attached: false,
+            deallocator: None,
         });
 
         *this.direct_mut(Self::BYTE_LENGTH_OFFSET) = JsValue::new(0u32);
         this
     }
 
+    /// Create ArrayBuffer that takes ownership of `vec` without copying it. Memory is released
+    /// when buffer is detached or collected.
+    pub fn from_vec(ctx: GcPointer<Context>, vec: Vec<u8>) -> Result<GcPointer<JsObject>, JsValue> {
+        let data = Box::into_raw(vec.into_boxed_slice());
+        let size = unsafe { (*data).len() };
+        unsafe {
+            Self::from_raw_parts(
+                ctx,
+                data.cast::<u8>(),
+                size,
+                Box::new(move |_, _| drop(Box::from_raw(data))),
+            )
+        }
+    }
+
+    /// Create ArrayBuffer over `size` bytes at `data`. `deallocator` is invoked exactly once when buffer
+    /// is detached or collected, if buffer creation fails it is invoked before returning error.
+    ///
+    /// # Safety
+    /// `data` must be valid for reads and writes of `size` bytes until `deallocator` is called.
+    pub unsafe fn from_raw_parts(
+        ctx: GcPointer<Context>,
+        data: *mut u8,
+        size: usize,
+        deallocator: ArrayBufferDeallocator,
+    ) -> Result<GcPointer<JsObject>, JsValue> {
+        if unlikely(size > u32::MAX as usize) {
+            deallocator(data, size);
+            let msg = JsString::new(ctx, "Cannot allocate a data block for the ArrayBuffer");
+            return Err(JsValue::new(JsRangeError::new(ctx, msg, None)));
+        }
+        let this = Self::new(ctx);
+        let mut buffer = TypedJsObject::<Self>::new(this);
+        buffer.data = data;
+        buffer.deallocator = Some(deallocator);
+        buffer.attached = true;
+        buffer.set_size(size);
+        Ok(this)
+    }
+
     pub fn attached(&self) -> bool {
         self.attached
     }
@@ -113,7 +160,7 @@ impl TypedJsObject<JsArrayBuffer> {
     pub fn byte_length(&self) -> usize {
         self.object()
             .direct(JsArrayBuffer::BYTE_LENGTH_OFFSET)
-            .get_int32() as u32 as usize
+            .get_number() as usize
     }
     pub fn size(&self) -> usize {
         self.byte_length()
@@ -132,7 +179,10 @@ impl TypedJsObject<JsArrayBuffer> {
     pub fn detach(&mut self) {
         if !self.data.is_null() {
             unsafe {
-                libc::free(self.data.cast());
+                match self.deallocator.take() {
+                    Some(deallocator) => deallocator(self.data, self.byte_length()),
+                    None => malloc::free(self.data.cast()),
+                }
                 self.data = null_mut();
                 self.set_size(0);
             }
@@ -161,9 +211,9 @@ impl TypedJsObject<JsArrayBuffer> {
         }
         unsafe {
             self.data = if zero {
-                libc::calloc(1, size).cast()
+                malloc::calloc(1, size).cast()
             } else {
-                libc::malloc(size).cast()
+                malloc::malloc(size).cast()
             };
 
             if unlikely(self.data.is_null()) {
@@ -176,3 +226,87 @@ impl TypedJsObject<JsArrayBuffer> {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::tests::new_context;
+    use std::{cell::RefCell, rc::Rc};
+
+    #[test]
+    fn test_from_vec() {
+        let (_vm, ctx) = new_context();
+        let buffer = JsArrayBuffer::from_vec(ctx, vec![1, 2, 3]).unwrap_or_else(|_| panic!());
+        let mut buffer = TypedJsObject::<JsArrayBuffer>::new(buffer);
+        assert!(buffer.attached());
+        assert_eq!(buffer.byte_length(), 3);
+        assert_eq!(buffer.data(), &[1, 2, 3]);
+        buffer.data_mut()[0] = 4;
+        assert_eq!(buffer.data(), &[4, 2, 3]);
+        buffer.detach();
+        assert!(!buffer.attached());
+        assert_eq!(buffer.byte_length(), 0);
+    }
+
+    #[test]
+    fn test_byte_length_above_i32() {
+        let (_vm, ctx) = new_context();
+        let size = 3 * 1024 * 1024 * 1024usize;
+        let freed = Rc::new(RefCell::new(vec![]));
+        let log = freed.clone();
+        // never read, only the length is checked.
+        let buffer = unsafe {
+            JsArrayBuffer::from_raw_parts(
+                ctx,
+                std::ptr::NonNull::dangling().as_ptr(),
+                size,
+                Box::new(move |_, size| log.borrow_mut().push(size)),
+            )
+        }
+        .unwrap_or_else(|_| panic!());
+        let mut buffer = TypedJsObject::<JsArrayBuffer>::new(buffer);
+        assert_eq!(buffer.byte_length(), size);
+        buffer.detach();
+        assert_eq!(*freed.borrow(), vec![size]);
+    }
+
+    /// Create `count` external buffers that record their index in `freed` when deallocated, buffers with even
+    /// index are detached right away.
+    #[inline(never)]
+    fn external_buffers(ctx: GcPointer<Context>, count: usize, freed: &Rc<RefCell<Vec<usize>>>) {
+        for i in 0..count {
+            let data = Box::into_raw(vec![i as u8; 16].into_boxed_slice());
+            let log = freed.clone();
+            let buffer = unsafe {
+                JsArrayBuffer::from_raw_parts(
+                    ctx,
+                    data.cast(),
+                    16,
+                    Box::new(move |_, _| {
+                        drop(Box::from_raw(data));
+                        log.borrow_mut().push(i);
+                    }),
+                )
+            }
+            .unwrap_or_else(|_| panic!());
+            if i % 2 == 0 {
+                TypedJsObject::<JsArrayBuffer>::new(buffer).detach();
+                assert_eq!(freed.borrow().last(), Some(&i));
+            }
+        }
+    }
+
+    #[test]
+    fn test_deallocator_runs_once() {
+        let (vm, mut ctx) = new_context();
+        let freed = Rc::new(RefCell::new(vec![]));
+        external_buffers(ctx, 64, &freed);
+        assert_eq!(freed.borrow().len(), 32);
+        // unreachable buffers are finalized by the collection, the rest when the heap is dropped.
+        ctx.heap().gc();
+        unsafe { vm.dispose() };
+        let mut freed = freed.borrow().clone();
+        freed.sort_unstable();
+        assert_eq!(freed, (0..64).collect::<Vec<_>>());
+    }
+}
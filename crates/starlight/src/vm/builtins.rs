@@ -9,7 +9,8 @@
 //!
 
 use super::{
-    arguments::*, array::*, error::*, interpreter::frame::CallFrame, string::*, symbol_table::*,
+    arguments::*, array::*, attributes::*, error::*, interpreter::frame::CallFrame,
+    interpreter::not_callable_error, object::*, property_descriptor::*, string::*, symbol_table::*,
 };
 use super::{value::*, Context};
 use crate::gc::cell::GcPointer;
@@ -18,7 +19,7 @@ use crate::vm::class::JsClass;
 pub unsafe fn reflect_apply(
     ctx: GcPointer<Context>,
     frame: &mut CallFrame,
-    _ip: &mut *mut u8,
+    ip: &mut *mut u8,
     _argc: u32,
     effect: u8,
 ) -> Result<(), JsValue> {
@@ -43,15 +44,17 @@ pub unsafe fn reflect_apply(
         argsv.push(args.get(ctx, Symbol::Index(i))?);
     }
 
+    // `call_builtin` has three operands.
+    let pc = ip.sub(13);
     if !func.is_callable() {
-        let msg = JsString::new(ctx, "not a callable object");
-        return Err(JsValue::encode_object_value(JsTypeError::new(
-            ctx, msg, None,
-        )));
+        return Err(not_callable_error(ctx, frame, pc, effect != 0));
     }
     letroot!(func_object = gcstack, func.get_jsobject());
     letroot!(funcc = gcstack, func.get_jsobject());
     let func = func_object.as_function_mut();
+    if effect != 0 && !func.is_constructor() {
+        return Err(not_callable_error(ctx, frame, pc, true));
+    }
 
     letroot!(args_ = gcstack, Arguments::new(this, &mut argsv));
     let result = if effect == 0 {
@@ -84,9 +87,66 @@ pub unsafe fn to_object(
     Ok(())
 }
 
+/// Define method or accessor of a class on the class constructor or its prototype. Takes target object, key
+/// and function from the stack and pushes the target back. `effect` is 0 for methods, 1 for getters and 2
+/// for setters. Class elements are not enumerable, accessors keep the other half of an accessor pair.
+pub unsafe fn define_method(
+    ctx: GcPointer<Context>,
+    frame: &mut CallFrame,
+    _ip: &mut *mut u8,
+    _argc: u32,
+    effect: u8,
+) -> Result<(), JsValue> {
+    let function = frame.pop();
+    let key = frame.pop().to_symbol(ctx)?;
+    let target = frame.pop();
+    let mut object = target.get_jsobject();
+    match effect {
+        0 => object.define_own_property(ctx, key, &*DataDescriptor::new(function, W | C), true)?,
+        1 => object.define_own_property(
+            ctx,
+            key,
+            &*AccessorDescriptor::new(function, JsValue::UNDEFINED, C | UNDEF_SETTER),
+            true,
+        )?,
+        _ => object.define_own_property(
+            ctx,
+            key,
+            &*AccessorDescriptor::new(JsValue::UNDEFINED, function, C | UNDEF_GETTER),
+            true,
+        )?,
+    };
+    frame.push(target);
+    Ok(())
+}
+
+/// Create global variable named by the `argc` operand, an index into `names`, unless the global object or its
+/// prototype has that property already. Top-level `var` and function declarations of scripts are declared
+/// this way before any code of the script runs. Pushes `undefined`.
+pub unsafe fn declare_global(
+    ctx: GcPointer<Context>,
+    frame: &mut CallFrame,
+    _ip: &mut *mut u8,
+    argc: u32,
+    _effect: u8,
+) -> Result<(), JsValue> {
+    let name = frame.code_block.unwrap().names[argc as usize];
+    let mut global = ctx.global_object();
+    if !global.has_property(ctx, name) {
+        global.put(ctx, name, JsValue::UNDEFINED, false)?;
+    }
+    frame.push(JsValue::UNDEFINED);
+    Ok(())
+}
+
 pub type Builtin =
     unsafe fn(GcPointer<Context>, &mut CallFrame, &mut *mut u8, u32, u8) -> Result<(), JsValue>;
 
-pub static BUILTIN_FUNCS: [Builtin; 1] = [reflect_apply];
+pub static BUILTIN_FUNCS: [Builtin; 3] = [reflect_apply, define_method, declare_global];
+
+pub const BUILTIN_ARGS: [usize; 3] = [3, 3, 0];
 
-pub const BUILTIN_ARGS: [usize; 1] = [3];
+/// Index of [define_method] in [BUILTIN_FUNCS].
+pub const BUILTIN_DEFINE_METHOD: u32 = 1;
+/// Index of [declare_global] in [BUILTIN_FUNCS].
+pub const BUILTIN_DECLARE_GLOBAL: u32 = 2;
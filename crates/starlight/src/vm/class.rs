@@ -15,7 +15,8 @@ use crate::gc::cell::{GcPointer, Visitor};
 
 /// Simple tpe that is used to implement custom JS objects.
 pub struct Class {
-    /// Class name. `Object.prototype.toString` will print this name.
+    /// Class name. `Object.prototype.toString` prints it for `Error`, `Boolean`, `String`, `Date` and
+    /// `RegExp` objects, other classes are reported through `Symbol.toStringTag`.
     pub name: &'static str,
     /// Class method table.
     pub method_table: MethodTable,
@@ -8,7 +8,9 @@ use crate::bytecode::opcodes::*;
 use starlight_bytecode::virtual_register;
 use comet::internal::finalize_trait::FinalizeTrait;
 use super::context::Context;
+use crate::bytecompiler::{LazyFunction, ScopeRef};
 use crate::interpreter::frame_register_count_for;
+use crate::jit::JitState;
 use crate::gc::{cell::GcPointer, cell::Visitor};
 use crate::{
     bytecode::TypeFeedBack,
@@ -16,6 +18,7 @@ use crate::{
 };
 use std::rc::Rc;
 use std::{fmt::Write, ops::Range};
+use swc_common::sync::Lrc;
 
 pub struct FileLocation {
     pub line: u32,
@@ -72,6 +75,31 @@ impl StackSizeState {
         Ok(())
     }
 }
+/// Names of bindings of a code block by their index, see [CodeBlock::var_names]. Hidden variables like
+/// `@completion` keep their internal name, indexes of temporaries have none.
+#[derive(Default, Clone)]
+pub struct VariableNames {
+    pub env: Vec<Option<Symbol>>,
+    pub slots: Vec<Option<Symbol>>,
+}
+
+impl VariableNames {
+    pub fn env(&self, index: usize) -> Option<Symbol> {
+        self.env.get(index).copied().flatten()
+    }
+
+    pub fn slot(&self, index: usize) -> Option<Symbol> {
+        self.slots.get(index).copied().flatten()
+    }
+
+    pub(crate) fn set(names: &mut Vec<Option<Symbol>>, index: usize, name: Symbol) {
+        if names.len() <= index {
+            names.resize(index + 1, None);
+        }
+        names[index] = Some(name);
+    }
+}
+
 /// A type representing single JS function bytecode.
 //#[derive(GcTrace)]
 #[repr(C)]
@@ -85,6 +113,15 @@ pub struct CodeBlock {
     pub var_count: u32,
     /// Parameters count
     pub param_count: u32,
+    /// Number of stack slots reserved in call frame for bindings that are not captured by closures.
+    /// Parameters keep their position in slots.
+    pub slot_count: u32,
+    /// Does call allocate new [Environment](super::environment::Environment)? When false all bindings live in
+    /// stack slots and function runs in the scope it was created in.
+    pub needs_environment: bool,
+    /// Every parameter is stored in a stack slot. Calls then leave arguments where the caller pushed them and
+    /// do not copy them into the environment.
+    pub params_in_slots: bool,
     /// Rest parameter position in argument list
     pub rest_at: Option<u32>,
     /// Names
@@ -102,6 +139,9 @@ pub struct CodeBlock {
     pub strict: bool,
     /// Feedback vector that is used for inline caching
     pub feedback: Vec<TypeFeedBack>,
+    /// Number of calls and loop iterations, used to decide when to JIT compile this code block.
+    pub hotness: u32,
+    pub jit_state: JitState,
 
     /// Does code internally use `arguments` variable?
     pub use_arguments: bool,
@@ -116,6 +156,21 @@ pub struct CodeBlock {
     pub path: Rc<str>,
     pub is_generator: bool,
     pub is_async: bool,
+    /// Source of a function that was skipped by the pre-parser. Set until the first call compiles the body,
+    /// bytecode and nested code blocks are empty meanwhile.
+    pub lazy: Option<Box<LazyFunction>>,
+    /// Compile-time scopes of direct `eval` calls in this code block, see [Opcode::OP_CALL_EVAL].
+    pub eval_scopes: Vec<ScopeRef>,
+    /// Was this code block compiled with debugger attached? `ret` reports function exit to the debugger then.
+    pub debug: bool,
+    /// Text of callees of `call`, `new` and spread calls by offset of the instruction, sorted by offset. Used
+    /// by `TypeError` messages, see [CodeBlock::callee_name].
+    pub callee_names: Vec<(u32, Box<str>)>,
+    /// Names of environment variables and stack slots, recorded when compiled with `Options::debug_info` or with
+    /// debugger attached.
+    pub var_names: Option<Box<VariableNames>>,
+    /// Script this function is declared in and byte range of the function in it, see [CodeBlock::source_text].
+    pub source: Option<(Lrc<String>, Range<usize>)>,
 }
 
 impl Trace for CodeBlock {
@@ -132,6 +187,27 @@ impl GcPointer<CodeBlock> {
     }
 }
 impl CodeBlock {
+    /// Source text of this function returned by `Function.prototype.toString`. `None` for scripts.
+    pub fn source_text(&self) -> Option<&str> {
+        self.source
+            .as_ref()
+            .map(|(source, range)| &source[range.clone()])
+    }
+
+    /// Move locations of this code block and nested functions for source starting at zero-based `line` and
+    /// `col` of its file. Only locations on the first line of the source get `col` added.
+    pub(crate) fn offset_locations(&mut self, line: u32, col: u32) {
+        for (_, loc) in self.loc.iter_mut() {
+            if loc.line == 1 {
+                loc.col += col;
+            }
+            loc.line += line;
+        }
+        for nested in self.codes.iter_mut() {
+            nested.offset_locations(line, col);
+        }
+    }
+
     /// Print bytecode to `output`.
     pub fn display_to<T: Write>(&self, output: &mut T) -> std::fmt::Result {
         unsafe {
@@ -188,6 +264,40 @@ impl CodeBlock {
                         pc = pc.add(4);
                         writeln!(output, "put_by_id {}, fdbk {}", name, feedback)?;
                     }
+                    Opcode::OP_GET_SLOT => {
+                        let index = pc.cast::<u32>().read_unaligned();
+                        pc = pc.add(4);
+                        writeln!(output, "get_slot {}", index)?;
+                    }
+                    Opcode::OP_SET_SLOT => {
+                        let index = pc.cast::<u32>().read_unaligned();
+                        pc = pc.add(4);
+                        writeln!(output, "set_slot {}", index)?;
+                    }
+                    Opcode::OP_INC_SLOT => {
+                        let index = pc.cast::<u32>().read_unaligned();
+                        pc = pc.add(4);
+                        writeln!(output, "inc_slot {}", index)?;
+                    }
+                    Opcode::OP_DEC_SLOT => {
+                        let index = pc.cast::<u32>().read_unaligned();
+                        pc = pc.add(4);
+                        writeln!(output, "dec_slot {}", index)?;
+                    }
+                    Opcode::OP_GET_GLOBAL => {
+                        let name = pc.cast::<u32>().read_unaligned();
+                        pc = pc.add(4);
+                        let feedback = pc.cast::<u32>().read_unaligned();
+                        pc = pc.add(4);
+                        writeln!(output, "get_global {}, fdbk {}", name, feedback)?;
+                    }
+                    Opcode::OP_PUT_GLOBAL => {
+                        let name = pc.cast::<u32>().read_unaligned();
+                        pc = pc.add(4);
+                        let feedback = pc.cast::<u32>().read_unaligned();
+                        pc = pc.add(4);
+                        writeln!(output, "put_global {}, fdbk {}", name, feedback)?;
+                    }
                     Opcode::OP_PUSH_LITERAL => {
                         let ix = pc.cast::<u32>().read_unaligned();
                         pc = pc.add(4);
@@ -260,6 +370,13 @@ impl CodeBlock {
                         pc = pc.add(4);
                         writeln!(output, "tail_call <{}>", argc)?;
                     }
+                    Opcode::OP_CALL_EVAL => {
+                        let argc = pc.cast::<u32>().read_unaligned();
+                        pc = pc.add(4);
+                        let scope = pc.cast::<u32>().read_unaligned();
+                        pc = pc.add(4);
+                        writeln!(output, "call_eval <{}>, scope {}", argc, scope)?;
+                    }
                     Opcode::OP_INSTANCEOF => {
                         writeln!(output, "instanceof")?;
                     }
@@ -297,6 +414,14 @@ impl CodeBlock {
                         pc = pc.add(4);
                         writeln!(output, "sub")?;
                     }
+                    Opcode::OP_ADD_INT => {
+                        pc = pc.add(4);
+                        writeln!(output, "add_int")?;
+                    }
+                    Opcode::OP_SUB_INT => {
+                        pc = pc.add(4);
+                        writeln!(output, "sub_int")?;
+                    }
                     Opcode::OP_DIV => {
                         pc = pc.add(4);
                         writeln!(output, "div")?;
@@ -474,6 +599,22 @@ impl CodeBlock {
                     Opcode::OP_DELETE_BY_VAL => {
                         writeln!(output, "delete")?;
                     }
+                    Opcode::OP_PUSH_EMPTY => writeln!(output, "push_empty")?,
+                    Opcode::OP_CHECK_TDZ => {
+                        let id = pc.cast::<u32>().read_unaligned();
+                        pc = pc.add(4);
+                        writeln!(output, "check_tdz {}", id)?;
+                    }
+                    Opcode::OP_THROW_CONST_ASSIGN => {
+                        let id = pc.cast::<u32>().read_unaligned();
+                        pc = pc.add(4);
+                        writeln!(output, "throw_const_assign {}", id)?;
+                    }
+                    Opcode::OP_DEBUG => {
+                        let kind = pc.cast::<u32>().read_unaligned();
+                        pc = pc.add(4);
+                        writeln!(output, "debug {}", kind)?;
+                    }
 
                     Opcode::OP_FORIN_LEAVE => {
                         writeln!(output, "for_in_leave")?;
@@ -515,6 +656,33 @@ impl CodeBlock {
                     Opcode::OP_YIELD => writeln!(output, "yield")?,
                     Opcode::OP_YIELD_STAR => writeln!(output, "yield_star")?,
                     Opcode::OP_AWAIT => writeln!(output, "await")?,
+                    // superinstructions print only their first half, second half follows as usual.
+                    Opcode::OP_PUSH_INT_ADD => {
+                        let ix = pc.cast::<u32>().read_unaligned();
+                        pc = pc.add(4);
+                        writeln!(output, "push_int <{}> (fused with add)", ix as i32)?;
+                    }
+                    Opcode::OP_GE0GL_GET_BY_ID => {
+                        let ix = pc.cast::<u32>().read_unaligned();
+                        pc = pc.add(4);
+                        writeln!(
+                            output,
+                            "get_environment 0->get_local {} (fused with get_by_id)",
+                            ix
+                        )?;
+                    }
+                    Opcode::OP_LESS_JMP_IF_FALSE => {
+                        writeln!(output, "less (fused with jmp_if_false)")?
+                    }
+                    Opcode::OP_LESSEQ_JMP_IF_FALSE => {
+                        writeln!(output, "lesseq (fused with jmp_if_false)")?
+                    }
+                    Opcode::OP_GREATER_JMP_IF_FALSE => {
+                        writeln!(output, "greater (fused with jmp_if_false)")?
+                    }
+                    Opcode::OP_GREATEREQ_JMP_IF_FALSE => {
+                        writeln!(output, "greatereq (fused with jmp_if_false)")?
+                    }
                     _ => todo!("{:?}", op),
                 }
             }
@@ -558,6 +726,20 @@ impl CodeBlock {
 
                     stack_len += 1;
                 }
+                OP_CALL_EVAL => {
+                    let p = pos as usize;
+                    let argc = u32::from_ne_bytes([
+                        self.code[p],
+                        self.code[p + 1],
+                        self.code[p + 2],
+                        self.code[p + 3],
+                    ]);
+                    pos += 8;
+                    stack_len -= argc as u16;
+                    stack_len -= 2;
+
+                    stack_len += 1;
+                }
                 OP_TAILCALL | OP_TAILNEW => {
                     let p = pos as usize;
                     let argc = u32::from_ne_bytes([
@@ -606,7 +788,26 @@ impl CodeBlock {
                     stack_len -= 1;
                     stack_len += 1;
                 }
-                OP_REM | OP_MUL | OP_DIV | OP_SUB | OP_ADD => {
+                OP_GET_SLOT => {
+                    pos += 4;
+                    stack_len += 1;
+                }
+                OP_SET_SLOT => {
+                    pos += 4;
+                    stack_len -= 1;
+                }
+                OP_INC_SLOT | OP_DEC_SLOT => {
+                    pos += 4;
+                }
+                OP_GET_GLOBAL => {
+                    pos += 8;
+                    stack_len += 1;
+                }
+                OP_PUT_GLOBAL => {
+                    pos += 8;
+                    stack_len -= 1;
+                }
+                OP_REM | OP_MUL | OP_DIV | OP_SUB | OP_ADD | OP_ADD_INT | OP_SUB_INT => {
                     pos += 4;
                     stack_len -= 2;
                     stack_len += 1;
@@ -720,6 +921,17 @@ impl CodeBlock {
                 OP_DELETE_BY_ID => {
                     pos += 4;
                 }
+                OP_PUSH_EMPTY => stack_len += 1,
+                OP_CHECK_TDZ => {
+                    pos += 4;
+                }
+                OP_THROW_CONST_ASSIGN => {
+                    pos += 4;
+                    stack_len -= 1;
+                }
+                OP_DEBUG => {
+                    pos += 4;
+                }
                 OP_DELETE_BY_VAL => {
                     stack_len -= 2;
                     stack_len += 1;
@@ -794,6 +1006,25 @@ impl CodeBlock {
     pub fn num_callee_locals(&self) -> u32 {
         self.num_callee_locals
     }
+    /// Source location of the innermost statement containing bytecode offset `pc`.
+    pub fn location_at(&self, pc: usize) -> Option<&FileLocation> {
+        self.loc
+            .iter()
+            .filter(|(range, _)| range.contains(&pc))
+            .min_by_key(|(range, _)| range.len())
+            .map(|(_, loc)| loc)
+    }
+    /// Text of the callee of call instruction at `pc`, e.g. `obj.method`.
+    pub fn callee_name(&self, pc: usize) -> Option<&str> {
+        self.callee_names
+            .binary_search_by_key(&(pc as u32), |(at, _)| *at)
+            .ok()
+            .map(|i| &*self.callee_names[i].1)
+    }
+    /// Line of the first statement of this code block, 0 when no locations were recorded.
+    pub fn first_line(&self) -> u32 {
+        self.loc.iter().map(|(_, loc)| loc.line).min().unwrap_or(0)
+    }
     /// Create new empty code block.
     pub fn new(
         mut ctx: GcPointer<Context>,
@@ -820,10 +1051,25 @@ impl CodeBlock {
             use_arguments: false,
             literals: vec![],
             feedback: vec![],
+            hotness: 0,
+            jit_state: JitState::Interpreted,
             var_count: 0,
             param_count: 0,
+            slot_count: 0,
+            needs_environment: true,
+            params_in_slots: false,
             is_async: false,
             is_generator: false,
+            lazy: None,
+            eval_scopes: vec![],
+            debug: false,
+            callee_names: vec![],
+            var_names: if ctx.vm.options.debug_info || ctx.vm.debugger.is_some() {
+                Some(Default::default())
+            } else {
+                None
+            },
+            source: None,
         };
 
         ctx.heap().allocate(this)
@@ -0,0 +1,651 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! Code cache: compiled top-level code blocks serialized independently of heap snapshots.
+//!
+//! Cache is bound to the exact source text it was compiled from (FNV-1a hash of the source is stored in
+//! the header) and to the Starlight version that produced it, mismatching cache is rejected and script is
+//! compiled again.
+//!
+//! ```rust,ignore
+//! let mut cache = std::fs::read("app.jsc").unwrap_or_default();
+//! ctx.eval_cached(Some("app.js"), &source, &mut cache)?;
+//! std::fs::write("app.jsc", &cache)?;
+//! ```
+//!
+//! [CompiledScript] separates compiling a script from running it, e.g. to run the same code many times or
+//! to compile once when an application starts and ship the bytecode to other contexts.
+use super::{
+    code_block::{CodeBlock, FileLocation, VariableNames},
+    context::Context,
+    string::JsString,
+    symbol_table::{symbol_table, Internable, Symbol},
+    value::JsValue,
+    PersistentRooted,
+};
+use crate::{
+    bytecode::TypeFeedBack,
+    bytecompiler::ByteCompiler,
+    gc::cell::GcPointer,
+    jsrt::regexp::{regexp_constructor, JsRegExp},
+    vm::{arguments::Arguments, class::JsClass},
+};
+use std::{collections::HashMap, fmt, rc::Rc};
+use swc_common::sync::Lrc;
+
+const MAGIC: &[u8; 4] = b"SLCC";
+/// Bump when bytecode encoding or layout of this format changes.
+const FORMAT_VERSION: u32 = 6;
+const STARLIGHT_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CodeCacheError {
+    BadMagic,
+    /// Cache was produced by another format version or Starlight release.
+    VersionMismatch,
+    /// Cache was compiled from different source text.
+    SourceMismatch,
+    Truncated,
+    /// Cache contains invalid data.
+    Corrupted,
+    /// Code block contains literal that can't be serialized (e.g. module loader).
+    UnsupportedLiteral,
+    /// Code block contains function skipped by lazy compilation.
+    NotCompiled,
+    /// Code block calls `eval` directly, compile-time scopes of such calls are not serialized.
+    DirectEval,
+}
+
+impl fmt::Display for CodeCacheError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BadMagic => write!(f, "not a code cache"),
+            Self::VersionMismatch => write!(f, "code cache version mismatch"),
+            Self::SourceMismatch => write!(f, "code cache does not match source"),
+            Self::Truncated => write!(f, "code cache is truncated"),
+            Self::Corrupted => write!(f, "code cache is corrupted"),
+            Self::UnsupportedLiteral => write!(f, "code block contains unsupported literal"),
+            Self::NotCompiled => write!(f, "code block contains function that is not compiled yet"),
+            Self::DirectEval => write!(f, "code block contains direct eval"),
+        }
+    }
+}
+
+impl std::error::Error for CodeCacheError {}
+
+/// 64-bit FNV-1a. Unlike `DefaultHasher` its output is stable between Rust releases.
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash = 0xcbf29ce484222325u64;
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+struct Writer {
+    buf: Vec<u8>,
+}
+
+impl Writer {
+    fn u8(&mut self, x: u8) {
+        self.buf.push(x);
+    }
+    fn u32(&mut self, x: u32) {
+        self.buf.extend_from_slice(&x.to_le_bytes());
+    }
+    fn u64(&mut self, x: u64) {
+        self.buf.extend_from_slice(&x.to_le_bytes());
+    }
+    fn str(&mut self, s: &str) {
+        self.u32(s.len() as u32);
+        self.buf.extend_from_slice(s.as_bytes());
+    }
+    fn symbol(&mut self, sym: Symbol) {
+        match sym {
+            Symbol::Key(id) => {
+                self.u8(0);
+                self.str(symbol_table().description(id));
+            }
+            Symbol::Private(id) => {
+                self.u8(1);
+                self.str(symbol_table().description(id));
+            }
+            Symbol::Index(index) => {
+                self.u8(2);
+                self.u32(index);
+            }
+        }
+    }
+
+    fn names(&mut self, names: &[Option<Symbol>]) {
+        self.u32(names.len() as u32);
+        for name in names.iter() {
+            match name {
+                Some(name) => {
+                    self.u8(1);
+                    self.symbol(*name);
+                }
+                None => self.u8(0),
+            }
+        }
+    }
+
+    fn literal(&mut self, value: JsValue) -> Result<(), CodeCacheError> {
+        if value.is_undefined() {
+            self.u8(0);
+        } else if value.is_null() {
+            self.u8(1);
+        } else if value.is_bool() {
+            self.u8(2);
+            self.u8(value.get_bool() as u8);
+        } else if value.is_int32() {
+            self.u8(3);
+            self.u32(value.get_int32() as u32);
+        } else if value.is_double() {
+            self.u8(4);
+            self.u64(value.get_double().to_bits());
+        } else if value.is_jsstring() {
+            self.u8(5);
+            self.str(value.get_jsstring().as_str());
+        } else if value.is_jsobject() && value.get_jsobject().is_class(JsRegExp::class()) {
+            let object = value.get_jsobject();
+            let regexp = object.data::<JsRegExp>();
+            self.u8(6);
+            self.str(&regexp.original_source);
+            self.str(&regexp.original_flags);
+        } else {
+            return Err(CodeCacheError::UnsupportedLiteral);
+        }
+        Ok(())
+    }
+
+    fn code_block(&mut self, code: &CodeBlock) -> Result<(), CodeCacheError> {
+        if code.lazy.is_some() {
+            return Err(CodeCacheError::NotCompiled);
+        }
+        if !code.eval_scopes.is_empty() {
+            return Err(CodeCacheError::DirectEval);
+        }
+        self.symbol(code.name);
+        self.u32(code.stack_size);
+        self.u32(code.num_callee_locals);
+        self.u32(code.var_count);
+        self.u32(code.slot_count);
+        self.u32(code.param_count);
+        self.u32(code.rest_at.map(|x| x + 1).unwrap_or(0));
+        self.u32(code.args_at);
+        for flag in [
+            code.top_level,
+            code.strict,
+            code.use_arguments,
+            code.is_constructor,
+            code.is_generator,
+            code.is_async,
+            code.needs_environment,
+            code.params_in_slots,
+        ]
+        .iter()
+        {
+            self.u8(*flag as u8);
+        }
+        self.str(&code.file_name);
+        self.str(&code.path);
+
+        self.u32(code.names.len() as u32);
+        for name in code.names.iter() {
+            self.symbol(*name);
+        }
+        self.u32(code.callee_names.len() as u32);
+        for (pc, name) in code.callee_names.iter() {
+            self.u32(*pc);
+            self.str(name);
+        }
+        match code.var_names {
+            Some(ref names) => {
+                self.u8(1);
+                self.names(&names.env);
+                self.names(&names.slots);
+            }
+            None => self.u8(0),
+        }
+        self.u32(code.code.len() as u32);
+        self.buf.extend_from_slice(&code.code);
+        self.u32(code.literals.len() as u32);
+        for literal in code.literals.iter() {
+            self.literal(*literal)?;
+        }
+        self.u32(code.feedback.len() as u32);
+        self.u32(code.loc.len() as u32);
+        for (range, loc) in code.loc.iter() {
+            self.u64(range.start as u64);
+            self.u64(range.end as u64);
+            self.u32(loc.line);
+            self.u32(loc.col);
+        }
+        match code.source {
+            Some((_, ref range)) => {
+                self.u8(1);
+                self.u64(range.start as u64);
+                self.u64(range.end as u64);
+            }
+            None => self.u8(0),
+        }
+        self.u32(code.codes.len() as u32);
+        for nested in code.codes.iter() {
+            self.code_block(nested)?;
+        }
+        Ok(())
+    }
+}
+
+struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+    /// Source the cache belongs to, function source ranges refer to it.
+    source: Lrc<String>,
+    /// String literals read so far. Like [LiteralPool](crate::bytecompiler::LiteralPool) they are shared by
+    /// all code blocks.
+    strings: HashMap<&'a str, GcPointer<JsString>>,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], CodeCacheError> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|end| *end <= self.buf.len())
+            .ok_or(CodeCacheError::Truncated)?;
+        let bytes = &self.buf[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+    fn u8(&mut self) -> Result<u8, CodeCacheError> {
+        Ok(self.bytes(1)?[0])
+    }
+    fn bool(&mut self) -> Result<bool, CodeCacheError> {
+        Ok(self.u8()? != 0)
+    }
+    fn u32(&mut self) -> Result<u32, CodeCacheError> {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(self.bytes(4)?);
+        Ok(u32::from_le_bytes(bytes))
+    }
+    fn u64(&mut self) -> Result<u64, CodeCacheError> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.bytes(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+    fn str(&mut self) -> Result<&'a str, CodeCacheError> {
+        let len = self.u32()? as usize;
+        std::str::from_utf8(self.bytes(len)?).map_err(|_| CodeCacheError::Corrupted)
+    }
+    fn symbol(&mut self) -> Result<Symbol, CodeCacheError> {
+        match self.u8()? {
+            0 => Ok(self.str()?.intern()),
+            1 => Ok(self.str()?.intern().private()),
+            2 => Ok(Symbol::Index(self.u32()?)),
+            _ => Err(CodeCacheError::Corrupted),
+        }
+    }
+
+    fn names(&mut self) -> Result<Vec<Option<Symbol>>, CodeCacheError> {
+        let mut names = vec![];
+        for _ in 0..self.u32()? {
+            names.push(if self.bool()? {
+                Some(self.symbol()?)
+            } else {
+                None
+            });
+        }
+        Ok(names)
+    }
+
+    fn literal(&mut self, ctx: GcPointer<Context>) -> Result<JsValue, CodeCacheError> {
+        Ok(match self.u8()? {
+            0 => JsValue::encode_undefined_value(),
+            1 => JsValue::encode_null_value(),
+            2 => JsValue::encode_bool_value(self.bool()?),
+            3 => JsValue::encode_int32(self.u32()? as i32),
+            4 => JsValue::encode_f64_value(f64::from_bits(self.u64()?)),
+            5 => {
+                let string = self.str()?;
+                let cell = *self
+                    .strings
+                    .entry(string)
+                    .or_insert_with(|| JsString::new(ctx, string));
+                JsValue::new(cell)
+            }
+            6 => {
+                let source = JsString::new(ctx, self.str()?);
+                let flags = JsString::new(ctx, self.str()?);
+                let mut args = [JsValue::new(source), JsValue::new(flags)];
+                let args = Arguments::new(JsValue::encode_undefined_value(), &mut args);
+                regexp_constructor(ctx, &args).map_err(|_| CodeCacheError::UnsupportedLiteral)?
+            }
+            _ => return Err(CodeCacheError::Corrupted),
+        })
+    }
+
+    fn code_block(
+        &mut self,
+        ctx: GcPointer<Context>,
+    ) -> Result<GcPointer<CodeBlock>, CodeCacheError> {
+        let name = self.symbol()?;
+        let stack_size = self.u32()?;
+        let num_callee_locals = self.u32()?;
+        let var_count = self.u32()?;
+        let slot_count = self.u32()?;
+        let param_count = self.u32()?;
+        let rest_at = self.u32()?.checked_sub(1);
+        let args_at = self.u32()?;
+        let top_level = self.bool()?;
+        let strict = self.bool()?;
+        let use_arguments = self.bool()?;
+        let is_constructor = self.bool()?;
+        let is_generator = self.bool()?;
+        let is_async = self.bool()?;
+        let needs_environment = self.bool()?;
+        let params_in_slots = self.bool()?;
+        let file_name = self.str()?.to_owned();
+        let path = self.str()?;
+
+        let mut code = CodeBlock::new(ctx, name, strict, path.into());
+        code.stack_size = stack_size;
+        code.num_callee_locals = num_callee_locals;
+        code.var_count = var_count;
+        code.slot_count = slot_count;
+        code.param_count = param_count;
+        code.rest_at = rest_at;
+        code.args_at = args_at;
+        code.top_level = top_level;
+        code.use_arguments = use_arguments;
+        code.is_constructor = is_constructor;
+        code.is_generator = is_generator;
+        code.is_async = is_async;
+        code.needs_environment = needs_environment;
+        code.params_in_slots = params_in_slots;
+        code.file_name = file_name;
+
+        for _ in 0..self.u32()? {
+            let name = self.symbol()?;
+            code.names.push(name);
+        }
+        for _ in 0..self.u32()? {
+            let pc = self.u32()?;
+            let name = self.str()?.into();
+            code.callee_names.push((pc, name));
+        }
+        code.var_names = if self.bool()? {
+            Some(Box::new(VariableNames {
+                env: self.names()?,
+                slots: self.names()?,
+            }))
+        } else {
+            None
+        };
+        let len = self.u32()? as usize;
+        code.code = self.bytes(len)?.to_vec();
+        for _ in 0..self.u32()? {
+            let literal = self.literal(ctx)?;
+            code.literals.push(literal);
+        }
+        for _ in 0..self.u32()? {
+            code.feedback.push(TypeFeedBack::None);
+        }
+        for _ in 0..self.u32()? {
+            let range = self.u64()? as usize..self.u64()? as usize;
+            let line = self.u32()?;
+            let col = self.u32()?;
+            code.loc.push((range, FileLocation { line, col }));
+        }
+        if self.bool()? {
+            let range = self.u64()? as usize..self.u64()? as usize;
+            if self.source.get(range.clone()).is_none() {
+                return Err(CodeCacheError::Corrupted);
+            }
+            code.source = Some((self.source.clone(), range));
+        }
+        for _ in 0..self.u32()? {
+            let nested = self.code_block(ctx)?;
+            code.codes.push(nested);
+        }
+        code.literals_ptr = code.literals.as_ptr();
+        Ok(code)
+    }
+}
+
+impl CodeBlock {
+    /// Serialize this code block and all nested functions. Resulting cache is valid only for `source`.
+    pub fn serialize_standalone(&self, source: &str) -> Result<Vec<u8>, CodeCacheError> {
+        let mut writer = Writer { buf: vec![] };
+        writer.buf.extend_from_slice(MAGIC);
+        writer.u32(FORMAT_VERSION);
+        writer.str(STARLIGHT_VERSION);
+        writer.u64(fnv1a(source.as_bytes()));
+        writer.code_block(self)?;
+        Ok(writer.buf)
+    }
+
+    /// Restore code block serialized by [CodeBlock::serialize_standalone] from the same `source`.
+    /// Inline caches start empty.
+    pub fn deserialize_standalone(
+        ctx: GcPointer<Context>,
+        bytes: &[u8],
+        source: &str,
+    ) -> Result<GcPointer<CodeBlock>, CodeCacheError> {
+        let mut reader = Reader {
+            buf: bytes,
+            pos: 0,
+            source: Lrc::new(source.to_owned()),
+            strings: HashMap::new(),
+        };
+        if reader.bytes(MAGIC.len())? != MAGIC {
+            return Err(CodeCacheError::BadMagic);
+        }
+        if reader.u32()? != FORMAT_VERSION || reader.str()? != STARLIGHT_VERSION {
+            return Err(CodeCacheError::VersionMismatch);
+        }
+        if reader.u64()? != fnv1a(source.as_bytes()) {
+            return Err(CodeCacheError::SourceMismatch);
+        }
+        reader.code_block(ctx)
+    }
+}
+
+impl GcPointer<Context> {
+    /// Evaluate `source` using compiled code from `cache` when it matches the source. Otherwise script is
+    /// compiled as usual and `cache` is replaced with fresh code cache (or cleared if code can't be cached).
+    pub fn eval_cached(
+        self,
+        path: Option<&str>,
+        source: &str,
+        cache: &mut Vec<u8>,
+    ) -> Result<JsValue, JsValue> {
+        let code = match CodeBlock::deserialize_standalone(self, cache, source) {
+            Ok(code) => code,
+            Err(_) => {
+                let code = self.compile_eval_code(path, false, source, false)?;
+                // cache is only useful with complete bytecode.
+                ByteCompiler::compile_all_lazy(self, code)?;
+                match code.serialize_standalone(source) {
+                    Ok(bytes) => *cache = bytes,
+                    Err(_) => cache.clear(),
+                }
+                code
+            }
+        };
+        self.run_eval_code(code)
+    }
+}
+
+/// Script compiled by [Context::compile_script], ready to run any number of times in the context it was
+/// compiled in. Clones share the code. Bytecode is complete, functions skipped by lazy compilation are
+/// compiled too.
+#[derive(Clone)]
+pub struct CompiledScript {
+    code: Rc<PersistentRooted>,
+    ctx: Rc<PersistentRooted>,
+    source: Rc<str>,
+}
+
+impl CompiledScript {
+    fn new(ctx: GcPointer<Context>, code: GcPointer<CodeBlock>, source: Rc<str>) -> Self {
+        let mut vm = ctx.vm();
+        Self {
+            code: Rc::new(vm.add_persistent_root(JsValue::new(code))),
+            ctx: Rc::new(vm.add_persistent_root(JsValue::new(ctx))),
+            source,
+        }
+    }
+
+    /// Top-level code block, nested functions are in its `codes`.
+    pub fn code(&self) -> GcPointer<CodeBlock> {
+        self.code
+            .get_value()
+            .get_object()
+            .downcast::<CodeBlock>()
+            .unwrap()
+    }
+
+    /// Context the script runs in.
+    pub fn context(&self) -> GcPointer<Context> {
+        self.ctx
+            .get_value()
+            .get_object()
+            .downcast::<Context>()
+            .unwrap()
+    }
+
+    /// Source text the script was compiled from.
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Run the script and return its completion value. Top-level `var` and function declarations are
+    /// properties of the global object, so a run sees what earlier runs stored there.
+    pub fn run(&self) -> Result<JsValue, JsValue> {
+        self.context().run_eval_code(self.code())
+    }
+
+    /// Serialize the bytecode, see [CodeBlock::serialize_standalone]. Scripts with direct `eval` or literals
+    /// that can't be serialized are rejected.
+    pub fn serialize(&self) -> Result<Vec<u8>, CodeCacheError> {
+        self.code().serialize_standalone(&self.source)
+    }
+
+    /// Restore script serialized by [CompiledScript::serialize] from the same `source` into `ctx`.
+    pub fn deserialize(
+        ctx: GcPointer<Context>,
+        bytes: &[u8],
+        source: &str,
+    ) -> Result<Self, CodeCacheError> {
+        let code = CodeBlock::deserialize_standalone(ctx, bytes, source)?;
+        Ok(Self::new(ctx, code, source.into()))
+    }
+
+    /// Copy of this script running in `ctx`. Code is not shared between contexts because literals such as
+    /// regular expressions belong to the context that created them, so bytecode goes through the code cache.
+    pub fn clone_into(&self, ctx: GcPointer<Context>) -> Result<Self, CodeCacheError> {
+        let bytes = self.serialize()?;
+        Self::deserialize(ctx, &bytes, &self.source)
+    }
+}
+
+impl GcPointer<Context> {
+    /// Compile `source` without running it. Compile errors are returned as `SyntaxError` like from
+    /// [Context::eval_internal].
+    pub fn compile_script(
+        self,
+        path: Option<&str>,
+        source: &str,
+    ) -> Result<CompiledScript, JsValue> {
+        let code = self.compile_eval_code(path, false, source, false)?;
+        ByteCompiler::compile_all_lazy(self, code)?;
+        Ok(CompiledScript::new(self, code, source.into()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::gc::cell::GcPointer;
+    use crate::vm::{
+        code_block::CodeBlock, code_cache::CompiledScript, context::Context, tests::new_context,
+    };
+
+    #[test]
+    fn test_eval_cached() {
+        let (mut vm, mut ctx) = new_context();
+        let source = "function f(x) { return x + 'b' + /a/g.source; } globalThis.r = f('a');";
+        let mut cache = vec![];
+        ctx.eval_cached(None, source, &mut cache).ok().unwrap();
+        assert!(!cache.is_empty());
+        let compiled = cache.clone();
+
+        let mut ctx = Context::new(&mut vm);
+        ctx.eval_cached(None, source, &mut cache).ok().unwrap();
+        assert_eq!(cache, compiled);
+        let r = ctx.eval("r").ok().unwrap();
+        assert_eq!(r.to_string(ctx).ok().unwrap(), "aba");
+        let f = ctx.eval("f.toString()").ok().unwrap();
+        assert_eq!(
+            f.to_string(ctx).ok().unwrap(),
+            "function f(x) { return x + 'b' + /a/g.source; }"
+        );
+
+        // stale cache is replaced
+        ctx.eval_cached(None, "1", &mut cache).ok().unwrap();
+        assert_ne!(cache, compiled);
+    }
+
+    #[test]
+    fn test_compiled_script() {
+        let (mut vm, mut ctx) = new_context();
+        let source = "var runs = (typeof runs === 'number' ? runs : 0) + 1; /a/.source + runs";
+        let script = ctx.compile_script(None, source).ok().unwrap();
+        let copy = script.clone();
+        let run = |script: &CompiledScript| {
+            let result = script.run().ok().unwrap();
+            result.to_string(script.context()).ok().unwrap()
+        };
+        assert_eq!(run(&script), "a1");
+        assert_eq!(run(&copy), "a2");
+        assert!(ctx.compile_script(None, "let = ;").is_err());
+
+        let other = Context::new(&mut vm);
+        let moved = script.clone_into(other).unwrap();
+        assert_eq!(run(&moved), "a1");
+        let bytes = script.serialize().unwrap();
+        let restored = CompiledScript::deserialize(other, &bytes, source).unwrap();
+        assert_eq!(run(&restored), "a2");
+        assert!(CompiledScript::deserialize(other, &bytes, "1").is_err());
+        assert_eq!(ctx.eval("runs").ok().unwrap().get_number(), 2.0);
+    }
+
+    #[test]
+    fn test_shared_literals() {
+        let (_vm, mut ctx) = new_context();
+        let source = "function f() { return 'shared'; } function g() { return 'shared' + 1.5; }";
+        let shared = |code: GcPointer<CodeBlock>| {
+            let literal = code
+                .literals
+                .iter()
+                .find(|literal| literal.is_jsstring())
+                .copied()
+                .unwrap();
+            assert_eq!(literal.get_jsstring().as_str(), "shared");
+            literal.get_jsstring()
+        };
+
+        let code = ctx
+            .compile_eval_code(None, false, source, false)
+            .ok()
+            .unwrap();
+        assert!(shared(code.codes[0]) == shared(code.codes[1]));
+
+        let bytes = code.serialize_standalone(source).unwrap();
+        let code = CodeBlock::deserialize_standalone(ctx, &bytes, source).unwrap();
+        assert!(shared(code.codes[0]) == shared(code.codes[1]));
+    }
+}
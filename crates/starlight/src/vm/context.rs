@@ -1,22 +1,24 @@
 use crate::{define_op_builtins, gc::cell::GcCell, vm::Lrc};
 use comet::internal::finalize_trait::FinalizeTrait;
-use std::{collections::HashMap, ptr::null};
-use swc_common::{errors::Handler, input::StringInput, FileName, SourceMap};
-use swc_ecmascript::parser::{Parser, Syntax};
+use std::{collections::HashMap, ptr::null, rc::Rc};
+use swc_common::{FileName, SourceFile, SourceMap};
+use swc_ecmascript::ast::Program;
 
 use crate::{
-    bytecompiler::{ByteCompiler, CompileError},
+    bytecompiler::{ByteCompiler, CompileError, ScopeRef},
     gc::{
         cell::{GcPointer, Trace, Visitor},
         Heap,
     },
     jsrt,
+    options::EvalOptions,
+    preparser::preparse,
     vm::{
         arguments::{Arguments, JsArguments},
         environment::Environment,
         error::JsSyntaxError,
+        frontend::{self, Frontend},
         function::JsVMFunction,
-        init_es_config, BufferedError,
     },
 };
 
@@ -24,13 +26,16 @@ use super::{
     array::JsArray,
     array_buffer::JsArrayBuffer,
     builder::{Builtin, ClassBuilder, ClassConstructor},
+    code_block::CodeBlock,
     class::JsClass,
     data_view::JsDataView,
-    error::JsError,
+    debugger,
+    error::{JsError, JsException},
     error::{JsRangeError, JsReferenceError, JsTypeError},
     function::JsNativeFunction,
     function::{JsFunction, JsGeneratorFunction},
     global::JsGlobal,
+    inspect::{self, InspectOptions},
     interpreter::{frame::CallFrame, stack::Stack},
     number::JsNumber,
     object::{JsObject, ObjectTag},
@@ -41,11 +46,16 @@ use super::{
     symbol_table::JsSymbolObject,
     symbol_table::{self, Internable, JsSymbol, Symbol},
     value::JsValue,
-    GlobalData, ModuleKind, MyEmiter, VirtualMachine, VirtualMachineRef,
+    GlobalData, ModuleKind, VirtualMachine, VirtualMachineRef,
 };
 
 use crate::jsrt::boolean::JsBoolean;
+use crate::jsrt::console::JsConsole;
 use crate::jsrt::date::JsDate;
+#[cfg(feature = "fetch")]
+use crate::jsrt::fetch::JsFetch;
+#[cfg(feature = "intl")]
+use crate::jsrt::intl::JsIntl;
 use crate::jsrt::math::JsMath;
 use crate::jsrt::regexp::JsRegExp;
 use crate::jsrt::weak_ref::JsWeakRef;
@@ -96,6 +106,18 @@ impl Context {
         self.vm.heap()
     }
 
+    /// Native references listed by every builtin that `init` registers.
+    pub(crate) fn builtin_native_references() -> Vec<usize> {
+        let mut references = vec![];
+        macro_rules! define_native_references {
+            ($class: ident) => {
+                references.append(&mut $class::native_references());
+            };
+        }
+        define_op_builtins!(define_native_references);
+        references
+    }
+
     pub fn module_loader(&mut self) -> Option<GcPointer<JsObject>> {
         self.module_loader
     }
@@ -120,7 +142,11 @@ impl Context {
             stack_len_max: Self::DEFAULT_STACK_LEN_MAX,
             global_object: None,
             vm: VirtualMachineRef(vm),
-            stack: Stack::new(),
+            stack: Stack::with_limits(
+                vm.options().stack_size,
+                vm.options().max_stack_depth,
+                vm.options().stack_reserve,
+            ),
             stacktrace: String::new(),
             module_loader: None,
             modules: HashMap::new(),
@@ -143,7 +169,7 @@ impl Context {
 }
 impl GcPointer<Context> {
     pub fn register_external_reference(&mut self, reference: usize) {
-        self.vm.external_references.push(reference);
+        self.vm.external_references.insert(reference);
     }
 
     pub fn register_class<T>(mut self) -> Result<(), JsValue>
@@ -173,12 +199,14 @@ impl GcPointer<Context> {
         let mut global_object = self.global_object();
         def_native_property!(self, global_object, name.intern(), constructor)?;
 
-        unsafe {
-            self.vm
-                .external_references
-                .push(T::class() as *const _ as _);
-            self.vm.external_references.push(T::raw_constructor as _);
-        }
+        self.vm
+            .external_references
+            .insert(T::class() as *const _ as _);
+        self.vm.external_references.insert(T::raw_constructor as _);
+        self.vm
+            .register_native(format!("{}::class", name), T::class() as *const _ as _);
+        self.vm
+            .register_native(format!("{}::constructor", name), T::raw_constructor as _);
         Ok(())
     }
 
@@ -207,6 +235,9 @@ impl GcPointer<Context> {
         define_op_builtins!(define_register_builtin);
         self.init_module_loader();
         self.init_internal_modules();
+        if self.vm.options.commonjs {
+            jsrt::commonjs::init_commonjs(*self)?;
+        }
         self.init_dollar();
         Ok(())
     }
@@ -233,6 +264,21 @@ impl GcPointer<Context> {
         let msg = JsString::new(self, msg);
         JsRangeError::new(self, msg, None)
     }
+    /// Construct new error from Rust error. Messages of error sources are appended to the message.
+    pub fn new_error_from(mut self, err: impl std::error::Error) -> GcPointer<JsObject> {
+        let mut msg = err.to_string();
+        let mut source = err.source();
+        while let Some(cause) = source {
+            msg.push_str(&format!(": {}", cause));
+            source = cause.source();
+        }
+        let msg = JsString::new(self, msg);
+        JsError::new(self, msg, None)
+    }
+    /// Convert thrown JS value into Rust error capturing its message and stack.
+    pub fn to_rust_error(self, exception: JsValue) -> JsException {
+        JsException::new(self, exception)
+    }
 }
 
 impl GcPointer<Context> {
@@ -247,8 +293,8 @@ impl GcPointer<Context> {
 
         Ok(code)
     }
-    /// Compile provided script into JS function. If error when compiling happens `SyntaxError` instance
-    /// is returned.
+    /// Compile provided script into JS function. Parse errors and syntax the compiler does not support are
+    /// returned as [CompileError], [CompileError::into_exception] makes a `SyntaxError` of them.
     pub fn compile(
         mut self,
         path: &str,
@@ -257,40 +303,20 @@ impl GcPointer<Context> {
         builtins: bool,
     ) -> Result<JsValue, CompileError> {
         let cm: Lrc<SourceMap> = Default::default();
-        let _e = BufferedError::default();
-
-        let handler = Handler::with_emitter(true, false, Box::new(MyEmiter::default()));
-
         let fm = cm.new_source_file(FileName::Custom(name.into()), script.into());
-
-        let mut parser = Parser::new(
-            Syntax::Es(Default::default()),
-            StringInput::from(&*fm),
-            None,
-        );
-
-        for e in parser.take_errors() {
-            e.into_diagnostic(&handler).emit();
-        }
-
-        let script = match parser.parse_script() {
-            Ok(script) => script,
-            Err(_e) => {
-                // let msg = JsString::new(self, e.kind().msg());
-                return Err(CompileError::NotYetImpl("parser error".to_string()));
-            }
-        };
+        let script = frontend::parse_script(&*self.vm.frontend, &fm.src, fm.start_pos)?;
         let mut code = ByteCompiler::compile_script(
             self,
             &script,
             &std::path::Path::new(&path)
                 .canonicalize()
-                .unwrap()
+                .unwrap_or_else(|_| path.into())
                 .parent()
                 .map(|x| x.to_str().unwrap().to_string())
                 .unwrap_or_else(|| "".to_string()),
             path.to_owned(),
             builtins,
+            Some(fm.clone()),
         )?;
         code.name = name.intern();
         //code.display_to(&mut OutBuf).unwrap();
@@ -306,190 +332,301 @@ impl GcPointer<Context> {
         script: &str,
     ) -> Result<JsValue, JsValue> {
         let cm: Lrc<SourceMap> = Default::default();
-        let _e = BufferedError::default();
-
-        let handler = Handler::with_emitter(true, false, Box::new(MyEmiter::default()));
-
         let fm = cm.new_source_file(FileName::Custom(name.into()), script.into());
-
-        let mut parser = Parser::new(Syntax::Es(init_es_config()), StringInput::from(&*fm), None);
-
-        for e in parser.take_errors() {
-            e.into_diagnostic(&handler).emit();
+        let module = frontend::parse_module(&*self.vm.frontend, &fm.src, fm.start_pos)
+            .map_err(|e| e.into_exception(self))?;
+        if self.vm.debugger.is_some() {
+            debugger::script_parsed(self, path, script);
         }
 
-        let module = match parser.parse_module() {
-            Ok(module) => module,
-            Err(e) => {
-                let msg = JsString::new(self, e.kind().msg());
-                return Err(JsValue::encode_object_value(JsSyntaxError::new(
-                    self, msg, None,
-                )));
-            }
-        };
-
         let mut code = ByteCompiler::compile_module(
             self,
             path,
             &std::path::Path::new(&path)
                 .canonicalize()
-                .unwrap()
+                .unwrap_or_else(|_| path.into())
                 .parent()
                 .map(|x| x.to_str().unwrap().to_string())
                 .unwrap_or_else(|| "".to_string()),
             name,
             &module,
+            Some(fm.clone()),
         )
-        .map_err(|e| self.new_syntax_error(format!("Compile Error {:?}", e)))?;
+        .map_err(|e| e.into_exception(self))?;
         code.name = name.intern();
 
         let env = Environment::new(self, 0);
         let fun = JsVMFunction::new(self, code, env);
         Ok(JsValue::encode_object_value(fun))
     }
-    /// Evaluates provided script.
+    /// Compile `program` that was parsed without the [Frontend](super::frontend::Frontend) of the runtime, e.g.
+    /// by a bundler that already has the swc AST. A script compiles to a function returning its completion
+    /// value like [Context::eval], a module to a function taking the module object like
+    /// [Context::compile_module]. `source_file` is the file spans of `program` point into, without it
+    /// compiled code has no line numbers and functions have no source text.
+    pub fn compile_ast(
+        self,
+        program: &Program,
+        name: &str,
+        source_file: Option<Lrc<SourceFile>>,
+    ) -> Result<JsValue, CompileError> {
+        if let (Some(file), true) = (&source_file, self.vm.debugger.is_some()) {
+            debugger::script_parsed(self, name, &file.src);
+        }
+        let mut code = match program {
+            Program::Script(script) => ByteCompiler::compile_eval(
+                self,
+                script,
+                "",
+                name.to_owned(),
+                false,
+                source_file,
+                None,
+            )?,
+            Program::Module(module) => {
+                ByteCompiler::compile_module(self, name, "", name, module, source_file)?
+            }
+        };
+        code.name = name.intern();
+
+        let env = Environment::new(self, 0);
+        let fun = JsVMFunction::new(self, code, env);
+        Ok(JsValue::encode_object_value(fun))
+    }
+    /// Evaluates provided script and returns its completion value, i.e. value of the last evaluated
+    /// expression statement.
     pub fn eval(&mut self, script: &str) -> Result<JsValue, JsValue> {
         self.eval_internal(None, false, script, false)
     }
     /// Tries to evaluate provided `script`. If error when parsing or execution occurs then `Err` with exception value is returned.
     ///
-    ///
-    ///
-    /// TODO: Return script execution result. Right now just `undefined` value is returned.
+    /// Result is the completion value of the script: `1; if (true) { 2 }` evaluates to `2`, `1; var x;` to `1`
+    /// and a script without expression statements to `undefined`.
     pub fn eval_internal(
-        mut self,
+        self,
         path: Option<&str>,
         force_strict: bool,
         script: &str,
         builtins: bool,
     ) -> Result<JsValue, JsValue> {
-        let res = {
-            let cm: Lrc<SourceMap> = Default::default();
-            let _e = BufferedError::default();
+        let code = self.compile_eval_code(path, force_strict, script, builtins)?;
+        self.run_eval_code(code)
+    }
 
-            let handler = Handler::with_emitter(true, false, Box::new(MyEmiter::default()));
+    /// Evaluate TypeScript `script` like [Context::eval_internal]. Types are stripped by swc before the code
+    /// is compiled and never checked.
+    #[cfg(feature = "typescript")]
+    pub fn eval_typescript(self, path: Option<&str>, script: &str) -> Result<JsValue, JsValue> {
+        let code = self.compile_eval_code_with(
+            &frontend::SwcFrontend::typescript(),
+            path,
+            false,
+            script,
+            false,
+        )?;
+        self.run_eval_code(code)
+    }
 
-            let fm = cm.new_source_file(FileName::Custom("<script>".into()), script.into());
+    /// Parse and compile `script` as top-level eval code.
+    pub(crate) fn compile_eval_code(
+        self,
+        path: Option<&str>,
+        force_strict: bool,
+        script: &str,
+        builtins: bool,
+    ) -> Result<GcPointer<CodeBlock>, JsValue> {
+        let frontend = self.vm.frontend.clone();
+        self.compile_eval_code_with(&*frontend, path, force_strict, script, builtins)
+    }
 
-            let mut parser =
-                Parser::new(Syntax::Es(init_es_config()), StringInput::from(&*fm), None);
+    /// [Context::compile_eval_code] parsing with `frontend` instead of the frontend of the runtime.
+    fn compile_eval_code_with(
+        mut self,
+        frontend: &dyn Frontend,
+        path: Option<&str>,
+        force_strict: bool,
+        script: &str,
+        builtins: bool,
+    ) -> Result<GcPointer<CodeBlock>, JsValue> {
+        let cm: Lrc<SourceMap> = Default::default();
+        let fm = cm.new_source_file(FileName::Custom("<script>".into()), script.into());
+        let preparse = if self.vm.options.lazy_compilation && frontend.is_javascript() {
+            preparse(&fm).map(Rc::new)
+        } else {
+            None
+        };
+        let source = match preparse {
+            // skipped bodies are blank, positions still refer to `fm`.
+            Some(ref preparse) => preparse.source.as_str(),
+            None => fm.src.as_str(),
+        };
+        let script = frontend::parse_script(frontend, source, fm.start_pos)
+            .map_err(|e| e.into_exception(self))?;
+        if self.vm.debugger.is_some() {
+            debugger::script_parsed(self, path.unwrap_or(""), &fm.src);
+        }
+        let mut code = ByteCompiler::compile_eval(
+            self,
+            &script,
+            &path
+                .map(|path| match std::path::Path::new(&path).canonicalize() {
+                    Ok(x) => x
+                        .parent()
+                        .map(|x| x.to_str().unwrap().to_string())
+                        .unwrap_or_else(|| "".to_string()),
+                    Err(_) => String::new(),
+                })
+                .unwrap_or_else(|| "".to_string()),
+            path.map(|x| x.to_owned()).unwrap_or_else(String::new),
+            builtins,
+            Some(fm.clone()),
+            preparse,
+        )
+        .map_err(|e| e.into_exception(self))?;
+        code.strict = code.strict || force_strict;
+        Ok(code)
+    }
 
-            for e in parser.take_errors() {
-                e.into_diagnostic(&handler).emit();
-            }
+    /// Run top-level code block produced by [Context::compile_eval_code].
+    pub(crate) fn run_eval_code(self, code: GcPointer<CodeBlock>) -> Result<JsValue, JsValue> {
+        letroot!(env = stack, Environment::new(self, 0));
+        letroot!(fun = stack, JsVMFunction::new(self, code, env));
+        letroot!(func = stack, fun);
+        letroot!(
+            args = stack,
+            Arguments::new(JsValue::encode_undefined_value(), &mut [])
+        );
 
-            let script = match parser.parse_script() {
-                Ok(script) => script,
-                Err(e) => {
-                    let msg = JsString::new(self, e.kind().msg());
-                    return Err(JsValue::encode_object_value(JsSyntaxError::new(
-                        self, msg, None,
-                    )));
-                }
-            };
-            let mut code = ByteCompiler::compile_eval(
-                self,
-                &script,
-                &path
-                    .map(|path| match std::path::Path::new(&path).canonicalize() {
-                        Ok(x) => x
-                            .parent()
-                            .map(|x| x.to_str().unwrap().to_string())
-                            .unwrap_or_else(|| "".to_string()),
-                        Err(_) => String::new(),
-                    })
-                    .unwrap_or_else(|| "".to_string()),
-                path.map(|x| x.to_owned()).unwrap_or_else(String::new),
-                builtins,
-            )
-            .map_err(|e| self.new_syntax_error(format!("Compile Error {:?}", &e)))?;
-            code.strict = code.strict || force_strict;
-            // code.file_name = path.map(|x| x.to_owned()).unwrap_or_else(|| String::new());
-            //code.display_to(&mut OutBuf).unwrap();
-
-            letroot!(env = stack, Environment::new(self, 0));
-            letroot!(fun = stack, JsVMFunction::new(self, code, env));
-            letroot!(func = stack, fun);
-            letroot!(
-                args = stack,
-                Arguments::new(JsValue::encode_undefined_value(), &mut [])
-            );
-
-            fun.as_function_mut()
-                .call(self, &mut args, JsValue::new(func))
-        };
-        res
+        fun.as_function_mut()
+            .call(self, &mut args, JsValue::new(func))
     }
+
+    /// Direct `eval(source)`: eval code sees bindings of `scope` stored in `env` and inherits `this` and
+    /// strictness of the caller. Values other than strings are returned as is.
+    pub(crate) fn direct_eval(
+        self,
+        source: JsValue,
+        scope: ScopeRef,
+        strict: bool,
+        env: GcPointer<Environment>,
+        this: JsValue,
+    ) -> Result<JsValue, JsValue> {
+        if !source.is_jsstring() {
+            return Ok(source);
+        }
+        let cm: Lrc<SourceMap> = Default::default();
+        let fm = cm.new_source_file(
+            FileName::Custom("<eval>".into()),
+            source.get_jsstring().as_str().to_string(),
+        );
+        let script = frontend::parse_script(&*self.vm.frontend, &fm.src, fm.start_pos)
+            .map_err(|e| e.into_exception(self))?;
+        let code =
+            ByteCompiler::compile_direct_eval(self, &script, scope, strict, Some(fm.clone()))
+                .map_err(|e| e.into_exception(self))?;
+        letroot!(fun = stack, JsVMFunction::new(self, code, env));
+        letroot!(func = stack, fun);
+        letroot!(args = stack, Arguments::new(this, &mut []));
+        fun.as_function_mut()
+            .call(self, &mut args, JsValue::new(func))
+    }
+    /// Evaluate `script` as an ES module, see [Context::eval_with].
     pub fn evalm(
-        mut self,
+        self,
         path: Option<&str>,
         force_strict: bool,
         script: &str,
     ) -> Result<JsValue, JsValue> {
-        let res = {
-            let cm: Lrc<SourceMap> = Default::default();
-            let _e = BufferedError::default();
-
-            let handler = Handler::with_emitter(true, false, Box::new(MyEmiter::default()));
-
-            let fm = cm.new_source_file(FileName::Custom("<script>".into()), script.into());
-
-            let mut parser =
-                Parser::new(Syntax::Es(init_es_config()), StringInput::from(&*fm), None);
-
-            for e in parser.take_errors() {
-                e.into_diagnostic(&handler).emit();
+        let code = self.compile_module_code(path, force_strict, script)?;
+        self.run_module_code(code)
+    }
+
+    /// Evaluate `script` as a script or a module as `options` say and return the completion value, or the
+    /// result of the module function for modules. [Context::eval_internal] and [Context::evalm] are shorthands
+    /// for the common cases.
+    pub fn eval_with(mut self, script: &str, options: &EvalOptions) -> Result<JsValue, JsValue> {
+        let path = options.filename.as_deref();
+        let debug_info = self.vm.options.debug_info;
+        self.vm.options.debug_info = options.debug_info.unwrap_or(debug_info);
+        let code = if options.module {
+            self.compile_module_code(path, options.strict, script)
+        } else {
+            self.compile_eval_code(path, options.strict, script, false)
+        }
+        .and_then(|code| {
+            // functions skipped by lazy compilation would be compiled with the options of the runtime.
+            if options.debug_info.is_some()
+                || options.line_offset != 0
+                || options.column_offset != 0
+            {
+                ByteCompiler::compile_all_lazy(self, code)?;
             }
+            Ok(code)
+        });
+        self.vm.options.debug_info = debug_info;
+        let mut code = code?;
+        code.offset_locations(options.line_offset, options.column_offset);
+        if options.module {
+            self.run_module_code(code)
+        } else {
+            self.run_eval_code(code)
+        }
+    }
 
-            let script = match parser.parse_module() {
-                Ok(script) => script,
-                Err(e) => {
-                    let msg = JsString::new(self, e.kind().msg());
-                    return Err(JsValue::encode_object_value(JsSyntaxError::new(
-                        self, msg, None,
-                    )));
-                }
-            };
-            let mut code = ByteCompiler::compile_module(
-                self,
-                &path.map(|x| x.to_owned()).unwrap_or_else(String::new),
-                &path
-                    .map(|path| {
-                        std::path::Path::new(&path)
-                            .canonicalize()
-                            .unwrap()
-                            .parent()
-                            .map(|x| x.to_str().unwrap().to_string())
-                            .unwrap_or_else(|| "".to_string())
-                    })
-                    .unwrap_or_else(|| "".to_string()),
-                &path.map(|x| x.to_owned()).unwrap_or_else(String::new),
-                &script,
+    /// Parse and compile `script` as a module function taking the module object.
+    fn compile_module_code(
+        mut self,
+        path: Option<&str>,
+        force_strict: bool,
+        script: &str,
+    ) -> Result<GcPointer<CodeBlock>, JsValue> {
+        let cm: Lrc<SourceMap> = Default::default();
+        let fm = cm.new_source_file(FileName::Custom("<script>".into()), script.into());
+        let script = frontend::parse_module(&*self.vm.frontend, &fm.src, fm.start_pos)
+            .map_err(|e| e.into_exception(self))?;
+        let mut code = ByteCompiler::compile_module(
+            self,
+            &path.map(|x| x.to_owned()).unwrap_or_else(String::new),
+            &path
+                .map(|path| {
+                    std::path::Path::new(&path)
+                        .canonicalize()
+                        .unwrap_or_else(|_| path.into())
+                        .parent()
+                        .map(|x| x.to_str().unwrap().to_string())
+                        .unwrap_or_else(|| "".to_string())
+                })
+                .unwrap_or_else(|| "".to_string()),
+            &path.map(|x| x.to_owned()).unwrap_or_else(String::new),
+            &script,
+            Some(fm.clone()),
+        )
+        .map_err(|e| e.into_exception(self))?;
+        code.strict = code.strict || force_strict;
+        Ok(code)
+    }
+
+    /// Run module code block produced by [Context::compile_module_code] with a fresh module object.
+    fn run_module_code(self, code: GcPointer<CodeBlock>) -> Result<JsValue, JsValue> {
+        letroot!(env = stack, Environment::new(self, 0));
+        letroot!(fun = stack, JsVMFunction::new(self, code, env));
+        letroot!(func = stack, fun);
+        letroot!(module_object = stack, JsObject::new_empty(self));
+        let exports = JsObject::new_empty(self);
+        module_object
+            .put(self, "@exports".intern(), JsValue::new(exports), false)
+            .unwrap_or_else(|_| unreachable!());
+        let mut args = [JsValue::new(module_object)];
+        letroot!(
+            args = stack,
+            Arguments::new(
+                JsValue::encode_object_value(self.global_object()),
+                &mut args
             )
-            .map_err(|e| self.new_syntax_error(format!("Compile Error {:?}", &e)))?;
-            code.strict = code.strict || force_strict;
-
-            letroot!(env = stack, Environment::new(self, 0));
-            letroot!(fun = stack, JsVMFunction::new(self, code, env));
-            letroot!(func = stack, fun);
-            letroot!(module_object = stack, JsObject::new_empty(self));
-            let exports = JsObject::new_empty(self);
-            module_object
-                .put(self, "@exports".intern(), JsValue::new(exports), false)
-                .unwrap_or_else(|_| unreachable!());
-            let mut args = [JsValue::new(module_object)];
-            letroot!(
-                args = stack,
-                Arguments::new(
-                    JsValue::encode_object_value(self.global_object()),
-                    &mut args
-                )
-            );
-
-            fun.as_function_mut()
-                .call(self, &mut args, JsValue::new(func))
-        };
-        res
+        );
+
+        fun.as_function_mut()
+            .call(self, &mut args, JsValue::new(func))
     }
 
     /// Collect stacktrace.
@@ -510,6 +647,25 @@ impl GcPointer<Context> {
         result
     }
 
+    /// Format `value` for humans the way `console.log` prints it, see [inspect](super::inspect).
+    pub fn inspect(self, value: JsValue, options: &InspectOptions) -> String {
+        inspect::inspect(self, value, options)
+    }
+
+    /// Stacktrace of thrown `exception`. Error objects keep the stacktrace collected when they were
+    /// created, so rethrowing a caught error does not replace it with the stacktrace of the rethrow site.
+    pub(crate) fn exception_stacktrace(&mut self, exception: JsValue) -> String {
+        if exception.is_jsobject() {
+            let mut object = exception.get_jsobject();
+            if let Some(desc) = object.get_own_property(*self, "stack".intern()) {
+                if desc.is_data() && desc.value().is_jsstring() {
+                    return desc.value().get_string().as_str().to_owned();
+                }
+            }
+        }
+        self.stacktrace()
+    }
+
     pub fn init_module_loader(mut self) {
         let loader = JsNativeFunction::new(self, "@loader".intern(), jsrt::module_load, 1);
         self.module_loader = Some(loader);
@@ -522,6 +678,19 @@ impl GcPointer<Context> {
         )
         .unwrap();
         assert!(self.modules.contains_key("std"));
+        self.add_module(
+            "assert",
+            ModuleKind::NativeUninit(crate::jsrt::jsstd::assert::init_assert),
+        )
+        .unwrap();
+        #[cfg(all(target_pointer_width = "64", feature = "ffi"))]
+        if self.vm.options.enable_ffi {
+            self.add_module("ffi", ModuleKind::NativeUninit(crate::jsrt::ffi::init_ffi))
+                .unwrap();
+        }
+        if self.vm.options.host_modules {
+            crate::jsrt::jsstd::register_host_modules(*self);
+        }
     }
 
     pub fn add_module(
@@ -597,3 +766,69 @@ impl Trace for Context {
 }
 
 impl FinalizeTrait<Context> for Context {}
+
+#[cfg(test)]
+mod tests {
+    use crate::options::EvalOptions;
+    use crate::vm::tests::new_context;
+
+    #[test]
+    fn test_eval() {
+        let (_vm, mut ctx) = new_context();
+
+        let result = ctx
+            .eval(
+                "var x = 'global', indirect = eval;
+                 function local() { var x = 'local'; return eval('x') + ',' + indirect('x'); }
+                 function assign(a) { var b = 1; eval('b = a + b'); return b; }
+                 function strict() { 'use strict'; eval('var y = 1'); return typeof y; }
+                 eval('var z = 2');
+                 local() + ',' + assign(4) + ',' + strict() + ',' + z + ',' + eval(7)",
+            )
+            .ok()
+            .unwrap();
+        assert_eq!(
+            result.get_jsstring().as_str(),
+            "local,global,5,undefined,2,7"
+        );
+    }
+
+    #[test]
+    fn test_completion_value() {
+        let (_vm, mut ctx) = new_context();
+
+        let mut eval = |src: &str| {
+            let value = ctx.eval(src).ok().unwrap();
+            value.to_string(ctx).ok().unwrap()
+        };
+        assert_eq!(eval("1; if (true) { 2 } var x = 3;"), "2");
+        assert_eq!(eval("1; if (false) { 2 }"), "undefined");
+        assert_eq!(eval("var i = 0; while (i < 3) { i++; i * 10 }"), "30");
+        assert_eq!(eval("try { 4 } finally { 5 }"), "4");
+        assert_eq!(eval("6; { var y; }"), "6");
+        assert_eq!(eval("var z = 1;"), "undefined");
+        assert_eq!(eval("eval('7; if (true) { 8 }')"), "8");
+    }
+
+    #[test]
+    fn test_eval_options() {
+        let (_vm, mut ctx) = new_context();
+        let strict = EvalOptions::default().with_strict(true);
+        let err = ctx.eval_with("undeclared = 1", &strict).err().unwrap();
+        assert!(err.to_string(ctx).ok().unwrap().contains("ReferenceError"));
+        ctx.eval_with("undeclared = 1", &EvalOptions::default())
+            .ok()
+            .unwrap();
+
+        let module = EvalOptions::default()
+            .with_module(true)
+            .with_filename("main.mjs");
+        ctx.eval_with(
+            "export const answer = 42; globalThis.exported = answer;",
+            &module,
+        )
+        .ok()
+        .unwrap();
+        assert_eq!(ctx.eval("exported").ok().unwrap().get_number(), 42.0);
+    }
+}
@@ -0,0 +1,520 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+//! Source level debugging of JS code.
+//!
+//! A [DebuggerDelegate] attached with [VirtualMachine::set_debugger](crate::vm::VirtualMachine::set_debugger)
+//! is notified about statements, function calls and exceptions and decides how execution continues after
+//! a pause. Code compiled while a debugger is attached starts every statement and function with a `debug`
+//! instruction, code compiled before that stops only at `debugger;` statements and exceptions. The
+//! instructions do nothing once the debugger is removed.
+//!
+//! Breakpoints are set by file name and line. Stepping compares depth of the call stack with the depth at
+//! the pause: step-in stops at the next statement anywhere, step-over at the next statement of the same or
+//! an outer frame and step-out at the next statement of an outer frame.
+use super::{
+    code_block::{CodeBlock, FileLocation},
+    context::Context,
+    environment::Environment,
+    interpreter::frame::CallFrame,
+    symbol_table::Symbol,
+    value::JsValue,
+};
+use crate::gc::cell::GcPointer;
+use std::collections::HashSet;
+
+/// Operand of `debug` instruction at the start of a statement.
+pub const DEBUG_STATEMENT: u32 = 0;
+/// Operand of `debug` instruction emitted for `debugger;` statement.
+pub const DEBUG_DEBUGGER_STATEMENT: u32 = 1;
+/// Operand of `debug` instruction at the start of a function body.
+pub const DEBUG_FUNCTION_ENTRY: u32 = 2;
+
+/// Why execution was paused.
+pub enum PauseReason {
+    Breakpoint,
+    Step,
+    DebuggerStatement,
+    /// Exception was thrown, `caught` tells whether a `catch` or `finally` block of JS code handles it.
+    Exception {
+        value: JsValue,
+        caught: bool,
+    },
+}
+
+/// How execution continues after a pause.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ResumeAction {
+    Continue,
+    StepIn,
+    StepOver,
+    StepOut,
+}
+
+/// Which exceptions pause execution.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PauseOnExceptions {
+    None,
+    Uncaught,
+    All,
+}
+
+/// Receiver of debugger events. Callbacks may run JS code with `ctx`, debugger events are not reported
+/// while a callback runs.
+pub trait DebuggerDelegate {
+    /// Execution stopped, `frames` start with the innermost frame. Returned action decides where execution
+    /// stops next.
+    fn paused(
+        &mut self,
+        ctx: GcPointer<Context>,
+        reason: PauseReason,
+        frames: &[DebugFrame],
+    ) -> ResumeAction;
+
+    /// Called before every statement of code compiled with debugger attached.
+    fn statement(&mut self, _ctx: GcPointer<Context>, _frame: &DebugFrame) {}
+
+    fn function_entered(&mut self, _ctx: GcPointer<Context>, _frame: &DebugFrame) {}
+
+    /// Called before `frame` returns `value`. Frames whose code was compiled without debugger attached
+    /// are not reported.
+    fn function_exited(&mut self, _ctx: GcPointer<Context>, _frame: &DebugFrame, _value: JsValue) {}
+
+    /// Called for every thrown exception, before it pauses execution.
+    fn exception(&mut self, _ctx: GcPointer<Context>, _value: JsValue, _caught: bool) {}
+
+    /// Called when a script or module from `file` was parsed, before it is compiled.
+    fn script_parsed(&mut self, _ctx: GcPointer<Context>, _file: &str, _source: &str) {}
+
+    /// Called by `console` methods, `level` is `"log"` or `"error"`.
+    fn console_message(&mut self, _ctx: GcPointer<Context>, _level: &str, _args: &[JsValue]) {}
+}
+
+/// Debugger state of a [VirtualMachine](crate::vm::VirtualMachine).
+pub struct Debugger {
+    /// Taken out while a callback runs, so that code evaluated by the delegate is not debugged.
+    delegate: Option<Box<dyn DebuggerDelegate>>,
+    breakpoints: HashSet<(String, u32)>,
+    pause_on_exceptions: PauseOnExceptions,
+    /// Pending step and stack depth at the pause it was requested from.
+    step: Option<(ResumeAction, usize)>,
+    /// Last reported exception. Exception passing through native code is seen by every interpreter loop it
+    /// leaves, it is reported again only after a statement was executed.
+    last_exception: Option<u64>,
+}
+
+impl Debugger {
+    pub(crate) fn new(delegate: Box<dyn DebuggerDelegate>) -> Self {
+        Self {
+            delegate: Some(delegate),
+            breakpoints: HashSet::new(),
+            pause_on_exceptions: PauseOnExceptions::None,
+            step: None,
+            last_exception: None,
+        }
+    }
+
+    /// Set breakpoint at `line` (1-based) of `file`. Returns false if it was set already.
+    pub fn set_breakpoint(&mut self, file: &str, line: u32) -> bool {
+        self.breakpoints.insert((file.to_owned(), line))
+    }
+
+    /// Remove breakpoint at `line` of `file`. Returns false if there was none.
+    pub fn remove_breakpoint(&mut self, file: &str, line: u32) -> bool {
+        self.breakpoints.remove(&(file.to_owned(), line))
+    }
+
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = (&str, u32)> {
+        self.breakpoints
+            .iter()
+            .map(|(file, line)| (file.as_str(), *line))
+    }
+
+    pub fn set_pause_on_exceptions(&mut self, pause: PauseOnExceptions) {
+        self.pause_on_exceptions = pause;
+    }
+
+    /// Pause at the next statement.
+    pub fn pause(&mut self) {
+        self.step = Some((ResumeAction::StepIn, usize::MAX));
+    }
+
+    fn has_breakpoint(&self, frame: &DebugFrame) -> bool {
+        if self.breakpoints.is_empty() {
+            return false;
+        }
+        match frame.location() {
+            Some(loc) => self
+                .breakpoints
+                .contains(&(frame.file_name().to_owned(), loc.line)),
+            None => false,
+        }
+    }
+
+    fn step_done(&self, depth: usize) -> bool {
+        match self.step {
+            Some((ResumeAction::StepIn, _)) => true,
+            Some((ResumeAction::StepOver, at)) => depth <= at,
+            Some((ResumeAction::StepOut, at)) => depth < at,
+            _ => false,
+        }
+    }
+}
+
+/// Frame of JS function on the call stack. Valid only during the callback it was passed to.
+#[derive(Clone, Copy)]
+pub struct DebugFrame {
+    frame: *mut CallFrame,
+    depth: usize,
+}
+
+impl DebugFrame {
+    fn frame(&self) -> &CallFrame {
+        unsafe { &*self.frame }
+    }
+
+    pub fn code_block(&self) -> GcPointer<CodeBlock> {
+        self.frame().code_block.unwrap()
+    }
+
+    /// Number of frames below and including this one.
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    pub fn function_name(&self, ctx: GcPointer<Context>) -> String {
+        ctx.description(self.code_block().name)
+    }
+
+    pub fn file_name(&self) -> &str {
+        unsafe { &(*self.frame).code_block.as_ref().unwrap().file_name }
+    }
+
+    /// Location of the statement being executed, or of the call for outer frames.
+    pub fn location(&self) -> Option<&FileLocation> {
+        let code = unsafe { (*self.frame).code_block.as_ref().unwrap() };
+        let pc = self.frame().ip as usize - code.code.as_ptr() as usize;
+        // `ip` points past the current instruction.
+        code.location_at(pc.checked_sub(1)?)
+    }
+
+    pub fn callee(&self) -> JsValue {
+        self.frame().callee
+    }
+
+    pub fn this(&self) -> JsValue {
+        self.frame().this
+    }
+
+    /// Innermost scope of this frame. Functions without own environment run in the scope they were created in.
+    pub fn environment(&self) -> GcPointer<Environment> {
+        self.frame().env
+    }
+
+    /// Scope chain of this frame from the innermost scope to the global one.
+    pub fn scopes(&self) -> impl Iterator<Item = GcPointer<Environment>> {
+        std::iter::successors(Some(self.environment()), |env| env.parent)
+    }
+
+    /// Stack slots of bindings that are not captured by closures, see [CodeBlock::slot_count].
+    pub fn slots(&self) -> &[JsValue] {
+        let count = self.code_block().slot_count;
+        unsafe { std::slice::from_raw_parts(self.frame().limit, count as _) }
+    }
+
+    /// Name of stack slot `index`, `None` unless the code block records [CodeBlock::var_names].
+    pub fn slot_name(&self, index: usize) -> Option<Symbol> {
+        self.code_block().var_names.as_ref()?.slot(index)
+    }
+}
+
+/// JS frames of the call stack starting with the innermost one.
+pub fn frames(ctx: GcPointer<Context>) -> Vec<DebugFrame> {
+    let mut frames = vec![];
+    let mut depth = ctx.stack.depth();
+    let mut frame = ctx.stack.current;
+    unsafe {
+        while !frame.is_null() {
+            if (*frame).code_block.is_some() {
+                frames.push(DebugFrame { frame, depth });
+            }
+            depth = depth.saturating_sub(1);
+            frame = (*frame).prev;
+        }
+    }
+    frames
+}
+
+/// Run `f` with delegate taken out of the debugger. Returns `None` when debugger is not attached or a callback
+/// is running already.
+fn with_delegate<R>(
+    ctx: GcPointer<Context>,
+    f: impl FnOnce(&mut dyn DebuggerDelegate) -> R,
+) -> Option<R> {
+    let mut vm = ctx.vm();
+    let mut delegate = vm.debugger.as_mut()?.delegate.take()?;
+    let result = f(&mut *delegate);
+    // delegate may have removed or replaced the debugger.
+    if let Some(debugger) = vm.debugger.as_mut() {
+        if debugger.delegate.is_none() {
+            debugger.delegate = Some(delegate);
+        }
+    }
+    Some(result)
+}
+
+fn pause(ctx: GcPointer<Context>, reason: PauseReason) {
+    let frames = frames(ctx);
+    let depth = ctx.stack.depth();
+    if let Some(action) = with_delegate(ctx, |delegate| delegate.paused(ctx, reason, &frames)) {
+        if let Some(debugger) = ctx.vm().debugger.as_mut() {
+            debugger.step = match action {
+                ResumeAction::Continue => None,
+                action => Some((action, depth)),
+            };
+        }
+    }
+}
+
+/// Executes `debug` instruction of `frame`.
+pub(crate) fn debug(ctx: GcPointer<Context>, frame: *mut CallFrame, kind: u32) {
+    let depth = ctx.stack.depth();
+    let frame = DebugFrame { frame, depth };
+    match kind {
+        DEBUG_FUNCTION_ENTRY => {
+            with_delegate(ctx, |delegate| delegate.function_entered(ctx, &frame));
+            return;
+        }
+        DEBUG_STATEMENT => {
+            if let Some(debugger) = ctx.vm().debugger.as_mut() {
+                debugger.last_exception = None;
+            }
+            with_delegate(ctx, |delegate| delegate.statement(ctx, &frame));
+        }
+        _ => (),
+    }
+    let vm = ctx.vm();
+    let reason = match vm.debugger.as_ref() {
+        None => return,
+        Some(_) if kind == DEBUG_DEBUGGER_STATEMENT => PauseReason::DebuggerStatement,
+        Some(debugger) if debugger.step_done(depth) => PauseReason::Step,
+        Some(debugger) if debugger.has_breakpoint(&frame) => PauseReason::Breakpoint,
+        Some(_) => return,
+    };
+    pause(ctx, reason);
+}
+
+/// Called by `ret` instruction of code compiled with debugger attached.
+pub(crate) fn function_exited(ctx: GcPointer<Context>, frame: *mut CallFrame, value: JsValue) {
+    let frame = DebugFrame {
+        frame,
+        depth: ctx.stack.depth(),
+    };
+    with_delegate(ctx, |delegate| delegate.function_exited(ctx, &frame, value));
+}
+
+/// Called when `exception` is thrown, before the stack is unwound.
+pub(crate) fn exception_thrown(ctx: GcPointer<Context>, exception: JsValue) {
+    let mut vm = ctx.vm();
+    let debugger = match vm.debugger.as_mut() {
+        Some(debugger) if debugger.last_exception != Some(exception.get_raw()) => debugger,
+        _ => return,
+    };
+    debugger.last_exception = Some(exception.get_raw());
+    let pause_on_exceptions = debugger.pause_on_exceptions;
+    let caught = is_caught(ctx);
+    with_delegate(ctx, |delegate| delegate.exception(ctx, exception, caught));
+    let pause_now = match pause_on_exceptions {
+        PauseOnExceptions::None => false,
+        PauseOnExceptions::Uncaught => !caught,
+        PauseOnExceptions::All => true,
+    };
+    if pause_now {
+        let reason = PauseReason::Exception {
+            value: exception,
+            caught,
+        };
+        pause(ctx, reason);
+    }
+}
+
+pub(crate) fn script_parsed(ctx: GcPointer<Context>, file: &str, source: &str) {
+    with_delegate(ctx, |delegate| delegate.script_parsed(ctx, file, source));
+}
+
+pub(crate) fn console_message(ctx: GcPointer<Context>, level: &str, args: &[JsValue]) {
+    with_delegate(ctx, |delegate| delegate.console_message(ctx, level, args));
+}
+
+/// Does unwinding of the current call stack find a `try` block before leaving JS code?
+fn is_caught(ctx: GcPointer<Context>) -> bool {
+    let mut frame = ctx.stack.current;
+    unsafe {
+        while !frame.is_null() {
+            if !(*frame).try_stack.is_empty() {
+                return true;
+            }
+            if (*frame).exit_on_return {
+                return false;
+            }
+            frame = (*frame).prev;
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{options::EvalOptions, vm::tests::new_context};
+    use std::{cell::RefCell, rc::Rc};
+
+    struct Recorder {
+        events: Rc<RefCell<Vec<String>>>,
+        /// Answers to pauses, last one first.
+        actions: Vec<ResumeAction>,
+    }
+
+    impl DebuggerDelegate for Recorder {
+        fn paused(
+            &mut self,
+            ctx: GcPointer<Context>,
+            reason: PauseReason,
+            frames: &[DebugFrame],
+        ) -> ResumeAction {
+            let reason = match reason {
+                PauseReason::Breakpoint => "breakpoint".to_owned(),
+                PauseReason::Step => "step".to_owned(),
+                PauseReason::DebuggerStatement => {
+                    let captured = frames[0]
+                        .scopes()
+                        .flat_map(|env| env.as_slice().to_vec())
+                        .any(|var| var.value.is_number() && var.value.get_number() == 42.0);
+                    format!("debugger(captured={})", captured)
+                }
+                PauseReason::Exception { value, caught } => format!(
+                    "exception({}, caught={})",
+                    value.to_string(ctx).ok().unwrap(),
+                    caught
+                ),
+            };
+            let frame = &frames[0];
+            self.events.borrow_mut().push(format!(
+                "{} {}:{}",
+                reason,
+                frame.file_name(),
+                frame.location().unwrap().line
+            ));
+            self.actions.pop().unwrap_or(ResumeAction::Continue)
+        }
+
+        fn function_entered(&mut self, ctx: GcPointer<Context>, frame: &DebugFrame) {
+            let name = frame.function_name(ctx);
+            self.events.borrow_mut().push(format!("enter {}", name));
+        }
+
+        fn function_exited(&mut self, ctx: GcPointer<Context>, frame: &DebugFrame, value: JsValue) {
+            let name = frame.function_name(ctx);
+            let value = value.to_string(ctx).ok().unwrap();
+            self.events
+                .borrow_mut()
+                .push(format!("exit {} {}", name, value));
+        }
+    }
+
+    fn run(
+        source: &str,
+        actions: &[ResumeAction],
+        setup: impl FnOnce(&mut Debugger),
+    ) -> Vec<String> {
+        let (mut vm, mut ctx) = new_context();
+        let events = Rc::new(RefCell::new(vec![]));
+        let mut actions = actions.to_vec();
+        actions.reverse();
+        let debugger = vm.set_debugger(Recorder {
+            events: events.clone(),
+            actions,
+        });
+        setup(debugger);
+        let _ = ctx.eval_internal(Some("test.js"), false, source, false);
+        events.take()
+    }
+
+    #[test]
+    fn test_breakpoints_and_stepping() {
+        let source = "function add(a, b) {
+                          var sum = a + b;
+                          return sum;
+                      }
+                      var x = add(1, 2);
+                      var y = add(x, 3);
+                      y;";
+        let events = run(
+            source,
+            &[
+                ResumeAction::StepIn,
+                ResumeAction::StepOut,
+                ResumeAction::StepOver,
+            ],
+            |debugger| {
+                debugger.set_breakpoint("test.js", 5);
+            },
+        );
+        assert_eq!(
+            events,
+            [
+                "breakpoint test.js:5",
+                "enter add",
+                "step test.js:2",
+                "exit add 3",
+                "step test.js:6",
+                "enter add",
+                "exit add 6",
+                "step test.js:7",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_debugger_statement_and_exceptions() {
+        let source = "function f() {
+                          var v = 42;
+                          function g() { return v; }
+                          debugger;
+                          try { throw 1; } catch (e) {}
+                          throw 2;
+                      }
+                      f();";
+        let events = run(source, &[], |debugger| {
+            debugger.set_pause_on_exceptions(PauseOnExceptions::All);
+        });
+        assert_eq!(
+            events,
+            [
+                "enter f",
+                "debugger(captured=true) test.js:4",
+                "exception(1, caught=true) test.js:5",
+                "exception(2, caught=false) test.js:6",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_eval_offset() {
+        let (mut vm, mut ctx) = new_context();
+        let events = Rc::new(RefCell::new(vec![]));
+        vm.set_debugger(Recorder {
+            events: events.clone(),
+            actions: vec![],
+        });
+        let options = EvalOptions::default()
+            .with_filename("page.html")
+            .with_offset(10, 4);
+        ctx.eval_with("1;\ndebugger;", &options).ok().unwrap();
+        assert_eq!(events.take(), ["debugger(captured=false) page.html:12"]);
+    }
+}
@@ -0,0 +1,201 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! Human readable bytecode listing.
+//!
+//! Unlike [CodeBlock::display_to] the disassembler resolves operands: property names, literals, nested
+//! functions and jump targets are printed next to raw indexes and every statement is prefixed with its
+//! source position.
+//!
+//! ```text
+//! function 'inc' (params: 1, vars: 0, slots: 1, strict: false)
+//!   ; line 2:5
+//!   0000: get_slot 0
+//!   0005: push_int 1
+//!   0010: add
+//!   0015: ret
+//! ```
+use super::{code_block::CodeBlock, context::Context, value::JsValue};
+use crate::{
+    bytecode::opcodes::Opcode,
+    gc::cell::GcPointer,
+    jsrt::regexp::JsRegExp,
+    vm::class::JsClass,
+};
+use std::{collections::BTreeMap, fmt::Write};
+
+fn mnemonic(op: Opcode) -> String {
+    let name = format!("{:?}", op);
+    name.trim_start_matches("OP_").to_lowercase()
+}
+
+fn literal(value: JsValue) -> String {
+    if value.is_undefined() {
+        "undefined".to_string()
+    } else if value.is_null() {
+        "null".to_string()
+    } else if value.is_bool() {
+        value.get_bool().to_string()
+    } else if value.is_int32() {
+        value.get_int32().to_string()
+    } else if value.is_double() {
+        value.get_double().to_string()
+    } else if value.is_jsstring() {
+        format!("{:?}", value.get_jsstring().as_str())
+    } else if value.is_jsobject() && value.get_jsobject().is_class(JsRegExp::class()) {
+        let object = value.get_jsobject();
+        let regexp = object.data::<JsRegExp>();
+        format!("/{}/{}", regexp.original_source, regexp.original_flags)
+    } else {
+        "<object>".to_string()
+    }
+}
+
+impl CodeBlock {
+    /// Disassemble this code block and all functions declared inside of it.
+    pub fn disassemble(&self, ctx: GcPointer<Context>) -> String {
+        let mut out = String::new();
+        self.disassemble_to(ctx, &mut out, true).unwrap();
+        out
+    }
+
+    /// Write listing of this code block to `out`. Nested functions are listed after the body when
+    /// `recursive` is true.
+    pub(crate) fn disassemble_to(
+        &self,
+        ctx: GcPointer<Context>,
+        out: &mut dyn Write,
+        recursive: bool,
+    ) -> std::fmt::Result {
+        let kind = if self.top_level { "script" } else { "function" };
+        writeln!(
+            out,
+            "{} '{}' (params: {}, vars: {}, slots: {}, strict: {})",
+            kind,
+            ctx.description(self.name),
+            self.param_count,
+            self.var_count,
+            self.slot_count,
+            self.strict
+        )?;
+        if self.lazy.is_some() {
+            return writeln!(out, "  ; not compiled yet");
+        }
+        // statements are recorded after their bodies, so the innermost one wins for shared start address.
+        let mut lines = BTreeMap::new();
+        for (range, loc) in self.loc.iter() {
+            lines.entry(range.start).or_insert((loc.line, loc.col));
+        }
+        let code = &self.code;
+        let operand =
+            |at: usize| u32::from_le_bytes([code[at], code[at + 1], code[at + 2], code[at + 3]]);
+        let name = |at: usize| ctx.description(self.names[operand(at) as usize]);
+        let mut pc = 0;
+        while pc < code.len() {
+            if let Some((line, col)) = lines.get(&pc) {
+                writeln!(out, "  ; line {}:{}", line, col)?;
+            }
+            let op = unsafe { std::mem::transmute::<u8, Opcode>(code[pc]) };
+            let next = pc + 1 + op.operand_size();
+            write!(out, "  {:04}: {}", pc, mnemonic(op))?;
+            match op {
+                _ if op.is_jump() => {
+                    let offset = operand(pc + 1) as i32;
+                    write!(out, " {} (-> {:04})", offset, next as i32 + offset)?;
+                }
+                Opcode::OP_PUSH_INT | Opcode::OP_PUSH_INT_ADD => {
+                    write!(out, " {}", operand(pc + 1) as i32)?
+                }
+                Opcode::OP_PUSH_LITERAL => {
+                    let ix = operand(pc + 1);
+                    write!(out, " #{} {}", ix, literal(self.literals[ix as usize]))?;
+                }
+                Opcode::OP_GET_FUNCTION => {
+                    let ix = operand(pc + 1);
+                    write!(
+                        out,
+                        " #{} '{}'",
+                        ix,
+                        ctx.description(self.codes[ix as usize].name)
+                    )?;
+                }
+                Opcode::OP_GET_BY_ID
+                | Opcode::OP_TRY_GET_BY_ID
+                | Opcode::OP_PUT_BY_ID
+                | Opcode::OP_GET_GLOBAL
+                | Opcode::OP_PUT_GLOBAL => {
+                    write!(out, " '{}' (feedback #{})", name(pc + 1), operand(pc + 5))?;
+                }
+                Opcode::OP_DELETE_BY_ID | Opcode::OP_CHECK_TDZ | Opcode::OP_THROW_CONST_ASSIGN => {
+                    write!(out, " '{}'", name(pc + 1))?
+                }
+                Opcode::OP_GE0GL
+                | Opcode::OP_GE0SL
+                | Opcode::OP_GET_LOCAL
+                | Opcode::OP_SET_LOCAL
+                | Opcode::OP_DECL_LET
+                | Opcode::OP_DECL_CONST
+                | Opcode::OP_GET_SLOT
+                | Opcode::OP_SET_SLOT
+                | Opcode::OP_INC_SLOT
+                | Opcode::OP_DEC_SLOT
+                | Opcode::OP_GE0GL_GET_BY_ID => write!(out, " {}", operand(pc + 1))?,
+                Opcode::OP_GET_ENV => write!(out, " depth {}", operand(pc + 1))?,
+                Opcode::OP_DEBUG => write!(out, " kind {}", operand(pc + 1))?,
+                Opcode::OP_CALL | Opcode::OP_TAILCALL | Opcode::OP_NEW | Opcode::OP_TAILNEW => {
+                    write!(out, " argc {}", operand(pc + 1))?
+                }
+                Opcode::OP_CALL_EVAL => {
+                    write!(out, " argc {} scope #{}", operand(pc + 1), operand(pc + 5))?
+                }
+                Opcode::OP_NEWARRAY => write!(out, " count {}", operand(pc + 1))?,
+                Opcode::OP_CALL_BUILTIN => write!(
+                    out,
+                    " argc {} builtin #{} effect {}",
+                    operand(pc + 1),
+                    operand(pc + 5),
+                    operand(pc + 9)
+                )?,
+                // remaining operands are profiles that only make sense at runtime.
+                _ => (),
+            }
+            writeln!(out)?;
+            pc = next;
+        }
+        if recursive {
+            for code in self.codes.iter() {
+                writeln!(out)?;
+                code.disassemble_to(ctx, out, true)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl GcPointer<Context> {
+    /// Compile `script` the same way [Context::eval](super::context::Context::eval) does and return its
+    /// disassembly instead of running it.
+    pub fn disassemble_script(self, script: &str) -> Result<String, JsValue> {
+        let code = self.compile_eval_code(None, false, script, false)?;
+        Ok(code.disassemble(self))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::vm::tests::new_context;
+
+    #[test]
+    fn test_disassemble() {
+        let (_vm, mut ctx) = new_context();
+        let listing = ctx
+            .disassemble_script("var o = {x: 1};\nfunction f(a) { return a + 1 }\no.x = f(o.x);")
+            .ok()
+            .unwrap();
+        assert!(listing.contains("script '<script>'"));
+        assert!(listing.contains("function 'f'"));
+        assert!(listing.contains("put_by_id 'x'"));
+        assert!(listing.contains("; line 3:1"));
+    }
+}
@@ -4,7 +4,7 @@
 use crate::prelude::*;
 use std::alloc::{alloc_zeroed, dealloc, Layout};
 
-use super::context::Context;
+use super::{code_block::CodeBlock, context::Context};
 
 #[derive(Clone, Copy, PartialEq, Eq)]
 #[repr(C)]
@@ -13,11 +13,26 @@ pub struct Variable {
     pub mutable: bool,
 }
 
+impl Variable {
+    /// Value of `let` and `const` bindings between entering their scope and evaluating the declaration.
+    /// Bytecode reads it with `check_tdz`, so it never escapes into user code.
+    pub fn uninitialized() -> JsValue {
+        JsValue::encode_empty_value()
+    }
+
+    pub fn is_initialized(&self) -> bool {
+        !self.value.is_empty()
+    }
+}
+
 #[repr(C)]
 pub struct Environment {
     pub parent: Option<GcPointer<Self>>,
     pub values_ptr: *mut Variable,
     pub values_count: u32,
+    /// Code block whose variables this environment holds, set when it records
+    /// [var_names](CodeBlock::var_names).
+    pub code: Option<GcPointer<CodeBlock>>,
 }
 
 impl Environment {
@@ -36,6 +51,7 @@ impl Environment {
                 parent: None,
                 values_ptr: ptr,
                 values_count: cap,
+                code: None,
             })
         }
     }
@@ -47,6 +63,23 @@ impl Environment {
     pub fn as_slice_mut(&mut self) -> &mut [Variable] {
         unsafe { std::slice::from_raw_parts_mut(self.values_ptr, self.values_count as _) }
     }
+
+    /// Name of variable at `index`, `None` without debug info.
+    pub fn name(&self, index: usize) -> Option<Symbol> {
+        self.code?.var_names.as_ref()?.env(index)
+    }
+
+    /// `TypeError` thrown by assignment to immutable variable at `index`.
+    pub fn immutable_error(&self, ctx: GcPointer<Context>, index: usize) -> JsValue {
+        let message = match self.name(index) {
+            Some(name) => format!(
+                "Assignment to constant variable '{}'",
+                ctx.description(name)
+            ),
+            None => "Cannot assign to immutable variable".to_string(),
+        };
+        JsValue::new(ctx.new_type_error(message))
+    }
 }
 
 impl Drop for Environment {
@@ -65,6 +98,7 @@ impl GcCell for Environment {}
 impl Trace for Environment {
     fn trace(&self, visitor: &mut Visitor) {
         self.parent.trace(visitor);
+        self.code.trace(visitor);
         for var in self.as_slice() {
             var.value.trace(visitor);
         }
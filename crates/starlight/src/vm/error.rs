@@ -286,4 +286,160 @@ impl JsURIError {
         }
         obj
     }
-}
\ No newline at end of file
+}
+/// Rust representation of a thrown JS value. Implements [std::error::Error] so exceptions
+/// returned from `eval` can be propagated with `?` into `Box<dyn Error>` or `anyhow::Error`.
+#[derive(Clone, Debug, Default)]
+pub struct JsException {
+    /// `name` property of thrown error, empty if thrown value is not an object.
+    pub name: String,
+    /// `message` property of thrown error or thrown value converted to string.
+    pub message: String,
+    /// `stack` property of thrown error captured at throw site.
+    pub stack: String,
+}
+
+impl JsException {
+    pub fn new(ctx: GcPointer<Context>, value: JsValue) -> Self {
+        let property = |name: &str| -> String {
+            if !value.is_jsobject() {
+                return String::new();
+            }
+            let mut object = value.get_jsobject();
+            match object.get(ctx, name.intern()) {
+                Ok(val) if !val.is_undefined() => val.to_string(ctx).unwrap_or_default(),
+                _ => String::new(),
+            }
+        };
+        if value.is_jsobject() {
+            Self {
+                name: property("name"),
+                message: property("message"),
+                stack: property("stack"),
+            }
+        } else {
+            Self {
+                name: String::new(),
+                message: value.to_string(ctx).unwrap_or_default(),
+                stack: String::new(),
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for JsException {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.name.is_empty() {
+            write!(f, "{}", self.message)?;
+        } else if self.message.is_empty() {
+            write!(f, "{}", self.name)?;
+        } else {
+            write!(f, "{}: {}", self.name, self.message)?;
+        }
+        if !self.stack.is_empty() {
+            write!(f, "\n{}", self.stack)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for JsException {}
+
+/// Converts Rust errors into JS exceptions so native functions can use `?` on them:
+///
+/// ```rust,ignore
+/// let contents = std::fs::read_to_string(path).or_throw(ctx)?;
+/// ```
+pub trait IntoJsResult<T> {
+    fn or_throw(self, ctx: GcPointer<Context>) -> Result<T, JsValue>;
+}
+
+impl<T, E: std::error::Error> IntoJsResult<T> for Result<T, E> {
+    fn or_throw(self, ctx: GcPointer<Context>) -> Result<T, JsValue> {
+        self.map_err(|err| JsValue::new(ctx.new_error_from(err)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::{arguments::Arguments, function::JsNativeFunction, tests::new_context};
+    use std::io;
+
+    #[derive(Debug)]
+    struct Outer(io::Error);
+
+    impl std::fmt::Display for Outer {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "outer")
+        }
+    }
+
+    impl std::error::Error for Outer {
+        fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+            Some(&self.0)
+        }
+    }
+
+    fn fail(ctx: GcPointer<Context>, _: &Arguments) -> Result<JsValue, JsValue> {
+        Err(Outer(io::Error::new(io::ErrorKind::Other, "inner"))).or_throw(ctx)
+    }
+
+    #[test]
+    fn test_rust_error_cause_chain() {
+        let (_vm, mut ctx) = new_context();
+        let fun = JsNativeFunction::new(ctx, "fail".intern(), fail, 0);
+        ctx.global_object()
+            .put(ctx, "fail".intern(), JsValue::new(fun), false)
+            .ok()
+            .unwrap();
+
+        let message = ctx
+            .eval("try { fail(); } catch (e) { e instanceof Error ? e.message : 'not an Error' }")
+            .ok()
+            .unwrap();
+        assert_eq!(message.get_jsstring().as_str(), "outer: inner");
+
+        let err = ctx
+            .eval("function caller() { fail(); }\ncaller();")
+            .err()
+            .unwrap();
+        let exception = ctx.to_rust_error(err);
+        assert_eq!(exception.name, "Error");
+        assert_eq!(exception.message, "outer: inner");
+        assert!(exception.stack.contains("'caller'"), "{}", exception.stack);
+    }
+
+    #[test]
+    fn test_exception_round_trip() {
+        let (_vm, mut ctx) = new_context();
+        let err = ctx
+            .eval("function thrower() { throw new TypeError('bad'); }\nthrower();")
+            .err()
+            .unwrap();
+        let exception = ctx.to_rust_error(err);
+        assert_eq!(exception.name, "TypeError");
+        assert_eq!(exception.message, "bad");
+        assert!(exception.stack.contains("'thrower'"), "{}", exception.stack);
+        assert_eq!(
+            exception.to_string(),
+            format!("TypeError: bad\n{}", exception.stack)
+        );
+
+        // errors coming back from JS keep their text when thrown into JS again
+        let value = Err::<(), _>(exception).or_throw(ctx).err().unwrap();
+        let again = ctx.to_rust_error(value);
+        assert_eq!(again.name, "Error");
+        assert!(
+            again.message.starts_with("TypeError: bad\n"),
+            "{}",
+            again.message
+        );
+
+        let err = ctx.eval("throw 42;").err().unwrap();
+        let exception = ctx.to_rust_error(err);
+        assert!(exception.name.is_empty());
+        assert!(exception.stack.is_empty());
+        assert_eq!(exception.to_string(), "42");
+    }
+}
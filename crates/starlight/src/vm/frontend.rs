@@ -0,0 +1,288 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+//! Parsing of source text into the swc AST the [ByteCompiler](crate::bytecompiler::ByteCompiler) compiles.
+//!
+//! Scripts, modules, `eval` code and `Function` bodies are parsed by the [Frontend] of the runtime, set with
+//! [VirtualMachine::with_frontend](crate::vm::VirtualMachine::with_frontend). The default is [SwcFrontend].
+//! Tools that already have an AST skip parsing altogether with
+//! [Context::compile_ast](crate::vm::context::Context::compile_ast).
+use super::init_es_config;
+use crate::{bytecompiler::CompileError, options::Options};
+use swc_common::{input::StringInput, BytePos};
+#[cfg(any(feature = "typescript", feature = "jsx"))]
+use swc_common::{Globals, GLOBALS};
+use swc_ecmascript::{
+    ast::{Module, Program, Script},
+    parser::{Parser, Syntax},
+};
+
+/// What source text is parsed as.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ParseGoal {
+    Script,
+    Module,
+}
+
+/// Parser producing the AST compiled by the runtime.
+pub trait Frontend {
+    /// Parse `source` as a [Program::Script] or a [Program::Module] depending on `goal`. `start` is the position
+    /// of the first byte of `source`; spans in the result must be positions in the same file so that line
+    /// numbers and `Function.prototype.toString` are right. Errors are messages of the `SyntaxError` thrown.
+    fn parse(&self, source: &str, start: BytePos, goal: ParseGoal) -> Result<Program, String>;
+    /// Whether the source is JavaScript the pre-parser understands. Only then `Options::lazy_compilation` skips
+    /// function bodies, which are parsed as plain JavaScript on their first call.
+    fn is_javascript(&self) -> bool {
+        false
+    }
+}
+
+/// Frontend using the swc parser, ECMAScript with dynamic `import` by default. With the `typescript` feature
+/// TypeScript is stripped of types after parsing, with the `jsx` feature JSX is compiled to calls of
+/// [SwcFrontend::jsx] pragmas.
+pub struct SwcFrontend {
+    pub syntax: Syntax,
+    pub jsx: Option<JsxPragmas>,
+}
+
+/// Functions JSX compiles to: `<a x={1}>b</a>` becomes `pragma("a", { x: 1 }, "b")` and `<>b</>` becomes
+/// `pragma(fragment, null, "b")`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct JsxPragmas {
+    pub pragma: String,
+    pub fragment: String,
+}
+
+impl Default for JsxPragmas {
+    fn default() -> Self {
+        Self {
+            pragma: "React.createElement".to_owned(),
+            fragment: "React.Fragment".to_owned(),
+        }
+    }
+}
+
+impl Default for SwcFrontend {
+    fn default() -> Self {
+        Self {
+            syntax: Syntax::Es(init_es_config()),
+            jsx: None,
+        }
+    }
+}
+
+impl SwcFrontend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Frontend for TypeScript, see [Context::eval_typescript](crate::vm::context::Context::eval_typescript).
+    #[cfg(feature = "typescript")]
+    pub fn typescript() -> Self {
+        Self::default().with_syntax(Syntax::Typescript(swc_ecmascript::parser::TsConfig {
+            dynamic_import: true,
+            ..Default::default()
+        }))
+    }
+
+    /// Default frontend of a runtime created with `options`.
+    pub fn for_options(options: &Options) -> Self {
+        match options.jsx {
+            Some(ref pragma) => Self::default().with_jsx(JsxPragmas {
+                pragma: pragma.clone(),
+                fragment: options.jsx_fragment.clone(),
+            }),
+            None => Self::default(),
+        }
+    }
+
+    pub fn with_syntax(mut self, syntax: Syntax) -> Self {
+        self.syntax = syntax;
+        self
+    }
+
+    /// Accept JSX and compile it to calls of `pragmas`.
+    pub fn with_jsx(mut self, pragmas: JsxPragmas) -> Self {
+        match self.syntax {
+            Syntax::Es(ref mut config) => config.jsx = true,
+            Syntax::Typescript(ref mut config) => config.tsx = true,
+        }
+        self.jsx = Some(pragmas);
+        self
+    }
+
+    /// Lower TypeScript and JSX to plain JavaScript the compiler understands.
+    fn transform(&self, program: Program) -> Program {
+        #[cfg(feature = "typescript")]
+        let program = match self.syntax {
+            Syntax::Typescript(_) => {
+                use swc_ecmascript::{transforms::typescript::strip, visit::FoldWith};
+                GLOBALS.set(&Globals::new(), || program.fold_with(&mut strip()))
+            }
+            _ => program,
+        };
+        #[cfg(feature = "jsx")]
+        let program = match self.jsx {
+            Some(ref pragmas) => {
+                use swc_common::{comments::SingleThreadedComments, sync::Lrc, SourceMap};
+                use swc_ecmascript::{transforms::react, visit::FoldWith};
+                let options = react::Options {
+                    pragma: pragmas.pragma.clone(),
+                    pragma_frag: pragmas.fragment.clone(),
+                    ..Default::default()
+                };
+                let cm: Lrc<SourceMap> = Default::default();
+                let mut jsx = react::jsx(cm, None::<SingleThreadedComments>, options);
+                GLOBALS.set(&Globals::new(), || program.fold_with(&mut jsx))
+            }
+            None => program,
+        };
+        program
+    }
+}
+
+impl Frontend for SwcFrontend {
+    fn parse(&self, source: &str, start: BytePos, goal: ParseGoal) -> Result<Program, String> {
+        let input = StringInput::new(source, start, start + BytePos(source.len() as u32));
+        let mut parser = Parser::new(self.syntax, input, None);
+        let program = match goal {
+            ParseGoal::Script => parser.parse_script().map(Program::Script),
+            ParseGoal::Module => parser.parse_module().map(Program::Module),
+        };
+        program
+            .map(|program| self.transform(program))
+            .map_err(|e| e.kind().msg().to_string())
+    }
+
+    fn is_javascript(&self) -> bool {
+        matches!(self.syntax, Syntax::Es(_)) && self.jsx.is_none()
+    }
+}
+
+/// Parse `source` starting at `start` as a script with `frontend`.
+pub(crate) fn parse_script(
+    frontend: &dyn Frontend,
+    source: &str,
+    start: BytePos,
+) -> Result<Script, CompileError> {
+    match frontend.parse(source, start, ParseGoal::Script) {
+        Ok(Program::Script(script)) => Ok(script),
+        Ok(Program::Module(_)) => Err(CompileError::Syntax(
+            "Frontend returned a module for a script".to_string(),
+        )),
+        Err(msg) => Err(CompileError::Syntax(msg)),
+    }
+}
+
+/// Parse `source` starting at `start` as an ES module with `frontend`.
+pub(crate) fn parse_module(
+    frontend: &dyn Frontend,
+    source: &str,
+    start: BytePos,
+) -> Result<Module, CompileError> {
+    match frontend.parse(source, start, ParseGoal::Module) {
+        Ok(Program::Module(module)) => Ok(module),
+        Ok(Program::Script(_)) => Err(CompileError::Syntax(
+            "Frontend returned a script for a module".to_string(),
+        )),
+        Err(msg) => Err(CompileError::Syntax(msg)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        options::Options,
+        prelude::*,
+        vm::tests::{new_context, new_context_with},
+        Platform,
+    };
+    use std::{cell::Cell, rc::Rc};
+
+    /// Counts parsed sources and rejects the ones containing `#`.
+    struct CountingFrontend(Rc<Cell<usize>>);
+
+    impl Frontend for CountingFrontend {
+        fn parse(&self, source: &str, start: BytePos, goal: ParseGoal) -> Result<Program, String> {
+            self.0.set(self.0.get() + 1);
+            if source.contains('#') {
+                return Err("no hashes".to_owned());
+            }
+            SwcFrontend::new().parse(source, start, goal)
+        }
+    }
+
+    #[test]
+    fn test_custom_frontend() {
+        Platform::initialize();
+        let count = Rc::new(Cell::new(0));
+        let mut vm = Platform::new_runtime(Options::default(), None)
+            .with_frontend(CountingFrontend(count.clone()));
+        let mut ctx = vm.new_context();
+        let result = ctx
+            .eval("eval('1 + 1') + new Function('return 3')()")
+            .unwrap_or_else(|_| panic!());
+        assert_eq!(result.to_number(ctx).unwrap_or_else(|_| panic!()), 5.0);
+        assert_eq!(count.get(), 3);
+        let err = ctx.eval("'#'").err().unwrap();
+        assert_eq!(
+            err.to_string(ctx).unwrap_or_else(|_| panic!()),
+            "SyntaxError: no hashes"
+        );
+    }
+
+    #[test]
+    fn test_compile_ast() {
+        let (mut vm, mut ctx) = new_context();
+        let program =
+            crate::vm::parse("var fromAst = 20; fromAst + 1", false).unwrap_or_else(|_| panic!());
+        let fun = ctx
+            .compile_ast(&program, "ast", None)
+            .unwrap_or_else(|_| panic!());
+        let mut args = Arguments::new(JsValue::encode_undefined_value(), &mut []);
+        let result = fun
+            .get_jsobject()
+            .as_function_mut()
+            .call(ctx, &mut args, fun)
+            .unwrap_or_else(|_| panic!());
+        assert_eq!(result.to_number(ctx).unwrap_or_else(|_| panic!()), 21.0);
+    }
+
+    #[cfg(feature = "typescript")]
+    #[test]
+    fn test_eval_typescript() {
+        let (_vm, mut ctx) = new_context();
+        let result = ctx
+            .eval_typescript(
+                None,
+                "interface Point { x: number; y: number }
+                 function norm(p: Point): number { return p.x * p.x + p.y * p.y; }
+                 const origin = { x: 3, y: 4 } as Point;
+                 norm(origin)",
+            )
+            .unwrap_or_else(|_| panic!());
+        assert_eq!(result.to_number(ctx).unwrap_or_else(|_| panic!()), 25.0);
+        assert!(ctx.eval_typescript(None, "let x: = 1").is_err());
+    }
+
+    #[cfg(feature = "jsx")]
+    #[test]
+    fn test_jsx() {
+        let options = Options::default().with_jsx("h", "Fragment");
+        let (_vm, mut ctx) = new_context_with(options);
+        let result = ctx
+            .eval(
+                "var Fragment = 'frag';
+                 function h(tag, props, ...children) {
+                     return [tag, props && props.id, children.join('')].join(':');
+                 }
+                 <><div id=\"x\">hi</div></>",
+            )
+            .unwrap_or_else(|_| panic!());
+        assert_eq!(
+            result.to_string(ctx).unwrap_or_else(|_| panic!()),
+            "frag::div:x:hi"
+        );
+    }
+}
@@ -6,17 +6,48 @@ use super::interpreter::eval;
 use super::structure::Structure;
 use super::symbol_table::Symbol;
 use super::value::*;
-use super::{arguments::*, code_block::CodeBlock};
+use super::{arguments::*, code_block::CodeBlock, debugger};
 use super::{array_storage::ArrayStorage, property_descriptor::*};
 use super::{attributes::*, symbol_table::Internable};
 use super::{environment::Environment, object::*};
 use super::{error::JsRangeError, string::*};
 use super::{error::JsTypeError, method_table::*};
 use super::{interpreter::frame::CallFrame, slot::*};
-use crate::constant::S_CONSTURCTOR;
 use crate::gc::cell::{GcPointer, Trace, Visitor};
 use crate::prelude::*;
-use std::{intrinsics::unlikely, mem::ManuallyDrop};
+use crate::utils::unlikely;
+use std::{
+    mem::ManuallyDrop,
+    panic::{self, AssertUnwindSafe},
+};
+
+/// Call native function `f`, turning a panic into a JS error with the panic message. Frames of JS calls made
+/// by `f` that were left by the panic are dropped, so the caller sees the stack as it was before the call.
+fn call_native(
+    mut ctx: GcPointer<Context>,
+    args: &Arguments,
+    f: impl FnOnce(GcPointer<Context>, &Arguments) -> Result<JsValue, JsValue>,
+) -> Result<JsValue, JsValue> {
+    if unlikely(ctx.stack.native_stack_exhausted()) {
+        let msg = JsString::new(ctx, "stack overflow");
+        return Err(JsValue::new(JsRangeError::new(ctx, msg, None)));
+    }
+    let mark = ctx.stack.mark();
+    let payload = match panic::catch_unwind(AssertUnwindSafe(|| f(ctx, args))) {
+        Ok(result) => return result,
+        Err(payload) => payload,
+    };
+    ctx.stack.unwind_to(mark);
+    let message = match payload.downcast_ref::<&str>() {
+        Some(message) => message.to_string(),
+        None => match payload.downcast_ref::<String>() {
+            Some(message) => message.clone(),
+            None => "unknown panic".to_owned(),
+        },
+    };
+    let message = JsString::new(ctx, format!("native function panicked: {}", message));
+    Err(JsValue::new(JsError::new(ctx, message, None)))
+}
 
 pub struct JsFunction {
     pub construct_struct: Option<GcPointer<Structure>>,
@@ -53,17 +84,29 @@ impl JsFunction {
     pub fn is_generator(&self) -> bool {
         matches!(self.ty, FuncType::Generator(_))
     }
+    /// Returns false for functions that throw when called with `new`, bound functions are constructors
+    /// when their target is.
+    pub fn is_constructor(&self) -> bool {
+        match self.ty {
+            FuncType::Generator(_) => false,
+            FuncType::Bound(ref x) => x.target.as_function().is_constructor(),
+            _ => true,
+        }
+    }
     pub fn has_instance(
         &self,
         this: &mut GcPointer<JsObject>,
         ctx: GcPointer<Context>,
         val: JsValue,
     ) -> Result<bool, JsValue> {
+        if let FuncType::Bound(ref bound) = self.ty {
+            return super::operations::instance_of(ctx, val, JsValue::new(bound.target));
+        }
         if !val.is_jsobject() {
             return Ok(false);
         }
 
-        let got = this.get(ctx, "prototype".intern())?;
+        let got = this.get(ctx, Symbol::PROTOTYPE)?;
         if !got.is_jsobject() {
             let msg = JsString::new(ctx, "'prototype' is not object");
             return Err(JsValue::encode_object_value(JsTypeError::new(
@@ -153,7 +196,7 @@ impl JsFunction {
         structure: Option<GcPointer<Structure>>,
         this_fn: JsValue,
     ) -> Result<JsValue, JsValue> {
-        if unlikely(self.is_generator()) {
+        if unlikely(!self.is_constructor()) {
             return Err(JsValue::new(
                 ctx.new_type_error("function not a constructor"),
             ));
@@ -174,22 +217,27 @@ impl JsFunction {
         this: JsValue,
     ) -> Result<JsValue, JsValue> {
         match self.ty {
-            FuncType::Native(ref x) => (x.func)(self.ctx, args),
-            FuncType::Closure(ref x) => (x.func)(self.ctx, args),
+            FuncType::Native(ref x) => call_native(self.ctx, args, x.func),
+            FuncType::Closure(ref x) => call_native(self.ctx, args, &*x.func),
             FuncType::User(ref x) => {
                 let mut ctx = self.ctx;
                 ctx.perform_vm_call(x, JsValue::encode_object_value(x.scope), args, this)
             }
             FuncType::Bound(ref mut x) => {
-                let mut ctx = self.ctx;
-
+                let ctx = self.ctx;
+                // bound arguments are followed by the arguments of this call, `this` is only bound when
+                // not constructing, `new` passes an object created from the prototype of the target
+                let mut values = x.args.as_slice().to_vec();
+                values.extend_from_slice(&args.values);
                 let mut args = Arguments {
-                    this: x.this,
+                    this: if args.ctor_call { args.this } else { x.this },
                     ctor_call: args.ctor_call,
-                    values: x.args.as_slice_mut(),
+                    values: &mut values,
                 };
                 let mut target = x.target;
-                target.as_function_mut().call(ctx, &mut args, this)
+                target
+                    .as_function_mut()
+                    .call(ctx, &mut args, JsValue::new(target))
             }
             FuncType::Generator(ref mut x) => x.call(self.ctx, args, this),
         }
@@ -408,7 +456,7 @@ impl JsFunction {
         throwable: bool,
     ) -> Result<bool, JsValue> {
         let function = obj.as_function_mut();
-        if name == "prototype".intern() {
+        if name == Symbol::PROTOTYPE {
             // prototype override
             function.construct_struct = None;
             slot.make_uncacheable();
@@ -443,7 +491,7 @@ impl JsNativeFunction {
         let mut func = JsFunction::new(ctx, FuncType::Native(JsNativeFunction { func: f }), false);
         let k = ctx.description(name.into());
 
-        let l = "length".intern();
+        let l = Symbol::LENGTH;
 
         let _ = func.define_own_property(
             ctx,
@@ -476,7 +524,7 @@ impl JsNativeFunction {
                 false,
             )
         );
-        let l = "length".intern();
+        let l = Symbol::LENGTH;
 
         let _ = func.define_own_property(
             ctx,
@@ -554,7 +602,7 @@ impl JsClosureFunction {
             FuncType::Closure(JsClosureFunction { func: Box::new(f) }),
             false,
         );
-        let l = "length".intern();
+        let l = Symbol::LENGTH;
 
         let _ = func.define_own_property(
             ctx,
@@ -622,13 +670,13 @@ impl JsVMFunction {
         drop(defer);
         let _ = proto.define_own_property(
             ctx,
-            S_CONSTURCTOR.intern(),
+            Symbol::CONSTRUCTOR,
             &*DataDescriptor::new(JsValue::encode_object_value(this), W | C),
             false,
         );
         let _ = this.define_own_property(
             ctx,
-            "prototype".intern(),
+            Symbol::PROTOTYPE,
             &*DataDescriptor::new(JsValue::encode_object_value(proto), W),
             false,
         );
@@ -654,9 +702,14 @@ impl GcPointer<JsObject> {
         if let Some(s) = func.construct_struct {
             return Ok(s);
         }
+        // `new` on a bound function creates an instance of the target
+        if let FuncType::Bound(ref x) = func.ty {
+            let mut target = x.target;
+            return target.func_construct_map(ctx);
+        }
 
         let mut slot = Slot::new();
-        let proto = "prototype".intern();
+        let proto = Symbol::PROTOTYPE;
         let res = JsObject::GetNonIndexedSlotMethod(&mut obj, ctx, proto, &mut slot)?;
         let structure = unsafe {
             Structure::new_indexed(
@@ -693,6 +746,64 @@ pub struct JsBoundFunction {
     pub target: GcPointer<JsObject>,
 }
 
+impl JsBoundFunction {
+    /// BoundFunctionCreate: https://tc39.es/ecma262/#sec-boundfunctioncreate
+    ///
+    /// Also defines `name` as "bound " followed by the name of `target` and `length` as the length of
+    /// `target` minus the number of bound arguments, as done by `Function.prototype.bind`.
+    pub fn new(
+        ctx: GcPointer<Context>,
+        mut target: GcPointer<JsObject>,
+        this: JsValue,
+        args: &[JsValue],
+    ) -> Result<GcPointer<JsObject>, JsValue> {
+        letroot!(
+            storage = stack,
+            ArrayStorage::with_size(ctx, args.len() as u32, args.len() as u32)
+        );
+        storage.as_slice_mut().copy_from_slice(args);
+        letroot!(
+            func = stack,
+            JsFunction::new(
+                ctx,
+                FuncType::Bound(JsBoundFunction {
+                    args: storage,
+                    this,
+                    target,
+                }),
+                false,
+            )
+        );
+
+        let length = target.get(ctx, Symbol::LENGTH)?;
+        let length = if length.is_number() {
+            (length.get_number().trunc() - args.len() as f64).max(0.0)
+        } else {
+            0.0
+        };
+        func.define_own_property(
+            ctx,
+            Symbol::LENGTH,
+            &*DataDescriptor::new(JsValue::new(length), C),
+            false,
+        )?;
+        let name = target.get(ctx, "name".intern())?;
+        let name = if name.is_jsstring() {
+            name.get_jsstring().as_str().to_owned()
+        } else {
+            String::new()
+        };
+        let name = JsString::new(ctx, format!("bound {}", name));
+        func.define_own_property(
+            ctx,
+            "name".intern(),
+            &*DataDescriptor::new(JsValue::new(name), C),
+            false,
+        )?;
+        Ok(func)
+    }
+}
+
 /// interpreter call frame copied allocated on the heap. It is used again copied to interpreter stack
 /// when function execution state is restored.
 pub struct HeapCallFrame {
@@ -795,7 +906,7 @@ impl JsGeneratorFunction {
         drop(defer);
         let _ = proto.define_own_property(
             ctx,
-            S_CONSTURCTOR.intern(),
+            Symbol::CONSTRUCTOR,
             &*DataDescriptor::new(JsValue::encode_object_value(this), W | C),
             false,
         );
@@ -803,7 +914,7 @@ impl JsGeneratorFunction {
         letroot!(s = stack, JsString::new(ctx, desc));
         let _ = this.define_own_property(
             ctx,
-            "prototype".intern(),
+            Symbol::PROTOTYPE,
             &*DataDescriptor::new(JsValue::encode_object_value(proto), W),
             false,
         );
@@ -833,8 +944,8 @@ impl JsGeneratorFunction {
         // execute up to OP_INITIAL_YIELD. It does return `undefined` value.
         let ret = self.function.as_function_mut().call(ctx, args, this)?;
         debug_assert!(ret.is_undefined());
-        let mut state = ctx.stack.pop_frame().expect("Empty call stack");
-        let state = unsafe { HeapCallFrame::save(&mut state) };
+        let state = ctx.stack.pop_frame().expect("Empty call stack");
+        let state = unsafe { HeapCallFrame::save(state) };
         let proto = ctx.global_data().generator_structure.unwrap();
         let mut generator = JsObject::new(ctx, &proto, Self::class(), ObjectTag::Ordinary);
         *generator.data::<GeneratorData>() = ManuallyDrop::new(GeneratorData {
@@ -873,7 +984,10 @@ fn async_func_resume(
             match result {
                 Ok(value) => return Ok(value),
                 Err(e) => {
-                    ctx.stacktrace = ctx.stacktrace();
+                    ctx.stacktrace = ctx.exception_stacktrace(e);
+                    if unlikely(ctx.vm.debugger.is_some()) {
+                        debugger::exception_thrown(ctx, e);
+                    }
 
                     if let Some(unwind_frame) = ctx.unwind() {
                         let (env, ip, sp) = (*unwind_frame).try_stack.pop().unwrap();
@@ -920,13 +1034,13 @@ pub(crate) fn js_generator_next(
                     s.state = GeneratorState::Yield;
                     if func_ret.is_native_value() {
                         let frame = ctx.stack.pop_frame();
-                        let mut frame = frame.unwrap();
+                        let frame = frame.unwrap();
                         ret = frame.top();
 
                         unsafe {
                             *frame.at(-1) = JsValue::encode_undefined_value();
                         }
-                        s.func_state.frame = Box::new(unsafe { HeapCallFrame::save(&mut frame) });
+                        s.func_state.frame = Box::new(unsafe { HeapCallFrame::save(frame) });
                         if func_ret.get_native_u32() == FuncRet::YieldStar as u32 {
                             s.state = GeneratorState::YieldStar;
                             *pdone = 2;
@@ -959,13 +1073,13 @@ pub(crate) fn js_generator_next(
 
                 if func_ret.is_native_value() {
                     let frame = ctx.stack.pop_frame();
-                    let mut frame = frame.unwrap();
+                    let frame = frame.unwrap();
 
                     ret = frame.top();
                     unsafe {
                         *frame.at(-1) = JsValue::encode_undefined_value();
                     }
-                    s.func_state.frame = Box::new(unsafe { HeapCallFrame::save(&mut frame) });
+                    s.func_state.frame = Box::new(unsafe { HeapCallFrame::save(frame) });
                     if func_ret.get_native_u32() == FuncRet::YieldStar as u32 {
                         s.state = GeneratorState::YieldStar;
                         *pdone = 2;
@@ -1043,3 +1157,47 @@ impl Trace for AsyncFunctionState {
             .for_each(|(env, _, _)| env.trace(visitor));
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::gc::cell::GcPointer;
+    use crate::vm::context::Context;
+    use crate::vm::symbol_table::Internable;
+    use crate::vm::tests::new_context;
+    use crate::vm::value::JsValue;
+
+    #[test]
+    fn test_native_panic() {
+        use crate::vm::{arguments::Arguments, function::JsNativeFunction};
+        fn boom(_: GcPointer<Context>, args: &Arguments) -> Result<JsValue, JsValue> {
+            panic!("boom {}", args.size());
+        }
+        let (_vm, mut ctx) = new_context();
+        let boom = JsNativeFunction::new(ctx, "boom".intern(), boom, 0);
+        ctx.global_object()
+            .put(ctx, "boom".intern(), JsValue::new(boom), false)
+            .ok()
+            .unwrap();
+
+        let result = ctx
+            .eval(
+                "function nested() { return [1, 2].map(x => x == 2 ? boom(x) : x) }
+                 var messages = [];
+                 try { boom() } catch (e) { messages.push(e.message) }
+                 try { nested() } catch (e) { messages.push(e instanceof Error) }
+                 messages.push(nested.length);
+                 messages.join()",
+            )
+            .ok()
+            .unwrap();
+        assert_eq!(
+            result.get_jsstring().as_str(),
+            "native function panicked: boom 0,true,0"
+        );
+        assert!(ctx.eval("boom()").is_err());
+        let depth = ctx.stack.depth();
+        let result = ctx.eval("[1, 2].map(x => x * 2).join()").ok().unwrap();
+        assert_eq!(result.get_jsstring().as_str(), "2,4");
+        assert_eq!(ctx.stack.depth(), depth);
+    }
+}
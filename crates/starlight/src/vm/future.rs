@@ -0,0 +1,350 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+//! Rust futures as JS promises.
+//!
+//! [VirtualMachine::promise_from_future] hands a future to the runtime and returns a promise settled with the
+//! output of the future. The runtime does not run an executor: futures are polled by
+//! [VirtualMachine::poll_futures], which the host event loop calls after a future was woken. Wakers may be
+//! used from any thread, they only mark the future and call the hook set by
+//! [VirtualMachine::with_future_waker] so the host knows it has to poll. Errors of settling the promises are
+//! returned by [VirtualMachine::poll_futures].
+//!
+//! The opposite direction is [JsPromise::into_future](crate::vm::promise::JsPromise::into_future).
+//!
+//! Futures are boxed in Rust memory that GC neither traces nor scans. JS values a future captures have to be
+//! passed to [VirtualMachine::promise_from_future_rooted], which keeps them alive until the future completes
+//! or its context is removed; values it creates itself and keeps across `.await` have to be held in a
+//! [PersistentRooted] (see [VirtualMachine::add_persistent_root]).
+//!
+//! ```rust,ignore
+//! let notify = Arc::new(tokio::sync::Notify::new());
+//! let waker = notify.clone();
+//! let mut vm = Platform::new_runtime(options, None)
+//!     .with_future_waker(Arc::new(move || waker.notify_one()));
+//! let promise = vm.promise_from_future(ctx, async move {
+//!     let body = client.get(url).await.map_err(|_| JsValue::encode_null_value())?;
+//!     Ok(JsValue::new(body.len() as u32))
+//! })?;
+//! while vm.poll_futures()? != 0 {
+//!     notify.notified().await;
+//! }
+//! ```
+use super::{
+    context::Context, promise::JsPromise, value::JsValue, PersistentRooted, VirtualMachine,
+    VirtualMachineRef,
+};
+use crate::gc::cell::GcPointer;
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    task::{Context as TaskContext, Poll, Wake, Waker},
+};
+
+/// Called by wakers of futures passed to [VirtualMachine::promise_from_future], possibly from other threads.
+pub type FutureWakeHook = Arc<dyn Fn() + Send + Sync>;
+
+pub(crate) struct FutureTask {
+    ctx: GcPointer<Context>,
+    promise: PersistentRooted,
+    /// Values captured by `future`, rooted while the task exists.
+    _roots: Vec<PersistentRooted>,
+    future: Pin<Box<dyn Future<Output = Result<JsValue, JsValue>>>>,
+    waker: Arc<TaskWaker>,
+}
+
+struct TaskWaker {
+    woken: AtomicBool,
+    hook: Option<FutureWakeHook>,
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.woken.store(true, Ordering::Release);
+        if let Some(hook) = &self.hook {
+            hook();
+        }
+    }
+}
+
+impl VirtualMachine {
+    /// Set the hook called when a future passed to [VirtualMachine::promise_from_future] is woken.
+    pub fn with_future_waker(
+        mut self: VirtualMachineRef,
+        hook: FutureWakeHook,
+    ) -> VirtualMachineRef {
+        self.future_wake_hook = Some(hook);
+        self
+    }
+
+    /// Return a promise of `ctx` settled with the output of `future`. The future is first polled by the next
+    /// [VirtualMachine::poll_futures] call, the future waker hook is called to request it.
+    ///
+    /// `future` is not traced by GC, it must not capture JS values, use
+    /// [VirtualMachine::promise_from_future_rooted] for futures that do.
+    pub fn promise_from_future<F>(
+        &mut self,
+        ctx: GcPointer<Context>,
+        future: F,
+    ) -> Result<JsValue, JsValue>
+    where
+        F: Future<Output = Result<JsValue, JsValue>> + 'static,
+    {
+        self.promise_from_future_rooted(ctx, &[], future)
+    }
+
+    /// Like [VirtualMachine::promise_from_future], but `roots` are kept alive until `future` completes. Every
+    /// JS value captured by `future` has to be listed in `roots`.
+    pub fn promise_from_future_rooted<F>(
+        &mut self,
+        ctx: GcPointer<Context>,
+        roots: &[JsValue],
+        future: F,
+    ) -> Result<JsValue, JsValue>
+    where
+        F: Future<Output = Result<JsValue, JsValue>> + 'static,
+    {
+        let promise = JsPromise::new_unresolving(ctx)?;
+        let root = self.add_persistent_root(promise);
+        let roots = roots
+            .iter()
+            .map(|value| self.add_persistent_root(*value))
+            .collect();
+        let waker = Arc::new(TaskWaker {
+            woken: AtomicBool::new(true),
+            hook: self.future_wake_hook.clone(),
+        });
+        self.futures.push(FutureTask {
+            ctx,
+            promise: root,
+            _roots: roots,
+            future: Box::pin(future),
+            waker,
+        });
+        if let Some(hook) = &self.future_wake_hook {
+            hook();
+        }
+        Ok(promise)
+    }
+
+    /// Poll woken futures passed to [VirtualMachine::promise_from_future] and settle promises of completed
+    /// ones. Returns number of futures that are still pending, or the first error of settling a promise, e.g.
+    /// when the runtime has no async scheduler to run its reactions. Other completed futures are settled
+    /// anyway.
+    pub fn poll_futures(&mut self) -> Result<usize, JsValue> {
+        let tasks = std::mem::take(&mut self.futures);
+        let mut pending = Vec::with_capacity(tasks.len());
+        let mut error = None;
+        for mut task in tasks {
+            if !task.waker.woken.swap(false, Ordering::Acquire) {
+                pending.push(task);
+                continue;
+            }
+            let waker = Waker::from(task.waker.clone());
+            let mut cx = TaskContext::from_waker(&waker);
+            let result = match task.future.as_mut().poll(&mut cx) {
+                Poll::Ready(result) => result,
+                Poll::Pending => {
+                    pending.push(task);
+                    continue;
+                }
+            };
+            let promise = task.promise.get_value();
+            let mut object = promise.get_jsobject();
+            let settled = match result {
+                Ok(value) => object.as_promise_mut().resolve(task.ctx, promise, value),
+                Err(error) => object.as_promise_mut().reject(task.ctx, promise, error),
+            };
+            if let Err(err) = settled {
+                error.get_or_insert(err);
+            }
+        }
+        // futures created while polling were pushed to `self.futures`
+        pending.append(&mut self.futures);
+        self.futures = pending;
+        match error {
+            Some(error) => Err(error),
+            None => Ok(self.futures.len()),
+        }
+    }
+
+    /// Drop pending futures of a context that is being removed.
+    pub(crate) fn drop_futures_of(&mut self, ctx: GcPointer<Context>) {
+        self.futures.retain(|task| task.ctx != ctx);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        gc::cell::GcPointer,
+        options::Options,
+        vm::{
+            context::Context as JsContext, promise::JsPromise, string::JsString,
+            tests::new_context, value::JsValue, VM,
+        },
+        Platform,
+    };
+    use std::{
+        cell::RefCell,
+        future::Future,
+        pin::Pin,
+        rc::Rc,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        task::{Context, Poll, Waker},
+    };
+
+    /// Future completed from the outside, like a timer or a socket.
+    #[derive(Default)]
+    struct Signal {
+        value: Option<i32>,
+        waker: Option<Waker>,
+    }
+
+    struct SignalFuture(Rc<RefCell<Signal>>);
+
+    impl Future for SignalFuture {
+        type Output = Result<JsValue, JsValue>;
+
+        fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+            let mut signal = self.0.borrow_mut();
+            match signal.value {
+                Some(value) => Poll::Ready(Ok(JsValue::new(value))),
+                None => {
+                    signal.waker = Some(cx.waker().clone());
+                    Poll::Pending
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_promise_from_future() {
+        Platform::initialize();
+        let jobs = Rc::new(RefCell::new(vec![]));
+        let queue = jobs.clone();
+        let wakes = Arc::new(AtomicUsize::new(0));
+        let counter = wakes.clone();
+        let mut vm = Platform::new_runtime(Options::default(), None)
+            .with_async_scheduler(Box::new(move |job| queue.borrow_mut().push(job)))
+            .with_future_waker(Arc::new(move || {
+                counter.fetch_add(1, Ordering::SeqCst);
+            }));
+        let ctx = vm.new_context();
+        let run_jobs = |ctx: GcPointer<JsContext>| loop {
+            let job = {
+                let mut jobs = jobs.borrow_mut();
+                if jobs.is_empty() {
+                    break;
+                }
+                jobs.remove(0)
+            };
+            job(ctx);
+        };
+
+        let signal = Rc::new(RefCell::new(Signal::default()));
+        let promise = vm
+            .promise_from_future(ctx, SignalFuture(signal.clone()))
+            .unwrap_or_else(|_| panic!());
+        let mut awaited = Box::pin(JsPromise::into_future(ctx, promise));
+        let waker = Waker::from(Arc::new(NoopWake));
+        let mut cx = Context::from_waker(&waker);
+        assert!(awaited.as_mut().poll(&mut cx).is_pending());
+
+        // the host is asked to poll the new future.
+        assert_eq!(wakes.load(Ordering::SeqCst), 1);
+        assert_eq!(vm.poll_futures().ok(), Some(1));
+        assert!(awaited.as_mut().poll(&mut cx).is_pending());
+
+        signal.borrow_mut().value = Some(42);
+        signal.borrow_mut().waker.take().unwrap().wake();
+        assert_eq!(wakes.load(Ordering::SeqCst), 2);
+        assert_eq!(vm.poll_futures().ok(), Some(0));
+        run_jobs(ctx);
+        match awaited.as_mut().poll(&mut cx) {
+            Poll::Ready(Ok(value)) => assert_eq!(value.to_int32(ctx).ok(), Some(42)),
+            _ => panic!("promise is not resolved"),
+        }
+    }
+
+    /// Promise of a future that holds the only reference to a string across `.await`.
+    #[inline(never)]
+    fn capture_string(
+        mut vm: VM,
+        ctx: GcPointer<JsContext>,
+        signal: Rc<RefCell<Signal>>,
+    ) -> JsValue {
+        let string = JsValue::new(JsString::new(ctx, "captured"));
+        vm.promise_from_future_rooted(ctx, &[string], async move {
+            SignalFuture(signal).await?;
+            Ok(string)
+        })
+        .unwrap_or_else(|_| panic!())
+    }
+
+    #[test]
+    fn test_rooted_future() {
+        Platform::initialize();
+        let jobs = Rc::new(RefCell::new(vec![]));
+        let queue = jobs.clone();
+        let mut vm = Platform::new_runtime(Options::default(), None)
+            .with_async_scheduler(Box::new(move |job| queue.borrow_mut().push(job)));
+        let mut ctx = vm.new_context();
+
+        let signal = Rc::new(RefCell::new(Signal::default()));
+        let promise = capture_string(vm, ctx, signal.clone());
+        assert_eq!(vm.poll_futures().ok(), Some(1));
+        ctx.heap().gc();
+
+        signal.borrow_mut().value = Some(0);
+        signal.borrow_mut().waker.take().unwrap().wake();
+        assert_eq!(vm.poll_futures().ok(), Some(0));
+        loop {
+            let job = {
+                let mut jobs = jobs.borrow_mut();
+                if jobs.is_empty() {
+                    break;
+                }
+                jobs.remove(0)
+            };
+            job(ctx);
+        }
+        let mut awaited = Box::pin(JsPromise::into_future(ctx, promise));
+        let waker = Waker::from(Arc::new(NoopWake));
+        match awaited.as_mut().poll(&mut Context::from_waker(&waker)) {
+            Poll::Ready(Ok(value)) => {
+                assert_eq!(value.to_string(ctx).ok().as_deref(), Some("captured"))
+            }
+            _ => panic!("promise is not resolved"),
+        }
+    }
+
+    #[test]
+    fn test_poll_futures_error() {
+        let (mut vm, mut ctx) = new_context();
+        vm.promise_from_future(ctx, std::future::ready(Ok(JsValue::new(1))))
+            .unwrap_or_else(|_| panic!());
+        // reactions of the promise can not be scheduled without an async scheduler.
+        let error = vm.poll_futures().err().unwrap();
+        let message = error.to_string(ctx).ok().unwrap();
+        assert!(message.contains("with_async_scheduler"), "{}", message);
+        assert_eq!(vm.poll_futures().ok(), Some(0));
+    }
+
+    struct NoopWake;
+
+    impl std::task::Wake for NoopWake {
+        fn wake(self: Arc<Self>) {}
+    }
+}
@@ -9,7 +9,7 @@ use super::{
     Context,
 };
 use super::{method_table::*, symbol_table::Internable};
-use crate::gc::cell::{GcPointer, Trace, Visitor};
+use crate::gc::cell::{Finalize, GcCell, GcPointer, Trace, Visitor};
 use wtf_rs::segmented_vec::SegmentedVec;
 
 use super::{
@@ -19,15 +19,39 @@ use super::{
     symbol_table::Symbol,
 };
 
+/// Location of own data property of the global object.
+///
+/// `OP_GET_GLOBAL` and `OP_PUT_GLOBAL` store the cell in their feedback slot and access global object storage
+/// directly while the cell stays valid. A cell is invalidated when the property is deleted or redefined in a
+/// way that changes its offset, kind or writability; the next access then creates a fresh cell.
+pub struct GlobalPropertyCell {
+    pub offset: u32,
+    pub writable: bool,
+    pub valid: bool,
+}
+
+/// Fallback installed with [VirtualMachine::set_global_resolver](crate::vm::VirtualMachine::set_global_resolver).
+/// Called with the name of a property the global object does not have as its own; a returned value is defined
+/// as a writable, configurable and non-enumerable property of the global object, so each name is resolved once.
+pub type GlobalResolver = dyn Fn(GcPointer<Context>, Symbol) -> Option<JsValue>;
+
+impl GcCell for GlobalPropertyCell {}
+impl Finalize<GlobalPropertyCell> for GlobalPropertyCell {}
+impl Trace for GlobalPropertyCell {}
+
 pub struct JsGlobal {
     pub(crate) sym_map: HashMap<Symbol, u32>,
     pub(crate) variables: SegmentedVec<StoredSlot>,
+    pub(crate) cells: HashMap<Symbol, GcPointer<GlobalPropertyCell>>,
     pub(crate) ctx: GcPointer<Context>,
+    /// Names the global resolver is called for, lookups of them while it runs do not call it again.
+    pub(crate) resolving: Vec<Symbol>,
 }
 
 impl Trace for JsGlobal {
     fn trace(&self, visitor: &mut Visitor) {
         self.variables.iter().for_each(|var| var.trace(visitor));
+        self.cells.values().for_each(|cell| cell.trace(visitor));
     }
 }
 
@@ -49,7 +73,9 @@ impl JsGlobal {
             *js_object.data::<JsGlobal>() = ManuallyDrop::new(Self {
                 sym_map: Default::default(),
                 variables: SegmentedVec::with_chunk_size(8),
+                cells: Default::default(),
                 ctx,
+                resolving: vec![],
             });
         }
         js_object
@@ -91,6 +117,39 @@ impl JsGlobal {
         &mut self.variables
     }
 
+    /// Returns property cell for `name` creating it if necessary. `None` is returned when `name` is not an own
+    /// data property of the global object stored in its slots.
+    pub fn property_cell(
+        obj: &mut GcPointer<JsObject>,
+        mut ctx: GcPointer<Context>,
+        name: Symbol,
+    ) -> Option<GcPointer<GlobalPropertyCell>> {
+        if let Some(cell) = obj.as_global().cells.get(&name) {
+            return Some(*cell);
+        }
+        let mut slot = Slot::new();
+        if !obj.get_own_property_slot(ctx, name, &mut slot) || !slot.is_load_cacheable() {
+            return None;
+        }
+        match slot.base() {
+            Some(base) if GcPointer::ptr_eq(base, obj) => (),
+            _ => return None,
+        }
+        let cell = ctx.heap().allocate(GlobalPropertyCell {
+            offset: slot.offset(),
+            writable: slot.attributes().is_writable(),
+            valid: true,
+        });
+        obj.as_global_mut().cells.insert(name, cell);
+        Some(cell)
+    }
+
+    fn invalidate_cell(obj: &mut GcPointer<JsObject>, name: Symbol) {
+        if let Some(mut cell) = obj.as_global_mut().cells.remove(&name) {
+            cell.valid = false;
+        }
+    }
+
     pub fn GetOwnPropertyNamesMethod(
         obj: &mut GcPointer<JsObject>,
         ctx: GcPointer<Context>,
@@ -114,7 +173,11 @@ impl JsGlobal {
             // all variables are configurable: false
             return Ok(false);
         }
-        JsObject::DeleteNonIndexedMethod(obj, ctx, name, throwable)
+        let deleted = JsObject::DeleteNonIndexedMethod(obj, ctx, name, throwable)?;
+        if deleted {
+            Self::invalidate_cell(obj, name);
+        }
+        Ok(deleted)
     }
     pub fn GetOwnNonIndexedPropertySlotMethod(
         obj: &mut GcPointer<JsObject>,
@@ -134,13 +197,38 @@ impl JsGlobal {
             return true;
         }
 
-        let res = JsObject::GetOwnNonIndexedPropertySlotMethod(obj, ctx, name, slot);
+        let mut res = JsObject::GetOwnNonIndexedPropertySlotMethod(obj, ctx, name, slot);
+        if !res && Self::resolve(obj, ctx, name) {
+            res = JsObject::GetOwnNonIndexedPropertySlotMethod(obj, ctx, name, slot);
+        }
         if !res {
             slot.make_uncacheable();
         }
         res
     }
 
+    /// Ask the global resolver for missing property `name` and define the value it returns.
+    fn resolve(obj: &mut GcPointer<JsObject>, ctx: GcPointer<Context>, name: Symbol) -> bool {
+        let resolver = match (name, &ctx.vm().global_resolver) {
+            (Symbol::Key(_), Some(resolver)) => resolver.clone(),
+            _ => return false,
+        };
+        if obj.as_global().resolving.contains(&name) {
+            return false;
+        }
+        obj.as_global_mut().resolving.push(name);
+        let value = resolver(ctx, name);
+        obj.as_global_mut()
+            .resolving
+            .retain(|resolving| *resolving != name);
+        match value {
+            Some(value) => obj
+                .define_own_property(ctx, name, &*DataDescriptor::new(value, W | C), false)
+                .unwrap_or(false),
+            None => false,
+        }
+    }
+
     pub fn DefineOwnNonIndexedPropertySlotMethod(
         mut obj: &mut GcPointer<JsObject>,
         ctx: GcPointer<Context>,
@@ -160,6 +248,96 @@ impl JsGlobal {
             }
             return Ok(returned);
         }
-        JsObject::DefineOwnNonIndexedPropertySlotMethod(obj, ctx, name, desc, slot, throwable)
+        let cell = obj.as_global().cells.get(&name).copied();
+        let result =
+            JsObject::DefineOwnNonIndexedPropertySlotMethod(obj, ctx, name, desc, slot, throwable);
+        if let Some(cell) = cell {
+            // plain stores are definitions too, keep the cell unless the property actually changed.
+            let mut current = Slot::new();
+            let unchanged =
+                JsObject::GetOwnNonIndexedPropertySlotMethod(obj, ctx, name, &mut current)
+                    && current.attributes().is_data()
+                    && current.offset() == cell.offset
+                    && current.attributes().is_writable() == cell.writable;
+            if !unchanged {
+                Self::invalidate_cell(obj, name);
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::options::Options;
+    use crate::vm::context::Context;
+    use crate::vm::symbol_table::Internable;
+    use crate::vm::tests::new_context;
+    use crate::vm::value::JsValue;
+    use crate::vm::VirtualMachine;
+    use crate::Platform;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[test]
+    fn test_global_property_cells() {
+        use crate::vm::{attributes::C, property_descriptor::DataDescriptor};
+        let (_vm, mut ctx) = new_context();
+
+        let result = ctx
+            .eval("g = 0; function read() { return g } for (var i = 0; i < 100; i++) { g = g + i; } read()")
+            .ok()
+            .unwrap();
+        assert_eq!(result.get_number(), 4950.0);
+
+        // cached cell must not survive deletion.
+        let mut global = ctx.global_object();
+        assert!(global.delete(ctx, "g".intern(), false).ok().unwrap());
+        assert!(ctx.eval("read()").is_err());
+
+        let result = ctx.eval("g = 1; read()").ok().unwrap();
+        assert_eq!(result.get_number(), 1.0);
+
+        // nor redefinition as read-only property.
+        let desc = DataDescriptor::new(JsValue::new(7), C);
+        global
+            .define_own_property(ctx, "g".intern(), &*desc, false)
+            .ok()
+            .unwrap();
+        let result = ctx.eval("g = 2; read()").ok().unwrap();
+        assert_eq!(result.get_number(), 7.0);
+    }
+
+    #[test]
+    fn test_global_resolver() {
+        Platform::initialize();
+        let mut vm = VirtualMachine::new(Options::default(), None);
+        let resolved = Rc::new(RefCell::new(vec![]));
+        let names = resolved.clone();
+        vm.set_global_resolver(move |ctx, name| {
+            let name = ctx.description(name);
+            names.borrow_mut().push(name.clone());
+            match name.as_str() {
+                "answer" => Some(JsValue::new(42)),
+                _ => None,
+            }
+        });
+        let mut ctx = Context::new(&mut vm);
+
+        let result = ctx
+            .eval(
+                "[answer, answer + 1, 'answer' in globalThis, typeof missing,
+                  Object.keys(globalThis).indexOf('answer')].join()",
+            )
+            .ok()
+            .unwrap();
+        assert_eq!(result.get_jsstring().as_str(), "42,43,true,undefined,-1");
+        assert!(ctx.eval("missing").is_err());
+        let answers = resolved
+            .borrow()
+            .iter()
+            .filter(|name| *name == "answer")
+            .count();
+        assert_eq!(answers, 1);
     }
 }
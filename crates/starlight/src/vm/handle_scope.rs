@@ -0,0 +1,154 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! Handle scopes for rooting many local values at once.
+//!
+//! A native opens one [HandleScope], creates as many [Handle]s as it needs and all of them are unrooted
+//! in bulk when scope is dropped. Handles are stored in a single vector in [VirtualMachine](super::VirtualMachine)
+//! which is traced precisely by GC, so values stored in handles survive collections even when they are not
+//! visible to the conservative stack scan (e.g. stored in boxed Rust collections).
+//!
+//! ```rust,ignore
+//! fn native(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, JsValue> {
+//!     let scope = HandleScope::new(ctx);
+//!     let values = (0..1000)
+//!         .map(|i| scope.handle(JsString::new(ctx, i.to_string())))
+//!         .collect::<Vec<_>>();
+//!     /* ... */
+//! }
+//! ```
+use super::{context::Context, value::JsValue, VirtualMachineRef};
+use crate::gc::cell::GcPointer;
+use std::marker::PhantomData;
+
+/// Scope that owns all handles created through it. Scopes must be dropped in reverse order of creation.
+pub struct HandleScope {
+    vm: VirtualMachineRef,
+    start: usize,
+}
+
+impl HandleScope {
+    pub fn new(ctx: GcPointer<Context>) -> Self {
+        let vm = ctx.vm();
+        Self {
+            start: vm.handles.len(),
+            vm,
+        }
+    }
+
+    /// Root `value` until this scope is dropped.
+    pub fn handle(&self, value: impl Into<JsValue>) -> Handle<'_> {
+        let mut vm = self.vm;
+        vm.handles.push(value.into());
+        Handle {
+            vm,
+            index: vm.handles.len() - 1,
+            marker: PhantomData,
+        }
+    }
+
+    /// Number of handles currently owned by this scope.
+    pub fn len(&self) -> usize {
+        self.vm.handles.len() - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Drop for HandleScope {
+    fn drop(&mut self) {
+        debug_assert!(
+            self.vm.handles.len() >= self.start,
+            "handle scopes dropped out of order"
+        );
+        self.vm.handles.truncate(self.start);
+    }
+}
+
+/// Scope that can move one value into its parent scope before being dropped. Useful for helpers that
+/// allocate many temporaries but return single value.
+pub struct EscapableHandleScope<'parent> {
+    scope: HandleScope,
+    escape_slot: usize,
+    marker: PhantomData<&'parent HandleScope>,
+}
+
+impl<'parent> EscapableHandleScope<'parent> {
+    pub fn new(parent: &'parent HandleScope) -> Self {
+        // reserve slot for escaped value in the parent before opening our own scope.
+        let mut vm = parent.vm;
+        vm.handles.push(JsValue::encode_undefined_value());
+        let escape_slot = vm.handles.len() - 1;
+        Self {
+            scope: HandleScope {
+                vm,
+                start: vm.handles.len(),
+            },
+            escape_slot,
+            marker: PhantomData,
+        }
+    }
+
+    pub fn handle(&self, value: impl Into<JsValue>) -> Handle<'_> {
+        self.scope.handle(value)
+    }
+
+    /// Drop this scope and return `value` rooted in the parent scope.
+    pub fn escape(self, value: impl Into<JsValue>) -> Handle<'parent> {
+        let mut vm = self.scope.vm;
+        vm.handles[self.escape_slot] = value.into();
+        Handle {
+            vm,
+            index: self.escape_slot,
+            marker: PhantomData,
+        }
+    }
+}
+
+/// Rooted value that lives as long as its scope.
+pub struct Handle<'scope> {
+    vm: VirtualMachineRef,
+    index: usize,
+    marker: PhantomData<&'scope HandleScope>,
+}
+
+impl<'scope> Handle<'scope> {
+    pub fn get(&self) -> JsValue {
+        self.vm.handles[self.index]
+    }
+
+    pub fn set(&mut self, value: impl Into<JsValue>) {
+        self.vm.handles[self.index] = value.into();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::vm::tests::new_context;
+
+    #[test]
+    fn test_handle_scope() {
+        use crate::vm::handle_scope::{EscapableHandleScope, HandleScope};
+        use crate::vm::string::JsString;
+        let (mut vm, mut ctx) = new_context();
+
+        let scope = HandleScope::new(ctx);
+        let escaped = {
+            let inner = EscapableHandleScope::new(&scope);
+            for i in 0..100 {
+                inner.handle(JsString::new(ctx, i.to_string()));
+            }
+            inner.escape(JsString::new(ctx, "escaped"))
+        };
+        // only the reserved escape slot is left in the outer scope
+        assert_eq!(scope.len(), 1);
+        vm.heap().gc();
+        assert_eq!(escaped.get().to_string(ctx).ok().unwrap(), "escaped");
+        drop(escaped);
+        drop(scope);
+        assert!(vm.handles.is_empty());
+    }
+}
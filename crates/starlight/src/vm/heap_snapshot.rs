@@ -0,0 +1,789 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! Heap snapshots in Chrome DevTools `.heapsnapshot` format.
+//!
+//! Snapshot is built by walking object graph reachable from context roots: global object, interpreter stack and
+//! persistent roots. Property edges are named using Structure property tables. Data that is reachable only
+//! through native `Class::trace` hooks is not visited.
+//!
+//! ```rust,ignore
+//! let snapshot = ctx.take_heap_snapshot();
+//! snapshot.write_to(&mut std::fs::File::create("app.heapsnapshot")?)?;
+//! ```
+use super::{
+    array_storage::ArrayStorage,
+    class::Class,
+    code_block::CodeBlock,
+    context::Context,
+    environment::{Environment, Variable},
+    function::FuncType,
+    object::{JsObject, ObjectTag},
+    property_descriptor::Accessor,
+    snapshot_check::NativeKind,
+    string::JsString,
+    structure::Structure,
+    symbol_table::{JsSymbol, Symbol},
+    value::JsValue,
+};
+use crate::gc::cell::{GcCell, GcPointer};
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::mem::size_of;
+
+/// Node types in the order expected by DevTools.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum NodeType {
+    Hidden,
+    Array,
+    String,
+    Object,
+    Code,
+    Closure,
+    RegExp,
+    Number,
+    Native,
+    Synthetic,
+    ConcatenatedString,
+    SlicedString,
+    Symbol,
+    BigInt,
+}
+
+/// Edge types in the order expected by DevTools.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum EdgeType {
+    Context,
+    Element,
+    Property,
+    Internal,
+    Hidden,
+    Shortcut,
+    Weak,
+}
+
+impl EdgeType {
+    /// Element and hidden edges are named by index, all other edges by string.
+    fn is_indexed(self) -> bool {
+        matches!(self, Self::Element | Self::Hidden)
+    }
+}
+
+struct Node {
+    ty: NodeType,
+    name: u32,
+    /// `GcCell::type_name` of the cell, empty for synthetic nodes.
+    type_name: &'static str,
+    self_size: usize,
+    /// Class name of objects, empty for other cells.
+    class_name: &'static str,
+    /// Native addresses a snapshot of the cell has to write as references.
+    natives: Vec<(NativeKind, usize)>,
+    edges: Vec<Edge>,
+}
+
+struct Edge {
+    ty: EdgeType,
+    name_or_index: u32,
+    to: usize,
+}
+
+/// Snapshot of reachable heap graph. Node `0` is synthetic root.
+pub struct HeapSnapshot {
+    nodes: Vec<Node>,
+    strings: Vec<String>,
+    string_ids: HashMap<String, u32>,
+    retained: Vec<usize>,
+    /// Nodes and addresses of reachable cells without type information, they are not visited.
+    invalid: Vec<(usize, usize)>,
+}
+
+impl HeapSnapshot {
+    fn new() -> Self {
+        Self {
+            nodes: vec![],
+            strings: vec![],
+            string_ids: HashMap::new(),
+            retained: vec![],
+            invalid: vec![],
+        }
+    }
+
+    fn intern(&mut self, s: &str) -> u32 {
+        if let Some(id) = self.string_ids.get(s) {
+            return *id;
+        }
+        let id = self.strings.len() as u32;
+        self.strings.push(s.to_owned());
+        self.string_ids.insert(s.to_owned(), id);
+        id
+    }
+
+    fn add_node(&mut self, ty: NodeType, name: &str, self_size: usize) -> usize {
+        let name = self.intern(name);
+        self.nodes.push(Node {
+            ty,
+            name,
+            type_name: "",
+            self_size,
+            class_name: "",
+            natives: vec![],
+            edges: vec![],
+        });
+        self.nodes.len() - 1
+    }
+
+    fn add_named_edge(&mut self, from: usize, ty: EdgeType, name: &str, to: usize) {
+        debug_assert!(!ty.is_indexed());
+        let name_or_index = self.intern(name);
+        self.nodes[from].edges.push(Edge {
+            ty,
+            name_or_index,
+            to,
+        });
+    }
+
+    fn add_indexed_edge(&mut self, from: usize, ty: EdgeType, index: u32, to: usize) {
+        debug_assert!(ty.is_indexed());
+        self.nodes[from].edges.push(Edge {
+            ty,
+            name_or_index: index,
+            to,
+        });
+    }
+
+    /// Number of nodes including synthetic ones.
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Number of edges in snapshot.
+    pub fn edge_count(&self) -> usize {
+        self.nodes.iter().map(|node| node.edges.len()).sum()
+    }
+
+    pub fn node_name(&self, node: usize) -> &str {
+        &self.strings[self.nodes[node].name as usize]
+    }
+
+    pub fn node_type(&self, node: usize) -> NodeType {
+        self.nodes[node].ty
+    }
+
+    /// Size of the node itself including out-of-line buffers owned by the cell.
+    pub fn self_size(&self, node: usize) -> usize {
+        self.nodes[node].self_size
+    }
+
+    /// Size of memory that would be freed if `node` became unreachable.
+    pub(crate) fn type_name(&self, node: usize) -> &'static str {
+        self.nodes[node].type_name
+    }
+
+    pub fn retained_size(&self, node: usize) -> usize {
+        self.retained[node]
+    }
+
+    pub(crate) fn class_name(&self, node: usize) -> &'static str {
+        self.nodes[node].class_name
+    }
+
+    pub(crate) fn natives(&self, node: usize) -> &[(NativeKind, usize)] {
+        &self.nodes[node].natives
+    }
+
+    /// Find the shortest path from the root to every node. Entries are `(parent, edge index)`, `None` for the
+    /// root itself.
+    pub(crate) fn shortest_paths(&self) -> Vec<Option<(usize, usize)>> {
+        let mut parents = vec![None; self.nodes.len()];
+        let mut visited = vec![false; self.nodes.len()];
+        let mut queue = std::collections::VecDeque::new();
+        visited[0] = true;
+        queue.push_back(0);
+        while let Some(node) = queue.pop_front() {
+            for (i, edge) in self.nodes[node].edges.iter().enumerate() {
+                if !visited[edge.to] {
+                    visited[edge.to] = true;
+                    parents[edge.to] = Some((node, i));
+                    queue.push_back(edge.to);
+                }
+            }
+        }
+        parents
+    }
+
+    /// Format path found by [HeapSnapshot::shortest_paths] like a property access, e.g. `global.foo[0].(code)`.
+    /// Internal edges are parenthesized.
+    pub(crate) fn format_path(
+        &self,
+        parents: &[Option<(usize, usize)>],
+        mut node: usize,
+    ) -> String {
+        let mut parts = vec![];
+        while let Some((parent, edge)) = parents[node] {
+            let edge = &self.nodes[parent].edges[edge];
+            if edge.ty.is_indexed() {
+                parts.push(format!("[{}]", edge.name_or_index));
+            } else {
+                let name = &self.strings[edge.name_or_index as usize];
+                parts.push(if parent == 0 {
+                    name.clone()
+                } else if edge.ty == EdgeType::Property {
+                    format!(".{}", name)
+                } else {
+                    format!(".({})", name)
+                });
+            }
+            node = parent;
+        }
+        parts.reverse();
+        parts.concat()
+    }
+
+    /// Computes retained sizes using dominator tree built with Cooper-Harvey-Kennedy algorithm.
+    fn compute_retained_sizes(&mut self) {
+        let count = self.nodes.len();
+        let mut post_order = Vec::with_capacity(count);
+        let mut post_index = vec![usize::MAX; count];
+        let mut visited = vec![false; count];
+        let mut stack = vec![(0usize, 0usize)];
+        visited[0] = true;
+        while let Some((node, edge)) = stack.pop() {
+            if edge < self.nodes[node].edges.len() {
+                stack.push((node, edge + 1));
+                let to = self.nodes[node].edges[edge].to;
+                if !visited[to] {
+                    visited[to] = true;
+                    stack.push((to, 0));
+                }
+            } else {
+                post_index[node] = post_order.len();
+                post_order.push(node);
+            }
+        }
+
+        let mut predecessors = vec![vec![]; count];
+        for (from, node) in self.nodes.iter().enumerate() {
+            for edge in node.edges.iter() {
+                if edge.ty != EdgeType::Weak {
+                    predecessors[edge.to].push(from);
+                }
+            }
+        }
+
+        let mut idom = vec![usize::MAX; count];
+        idom[0] = 0;
+        let intersect = |idom: &[usize], mut a: usize, mut b: usize| {
+            while a != b {
+                while post_index[a] < post_index[b] {
+                    a = idom[a];
+                }
+                while post_index[b] < post_index[a] {
+                    b = idom[b];
+                }
+            }
+            a
+        };
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &node in post_order.iter().rev().skip(1) {
+                let mut new_idom = usize::MAX;
+                for &pred in predecessors[node].iter() {
+                    if idom[pred] == usize::MAX {
+                        continue;
+                    }
+                    new_idom = if new_idom == usize::MAX {
+                        pred
+                    } else {
+                        intersect(&idom, pred, new_idom)
+                    };
+                }
+                if new_idom != usize::MAX && idom[node] != new_idom {
+                    idom[node] = new_idom;
+                    changed = true;
+                }
+            }
+        }
+
+        self.retained = self.nodes.iter().map(|node| node.self_size).collect();
+        // dominator is always an ancestor in DFS tree so it is visited after all dominated nodes.
+        for &node in post_order.iter() {
+            if node != 0 && idom[node] != usize::MAX {
+                self.retained[idom[node]] += self.retained[node];
+            }
+        }
+    }
+
+    /// Write human readable listing of every node with its size and outgoing edges. Output is stable for the same
+    /// heap so dumps of two runs can be compared with `diff`.
+    pub fn dump(&self, out: &mut dyn Write) -> io::Result<()> {
+        for (i, node) in self.nodes.iter().enumerate() {
+            let type_name = if node.type_name.is_empty() {
+                "(synthetic)"
+            } else {
+                node.type_name
+            };
+            write!(out, "@{} {} ", i, type_name)?;
+            write_json_string(out, &self.strings[node.name as usize])?;
+            write!(out, " self={}", node.self_size)?;
+            if let Some(retained) = self.retained.get(i) {
+                write!(out, " retained={}", retained)?;
+            }
+            writeln!(out)?;
+            for edge in node.edges.iter() {
+                let ty = format!("{:?}", edge.ty).to_lowercase();
+                if edge.ty.is_indexed() {
+                    writeln!(out, "  {} [{}] -> @{}", ty, edge.name_or_index, edge.to)?;
+                } else {
+                    write!(out, "  {} ", ty)?;
+                    write_json_string(out, &self.strings[edge.name_or_index as usize])?;
+                    writeln!(out, " -> @{}", edge.to)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Write snapshot as JSON loadable by Chrome DevTools "Memory" tab.
+    pub fn write_to(&self, out: &mut dyn Write) -> io::Result<()> {
+        const NODE_FIELD_COUNT: usize = 6;
+        write!(
+            out,
+            "{{\"snapshot\":{{\"meta\":{{\
+             \"node_fields\":[\"type\",\"name\",\"id\",\"self_size\",\"edge_count\",\"trace_node_id\"],\
+             \"node_types\":[[\"hidden\",\"array\",\"string\",\"object\",\"code\",\"closure\",\"regexp\",\
+             \"number\",\"native\",\"synthetic\",\"concatenated string\",\"sliced string\",\"symbol\",\
+             \"bigint\"],\"string\",\"number\",\"number\",\"number\",\"number\"],\
+             \"edge_fields\":[\"type\",\"name_or_index\",\"to_node\"],\
+             \"edge_types\":[[\"context\",\"element\",\"property\",\"internal\",\"hidden\",\"shortcut\",\
+             \"weak\"],\"string_or_number\",\"node\"],\
+             \"trace_function_info_fields\":[],\"trace_node_fields\":[],\"sample_fields\":[],\
+             \"location_fields\":[]}},\"node_count\":{},\"edge_count\":{},\"trace_function_count\":0}},\n",
+            self.node_count(),
+            self.edge_count()
+        )?;
+        write!(out, "\"nodes\":[")?;
+        for (i, node) in self.nodes.iter().enumerate() {
+            if i != 0 {
+                write!(out, ",\n")?;
+            }
+            write!(
+                out,
+                "{},{},{},{},{},0",
+                node.ty as u8,
+                node.name,
+                i * 2 + 1,
+                node.self_size,
+                node.edges.len()
+            )?;
+        }
+        write!(out, "],\n\"edges\":[")?;
+        let mut first = true;
+        for node in self.nodes.iter() {
+            for edge in node.edges.iter() {
+                if !first {
+                    write!(out, ",\n")?;
+                }
+                first = false;
+                write!(
+                    out,
+                    "{},{},{}",
+                    edge.ty as u8,
+                    edge.name_or_index,
+                    edge.to * NODE_FIELD_COUNT
+                )?;
+            }
+        }
+        write!(
+            out,
+            "],\n\"trace_function_infos\":[],\"trace_tree\":[],\"samples\":[],\"locations\":[],\n\"strings\":["
+        )?;
+        for (i, string) in self.strings.iter().enumerate() {
+            if i != 0 {
+                write!(out, ",\n")?;
+            }
+            write_json_string(out, string)?;
+        }
+        write!(out, "]}}")
+    }
+}
+
+pub(crate) fn write_json_string(out: &mut dyn Write, s: &str) -> io::Result<()> {
+    write!(out, "\"")?;
+    for ch in s.chars() {
+        match ch {
+            '"' => write!(out, "\\\"")?,
+            '\\' => write!(out, "\\\\")?,
+            '\n' => write!(out, "\\n")?,
+            '\r' => write!(out, "\\r")?,
+            '\t' => write!(out, "\\t")?,
+            ch if (ch as u32) < 0x20 => write!(out, "\\u{:04x}", ch as u32)?,
+            ch => write!(out, "{}", ch)?,
+        }
+    }
+    write!(out, "\"")
+}
+
+/// Longest string node name, longer strings are truncated.
+const MAX_STRING_NAME: usize = 1024;
+
+struct SnapshotBuilder {
+    ctx: GcPointer<Context>,
+    snapshot: HeapSnapshot,
+    ids: HashMap<usize, usize>,
+    worklist: Vec<(usize, GcPointer<dyn GcCell>)>,
+}
+
+impl SnapshotBuilder {
+    fn node_for(&mut self, cell: GcPointer<dyn GcCell>) -> usize {
+        if let Some(node) = self.ids.get(&cell.address()) {
+            return *node;
+        }
+        if !cell.has_type_info() {
+            let node = self
+                .snapshot
+                .add_node(NodeType::Hidden, "(invalid cell)", 0);
+            self.snapshot.invalid.push((node, cell.address()));
+            self.ids.insert(cell.address(), node);
+            return node;
+        }
+        let (ty, name) = self.describe(cell);
+        let size = cell.allocation_size() + external_size(cell);
+        let node = self.snapshot.add_node(ty, &name, size);
+        self.snapshot.nodes[node].type_name = cell.get_dyn().type_name();
+        if let Some(object) = cell.downcast::<JsObject>() {
+            self.snapshot.nodes[node].class_name = object.class.name;
+            self.snapshot.nodes[node].natives = natives_of(object);
+        }
+        self.ids.insert(cell.address(), node);
+        self.worklist.push((node, cell));
+        node
+    }
+
+    fn value_edge(&mut self, from: usize, ty: EdgeType, name: &str, value: JsValue) {
+        if value.is_object() {
+            let to = self.node_for(value.get_object());
+            self.snapshot.add_named_edge(from, ty, name, to);
+        }
+    }
+
+    fn indexed_value_edge(&mut self, from: usize, ty: EdgeType, index: u32, value: JsValue) {
+        if value.is_object() {
+            let to = self.node_for(value.get_object());
+            self.snapshot.add_indexed_edge(from, ty, index, to);
+        }
+    }
+
+    fn cell_edge<T: GcCell + ?Sized>(
+        &mut self,
+        from: usize,
+        ty: EdgeType,
+        name: &str,
+        cell: GcPointer<T>,
+    ) {
+        let to = self.node_for(cell.as_dyn());
+        self.snapshot.add_named_edge(from, ty, name, to);
+    }
+
+    fn describe(&self, cell: GcPointer<dyn GcCell>) -> (NodeType, String) {
+        if let Some(object) = cell.downcast::<JsObject>() {
+            return match object.tag() {
+                ObjectTag::Function => (NodeType::Closure, self.function_name(object)),
+                ObjectTag::Regex => (NodeType::RegExp, object.class.name.to_owned()),
+                ObjectTag::Array => (NodeType::Array, object.class.name.to_owned()),
+                _ => (NodeType::Object, object.class.name.to_owned()),
+            };
+        }
+        if let Some(string) = cell.downcast::<JsString>() {
+            return (
+                NodeType::String,
+                string.as_str().chars().take(MAX_STRING_NAME).collect(),
+            );
+        }
+        if let Some(symbol) = cell.downcast::<JsSymbol>() {
+            return (NodeType::Symbol, self.ctx.description(symbol.sym));
+        }
+        if let Some(code) = cell.downcast::<CodeBlock>() {
+            return (
+                NodeType::Code,
+                format!("(code for {})", self.ctx.description(code.name)),
+            );
+        }
+        if cell.is::<Structure>() {
+            return (NodeType::Hidden, "system / Structure".to_owned());
+        }
+        if cell.is::<Environment>() {
+            return (NodeType::Hidden, "system / Context".to_owned());
+        }
+        if cell.is::<Accessor>() {
+            return (NodeType::Hidden, "system / AccessorPair".to_owned());
+        }
+        if cell.is::<ArrayStorage>() {
+            return (NodeType::Array, "(object elements)".to_owned());
+        }
+        (NodeType::Hidden, cell.get_dyn().type_name().to_owned())
+    }
+
+    fn function_name(&self, object: GcPointer<JsObject>) -> String {
+        match object.as_function().ty {
+            FuncType::User(ref func) => self.ctx.description(func.code.name),
+            FuncType::Generator(ref func) => self.function_name(func.function),
+            FuncType::Bound(ref func) => format!("bound {}", self.function_name(func.target)),
+            FuncType::Native(_) => "(native function)".to_owned(),
+            FuncType::Closure(_) => "(native closure)".to_owned(),
+        }
+    }
+
+    fn add_roots(&mut self) {
+        let root = self.snapshot.add_node(NodeType::Synthetic, "", 0);
+        let global = self.ctx.global_object();
+        self.cell_edge(root, EdgeType::Shortcut, "global", global);
+
+        let stack_roots = self
+            .snapshot
+            .add_node(NodeType::Synthetic, "(Stack roots)", 0);
+        self.snapshot
+            .add_named_edge(root, EdgeType::Internal, "(Stack roots)", stack_roots);
+        let mut index = 0;
+        let mut frame = self.ctx.stack.current;
+        unsafe {
+            while !frame.is_null() {
+                let f = &*frame;
+                self.indexed_value_edge(stack_roots, EdgeType::Element, index, f.callee);
+                self.indexed_value_edge(stack_roots, EdgeType::Element, index + 1, f.this);
+                let env = self.node_for(f.env.as_dyn());
+                self.snapshot
+                    .add_indexed_edge(stack_roots, EdgeType::Element, index + 2, env);
+                index += 3;
+                let mut scan = f.limit;
+                while scan < f.sp {
+                    self.indexed_value_edge(stack_roots, EdgeType::Element, index, scan.read());
+                    index += 1;
+                    scan = scan.add(1);
+                }
+                frame = f.prev;
+            }
+        }
+
+        let persistent = self
+            .snapshot
+            .add_node(NodeType::Synthetic, "(Persistent roots)", 0);
+        self.snapshot.add_named_edge(
+            root,
+            EdgeType::Internal,
+            "(Persistent roots)",
+            persistent,
+        );
+        let roots = self
+            .ctx
+            .vm
+            .persistent_roots
+            .borrow()
+            .values()
+            .copied()
+            .collect::<Vec<_>>();
+        for (i, value) in roots.into_iter().enumerate() {
+            self.indexed_value_edge(persistent, EdgeType::Element, i as u32, value);
+        }
+    }
+
+    fn visit(&mut self, node: usize, cell: GcPointer<dyn GcCell>) {
+        if let Some(object) = cell.downcast::<JsObject>() {
+            self.visit_object(node, object);
+        } else if let Some(env) = cell.downcast::<Environment>() {
+            if let Some(parent) = env.parent {
+                self.cell_edge(node, EdgeType::Internal, "previous", parent);
+            }
+            if let Some(code) = env.code {
+                self.cell_edge(node, EdgeType::Internal, "code", code);
+            }
+            for (i, var) in env.as_slice().iter().enumerate() {
+                let name = match env.name(i) {
+                    Some(name) => self.ctx.description(name),
+                    None => i.to_string(),
+                };
+                self.value_edge(node, EdgeType::Context, &name, var.value);
+            }
+        } else if let Some(accessor) = cell.downcast::<Accessor>() {
+            self.value_edge(node, EdgeType::Internal, "getter", accessor.getter());
+            self.value_edge(node, EdgeType::Internal, "setter", accessor.setter());
+        } else if let Some(code) = cell.downcast::<CodeBlock>() {
+            for (i, literal) in code.literals.iter().enumerate() {
+                self.indexed_value_edge(node, EdgeType::Hidden, i as u32, *literal);
+            }
+            for nested in code.codes.iter() {
+                self.cell_edge(node, EdgeType::Internal, "function", *nested);
+            }
+        } else if let Some(structure) = cell.downcast::<Structure>() {
+            if let Some(proto) = structure.prototype {
+                self.cell_edge(node, EdgeType::Internal, "prototype", proto);
+            }
+            if let Some(previous) = structure.previous {
+                self.cell_edge(node, EdgeType::Weak, "back_pointer", previous);
+            }
+        }
+    }
+
+    fn visit_object(&mut self, node: usize, mut object: GcPointer<JsObject>) {
+        let ctx = self.ctx;
+        let mut structure = object.structure();
+        self.cell_edge(node, EdgeType::Internal, "map", structure);
+        if let Some(proto) = structure.prototype() {
+            let proto = *proto;
+            self.cell_edge(node, EdgeType::Property, "__proto__", proto);
+        }
+        self.cell_edge(node, EdgeType::Internal, "properties", object.slots);
+
+        let mut properties = vec![];
+        structure.get_own_property_names(ctx, true, |name, offset| {
+            properties.push((name, offset));
+        });
+        for (name, offset) in properties {
+            let value = *object.direct(offset as _);
+            match name {
+                Symbol::Index(index) => {
+                    self.indexed_value_edge(node, EdgeType::Element, index, value)
+                }
+                _ => self.value_edge(node, EdgeType::Property, &ctx.description(name), value),
+            }
+        }
+
+        let vector = object.indexed.vector;
+        self.cell_edge(node, EdgeType::Internal, "elements", vector);
+        for i in 0..vector.size() {
+            let value = *vector.at(i);
+            if !value.is_empty() {
+                self.indexed_value_edge(node, EdgeType::Element, i, value);
+            }
+        }
+        if let Some(map) = object.indexed.map {
+            self.cell_edge(node, EdgeType::Internal, "sparse_elements", map);
+            for (index, slot) in map.iter() {
+                self.indexed_value_edge(node, EdgeType::Element, *index, slot.value());
+            }
+        }
+
+        match object.tag() {
+            ObjectTag::Function => match object.as_function().ty {
+                FuncType::User(ref func) => {
+                    let (code, scope) = (func.code, func.scope);
+                    self.cell_edge(node, EdgeType::Internal, "code", code);
+                    self.cell_edge(node, EdgeType::Internal, "context", scope);
+                }
+                FuncType::Bound(ref func) => {
+                    let (target, this, args) = (func.target, func.this, func.args);
+                    self.cell_edge(node, EdgeType::Internal, "bound_function", target);
+                    self.value_edge(node, EdgeType::Internal, "bound_this", this);
+                    for i in 0..args.size() {
+                        let arg = *args.at(i);
+                        self.value_edge(node, EdgeType::Internal, "bound_argument", arg);
+                    }
+                }
+                FuncType::Generator(ref func) => {
+                    let function = func.function;
+                    self.cell_edge(node, EdgeType::Internal, "function", function);
+                }
+                _ => (),
+            },
+            ObjectTag::String => {
+                let value = object.as_string_object().value;
+                self.cell_edge(node, EdgeType::Internal, "value", value);
+            }
+            _ => (),
+        }
+    }
+
+    fn build(mut self) -> HeapSnapshot {
+        self.add_roots();
+        while let Some((node, cell)) = self.worklist.pop() {
+            self.visit(node, cell);
+        }
+        self.snapshot
+    }
+}
+
+/// Class and native function of `object`. Closures have no static address and are reported with the address of
+/// their boxed state.
+fn natives_of(object: GcPointer<JsObject>) -> Vec<(NativeKind, usize)> {
+    let mut natives = vec![(NativeKind::Class, object.class as *const Class as usize)];
+    if object.tag() == ObjectTag::Function {
+        match object.as_function().ty {
+            FuncType::Native(ref func) => natives.push((NativeKind::Function, func.func as usize)),
+            FuncType::Closure(ref func) => natives.push((
+                NativeKind::Closure,
+                &*func.func as *const _ as *const u8 as usize,
+            )),
+            _ => (),
+        }
+    }
+    natives
+}
+
+/// Memory owned by the cell but allocated outside of GC heap.
+fn external_size(cell: GcPointer<dyn GcCell>) -> usize {
+    if let Some(string) = cell.downcast::<JsString>() {
+        return string.owned_size();
+    } else if let Some(env) = cell.downcast::<Environment>() {
+        return env.values_count as usize * size_of::<Variable>();
+    }
+    0
+}
+
+impl GcPointer<Context> {
+    /// Take snapshot of heap graph reachable from this context.
+    pub fn take_heap_snapshot(self) -> HeapSnapshot {
+        let mut snapshot = self.walk_heap_graph();
+        snapshot.compute_retained_sizes();
+        snapshot
+    }
+
+    /// Build snapshot graph without computing retained sizes.
+    pub(crate) fn walk_heap_graph(mut self) -> HeapSnapshot {
+        let _defer = self.heap().defer();
+        SnapshotBuilder {
+            ctx: self,
+            snapshot: HeapSnapshot::new(),
+            ids: HashMap::new(),
+            worklist: vec![],
+        }
+        .build()
+    }
+
+    /// Check that every cell reachable from this context still has type information.
+    ///
+    /// # Panics
+    /// Panics with paths of the broken cells, these are usually freed cells that are still referenced from
+    /// memory the GC does not trace.
+    pub fn verify_heap(self) {
+        let snapshot = self.walk_heap_graph();
+        if snapshot.invalid.is_empty() {
+            return;
+        }
+        let parents = snapshot.shortest_paths();
+        let cells = snapshot
+            .invalid
+            .iter()
+            .map(|&(node, address)| {
+                format!(
+                    "  {:#x} at {}",
+                    address,
+                    snapshot.format_path(&parents, node)
+                )
+            })
+            .collect::<Vec<_>>();
+        panic!(
+            "heap verification failed, cells without type information:\n{}",
+            cells.join("\n")
+        );
+    }
+
+    /// Take heap snapshot and write it to `path`. Resulting file can be loaded in Chrome DevTools.
+    pub fn write_heap_snapshot(self, path: impl AsRef<std::path::Path>) -> io::Result<()> {
+        let snapshot = self.take_heap_snapshot();
+        let mut file = io::BufWriter::new(std::fs::File::create(path)?);
+        snapshot.write_to(&mut file)?;
+        file.flush()
+    }
+}
@@ -0,0 +1,446 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+//! Readable formatting of JS values in the spirit of Node's `util.inspect`, used by `console.log`.
+//!
+//! Inspection never runs user code: accessors are printed as `[Getter]`/`[Setter]` and names of
+//! functions, constructors and errors are read only from data properties.
+use crate::prelude::*;
+use crate::vm::{context::Context, value::number_to_string};
+
+/// Options of [inspect].
+#[derive(Clone, Debug)]
+pub struct InspectOptions {
+    /// Objects nested deeper than this are printed as `[Object]` or `[Array]`.
+    pub depth: u32,
+    /// Highlight values with ANSI color codes.
+    pub colors: bool,
+    /// Number of array elements printed before the rest is summarized as `... n more items`.
+    pub max_array_length: usize,
+    /// Objects whose single line form is longer than this are printed one property per line.
+    pub break_length: usize,
+}
+
+impl Default for InspectOptions {
+    fn default() -> Self {
+        Self {
+            depth: 2,
+            colors: false,
+            max_array_length: 100,
+            break_length: 80,
+        }
+    }
+}
+
+const GREEN: u8 = 32;
+const YELLOW: u8 = 33;
+const MAGENTA: u8 = 35;
+const CYAN: u8 = 36;
+const GREY: u8 = 90;
+const RED: u8 = 31;
+const BOLD: u8 = 1;
+
+/// Format `value` for humans.
+pub fn inspect(ctx: GcPointer<Context>, value: JsValue, options: &InspectOptions) -> String {
+    let mut inspector = Inspector {
+        ctx,
+        options,
+        seen: vec![],
+    };
+    inspector.value(value, 0)
+}
+
+struct Inspector<'a> {
+    ctx: GcPointer<Context>,
+    options: &'a InspectOptions,
+    /// Objects being printed, used to detect cycles.
+    seen: Vec<GcPointer<JsObject>>,
+}
+
+impl Inspector<'_> {
+    fn stylize(&self, string: String, color: u8) -> String {
+        if self.options.colors {
+            format!("\x1b[{}m{}\x1b[0m", color, string)
+        } else {
+            string
+        }
+    }
+
+    fn value(&mut self, value: JsValue, depth: u32) -> String {
+        if value.is_number() {
+            let number = value.get_number();
+            let string = if number == 0.0 && number.is_sign_negative() {
+                "-0".to_owned()
+            } else {
+                number_to_string(number)
+            };
+            self.stylize(string, YELLOW)
+        } else if value.is_bool() {
+            self.stylize(value.get_bool().to_string(), YELLOW)
+        } else if value.is_undefined() {
+            self.stylize("undefined".to_owned(), GREY)
+        } else if value.is_null() {
+            self.stylize("null".to_owned(), BOLD)
+        } else if value.is_jsstring() {
+            self.stylize(quote(value.get_string().as_str()), GREEN)
+        } else if value.is_symbol() {
+            let symbol = value.get_object().downcast::<JsSymbol>().unwrap().symbol();
+            let string = format!("Symbol({})", self.ctx.description(symbol));
+            self.stylize(string, GREEN)
+        } else if value.is_jsobject() {
+            self.object(value.get_jsobject(), depth)
+        } else {
+            self.stylize("[internal]".to_owned(), GREY)
+        }
+    }
+
+    fn object(&mut self, mut object: GcPointer<JsObject>, depth: u32) -> String {
+        if self
+            .seen
+            .iter()
+            .any(|seen| GcPointer::ptr_eq(seen, &object))
+        {
+            return self.stylize("[Circular]".to_owned(), CYAN);
+        }
+        if object.is_callable() {
+            let name = match data_property(self.ctx, object, "name".intern()) {
+                Some(name) if name.is_jsstring() && !name.get_string().as_str().is_empty() => {
+                    format!("[Function: {}]", name.get_string().as_str())
+                }
+                _ => "[Function (anonymous)]".to_owned(),
+            };
+            return self.stylize(name, CYAN);
+        }
+        if is_error(object) {
+            let header = self.error_header(object);
+            if depth != 0 {
+                return self.stylize(format!("[{}]", header), RED);
+            }
+            return match data_property(self.ctx, object, "stack".intern()) {
+                Some(stack) if stack.is_jsstring() && !stack.get_string().as_str().is_empty() => {
+                    format!("{}\n{}", header, stack.get_string().as_str().trim_end())
+                }
+                _ => header,
+            };
+        }
+        if matches!(object.class.name, "Date" | "RegExp") {
+            // both print themselves without observable side effects unless their prototype was patched.
+            let color = if object.class.name == "Date" {
+                MAGENTA
+            } else {
+                RED
+            };
+            return match JsValue::new(object).to_string(self.ctx) {
+                Ok(string) => self.stylize(string, color),
+                Err(_) => self.stylize(format!("[{}]", object.class.name), color),
+            };
+        }
+
+        let is_array = object.tag() == ObjectTag::Array;
+        let prefix = self.prefix(object, is_array);
+        if depth > self.options.depth {
+            let name = if is_array {
+                "Array".to_owned()
+            } else {
+                prefix.clone().unwrap_or_else(|| "Object".to_owned())
+            };
+            return self.stylize(format!("[{}]", name), CYAN);
+        }
+
+        self.seen.push(object);
+        let mut entries = vec![];
+        let mut names = vec![];
+        object.get_own_property_names(
+            self.ctx,
+            &mut |name, _| names.push(name),
+            EnumerationMode::Default,
+        );
+        if is_array {
+            self.array_elements(object, depth, &mut entries);
+            names.retain(|name| !matches!(name, Symbol::Index(_)));
+        }
+        for name in names {
+            let entry = self.property(object, name, depth);
+            entries.push(entry);
+        }
+        self.seen.pop();
+
+        let (open, close) = if is_array { ("[", "]") } else { ("{", "}") };
+        let body = self.join(&entries, open, close, depth);
+        match prefix {
+            Some(prefix) => format!("{} {}", prefix, body),
+            None => body,
+        }
+    }
+
+    /// Name printed before objects that are not plain objects or arrays, e.g. `Foo { x: 1 }`.
+    fn prefix(&self, object: GcPointer<JsObject>, is_array: bool) -> Option<String> {
+        let proto = match object.prototype() {
+            Some(proto) => *proto,
+            None => return Some("[Object: null prototype]".to_owned()),
+        };
+        let constructor = data_property(self.ctx, proto, Symbol::CONSTRUCTOR)?;
+        if !constructor.is_jsobject() {
+            return None;
+        }
+        let name = data_property(self.ctx, constructor.get_jsobject(), "name".intern())?;
+        if !name.is_jsstring() {
+            return None;
+        }
+        let name = name.get_string().as_str().to_owned();
+        match name.as_str() {
+            "" | "Object" => None,
+            "Array" if is_array => None,
+            _ => Some(name),
+        }
+    }
+
+    fn error_header(&self, object: GcPointer<JsObject>) -> String {
+        let string = |name: &str| match data_property(self.ctx, object, name.intern()) {
+            Some(value) if value.is_jsstring() => value.get_string().as_str().to_owned(),
+            _ => String::new(),
+        };
+        let name = string("name");
+        let message = string("message");
+        match (name.is_empty(), message.is_empty()) {
+            (true, _) => format!("Error: {}", message),
+            (false, true) => name,
+            (false, false) => format!("{}: {}", name, message),
+        }
+    }
+
+    fn array_elements(
+        &mut self,
+        object: GcPointer<JsObject>,
+        depth: u32,
+        entries: &mut Vec<String>,
+    ) {
+        let length = match data_property(self.ctx, object, Symbol::LENGTH) {
+            Some(length) if length.is_number() => length.get_number() as u32,
+            _ => 0,
+        };
+        let shown = length.min(self.options.max_array_length as u32);
+        let mut holes = 0;
+        for index in 0..shown {
+            let mut element = object;
+            match element.get_own_property(self.ctx, Symbol::Index(index)) {
+                Some(desc) => {
+                    if holes != 0 {
+                        entries.push(self.holes(holes));
+                        holes = 0;
+                    }
+                    let entry = self.descriptor(desc, depth);
+                    entries.push(entry);
+                }
+                None => holes += 1,
+            }
+        }
+        if holes != 0 {
+            entries.push(self.holes(holes));
+        }
+        if length > shown {
+            let more = length - shown;
+            entries.push(format!(
+                "... {} more item{}",
+                more,
+                if more == 1 { "" } else { "s" }
+            ));
+        }
+    }
+
+    fn holes(&self, count: u32) -> String {
+        let string = format!(
+            "<{} empty item{}>",
+            count,
+            if count == 1 { "" } else { "s" }
+        );
+        self.stylize(string, GREY)
+    }
+
+    fn property(&mut self, mut object: GcPointer<JsObject>, name: Symbol, depth: u32) -> String {
+        let key = match name {
+            Symbol::Index(index) => format!("'{}'", index),
+            Symbol::Private(_) => format!("[{}]", self.ctx.description(name)),
+            Symbol::Key(_) => {
+                let key = self.ctx.description(name);
+                if is_identifier(&key) {
+                    key
+                } else {
+                    self.stylize(quote(&key), GREEN)
+                }
+            }
+        };
+        let value = match object.get_own_property(self.ctx, name) {
+            Some(desc) => self.descriptor(desc, depth),
+            None => self.stylize("undefined".to_owned(), GREY),
+        };
+        format!("{}: {}", key, value)
+    }
+
+    fn descriptor(&mut self, desc: PropertyDescriptor, depth: u32) -> String {
+        if desc.is_accessor() {
+            let string = match (desc.getter().is_callable(), desc.setter().is_callable()) {
+                (true, true) => "[Getter/Setter]",
+                (true, false) => "[Getter]",
+                (false, true) => "[Setter]",
+                (false, false) => "undefined",
+            };
+            return self.stylize(string.to_owned(), CYAN);
+        }
+        self.value(desc.value(), depth + 1)
+    }
+
+    fn join(&self, entries: &[String], open: &str, close: &str, depth: u32) -> String {
+        if entries.is_empty() {
+            return format!("{}{}", open, close);
+        }
+        let length = entries.iter().map(|entry| entry.len() + 2).sum::<usize>();
+        let indent = "  ".repeat(depth as usize);
+        if length + indent.len() + 4 <= self.options.break_length
+            && entries.iter().all(|entry| !entry.contains('\n'))
+        {
+            return format!("{} {} {}", open, entries.join(", "), close);
+        }
+        let separator = format!(",\n{}  ", indent);
+        format!(
+            "{}\n{}  {}\n{}{}",
+            open,
+            indent,
+            entries.join(&separator),
+            indent,
+            close
+        )
+    }
+}
+
+/// Value of data property `name` found on `object` or its prototypes. Lookup stops at accessors.
+fn data_property(
+    ctx: GcPointer<Context>,
+    mut object: GcPointer<JsObject>,
+    name: Symbol,
+) -> Option<JsValue> {
+    loop {
+        if let Some(desc) = object.get_own_property(ctx, name) {
+            if desc.is_data() {
+                return Some(desc.value());
+            }
+            return None;
+        }
+        object = *object.prototype()?;
+    }
+}
+
+fn is_error(object: GcPointer<JsObject>) -> bool {
+    object.tag() == ObjectTag::Error || object.class.name.ends_with("Error")
+}
+
+fn is_identifier(key: &str) -> bool {
+    let mut chars = key.chars();
+    match chars.next() {
+        Some(c) if c.is_alphabetic() || c == '_' || c == '$' => {}
+        _ => return false,
+    }
+    chars.all(|c| c.is_alphanumeric() || c == '_' || c == '$')
+}
+
+/// Quote `string` with single quotes, escaping quotes, backslashes and control characters.
+fn quote(string: &str) -> String {
+    let mut result = String::with_capacity(string.len() + 2);
+    result.push('\'');
+    for c in string.chars() {
+        match c {
+            '\'' => result.push_str("\\'"),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            c if c.is_control() => result.push_str(&format!("\\u{:04x}", c as u32)),
+            c => result.push(c),
+        }
+    }
+    result.push('\'');
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::tests::new_context;
+
+    fn inspect_source(source: &str, options: &InspectOptions) -> String {
+        let (_vm, mut ctx) = new_context();
+        let value = ctx.eval(source).ok().unwrap();
+        inspect(ctx, value, options)
+    }
+
+    #[test]
+    fn test_inspect_values() {
+        let options = InspectOptions::default();
+        let cases = [
+            ("-0", "-0"),
+            ("'it\\'s'", "'it\\'s'"),
+            (
+                "[1, 'a', [2, [3, [4]]]]",
+                "[ 1, 'a', [ 2, [ 3, [Array] ] ] ]",
+            ),
+            (
+                "({ a: 1, 'b-c': { d: null } })",
+                "{ a: 1, 'b-c': { d: null } }",
+            ),
+            (
+                "var o = { x: 1 }; o.self = o; o",
+                "{ x: 1, self: [Circular] }",
+            ),
+            ("var a = [1]; a[3] = 4; a", "[ 1, <2 empty items>, 4 ]"),
+            (
+                "var o = {};
+                 Object.defineProperty(o, 'a', { get: function () { throw 1; }, enumerable: true });
+                 Object.defineProperty(o, 'b', { set: function (v) {}, enumerable: true });
+                 o",
+                "{ a: [Getter], b: [Setter] }",
+            ),
+            (
+                "function Point() { this.x = 1; } new Point()",
+                "Point { x: 1 }",
+            ),
+            ("Object.create(null)", "[Object: null prototype] {}"),
+            ("(function foo() {})", "[Function: foo]"),
+            ("({ e: new TypeError('bad') })", "{ e: [TypeError: bad] }"),
+        ];
+        for &(source, expected) in cases.iter() {
+            assert_eq!(inspect_source(source, &options), expected, "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_inspect_options() {
+        let options = InspectOptions {
+            depth: 0,
+            max_array_length: 2,
+            ..Default::default()
+        };
+        assert_eq!(
+            inspect_source("[1, 2, 3, { a: 1 }]", &options),
+            "[ 1, 2, ... 2 more items ]"
+        );
+        assert_eq!(
+            inspect_source("({ a: { b: 1 } })", &options),
+            "{ a: [Object] }"
+        );
+
+        let options = InspectOptions {
+            colors: true,
+            ..Default::default()
+        };
+        assert_eq!(inspect_source("[1]", &options), "[ \x1b[33m1\x1b[0m ]");
+
+        let options = InspectOptions {
+            break_length: 10,
+            ..Default::default()
+        };
+        assert_eq!(
+            inspect_source("({ first: 1, second: 2 })", &options),
+            "{\n  first: 1,\n  second: 2\n}"
+        );
+    }
+}
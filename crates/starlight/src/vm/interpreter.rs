@@ -4,10 +4,14 @@
 use self::{frame::CallFrame, stack::Stack};
 use super::function::*;
 use super::{
-    arguments::*, array::*, code_block::CodeBlock, environment::*, error::JsTypeError, error::*,
-    native_iterator::*, object::*, slot::*, string::JsString, symbol_table::*, value::*,
+    arguments::*, array::*, code_block::CodeBlock, debugger, environment::*, error::JsTypeError,
+    error::*,
+    global::JsGlobal, native_iterator::*, object::*, profiler, slot::*, string::JsString, symbol_table::*,
+    value::*,
 };
+use crate::bytecompiler::ByteCompiler;
 use crate::letroot;
+use crate::utils::{likely, unlikely};
 use crate::vm::class::JsClass;
 use crate::vm::context::Context;
 use crate::{
@@ -16,8 +20,7 @@ use crate::{
 };
 use crate::{bytecode::*, gc::cell::Visitor};
 use comet::internal::finalize_trait::FinalizeTrait;
-use profile::{ArithProfile, ByValProfile};
-use std::intrinsics::{likely, unlikely};
+use profile::{ArithProfile, ByValMode, ByValProfile};
 use wtf_rs::unwrap_unchecked;
 pub mod frame;
 pub mod stack;
@@ -30,24 +33,40 @@ impl GcPointer<Context> {
         args_: &Arguments,
         callee: JsValue,
     ) -> Result<JsValue, JsValue> {
+        if unlikely(func.code.lazy.is_some()) {
+            ByteCompiler::compile_lazy(self, func.code)?;
+        }
+        #[cfg(feature = "jit")]
+        crate::jit::count(func.code);
         let mut scope = unsafe { env.get_object().downcast::<Environment>().unwrap() };
+        if !func.code.needs_environment {
+            let this = self.this_for_call(func, args_);
+            return unsafe {
+                eval_internal(
+                    self,
+                    func.code,
+                    &func.code.code[0] as *const u8 as *mut u8,
+                    this,
+                    args_,
+                    scope,
+                    callee,
+                )
+            };
+        }
 
         let mut nscope = Environment::new(
             self,
-            func.code.param_count
-                + func.code.var_count
-                + func.code.rest_at.map(|_| 1).unwrap_or(0)
-                + if func.code.use_arguments { 1 } else { 0 },
+            func.code.param_count + func.code.var_count + func.code.rest_at.map(|_| 1).unwrap_or(0),
         );
         nscope.parent = Some(scope);
-        let mut i = 0;
-        for _ in 0..func.code.param_count {
-            nscope.as_slice_mut()[i as usize].value = args_.at(i);
-            i += 1;
+        if func.code.var_names.is_some() {
+            nscope.code = Some(func.code);
         }
+        self.copy_params(func, *nscope, args_);
+        let i = func.code.param_count as usize;
 
         if let Some(rest) = func.code.rest_at {
-            let mut args_arr = JsArray::new(self, args_.size() as u32 - i as u32);
+            let mut args_arr = JsArray::new(self, args_.size().saturating_sub(i) as u32);
             let mut ai = 0;
             for ix in i..args_.size() {
                 args_arr.put_indexed_slot(
@@ -63,28 +82,10 @@ impl GcPointer<Context> {
         }
 
         if func.code.use_arguments {
-            let p = {
-                let mut p = vec![];
-                for i in 0..func.code.param_count {
-                    p.push(Symbol::Index(i));
-                }
-                p
-            };
-            let mut args = JsArguments::new(self, nscope, &p, args_.size() as _, args_.values);
-
-            for k in i..args_.size() {
-                args.put(self, Symbol::Index(k as _), args_.at(k), false)?;
-            }
-
+            let args = self.create_arguments(&func.code, nscope, args_)?;
             nscope.as_slice_mut()[func.code.args_at as usize].value = JsValue::new(args);
         }
-        let _this = if func.code.strict && !args_.this.is_object() {
-            JsValue::encode_undefined_value()
-        } else if args_.this.is_undefined() {
-            JsValue::encode_object_value(self.global_object())
-        } else {
-            args_.this
-        };
+        let _this = self.this_for_call(func, args_);
 
         unsafe {
             eval_internal(
@@ -92,22 +93,80 @@ impl GcPointer<Context> {
                 func.code,
                 &func.code.code[0] as *const u8 as *mut u8,
                 _this,
-                args_.ctor_call,
+                args_,
                 nscope,
                 callee,
             )
         }
     }
 
+    /// Create `arguments` object for a call of `code`. Sloppy functions with simple parameter list get mapped
+    /// object whose indexes alias parameters stored in `env`, strict functions and functions with rest
+    /// parameter get a plain copy of argument values.
+    fn create_arguments(
+        self,
+        code: &CodeBlock,
+        env: GcPointer<Environment>,
+        args_: &Arguments,
+    ) -> Result<GcPointer<JsObject>, JsValue> {
+        let mapped = if !code.strict && code.rest_at.is_none() {
+            code.param_count
+        } else {
+            0
+        };
+        let params = (0..mapped).map(Symbol::Index).collect::<Vec<_>>();
+        let mut args = JsArguments::new(self, env, &params, args_.size() as _, args_.values);
+        for k in params.len().min(args_.size())..args_.size() {
+            args.put(self, Symbol::Index(k as _), args_.at(k), false)?;
+        }
+        Ok(args)
+    }
+
+    /// Copy arguments to parameters stored in `env`. Parameters in stack slots are set up by [init_slots].
+    #[inline]
+    fn copy_params(self, func: &JsVMFunction, mut env: GcPointer<Environment>, args_: &Arguments) {
+        if func.code.params_in_slots {
+            return;
+        }
+        for i in 0..func.code.param_count as usize {
+            env.as_slice_mut()[i].value = args_.at(i);
+        }
+    }
+
+    /// `this` of a call of `func`. Strict functions get the receiver unchanged, sloppy ones get the global object
+    /// instead of a missing, `undefined` or `null` receiver.
+    fn this_for_call(self, func: &JsVMFunction, args_: &Arguments) -> JsValue {
+        if func.code.strict {
+            if args_.this.is_empty() {
+                JsValue::encode_undefined_value()
+            } else {
+                args_.this
+            }
+        } else if args_.this.is_empty() || args_.this.is_undefined() || args_.this.is_null() {
+            JsValue::encode_object_value(self.global_object())
+        } else {
+            args_.this
+        }
+    }
+
     pub(crate) fn setup_for_vm_call(
         mut self,
         func: &JsVMFunction,
         env: JsValue,
         args_: &Arguments,
     ) -> Result<(JsValue, GcPointer<Environment>), JsValue> {
+        if unlikely(func.code.lazy.is_some()) {
+            ByteCompiler::compile_lazy(self, func.code)?;
+        }
+        #[cfg(feature = "jit")]
+        crate::jit::count(func.code);
         letroot!(scope = stack, unsafe {
             env.get_object().downcast::<Environment>().unwrap()
         });
+        if !func.code.needs_environment {
+            // every binding lives in stack slots, callee runs directly in the scope of closure.
+            return Ok((self.this_for_call(func, args_), *scope));
+        }
 
         letroot!(
             nscope = stack,
@@ -116,22 +175,17 @@ impl GcPointer<Context> {
                 func.code.param_count
                     + func.code.var_count
                     + func.code.rest_at.map(|_| 1).unwrap_or(0)
-                    + if func.code.use_arguments { 1 } else { 0 }
             )
         );
         nscope.parent = Some(scope);
-        let mut i = 0;
-        for _ in 0..func.code.param_count {
-            /*let _ = nscope
-                .put(self, *p, args_.at(i), false)
-                .unwrap_or_else(|_| unsafe { unreachable_unchecked() });
-            */
-            nscope.as_slice_mut()[i as usize].value = args_.at(i);
-            i += 1;
+        if func.code.var_names.is_some() {
+            nscope.code = Some(func.code);
         }
+        self.copy_params(func, *nscope, args_);
+        let i = func.code.param_count as usize;
 
         if let Some(rest) = func.code.rest_at {
-            let mut args_arr = JsArray::new(self, args_.size() as u32 - i as u32);
+            let mut args_arr = JsArray::new(self, args_.size().saturating_sub(i) as u32);
             let mut ai = 0;
             for ix in i..args_.size() {
                 args_arr.put_indexed_slot(
@@ -153,44 +207,57 @@ impl GcPointer<Context> {
             //   vscope.put(self, *val, JsValue::encode_undefined_value(), false)?;
         }*/
         if func.code.use_arguments {
-            let p = {
-                let mut p = vec![];
-                for i in 0..func.code.param_count {
-                    p.push(Symbol::Index(i));
-                }
-                p
-            };
-            let mut args = JsArguments::new(self, nscope, &p, args_.size() as _, args_.values);
-
-            for k in i..args_.size() {
-                args.put(self, Symbol::Index(k as _), args_.at(k), false)?;
-            }
-
+            let args = self.create_arguments(&func.code, *nscope, args_)?;
             nscope.as_slice_mut()[func.code.args_at as usize].value = JsValue::new(args);
         }
-        let _this = if func.code.strict && !args_.this.is_object() {
-            JsValue::encode_undefined_value()
-        } else if args_.this.is_undefined() {
-            JsValue::encode_object_value(self.global_object())
-        } else {
-            args_.this
-        };
 
-        Ok((_this, nscope))
+        Ok((self.this_for_call(func, args_), *nscope))
+    }
+}
+
+/// Move arguments into parameter slots of `frame` created by [Stack::new_frame] and clear remaining slots.
+/// Arguments may still live in stack memory that is now occupied by slots so copy has to handle overlap.
+///
+/// `call` and `new` create the callee frame right where the caller pushed the arguments, so they already are
+/// in place and only the slots of missing arguments and of locals are written. Arguments passed from native
+/// code or moved by a tail call are copied.
+#[inline]
+unsafe fn init_slots(frame: &mut CallFrame, code: &CodeBlock, args: &Arguments) {
+    let count = code.slot_count as usize;
+    if count == 0 {
+        return;
+    }
+    let params = (code.param_count as usize).min(args.values.len());
+    if args.values.as_ptr() != frame.limit as *const JsValue {
+        std::ptr::copy(args.values.as_ptr(), frame.limit, params);
+    }
+    for i in params..count {
+        frame.limit.add(i).write(JsValue::encode_undefined_value());
     }
 }
 
+/// Can `frame` be popped before call in tail position?
+///
+/// - frames entered from native code (`exit_on_return`) are kept so native caller, generators and async
+///   functions always see the frame they created;
+/// - constructor frames have to replace non-object return value with `this`;
+/// - frames with active `try` blocks have to catch exceptions thrown by callee.
+#[inline]
+fn can_reuse_frame(frame: &CallFrame) -> bool {
+    !frame.exit_on_return && !frame.ctor && frame.try_stack.is_empty()
+}
+
 #[inline(never)]
 unsafe fn eval_internal(
     mut ctx: GcPointer<Context>,
     code: GcPointer<CodeBlock>,
     ip: *mut u8,
     this: JsValue,
-    ctor: bool,
+    args: &Arguments,
     scope: GcPointer<Environment>,
     callee: JsValue,
 ) -> Result<JsValue, JsValue> {
-    let frame = ctx.stack.new_frame(0, callee, scope);
+    let frame = ctx.stack.new_frame(code.slot_count, callee, scope);
     if frame.is_none() {
         let msg = JsString::new(ctx, "stack overflow");
         return Err(JsValue::encode_object_value(JsRangeError::new(
@@ -198,10 +265,11 @@ unsafe fn eval_internal(
         )));
     }
     let mut frame = unwrap_unchecked(frame);
+    init_slots(&mut *frame, &code, args);
     (*frame).code_block = Some(code);
     (*frame).this = this;
     (*frame).env = scope;
-    (*frame).ctor = ctor;
+    (*frame).ctor = args.ctor_call;
     (*frame).exit_on_return = true;
     (*frame).ip = ip;
 
@@ -210,7 +278,10 @@ unsafe fn eval_internal(
         match result {
             Ok(value) => return Ok(value),
             Err(e) => {
-                ctx.stacktrace = ctx.stacktrace();
+                ctx.stacktrace = ctx.exception_stacktrace(e);
+                if unlikely(ctx.vm.debugger.is_some()) {
+                    debugger::exception_thrown(ctx, e);
+                }
 
                 if let Some(unwind_frame) = ctx.unwind() {
                     let (env, ip, sp) = (*unwind_frame).try_stack.pop().unwrap();
@@ -239,7 +310,9 @@ pub unsafe fn eval(mut ctx: GcPointer<Context>, frame: *mut CallFrame) -> Result
         ip = ip.add(1);
         #[cfg(feature = "perf")]
         {
-            ctx.perf.get_perf(opcode as u8);
+            ctx.vm
+                .perf
+                .get_perf_in(opcode as u8, unwrap_unchecked(frame.code_block));
         }
         /*println!(
             "exec block({:p}): {}: {:?} (sp {})",
@@ -283,9 +356,7 @@ pub unsafe fn eval(mut ctx: GcPointer<Context>, frame: *mut CallFrame) -> Result
                 debug_assert!(index < env.as_slice_mut().len() as u32);
                 let val = frame.pop();
                 if unlikely(!env.as_slice_mut()[index as usize].mutable) {
-                    return Err(JsValue::new(
-                        ctx.new_type_error("Cannot assign to immutable variable".to_string()),
-                    ));
+                    return Err(env.immutable_error(ctx, index as _));
                 }
 
                 env.as_slice_mut().get_unchecked_mut(index as usize).value = val;
@@ -310,13 +381,37 @@ pub unsafe fn eval(mut ctx: GcPointer<Context>, frame: *mut CallFrame) -> Result
                 debug_assert!(index < env.as_slice_mut().len() as u32);
                 let val = frame.pop();
                 if unlikely(!env.as_slice_mut()[index as usize].mutable) {
-                    return Err(JsValue::new(
-                        ctx.new_type_error("Cannot assign to immutable variable".to_string()),
-                    ));
+                    return Err(env.immutable_error(ctx, index as _));
                 }
 
                 env.as_slice_mut().get_unchecked_mut(index as usize).value = val;
             }
+            Opcode::OP_GET_SLOT => {
+                let index = ip.cast::<u32>().read_unaligned();
+                ip = ip.add(4);
+                let value = *frame.slot(index);
+                frame.push(value);
+            }
+            Opcode::OP_SET_SLOT => {
+                let index = ip.cast::<u32>().read_unaligned();
+                ip = ip.add(4);
+                let value = frame.pop();
+                *frame.slot(index) = value;
+            }
+            Opcode::OP_INC_SLOT | Opcode::OP_DEC_SLOT => {
+                let index = ip.cast::<u32>().read_unaligned();
+                ip = ip.add(4);
+                let delta = if opcode == Opcode::OP_INC_SLOT { 1 } else { -1 };
+                let value = *frame.slot(index);
+                if likely(value.is_int32()) {
+                    if let Some(result) = value.get_int32().checked_add(delta) {
+                        *frame.slot(index) = JsValue::encode_int32(result);
+                        continue;
+                    }
+                }
+                let number = value.to_number(ctx)?;
+                *frame.slot(index) = JsValue::new(number + delta as f64);
+            }
             Opcode::OP_GET_ENV => {
                 let mut depth = ip.cast::<u32>().read_unaligned();
                 ip = ip.add(4);
@@ -334,6 +429,11 @@ pub unsafe fn eval(mut ctx: GcPointer<Context>, frame: *mut CallFrame) -> Result
                 ctx.heap().collect_if_necessary();
                 let offset = ip.cast::<i32>().read();
                 ip = ip.add(4);
+                if offset < 0 {
+                    #[cfg(feature = "jit")]
+                    crate::jit::count(unwrap_unchecked(frame.code_block));
+                    profiler::safepoint(ctx);
+                }
                 ip = ip.offset(offset as isize);
             }
             Opcode::OP_JMP_IF_FALSE => {
@@ -394,6 +494,11 @@ pub unsafe fn eval(mut ctx: GcPointer<Context>, frame: *mut CallFrame) -> Result
                 if frame.ctor && !value.is_jsobject() {
                     value = frame.this;
                 }
+                if unlikely(unwrap_unchecked(frame.code_block).debug) {
+                    frame.ip = ip;
+                    debugger::function_exited(ctx, frame, value);
+                }
+                profiler::safepoint(ctx);
                 let prev = ctx.stack.pop_frame().unwrap();
                 if prev.exit_on_return || prev.prev.is_null() {
                     return Ok(value);
@@ -440,10 +545,19 @@ pub unsafe fn eval(mut ctx: GcPointer<Context>, frame: *mut CallFrame) -> Result
                             lhs: JsValue,
                             rhs: JsValue,
                         ) -> Result<JsValue, JsValue> {
-                            let lhs = lhs.to_string(ctx)?;
-                            let rhs = rhs.to_string(ctx)?;
-                            let string = format!("{}{}", lhs, rhs);
-                            Ok(JsValue::encode_object_value(JsString::new(ctx, string)))
+                            let to_jsstring =
+                                |value: JsValue| -> Result<GcPointer<JsString>, JsValue> {
+                                    if value.is_jsstring() {
+                                        Ok(value.get_jsstring())
+                                    } else {
+                                        Ok(JsString::new(ctx, value.to_string(ctx)?))
+                                    }
+                                };
+                            let lhs = to_jsstring(lhs)?;
+                            let rhs = to_jsstring(rhs)?;
+                            Ok(JsValue::encode_object_value(JsString::concat(
+                                ctx, lhs, rhs,
+                            )))
                         }
 
                         let result = concat(ctx, lhs, rhs)?;
@@ -486,6 +600,30 @@ pub unsafe fn eval(mut ctx: GcPointer<Context>, frame: *mut CallFrame) -> Result
                 let rhs = rhs.to_number(ctx)?;
                 frame.push(JsValue::new(lhs - rhs));
             }
+            Opcode::OP_ADD_INT | Opcode::OP_SUB_INT => {
+                let (lhs, rhs) = (*frame.at(-1), *frame.at(-2));
+                if likely(lhs.is_int32() && rhs.is_int32()) {
+                    let result = if opcode == Opcode::OP_ADD_INT {
+                        lhs.get_int32().checked_add(rhs.get_int32())
+                    } else {
+                        lhs.get_int32().checked_sub(rhs.get_int32())
+                    };
+                    if let Some(result) = result {
+                        frame.pop();
+                        frame.pop();
+                        frame.push(JsValue::encode_int32(result));
+                        ip = ip.add(4);
+                        continue;
+                    }
+                }
+                // speculation failed, restore generic opcode and execute it.
+                ip = ip.sub(1);
+                ip.write(if opcode == Opcode::OP_ADD_INT {
+                    Opcode::OP_ADD
+                } else {
+                    Opcode::OP_SUB
+                } as u8);
+            }
             Opcode::OP_DIV => {
                 let profile = &mut *ip.cast::<ArithProfile>();
                 ip = ip.add(4);
@@ -609,6 +747,81 @@ pub unsafe fn eval(mut ctx: GcPointer<Context>, frame: *mut CallFrame) -> Result
                     lhs.compare(rhs, true, ctx)? == CMP_FALSE,
                 ));
             }
+            Opcode::OP_LESS_JMP_IF_FALSE
+            | Opcode::OP_LESSEQ_JMP_IF_FALSE
+            | Opcode::OP_GREATER_JMP_IF_FALSE
+            | Opcode::OP_GREATEREQ_JMP_IF_FALSE => {
+                let lhs = frame.pop();
+                let rhs = frame.pop();
+                let result = if likely(lhs.is_int32() && rhs.is_int32()) {
+                    let (lhs, rhs) = (lhs.get_int32(), rhs.get_int32());
+                    match opcode {
+                        Opcode::OP_LESS_JMP_IF_FALSE => lhs < rhs,
+                        Opcode::OP_LESSEQ_JMP_IF_FALSE => lhs <= rhs,
+                        Opcode::OP_GREATER_JMP_IF_FALSE => lhs > rhs,
+                        _ => lhs >= rhs,
+                    }
+                } else {
+                    match opcode {
+                        Opcode::OP_LESS_JMP_IF_FALSE => lhs.compare(rhs, true, ctx)? == CMP_TRUE,
+                        Opcode::OP_LESSEQ_JMP_IF_FALSE => {
+                            rhs.compare(lhs, false, ctx)? == CMP_FALSE
+                        }
+                        Opcode::OP_GREATER_JMP_IF_FALSE => {
+                            rhs.compare(lhs, false, ctx)? == CMP_TRUE
+                        }
+                        _ => lhs.compare(rhs, true, ctx)? == CMP_FALSE,
+                    }
+                };
+                // skip opcode of jmp_if_false.
+                let offset = ip.add(1).cast::<i32>().read_unaligned();
+                ip = ip.add(5);
+                if !result {
+                    ip = ip.offset(offset as _);
+                }
+            }
+            Opcode::OP_PUSH_INT_ADD => {
+                let int = ip.cast::<i32>().read_unaligned();
+                ip = ip.add(4);
+                let rhs = frame.pop();
+                if likely(rhs.is_int32()) {
+                    if let Some(val) = int.checked_add(rhs.get_int32()) {
+                        // skip add and its profile.
+                        ip = ip.add(5);
+                        frame.push(JsValue::encode_int32(val));
+                        continue;
+                    }
+                }
+                // let op_add handle overflow, doubles and strings.
+                frame.push(rhs);
+                frame.push(JsValue::encode_int32(int));
+            }
+            Opcode::OP_GE0GL_GET_BY_ID => {
+                let index = ip.cast::<u32>().read_unaligned();
+                ip = ip.add(4);
+                let value = frame.env.as_slice().get_unchecked(index as usize).value;
+                #[cfg(not(feature = "no-inline-caching"))]
+                if likely(value.is_jsobject()) {
+                    let fdbk = ip.add(5).cast::<u32>().read_unaligned();
+                    if let TypeFeedBack::PropertyCache {
+                        structure,
+                        offset,
+                        mode: GetByIdMode::Default,
+                    } = unwrap_unchecked(frame.code_block)
+                        .feedback
+                        .get_unchecked(fdbk as usize)
+                    {
+                        let obj = value.get_jsobject();
+                        if GcPointer::ptr_eq(structure, &obj.structure()) {
+                            // skip get_by_id and its operands.
+                            ip = ip.add(9);
+                            frame.push(*obj.direct(*offset as _));
+                            continue;
+                        }
+                    }
+                }
+                frame.push(value);
+            }
             Opcode::OP_GET_BY_ID | Opcode::OP_TRY_GET_BY_ID => {
                 let name = ip.cast::<u32>().read_unaligned();
                 let name = *unwrap_unchecked(frame.code_block)
@@ -722,10 +935,9 @@ pub unsafe fn eval(mut ctx: GcPointer<Context>, frame: *mut CallFrame) -> Result
                         } else {
                             if unlikely(is_try) {
                                 let desc = ctx.description(name);
-                                return Err(JsValue::new(ctx.new_reference_error(format!(
-                                    "Property '{}' not found",
-                                    desc
-                                ))));
+                                return Err(JsValue::new(
+                                    ctx.new_reference_error(format!("{} is not defined", desc)),
+                                ));
                             }
                             frame.push(JsValue::encode_undefined_value());
                         }
@@ -804,6 +1016,101 @@ pub unsafe fn eval(mut ctx: GcPointer<Context>, frame: *mut CallFrame) -> Result
                     continue;
                 }
             }
+            Opcode::OP_GET_GLOBAL => {
+                let name = ip.cast::<u32>().read_unaligned();
+                let name = *unwrap_unchecked(frame.code_block)
+                    .names
+                    .get_unchecked(name as usize);
+                ip = ip.add(4);
+                let fdbk = ip.cast::<u32>().read_unaligned();
+                ip = ip.add(4);
+                #[cfg(not(feature = "no-inline-caching"))]
+                if let TypeFeedBack::GlobalCell { cell } = unwrap_unchecked(frame.code_block)
+                    .feedback
+                    .get_unchecked(fdbk as usize)
+                {
+                    if likely(cell.valid) {
+                        frame.push(*ctx.global_object().direct(cell.offset as _));
+                        continue;
+                    }
+                }
+
+                #[inline(never)]
+                #[cold]
+                unsafe fn slow_get_global(
+                    ctx: GcPointer<Context>,
+                    frame: &mut CallFrame,
+                    name: Symbol,
+                    fdbk: u32,
+                ) -> Result<JsValue, JsValue> {
+                    let mut global = ctx.global_object();
+                    #[cfg(not(feature = "no-inline-caching"))]
+                    if let Some(cell) = JsGlobal::property_cell(&mut global, ctx, name) {
+                        *unwrap_unchecked(frame.code_block)
+                            .feedback
+                            .get_unchecked_mut(fdbk as usize) = TypeFeedBack::GlobalCell { cell };
+                        return Ok(*global.direct(cell.offset as _));
+                    }
+                    let mut slot = Slot::new();
+                    if global.get_property_slot(ctx, name, &mut slot) {
+                        return slot.get(ctx, JsValue::new(global));
+                    }
+                    let desc = ctx.description(name);
+                    Err(JsValue::new(
+                        ctx.new_reference_error(format!("{} is not defined", desc)),
+                    ))
+                }
+                frame.push(slow_get_global(ctx, frame, name, fdbk)?);
+            }
+            Opcode::OP_PUT_GLOBAL => {
+                let name = ip.cast::<u32>().read_unaligned();
+                let name = *unwrap_unchecked(frame.code_block)
+                    .names
+                    .get_unchecked(name as usize);
+                ip = ip.add(4);
+                let fdbk = ip.cast::<u32>().read_unaligned();
+                ip = ip.add(4);
+                let value = frame.pop();
+                #[cfg(not(feature = "no-inline-caching"))]
+                if let TypeFeedBack::GlobalCell { cell } = unwrap_unchecked(frame.code_block)
+                    .feedback
+                    .get_unchecked(fdbk as usize)
+                {
+                    if likely(cell.valid && cell.writable) {
+                        *ctx.global_object().direct_mut(cell.offset as _) = value;
+                        continue;
+                    }
+                }
+
+                #[inline(never)]
+                #[cold]
+                unsafe fn slow_put_global(
+                    ctx: GcPointer<Context>,
+                    frame: &mut CallFrame,
+                    name: Symbol,
+                    value: JsValue,
+                    fdbk: u32,
+                ) -> Result<(), JsValue> {
+                    let mut global = ctx.global_object();
+                    let strict = unwrap_unchecked(frame.code_block).strict;
+                    // strict code can not create globals by assignment.
+                    if strict && !global.has_property(ctx, name) {
+                        let desc = ctx.description(name);
+                        return Err(JsValue::new(
+                            ctx.new_reference_error(format!("{} is not defined", desc)),
+                        ));
+                    }
+                    global.put(ctx, name, value, strict)?;
+                    #[cfg(not(feature = "no-inline-caching"))]
+                    if let Some(cell) = JsGlobal::property_cell(&mut global, ctx, name) {
+                        *unwrap_unchecked(frame.code_block)
+                            .feedback
+                            .get_unchecked_mut(fdbk as usize) = TypeFeedBack::GlobalCell { cell };
+                    }
+                    Ok(())
+                }
+                slow_put_global(ctx, frame, name, value, fdbk)?;
+            }
 
             Opcode::OP_CALL | Opcode::OP_TAILCALL => {
                 ctx.heap().collect_if_necessary();
@@ -817,10 +1124,7 @@ pub unsafe fn eval(mut ctx: GcPointer<Context>, frame: *mut CallFrame) -> Result
                 let mut this = frame.pop();
                 let mut args = std::slice::from_raw_parts_mut(args_start, argc as _);
                 if unlikely(!func.is_callable()) {
-                    let msg = JsString::new(ctx, "not a callable object".to_string());
-                    return Err(JsValue::encode_object_value(JsTypeError::new(
-                        ctx, msg, None,
-                    )));
+                    return Err(not_callable_error(ctx, frame, ip.sub(5), false));
                 }
                 letroot!(func_object = gcstack, func.get_jsobject());
                 letroot!(funcc = gcstack, func_object);
@@ -835,15 +1139,16 @@ pub unsafe fn eval(mut ctx: GcPointer<Context>, frame: *mut CallFrame) -> Result
                     let scope = JsValue::new(vm_fn.scope);
                     let (this, scope) = ctx.setup_for_vm_call(vm_fn, scope, &args_)?;
                     let mut exit = false;
-                    if !frame.exit_on_return
-                        && (opcode == Opcode::OP_TAILCALL
-                            || (ip.cast::<Opcode>().read() == Opcode::OP_POP
-                                && ip.add(1).cast::<Opcode>().read() == Opcode::OP_RET))
-                    {
-                        // ctx.stack.pop_frame().unwrap();
+                    if opcode == Opcode::OP_TAILCALL && can_reuse_frame(frame) {
+                        // callee takes over caller's stack range and returns directly to caller's caller.
                         exit = ctx.stack.pop_frame().unwrap().exit_on_return;
+                    } else {
+                        // arguments become the first slots of callee.
+                        stack.cursor = args_start;
                     }
-                    let cframe = ctx.stack.new_frame(0, JsValue::new(funcc), scope);
+                    let cframe =
+                        ctx.stack
+                            .new_frame(vm_fn.code.slot_count, JsValue::new(funcc), scope);
                     if unlikely(cframe.is_none()) {
                         let msg = JsString::new(ctx, "stack overflow");
                         return Err(JsValue::encode_object_value(JsRangeError::new(
@@ -851,6 +1156,7 @@ pub unsafe fn eval(mut ctx: GcPointer<Context>, frame: *mut CallFrame) -> Result
                         )));
                     }
                     let cframe = unwrap_unchecked(cframe);
+                    init_slots(&mut *cframe, &vm_fn.code, &args_);
                     (*cframe).code_block = Some(vm_fn.code);
                     (*cframe).this = this;
 
@@ -866,6 +1172,47 @@ pub unsafe fn eval(mut ctx: GcPointer<Context>, frame: *mut CallFrame) -> Result
                     frame.push(result);
                 }
             }
+            Opcode::OP_CALL_EVAL => {
+                ctx.heap().collect_if_necessary();
+                let argc = ip.cast::<u32>().read();
+                ip = ip.add(4);
+                let scope = ip.cast::<u32>().read();
+                ip = ip.add(4);
+
+                let args_start = frame.sp.sub(argc as _);
+                frame.sp = args_start;
+                let func = frame.pop();
+                let this = frame.pop();
+                let mut args = std::slice::from_raw_parts_mut(args_start, argc as _);
+                if unlikely(!func.is_callable()) {
+                    return Err(not_callable_error(ctx, frame, ip.sub(9), false));
+                }
+                frame.ip = ip;
+                stack.cursor = frame.sp;
+                letroot!(func_object = gcstack, func.get_jsobject());
+                let callee = func_object.as_function_mut();
+                let result = if callee.is_native()
+                    && callee.as_native().func as usize == crate::jsrt::global::eval as usize
+                {
+                    let code = unwrap_unchecked(frame.code_block);
+                    let source = args
+                        .get(0)
+                        .copied()
+                        .unwrap_or_else(JsValue::encode_undefined_value);
+                    ctx.direct_eval(
+                        source,
+                        code.eval_scopes[scope as usize].clone(),
+                        code.strict,
+                        frame.env,
+                        frame.this,
+                    )?
+                } else {
+                    // `eval` is shadowed, plain call.
+                    letroot!(args_ = gcstack, Arguments::new(this, &mut args));
+                    callee.call(ctx, &mut args_, func)?
+                };
+                frame.push(result);
+            }
             Opcode::OP_NEW | Opcode::OP_TAILNEW => {
                 ctx.heap().collect_if_necessary();
                 let argc = ip.cast::<u32>().read();
@@ -878,14 +1225,14 @@ pub unsafe fn eval(mut ctx: GcPointer<Context>, frame: *mut CallFrame) -> Result
                 let mut args = std::slice::from_raw_parts_mut(args_start, argc as _);
 
                 if unlikely(!func.is_callable()) {
-                    let msg = JsString::new(ctx, "not a callable constructor object ".to_string());
-                    return Err(JsValue::encode_object_value(JsTypeError::new(
-                        ctx, msg, None,
-                    )));
+                    return Err(not_callable_error(ctx, frame, ip.sub(5), true));
                 }
 
                 letroot!(func_object = gcstack, func.get_jsobject());
                 letroot!(funcc = gcstack, func.get_jsobject());
+                if unlikely(!func_object.as_function().is_constructor()) {
+                    return Err(not_callable_error(ctx, frame, ip.sub(5), true));
+                }
                 let map = func_object.func_construct_map(ctx)?;
                 let func = func_object.as_function_mut();
                 let object = JsObject::new(ctx, &map, JsObject::class(), ObjectTag::Ordinary);
@@ -902,11 +1249,14 @@ pub unsafe fn eval(mut ctx: GcPointer<Context>, frame: *mut CallFrame) -> Result
                     let scope = JsValue::new(vm_fn.scope);
                     let (this, scope) = ctx.setup_for_vm_call(vm_fn, scope, &args_)?;
                     let mut exit = false;
-                    if !frame.exit_on_return && (opcode == Opcode::OP_TAILNEW) {
-                        // stack.pop_frame().unwrap();
+                    if opcode == Opcode::OP_TAILNEW && can_reuse_frame(frame) {
                         exit = stack.pop_frame().unwrap().exit_on_return;
+                    } else {
+                        stack.cursor = args_start;
                     }
-                    let cframe = ctx.stack.new_frame(0, JsValue::new(funcc), scope);
+                    let cframe =
+                        ctx.stack
+                            .new_frame(vm_fn.code.slot_count, JsValue::new(funcc), scope);
                     if unlikely(cframe.is_none()) {
                         let msg = JsString::new(ctx, "stack overflow");
                         return Err(JsValue::encode_object_value(JsRangeError::new(
@@ -915,6 +1265,7 @@ pub unsafe fn eval(mut ctx: GcPointer<Context>, frame: *mut CallFrame) -> Result
                     }
 
                     let cframe = unwrap_unchecked(cframe);
+                    init_slots(&mut *cframe, &vm_fn.code, &args_);
                     (*cframe).code_block = Some(vm_fn.code);
                     (*cframe).this = this;
                     (*cframe).ctor = true;
@@ -978,20 +1329,19 @@ pub unsafe fn eval(mut ctx: GcPointer<Context>, frame: *mut CallFrame) -> Result
                 let key = frame.pop();
                 let value = frame.pop();
                 profile.observe_key_and_object(key, object);
-                if key.is_number() && object.is_jsobject() {
-                    let index = if likely(key.is_int32()) {
-                        key.get_int32() as u32
-                    } else {
-                        key.get_double().floor() as u32
-                    };
+                if profile.mode == ByValMode::DenseArray && key.is_int32() && object.is_jsobject() {
+                    // negative keys wrap around and fail the bounds check.
+                    let index = key.get_int32() as u32;
                     let mut object = object.get_jsobject();
                     if likely(object.indexed.dense())
+                        && likely(object.indexed.writable())
                         && likely(index < object.indexed.vector.size())
                     {
                         *object.indexed.vector.at_mut(index) = value;
                         continue;
                     }
                 }
+                profile.update_mode(key, object);
                 let key = key.to_symbol(ctx)?;
 
                 if likely(object.is_jsobject()) {
@@ -1026,25 +1376,36 @@ pub unsafe fn eval(mut ctx: GcPointer<Context>, frame: *mut CallFrame) -> Result
                 let object = frame.pop();
                 let key = frame.pop();
                 profile.observe_key_and_object(key, object);
-                if key.is_number() && object.is_jsobject() {
-                    let index = if likely(key.is_int32()) {
-                        key.get_int32() as usize
-                    } else {
-                        key.get_double().floor() as usize
-                    };
-                    let object = object.get_jsobject();
-                    if likely(object.indexed.dense())
-                        && likely(index < object.indexed.vector.size() as usize)
-                        && likely(!object.indexed.vector.at(index as _).is_empty())
-                    {
-                        if opcode == Opcode::OP_GET_BY_VAL_PUSH_OBJ {
-                            frame.push(JsValue::new(object));
+                match profile.mode {
+                    ByValMode::DenseArray if key.is_int32() && object.is_jsobject() => {
+                        let index = key.get_int32() as u32;
+                        let array = object.get_jsobject();
+                        if likely(array.indexed.dense())
+                            && likely(index < array.indexed.vector.size())
+                            && likely(!array.indexed.vector.at(index).is_empty())
+                        {
+                            if opcode == Opcode::OP_GET_BY_VAL_PUSH_OBJ {
+                                frame.push(object);
+                            }
+                            frame.push(*array.indexed.vector.at(index));
+                            continue;
+                        }
+                    }
+                    ByValMode::StringIndex if key.is_int32() && object.is_jsstring() => {
+                        let index = key.get_int32();
+                        if index >= 0 {
+                            if let Some(ch) = object.get_jsstring().char_at(ctx, index as _) {
+                                if opcode == Opcode::OP_GET_BY_VAL_PUSH_OBJ {
+                                    frame.push(object);
+                                }
+                                frame.push(JsValue::new(ch));
+                                continue;
+                            }
                         }
-                        frame.push(*object.indexed.vector.at(index as _));
-
-                        continue;
                     }
+                    _ => (),
                 }
+                profile.update_mode(key, object);
                 let key = key.to_symbol(ctx)?;
                 let mut slot = Slot::new();
                 let _ = object.get_slot(ctx, key, &mut slot)?;
@@ -1059,24 +1420,8 @@ pub unsafe fn eval(mut ctx: GcPointer<Context>, frame: *mut CallFrame) -> Result
             Opcode::OP_INSTANCEOF => {
                 let lhs = frame.pop();
                 let rhs = frame.pop();
-                if unlikely(!rhs.is_jsobject()) {
-                    let msg = JsString::new(ctx, "'instanceof' requires object");
-                    return Err(JsValue::encode_object_value(JsTypeError::new(
-                        ctx, msg, None,
-                    )));
-                }
-
-                letroot!(robj = gcstack, rhs.get_jsobject());
-                let mut robj2 = robj;
-                if unlikely(!robj.is_callable()) {
-                    let msg = JsString::new(ctx, "'instanceof' requires constructor");
-                    return Err(JsValue::encode_object_value(JsTypeError::new(
-                        ctx, msg, None,
-                    )));
-                }
-
                 frame.push(JsValue::encode_bool_value(
-                    robj.as_function().has_instance(&mut robj2, ctx, lhs)?,
+                    crate::vm::operations::instance_of(ctx, lhs, rhs)?,
                 ));
             }
             Opcode::OP_IN => {
@@ -1089,8 +1434,10 @@ pub unsafe fn eval(mut ctx: GcPointer<Context>, frame: *mut CallFrame) -> Result
                     )));
                 }
                 let sym = lhs.to_symbol(ctx)?;
+                // properties inherited from the prototype chain count too.
+                let mut slot = Slot::new();
                 frame.push(JsValue::encode_bool_value(
-                    rhs.get_jsobject().has_own_property(ctx, sym),
+                    rhs.get_jsobject().get_property_slot(ctx, sym, &mut slot),
                 ));
             }
 
@@ -1122,9 +1469,8 @@ pub unsafe fn eval(mut ctx: GcPointer<Context>, frame: *mut CallFrame) -> Result
                     .get_object()
                     .downcast_unchecked::<NativeIterator>();
                 frame.push(JsValue::new(it));
-                if let Some(sym) = it.next() {
-                    let desc = ctx.description(sym);
-                    frame.push(JsValue::new(JsString::new(ctx, desc)));
+                if let Some(sym) = it.next(ctx) {
+                    frame.push(JsValue::new(JsString::from_key(ctx, sym)));
                 } else {
                     frame.push(JsValue::encode_empty_value());
                     ip = ip.offset(offset as _);
@@ -1206,6 +1552,36 @@ pub unsafe fn eval(mut ctx: GcPointer<Context>, frame: *mut CallFrame) -> Result
                 };
             }
 
+            Opcode::OP_PUSH_EMPTY => frame.push(Variable::uninitialized()),
+            Opcode::OP_CHECK_TDZ => {
+                let name = ip.cast::<u32>().read_unaligned();
+                ip = ip.add(4);
+                if unlikely(frame.top().is_empty()) {
+                    let name = unwrap_unchecked(frame.code_block).names[name as usize];
+                    let desc = ctx.description(name);
+                    return Err(JsValue::new(ctx.new_reference_error(format!(
+                        "Cannot access '{}' before initialization",
+                        desc
+                    ))));
+                }
+            }
+            Opcode::OP_THROW_CONST_ASSIGN => {
+                let name = ip.cast::<u32>().read_unaligned();
+                let name = unwrap_unchecked(frame.code_block).names[name as usize];
+                let desc = ctx.description(name);
+                return Err(JsValue::new(ctx.new_type_error(format!(
+                    "Assignment to constant variable '{}'",
+                    desc
+                ))));
+            }
+            Opcode::OP_DEBUG => {
+                let kind = ip.cast::<u32>().read_unaligned();
+                ip = ip.add(4);
+                if unlikely(ctx.vm.debugger.is_some()) {
+                    frame.ip = ip;
+                    debugger::debug(ctx, frame, kind);
+                }
+            }
             Opcode::OP_DELETE_BY_ID => {
                 let name = ip.cast::<u32>().read();
                 ip = ip.add(4);
@@ -1367,13 +1743,11 @@ pub unsafe fn eval(mut ctx: GcPointer<Context>, frame: *mut CallFrame) -> Result
                 let val = frame.pop();
                 frame.push(JsValue::new(val.is_jsobject()));
             }
-            Opcode::OP_NOP => todo!(),
+            Opcode::OP_NOP => {}
             Opcode::OP_LOOPHINT => todo!(),
             Opcode::OP_PUSH_ENV => todo!(),
             Opcode::OP_POP_ENV => todo!(),
             Opcode::OP_SET_ENV => todo!(),
-            Opcode::OP_SET_GLOBAL => todo!(),
-            Opcode::OP_GET_GLOBAL => todo!(),
             Opcode::OP_DELETE_VAR => todo!(),
             Opcode::OP_FOROF_SETUP => todo!(),
             Opcode::OP_FOROF_ENUMERATE => todo!(),
@@ -1419,6 +1793,25 @@ impl Trace for SpreadValue {
 
 impl FinalizeTrait<SpreadValue> for SpreadValue {}
 
+/// `TypeError` thrown when the callee of call instruction at `pc` is not a function, or not a constructor when
+/// `construct` is set. The message names the callee when the compiler recorded its text.
+pub(crate) fn not_callable_error(
+    ctx: GcPointer<Context>,
+    frame: &CallFrame,
+    pc: *const u8,
+    construct: bool,
+) -> JsValue {
+    let code = frame.code_block.unwrap();
+    let name = code.callee_name(pc as usize - code.code.as_ptr() as usize);
+    let message = match (name, construct) {
+        (Some(name), false) => format!("{} is not a function", name),
+        (Some(name), true) => format!("{} is not a constructor", name),
+        (None, false) => "not a callable object".to_string(),
+        (None, true) => "not a constructor".to_string(),
+    };
+    JsValue::new(ctx.new_type_error(message))
+}
+
 pub fn get_by_id_slow(
     ctx: GcPointer<Context>,
     name: Symbol,
@@ -1503,3 +1896,197 @@ pub(crate) unsafe fn put_by_id_slow(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::vm::tests::new_context;
+
+    #[test]
+    fn test_tail_calls() {
+        let (_vm, mut ctx) = new_context();
+
+        let result = ctx.eval(
+            "function count(n, acc) { if (n === 0) return acc; return count(n - 1, acc + 1); }
+             count(100000, 0)",
+        );
+        assert_eq!(result.ok().unwrap().get_int32(), 100000);
+
+        // result of call that is not in tail position must not leak as return value
+        let result = ctx.eval("function one() { return 1; } function f() { one(); } f()");
+        assert!(result.ok().unwrap().is_undefined());
+
+        // callee exceptions are still caught by caller
+        let result = ctx.eval(
+            "function thrower() { throw 1; }
+             function g() { try { return thrower(); } catch (e) { return 2; } }
+             g()",
+        );
+        assert_eq!(result.ok().unwrap().get_int32(), 2);
+    }
+
+    #[test]
+    fn test_call_arity() {
+        let (_vm, mut ctx) = new_context();
+
+        let result = ctx
+            .eval(
+                "function three(a, b, c) { var d = 4; return [a, b, c, d].join(); }
+                 function counter(a, b) {
+                     var n = 0; var inc = function () { return ++n; };
+                     inc(); return a + b + inc();
+                 }
+                 function rest(a, b, ...r) { return a + ':' + b + ':' + r.length; }
+                 function Point(x, y) { this.sum = x + (y || 0); }
+                 [1 + three(1, 2, 3), three(1), three(1, 2, 3, 4, 5), 10 * counter(1, 2), counter(1),
+                  rest(1), rest(1, 2, 3, 4), new Point(1, 2).sum + new Point(5).sum].join(' ')",
+            )
+            .ok()
+            .unwrap();
+        assert_eq!(
+            result.get_jsstring().as_str(),
+            "11,2,3,4 1,,,4 1,2,3,4 50 NaN 1:undefined:0 1:2:2 8"
+        );
+    }
+
+    #[test]
+    fn test_stack_slots() {
+        let (_vm, mut ctx) = new_context();
+
+        let script = "
+            function sum(n) { var s = 0; for (let i = 0; i < n; i++) { s = s + i } return s }
+            function counter() { var c = 0; return function () { c = c + 1; return c } }
+            function fib(n) { return n < 2 ? n : fib(n - 1) + fib(n - 2) }
+            var next = counter(); next(); next(); next(); next();
+            [sum(10), next(), fib(15), sum.apply(null, [5])].join()";
+        let result = ctx.eval(script).ok().unwrap();
+        assert_eq!(result.get_jsstring().as_str(), "45,5,610,10");
+
+        let listing = ctx.disassemble_script(script).ok().unwrap();
+        assert!(listing.contains("get_slot"));
+    }
+
+    #[test]
+    fn test_instanceof_has_instance() {
+        let (_vm, mut ctx) = new_context();
+
+        let result = ctx
+            .eval(
+                "function A() {}
+                 var Bound = A.bind(null);
+                 var Even = {};
+                 Object.defineProperty(Even, Symbol.hasInstance, {
+                     value: function (x) { return x % 2 == 0; }
+                 });
+                 var a = new A();
+                 [a instanceof A, a instanceof Bound, 2 instanceof Even, 3 instanceof Even,
+                  A[Symbol.hasInstance](a), ({}) instanceof A].join()",
+            )
+            .ok()
+            .unwrap();
+        assert_eq!(
+            result.to_string(ctx).ok().unwrap(),
+            "true,true,true,false,true,false"
+        );
+        let err = ctx.eval("({}) instanceof {}").err().unwrap();
+        assert!(err.to_string(ctx).ok().unwrap().contains("TypeError"));
+    }
+
+    #[test]
+    fn test_try_finally_completions() {
+        let (_vm, mut ctx) = new_context();
+
+        let result = ctx
+            .eval(
+                "var log = [];
+                 function ret() { try { return 'try'; } finally { log.push('ret'); } }
+                 function override() { try { return 1; } finally { return 'finally'; } }
+                 function nested() {
+                     try {
+                         try { return 'inner'; } finally { log.push('a'); }
+                     } finally { log.push('b'); }
+                 }
+                 function loops() {
+                     var n = 0;
+                     for (var i = 0; i < 5; i++) {
+                         try {
+                             if (i == 1) continue;
+                             if (i == 3) break;
+                             n++;
+                         } finally { n += 10; }
+                     }
+                     for (var k in { x: 1, y: 2 }) {
+                         try { return n; } finally { n++; }
+                     }
+                 }
+                 function rethrow() {
+                     try { try { throw 'e'; } finally { log.push('no catch'); } }
+                     catch (e) { return e; }
+                 }
+                 function fromCatch() {
+                     try {
+                         try { throw 1; } catch (e) { throw 2; } finally { log.push('catch'); }
+                     } catch (e) { return e; }
+                 }
+                 function handlers() {
+                     for (;;) { try { break; } catch (e) {} }
+                     throw 'outside';
+                 }
+                 var caught;
+                 try { handlers(); } catch (e) { caught = e; }
+                 [ret(), override(), nested(), loops(), rethrow(), fromCatch(), caught,
+                  log.join(' ')].join()",
+            )
+            .ok()
+            .unwrap();
+        assert_eq!(
+            result.to_string(ctx).ok().unwrap(),
+            "try,finally,inner,42,e,2,outside,ret a b no catch catch"
+        );
+    }
+
+    #[test]
+    fn test_computed_members() {
+        let (_vm, mut ctx) = new_context();
+
+        let result = ctx
+            .eval(
+                "var calls = 0; var o = { a: [1, 2, 3] };
+                 function obj() { calls++; return o }
+                 var k = 'a';
+                 obj()[k][1] += 10;
+                 obj()['a'][2]++;
+                 var s = Symbol('s'); o[s] = 5; o[s] *= 2;
+                 var i = 0; var arr = [0, 0]; arr[i++] += 1;
+                 var m = 'join';
+                 [o.a.join('-'), calls, o[s], arr.join('-'), i, arr['1'] === arr[1], ['x', 'y']['1'],
+                  [1, 2][m]('+')].join()",
+            )
+            .ok()
+            .unwrap();
+        assert_eq!(
+            result.get_jsstring().as_str(),
+            "1-12-4,2,10,1-0,1,true,y,1+2"
+        );
+    }
+
+    #[test]
+    fn test_in_operator() {
+        let (_vm, mut ctx) = new_context();
+
+        let result = ctx
+            .eval(
+                "var s = Symbol('s'); var hidden = Symbol('hidden');
+                 var base = { inherited: 1 }; base[s] = 2;
+                 var o = Object.create(base); o.own = 3;
+                 ['own' in o, 'inherited' in o, s in o, hidden in o, 'toString' in o, 'missing' in o,
+                  0 in [1], 1 in [1], 'length' in []].join()",
+            )
+            .ok()
+            .unwrap();
+        assert_eq!(
+            result.get_jsstring().as_str(),
+            "true,true,true,false,true,false,true,false,true"
+        );
+        assert!(ctx.eval("'x' in 1").is_err());
+    }
+}
@@ -36,6 +36,11 @@ impl CallFrame {
     pub unsafe fn at(&mut self, index: isize) -> &mut JsValue {
         &mut *self.sp.offset(index)
     }
+    /// Stack slot `index` of this frame, see [CodeBlock::slot_count].
+    #[inline(always)]
+    pub unsafe fn slot(&mut self, index: u32) -> &mut JsValue {
+        &mut *self.limit.add(index as _)
+    }
     #[inline(always)]
     pub unsafe fn push(&mut self, val: JsValue) {
         self.sp.write(val);
@@ -4,7 +4,17 @@
 use super::*;
 use crate::gc::cell::Trace;
 
-use std::{intrinsics::unlikely, ptr::null_mut};
+use crate::{
+    utils::{malloc, unlikely},
+    vm::thread::THREAD,
+};
+use std::ptr::null_mut;
+/// Interpreter stack of a single [Context](crate::vm::context::Context).
+///
+/// Operand stacks and stack slots of all frames live in one contiguous block of `JsValue`s, arguments of
+/// calls are passed in place on the operand stack of the caller. Call frames themselves are pooled: every
+/// nesting level owns a frame that is reused by the next call at the same depth, so a call neither allocates
+/// the frame nor its `try_stack` once the pool is warm.
 #[allow(dead_code)]
 pub struct Stack {
     mem: *mut u8,
@@ -12,16 +22,42 @@ pub struct Stack {
     pub(crate) cursor: *mut JsValue,
     end: *mut JsValue,
     pub(crate) current: *mut CallFrame,
+    /// `frames[i]` is the frame at depth `i`. Boxed so frames don't move when pool grows.
+    frames: Vec<Box<CallFrame>>,
+    depth: usize,
+    max_depth: usize,
+    /// Free bytes of the native stack calls require.
+    native_reserve: usize,
 }
 
+/// Position of a [Stack] returned by [Stack::mark].
+#[derive(Clone, Copy)]
+pub(crate) struct StackMark {
+    cursor: *mut JsValue,
+    current: *mut CallFrame,
+    depth: usize,
+}
+
+/// Default size of value stack in `JsValue`s.
 pub const STACK_SIZE: usize = 16 * 1024;
+/// Default maximal number of nested calls.
+pub const MAX_STACK_DEPTH: usize = 4 * 1024;
+/// Default free space of the native stack required to enter a call, in bytes.
+pub const STACK_RESERVE: usize = 256 * 1024;
 
 impl Stack {
     pub fn new() -> Self {
-        let mut map = unsafe { libc::calloc(1, STACK_SIZE * 8).cast::<u8>() };
+        Self::with_limits(STACK_SIZE, MAX_STACK_DEPTH, STACK_RESERVE)
+    }
+
+    /// Create stack that holds `size` values and at most `max_depth` frames. Calls fail when less than
+    /// `reserve` bytes of the native stack of the current thread are left.
+    pub fn with_limits(size: usize, max_depth: usize, reserve: usize) -> Self {
+        let map = unsafe { malloc::calloc(1, size * 8).cast::<u8>() };
+        assert!(!map.is_null(), "failed to allocate interpreter stack");
         unsafe {
             let mut scan = map.cast::<JsValue>();
-            let end = scan.add(STACK_SIZE);
+            let end = scan.add(size);
             while scan < end {
                 scan.write(JsValue::encode_undefined_value());
                 scan = scan.add(1);
@@ -29,12 +65,39 @@ impl Stack {
         }
         Self {
             start: map.cast(),
-            end: unsafe { map.cast::<JsValue>().add(STACK_SIZE) },
+            end: unsafe { map.cast::<JsValue>().add(size) },
             cursor: map.cast(),
             current: null_mut(),
             mem: map.cast(),
+            frames: Vec::new(),
+            depth: 0,
+            max_depth,
+            native_reserve: reserve,
         }
     }
+
+    /// Number of active frames.
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Maximal number of active frames, see [Options::max_stack_depth](crate::options::Options::max_stack_depth).
+    pub fn max_depth(&self) -> usize {
+        self.max_depth
+    }
+
+    /// Returns true if the native stack has less free space than the reserve. Native calls and entries
+    /// into the interpreter recurse on the native stack, nested frames of JS calls do not. Bounds are
+    /// those of the calling thread, which is not necessarily the thread that created this stack.
+    #[inline]
+    pub fn native_stack_exhausted(&self) -> bool {
+        let probe = 0u8;
+        let limit = THREAD.with(|thread| thread.bounds.bound as usize) + self.native_reserve;
+        (&probe as *const u8 as usize) < limit
+    }
+
+    /// Push new frame with `iloc_count` stack slots reserved below its operand stack. Slots are not
+    /// initialized. Returns `None` on stack overflow, including overflow of the native stack.
     pub fn new_frame(
         &mut self,
         iloc_count: u32,
@@ -42,37 +105,75 @@ impl Stack {
         env: GcPointer<Environment>,
     ) -> Option<*mut CallFrame> {
         unsafe {
-            if self.cursor.add(iloc_count as _) >= self.end {
+            if self.cursor.add(iloc_count as _) >= self.end
+                || self.depth >= self.max_depth
+                || self.native_stack_exhausted()
+            {
                 return None;
             }
+            if self.depth == self.frames.len() {
+                self.frames.push(Box::new(CallFrame {
+                    exit_on_return: true,
+                    ctor: false,
+                    prev: null_mut(),
+                    try_stack: vec![],
+                    env,
+                    this: JsValue::encode_empty_value(),
+                    sp: null_mut(),
+                    limit: null_mut(),
+                    code_block: None,
+                    callee: JsValue::encode_undefined_value(),
+                    ip: null_mut(),
+                }));
+            }
+            let frame = &mut *self.frames[self.depth];
+            self.depth += 1;
+            frame.exit_on_return = true;
+            frame.ctor = false;
+            frame.prev = self.current;
+            // keeps capacity of previous user of this frame.
+            frame.try_stack.clear();
+            frame.env = env;
+            frame.this = JsValue::encode_empty_value();
+            frame.sp = self.cursor.add(iloc_count as _);
+            frame.limit = self.cursor;
+            frame.code_block = None;
+            frame.callee = JsValue::encode_undefined_value();
+            frame.ip = null_mut();
 
-            let frame = Box::into_raw(Box::new(CallFrame {
-                exit_on_return: true,
-                ctor: false,
-                prev: self.current,
-                try_stack: vec![],
-                env,
-                this: JsValue::encode_empty_value(),
-                sp: self.cursor,
-                limit: self.cursor,
-                code_block: None,
-
-                callee: JsValue::encode_undefined_value(),
-                ip: null_mut(),
-            }));
             self.current = frame;
+            self.cursor = frame.sp;
 
             Some(frame)
         }
     }
 
-    pub fn pop_frame(&mut self) -> Option<Box<CallFrame>> {
+    /// Current position of the stack, see [Stack::unwind_to].
+    pub(crate) fn mark(&self) -> StackMark {
+        StackMark {
+            cursor: self.cursor,
+            current: self.current,
+            depth: self.depth,
+        }
+    }
+
+    /// Drop frames pushed after `mark` was taken. Used when a call was left without popping its frames.
+    pub(crate) fn unwind_to(&mut self, mark: StackMark) {
+        self.cursor = mark.cursor;
+        self.current = mark.current;
+        self.depth = mark.depth;
+    }
+
+    /// Pop current frame. Frame goes back to the pool and stays intact until the next call to
+    /// [Stack::new_frame].
+    pub fn pop_frame(&mut self) -> Option<&mut CallFrame> {
         if self.current.is_null() {
             return None;
         }
 
         unsafe {
-            let frame = Box::from_raw(self.current);
+            let frame = &mut *self.current;
+            self.depth -= 1;
             self.current = frame.prev;
             self.cursor = if frame.prev.is_null() {
                 self.start
@@ -132,7 +233,80 @@ impl Trace for Stack {
 impl Drop for Stack {
     fn drop(&mut self) {
         unsafe {
-            libc::free(self.mem.cast());
+            malloc::free(self.mem.cast());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Stack;
+    use crate::options::Options;
+    use crate::vm::tests::new_context_with;
+
+    #[test]
+    fn test_max_stack_depth() {
+        let (_vm, mut ctx) = new_context_with(Options::default().with_max_stack_depth(100));
+        assert_eq!(ctx.stack.max_depth(), 100);
+
+        ctx.eval("function depth(n) { return n == 0 ? 0 : 1 + depth(n - 1) }")
+            .ok()
+            .unwrap();
+        let result = ctx.eval("depth(50)").ok().unwrap();
+        assert_eq!(result.get_number(), 50.0);
+        assert!(ctx.eval("depth(200)").is_err());
+        assert_eq!(ctx.stack.depth(), 0);
+        // pooled frames are reused after overflow.
+        let result = ctx.eval("depth(90)").ok().unwrap();
+        assert_eq!(result.get_number(), 90.0);
+    }
+
+    #[test]
+    fn test_native_recursion() {
+        let options = Options::default()
+            .with_stack_size(1 << 20)
+            .with_max_stack_depth(1 << 20);
+        let (_vm, mut ctx) = new_context_with(options);
+
+        let result = ctx
+            .eval(
+                "function viaMap(n) { return [n].map(x => viaMap(x + 1))[0] }
+                 var o = { toString() { return '' + o } };
+                 var results = [];
+                 try { viaMap(0) } catch (e) { results.push(e instanceof RangeError) }
+                 try { '' + o } catch (e) { results.push(e instanceof RangeError) }
+                 results.push([1].map(x => x + 1)[0]);
+                 results.join()",
+            )
+            .ok()
+            .unwrap();
+        assert_eq!(result.get_jsstring().as_str(), "true,true,2");
+    }
+
+    #[test]
+    fn test_native_limit_of_current_thread() {
+        struct SendStack(Stack);
+        unsafe impl Send for SendStack {}
+
+        fn recurse(stack: &Stack, depth: usize) -> usize {
+            if stack.native_stack_exhausted() {
+                return depth;
+            }
+            let padding = [0u8; 1024];
+            recurse(stack, depth + 1) + unsafe { std::ptr::read_volatile(&padding[0]) } as usize
         }
+
+        // created on this thread, used on a thread with a stack of its own.
+        let stack = SendStack(Stack::with_limits(16, 16, 64 * 1024));
+        let depth = std::thread::Builder::new()
+            .stack_size(1 << 20)
+            .spawn(move || {
+                let stack = stack;
+                recurse(&stack.0, 0)
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+        assert!(depth > 100, "{}", depth);
     }
 }
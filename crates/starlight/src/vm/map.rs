@@ -4,8 +4,8 @@ use super::context::Context;
  * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
 use super::value::HashValueZero;
 use crate::prelude::*;
+use crate::utils::unlikely;
 use std::collections::HashMap;
-use std::intrinsics::*;
 pub type MapInternal = HashMap<HashValueZero, JsValue>;
 
 pub struct JsMap {
@@ -0,0 +1,120 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! Per-type heap accounting.
+//!
+//! Comet does not expose heap iteration yet so usage is computed from the same reachable graph that
+//! [heap snapshots](super::heap_snapshot) walk. Dead objects that were not swept yet are not counted.
+//!
+//! ```rust,ignore
+//! println!("{}", ctx.memory_usage());
+//! ```
+use super::context::Context;
+use crate::gc::cell::GcPointer;
+use std::collections::HashMap;
+use std::fmt;
+
+/// Memory used by all reachable cells of a single type.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TypeUsage {
+    /// Type name without module path, e.g. `JsObject` or `HashMap<u32, StoredSlot>`.
+    pub type_name: String,
+    pub count: usize,
+    /// Size of GC allocations plus memory that cells own outside of GC heap.
+    pub bytes: usize,
+}
+
+/// Breakdown of reachable heap memory by cell type.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryUsage {
+    pub total_bytes: usize,
+    pub total_count: usize,
+    /// Per-type usage sorted by `bytes`, largest first.
+    pub types: Vec<TypeUsage>,
+}
+
+impl MemoryUsage {
+    /// Find usage for type with `type_name` (without module path).
+    pub fn get(&self, type_name: &str) -> Option<&TypeUsage> {
+        self.types.iter().find(|usage| usage.type_name == type_name)
+    }
+}
+
+impl fmt::Display for MemoryUsage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{:>12} {:>10}  type", "bytes", "count")?;
+        for usage in self.types.iter() {
+            writeln!(
+                f,
+                "{:>12} {:>10}  {}",
+                usage.bytes, usage.count, usage.type_name
+            )?;
+        }
+        write!(
+            f,
+            "{:>12} {:>10}  (total)",
+            self.total_bytes, self.total_count
+        )
+    }
+}
+
+/// Strip module paths from every path in `name`: `alloc::vec::Vec<a::B>` becomes `Vec<B>`.
+fn short_type_name(name: &str) -> String {
+    let mut out = String::with_capacity(name.len());
+    let mut ident = String::new();
+    let mut chars = name.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == ':' && chars.peek() == Some(&':') {
+            chars.next();
+            ident.clear();
+        } else if c.is_alphanumeric() || c == '_' {
+            ident.push(c);
+        } else {
+            out.push_str(&ident);
+            ident.clear();
+            out.push(c);
+        }
+    }
+    out.push_str(&ident);
+    out
+}
+
+impl GcPointer<Context> {
+    /// Report memory used by cells reachable from this context grouped by their `GcCell::type_name`.
+    pub fn memory_usage(self) -> MemoryUsage {
+        let graph = self.walk_heap_graph();
+        let mut by_type = HashMap::<&'static str, (usize, usize)>::new();
+        for node in 0..graph.node_count() {
+            let type_name = graph.type_name(node);
+            if type_name.is_empty() {
+                continue;
+            }
+            let entry = by_type.entry(type_name).or_default();
+            entry.0 += 1;
+            entry.1 += graph.self_size(node);
+        }
+
+        let mut usage = MemoryUsage::default();
+        let mut merged = HashMap::<String, TypeUsage>::new();
+        for (type_name, (count, bytes)) in by_type {
+            usage.total_count += count;
+            usage.total_bytes += bytes;
+            let type_name = short_type_name(type_name);
+            let entry = merged
+                .entry(type_name.clone())
+                .or_insert_with(|| TypeUsage {
+                    type_name,
+                    count: 0,
+                    bytes: 0,
+                });
+            entry.count += count;
+            entry.bytes += bytes;
+        }
+        usage.types = merged.into_values().collect();
+        usage
+            .types
+            .sort_by(|a, b| b.bytes.cmp(&a.bytes).then(a.type_name.cmp(&b.type_name)));
+        usage
+    }
+}
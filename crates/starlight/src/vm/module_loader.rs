@@ -0,0 +1,350 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+//! Resolution and loading of ES modules.
+//!
+//! `import` goes through the [ModuleLoader] of the runtime, set with
+//! [VirtualMachine::with_module_loader](crate::vm::VirtualMachine::with_module_loader). Native modules
+//! registered with [Context::add_module](crate::vm::context::Context::add_module) are looked up by their
+//! name before the loader is asked. The default loader is [FsModuleLoader].
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+
+/// Hooks used to find, read and preprocess modules.
+pub trait ModuleLoader {
+    /// Resolve `specifier` of an `import` to the key of a module. `referrer` is the directory of the importing
+    /// module or an empty string for code that is not in a file. Modules are cached by key and the directory
+    /// of a key is the referrer of its imports, so keys should look like paths.
+    fn resolve(&self, specifier: &str, referrer: &str) -> Result<String, String>;
+    /// Resolve `specifier` of a CommonJS `require`, see [commonjs](crate::jsrt::commonjs). Same as
+    /// [ModuleLoader::resolve] by default.
+    fn resolve_require(&self, specifier: &str, referrer: &str) -> Result<String, String> {
+        self.resolve(specifier, referrer)
+    }
+    /// Read source of module with `key` returned by [ModuleLoader::resolve].
+    fn load(&self, key: &str) -> Result<String, String>;
+    /// Rewrite source of a module before it is compiled, e.g. to strip types or replace macros.
+    fn transform(&self, _key: &str, source: String) -> Result<String, String> {
+        Ok(source)
+    }
+}
+
+/// Loader resolving imports against the file system in the way of Node.js:
+/// - `./`, `../` and absolute specifiers are files or directories. Files are tried as is and with
+///   [FsModuleLoader::extensions].
+/// - Directories load `main` of their `package.json` or `index` with one of the extensions.
+/// - Other specifiers are packages searched in `node_modules` of the referrer and its ancestors. `exports` of
+///   `package.json` takes precedence over `main` and only exported subpaths can be imported.
+pub struct FsModuleLoader {
+    /// Extensions appended to specifiers without one, in order of preference.
+    pub extensions: Vec<String>,
+    /// Conditions matched against conditional `exports` of packages, in order of preference.
+    pub conditions: Vec<String>,
+    /// Conditions used instead of [FsModuleLoader::conditions] for `require`.
+    pub require_conditions: Vec<String>,
+}
+
+impl Default for FsModuleLoader {
+    fn default() -> Self {
+        Self {
+            extensions: vec![".js".to_owned(), ".mjs".to_owned()],
+            conditions: vec!["import".to_owned(), "default".to_owned()],
+            require_conditions: vec!["require".to_owned(), "default".to_owned()],
+        }
+    }
+}
+
+impl FsModuleLoader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_extensions(mut self, extensions: &[&str]) -> Self {
+        self.extensions = extensions.iter().map(|ext| ext.to_string()).collect();
+        self
+    }
+
+    pub fn with_conditions(mut self, conditions: &[&str]) -> Self {
+        self.conditions = conditions.iter().map(|cond| cond.to_string()).collect();
+        self
+    }
+
+    fn resolve_file(&self, path: &Path) -> Option<PathBuf> {
+        if path.is_file() {
+            return Some(path.to_path_buf());
+        }
+        self.extensions.iter().find_map(|ext| {
+            let mut candidate = path.as_os_str().to_owned();
+            candidate.push(ext);
+            let candidate = PathBuf::from(candidate);
+            if candidate.is_file() {
+                Some(candidate)
+            } else {
+                None
+            }
+        })
+    }
+
+    fn resolve_directory(&self, dir: &Path) -> Option<PathBuf> {
+        if !dir.is_dir() {
+            return None;
+        }
+        let main = read_package(dir)
+            .and_then(|package| package.get("main")?.as_str().map(|main| dir.join(main)))
+            .and_then(|main| self.resolve_path(&main));
+        main.or_else(|| self.resolve_file(&dir.join("index")))
+    }
+
+    fn resolve_path(&self, path: &Path) -> Option<PathBuf> {
+        self.resolve_file(path)
+            .or_else(|| self.resolve_directory(path))
+    }
+
+    fn resolve_package(&self, base: &Path, specifier: &str) -> Option<PathBuf> {
+        let (name, subpath) = split_package_specifier(specifier);
+        for dir in base.ancestors() {
+            let package_dir = dir.join("node_modules").join(name);
+            if !package_dir.is_dir() {
+                continue;
+            }
+            if let Some(exports) =
+                read_package(&package_dir).and_then(|package| package.get("exports").cloned())
+            {
+                let target = self.exports_target(&exports, &subpath)?;
+                return self.resolve_file(&package_dir.join(target));
+            }
+            return if subpath == "." {
+                self.resolve_directory(&package_dir)
+            } else {
+                self.resolve_path(&package_dir.join(&subpath[2..]))
+            };
+        }
+        None
+    }
+
+    /// Target of `subpath` in `exports` field of `package.json`.
+    fn exports_target(&self, exports: &Value, subpath: &str) -> Option<String> {
+        match exports {
+            Value::Object(map) if map.keys().all(|key| key.starts_with('.')) => {
+                if let Some(target) = map.get(subpath) {
+                    return self.condition_target(target).map(str::to_owned);
+                }
+                // patterns such as "./features/*": "./src/features/*.js"
+                map.iter().find_map(|(pattern, target)| {
+                    let (prefix, suffix) = pattern.split_once('*')?;
+                    let matched = subpath.strip_prefix(prefix)?.strip_suffix(suffix)?;
+                    Some(self.condition_target(target)?.replace('*', matched))
+                })
+            }
+            _ if subpath == "." => self.condition_target(exports).map(str::to_owned),
+            _ => None,
+        }
+    }
+
+    fn condition_target<'a>(&self, target: &'a Value) -> Option<&'a str> {
+        match target {
+            Value::String(target) => Some(target),
+            Value::Object(map) => self
+                .conditions
+                .iter()
+                .find_map(|condition| self.condition_target(map.get(condition)?)),
+            Value::Array(targets) => targets
+                .iter()
+                .find_map(|target| self.condition_target(target)),
+            _ => None,
+        }
+    }
+}
+
+impl ModuleLoader for FsModuleLoader {
+    fn resolve(&self, specifier: &str, referrer: &str) -> Result<String, String> {
+        let base = if referrer.is_empty() {
+            std::env::current_dir().map_err(|e| e.to_string())?
+        } else {
+            PathBuf::from(referrer)
+        };
+        let relative = specifier == "."
+            || specifier == ".."
+            || specifier.starts_with("./")
+            || specifier.starts_with("../");
+        let path = if relative || Path::new(specifier).is_absolute() {
+            self.resolve_path(&base.join(specifier))
+        } else {
+            self.resolve_package(&base, specifier)
+        };
+        match path.map(|path| path.canonicalize()) {
+            Some(Ok(path)) => Ok(path.to_string_lossy().into_owned()),
+            Some(Err(e)) => Err(e.to_string()),
+            None => Err(format!(
+                "cannot resolve '{}' from '{}'",
+                specifier,
+                base.display()
+            )),
+        }
+    }
+
+    /// Like [FsModuleLoader::resolve] with [FsModuleLoader::require_conditions], `.cjs` and `.json` files are
+    /// found without extension too.
+    fn resolve_require(&self, specifier: &str, referrer: &str) -> Result<String, String> {
+        let mut extensions = self.extensions.clone();
+        for ext in [".cjs", ".json"].iter() {
+            if !extensions.iter().any(|x| x == ext) {
+                extensions.push(ext.to_string());
+            }
+        }
+        let loader = FsModuleLoader {
+            extensions,
+            conditions: self.require_conditions.clone(),
+            require_conditions: self.require_conditions.clone(),
+        };
+        loader.resolve(specifier, referrer)
+    }
+
+    fn load(&self, key: &str) -> Result<String, String> {
+        std::fs::read_to_string(key).map_err(|e| e.to_string())
+    }
+}
+
+/// Split `@scope/name/sub/path` into `@scope/name` and `./sub/path`.
+fn split_package_specifier(specifier: &str) -> (&str, String) {
+    let mut separators = specifier.match_indices('/').map(|(i, _)| i);
+    let end = if specifier.starts_with('@') {
+        separators.nth(1)
+    } else {
+        separators.next()
+    };
+    match end {
+        Some(end) => (&specifier[..end], format!(".{}", &specifier[end..])),
+        None => (specifier, ".".to_owned()),
+    }
+}
+
+pub(crate) fn read_package(dir: &Path) -> Option<Value> {
+    let source = std::fs::read_to_string(dir.join("package.json")).ok()?;
+    serde_json::from_str(&source).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{options::Options, prelude::*, vm::tests::new_context, Platform};
+    use std::{collections::HashMap, fs};
+
+    fn write(root: &Path, path: &str, source: &str) {
+        let path = root.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, source).unwrap();
+    }
+
+    fn global_string(ctx: GcPointer<crate::vm::context::Context>, name: &str) -> String {
+        let value = ctx
+            .global_object()
+            .get(ctx, name.intern())
+            .unwrap_or_else(|_| panic!());
+        value.to_string(ctx).unwrap_or_else(|_| panic!())
+    }
+
+    #[test]
+    fn test_fs_loader() {
+        let root = std::env::temp_dir().join(format!("starlight-loader-{}", std::process::id()));
+        write(&root, "lib/index.js", "export const lib = 'lib';");
+        write(
+            &root,
+            "node_modules/pkg/package.json",
+            r#"{ "main": "./cjs.js", "exports": { ".": { "require": "./cjs.js", "import": "./esm.js" }, "./features/*": "./src/*.js" } }"#,
+        );
+        write(&root, "node_modules/pkg/esm.js", "export default 'esm';");
+        write(&root, "node_modules/pkg/src/a.js", "export const a = 'a';");
+        write(
+            &root,
+            "node_modules/@scope/old/package.json",
+            r#"{ "main": "main" }"#,
+        );
+        write(
+            &root,
+            "node_modules/@scope/old/main.js",
+            "import { lib } from '../../../lib'; export const old = 'old+' + lib;",
+        );
+        write(
+            &root,
+            "main.js",
+            "import { lib } from './lib';
+             import pkg from 'pkg';
+             import { a } from 'pkg/features/a';
+             import { old } from '@scope/old';
+             globalThis.result = [lib, pkg, a, old].join(',');",
+        );
+
+        let loader = FsModuleLoader::new();
+        let referrer = root.to_string_lossy();
+        assert!(loader.resolve("./missing", &referrer).is_err());
+        assert!(loader.resolve("pkg/esm.js", &referrer).is_err());
+
+        let (_vm, mut ctx) = new_context();
+        let main = root.join("main.js");
+        let source = fs::read_to_string(&main).unwrap();
+        ctx.evalm(Some(main.to_str().unwrap()), false, &source)
+            .unwrap_or_else(|_| panic!());
+        assert_eq!(global_string(ctx, "result"), "lib,esm,a,old+lib");
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// Modules kept in memory, `.ts` files have type annotations stripped by a naive transform.
+    struct MemoryLoader(HashMap<&'static str, &'static str>);
+
+    impl ModuleLoader for MemoryLoader {
+        fn resolve(&self, specifier: &str, _referrer: &str) -> Result<String, String> {
+            let key = format!("/virtual/{}", specifier.trim_start_matches("./"));
+            if self.0.contains_key(key.as_str()) {
+                Ok(key)
+            } else {
+                Err("no such module".to_owned())
+            }
+        }
+
+        fn load(&self, key: &str) -> Result<String, String> {
+            Ok(self.0[key].to_owned())
+        }
+
+        fn transform(&self, key: &str, source: String) -> Result<String, String> {
+            if key.ends_with(".ts") {
+                Ok(source.replace(": number", ""))
+            } else {
+                Ok(source)
+            }
+        }
+    }
+
+    #[test]
+    fn test_custom_loader() {
+        Platform::initialize();
+        let mut modules = HashMap::new();
+        modules.insert(
+            "/virtual/math.ts",
+            "export function double(x: number) { return x * 2; }",
+        );
+        modules.insert(
+            "/virtual/a.js",
+            "import { b } from './b.js'; export const a = () => 'a' + b();",
+        );
+        modules.insert(
+            "/virtual/b.js",
+            "import { a } from './a.js'; export const b = () => 'b';",
+        );
+        let mut vm = Platform::new_runtime(Options::default(), None)
+            .with_module_loader(MemoryLoader(modules));
+        let ctx = vm.new_context();
+        ctx.evalm(
+            None,
+            false,
+            "import { double } from './math.ts';
+             import { a } from './a.js';
+             globalThis.result = double(21) + a();",
+        )
+        .unwrap_or_else(|_| panic!());
+        assert_eq!(global_string(ctx, "result"), "42ab");
+        assert!(ctx
+            .evalm(None, false, "import x from './missing.js';")
+            .is_err());
+    }
+}
@@ -2,40 +2,171 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
 use crate::{gc::cell::GcCell, prelude::*};
+use std::{collections::HashSet, rc::Rc};
 
-use super::context::Context;
+use super::{context::Context, object::PropertyFilter, structure_chain::StructureChain};
+
+/// Iterator of for-in loops. Keys are collected when the loop starts: own keys of the object in
+/// [own_property_names](GcPointer::<JsObject>::own_property_names) order followed by keys of its
+/// prototypes that are not shadowed. Keys deleted during the loop are skipped, keys added are not visited.
 pub struct NativeIterator {
-    names: Vec<Symbol>,
+    names: Rc<[Symbol]>,
     at: u32,
+    /// Enumerated object, `None` for strings whose keys can not change.
+    object: Option<GcPointer<JsObject>>,
+}
+
+/// Keys of for-in enumeration stored on the [Structure] of enumerated object. Keys depend on the structures
+/// of the whole prototype chain so the cache remembers them and is used only while they are the same.
+pub struct EnumerationCache {
+    chain: GcPointer<StructureChain>,
+    names: Rc<[Symbol]>,
+}
+
+impl Trace for EnumerationCache {
+    fn trace(&self, visitor: &mut Visitor) {
+        self.chain.trace(visitor);
+    }
+}
+
+/// Object whose keys are described by its structure alone: ordinary object without indexed properties.
+fn has_shaped_keys(obj: &GcPointer<JsObject>) -> bool {
+    std::ptr::eq(obj.class, JsObject::class())
+        && obj.indexed.length() == 0
+        && obj.indexed.map.is_none()
+}
+
+impl EnumerationCache {
+    fn lookup(ctx: GcPointer<Context>, obj: &GcPointer<JsObject>) -> Option<Rc<[Symbol]>> {
+        let mut structure = obj.structure;
+        let cache = structure.enum_cache.as_ref()?;
+        if !has_shaped_keys(obj) {
+            return None;
+        }
+        let mut prototype = structure.stored_prototype(ctx, obj);
+        for cached in cache.chain.vector.iter() {
+            if !prototype.is_jsobject() {
+                return None;
+            }
+            let proto = prototype.get_jsobject();
+            if !GcPointer::ptr_eq(&proto.structure, cached) || !has_shaped_keys(&proto) {
+                return None;
+            }
+            prototype = proto.structure.stored_prototype(ctx, &proto);
+        }
+        if prototype.is_jsobject() {
+            return None;
+        }
+        Some(cache.names.clone())
+    }
+
+    fn store(ctx: GcPointer<Context>, obj: &GcPointer<JsObject>, names: &Rc<[Symbol]>) {
+        let mut structure = obj.structure;
+        if !has_shaped_keys(obj) || !structure.changes_on_mutation() {
+            return;
+        }
+        let prototype = structure.stored_prototype(ctx, obj);
+        let head = if prototype.is_jsobject() {
+            Some(prototype.get_jsobject())
+        } else {
+            None
+        };
+        let mut current = head;
+        while let Some(proto) = current {
+            let mut proto_structure = proto.structure;
+            if !has_shaped_keys(&proto) {
+                return;
+            }
+            if !proto_structure.changes_on_mutation() {
+                // same rule as for inline caches, see `normalize_prototype_chain`.
+                if proto_structure.has_been_flattened_before {
+                    return;
+                }
+                proto_structure.flatten_dictionary_structure(ctx, &proto);
+            }
+            let next = proto_structure.stored_prototype(ctx, &proto);
+            current = if next.is_jsobject() {
+                Some(next.get_jsobject())
+            } else {
+                None
+            };
+        }
+        structure.enum_cache = Some(EnumerationCache {
+            chain: StructureChain::create(ctx, head),
+            names: names.clone(),
+        });
+    }
+}
+
+/// Enumerable string keys of `obj` and its prototypes. A key is reported once, where it is found first,
+/// and not at all if that property is not enumerable.
+fn collect_keys(ctx: GcPointer<Context>, obj: GcPointer<JsObject>) -> Vec<Symbol> {
+    let mut names = vec![];
+    let mut visited = HashSet::new();
+    let mut current = Some(obj);
+    while let Some(mut object) = current {
+        let enumerable = object
+            .own_property_names(ctx, PropertyFilter::Enumerable)
+            .into_iter()
+            .collect::<HashSet<_>>();
+        for name in object.own_property_names(ctx, PropertyFilter::StringKeys) {
+            if visited.insert(name) && enumerable.contains(&name) {
+                names.push(name);
+            }
+        }
+        current = object.prototype().copied();
+    }
+    names
 }
 
 impl NativeIterator {
     #[allow(clippy::should_implement_trait)]
-    pub fn next(&mut self) -> Option<Symbol> {
-        if self.at != self.names.len() as u32 {
+    pub fn next(&mut self, ctx: GcPointer<Context>) -> Option<Symbol> {
+        while self.at != self.names.len() as u32 {
             let result = self.names[self.at as usize];
             self.at += 1;
-            return Some(result);
+            match self.object {
+                Some(mut object) if !object.has_property(ctx, result) => continue,
+                _ => return Some(result),
+            }
         }
         None
     }
 
     pub fn new(mut ctx: GcPointer<Context>, obj: GcPointer<dyn GcCell>) -> GcPointer<Self> {
         let mut names = vec![];
-        if let Some(mut obj) = obj.downcast::<JsObject>() {
-            obj.get_property_names(
-                ctx,
-                &mut |name, _| names.push(name),
-                EnumerationMode::Default,
-            );
+        if let Some(obj) = obj.downcast::<JsObject>() {
+            let names = match EnumerationCache::lookup(ctx, &obj) {
+                Some(names) => names,
+                None => {
+                    let names: Rc<[Symbol]> = collect_keys(ctx, obj).into();
+                    EnumerationCache::store(ctx, &obj, &names);
+                    names
+                }
+            };
+            return ctx.heap().allocate(Self {
+                names,
+                at: 0,
+                object: Some(obj),
+            });
         } else if let Some(string) = obj.downcast::<JsString>() {
-            for i in 0..string.as_str().len() {
-                names.push(Symbol::Index(i as _));
+            for i in 0..string.utf16_len() {
+                names.push(Symbol::Index(i));
             }
         } else {
             todo!()
         }
-        ctx.heap().allocate(Self { names, at: 0 })
+        ctx.heap().allocate(Self {
+            names: names.into(),
+            at: 0,
+            object: None,
+        })
+    }
+
+    /// Returns true if `self` and `other` enumerate the same shared key list.
+    #[cfg(test)]
+    pub(crate) fn shares_keys_with(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.names, &other.names)
     }
 }
 
@@ -46,6 +177,70 @@ impl GcCell for NativeIterator {
 }
 
 impl Trace for NativeIterator {
-    fn trace(&self, _visitor: &mut Visitor) {}
+    fn trace(&self, visitor: &mut Visitor) {
+        self.object.trace(visitor);
+    }
 }
 impl Finalize<NativeIterator> for NativeIterator {}
+
+#[cfg(test)]
+mod tests {
+    use crate::vm::symbol_table::Internable;
+    use crate::vm::tests::new_context;
+    use crate::vm::value::JsValue;
+
+    #[test]
+    fn test_forin_enumeration_cache() {
+        use crate::vm::native_iterator::NativeIterator;
+        let (_vm, mut ctx) = new_context();
+
+        let objects = ctx
+            .eval("var a = {x: 1, y: 2}, b = {x: 3, y: 4}; [a, b]")
+            .ok()
+            .unwrap()
+            .get_jsobject();
+        let a = objects.indexed.vector.at(0).get_jsobject();
+        let mut b = objects.indexed.vector.at(1).get_jsobject();
+        let first = NativeIterator::new(ctx, a.as_dyn());
+        let second = NativeIterator::new(ctx, b.as_dyn());
+        assert!(first.shares_keys_with(&second));
+
+        // transition to another structure does not see keys of the old one.
+        b.put(ctx, "z".intern(), JsValue::new(5), false)
+            .ok()
+            .unwrap();
+        let third = NativeIterator::new(ctx, b.as_dyn());
+        assert!(!first.shares_keys_with(&third));
+
+        // nor does a change in the prototype chain.
+        let result = ctx
+            .eval("var keys = ''; for (var k in a) keys += k; Object.prototype.w = 0; for (var k in a) keys += k; keys")
+            .ok()
+            .unwrap();
+        assert_eq!(result.get_jsstring().as_str(), "xyxyw");
+    }
+
+    #[test]
+    fn test_forin_mutation() {
+        let (_vm, mut ctx) = new_context();
+
+        let result = ctx
+            .eval(
+                "var log = [];
+                 var o = { a: 1, b: 2, c: 3 };
+                 for (var k in o) { log.push(k); if (k == 'a') { delete o.b; o.d = 4; } }
+                 var child = Object.create({ x: 1, y: 2, z: 3 });
+                 child.y = 3;
+                 Object.defineProperty(child, 'x', { value: 0, enumerable: false });
+                 for (var k in child) log.push(k);
+                 var arr = [1, 2, 3];
+                 arr.extra = true;
+                 for (var k in arr) { log.push(k); arr.length = 1; }
+                 for (var k in 'ab\u{1F600}') log.push(k);
+                 log.join()",
+            )
+            .ok()
+            .unwrap();
+        assert_eq!(result.get_jsstring().as_str(), "a,c,y,z,0,extra,0,1,2,3");
+    }
+}
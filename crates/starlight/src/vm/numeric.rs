@@ -0,0 +1,287 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+//! String to number conversions shared by `ToNumber`, `parseInt` and `parseFloat`.
+//!
+//! The three differ in what they accept: `ToNumber` requires the whole string (modulo surrounding
+//! whitespace) to be a numeric literal and understands `0x`/`0o`/`0b` prefixes, `parseFloat` takes the
+//! longest decimal prefix, and `parseInt` takes the longest run of digits in the requested radix.
+
+/// Helper function to check if a `char` is trimmable.
+#[inline]
+pub fn is_trimmable_whitespace(c: char) -> bool {
+    // The rust implementation of `trim` does not regard the same characters whitespace as ecma standard does
+    //
+    // Rust uses \p{White_Space} by default, which also includes:
+    // `\u{0085}' (next line)
+    // And does not include:
+    // '\u{FEFF}' (zero width non-breaking space)
+    // Explicit whitespace: https://tc39.es/ecma262/#sec-white-space
+    matches!(
+        c,
+        '\u{0009}' | '\u{000B}' | '\u{000C}' | '\u{0020}' | '\u{00A0}' | '\u{FEFF}' |
+    // Unicode Space_Separator category
+    '\u{1680}' | '\u{2000}'
+            ..='\u{200A}' | '\u{202F}' | '\u{205F}' | '\u{3000}' |
+    // Line terminators: https://tc39.es/ecma262/#sec-line-terminators
+    '\u{000A}' | '\u{000D}' | '\u{2028}' | '\u{2029}'
+    )
+}
+
+/// StringToNumber: https://tc39.es/ecma262/#sec-stringtonumber
+pub fn string_to_number(s: &str) -> f64 {
+    let s = s.trim_matches(is_trimmable_whitespace);
+    if s.is_empty() {
+        return 0.0;
+    }
+    // NonDecimalIntegerLiteral does not allow a sign.
+    let bytes = s.as_bytes();
+    if bytes.len() > 2 && bytes[0] == b'0' {
+        let radix = match bytes[1] {
+            b'x' | b'X' => 16,
+            b'o' | b'O' => 8,
+            b'b' | b'B' => 2,
+            _ => 0,
+        };
+        if radix != 0 {
+            let digits = &s[2..];
+            if digits.len() != digit_run_len(digits, radix) {
+                return f64::NAN;
+            }
+            return digits_to_number(digits, radix);
+        }
+    }
+
+    let (negative, unsigned) = strip_sign(s);
+    let value = if unsigned == "Infinity" {
+        f64::INFINITY
+    } else {
+        let len = decimal_literal_len(unsigned);
+        if len == 0 || len != unsigned.len() {
+            return f64::NAN;
+        }
+        parse_decimal(unsigned)
+    };
+    if negative {
+        -value
+    } else {
+        value
+    }
+}
+
+/// `parseFloat` applied to an already stringified argument:
+/// https://tc39.es/ecma262/#sec-parsefloat-string
+pub fn parse_float(s: &str) -> f64 {
+    let (negative, unsigned) = strip_sign(s.trim_start_matches(is_trimmable_whitespace));
+    let value = if unsigned.starts_with("Infinity") {
+        f64::INFINITY
+    } else {
+        let len = decimal_literal_len(unsigned);
+        if len == 0 {
+            return f64::NAN;
+        }
+        parse_decimal(&unsigned[..len])
+    };
+    if negative {
+        -value
+    } else {
+        value
+    }
+}
+
+/// `parseInt` applied to an already stringified argument and `ToInt32(radix)`:
+/// https://tc39.es/ecma262/#sec-parseint-string-radix
+pub fn parse_int(s: &str, radix: i32) -> f64 {
+    let (negative, mut s) = strip_sign(s.trim_start_matches(is_trimmable_whitespace));
+    let mut radix = radix as u32;
+    let mut strip_prefix = true;
+    if radix != 0 {
+        if !(2..=36).contains(&radix) {
+            return f64::NAN;
+        }
+        if radix != 16 {
+            strip_prefix = false;
+        }
+    } else {
+        radix = 10;
+    }
+    if strip_prefix && (s.starts_with("0x") || s.starts_with("0X")) {
+        s = &s[2..];
+        radix = 16;
+    }
+
+    let end = digit_run_len(s, radix);
+    if end == 0 {
+        return f64::NAN;
+    }
+    let digits = &s[..end];
+    // Decimal digits go through the correctly rounded float parser so that integers with more than
+    // 15 significant digits come out the same as their literal in source code.
+    let value = if radix == 10 {
+        parse_decimal(digits)
+    } else {
+        digits_to_number(digits, radix)
+    };
+    if negative {
+        -value
+    } else {
+        value
+    }
+}
+
+fn strip_sign(s: &str) -> (bool, &str) {
+    if let Some(rest) = s.strip_prefix('-') {
+        (true, rest)
+    } else {
+        (false, s.strip_prefix('+').unwrap_or(s))
+    }
+}
+
+/// Length of the longest run of ASCII digits valid in `radix` at the start of `s`.
+fn digit_run_len(s: &str, radix: u32) -> usize {
+    s.bytes()
+        .take_while(|&b| (b as char).is_digit(radix))
+        .count()
+}
+
+fn digits_to_number(digits: &str, radix: u32) -> f64 {
+    if let Ok(value) = u64::from_str_radix(digits, radix) {
+        return value as f64;
+    }
+    digits.bytes().fold(0.0, |acc, b| {
+        acc * radix as f64 + (b as char).to_digit(radix).unwrap() as f64
+    })
+}
+
+/// Length of the longest prefix of `s` matching StrUnsignedDecimalLiteral without `Infinity`:
+/// `digits [. digits] [e [sign] digits]` or `. digits [e [sign] digits]`.
+fn decimal_literal_len(s: &str) -> usize {
+    let bytes = s.as_bytes();
+    let mut pos = digit_run_len(s, 10);
+    let mut mantissa_digits = pos;
+    if bytes.get(pos) == Some(&b'.') {
+        let fraction = digit_run_len(&s[pos + 1..], 10);
+        if mantissa_digits == 0 && fraction == 0 {
+            return 0;
+        }
+        mantissa_digits += fraction;
+        pos += 1 + fraction;
+    }
+    if mantissa_digits == 0 {
+        return 0;
+    }
+    if matches!(bytes.get(pos), Some(b'e') | Some(b'E')) {
+        let mut exp = pos + 1;
+        if matches!(bytes.get(exp), Some(b'+') | Some(b'-')) {
+            exp += 1;
+        }
+        let exp_digits = digit_run_len(&s[exp..], 10);
+        if exp_digits != 0 {
+            pos = exp + exp_digits;
+        }
+    }
+    pos
+}
+
+/// Parse a string already validated by `decimal_literal_len`.
+fn parse_decimal(s: &str) -> f64 {
+    s.parse::<f64>().unwrap_or(f64::NAN)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn same(a: f64, b: f64) -> bool {
+        (a.is_nan() && b.is_nan()) || (a == b && a.is_sign_negative() == b.is_sign_negative())
+    }
+
+    #[test]
+    fn test_string_to_number() {
+        let cases = [
+            ("", 0.0),
+            (" \n\t ", 0.0),
+            ("  42  ", 42.0),
+            ("-0", -0.0),
+            ("+1.5e3", 1500.0),
+            (".5", 0.5),
+            ("5.", 5.0),
+            ("0x1F", 31.0),
+            ("0o17", 15.0),
+            ("0B101", 5.0),
+            ("-Infinity", f64::NEG_INFINITY),
+            ("-0x10", f64::NAN),
+            ("0x", f64::NAN),
+            ("1e", f64::NAN),
+            ("12px", f64::NAN),
+            ("infinity", f64::NAN),
+            ("NaN", f64::NAN),
+            (".", f64::NAN),
+            ("1_000", f64::NAN),
+        ];
+        for &(input, expected) in cases.iter() {
+            let actual = string_to_number(input);
+            assert!(
+                same(actual, expected),
+                "{:?}: {} != {}",
+                input,
+                actual,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_float() {
+        let cases = [
+            ("  3.14abc", 3.14),
+            ("-.5e-1x", -0.05),
+            ("1e", 1.0),
+            ("1e+", 1.0),
+            ("-0", -0.0),
+            ("Infinityx", f64::INFINITY),
+            ("0x10", 0.0),
+            ("e5", f64::NAN),
+            ("", f64::NAN),
+        ];
+        for &(input, expected) in cases.iter() {
+            let actual = parse_float(input);
+            assert!(
+                same(actual, expected),
+                "{:?}: {} != {}",
+                input,
+                actual,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_int() {
+        let cases = [
+            ("  42px", 0, 42.0),
+            ("-0", 0, -0.0),
+            ("0x1f", 0, 31.0),
+            ("-0XfF", 16, -255.0),
+            ("0x10", 10, 0.0),
+            ("z", 36, 35.0),
+            ("12", 2, 1.0),
+            ("12", 1, f64::NAN),
+            ("12", 37, f64::NAN),
+            ("1.9", 0, 1.0),
+            ("Infinity", 0, f64::NAN),
+            ("9007199254740993", 0, 9007199254740992.0),
+            ("123456789012345678901234567890", 0, 1.2345678901234568e29),
+        ];
+        for &(input, radix, expected) in cases.iter() {
+            let actual = parse_int(input, radix);
+            assert!(
+                same(actual, expected),
+                "{:?}: {} != {}",
+                input,
+                actual,
+                expected
+            );
+        }
+    }
+}
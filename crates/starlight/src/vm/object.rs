@@ -14,21 +14,23 @@ use super::{
     property_descriptor::{DataDescriptor, PropertyDescriptor},
     slot::*,
     string::*,
-    structure::Structure,
+    structure::{Structure, DICTIONARY_STABLE_READS},
     symbol_table::{Internable, Symbol},
     value::JsValue,
     Context,
 };
 use super::{indexed_elements::MAX_VECTOR_SIZE, method_table::*};
 use crate::prelude::*;
+use crate::utils::{likely, unlikely};
 use crate::{gc::cell::GcPointerBase, vm::promise::JsPromise};
 use crate::{
     gc::cell::{GcCell, GcPointer, Trace, Visitor},
     JsTryFrom,
 };
 use std::{
-    collections::hash_map::Entry,
-    intrinsics::{likely, transmute, unlikely},
+    cell::RefCell,
+    collections::{hash_map::Entry, HashMap},
+    intrinsics::transmute,
     marker::PhantomData,
     mem::{size_of, ManuallyDrop},
     ops::{Deref, DerefMut},
@@ -39,6 +41,38 @@ pub enum EnumerationMode {
     Default,
     IncludeNotEnumerable,
 }
+
+/// Own properties reported by [JsObject::own_property_names].
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum PropertyFilter {
+    /// Enumerable properties with string keys, like `Object.keys`.
+    Enumerable,
+    /// Properties with string keys, like `Object.getOwnPropertyNames`.
+    StringKeys,
+    /// Every own property including symbol keyed ones, like `Reflect.ownKeys`.
+    All,
+}
+
+/// Iterator over own properties of an object returned by [JsObject::own_properties].
+pub struct OwnProperties {
+    ctx: GcPointer<Context>,
+    object: GcPointer<JsObject>,
+    names: std::vec::IntoIter<Symbol>,
+}
+
+impl Iterator for OwnProperties {
+    type Item = (Symbol, PropertyDescriptor);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for name in &mut self.names {
+            // properties deleted since the iterator was created are skipped.
+            if let Some(desc) = self.object.get_own_property(self.ctx, name) {
+                return Some((name, desc));
+            }
+        }
+        None
+    }
+}
 #[derive(Copy, Clone, PartialEq, Eq)]
 pub enum JsHint {
     String,
@@ -48,6 +82,16 @@ pub enum JsHint {
 pub const OBJ_FLAG_TUPLE: u32 = 0x4;
 pub const OBJ_FLAG_CALLABLE: u32 = 0x2;
 pub const OBJ_FLAG_EXTENSIBLE: u32 = 0x1;
+pub const OBJ_FLAG_HAS_FINALIZER: u32 = 0x8;
+
+/// Callback invoked after object is collected. Object is dead at this point, finalizer
+/// must not capture pointers to it or to other objects that might be collected in the same cycle.
+pub type ObjectFinalizer = Box<dyn FnOnce()>;
+
+thread_local! {
+    /// Finalizers registered with [GcPointer::<JsObject>::add_finalizer] keyed by object address.
+    static FINALIZERS: RefCell<HashMap<usize, Vec<ObjectFinalizer>>> = RefCell::new(HashMap::new());
+}
 pub type FixedStorage = GcPointer<ArrayStorage>;
 #[repr(u8)]
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
@@ -218,6 +262,16 @@ impl Drop for JsObject {
                 std::mem::transmute(self as *mut Self as usize - size_of::<GcPointerBase>())
             });
         }
+        if (self.flags & OBJ_FLAG_HAS_FINALIZER) != 0 {
+            let key = self as *const Self as usize;
+            let finalizers = FINALIZERS
+                .try_with(|finalizers| finalizers.borrow_mut().remove(&key))
+                .ok()
+                .flatten();
+            for finalizer in finalizers.into_iter().flatten() {
+                finalizer();
+            }
+        }
     }
 }
 
@@ -288,6 +342,9 @@ impl JsObject {
         slot: &mut Slot,
     ) -> bool {
         //("obj {:p}", *obj);
+        if unlikely(obj.structure.is_dictionary()) {
+            return Self::get_own_dictionary_property_slot(obj, ctx, name, slot);
+        }
         let entry = obj.structure.get(ctx, name);
 
         if !entry.is_not_found() {
@@ -303,6 +360,34 @@ impl JsObject {
         false
     }
 
+    #[cold]
+    fn get_own_dictionary_property_slot(
+        obj: &mut GcPointer<Self>,
+        ctx: GcPointer<Context>,
+        name: Symbol,
+        slot: &mut Slot,
+    ) -> bool {
+        let entry = obj.structure.get(ctx, name);
+        obj.structure.stable_reads += 1;
+        if obj.structure.stable_reads >= DICTIONARY_STABLE_READS {
+            obj.structure = obj.structure.from_dictionary(ctx);
+        }
+        if entry.is_not_found() {
+            return false;
+        }
+        slot.set_woffset(
+            *obj.direct(entry.offset as _),
+            entry.attrs as _,
+            Some(obj.as_dyn()),
+            entry.offset,
+        );
+        if obj.structure.is_dictionary() {
+            // dictionary reuses offsets of deleted properties in place.
+            slot.make_uncacheable();
+        }
+        true
+    }
+
     pub fn PutNonIndexedSlotMethod(
         obj: &mut GcPointer<Self>,
         ctx: GcPointer<Context>,
@@ -960,6 +1045,19 @@ impl JsObject {
 }
 
 impl GcPointer<JsObject> {
+    /// Register `finalizer` that is invoked when this object is collected, after class `drop` hook runs.
+    /// Can be used to release native resources (files, sockets) owned by plain JS objects.
+    pub fn add_finalizer(&mut self, finalizer: impl FnOnce() + 'static) {
+        let key = &**self as *const JsObject as usize;
+        FINALIZERS.with(|finalizers| {
+            finalizers
+                .borrow_mut()
+                .entry(key)
+                .or_insert_with(Vec::new)
+                .push(Box::new(finalizer));
+        });
+        self.flags |= OBJ_FLAG_HAS_FINALIZER;
+    }
     pub fn get_own_property_names(
         &mut self,
         ctx: GcPointer<Context>,
@@ -976,6 +1074,55 @@ impl GcPointer<JsObject> {
     ) {
         (self.class.method_table.GetPropertyNames)(self, ctx, collector, mode)
     }
+
+    /// Own property keys in the order of `Reflect.ownKeys`: array indices ascending, then string keys and then
+    /// symbols in the order they were added.
+    pub fn own_property_names(
+        &mut self,
+        ctx: GcPointer<Context>,
+        filter: PropertyFilter,
+    ) -> Vec<Symbol> {
+        let mode = if filter == PropertyFilter::Enumerable {
+            EnumerationMode::Default
+        } else {
+            EnumerationMode::IncludeNotEnumerable
+        };
+        let mut indices = vec![];
+        let mut keys = vec![];
+        self.get_own_property_names(
+            ctx,
+            &mut |name, offset| match name {
+                Symbol::Index(index) => indices.push(index),
+                Symbol::Private(_) if filter != PropertyFilter::All => {}
+                _ => keys.push((name, offset)),
+            },
+            mode,
+        );
+        indices.sort_unstable();
+        indices.dedup();
+        // structures hand out offsets in the order properties are added.
+        keys.sort_by_key(|(name, offset)| (matches!(name, Symbol::Private(_)), *offset));
+        indices
+            .into_iter()
+            .map(Symbol::Index)
+            .chain(keys.into_iter().map(|(name, _)| name))
+            .collect()
+    }
+
+    /// Iterate own properties and their descriptors in the order of [JsObject::own_property_names] without
+    /// calling getters. Keys are collected when the iterator is created. The iterator does not root the
+    /// object.
+    pub fn own_properties(
+        &mut self,
+        ctx: GcPointer<Context>,
+        filter: PropertyFilter,
+    ) -> OwnProperties {
+        OwnProperties {
+            ctx,
+            object: *self,
+            names: self.own_property_names(ctx, filter).into_iter(),
+        }
+    }
     pub fn put_non_indexed_slot(
         &mut self,
         ctx: GcPointer<Context>,
@@ -1515,17 +1662,11 @@ impl GcPointer<JsObject> {
 
 #[cfg(test)]
 mod tests {
-    use crate::{
-        vm::{object::*, value::*, *},
-        Platform,
-    };
+    use crate::vm::{object::*, tests::new_context, value::*, *};
 
     #[test]
     fn test_put() {
-        Platform::initialize();
-        let options = Options::default();
-        let mut vm = VirtualMachine::new(options, None);
-        let mut ctx = Context::new(&mut vm);
+        let (mut vm, mut ctx) = new_context();
 
         letroot!(object = stack, JsObject::new_empty(ctx));
 
@@ -1545,10 +1686,7 @@ mod tests {
 
     #[test]
     fn test_indexed() {
-        Platform::initialize();
-        let options = Options::default();
-        let mut vm = VirtualMachine::new(options, None);
-        let mut ctx = Context::new(&mut vm);
+        let (mut vm, mut ctx) = new_context();
 
         letroot!(object = stack, JsObject::new_empty(ctx));
         for i in 0..10000u32 {
@@ -1601,6 +1739,52 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_own_properties() {
+        let (_vm, mut ctx) = new_context();
+
+        let value = ctx
+            .eval(
+                "globalThis.calls = 0;
+                 let o = { b: 1, 2: 'two', a: 2, 0: 'zero' };
+                 Object.defineProperty(o, 'hidden', { value: 3, enumerable: false });
+                 Object.defineProperty(o, 'c', { get() { calls++; }, enumerable: true });
+                 o",
+            )
+            .ok()
+            .unwrap();
+        let mut object = value.get_jsobject();
+        object
+            .put(ctx, "@@secret".intern().private(), JsValue::new(4), false)
+            .ok()
+            .unwrap();
+
+        let enumerable = object.own_property_names(ctx, PropertyFilter::Enumerable);
+        let expected = [
+            Symbol::Index(0),
+            Symbol::Index(2),
+            "b".intern(),
+            "a".intern(),
+            "c".intern(),
+        ];
+        assert_eq!(enumerable, expected);
+        let names = object.own_property_names(ctx, PropertyFilter::StringKeys);
+        assert_eq!(names.len(), 6);
+        assert_eq!(names[4], "hidden".intern());
+        let all = object.own_property_names(ctx, PropertyFilter::All);
+        assert_eq!(all.len(), 7);
+        assert_eq!(all[6], "@@secret".intern().private());
+
+        let properties = object
+            .own_properties(ctx, PropertyFilter::StringKeys)
+            .collect::<Vec<_>>();
+        assert_eq!(properties[1].1.value().get_jsstring().as_str(), "two");
+        assert!(!properties[4].1.is_enumerable());
+        assert!(properties[5].1.is_accessor());
+        let calls = ctx.global_object().get(ctx, "calls".intern()).ok().unwrap();
+        assert_eq!(calls.to_int32(ctx).ok(), Some(0));
+    }
 }
 
 impl JsClass for () {
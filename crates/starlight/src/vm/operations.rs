@@ -5,6 +5,8 @@ use crate::prelude::*;
 
 use super::context::Context;
 
+pub mod equality;
+
 pub fn normalize_prototype_chain(
     ctx: GcPointer<Context>,
     base: &GcPointer<JsObject>,
@@ -33,3 +35,138 @@ pub fn normalize_prototype_chain(
         count += 1;
     }
 }
+
+/// InstanceofOperator: https://tc39.es/ecma262/#sec-instanceofoperator
+///
+/// `Symbol.hasInstance` of `target` takes priority over the prototype chain walk. The one inherited
+/// from `Function.prototype` is recognized and skipped since it does the same walk.
+pub fn instance_of(
+    ctx: GcPointer<Context>,
+    value: JsValue,
+    target: JsValue,
+) -> Result<bool, JsValue> {
+    if !target.is_jsobject() {
+        return Err(JsValue::new(
+            ctx.new_type_error("'instanceof' requires object"),
+        ));
+    }
+    letroot!(target = stack, target.get_jsobject());
+    let handler = target.get(ctx, "Symbol.hasInstance".intern().private())?;
+    if !handler.is_undefined() && !handler.is_null() && !is_ordinary_has_instance(handler) {
+        if !handler.is_callable() {
+            return Err(JsValue::new(
+                ctx.new_type_error("Symbol.hasInstance is not a function"),
+            ));
+        }
+        letroot!(func = stack, handler.get_jsobject());
+        let mut argv = [value];
+        letroot!(
+            args = stack,
+            Arguments::new(JsValue::new(target), &mut argv)
+        );
+        return Ok(func
+            .as_function_mut()
+            .call(ctx, &mut args, handler)?
+            .to_boolean());
+    }
+    if !target.is_callable() {
+        return Err(JsValue::new(
+            ctx.new_type_error("'instanceof' requires constructor"),
+        ));
+    }
+    letroot!(target2 = stack, target);
+    target.as_function().has_instance(&mut target2, ctx, value)
+}
+
+fn is_ordinary_has_instance(handler: JsValue) -> bool {
+    handler.is_callable() && {
+        let func = handler.get_jsobject();
+        let func = func.as_function();
+        func.is_native()
+            && func.as_native().func as usize
+                == crate::jsrt::function::function_has_instance as usize
+    }
+}
+
+/// Construct: https://tc39.es/ecma262/#sec-construct
+pub fn construct(
+    ctx: GcPointer<Context>,
+    constructor: JsValue,
+    args: &mut [JsValue],
+) -> Result<JsValue, JsValue> {
+    if !constructor.is_callable() {
+        return Err(JsValue::new(ctx.new_type_error("not a constructor")));
+    }
+    letroot!(func_object = stack, constructor.get_jsobject());
+    let structure = func_object.func_construct_map(ctx)?;
+    letroot!(
+        args = stack,
+        Arguments::new(JsValue::encode_undefined_value(), args)
+    );
+    func_object
+        .as_function_mut()
+        .construct(ctx, &mut args, Some(structure), constructor)
+}
+
+/// SpeciesConstructor: https://tc39.es/ecma262/#sec-speciesconstructor
+///
+/// Returns `default` when `object` has no `constructor` or the constructor has no `Symbol.species`.
+pub fn species_constructor(
+    ctx: GcPointer<Context>,
+    mut object: GcPointer<JsObject>,
+    default: JsValue,
+) -> Result<JsValue, JsValue> {
+    let constructor = object.get(ctx, Symbol::CONSTRUCTOR)?;
+    if constructor.is_undefined() {
+        return Ok(default);
+    }
+    if !constructor.is_jsobject() {
+        return Err(JsValue::new(
+            ctx.new_type_error("object.constructor is not an object"),
+        ));
+    }
+    let species = constructor
+        .get_jsobject()
+        .get(ctx, "Symbol.species".intern().private())?;
+    if species.is_undefined() || species.is_null() {
+        return Ok(default);
+    }
+    if !species.is_callable() {
+        return Err(JsValue::new(ctx.new_type_error(
+            "object.constructor[Symbol.species] is not a constructor",
+        )));
+    }
+    Ok(species)
+}
+
+/// ArraySpeciesCreate: https://tc39.es/ecma262/#sec-arrayspeciescreate
+///
+/// Creates a plain array of `length` unless `original` is an array whose species is not `Array`.
+pub fn array_species_create(
+    ctx: GcPointer<Context>,
+    mut original: GcPointer<JsObject>,
+    length: u32,
+) -> Result<GcPointer<JsObject>, JsValue> {
+    if original.tag() != ObjectTag::Array {
+        return Ok(JsArray::new(ctx, length));
+    }
+    let mut constructor = original.get(ctx, Symbol::CONSTRUCTOR)?;
+    if constructor.is_jsobject() {
+        constructor = constructor
+            .get_jsobject()
+            .get(ctx, "Symbol.species".intern().private())?;
+        if constructor.is_null() {
+            constructor = JsValue::encode_undefined_value();
+        }
+    }
+    let array_constructor = ctx.global_data.array_constructor.map(JsValue::new);
+    if constructor.is_undefined() || Some(constructor) == array_constructor {
+        return Ok(JsArray::new(ctx, length));
+    }
+    if !constructor.is_callable() {
+        return Err(JsValue::new(
+            ctx.new_type_error("Array species is not a constructor"),
+        ));
+    }
+    construct(ctx, constructor, &mut [JsValue::new(length)])?.to_object(ctx)
+}
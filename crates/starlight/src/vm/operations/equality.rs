@@ -0,0 +1,472 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+//! Equality and relational comparison of JS values.
+//!
+//! There is no BigInt primitive in the engine yet (see `vm::bigint`), so the BigInt rows of the
+//! spec tables are not implemented here.
+use crate::utils::likely;
+use std::cmp::Ordering;
+
+use crate::jsrt::{date::JsDate, regexp::JsRegExp};
+use crate::prelude::*;
+use crate::vm::{
+    context::Context,
+    object::PropertyFilter,
+    value::{CMP_FALSE, CMP_TRUE, CMP_UNDEF},
+};
+
+/// IsStrictlyEqual: https://tc39.es/ecma262/#sec-isstrictlyequal
+pub fn strict_equal(x: JsValue, y: JsValue) -> bool {
+    if likely(x.is_number() && y.is_number()) {
+        return x.get_number() == y.get_number();
+    }
+    if x.is_jsstring() && y.is_jsstring() {
+        return x.get_string().equals(&y.get_string());
+    }
+    x.get_raw() == y.get_raw()
+}
+
+/// SameValue: https://tc39.es/ecma262/#sec-samevalue
+pub fn same_value(x: JsValue, y: JsValue) -> bool {
+    same_value_impl(x, y, false)
+}
+
+/// SameValueZero: https://tc39.es/ecma262/#sec-samevaluezero
+pub fn same_value_zero(x: JsValue, y: JsValue) -> bool {
+    same_value_impl(x, y, true)
+}
+
+fn same_value_impl(x: JsValue, y: JsValue, zero: bool) -> bool {
+    if x.is_number() && y.is_number() {
+        let (x, y) = (x.get_number(), y.get_number());
+        if x.is_nan() && y.is_nan() {
+            return true;
+        }
+        return x == y && (zero || x.is_sign_negative() == y.is_sign_negative());
+    }
+    strict_equal(x, y)
+}
+
+/// IsLooselyEqual (`==`): https://tc39.es/ecma262/#sec-islooselyequal
+pub fn abstract_equal(
+    ctx: GcPointer<Context>,
+    mut x: JsValue,
+    mut y: JsValue,
+) -> Result<bool, JsValue> {
+    loop {
+        if likely(x.is_int32() && y.is_int32()) {
+            return Ok(x.get_int32() == y.get_int32());
+        }
+        if same_type(x, y) {
+            return Ok(strict_equal(x, y));
+        }
+        if is_nullish(x) && is_nullish(y) {
+            return Ok(true);
+        }
+        if x.is_number() && y.is_jsstring() {
+            y = JsValue::new(y.to_number(ctx)?);
+            continue;
+        }
+        if x.is_jsstring() && y.is_number() {
+            x = JsValue::new(x.to_number(ctx)?);
+            continue;
+        }
+        if x.is_bool() {
+            x = JsValue::new(x.to_number(ctx)?);
+            continue;
+        }
+        if y.is_bool() {
+            y = JsValue::new(y.to_number(ctx)?);
+            continue;
+        }
+        if is_primitive_key(x) && y.is_jsobject() {
+            y = y.to_primitive(ctx, JsHint::None)?;
+            continue;
+        }
+        if x.is_jsobject() && is_primitive_key(y) {
+            x = x.to_primitive(ctx, JsHint::None)?;
+            continue;
+        }
+        return Ok(false);
+    }
+}
+
+/// IsLessThan: https://tc39.es/ecma262/#sec-islessthan
+///
+/// Returns `CMP_TRUE` when `x < y`, `CMP_UNDEF` when either side converts to NaN and `CMP_FALSE`
+/// otherwise. `left_first` selects the order in which operands are converted to primitives.
+pub fn compare(
+    ctx: GcPointer<Context>,
+    x: JsValue,
+    y: JsValue,
+    left_first: bool,
+) -> Result<i32, JsValue> {
+    if likely(x.is_number() && y.is_number()) {
+        return Ok(number_compare(x.get_number(), y.get_number()));
+    }
+
+    let (px, py) = if left_first {
+        let px = x.to_primitive(ctx, JsHint::Number)?;
+        (px, y.to_primitive(ctx, JsHint::Number)?)
+    } else {
+        let py = y.to_primitive(ctx, JsHint::Number)?;
+        (x.to_primitive(ctx, JsHint::Number)?, py)
+    };
+    if px.is_jsstring() && py.is_jsstring() {
+        return Ok(string_compare(
+            px.get_string().code_units(),
+            py.get_string().code_units(),
+        ));
+    }
+    let nx = px.to_number(ctx)?;
+    let ny = py.to_number(ctx)?;
+    Ok(number_compare(nx, ny))
+}
+
+fn number_compare(x: f64, y: f64) -> i32 {
+    if x.is_nan() || y.is_nan() {
+        return CMP_UNDEF;
+    }
+    if x < y {
+        CMP_TRUE
+    } else {
+        CMP_FALSE
+    }
+}
+
+fn string_compare(x: CodeUnits, y: CodeUnits) -> i32 {
+    match x.cmp(&y) {
+        Ordering::Less => CMP_TRUE,
+        _ => CMP_FALSE,
+    }
+}
+
+fn is_nullish(x: JsValue) -> bool {
+    x.is_undefined() || x.is_null()
+}
+
+fn is_primitive_key(x: JsValue) -> bool {
+    x.is_number() || x.is_jsstring() || x.is_symbol()
+}
+
+fn same_type(x: JsValue, y: JsValue) -> bool {
+    (x.is_number() && y.is_number())
+        || (x.is_jsstring() && y.is_jsstring())
+        || (x.is_bool() && y.is_bool())
+        || (x.is_undefined() && y.is_undefined())
+        || (x.is_null() && y.is_null())
+        || (x.is_symbol() && y.is_symbol())
+        || (x.is_jsobject() && y.is_jsobject())
+}
+
+/// Structural equality of `x` and `y` like `assert.deepStrictEqual` of Node.js.
+///
+/// Primitives are compared with [same_value]. Objects are equal when they have the same kind (class and
+/// tag) and equal own enumerable string keyed properties, arrays also need equal lengths, dates equal time
+/// values and regular expressions equal source and flags. Functions are only equal to themselves.
+/// Prototypes are not compared, so objects created in different contexts of a runtime can be equal.
+/// Accessors are compared by identity and never called, which means no JS code runs. Cycles are handled
+/// by assuming objects already being compared are equal.
+pub fn deep_equal(ctx: GcPointer<Context>, x: JsValue, y: JsValue) -> bool {
+    DeepEqual {
+        ctx,
+        visiting: vec![],
+    }
+    .values(x, y)
+}
+
+struct DeepEqual {
+    ctx: GcPointer<Context>,
+    /// Pairs of objects compared by the callers.
+    visiting: Vec<(GcPointer<JsObject>, GcPointer<JsObject>)>,
+}
+
+impl DeepEqual {
+    fn values(&mut self, x: JsValue, y: JsValue) -> bool {
+        if !x.is_jsobject() || !y.is_jsobject() {
+            return same_value(x, y);
+        }
+        let (x, y) = (x.get_jsobject(), y.get_jsobject());
+        if GcPointer::ptr_eq(&x, &y)
+            || self
+                .visiting
+                .iter()
+                .any(|(a, b)| GcPointer::ptr_eq(a, &x) && GcPointer::ptr_eq(b, &y))
+        {
+            return true;
+        }
+        self.visiting.push((x, y));
+        let equal = self.objects(x, y);
+        self.visiting.pop();
+        equal
+    }
+
+    fn objects(&mut self, mut x: GcPointer<JsObject>, mut y: GcPointer<JsObject>) -> bool {
+        let ctx = self.ctx;
+        if x.tag() != y.tag() || !std::ptr::eq(x.class, y.class) || x.is_callable() {
+            return false;
+        }
+        if x.is_class(JsDate::class())
+            && !same_value(
+                JsValue::new(x.data::<JsDate>().get_time()),
+                JsValue::new(y.data::<JsDate>().get_time()),
+            )
+        {
+            return false;
+        }
+        if x.is_class(JsRegExp::class()) {
+            let (a, b) = (x.data::<JsRegExp>(), y.data::<JsRegExp>());
+            if a.original_source != b.original_source || a.original_flags != b.original_flags {
+                return false;
+            }
+        }
+        if x.tag() == ObjectTag::Array {
+            let length = Symbol::LENGTH;
+            let lengths = (
+                x.get_own_property(ctx, length),
+                y.get_own_property(ctx, length),
+            );
+            if !matches!(lengths, (Some(a), Some(b)) if same_value(a.value(), b.value())) {
+                return false;
+            }
+        }
+
+        let names = x.own_property_names(ctx, PropertyFilter::Enumerable);
+        if names.len() != y.own_property_names(ctx, PropertyFilter::Enumerable).len() {
+            return false;
+        }
+        for name in names {
+            let (a, b) = match (x.get_own_property(ctx, name), y.get_own_property(ctx, name)) {
+                (Some(a), Some(b)) if b.is_enumerable() => (a, b),
+                _ => return false,
+            };
+            let equal = match (a.is_accessor(), b.is_accessor()) {
+                (false, false) => self.values(a.value(), b.value()),
+                (true, true) => {
+                    same_value(a.getter(), b.getter()) && same_value(a.setter(), b.setter())
+                }
+                _ => false,
+            };
+            if !equal {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::tests::new_context;
+
+    /// Values covering every type and the interesting conversions between them.
+    const VALUES: [&str; 18] = [
+        "undefined",
+        "null",
+        "true",
+        "false",
+        "0",
+        "-0",
+        "1",
+        "NaN",
+        "''",
+        "'0'",
+        "'1'",
+        "'abc'",
+        "sym",
+        "obj",
+        "one",
+        "[1]",
+        "['abc']",
+        "Infinity",
+    ];
+
+    /// Evaluates `VALUES` into a global array so that they stay reachable while the test runs.
+    fn values(ctx: &mut GcPointer<Context>) -> Vec<JsValue> {
+        let source = format!(
+            "var sym = Symbol('s');
+             var obj = {{}};
+             var one = {{ valueOf() {{ return 1; }} }};
+             var values = [{}];
+             values",
+            VALUES.join(", ")
+        );
+        let mut array = ctx.eval(&source).ok().unwrap().get_jsobject();
+        (0..VALUES.len())
+            .map(|i| array.get(*ctx, Symbol::Index(i as _)).ok().unwrap())
+            .collect()
+    }
+
+    /// Checks `op` on every pair of `VALUES`. `expected` lists the index pairs for which `op` holds,
+    /// in either order.
+    fn matrix(
+        op: impl Fn(GcPointer<Context>, JsValue, JsValue) -> bool,
+        expected: &[(usize, usize)],
+    ) {
+        let (_vm, mut ctx) = new_context();
+        let values = values(&mut ctx);
+        for (i, &x) in values.iter().enumerate() {
+            for (j, &y) in values.iter().enumerate() {
+                let want = expected.contains(&(i, j)) || expected.contains(&(j, i));
+                assert_eq!(op(ctx, x, y), want, "{} vs {}", VALUES[i], VALUES[j]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_strict_equal() {
+        // Every value except NaN equals itself, and 0 equals -0.
+        let mut expected = (0..VALUES.len())
+            .filter(|&i| VALUES[i] != "NaN")
+            .map(|i| (i, i))
+            .collect::<Vec<_>>();
+        expected.push((4, 5));
+        matrix(|_, x, y| strict_equal(x, y), &expected);
+    }
+
+    #[test]
+    fn test_same_value() {
+        let expected = (0..VALUES.len()).map(|i| (i, i)).collect::<Vec<_>>();
+        matrix(|_, x, y| same_value(x, y), &expected);
+
+        let mut expected = expected;
+        expected.push((4, 5));
+        matrix(|_, x, y| same_value_zero(x, y), &expected);
+    }
+
+    #[test]
+    fn test_abstract_equal() {
+        let mut expected = (0..VALUES.len())
+            .filter(|&i| VALUES[i] != "NaN")
+            .map(|i| (i, i))
+            .collect::<Vec<_>>();
+        expected.extend_from_slice(&[
+            // undefined == null
+            (0, 1),
+            // true == 1 == '1' == one == [1]
+            (2, 6),
+            (2, 10),
+            (2, 14),
+            (2, 15),
+            (6, 10),
+            (6, 14),
+            (6, 15),
+            (10, 14),
+            (10, 15),
+            // false == 0 == -0 == '' == '0'
+            (3, 4),
+            (3, 5),
+            (3, 8),
+            (3, 9),
+            (4, 5),
+            (4, 8),
+            (4, 9),
+            (5, 8),
+            (5, 9),
+            // 'abc' == ['abc']
+            (11, 16),
+        ]);
+        matrix(
+            |ctx, x, y| abstract_equal(ctx, x, y).ok().unwrap(),
+            &expected,
+        );
+    }
+
+    #[test]
+    fn test_compare() {
+        let (_vm, mut ctx) = new_context();
+        let values = values(&mut ctx);
+        let at = |src: &str| values[VALUES.iter().position(|v| *v == src).unwrap()];
+
+        let lt = |x: JsValue, y: JsValue| compare(ctx, x, y, true).ok().unwrap();
+        assert_eq!(lt(at("0"), at("1")), CMP_TRUE);
+        assert_eq!(lt(at("1"), at("0")), CMP_FALSE);
+        assert_eq!(lt(at("-0"), at("0")), CMP_FALSE);
+        assert_eq!(lt(at("NaN"), at("1")), CMP_UNDEF);
+        assert_eq!(lt(at("undefined"), at("1")), CMP_UNDEF);
+        assert_eq!(lt(at("null"), at("1")), CMP_TRUE);
+        assert_eq!(lt(at("false"), at("true")), CMP_TRUE);
+        assert_eq!(lt(at("'0'"), at("'1'")), CMP_TRUE);
+        assert_eq!(lt(at("''"), at("'0'")), CMP_TRUE);
+        assert_eq!(lt(at("'abc'"), at("'abc'")), CMP_FALSE);
+        assert_eq!(lt(at("'abc'"), at("1")), CMP_UNDEF);
+        assert_eq!(lt(at("'0'"), at("one")), CMP_TRUE);
+        assert_eq!(lt(at("[1]"), at("Infinity")), CMP_TRUE);
+        assert!(compare(ctx, at("sym"), at("1"), true).is_err());
+
+        let mut eval = |src: &str| ctx.eval(src).ok().unwrap().get_bool();
+        assert!(eval("obj < '[object Z'"));
+        // Code unit order puts astral characters (surrogates) before the end of the BMP.
+        assert!(eval("'\\u{10000}' < '\\uFFFF'"));
+        assert!(!eval("'\\uFFFF' < '\\u{10000}'"));
+    }
+
+    #[test]
+    fn test_lone_surrogates() {
+        let (_vm, mut ctx) = new_context();
+        let result = ctx
+            .eval(
+                "var s = '\u{1F600}';
+                 [s[0] === s[1], s[0] === '\u{FFFD}', s[0] === s.charAt(0), Object.is(s[1], s.charAt(1)),
+                  s[0] < s[1], s[1] < '\u{FFFD}'].join()",
+            )
+            .ok()
+            .unwrap();
+        assert_eq!(
+            result.get_jsstring().as_str(),
+            "false,false,true,true,true,true"
+        );
+    }
+
+    #[test]
+    fn test_compare_conversion_order() {
+        let (_vm, mut ctx) = new_context();
+        let result = ctx
+            .eval(
+                "var log = '';
+                 var a = { valueOf() { log += 'a'; return 1; } };
+                 var b = { valueOf() { log += 'b'; return 2; } };
+                 a < b; a > b; a <= b; a >= b;
+                 log",
+            )
+            .ok()
+            .unwrap();
+        assert_eq!(result.to_string(ctx).ok().unwrap(), "abababab");
+    }
+
+    #[test]
+    fn test_deep_equal() {
+        let (mut vm, mut ctx) = new_context();
+        let mut other = Context::new(&mut vm);
+        let mut at = |ctx: &mut GcPointer<Context>, src: &str| ctx.eval(src).ok().unwrap();
+
+        let a = at(
+            &mut ctx,
+            "var a = { x: [1, { y: NaN }, , 'z'], d: new Date(5), r: /a/g }; a.self = a; a",
+        );
+        let b = at(
+            &mut other,
+            "var b = { x: [1, { y: NaN }, , 'z'], d: new Date(5), r: /a/g }; b.self = b; b",
+        );
+        assert!(vm.deep_equal(a, b));
+        assert!(deep_equal(ctx, a, b));
+
+        for (x, y) in [
+            ("[1, , 3]", "[1, undefined, 3]"),
+            ("[1, 2]", "[1, 2, 3]"),
+            ("({ a: 0 })", "({ a: -0 })"),
+            ("({ a: 1 })", "({ a: 1, b: undefined })"),
+            ("new Date(1)", "new Date(2)"),
+            ("/a/g", "/a/i"),
+            ("[]", "({})"),
+            ("(function () {})", "(function () {})"),
+        ]
+        .iter()
+        {
+            let (x, y) = (at(&mut ctx, x), at(&mut other, y));
+            assert!(!deep_equal(ctx, x, y));
+        }
+    }
+}
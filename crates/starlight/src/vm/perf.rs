@@ -1,7 +1,10 @@
 /* This Source Code Form is subject to the terms of the Mozilla Public
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+use super::{code_block::CodeBlock, symbol_table::*};
 use crate::bytecode::opcodes::Opcode;
+use crate::gc::cell::GcPointer;
+use std::collections::HashMap;
 use std::time::{Duration, Instant};
 #[derive(Debug, Clone)]
 pub struct PerfCounter {
@@ -18,12 +21,63 @@ impl PerfCounter {
     }
 }
 
+/// Counts of one opcode or of one of the [Perf::GC], [Perf::CODEGEN] and [Perf::EXTERN] pseudo instructions.
+#[derive(Debug, Clone)]
+pub struct OpcodeStats {
+    /// `None` for pseudo instructions.
+    pub opcode: Option<Opcode>,
+    pub name: String,
+    pub count: u64,
+    pub duration: Duration,
+}
+
+/// Instructions executed in one code block.
+#[derive(Debug, Clone)]
+pub struct CodeBlockStats {
+    pub function: String,
+    pub file: String,
+    /// Line of the first statement of the code block.
+    pub line: u32,
+    pub count: u64,
+    pub duration: Duration,
+}
+
+/// Snapshot of interpreter counters returned by [VirtualMachine::perf_stats](crate::vm::VirtualMachine::perf_stats).
+#[derive(Debug, Clone, Default)]
+pub struct PerfStats {
+    /// Instructions that were executed at least once, in opcode order.
+    pub opcodes: Vec<OpcodeStats>,
+    /// Code blocks that were executed, the most expensive first.
+    pub code_blocks: Vec<CodeBlockStats>,
+}
+
+impl PerfStats {
+    /// Time spent in all recorded instructions.
+    pub fn total_duration(&self) -> Duration {
+        self.opcodes.iter().map(|stats| stats.duration).sum()
+    }
+}
+
+#[derive(Debug, Clone)]
+struct CodeBlockCounter {
+    name: Symbol,
+    file: String,
+    line: u32,
+    counter: PerfCounter,
+}
+
 #[derive(Debug, Clone)]
 pub struct Perf {
     counter: Vec<PerfCounter>,
     timer: Instant,
     prev_time: Duration,
     prev_inst: u8,
+    code_blocks: Vec<CodeBlockCounter>,
+    /// Code block address to index in `code_blocks`. Addresses are reused after GC so entries are checked
+    /// against the code block before use.
+    code_ids: HashMap<usize, usize>,
+    prev_code: Option<usize>,
+    prev_code_addr: usize,
 }
 
 impl Perf {
@@ -44,9 +98,26 @@ impl Perf {
             timer: Instant::now(),
             prev_time: Duration::from_secs(0),
             prev_inst: Perf::INVALID,
+            code_blocks: vec![],
+            code_ids: HashMap::new(),
+            prev_code: None,
+            prev_code_addr: 0,
         }
     }
 
+    /// Forget all recorded counts.
+    pub fn reset(&mut self) {
+        self.counter
+            .iter_mut()
+            .for_each(|counter| *counter = PerfCounter::new());
+        self.code_blocks.clear();
+        self.code_ids.clear();
+        self.prev_time = self.timer.elapsed();
+        self.prev_inst = Perf::INVALID;
+        self.prev_code = None;
+        self.prev_code_addr = 0;
+    }
+
     /// Record duration for current instruction.
     pub fn get_perf(&mut self, next_inst: u8) {
         let prev = self.prev_inst;
@@ -56,9 +127,103 @@ impl Perf {
         if prev != Perf::INVALID {
             self.counter[prev as usize].count += 1;
             self.counter[prev as usize].duration += elapsed - self.prev_time;
+            if let Some(code) = self.prev_code {
+                self.code_blocks[code].counter.count += 1;
+                self.code_blocks[code].counter.duration += elapsed - self.prev_time;
+            }
         }
         self.prev_time = elapsed;
         self.prev_inst = next_inst;
+        self.prev_code = None;
+    }
+
+    /// Record duration for current instruction, next instruction belongs to `code`.
+    pub fn get_perf_in(&mut self, next_inst: u8, code: GcPointer<CodeBlock>) {
+        let prev_code = self.prev_code;
+        self.get_perf(next_inst);
+        let addr = &*code as *const CodeBlock as usize;
+        self.prev_code = if addr == self.prev_code_addr {
+            prev_code
+        } else {
+            self.prev_code_addr = addr;
+            Some(self.code_id(code))
+        };
+    }
+
+    fn code_id(&mut self, code: GcPointer<CodeBlock>) -> usize {
+        let addr = &*code as *const CodeBlock as usize;
+        let line = code.first_line();
+        if let Some(&id) = self.code_ids.get(&addr) {
+            let entry = &self.code_blocks[id];
+            if entry.name == code.name && entry.line == line && entry.file == code.file_name {
+                return id;
+            }
+        }
+        let id = self.code_blocks.len();
+        self.code_blocks.push(CodeBlockCounter {
+            name: code.name,
+            file: code.file_name.clone(),
+            line,
+            counter: PerfCounter::new(),
+        });
+        self.code_ids.insert(addr, id);
+        id
+    }
+
+    fn inst_name(inst: usize) -> String {
+        if inst as u8 == Perf::CODEGEN {
+            "CODEGEN".to_string()
+        } else if inst as u8 == Perf::EXTERN {
+            "EXTERN".to_string()
+        } else if inst as u8 == Perf::GC {
+            "GC".to_string()
+        } else {
+            format!("{:?}", unsafe {
+                std::mem::transmute::<_, Opcode>(inst as u8)
+            })
+        }
+    }
+
+    /// Collect counts recorded since the VM was created or since the last [Perf::reset].
+    pub fn stats(&self) -> PerfStats {
+        let opcodes = self
+            .counter
+            .iter()
+            .enumerate()
+            .filter(|(i, counter)| *i != 0 && counter.count != 0)
+            .map(|(i, counter)| OpcodeStats {
+                opcode: if i as u8 >= Perf::GC {
+                    None
+                } else {
+                    Some(unsafe { std::mem::transmute::<_, Opcode>(i as u8) })
+                },
+                name: Self::inst_name(i),
+                count: counter.count,
+                duration: counter.duration,
+            })
+            .collect();
+        let mut code_blocks = self
+            .code_blocks
+            .iter()
+            .filter(|code| code.counter.count != 0)
+            .map(|code| CodeBlockStats {
+                function: match code.name {
+                    Symbol::Key(key) | Symbol::Private(key) => {
+                        symbol_table().description(key).to_owned()
+                    }
+                    Symbol::Index(x) => x.to_string(),
+                },
+                file: code.file.clone(),
+                line: code.line,
+                count: code.counter.count,
+                duration: code.counter.duration,
+            })
+            .collect::<Vec<_>>();
+        code_blocks.sort_by(|a, b| b.duration.cmp(&a.duration));
+        PerfStats {
+            opcodes,
+            code_blocks,
+        }
     }
 
     pub fn get_perf_no_count(&mut self, next_inst: u8) {
@@ -101,15 +266,7 @@ impl Perf {
             }
             eprintln!(
                 "  {:<14}{:>10} {:>8.2} {:>8}",
-                if i as u8 == Perf::CODEGEN {
-                    "CODEGEN".to_string()
-                } else if i as u8 == Perf::EXTERN {
-                    "EXTERN".to_string()
-                } else if i as u8 == Perf::GC {
-                    "GC".to_string()
-                } else {
-                    format!("{:?}", unsafe { std::mem::transmute::<_, Opcode>(i as u8) })
-                },
+                Self::inst_name(i),
                 if *c > 10_000_000 {
                     format!("{:>12}M", c / 1_000_000)
                 } else if *c > 10000 {
@@ -123,3 +280,47 @@ impl Perf {
         }
     }
 }
+
+#[cfg(all(test, feature = "perf"))]
+mod tests {
+    use crate::vm::tests::new_context;
+
+    #[test]
+    fn test_perf_stats() {
+        let (mut vm, mut ctx) = new_context();
+        ctx.eval_internal(
+            Some("test.js"),
+            false,
+            "function sum() {
+                 var x = 0;
+                 for (var i = 0; i < 100; i++) x += i;
+                 return x;
+             }
+             sum();",
+            false,
+        )
+        .ok()
+        .unwrap();
+        let stats = vm.perf_stats();
+        let add = stats
+            .opcodes
+            .iter()
+            .find(|stats| stats.name == "OP_ADD")
+            .unwrap();
+        assert!(add.count >= 100);
+        let sum = stats
+            .code_blocks
+            .iter()
+            .find(|stats| stats.function == "sum")
+            .unwrap();
+        assert_eq!(sum.file, "test.js");
+        assert_eq!(sum.line, 2);
+        assert!(sum.count > add.count);
+        assert!(stats.total_duration() >= sum.duration);
+
+        vm.reset_perf_stats();
+        let stats = vm.perf_stats();
+        assert!(stats.opcodes.is_empty());
+        assert!(stats.code_blocks.is_empty());
+    }
+}
@@ -0,0 +1,319 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+//! Sampling CPU profiler.
+//!
+//! A timer thread started by [VirtualMachine::start_profiling](crate::vm::VirtualMachine::start_profiling)
+//! raises a flag every sampling interval. The interpreter checks the flag at loop back edges and returns and
+//! records the chain of call frames, so a native function running for a long time is attributed to the JS code
+//! that called it. Frames are identified by function name, file and line of the first statement of the function.
+//!
+//! ```rust,ignore
+//! vm.start_profiling(Duration::from_millis(1));
+//! ctx.eval(source)?;
+//! let profile = vm.stop_profiling().unwrap();
+//! profile.write_collapsed(&mut std::fs::File::create("out.folded")?)?;
+//! ```
+use super::{context::Context, heap_snapshot::write_json_string};
+use crate::gc::cell::GcPointer;
+use std::{
+    collections::HashMap,
+    io::{self, Write},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+/// Function on the sampled stack.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct ProfileFrame {
+    pub function: String,
+    pub file: String,
+    /// Line of the first statement of the function, 0 for native code.
+    pub line: u32,
+}
+
+impl ProfileFrame {
+    fn label(&self) -> String {
+        if self.file.is_empty() && self.line == 0 {
+            return self.function.clone();
+        }
+        format!("{} ({}:{})", self.function, self.file, self.line)
+    }
+}
+
+pub struct Sample {
+    /// Time since the start of profiling.
+    pub time: Duration,
+    /// Indices into [CpuProfile::frames], outermost frame first.
+    pub stack: Vec<usize>,
+}
+
+#[derive(Default)]
+pub struct CpuProfile {
+    pub frames: Vec<ProfileFrame>,
+    pub samples: Vec<Sample>,
+    pub duration: Duration,
+}
+
+impl CpuProfile {
+    /// Distinct stacks with the number of samples that hit them, most frequent first.
+    pub fn aggregate(&self) -> Vec<(&[usize], usize)> {
+        let mut counts: HashMap<&[usize], usize> = HashMap::new();
+        for sample in self.samples.iter() {
+            *counts.entry(sample.stack.as_slice()).or_default() += 1;
+        }
+        let mut stacks = counts.into_iter().collect::<Vec<_>>();
+        stacks.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+        stacks
+    }
+
+    /// Write stacks in the collapsed format of `flamegraph.pl` and `inferno`: one line per distinct stack,
+    /// frames separated by `;` followed by the sample count.
+    pub fn write_collapsed(&self, out: &mut dyn Write) -> io::Result<()> {
+        for (stack, count) in self.aggregate() {
+            let labels = stack
+                .iter()
+                .map(|&frame| self.frames[frame].label().replace(';', ":"))
+                .collect::<Vec<_>>();
+            writeln!(out, "{} {}", labels.join(";"), count)?;
+        }
+        Ok(())
+    }
+
+    /// Write samples as Chrome trace event JSON loadable by `chrome://tracing`, Perfetto or speedscope. Frames
+    /// become duration events starting at the first sample that has them on the stack.
+    pub fn write_chrome_trace(&self, out: &mut dyn Write) -> io::Result<()> {
+        write!(out, "{{\"traceEvents\":[")?;
+        let mut first = true;
+        let mut event = |out: &mut dyn Write, phase: char, frame: usize, time: Duration| {
+            if !first {
+                write!(out, ",")?;
+            }
+            first = false;
+            let frame = &self.frames[frame];
+            write!(out, "\n{{\"name\":")?;
+            write_json_string(out, &frame.function)?;
+            write!(
+                out,
+                ",\"cat\":\"js\",\"ph\":\"{}\",\"ts\":{},\"pid\":1,\"tid\":1,\"args\":{{\"file\":",
+                phase,
+                time.as_micros()
+            )?;
+            write_json_string(out, &frame.file)?;
+            write!(out, ",\"line\":{}}}}}", frame.line)
+        };
+        let empty = vec![];
+        let mut previous = &empty;
+        for sample in self.samples.iter() {
+            let common = previous
+                .iter()
+                .zip(sample.stack.iter())
+                .take_while(|(a, b)| a == b)
+                .count();
+            for &frame in previous[common..].iter().rev() {
+                event(out, 'E', frame, sample.time)?;
+            }
+            for &frame in sample.stack[common..].iter() {
+                event(out, 'B', frame, sample.time)?;
+            }
+            previous = &sample.stack;
+        }
+        for &frame in previous.iter().rev() {
+            event(out, 'E', frame, self.duration)?;
+        }
+        write!(out, "\n]}}")
+    }
+}
+
+pub(crate) struct SamplingProfiler {
+    requested: Arc<AtomicBool>,
+    stop: Arc<AtomicBool>,
+    timer: Option<JoinHandle<()>>,
+    start: Instant,
+    profile: CpuProfile,
+    frame_ids: HashMap<ProfileFrame, usize>,
+}
+
+impl SamplingProfiler {
+    #[cfg(feature = "threads")]
+    pub(crate) fn start(interval: Duration) -> Self {
+        let requested = Arc::new(AtomicBool::new(false));
+        let stop = Arc::new(AtomicBool::new(false));
+        let timer = {
+            let requested = requested.clone();
+            let stop = stop.clone();
+            std::thread::spawn(move || {
+                while !stop.load(Ordering::Relaxed) {
+                    std::thread::sleep(interval);
+                    requested.store(true, Ordering::Relaxed);
+                }
+            })
+        };
+        Self {
+            requested,
+            stop,
+            timer: Some(timer),
+            start: Instant::now(),
+            profile: CpuProfile::default(),
+            frame_ids: HashMap::new(),
+        }
+    }
+
+    pub(crate) fn finish(mut self) -> CpuProfile {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(timer) = self.timer.take() {
+            let _ = timer.join();
+        }
+        let mut profile = std::mem::take(&mut self.profile);
+        profile.duration = self.start.elapsed();
+        profile
+    }
+
+    fn frame_id(&mut self, frame: ProfileFrame) -> usize {
+        if let Some(&id) = self.frame_ids.get(&frame) {
+            return id;
+        }
+        let id = self.profile.frames.len();
+        self.profile.frames.push(frame.clone());
+        self.frame_ids.insert(frame, id);
+        id
+    }
+
+    fn sample(&mut self, ctx: GcPointer<Context>) {
+        let mut stack = vec![];
+        let mut frame = ctx.stack.current;
+        unsafe {
+            while !frame.is_null() {
+                let profile_frame = match (*frame).code_block {
+                    Some(code) => ProfileFrame {
+                        function: ctx.description(code.name),
+                        file: code.file_name.clone(),
+                        line: code.first_line(),
+                    },
+                    None => ProfileFrame {
+                        function: "(native)".to_owned(),
+                        file: String::new(),
+                        line: 0,
+                    },
+                };
+                stack.push(self.frame_id(profile_frame));
+                frame = (*frame).prev;
+            }
+        }
+        stack.reverse();
+        let time = self.start.elapsed();
+        self.profile.samples.push(Sample { time, stack });
+    }
+}
+
+impl Drop for SamplingProfiler {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Take a sample if the timer asked for one. Called by the interpreter at loop back edges and returns.
+#[inline]
+pub(crate) fn safepoint(ctx: GcPointer<Context>) {
+    let mut vm = ctx.vm();
+    if let Some(profiler) = vm.profiler.as_mut() {
+        if profiler.requested.swap(false, Ordering::Relaxed) {
+            profiler.sample(ctx);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::tests::new_context;
+
+    fn frame(function: &str, line: u32) -> ProfileFrame {
+        ProfileFrame {
+            function: function.to_owned(),
+            file: "a.js".to_owned(),
+            line,
+        }
+    }
+
+    fn sample(ms: u64, stack: &[usize]) -> Sample {
+        Sample {
+            time: Duration::from_millis(ms),
+            stack: stack.to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_exports() {
+        let profile = CpuProfile {
+            frames: vec![frame("main", 1), frame("f", 2), frame("g", 5)],
+            samples: vec![sample(1, &[0, 1]), sample(2, &[0, 1]), sample(3, &[0, 2])],
+            duration: Duration::from_millis(4),
+        };
+        let mut collapsed = vec![];
+        profile.write_collapsed(&mut collapsed).unwrap();
+        assert_eq!(
+            String::from_utf8(collapsed).unwrap(),
+            "main (a.js:1);f (a.js:2) 2\nmain (a.js:1);g (a.js:5) 1\n"
+        );
+
+        let mut trace = vec![];
+        profile.write_chrome_trace(&mut trace).unwrap();
+        let trace = String::from_utf8(trace).unwrap();
+        let phases = trace
+            .lines()
+            .filter_map(|line| {
+                let name = line.split("\"name\":\"").nth(1)?.split('"').next()?;
+                let phase = line.split("\"ph\":\"").nth(1)?.split('"').next()?;
+                let ts = line.split("\"ts\":").nth(1)?.split(',').next()?;
+                Some(format!("{}{}@{}", phase, name, ts))
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            phases,
+            [
+                "Bmain@1000",
+                "Bf@1000",
+                "Ef@3000",
+                "Bg@3000",
+                "Eg@4000",
+                "Emain@4000"
+            ]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "threads")]
+    fn test_sampling() {
+        let (mut vm, mut ctx) = new_context();
+        vm.start_profiling(Duration::from_micros(100));
+        ctx.eval_internal(
+            Some("test.js"),
+            false,
+            "function spin() {
+                 var start = Date.now();
+                 while (Date.now() - start < 30) {}
+             }
+             spin();",
+            false,
+        )
+        .ok()
+        .unwrap();
+        let profile = vm.stop_profiling().unwrap();
+        assert!(!profile.samples.is_empty());
+        let spin = profile
+            .frames
+            .iter()
+            .position(|frame| frame.function == "spin" && frame.line == 2)
+            .unwrap();
+        assert!(profile
+            .samples
+            .iter()
+            .any(|sample| sample.stack.last() == Some(&spin)));
+        assert!(vm.stop_profiling().is_none());
+    }
+}
@@ -13,7 +13,13 @@ use crate::prelude::*;
 use crate::vm::array::JsArray;
 use crate::vm::function::JsClosureFunction;
 use crate::vm::structure::Structure;
-use std::mem::ManuallyDrop;
+use crate::vm::PersistentRooted;
+use std::{
+    future::Future,
+    mem::ManuallyDrop,
+    pin::Pin,
+    task::{Context as TaskContext, Poll, Waker},
+};
 
 pub enum TrackingMode {
     All,
@@ -30,6 +36,8 @@ pub struct JsPromise {
     tracking_results: Option<Vec<Option<Result<JsValue, JsValue>>>>,
     // resolution for this Promise
     resolution: Option<Result<JsValue, JsValue>>,
+    // Rust tasks awaiting this Promise through PromiseFuture
+    wakers: Vec<Waker>,
 }
 
 impl JsClass for JsPromise {
@@ -102,7 +110,7 @@ impl JsPromise {
             .global_object()
             .get(ctx, "Promise".intern())?
             .to_object(ctx)?
-            .get(ctx, "prototype".intern())?
+            .get(ctx, Symbol::PROTOTYPE)?
             .to_object(ctx)?;
 
         let structure = Structure::new_indexed(ctx, Some(proto), false);
@@ -113,6 +121,7 @@ impl JsPromise {
             tracking_mode: None,
             tracking_results: None,
             resolution: None,
+            wakers: vec![],
         });
         Ok(JsValue::new(obj))
     }
@@ -125,7 +134,7 @@ impl JsPromise {
             .global_object()
             .get(ctx, "Promise".intern())?
             .to_object(ctx)?
-            .get(ctx, "prototype".intern())?
+            .get(ctx, Symbol::PROTOTYPE)?
             .to_object(ctx)?;
 
         if !promises_array.is_jsobject() {
@@ -159,6 +168,7 @@ impl JsPromise {
             tracking_mode: Some(mode),
             tracking_results: Some(results),
             resolution: None,
+            wakers: vec![],
         });
         let promise_value = JsValue::new(obj);
 
@@ -317,6 +327,7 @@ impl JsPromise {
             }
 
             self.resolution = Some(resolution);
+            self.wakers.drain(..).for_each(Waker::wake);
 
             // todo everything below needs to be in async job.. need to root persistent again... later
             // root prom_this
@@ -428,6 +439,44 @@ fn array_util_get_value_at(
     arr_object.get(ctx, Symbol::Index(index))
 }
 
+impl JsPromise {
+    /// Convert `value` into a future that completes when the promise is settled, other values complete
+    /// immediately like with `await`. The promise is settled only while JS runs, so the future must be polled
+    /// on the thread of the runtime. The output is not rooted.
+    pub fn into_future(ctx: GcPointer<Context>, value: JsValue) -> PromiseFuture {
+        PromiseFuture {
+            promise: ctx.vm().add_persistent_root(value),
+        }
+    }
+}
+
+/// Future returned by [JsPromise::into_future].
+pub struct PromiseFuture {
+    promise: PersistentRooted,
+}
+
+impl Future for PromiseFuture {
+    type Output = Result<JsValue, JsValue>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut TaskContext) -> Poll<Self::Output> {
+        let value = self.promise.get_value();
+        if !value.is_jsobject() || !value.get_jsobject().is_class(JsPromise::class()) {
+            return Poll::Ready(Ok(value));
+        }
+        let mut object = value.get_jsobject();
+        let promise = object.as_promise_mut();
+        match promise.resolution {
+            Some(resolution) => Poll::Ready(resolution),
+            None => {
+                if !promise.wakers.iter().any(|waker| waker.will_wake(cx.waker())) {
+                    promise.wakers.push(cx.waker().clone());
+                }
+                Poll::Pending
+            }
+        }
+    }
+}
+
 fn array_util_set_value_at(
     ctx: GcPointer<Context>,
     arr_object: &mut GcPointer<JsObject>,
@@ -0,0 +1,87 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! Table of positional native references.
+//!
+//! Snapshots write classes, native functions and other static addresses as their index in this table. The
+//! table keeps references in registration order next to a map from address to index, so finding the index of
+//! a reference while writing the heap is O(1) instead of a scan over all registered references.
+use std::collections::HashMap;
+
+#[derive(Default)]
+pub struct ReferenceMap {
+    references: Vec<usize>,
+    indices: HashMap<usize, u32>,
+}
+
+impl ReferenceMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `reference` at the end of the table unless it is already registered. Returns index of `reference`.
+    pub fn insert(&mut self, reference: usize) -> u32 {
+        let references = &mut self.references;
+        *self.indices.entry(reference).or_insert_with(|| {
+            references.push(reference);
+            references.len() as u32 - 1
+        })
+    }
+
+    /// Index that `reference` is written as.
+    pub fn index_of(&self, reference: usize) -> Option<u32> {
+        self.indices.get(&reference).copied()
+    }
+
+    /// Reference that `index` was written for.
+    pub fn get(&self, index: u32) -> Option<usize> {
+        self.references.get(index as usize).copied()
+    }
+
+    pub fn contains(&self, reference: usize) -> bool {
+        self.indices.contains_key(&reference)
+    }
+
+    pub fn len(&self) -> usize {
+        self.references.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.references.is_empty()
+    }
+
+    /// References in index order.
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.references.iter().copied()
+    }
+}
+
+impl From<Vec<usize>> for ReferenceMap {
+    fn from(references: Vec<usize>) -> Self {
+        let mut map = Self::new();
+        for reference in references {
+            map.insert(reference);
+        }
+        map
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_indices_follow_registration_order() {
+        let mut map = ReferenceMap::from(vec![0x10, 0x20, 0x10]);
+        assert_eq!(map.len(), 2);
+        assert_eq!(map.insert(0x30), 2);
+        assert_eq!(map.insert(0x20), 1);
+        assert_eq!(map.index_of(0x10), Some(0));
+        assert_eq!(map.index_of(0x30), Some(2));
+        assert_eq!(map.index_of(0x40), None);
+        assert_eq!(map.get(1), Some(0x20));
+        assert_eq!(map.get(3), None);
+        assert_eq!(map.iter().collect::<Vec<_>>(), vec![0x10, 0x20, 0x30]);
+    }
+}
@@ -0,0 +1,156 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! Validation of native references before taking a snapshot.
+//!
+//! A snapshot writes classes and native functions as indices into the table of registered references, so every
+//! native address reachable from the heap has to be listed by a builtin's `native_references`, registered with
+//! [register_external_reference](GcPointer::register_external_reference) or with
+//! [VirtualMachine::register_native](super::VirtualMachine::register_native). Closures can never be written.
+//! The check walks the same graph as [heap snapshots](super::heap_snapshot) and reports the type of every
+//! object with an unregistered address and the path it is reachable through.
+//!
+//! ```rust,ignore
+//! for native in ctx.unregistered_natives() {
+//!     eprintln!("{}", native);
+//! }
+//! ```
+use super::{context::Context, ModuleKind};
+use crate::gc::cell::GcPointer;
+use std::collections::HashSet;
+use std::fmt;
+
+/// Kind of native address held by a heap object.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NativeKind {
+    /// `&'static Class` of an object.
+    Class,
+    /// Function of a `JsNativeFunction`.
+    Function,
+    /// Closure of a `JsClosureFunction`, these have no static address and cannot be registered.
+    Closure,
+    /// Initializer of a native module that was not imported yet.
+    Module,
+}
+
+/// Native address that a snapshot could not write.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnregisteredNative {
+    pub kind: NativeKind,
+    pub address: usize,
+    /// Class name of the object holding the address, `Module` for module initializers.
+    pub class_name: &'static str,
+    /// Heap snapshot name of the object, e.g. the function name.
+    pub name: String,
+    /// Shortest path from the roots, e.g. `global.foo.bar` or `(Stack roots)[2].(context)`.
+    pub path: String,
+}
+
+impl fmt::Display for UnregisteredNative {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self.kind {
+            NativeKind::Class => "class",
+            NativeKind::Function => "native function",
+            NativeKind::Closure => "native closure",
+            NativeKind::Module => "module initializer",
+        };
+        write!(
+            f,
+            "unregistered {} {:#x} of {} '{}' at {}",
+            kind, self.address, self.class_name, self.name, self.path
+        )
+    }
+}
+
+impl GcPointer<Context> {
+    /// Find native addresses reachable from this context that are not registered as snapshot references.
+    pub fn unregistered_natives(self) -> Vec<UnregisteredNative> {
+        let builtins = Context::builtin_native_references()
+            .into_iter()
+            .collect::<HashSet<_>>();
+        let vm = self.vm;
+        let registered = |address: usize| {
+            vm.external_references.contains(address)
+                || vm.reference_keys.contains_key(&address)
+                || builtins.contains(&address)
+        };
+
+        let graph = self.walk_heap_graph();
+        let parents = graph.shortest_paths();
+        let mut unregistered = vec![];
+        for node in 0..graph.node_count() {
+            for &(kind, address) in graph.natives(node) {
+                if kind != NativeKind::Closure && registered(address) {
+                    continue;
+                }
+                unregistered.push(UnregisteredNative {
+                    kind,
+                    address,
+                    class_name: graph.class_name(node),
+                    name: graph.node_name(node).to_owned(),
+                    path: graph.format_path(&parents, node),
+                });
+            }
+        }
+
+        let mut modules = self
+            .modules
+            .iter()
+            .filter_map(|(name, module)| match module {
+                ModuleKind::NativeUninit(init) => Some((name.clone(), *init as usize)),
+                ModuleKind::Initialized(_) => None,
+            })
+            .collect::<Vec<_>>();
+        modules.sort();
+        for (name, address) in modules {
+            if !registered(address) {
+                unregistered.push(UnregisteredNative {
+                    kind: NativeKind::Module,
+                    address,
+                    class_name: "Module",
+                    path: format!("(modules).{}", name),
+                    name,
+                });
+            }
+        }
+        unregistered
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::gc::cell::GcPointer;
+    use crate::vm::context::Context;
+    use crate::vm::symbol_table::Internable;
+    use crate::vm::tests::new_context;
+    use crate::vm::value::JsValue;
+
+    #[test]
+    fn test_unregistered_natives() {
+        use crate::vm::{
+            arguments::Arguments, function::JsNativeFunction, snapshot_check::NativeKind,
+        };
+        fn unregistered(_: GcPointer<Context>, _: &Arguments) -> Result<JsValue, JsValue> {
+            Ok(JsValue::encode_undefined_value())
+        }
+        let (_vm, mut ctx) = new_context();
+        let func = JsNativeFunction::new(ctx, "unregistered".intern(), unregistered, 0);
+        let mut foo = ctx.eval("var foo = {}; foo").ok().unwrap().get_jsobject();
+        foo.put(ctx, "bar".intern(), JsValue::new(func), false)
+            .ok()
+            .unwrap();
+
+        let find = |ctx: GcPointer<Context>| {
+            ctx.unregistered_natives()
+                .into_iter()
+                .find(|native| native.address == unregistered as usize)
+        };
+        let native = find(ctx).unwrap();
+        assert_eq!(native.kind, NativeKind::Function);
+        assert_eq!(native.name, "(native function)");
+        assert_eq!(native.path, "global.foo.bar");
+        ctx.register_external_reference(unregistered as usize);
+        assert!(find(ctx).is_none());
+    }
+}
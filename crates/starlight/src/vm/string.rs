@@ -7,45 +7,659 @@ use super::{
     object::{EnumerationMode, JsObject, ObjectTag},
     slot::*,
     structure::Structure,
-    symbol_table::{Internable, Symbol},
+    symbol_table::Symbol,
     value::*,
     Context,
 };
 
-use crate::gc::cell::{GcCell, GcPointer, Trace};
+use crate::gc::cell::{GcCell, GcPointer, Trace, Visitor};
 use crate::prelude::*;
-use std::mem::size_of;
+use once_cell::unsync::OnceCell;
+use std::{cell::UnsafeCell, cmp::Ordering, mem::size_of, string::FromUtf16Error};
+
+/// Source of characters for an external string.
+///
+/// External strings do not copy their contents into the GC heap, the resource is kept alive
+/// until the string cell is finalized. Useful for large static or mmap'd text.
+pub trait ExternalStringResource: 'static {
+    /// Length of the resource in bytes.
+    fn len(&self) -> usize {
+        self.slice().len()
+    }
+    /// UTF-8 contents of the resource. Must return the same slice for the whole resource lifetime.
+    fn slice(&self) -> &str;
+}
+
+impl ExternalStringResource for &'static str {
+    fn len(&self) -> usize {
+        str::len(self)
+    }
+    fn slice(&self) -> &str {
+        self
+    }
+}
+
+impl ExternalStringResource for String {
+    fn slice(&self) -> &str {
+        self.as_str()
+    }
+}
+
+pub(crate) enum JsStringRepr {
+    /// Latin-1 code units. When every unit is ASCII they are the UTF-8 contents of the string as well.
+    OneByte { units: Box<[u8]>, ascii: bool },
+    /// UTF-16 code units of a string with characters above U+00FF, possibly with lone surrogates.
+    TwoByte {
+        units: Box<[u16]>,
+        lone_surrogates: bool,
+    },
+    /// UTF-8 contents owned by the embedder. `len` is the length in code units.
+    External {
+        resource: Box<dyn ExternalStringResource>,
+        ascii: bool,
+        len: u32,
+    },
+    /// Concatenation of two strings, `len` is the length in code units. Flattened into code units on first
+    /// access to string contents.
+    Rope {
+        left: GcPointer<JsString>,
+        right: GcPointer<JsString>,
+        len: u32,
+    },
+}
+
+impl JsStringRepr {
+    /// Code units of UTF-8 `string`, one byte per unit when it has no character above U+00FF.
+    fn from_contents(string: &str) -> Self {
+        if string.is_ascii() {
+            Self::OneByte {
+                units: string.as_bytes().into(),
+                ascii: true,
+            }
+        } else {
+            Self::from_non_ascii(string)
+        }
+    }
+
+    /// Same as [JsStringRepr::from_contents] but ASCII `string` gives its buffer to the code units.
+    fn from_owned(string: String) -> Self {
+        if string.is_ascii() {
+            Self::OneByte {
+                units: string.into_bytes().into_boxed_slice(),
+                ascii: true,
+            }
+        } else {
+            Self::from_non_ascii(&string)
+        }
+    }
+
+    fn from_non_ascii(string: &str) -> Self {
+        if string.chars().all(|ch| (ch as u32) <= 0xff) {
+            Self::OneByte {
+                units: string.chars().map(|ch| ch as u8).collect(),
+                ascii: false,
+            }
+        } else {
+            Self::TwoByte {
+                units: string.encode_utf16().collect(),
+                lone_surrogates: false,
+            }
+        }
+    }
+
+    fn from_utf16(units: &[u16]) -> Self {
+        if units.iter().all(|&unit| unit <= 0xff) {
+            Self::OneByte {
+                units: units.iter().map(|&unit| unit as u8).collect(),
+                ascii: units.iter().all(|&unit| unit < 0x80),
+            }
+        } else {
+            Self::TwoByte {
+                units: units.into(),
+                lone_surrogates: char::decode_utf16(units.iter().copied()).any(|ch| ch.is_err()),
+            }
+        }
+    }
+}
+
+/// Concatenations shorter than this are copied right away, ropes do not pay off for them.
+const MIN_ROPE_LENGTH: u32 = 13;
+
+/// Code units of a string as seen by JS.
+#[derive(Clone, Copy)]
+pub enum CodeUnits<'a> {
+    /// Every code unit fits into one byte (Latin-1).
+    OneByte(&'a [u8]),
+    TwoByte(&'a [u16]),
+}
+
+impl CodeUnits<'_> {
+    pub fn len(&self) -> usize {
+        match self {
+            Self::OneByte(units) => units.len(),
+            Self::TwoByte(units) => units.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, index: usize) -> Option<u16> {
+        match self {
+            Self::OneByte(units) => units.get(index).map(|&unit| unit as u16),
+            Self::TwoByte(units) => units.get(index).copied(),
+        }
+    }
+
+    pub fn to_vec(&self) -> Vec<u16> {
+        match self {
+            Self::OneByte(units) => units.iter().map(|&unit| unit as u16).collect(),
+            Self::TwoByte(units) => units.to_vec(),
+        }
+    }
+}
+
+impl PartialEq for CodeUnits<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        match (*self, *other) {
+            (Self::OneByte(x), Self::OneByte(y)) => x == y,
+            (Self::TwoByte(x), Self::TwoByte(y)) => x == y,
+            (Self::OneByte(x), Self::TwoByte(y)) | (Self::TwoByte(y), Self::OneByte(x)) => {
+                x.len() == y.len() && x.iter().zip(y).all(|(&x, &y)| x as u16 == y)
+            }
+        }
+    }
+}
+
+impl Eq for CodeUnits<'_> {}
+
+impl PartialOrd for CodeUnits<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Strings are ordered by code units, which differs from `str` ordering once characters outside of the BMP
+/// are compared with characters above the surrogate range.
+impl Ord for CodeUnits<'_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (*self, *other) {
+            (Self::OneByte(x), Self::OneByte(y)) => x.cmp(y),
+            (Self::TwoByte(x), Self::TwoByte(y)) => x.cmp(y),
+            (Self::OneByte(x), Self::TwoByte(y)) => {
+                x.iter().map(|&unit| unit as u16).cmp(y.iter().copied())
+            }
+            (Self::TwoByte(x), Self::OneByte(y)) => {
+                x.iter().copied().cmp(y.iter().map(|&unit| unit as u16))
+            }
+        }
+    }
+}
+
+/// Second representation of a string, built from the primary one on first use.
+enum Derived {
+    /// UTF-8 contents of a non-ASCII string.
+    Utf8(Box<str>),
+    /// Code units of a non-ASCII external string.
+    OneByte(Box<[u8]>),
+    TwoByte(Box<[u16]>),
+}
+
+impl Derived {
+    /// Bytes of the buffer, it is allocated outside of the GC heap.
+    fn size(&self) -> usize {
+        match self {
+            Derived::Utf8(string) => string.len(),
+            Derived::OneByte(units) => units.len(),
+            Derived::TwoByte(units) => units.len() * size_of::<u16>(),
+        }
+    }
+}
 
 #[repr(C)]
 pub struct JsString {
-    pub string: String,
+    pub(crate) repr: UnsafeCell<JsStringRepr>,
+    derived: OnceCell<Derived>,
 }
 
 impl JsString {
     pub fn is_empty(&self) -> bool {
-        self.string.is_empty()
+        self.utf16_len() == 0
+    }
+
+    fn allocate(mut ctx: GcPointer<Context>, repr: JsStringRepr) -> GcPointer<Self> {
+        ctx.heap().allocate(Self {
+            repr: UnsafeCell::new(repr),
+            derived: OnceCell::new(),
+        })
+    }
+
+    pub fn new(ctx: GcPointer<Context>, as_str: impl AsRef<str>) -> GcPointer<Self> {
+        Self::allocate(ctx, JsStringRepr::from_contents(as_str.as_ref()))
     }
-    pub fn new(mut ctx: GcPointer<Context>, as_str: impl AsRef<str>) -> GcPointer<Self> {
-        let str = as_str.as_ref();
-        let proto = Self {
-            string: str.to_owned(),
+
+    /// Create string from UTF-16 code units. Lone surrogates are kept in [JsString::code_units], UTF-8
+    /// contents returned by [JsString::as_str] have them replaced with U+FFFD.
+    pub fn from_utf16(ctx: GcPointer<Context>, units: &[u16]) -> GcPointer<Self> {
+        Self::allocate(ctx, JsStringRepr::from_utf16(units))
+    }
+
+    /// Create string from UTF-8 `bytes`, replacing invalid sequences with U+FFFD.
+    pub fn from_utf8_lossy(ctx: GcPointer<Context>, bytes: &[u8]) -> GcPointer<Self> {
+        Self::new(ctx, String::from_utf8_lossy(bytes))
+    }
+
+    /// Create string from UTF-8 `bytes`, failing on invalid sequences.
+    pub fn from_utf8(
+        ctx: GcPointer<Context>,
+        bytes: &[u8],
+    ) -> Result<GcPointer<Self>, std::str::Utf8Error> {
+        Ok(Self::new(ctx, std::str::from_utf8(bytes)?))
+    }
+
+    /// Create string that references `str` directly without copying it into the heap.
+    pub fn new_external(ctx: GcPointer<Context>, str: &'static str) -> GcPointer<Self> {
+        Self::new_external_resource(ctx, str)
+    }
+
+    /// Create string backed by external `resource`. `resource` is dropped when string cell is finalized.
+    /// Resources with non-ASCII contents get a copy of their code units on first indexed access.
+    pub fn new_external_resource(
+        ctx: GcPointer<Context>,
+        resource: impl ExternalStringResource,
+    ) -> GcPointer<Self> {
+        let contents = resource.slice();
+        let ascii = contents.is_ascii();
+        let len = if ascii {
+            contents.len()
+        } else {
+            contents.encode_utf16().count()
+        };
+        let repr = JsStringRepr::External {
+            resource: Box::new(resource),
+            ascii,
+            len: len as _,
         };
-        let cell = ctx.heap().allocate(proto);
+        Self::allocate(ctx, repr)
+    }
+
+    /// Concatenate `left` and `right` without copying their contents. Repeated concatenation builds a tree
+    /// that is flattened once when the contents are needed, so building a string in a loop is linear.
+    pub fn concat(
+        ctx: GcPointer<Context>,
+        left: GcPointer<JsString>,
+        right: GcPointer<JsString>,
+    ) -> GcPointer<Self> {
+        if left.is_empty() {
+            return right;
+        }
+        if right.is_empty() {
+            return left;
+        }
+        let len = left.utf16_len() + right.utf16_len();
+        if len < MIN_ROPE_LENGTH {
+            let mut units = left.code_units().to_vec();
+            units.extend(right.code_units().to_vec());
+            return Self::from_utf16(ctx, &units);
+        }
+        Self::allocate(ctx, JsStringRepr::Rope { left, right, len })
+    }
 
-        cell
+    fn repr(&self) -> &JsStringRepr {
+        unsafe { &*self.repr.get() }
     }
 
+    /// Returns true if string contents are not owned by the GC heap.
+    pub fn is_external(&self) -> bool {
+        matches!(self.repr(), JsStringRepr::External { .. })
+    }
+
+    /// Returns true if string is a concatenation that was not flattened yet.
+    pub fn is_rope(&self) -> bool {
+        matches!(self.repr(), JsStringRepr::Rope { .. })
+    }
+
+    /// Flat strings of a rope in order. Ropes built in loops are deep, they are walked without recursion.
+    fn leaves(&self) -> Vec<GcPointer<JsString>> {
+        let mut leaves = vec![];
+        let mut stack = vec![];
+        if let JsStringRepr::Rope { left, right, .. } = self.repr() {
+            stack.push(*right);
+            stack.push(*left);
+        }
+        while let Some(node) = stack.pop() {
+            match node.repr() {
+                JsStringRepr::Rope { left, right, .. } => {
+                    stack.push(*right);
+                    stack.push(*left);
+                }
+                _ => leaves.push(node),
+            }
+        }
+        leaves
+    }
+
+    #[cold]
+    #[inline(never)]
+    fn flatten(&self) {
+        let leaves = self.leaves();
+        let len = self.utf16_len() as usize;
+        // halves of a surrogate pair may meet in the flat string, so lone surrogates are searched again.
+        let repr = if leaves.iter().all(|leaf| leaf.is_one_byte()) {
+            let mut units = Vec::with_capacity(len);
+            for leaf in leaves.iter() {
+                if let CodeUnits::OneByte(bytes) = leaf.code_units() {
+                    units.extend_from_slice(bytes);
+                }
+            }
+            JsStringRepr::OneByte {
+                ascii: units.is_ascii(),
+                units: units.into_boxed_slice(),
+            }
+        } else {
+            let mut units = Vec::with_capacity(len);
+            for leaf in leaves.iter() {
+                match leaf.code_units() {
+                    CodeUnits::OneByte(bytes) => {
+                        units.extend(bytes.iter().map(|&unit| unit as u16))
+                    }
+                    CodeUnits::TwoByte(two_byte) => units.extend_from_slice(two_byte),
+                }
+            }
+            JsStringRepr::TwoByte {
+                lone_surrogates: char::decode_utf16(units.iter().copied()).any(|ch| ch.is_err()),
+                units: units.into_boxed_slice(),
+            }
+        };
+        // SAFETY: contents of a string never change, flattening only replaces its representation and
+        // no reference into the rope can be alive since ropes never hand out their contents.
+        unsafe {
+            *self.repr.get() = repr;
+        }
+    }
+
+    /// UTF-8 contents of this string. Lone surrogates are replaced with U+FFFD, use [JsString::to_str] to
+    /// detect them or [JsString::code_units] to keep them. Only ASCII and external strings are stored as
+    /// UTF-8, others build it on the first call and keep it next to their code units.
     pub fn as_str(&self) -> &str {
-        &self.string
+        let derived = match self.repr() {
+            // SAFETY: ASCII is valid UTF-8.
+            JsStringRepr::OneByte { units, ascii: true } => unsafe {
+                return std::str::from_utf8_unchecked(units);
+            },
+            JsStringRepr::External { resource, .. } => return resource.slice(),
+            JsStringRepr::Rope { .. } => {
+                self.flatten();
+                return self.as_str();
+            }
+            JsStringRepr::OneByte { units, .. } => self.derived.get_or_init(|| {
+                Derived::Utf8(
+                    units
+                        .iter()
+                        .map(|&unit| unit as char)
+                        .collect::<String>()
+                        .into_boxed_str(),
+                )
+            }),
+            JsStringRepr::TwoByte { units, .. } => self
+                .derived
+                .get_or_init(|| Derived::Utf8(String::from_utf16_lossy(units).into_boxed_str())),
+        };
+        match derived {
+            Derived::Utf8(string) => string,
+            _ => unreachable!(),
+        }
     }
 
+    /// Length of UTF-8 contents in bytes, the size of [JsString::as_str]. This is not the JS `length` of the
+    /// string and differs from it for every non-ASCII string, code that deals with JS indices must use
+    /// [JsString::utf16_len] instead. Computed from code units without building UTF-8 contents.
     pub fn len(&self) -> u32 {
-        self.string.len() as _
+        match self.repr() {
+            JsStringRepr::OneByte { units, ascii: true } => units.len() as _,
+            JsStringRepr::OneByte { units, .. } => {
+                (units.len() + units.iter().filter(|&&unit| unit >= 0x80).count()) as _
+            }
+            JsStringRepr::TwoByte { units, .. } => char::decode_utf16(units.iter().copied())
+                .map(|ch| ch.map_or(3, char::len_utf8) as u32)
+                .sum(),
+            JsStringRepr::External { resource, .. } => resource.len() as _,
+            JsStringRepr::Rope { .. } => self.leaves().iter().map(|leaf| leaf.len()).sum(),
+        }
+    }
+
+    /// UTF-8 contents of this string, failing if it contains lone surrogates.
+    pub fn to_str(&self) -> Result<&str, FromUtf16Error> {
+        match self.code_units() {
+            CodeUnits::TwoByte(units) if !self.is_well_formed() => {
+                Err(String::from_utf16(units).unwrap_err())
+            }
+            _ => Ok(self.as_str()),
+        }
+    }
+
+    /// Returns false if this string contains lone surrogates, like `String.prototype.isWellFormed`.
+    pub fn is_well_formed(&self) -> bool {
+        match self.repr() {
+            JsStringRepr::TwoByte {
+                lone_surrogates, ..
+            } => !lone_surrogates,
+            JsStringRepr::Rope { .. } => {
+                self.flatten();
+                self.is_well_formed()
+            }
+            _ => true,
+        }
+    }
+
+    /// UTF-16 code units of this string. Strings that contain only Latin-1 characters are exposed as one
+    /// byte per code unit.
+    pub fn code_units(&self) -> CodeUnits<'_> {
+        let derived = match self.repr() {
+            JsStringRepr::OneByte { units, .. } => return CodeUnits::OneByte(units),
+            JsStringRepr::TwoByte { units, .. } => return CodeUnits::TwoByte(units),
+            JsStringRepr::External {
+                resource,
+                ascii: true,
+                ..
+            } => return CodeUnits::OneByte(resource.slice().as_bytes()),
+            JsStringRepr::Rope { .. } => {
+                self.flatten();
+                return self.code_units();
+            }
+            JsStringRepr::External { resource, .. } => {
+                self.derived
+                    .get_or_init(|| match JsStringRepr::from_contents(resource.slice()) {
+                        JsStringRepr::OneByte { units, .. } => Derived::OneByte(units),
+                        JsStringRepr::TwoByte { units, .. } => Derived::TwoByte(units),
+                        _ => unreachable!(),
+                    })
+            }
+        };
+        match derived {
+            Derived::OneByte(units) => CodeUnits::OneByte(units),
+            Derived::TwoByte(units) => CodeUnits::TwoByte(units),
+            Derived::Utf8(_) => unreachable!(),
+        }
+    }
+
+    /// Returns true if every code unit of this string fits into one byte.
+    pub fn is_one_byte(&self) -> bool {
+        matches!(self.code_units(), CodeUnits::OneByte(_))
+    }
+
+    /// Length in UTF-16 code units, the value of JS `length` property. Ropes are not flattened.
+    pub fn utf16_len(&self) -> u32 {
+        match self.repr() {
+            JsStringRepr::OneByte { units, .. } => units.len() as _,
+            JsStringRepr::TwoByte { units, .. } => units.len() as _,
+            JsStringRepr::External { len, .. } | JsStringRepr::Rope { len, .. } => *len,
+        }
+    }
+
+    /// Bytes this string owns outside of the GC heap: flat code units and the derived representation once
+    /// built. External resources are not counted, the embedder owns them.
+    pub(crate) fn owned_size(&self) -> usize {
+        let units = match self.repr() {
+            JsStringRepr::OneByte { units, .. } => units.len(),
+            JsStringRepr::TwoByte { units, .. } => units.len() * size_of::<u16>(),
+            _ => 0,
+        };
+        units + self.derived.get().map_or(0, Derived::size)
+    }
+
+    /// UTF-16 code unit at `index`.
+    pub fn code_unit_at(&self, index: u32) -> Option<u16> {
+        self.code_units().get(index as _)
+    }
+
+    /// Returns true if both strings have the same code units. Unlike comparing [JsString::as_str], lone
+    /// surrogates are neither equal to each other nor to U+FFFD.
+    pub fn equals(&self, other: &JsString) -> bool {
+        std::ptr::eq(self, other) || self.code_units() == other.code_units()
+    }
+
+    /// String naming property `key`. Keys interned from strings with lone surrogates keep them.
+    pub fn from_key(ctx: GcPointer<Context>, key: Symbol) -> GcPointer<JsString> {
+        match key {
+            Symbol::Key(id) | Symbol::Private(id) => match symbol_table().code_units(id) {
+                Some(units) => Self::from_utf16(ctx, units),
+                None => Self::new(ctx, symbol_table().description(id)),
+            },
+            Symbol::Index(index) => Self::new(ctx, index.to_string()),
+        }
+    }
+
+    /// String made of the single code unit at `index`, i.e. result of `str[index]`.
+    pub fn char_at(&self, ctx: GcPointer<Context>, index: u32) -> Option<GcPointer<JsString>> {
+        let unit = self.code_unit_at(index)?;
+        Some(JsString::from_utf16(ctx, &[unit]))
+    }
+}
+
+/// Accumulates segments and creates a single flat [JsString] from them, used by builtins that join many
+/// values instead of concatenating intermediate strings. Segments are copied when appended so the builder
+/// holds no GC references and can live on the Rust stack across allocations.
+///
+/// ```rust,ignore
+/// let mut builder = StringBuilder::new();
+/// builder.append_str("length: ");
+/// builder.append_value(ctx, JsValue::new(42))?;
+/// let string = builder.build(ctx);
+/// ```
+#[derive(Default)]
+pub struct StringBuilder {
+    string: String,
+    /// Code units of the contents once a segment with lone surrogates was appended, `string` is unused then.
+    units: Option<Vec<u16>>,
+}
+
+impl StringBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create builder with room for `capacity` bytes of UTF-8 contents.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            string: String::with_capacity(capacity),
+            units: None,
+        }
+    }
+
+    /// Length of the contents in bytes of UTF-8, or in code units after a lone surrogate was appended.
+    pub fn len(&self) -> usize {
+        match self.units {
+            Some(ref units) => units.len(),
+            None => self.string.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Reserve room for `additional` more bytes.
+    pub fn reserve(&mut self, additional: usize) {
+        match self.units {
+            Some(ref mut units) => units.reserve(additional),
+            None => self.string.reserve(additional),
+        }
+    }
+
+    pub fn append_str(&mut self, str: &str) {
+        match self.units {
+            Some(ref mut units) => units.extend(str.encode_utf16()),
+            None => self.string.push_str(str),
+        }
+    }
+
+    pub fn append_char(&mut self, ch: char) {
+        match self.units {
+            Some(ref mut units) => units.extend(ch.encode_utf16(&mut [0; 2]).iter()),
+            None => self.string.push(ch),
+        }
+    }
+
+    /// Append contents of `string`, keeping its lone surrogates.
+    pub fn append(&mut self, string: GcPointer<JsString>) {
+        if string.is_well_formed() && self.units.is_none() {
+            self.string.push_str(string.as_str());
+            return;
+        }
+        let string_units = string.code_units();
+        if self.units.is_none() {
+            let mut units = Vec::with_capacity(self.string.len() + string_units.len());
+            units.extend(self.string.encode_utf16());
+            self.string = String::new();
+            self.units = Some(units);
+        }
+        let units = self.units.as_mut().unwrap();
+        match string_units {
+            CodeUnits::OneByte(bytes) => units.extend(bytes.iter().map(|&unit| unit as u16)),
+            CodeUnits::TwoByte(two_byte) => units.extend_from_slice(two_byte),
+        }
+    }
+
+    /// Append `value` converted to a string. Strings are appended without an intermediate copy.
+    pub fn append_value(&mut self, ctx: GcPointer<Context>, value: JsValue) -> Result<(), JsValue> {
+        if value.is_jsstring() {
+            self.append(value.get_jsstring());
+        } else {
+            self.append_str(&value.to_string(ctx)?);
+        }
+        Ok(())
+    }
+
+    /// Create string from the contents appended so far.
+    pub fn build(self, ctx: GcPointer<Context>) -> GcPointer<JsString> {
+        match self.units {
+            Some(units) => JsString::from_utf16(ctx, &units),
+            // ASCII buffer becomes the code units of the string, `JsString::new` would copy it once more.
+            None => JsString::allocate(ctx, JsStringRepr::from_owned(self.string)),
+        }
+    }
+}
+
+impl std::fmt::Write for StringBuilder {
+    fn write_str(&mut self, str: &str) -> std::fmt::Result {
+        self.append_str(str);
+        Ok(())
     }
 }
 
-impl Trace for JsString {}
+impl Trace for JsString {
+    fn trace(&self, visitor: &mut Visitor) {
+        if let JsStringRepr::Rope { left, right, .. } = self.repr() {
+            left.trace(visitor);
+            right.trace(visitor);
+        }
+    }
+}
 impl GcCell for JsString {
+    /// Size of the cell allocation. Code units and derived contents live in Rust allocations that
+    /// [JsString::owned_size] reports to heap snapshots and [memory usage](super::memory_usage).
     fn compute_size(&self) -> usize {
         size_of::<Self>()
     }
@@ -66,12 +680,8 @@ impl JsClass for JsStringObject {
         slot: &mut Slot,
     ) -> bool {
         let value = obj.as_string_object().value;
-        if index < value.len() {
-            let ch = value.as_str().chars().nth(index as usize).unwrap();
-            slot.set(
-                JsValue::encode_object_value(JsString::new(ctx, ch.to_string())),
-                string_indexed(),
-            );
+        if let Some(ch) = value.char_at(ctx, index) {
+            slot.set(JsValue::encode_object_value(ch), string_indexed());
             return true;
         }
         JsObject::GetOwnIndexedPropertySlotMethod(obj, ctx, index, slot)
@@ -83,10 +693,10 @@ impl JsClass for JsStringObject {
         mode: EnumerationMode,
     ) {
         if mode == EnumerationMode::IncludeNotEnumerable {
-            collector("length".intern(), 0);
+            collector(Symbol::LENGTH, 0);
         }
         let value = obj.as_string_object().value;
-        for i in 0..value.len() {
+        for i in 0..value.utf16_len() {
             collector(Symbol::Index(i), i);
         }
         JsObject::GetOwnPropertyNamesMethod(obj, ctx, collector, mode)
@@ -99,8 +709,8 @@ impl JsClass for JsStringObject {
         slot: &mut Slot,
     ) -> bool {
         let value = obj.as_string_object().value;
-        if name == "length".intern() {
-            slot.set(JsValue::new(value.len() as f64), string_length());
+        if name == Symbol::LENGTH {
+            slot.set(JsValue::new(value.utf16_len() as f64), string_length());
             return true;
         }
         JsObject::GetOwnNonIndexedPropertySlotMethod(obj, ctx, name, slot)
@@ -132,3 +742,175 @@ impl JsStringObject {
         obj
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::vm::string::{ExternalStringResource, JsString};
+    use crate::vm::symbol_table::Internable;
+    use crate::vm::tests::new_context;
+    use crate::vm::value::JsValue;
+    use std::{cell::Cell, rc::Rc};
+
+    #[test]
+    fn test_string_rope() {
+        let (_vm, mut ctx) = new_context();
+
+        let result = ctx
+            .eval("var s = ''; for (var i = 0; i < 100000; i++) { s += 'abcdefghij'; } s")
+            .ok()
+            .unwrap();
+        let string = result.get_jsstring();
+        assert!(string.is_rope());
+        assert_eq!(string.len(), 1000000);
+        assert_eq!(string.utf16_len(), 1000000);
+        assert!(string.is_rope());
+        assert!(string.as_str().starts_with("abcdefghijabcdefghij"));
+        assert!(!string.is_rope());
+
+        let result = ctx.eval("'x'.repeat(20) + 1 + 'y'").ok().unwrap();
+        assert_eq!(result.get_jsstring().as_str(), "xxxxxxxxxxxxxxxxxxxx1y");
+    }
+
+    #[test]
+    fn test_string_code_units() {
+        let (_vm, mut ctx) = new_context();
+
+        assert!(JsString::new(ctx, "abc").is_one_byte());
+        let cafe = JsString::new(ctx, "caf\u{e9}");
+        assert!(cafe.is_one_byte());
+        assert_eq!(cafe.utf16_len(), 4);
+        assert_eq!(cafe.len(), 5);
+        // Latin-1 code units are the contents, UTF-8 is built on demand.
+        assert_eq!(cafe.owned_size(), 4);
+        assert_eq!(cafe.as_str(), "caf\u{e9}");
+        assert_eq!(cafe.owned_size(), 4 + 5);
+        let emoji = JsString::new(ctx, "a\u{1F600}");
+        assert!(!emoji.is_one_byte());
+        assert_eq!(emoji.utf16_len(), 3);
+        assert_eq!(emoji.code_unit_at(1), Some(0xD83D));
+
+        let result = ctx
+            .eval(
+                "var s = 'caf\u{e9}\u{1F600}';
+                 [s.length, s.charCodeAt(3), s.charCodeAt(5), s.codePointAt(4), s[3] === s.charAt(3)].join()",
+            )
+            .ok()
+            .unwrap();
+        assert_eq!(result.get_jsstring().as_str(), "6,233,56832,128512,true");
+    }
+
+    #[test]
+    fn test_string_utf16() {
+        let (_vm, mut ctx) = new_context();
+
+        let units = [0x61, 0xD83D, 0xDE00, 0xD800];
+        let string = JsString::from_utf16(ctx, &units);
+        assert_eq!(string.code_units().to_vec(), units);
+        assert_eq!(string.as_str(), "a\u{1F600}\u{FFFD}");
+        assert!(!string.is_well_formed());
+        assert!(string.to_str().is_err());
+        assert_eq!(
+            JsString::from_utf16(ctx, &units[..3]).to_str().ok(),
+            Some("a\u{1F600}")
+        );
+        assert!(JsString::from_utf8(ctx, b"\xff").is_err());
+        assert_eq!(
+            JsString::from_utf8_lossy(ctx, b"a\xff").as_str(),
+            "a\u{FFFD}"
+        );
+
+        ctx.global_object()
+            .put(ctx, "s".intern(), JsValue::new(string), false)
+            .ok()
+            .unwrap();
+        let result = ctx
+            .eval("[s.length, s.charCodeAt(3), (s[1] + s[2]).codePointAt(0)].join()")
+            .ok()
+            .unwrap();
+        assert_eq!(result.get_jsstring().as_str(), "4,55296,128512");
+    }
+
+    struct Resource {
+        text: String,
+        drops: Rc<Cell<usize>>,
+    }
+
+    impl ExternalStringResource for Resource {
+        fn slice(&self) -> &str {
+            &self.text
+        }
+    }
+
+    impl Drop for Resource {
+        fn drop(&mut self) {
+            self.drops.set(self.drops.get() + 1);
+        }
+    }
+
+    #[test]
+    fn test_external_string() {
+        let (vm, mut ctx) = new_context();
+
+        let text = "external text";
+        let string = JsString::new_external(ctx, text);
+        assert!(string.is_external());
+        assert_eq!(string.as_str().as_ptr(), text.as_ptr());
+        let drops = Rc::new(Cell::new(0));
+        let resource = JsString::new_external_resource(
+            ctx,
+            Resource {
+                text: "caf\u{e9}".to_owned(),
+                drops: drops.clone(),
+            },
+        );
+        let mut global = ctx.global_object();
+        global
+            .put(ctx, "s".intern(), JsValue::new(string), false)
+            .ok()
+            .unwrap();
+        global
+            .put(ctx, "r".intern(), JsValue::new(resource), false)
+            .ok()
+            .unwrap();
+
+        ctx.heap().gc();
+        let result = ctx
+            .eval("[s.length, s.indexOf('text'), r.length, r.charCodeAt(3)].join()")
+            .ok()
+            .unwrap();
+        assert_eq!(result.get_jsstring().as_str(), "13,9,4,233");
+        assert_eq!(drops.get(), 0);
+        // resources of live strings are dropped with the heap.
+        unsafe { vm.dispose() };
+        assert_eq!(drops.get(), 1);
+    }
+
+    #[test]
+    fn test_string_builder() {
+        use crate::vm::string::StringBuilder;
+        let (_vm, mut ctx) = new_context();
+
+        let mut builder = StringBuilder::new();
+        builder.append_str("a");
+        builder.append_char('\u{e9}');
+        builder.append_value(ctx, JsValue::new(42)).ok().unwrap();
+        let string = builder.build(ctx);
+        assert_eq!(string.as_str(), "a\u{e9}42");
+        assert!(!string.is_rope());
+
+        // lone surrogates of appended strings are kept
+        let mut builder = StringBuilder::new();
+        builder.append_str("x");
+        builder.append(JsString::from_utf16(ctx, &[0xD83D]));
+        builder.append(JsString::from_utf16(ctx, &[0xDE00]));
+        assert_eq!(builder.build(ctx).as_str(), "x\u{1F600}");
+
+        let result = ctx
+            .eval(
+                "[[1, null, 'b', undefined].join('-'), 'a'.concat(1, [2, 3]), [].join()].join('|')",
+            )
+            .ok()
+            .unwrap();
+        assert_eq!(result.get_jsstring().as_str(), "1--b-|a12,3|");
+    }
+}
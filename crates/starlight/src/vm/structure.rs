@@ -1,12 +1,16 @@
 /* This Source Code Form is subject to the terms of the Mozilla Public
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
-use super::{attributes::*, object::JsObject, structure_chain::StructureChain};
+use super::{
+    attributes::*, native_iterator::EnumerationCache, object::JsObject,
+    structure_chain::StructureChain,
+};
 use super::{symbol_table::*, Context};
 use crate::gc::cell::Visitor;
 use crate::gc::cell::{GcCell, GcPointer, Trace};
 use crate::prelude::*;
-use std::{collections::HashMap, intrinsics::likely};
+use crate::utils::likely;
+use std::collections::HashMap;
 use wtf_rs::unwrap_unchecked;
 /// In JavaScript programs, it's common to have multiple objects with the same property keys. Such objects
 /// have the same *shape*.
@@ -48,10 +52,24 @@ pub struct Structure {
     pub(crate) transit_count: u32,
     pub(crate) has_been_flattened_before: bool,
     pub(crate) cached_prototype_chain: Option<GcPointer<StructureChain>>,
+    /// Structure is owned by a single object in dictionary mode and is mutated in place on add, delete and
+    /// attribute change. Slots loaded from dictionary are not cacheable since offsets are reused.
+    pub(crate) dictionary: bool,
+    /// Number of deletes performed on the owning object since it left the shared transition tree.
+    pub(crate) deletes: u32,
+    /// Number of lookups since last mutation of dictionary.
+    pub(crate) stable_reads: u32,
+    /// Keys enumerated by for-in over objects of this structure, see [EnumerationCache].
+    pub(crate) enum_cache: Option<EnumerationCache>,
 }
 
 pub type StructureID = u32;
 
+/// Object switches to dictionary mode after this number of deleted properties.
+pub const DICTIONARY_DELETE_THRESHOLD: u32 = 8;
+/// Dictionary is converted back to cacheable structure after this number of lookups without mutation.
+pub const DICTIONARY_STABLE_READS: u32 = 1024;
+
 #[derive(Copy, Clone)]
 pub struct MapEntry {
     pub offset: u32,
@@ -188,6 +206,9 @@ impl Trace for Structure {
         self.table.trace(tracer);
         self.prototype.trace(tracer);
         self.deleted.entry.trace(tracer);
+        if let Some(cache) = self.enum_cache.as_ref() {
+            cache.trace(tracer);
+        }
         match self.previous.as_ref() {
             Some(x) => {
                 x.trace(tracer);
@@ -300,6 +321,10 @@ impl Structure {
             transit_count: 0,
             has_been_flattened_before: previous.has_been_flattened_before,
             cached_prototype_chain: None,
+            dictionary: false,
+            deletes: if unique { previous.deletes } else { 0 },
+            stable_reads: 0,
+            enum_cache: None,
         });
         this.calculated_size = this.get_slots_size() as _;
 
@@ -334,6 +359,10 @@ impl Structure {
             id: 0,
             calculated_size: 0,
             transit_count: 0,
+            dictionary: false,
+            deletes: 0,
+            stable_reads: 0,
+            enum_cache: None,
         });
 
         this
@@ -376,6 +405,10 @@ impl Structure {
             id: 0,
             calculated_size: 0,
             transit_count: 0,
+            dictionary: false,
+            deletes: 0,
+            stable_reads: 0,
+            enum_cache: None,
         });
 
         this.calculated_size = this.get_slots_size() as _;
@@ -600,9 +633,20 @@ impl GcPointer<Structure> {
         if self.is_unique() {
             self.transitions.enable_unique_transition();
             self.has_been_flattened_before = true;
+            self.dictionary = false;
+            self.deletes = 0;
         }
     }
 
+    pub fn is_dictionary(&self) -> bool {
+        self.dictionary
+    }
+
+    /// Returns true if any change of the owning object moves it to another structure.
+    pub fn changes_on_mutation(&self) -> bool {
+        !self.is_unique() || self.transitions.is_enabled_unique_transition()
+    }
+
     pub fn get_slots_size(&self) -> usize {
         if let Some(table) = self.table.as_ref() {
             table.len() + self.deleted.size as usize
@@ -618,13 +662,47 @@ impl GcPointer<Structure> {
         ctx: GcPointer<Context>,
         name: Symbol,
     ) -> GcPointer<Structure> {
+        if self.dictionary {
+            self.delete(ctx, name);
+            self.stable_reads = 0;
+            return *self;
+        }
         let mut map = Structure::new_unique(ctx, *self);
+        map.deletes += 1;
+        if map.deletes >= DICTIONARY_DELETE_THRESHOLD {
+            return map.to_dictionary(ctx).delete_property_transition(ctx, name);
+        }
         if !map.has_table() {
             map.allocate_table(ctx);
         }
         map.delete(ctx, name);
         map
     }
+
+    /// Make a private copy of this structure that is mutated in place from now on.
+    pub fn to_dictionary(&mut self, mut ctx: GcPointer<Context>) -> GcPointer<Structure> {
+        let mut map = Structure::new_unique(ctx, *self);
+        if map.has_table() {
+            // table might still be shared with `self`, which inline caches can refer to.
+            let table = (**map.table.as_ref().unwrap()).clone();
+            map.table = Some(ctx.heap().allocate(table));
+        } else {
+            map.allocate_table(ctx);
+        }
+        map.dictionary = true;
+        map.stable_reads = 0;
+        map
+    }
+
+    /// Leave dictionary mode once the owning object stopped changing. Returned structure is a fresh unique
+    /// structure so it can not collide with any cached one. The delete count is kept, objects used as maps
+    /// go back to dictionary mode on their next delete instead of copying the table on every delete again.
+    pub fn from_dictionary(&mut self, ctx: GcPointer<Context>) -> GcPointer<Structure> {
+        debug_assert!(self.dictionary);
+        let mut map = Structure::new_unique(ctx, *self);
+        map.dictionary = false;
+        map
+    }
     pub fn change_indexed_transition(&mut self, ctx: GcPointer<Context>) -> GcPointer<Structure> {
         if self.is_unique() {
             let mut map = if self.transitions.is_enabled_unique_transition() {
@@ -670,6 +748,11 @@ impl GcPointer<Structure> {
         name: Symbol,
         attributes: AttrSafe,
     ) -> GcPointer<Structure> {
+        if self.dictionary {
+            self.change_attributes(name, attributes);
+            self.stable_reads = 0;
+            return *self;
+        }
         let mut map = Structure::new_unique(ctx, *self);
         if !map.has_table() {
             map.allocate_table(ctx);
@@ -727,6 +810,7 @@ impl GcPointer<Structure> {
                 entry.offset = self.get_slots_size() as _;
             }
             unwrap_unchecked(map.table.as_mut()).insert(name, entry);
+            map.stable_reads = 0;
             *offset = entry.offset;
             return map;
         }
@@ -814,3 +898,150 @@ impl GcPointer<Structure> {
         *self
     }
 }
+
+/// Property of a [Structure] reported by [Structure::describe].
+#[derive(Clone, Copy)]
+pub struct PropertyInfo {
+    pub name: Symbol,
+    /// Offset of the property value in slots of objects with the structure.
+    pub offset: u32,
+    pub attributes: AttrSafe,
+}
+
+/// Snapshot of a [Structure] for debuggers, serializers and shape checks. Objects that share a structure
+/// pointer have the same properties at the same offsets.
+pub struct StructureInfo {
+    pub id: StructureID,
+    /// Own properties ordered by offset.
+    pub properties: Vec<PropertyInfo>,
+    /// Properties added by the transitions this structure was reached by, oldest first. Transitions before
+    /// a structure that owns its property table are not recorded, so unique and dictionary structures and
+    /// structures whose table was built already report only the transitions after it.
+    pub transitions: Vec<PropertyInfo>,
+    /// Prototype shared by objects of this structure, `None` when it is stored in the objects themselves.
+    pub prototype: Option<GcPointer<JsObject>>,
+    pub unique: bool,
+    pub dictionary: bool,
+    pub indexed: bool,
+}
+
+impl GcPointer<Structure> {
+    /// Describe properties, transition history and prototype of the structure. Unlike
+    /// `get_own_property_names` this does not build the property table and leaves the structure untouched.
+    pub fn describe(&self) -> StructureInfo {
+        let mut transitions = vec![];
+        let mut table = None;
+        let mut current = Some(*self);
+        while let Some(structure) = current {
+            if let Some(own) = structure.table {
+                table = Some(own);
+                break;
+            }
+            if structure.is_adding_map() {
+                let (name, entry) = structure.added;
+                transitions.push(PropertyInfo {
+                    name,
+                    offset: entry.offset,
+                    attributes: entry.attrs,
+                });
+            }
+            current = structure.previous;
+        }
+        transitions.reverse();
+
+        let mut properties = table
+            .map(|table| {
+                table
+                    .iter()
+                    .map(|(name, entry)| PropertyInfo {
+                        name: *name,
+                        offset: entry.offset,
+                        attributes: entry.attrs,
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        properties.extend(transitions.iter().copied());
+        properties.sort_by_key(|property| property.offset);
+        StructureInfo {
+            id: self.id,
+            properties,
+            transitions,
+            prototype: self.prototype,
+            unique: self.is_unique(),
+            dictionary: self.dictionary,
+            indexed: self.is_indexed(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::vm::{object::*, tests::new_context, value::*, *};
+
+    #[test]
+    fn test_describe() {
+        let (_vm, mut ctx) = new_context();
+
+        let mut a = JsObject::new_empty(ctx);
+        let mut b = JsObject::new_empty(ctx);
+        for (name, value) in [("x", 1), ("y", 2)].iter() {
+            let value = JsValue::new(*value);
+            a.put(ctx, name.intern(), value, false).ok().unwrap();
+            b.put(ctx, name.intern(), value, false).ok().unwrap();
+        }
+        assert!(GcPointer::ptr_eq(&a.structure(), &b.structure()));
+
+        let info = a.structure().describe();
+        let names = info
+            .properties
+            .iter()
+            .map(|property| ctx.description(property.name))
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["x", "y"]);
+        assert_eq!(info.properties[1].offset, 1);
+        assert!(info.properties[0].attributes.is_enumerable());
+        // lookups of "y" before it was added may have built the table of the previous structure.
+        let last = info.transitions.last().unwrap();
+        assert_eq!(ctx.description(last.name), "y");
+        assert!(GcPointer::ptr_eq(
+            &info.prototype.unwrap(),
+            &ctx.global_data().get_object_prototype()
+        ));
+        assert!(!info.unique && !info.dictionary);
+    }
+
+    #[test]
+    fn test_dictionary_mode() {
+        let (_vm, mut ctx) = new_context();
+
+        let object = ctx
+            .eval(
+                "var o = {};
+                for (var i = 0; i < 200; i++) o['p' + i] = i;
+                for (var i = 0; i < 150; i++) delete o['p' + i];
+                o",
+            )
+            .ok()
+            .unwrap();
+        assert!(object.get_jsobject().structure.is_dictionary());
+
+        // deleted offsets are reused by new properties.
+        let result = ctx
+            .eval("o.q = -1; var s = 0; for (var k in o) s += o[k]; s + ',' + o.p0 + ',' + o.p199")
+            .ok()
+            .unwrap();
+        assert_eq!(result.get_jsstring().as_str(), "8724,undefined,199");
+
+        let result = ctx
+            .eval("var t = 0; for (var i = 0; i < 2000; i++) t += o.p199; t")
+            .ok()
+            .unwrap();
+        assert_eq!(result.get_number(), 398000.0);
+        assert!(!object.get_jsobject().structure.is_dictionary());
+
+        // object that was a dictionary before skips the delete transitions.
+        ctx.eval("delete o.p199").ok().unwrap();
+        assert!(object.get_jsobject().structure.is_dictionary());
+    }
+}
@@ -9,24 +9,41 @@ use crate::gc::cell::{GcCell, GcPointer, Trace, Visitor};
 use crate::prelude::*;
 use crate::vm::object::JsObject;
 use dashmap::DashMap;
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::mem::ManuallyDrop;
 use std::sync::atomic::Ordering;
 use std::{mem::MaybeUninit, sync::atomic::AtomicU32};
 pub struct SymbolTable {
     pub(crate) symbols: DashMap<&'static str, u32>,
     pub(crate) ids: DashMap<u32, &'static str>,
+    /// Keys with lone surrogates. They have no exact UTF-8 form so they are interned by their code units,
+    /// `ids` holds their descriptions with lone surrogates replaced by U+FFFD.
+    utf16_symbols: DashMap<&'static [u16], u32>,
+    utf16_ids: DashMap<u32, &'static [u16]>,
     key: AtomicU32,
 }
 impl Drop for SymbolTable {
     fn drop(&mut self) {
         for entry in self.ids.iter_mut() {
+            if *entry.key() < SymbolID::PUBLIC_START.0 {
+                // descriptions of builtin symbols are static.
+                continue;
+            }
             let key = entry.value();
             unsafe {
                 let _ = Box::from_raw((*key) as *const _ as *mut str);
             }
         }
+        for entry in self.utf16_ids.iter_mut() {
+            unsafe {
+                let _ = Box::from_raw((*entry.value()) as *const _ as *mut [u16]);
+            }
+        }
         self.symbols.clear();
         self.ids.clear();
+        self.utf16_symbols.clear();
+        self.utf16_ids.clear();
     }
 }
 
@@ -40,6 +57,8 @@ impl SymbolTable {
         Self {
             symbols: DashMap::with_capacity(0),
             ids: DashMap::with_capacity(0),
+            utf16_symbols: DashMap::with_capacity(0),
+            utf16_ids: DashMap::with_capacity(0),
             key: AtomicU32::new(128),
         }
     }
@@ -48,36 +67,84 @@ impl SymbolTable {
         *self.ids.get(&symbol.0).unwrap()
     }
     pub fn intern(&self, val: impl AsRef<str>) -> SymbolID {
-        let string = val.as_ref();
-        if let Some(key) = self.symbols.get(string) {
-            return SymbolID(*key.value());
+        self.intern_entry(val.as_ref()).0
+    }
+
+    /// Intern `string` and return its id together with the description stored in this table.
+    fn intern_entry(&self, string: &str) -> (SymbolID, &'static str) {
+        if let Some(entry) = self.symbols.get(string) {
+            return (SymbolID(*entry.value()), *entry.key());
         }
 
         let string = Box::leak(string.to_string().into_boxed_str());
         let make_new_key = || self.key.fetch_add(1, Ordering::Relaxed);
-        let key = *self
-            .symbols
-            .entry(string)
-            .or_insert_with(make_new_key)
-            .value();
+        let entry = self.symbols.entry(string).or_insert_with(make_new_key);
+        let (key, string) = (*entry.value(), *entry.key());
+        drop(entry);
         self.ids.insert(key, string);
+        (SymbolID(key), string)
+    }
+
+    /// Intern key made of code `units` that contain lone surrogates. Well-formed keys must be interned as
+    /// strings with [SymbolTable::intern], otherwise the same name gets two ids.
+    pub fn intern_utf16(&self, units: &[u16]) -> SymbolID {
+        if let Some(entry) = self.utf16_symbols.get(units) {
+            return SymbolID(*entry.value());
+        }
+
+        let units: &'static [u16] = Box::leak(units.into());
+        let make_new_key = || self.key.fetch_add(1, Ordering::Relaxed);
+        let entry = self.utf16_symbols.entry(units).or_insert_with(make_new_key);
+        let (key, units) = (*entry.value(), *entry.key());
+        drop(entry);
+        self.utf16_ids.insert(key, units);
+        self.ids
+            .entry(key)
+            .or_insert_with(|| &*Box::leak(String::from_utf16_lossy(units).into_boxed_str()));
         SymbolID(key)
     }
+
+    /// Code units of `symbol` if it was interned with [SymbolTable::intern_utf16]. Its description is lossy
+    /// then.
+    pub fn code_units(&self, symbol: SymbolID) -> Option<&'static [u16]> {
+        self.utf16_ids.get(&symbol.0).map(|entry| *entry.value())
+    }
+}
+
+thread_local! {
+    /// Symbols interned on this thread. A runtime never leaves the thread it was created on, so this is the
+    /// cache of every runtime of the thread and lookups of known names do not touch the shared [SymbolTable].
+    static SYMBOL_CACHE: RefCell<HashMap<&'static str, SymbolID>> = RefCell::new(HashMap::new());
+}
+
+/// Intern `string` through the symbol cache of the current thread.
+fn intern_cached(string: &str) -> SymbolID {
+    SYMBOL_CACHE.with(|cache| {
+        if let Some(&id) = cache.borrow().get(string) {
+            return id;
+        }
+        let (id, description) = symbol_table().intern_entry(string);
+        cache.borrow_mut().insert(description, id);
+        id
+    })
 }
 
+/// Names that get fixed ids when the symbol table is created. They are available as constants, e.g.
+/// `Symbol::LENGTH`, so hot paths compare property names without interning.
 macro_rules! builtin_symbols {
     ($m: ident) => {
         $m! {
-            /*PROTOTYPE prototype 0,
-            TO_STRING toString 1,
-            CONSTRUCTOR constructor 2,
-            LENGTH length 3,
-            BYTE_LENGTH byteLength 4,
-            GET get 5,
-            SET set 6,
-            CALL call 7,
-            APPLY apply 8*/
-
+            PROTOTYPE prototype 1,
+            TO_STRING toString 2,
+            CONSTRUCTOR constructor 3,
+            LENGTH length 4,
+            BYTE_LENGTH byteLength 5,
+            GET get 6,
+            SET set 7,
+            CALL call 8,
+            APPLY apply 9,
+            NAME name 10,
+            VALUE value 11
         }
     };
 }
@@ -165,13 +232,13 @@ pub static mut SYMBOL_TABLE: MaybeUninit<SymbolTable> = MaybeUninit::uninit();
 
 macro_rules! globals {
     ($($id: ident $val: ident $ix: expr),*) => {
-       $( pub static $id: &'static str = stringify!($val);)*
+       $( static $id: &'static str = stringify!($val);)*
     };
 }
 builtin_symbols!(globals);
 macro_rules! intern_builtins {
     ($($id: ident $val: ident $ix: expr),*) => {
-        let mut _symtab = symbol_table();
+        let _symtab = symbol_table();
         $(
             _symtab.ids.insert($ix,$id);
             _symtab.symbols.insert($id,$ix);
@@ -181,13 +248,12 @@ macro_rules! intern_builtins {
 pub(crate) fn initialize_symbol_table() {
     unsafe {
         SYMBOL_TABLE.as_mut_ptr().write(SymbolTable::new());
-        LENGTH = "length".intern();
     }
     builtin_symbols!(intern_builtins);
 }
 
 pub fn length_id() -> Symbol {
-    unsafe { LENGTH }
+    Symbol::LENGTH
 }
 pub fn symbol_table() -> &'static SymbolTable {
     unsafe { &*SYMBOL_TABLE.as_ptr() }
@@ -198,13 +264,24 @@ pub trait Internable {
 
 impl Internable for str {
     fn intern(&self) -> Symbol {
-        Symbol::Key(symbol_table().intern(self))
+        Symbol::Key(intern_cached(self))
     }
 }
 
 impl Internable for String {
     fn intern(&self) -> Symbol {
-        Symbol::Key(symbol_table().intern(self))
+        Symbol::Key(intern_cached(self))
+    }
+}
+
+impl Internable for JsString {
+    fn intern(&self) -> Symbol {
+        match self.code_units() {
+            CodeUnits::TwoByte(units) if !self.is_well_formed() => {
+                Symbol::Key(symbol_table().intern_utf16(units))
+            }
+            _ => self.as_str().intern(),
+        }
     }
 }
 
@@ -258,8 +335,6 @@ impl std::fmt::Display for SymbolID {
     }
 }
 
-static mut LENGTH: Symbol = Symbol::Key(SymbolID(0));
-
 pub struct JsSymbolObject {
     sym: GcPointer<JsSymbol>,
 }
@@ -290,3 +365,47 @@ impl JsSymbolObject {
         obj
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Platform;
+
+    #[test]
+    fn test_builtin_symbols() {
+        Platform::initialize();
+        assert_eq!("length".intern(), Symbol::LENGTH);
+        assert_eq!("prototype".to_string().intern(), Symbol::PROTOTYPE);
+        assert_eq!(Symbol::CONSTRUCTOR.get_id().to_string(), "constructor");
+
+        // the cache of another thread resolves to the same id.
+        let name = "test_builtin_symbols".intern();
+        assert!(name.get_id() >= SymbolID::PUBLIC_START);
+        let other = std::thread::spawn(|| "test_builtin_symbols".intern())
+            .join()
+            .unwrap();
+        assert_eq!(name, other);
+        assert_eq!("test_builtin_symbols".intern(), name);
+    }
+
+    #[test]
+    fn test_lone_surrogate_keys() {
+        let (_vm, mut ctx) = crate::vm::tests::new_context();
+        let high = JsString::from_utf16(ctx, &[0xD83D]);
+        assert_ne!(high.intern(), "\u{FFFD}".intern());
+        assert_ne!(high.intern(), JsString::from_utf16(ctx, &[0xDE00]).intern());
+        assert_eq!(high.intern(), JsString::from_utf16(ctx, &[0xD83D]).intern());
+
+        let result = ctx
+            .eval(
+                "var s = '\u{1F600}', o = {};
+                 o[s[0]] = 1; o[s[1]] = 2; o['\u{FFFD}'] = 3;
+                 var keys = Object.keys(o), forIn = [];
+                 for (var k in o) forIn.push(k);
+                 [o[s[0]], o[s[1]], keys.length, keys[0] === s[0], forIn[1] === s[1], keys[0] + keys[1] === s].join()",
+            )
+            .ok()
+            .unwrap();
+        assert_eq!(result.get_jsstring().as_str(), "1,2,3,true,true,true");
+    }
+}
@@ -1,6 +1,8 @@
 /* This Source Code Form is subject to the terms of the Mozilla Public
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+use std::arch::asm;
 use wtf_rs::stack_bounds::StackBounds;
 
 pub struct Thread {
@@ -22,27 +24,25 @@ impl Thread {
         let mut buf = std::mem::MaybeUninit::uninit();
         let buf_ptr = buf.as_mut_ptr();
         unsafe {
-            llvm_asm!(
-                "
-                mov [$0+0], rsp
-                mov [$0+8], rax
-                mov [$0+16], rbx
-                mov [$0+24], rcx
-                mov [$0+32], rdx
-                mov [$0+40], rbp
-                mov [$0+48], rsi
-                mov [$0+56], rdi
-                mov [$0+64], r8
-                mov [$0+72], r9
-                mov [$0+80], r10
-                mov [$0+88], r11
-                mov [$0+96], r12
-                mov [$0+104], r13
-                mov [$0+112], r14
-                mov [$0+120], r15
-                
-
-                " :: "r"(buf_ptr) :: "intel"
+            asm!(
+                "mov [{0}+0], rsp",
+                "mov [{0}+8], rax",
+                "mov [{0}+16], rbx",
+                "mov [{0}+24], rcx",
+                "mov [{0}+32], rdx",
+                "mov [{0}+40], rbp",
+                "mov [{0}+48], rsi",
+                "mov [{0}+56], rdi",
+                "mov [{0}+64], r8",
+                "mov [{0}+72], r9",
+                "mov [{0}+80], r10",
+                "mov [{0}+88], r11",
+                "mov [{0}+96], r12",
+                "mov [{0}+104], r13",
+                "mov [{0}+112], r14",
+                "mov [{0}+120], r15",
+                in(reg) buf_ptr,
+                options(nostack, preserves_flags)
             );
         }
         unsafe { buf.assume_init() }
@@ -61,6 +61,15 @@ impl Thread {
         /* TODO */
         []
     }
+    #[cfg(target_arch = "wasm32")]
+    pub fn capture_registers() -> [usize; 0] {
+        /*
+            wasm has no addressable registers, values that are not spilled to the shadow stack are not visible to
+            the conservative scan at all. Embedders must collect only when no cell is referenced from wasm locals,
+            `starlight-wasm` defers GC while JS code runs and collects between evaluations.
+        */
+        []
+    }
     #[cfg(target_arch = "aarch64")]
     pub fn capture_registers() -> [usize; 30] {
         let x0;
@@ -94,36 +103,36 @@ impl Thread {
         let x28;
         let x29;
         unsafe {
-            llvm_asm!("mov $0, x0" : "=r"(x0));
-            llvm_asm!("mov $0, x1" : "=r"(x1));
-            llvm_asm!("mov $0, x2" : "=r"(x2));
-            llvm_asm!("mov $0, x3" : "=r"(x3));
-            llvm_asm!("mov $0, x4" : "=r"(x4));
-            llvm_asm!("mov $0, x5" : "=r"(x5));
-            llvm_asm!("mov $0, x6" : "=r"(x6));
-            llvm_asm!("mov $0, x7" : "=r"(x7));
-            llvm_asm!("mov $0, x8" : "=r"(x8));
-            llvm_asm!("mov $0, x9" : "=r"(x9));
-            llvm_asm!("mov $0, x10" : "=r"(x10));
-            llvm_asm!("mov $0, x11" : "=r"(x11));
-            llvm_asm!("mov $0, x12" : "=r"(x12));
-            llvm_asm!("mov $0, x13" : "=r"(x13));
-            llvm_asm!("mov $0, x14" : "=r"(x14));
-            llvm_asm!("mov $0, x15" : "=r"(x15));
-            llvm_asm!("mov $0, x16" : "=r"(x16));
-            llvm_asm!("mov $0, x17" : "=r"(x17));
-            llvm_asm!("mov $0, x18" : "=r"(x18));
-            llvm_asm!("mov $0, x19" : "=r"(x19));
-            llvm_asm!("mov $0, x20" : "=r"(x20));
-            llvm_asm!("mov $0, x21" : "=r"(x21));
-            llvm_asm!("mov $0, x22" : "=r"(x22));
-            llvm_asm!("mov $0, x23" : "=r"(x23));
-            llvm_asm!("mov $0, x24" : "=r"(x24));
-            llvm_asm!("mov $0, x25" : "=r"(x25));
-            llvm_asm!("mov $0, x26" : "=r"(x26));
-            llvm_asm!("mov $0, x27" : "=r"(x27));
-            llvm_asm!("mov $0, x28" : "=r"(x28));
-            llvm_asm!("mov $0, x29" : "=r"(x29));
+            asm!("mov {}, x0", out(reg) x0, options(nomem, nostack, preserves_flags));
+            asm!("mov {}, x1", out(reg) x1, options(nomem, nostack, preserves_flags));
+            asm!("mov {}, x2", out(reg) x2, options(nomem, nostack, preserves_flags));
+            asm!("mov {}, x3", out(reg) x3, options(nomem, nostack, preserves_flags));
+            asm!("mov {}, x4", out(reg) x4, options(nomem, nostack, preserves_flags));
+            asm!("mov {}, x5", out(reg) x5, options(nomem, nostack, preserves_flags));
+            asm!("mov {}, x6", out(reg) x6, options(nomem, nostack, preserves_flags));
+            asm!("mov {}, x7", out(reg) x7, options(nomem, nostack, preserves_flags));
+            asm!("mov {}, x8", out(reg) x8, options(nomem, nostack, preserves_flags));
+            asm!("mov {}, x9", out(reg) x9, options(nomem, nostack, preserves_flags));
+            asm!("mov {}, x10", out(reg) x10, options(nomem, nostack, preserves_flags));
+            asm!("mov {}, x11", out(reg) x11, options(nomem, nostack, preserves_flags));
+            asm!("mov {}, x12", out(reg) x12, options(nomem, nostack, preserves_flags));
+            asm!("mov {}, x13", out(reg) x13, options(nomem, nostack, preserves_flags));
+            asm!("mov {}, x14", out(reg) x14, options(nomem, nostack, preserves_flags));
+            asm!("mov {}, x15", out(reg) x15, options(nomem, nostack, preserves_flags));
+            asm!("mov {}, x16", out(reg) x16, options(nomem, nostack, preserves_flags));
+            asm!("mov {}, x17", out(reg) x17, options(nomem, nostack, preserves_flags));
+            asm!("mov {}, x18", out(reg) x18, options(nomem, nostack, preserves_flags));
+            asm!("mov {}, x19", out(reg) x19, options(nomem, nostack, preserves_flags));
+            asm!("mov {}, x20", out(reg) x20, options(nomem, nostack, preserves_flags));
+            asm!("mov {}, x21", out(reg) x21, options(nomem, nostack, preserves_flags));
+            asm!("mov {}, x22", out(reg) x22, options(nomem, nostack, preserves_flags));
+            asm!("mov {}, x23", out(reg) x23, options(nomem, nostack, preserves_flags));
+            asm!("mov {}, x24", out(reg) x24, options(nomem, nostack, preserves_flags));
+            asm!("mov {}, x25", out(reg) x25, options(nomem, nostack, preserves_flags));
+            asm!("mov {}, x26", out(reg) x26, options(nomem, nostack, preserves_flags));
+            asm!("mov {}, x27", out(reg) x27, options(nomem, nostack, preserves_flags));
+            asm!("mov {}, x28", out(reg) x28, options(nomem, nostack, preserves_flags));
+            asm!("mov {}, x29", out(reg) x29, options(nomem, nostack, preserves_flags));
             [
                 x0, x1, x2, x3, x4, x5, x6, x7, x8, x9, x10, x11, x12, x13, x14, x15, x16, x17,
                 x18, x19, x20, x21, x22, x23, x24, x25, x26, x27, x28, x29,
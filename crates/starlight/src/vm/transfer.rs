@@ -0,0 +1,320 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+//! Copying values between contexts and runtimes.
+//!
+//! Values can not be shared between runtimes since each runtime has its own heap.
+//! [transfer](GcPointer::<Context>::transfer) deep copies a value graph into another context following the
+//! structured clone rules: primitives, strings, plain objects, arrays, `Date`, `ArrayBuffer` and errors are
+//! copied, shared and circular references are kept. Own enumerable string keyed properties are read with
+//! `get`, so getters run in the source context. Functions, symbols and other objects can not be copied and
+//! make the transfer fail with a `TypeError` of the source context.
+//!
+//! ```rust,ignore
+//! let result = worker.context(0).eval("compute()")?;
+//! let result = worker.transfer(result, &mut main)?;
+//! ```
+use super::{
+    array::JsArray,
+    array_buffer::JsArrayBuffer,
+    class::{Class, JsClass},
+    context::Context,
+    error::*,
+    object::{JsObject, ObjectTag, PropertyFilter, TypedJsObject},
+    string::JsString,
+    structure::Structure,
+    symbol_table::Internable,
+    value::JsValue,
+    VirtualMachine,
+};
+use crate::{gc::cell::GcPointer, jsrt::date::JsDate};
+use std::{collections::HashMap, mem::ManuallyDrop};
+
+type ErrorConstructor = fn(
+    GcPointer<Context>,
+    GcPointer<JsString>,
+    Option<GcPointer<Structure>>,
+) -> GcPointer<JsObject>;
+
+impl GcPointer<Context> {
+    /// Deep copy `value` of this context into `target`, which may belong to another runtime.
+    pub fn transfer(
+        mut self,
+        value: JsValue,
+        mut target: GcPointer<Context>,
+    ) -> Result<JsValue, JsValue> {
+        // copies and values returned by getters of the source are only reachable from the stack until the
+        // transfer finishes, getters may allocate in the source heap.
+        let source_defer = self.heap().defer();
+        let target_defer = target.heap().defer();
+        let result = Transfer {
+            source: self,
+            target,
+            copied: HashMap::new(),
+        }
+        .value(value);
+        drop(target_defer);
+        drop(source_defer);
+        result
+    }
+}
+
+impl VirtualMachine {
+    /// Deep copy `value` of the first context of this runtime into the first context of `other`. Both
+    /// runtimes must be used from the calling thread. See [GcPointer::<Context>::transfer].
+    pub fn transfer(
+        &mut self,
+        value: JsValue,
+        other: &mut VirtualMachine,
+    ) -> Result<JsValue, JsValue> {
+        self.context(0).transfer(value, other.context(0))
+    }
+}
+
+struct Transfer {
+    source: GcPointer<Context>,
+    target: GcPointer<Context>,
+    /// Copies of objects by address of the source object.
+    copied: HashMap<usize, JsValue>,
+}
+
+impl Transfer {
+    fn value(&mut self, value: JsValue) -> Result<JsValue, JsValue> {
+        if !value.is_object() {
+            return Ok(value);
+        }
+        if value.is_jsstring() {
+            return Ok(JsValue::new(self.string(value.get_jsstring())));
+        }
+        if value.is_jsobject() {
+            return self.object(value.get_jsobject());
+        }
+        Err(self.error("Symbol"))
+    }
+
+    fn string(&mut self, string: GcPointer<JsString>) -> GcPointer<JsString> {
+        if string.is_well_formed() {
+            JsString::new(self.target, string.as_str())
+        } else {
+            JsString::from_utf16(self.target, &string.code_units().to_vec())
+        }
+    }
+
+    fn object(&mut self, mut object: GcPointer<JsObject>) -> Result<JsValue, JsValue> {
+        let address = &*object as *const JsObject as usize;
+        if let Some(copy) = self.copied.get(&address) {
+            return Ok(*copy);
+        }
+        let mut copy = if object.tag() == ObjectTag::Array {
+            JsArray::new(self.target, object.indexed.length())
+        } else if object.is_class(JsObject::class()) && object.tag() == ObjectTag::Ordinary {
+            JsObject::new_empty(self.target)
+        } else if object.is_class(JsDate::class()) {
+            let structure = self.target.global_data().date_structure.unwrap();
+            let copy = JsObject::new(
+                self.target,
+                &structure,
+                JsDate::class(),
+                ObjectTag::Ordinary,
+            );
+            *copy.data::<JsDate>() = ManuallyDrop::new(**object.data::<JsDate>());
+            copy
+        } else if object.is_class(JsArrayBuffer::class()) {
+            let buffer = TypedJsObject::<JsArrayBuffer>::new(object);
+            let bytes = if buffer.byte_length() == 0 {
+                vec![]
+            } else {
+                buffer.data().to_vec()
+            };
+            match JsArrayBuffer::from_vec(self.target, bytes) {
+                Ok(copy) => copy,
+                Err(_) => return Err(self.error(object.class.name)),
+            }
+        } else if let Some(constructor) = error_constructor(object) {
+            let message = match object.get_own_property(self.source, "message".intern()) {
+                Some(desc) if desc.is_data() && desc.value().is_jsstring() => {
+                    self.string(desc.value().get_jsstring())
+                }
+                _ => JsString::new(self.target, ""),
+            };
+            constructor(self.target, message, None)
+        } else {
+            return Err(self.error(object.class.name));
+        };
+        self.copied.insert(address, JsValue::new(copy));
+
+        for name in object.own_property_names(self.source, PropertyFilter::Enumerable) {
+            let value = object.get(self.source, name)?;
+            let value = self.value(value)?;
+            if copy.put(self.target, name, value, false).is_err() {
+                return Err(self.error(object.class.name));
+            }
+        }
+        Ok(JsValue::new(copy))
+    }
+
+    fn error(&mut self, kind: &str) -> JsValue {
+        JsValue::new(
+            self.source
+                .new_type_error(format!("{} object could not be transferred", kind)),
+        )
+    }
+}
+
+fn error_constructor(object: GcPointer<JsObject>) -> Option<ErrorConstructor> {
+    let constructors: [(&'static Class, ErrorConstructor); 7] = [
+        (JsError::class(), JsError::new),
+        (JsEvalError::class(), JsEvalError::new),
+        (JsRangeError::class(), JsRangeError::new),
+        (JsReferenceError::class(), JsReferenceError::new),
+        (JsSyntaxError::class(), JsSyntaxError::new),
+        (JsTypeError::class(), JsTypeError::new),
+        (JsURIError::class(), JsURIError::new),
+    ];
+    constructors
+        .iter()
+        .find(|(class, _)| object.is_class(class))
+        .map(|(_, constructor)| *constructor)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        options::Options,
+        prelude::*,
+        vm::{context::Context, tests::new_context, VirtualMachine},
+        Platform,
+    };
+
+    #[test]
+    fn test_transfer() {
+        Platform::initialize();
+        let mut worker = Platform::new_runtime(Options::default(), None);
+        let mut main = Platform::new_runtime(Options::default(), None);
+        let mut source = worker.new_context();
+        let mut target = main.new_context();
+
+        let value = source
+            .eval(
+                "var shared = { n: 1 };
+                 var date = new Date(2020, 5, 1);
+                 var graph = { list: [1, 'two', shared], shared, date, time: date.getTime(),
+                               error: new RangeError('bad'), text: 'caf\u{e9}' };
+                 graph.list[4] = 5;
+                 graph.self = graph;
+                 graph",
+            )
+            .ok()
+            .unwrap();
+        let copy = worker.transfer(value, &mut main).ok().unwrap();
+        target
+            .global_object()
+            .put(target, "graph".intern(), copy, false)
+            .ok()
+            .unwrap();
+        let result = target
+            .eval(
+                "[graph.list.length, 3 in graph.list, graph.list[1], graph.list[2] === graph.shared,
+                  graph.self === graph, graph.date.getTime() === graph.time, graph.error instanceof RangeError,
+                  graph.error.message, graph.text, Object.getPrototypeOf(graph) === Object.prototype].join()",
+            )
+            .ok()
+            .unwrap();
+        assert_eq!(
+            result.get_jsstring().as_str(),
+            "5,false,two,true,true,true,true,bad,caf\u{e9},true"
+        );
+
+        let function = source.eval("({ f: function () {} })").ok().unwrap();
+        assert!(worker.transfer(function, &mut main).is_err());
+    }
+
+    #[test]
+    fn test_transfer_between_contexts() {
+        let (mut vm, mut source) = new_context();
+        let mut target = Context::new(&mut vm);
+        let value = source
+            .eval(
+                "var buffer = new ArrayBuffer(4);
+                 new DataView(buffer).setUint8(2, 7);
+                 var shared = [1, 2];
+                 var value = { plain: { a: 1, b: 'b' }, list: [shared, shared], date: new Date(86400000),
+                               buffer: buffer, error: new TypeError('wrong') };
+                 value.list.push(value);
+                 value",
+            )
+            .ok()
+            .unwrap();
+        let copy = source.transfer(value, target).ok().unwrap();
+        target
+            .global_object()
+            .put(target, "copy".intern(), copy, false)
+            .ok()
+            .unwrap();
+        let result = target
+            .eval(
+                "[copy.plain.a + copy.plain.b, copy.list[0] === copy.list[1], copy.list[2] === copy,
+                  copy.list[0].length, copy.date.getTime(), new DataView(copy.buffer).getUint8(2),
+                  copy.buffer.byteLength, copy.error instanceof TypeError, copy.error.message,
+                  Object.getPrototypeOf(copy.plain) === Object.prototype].join()",
+            )
+            .ok()
+            .unwrap();
+        assert_eq!(
+            result.get_jsstring().as_str(),
+            "1b,true,true,2,86400000,7,4,true,wrong,true"
+        );
+
+        for src in [
+            "(function () {})",
+            "Symbol('s')",
+            "({ nested: [function () {}] })",
+            "({ key: Symbol('s') })",
+        ]
+        .iter()
+        {
+            let value = source.eval(src).ok().unwrap();
+            let err = source.transfer(value, target).err().unwrap();
+            let message = err.to_string(source).ok().unwrap();
+            assert!(message.starts_with("TypeError"), "{}: {}", src, message);
+        }
+    }
+
+    #[test]
+    fn test_transfer_between_runtimes() {
+        Platform::initialize();
+        let mut worker = VirtualMachine::new(Options::default(), None);
+        let mut main = VirtualMachine::new(Options::default(), None);
+        let mut source = Context::new(&mut worker);
+        let mut target = Context::new(&mut main);
+        let value = source
+            .eval(
+                "var node = { name: 'node' };
+                 var value = { nodes: [node, node] };
+                 Object.defineProperty(value, 'fresh', {
+                     get: function () { return { list: ['a', 'b'].map(x => x + x) }; },
+                     enumerable: true
+                 });
+                 value",
+            )
+            .ok()
+            .unwrap();
+        // the getter allocates in the source heap while the copy is built.
+        worker.heap().set_stress(true);
+        let copy = worker.transfer(value, &mut main);
+        worker.heap().set_stress(false);
+        let copy = copy.ok().unwrap();
+        target
+            .global_object()
+            .put(target, "copy".intern(), copy, false)
+            .ok()
+            .unwrap();
+        let result = target
+            .eval("[copy.nodes[0] === copy.nodes[1], copy.nodes[0].name, copy.fresh.list.join('')].join()")
+            .ok()
+            .unwrap();
+        assert_eq!(result.get_jsstring().as_str(), "true,node,aabb");
+        worker.verify_heap();
+        main.verify_heap();
+    }
+}
@@ -3,11 +3,11 @@
  * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
 use crate::{gc::cell::*, jsrt::boolean::JsBoolean, vm::interpreter::SpreadValue};
 
+use crate::utils::{likely, unlikely};
 use std::{
     convert::TryFrom,
     hash::{Hash, Hasher},
     hint::unreachable_unchecked,
-    intrinsics::{likely, unlikely},
 };
 
 use super::{
@@ -15,7 +15,9 @@ use super::{
     class::JsClass,
     error::*,
     number::*,
+    numeric::string_to_number,
     object::{JsHint, JsObject, TypedJsObject},
+    operations::equality,
     slot::*,
     string::*,
     symbol_table::*,
@@ -346,40 +348,18 @@ impl JsValue {
         result
     }
 
-    pub fn same_value_impl(lhs: Self, rhs: Self, _zero: bool) -> bool {
-        if lhs.is_number() {
-            if !rhs.is_number() {
-                return false;
-            }
-
-            let lhsn = lhs.get_number();
-            let rhsn = rhs.get_number();
-            if lhsn == rhsn {
-                return true;
-            }
-            return lhsn.is_nan() && rhsn.is_nan();
-        }
-
-        if !lhs.is_object() || !rhs.is_object() {
-            return lhs.get_raw() == rhs.get_raw();
-        }
-        if lhs.is_object()
-            && rhs.is_object()
-            && lhs.get_object().is::<JsString>()
-            && rhs.get_object().is::<JsString>()
-        {
-            return unsafe {
-                lhs.get_object().downcast_unchecked::<JsString>().as_str()
-                    == rhs.get_object().downcast_unchecked::<JsString>().as_str()
-            };
+    pub fn same_value_impl(lhs: Self, rhs: Self, zero: bool) -> bool {
+        if zero {
+            equality::same_value_zero(lhs, rhs)
+        } else {
+            equality::same_value(lhs, rhs)
         }
-        lhs.get_raw() == rhs.get_raw()
     }
     pub fn same_value(x: JsValue, y: JsValue) -> bool {
-        Self::same_value_impl(x, y, false)
+        equality::same_value(x, y)
     }
     pub fn same_value_zero(lhs: Self, rhs: Self) -> bool {
-        Self::same_value_impl(lhs, rhs, true)
+        equality::same_value_zero(lhs, rhs)
     }
     pub fn to_object(self, ctx: GcPointer<Context>) -> Result<GcPointer<JsObject>, JsValue> {
         if self.is_undefined() || self.is_null() {
@@ -423,19 +403,6 @@ impl JsValue {
             Ok(self)
         }
     }
-    fn number_compare(x: f64, y: f64) -> i32 {
-        if x.is_nan() || y.is_nan() {
-            return CMP_UNDEF;
-        }
-        if x == y {
-            return CMP_FALSE;
-        }
-        if x < y {
-            CMP_TRUE
-        } else {
-            CMP_FALSE
-        }
-    }
     pub fn get_string(&self) -> GcPointer<JsString> {
         assert!(self.is_jsstring());
         unsafe { self.get_object().downcast_unchecked() }
@@ -448,76 +415,11 @@ impl JsValue {
     }
 
     pub fn abstract_equal(self, other: JsValue, ctx: GcPointer<Context>) -> Result<bool, JsValue> {
-        let mut lhs = self;
-        let mut rhs = other;
-
-        loop {
-            if likely(lhs.is_int32() && rhs.is_int32()) {
-                return Ok(lhs.get_int32() == rhs.get_int32());
-            }
-            if likely(lhs.is_number() && rhs.is_number()) {
-                return Ok(lhs.get_number() == rhs.get_number());
-            }
-
-            if (lhs.is_undefined() || lhs.is_null()) && (rhs.is_undefined() || rhs.is_null()) {
-                return Ok(true);
-            }
-
-            if lhs.is_jsstring() && rhs.is_jsstring() {
-                return Ok(lhs.get_string().as_str() == rhs.get_string().as_str());
-            }
-
-            if lhs.is_symbol() && rhs.is_symbol() {
-                return Ok(lhs.get_raw() == rhs.get_raw());
-            }
-            if lhs.is_jsobject() && rhs.is_jsobject() {
-                return Ok(lhs.get_raw() == rhs.get_raw());
-            }
-            if lhs.is_number() && rhs.is_jsstring() {
-                rhs = JsValue::new(rhs.to_number(ctx)?);
-                continue;
-            }
-            if lhs.is_jsstring() && rhs.is_number() {
-                lhs = JsValue::new(lhs.to_number(ctx)?);
-                continue;
-            }
-
-            if lhs.is_bool() {
-                lhs = JsValue::new(lhs.to_number(ctx)?);
-                continue;
-            }
-
-            if rhs.is_bool() {
-                rhs = JsValue::new(rhs.to_number(ctx)?);
-                continue;
-            }
-
-            if (lhs.is_jsstring() || lhs.is_number()) && rhs.is_object() {
-                rhs = rhs.to_primitive(ctx, JsHint::None)?;
-                continue;
-            }
-
-            if lhs.is_object() && (rhs.is_jsstring() || rhs.is_number()) {
-                lhs = lhs.to_primitive(ctx, JsHint::None)?;
-                continue;
-            }
-            break Ok(false);
-        }
+        equality::abstract_equal(ctx, self, other)
     }
 
     pub fn strict_equal(self, other: JsValue) -> bool {
-        if likely(self.is_number() && other.is_number()) {
-            return self.get_number() == other.get_number();
-        }
-
-        if !self.is_object() || !other.is_object() {
-            return self.get_raw() == other.get_raw();
-        }
-
-        if self.is_jsstring() && other.is_jsstring() {
-            return self.get_string().as_str() == other.get_string().as_str();
-        }
-        self.get_raw() == other.get_raw()
+        equality::strict_equal(self, other)
     }
     #[inline]
     pub fn compare(
@@ -526,48 +428,7 @@ impl JsValue {
         left_first: bool,
         ctx: GcPointer<Context>,
     ) -> Result<i32, JsValue> {
-        let lhs = self;
-        if likely(lhs.is_number() && rhs.is_number()) {
-            return Ok(Self::number_compare(lhs.get_number(), rhs.get_number()));
-        }
-
-        let px;
-        let py;
-        if left_first {
-            px = lhs.to_primitive(ctx, JsHint::Number)?;
-            py = rhs.to_primitive(ctx, JsHint::Number)?;
-        } else {
-            py = rhs.to_primitive(ctx, JsHint::Number)?;
-            px = lhs.to_primitive(ctx, JsHint::Number)?;
-        }
-        if likely(px.is_number() && py.is_number()) {
-            return Ok(Self::number_compare(px.get_number(), py.get_number()));
-        }
-        if likely(px.is_jsstring() && py.is_jsstring()) {
-            #[inline(never)]
-            fn slow_string_cmp(x: &str, y: &str) -> Result<i32, JsValue> {
-                if x.starts_with(y) {
-                    return Ok(CMP_FALSE);
-                }
-                if y.starts_with(x) {
-                    return Ok(CMP_TRUE);
-                }
-                for (x, y) in x.chars().zip(y.chars()) {
-                    if x != y {
-                        return Ok(if x < y { CMP_TRUE } else { CMP_FALSE });
-                    }
-                }
-                unreachable!()
-            }
-            let x = px.get_string();
-            let y = py.get_string();
-            let (x, y) = (x.as_str(), y.as_str());
-            return slow_string_cmp(x, y);
-        } else {
-            let nx = px.to_number(ctx)?;
-            let ny = py.to_number(ctx)?;
-            Ok(Self::number_compare(nx, ny))
-        }
+        equality::compare(ctx, self, rhs, left_first)
     }
     pub fn compare_left(self, rhs: Self, ctx: GcPointer<Context>) -> Result<i32, JsValue> {
         Self::compare(self, rhs, true, ctx)
@@ -630,12 +491,7 @@ impl JsValue {
             Ok(self.get_int32() as _)
         } else if self.is_object() && self.get_object().is::<JsString>() {
             let s = unsafe { self.get_object().downcast_unchecked::<JsString>() };
-            if let Ok(n) = s.as_str().parse::<i32>() {
-                return Ok(n as f64);
-            }
-            Ok(s.as_str()
-                .parse::<f64>()
-                .unwrap_or_else(|_| f64::from_bits(0x7ff8000000000000)))
+            Ok(string_to_number(s.as_str()))
         } else if self.is_bool() {
             Ok(self.get_bool() as u8 as f64)
         } else if self.is_null() {
@@ -679,7 +535,7 @@ impl JsValue {
 
     pub fn to_string(&self, ctx: GcPointer<Context>) -> Result<String, JsValue> {
         if self.is_number() {
-            Ok(self.get_number().to_string())
+            Ok(number_to_string(self.get_number()))
         } else if self.is_null() {
             Ok("null".to_string())
         } else if self.is_undefined() {
@@ -721,10 +577,16 @@ impl JsValue {
             if n as u32 as f64 == n {
                 return Ok(Symbol::Index(n as u32));
             }
-            return Ok(n.to_string().intern());
+            return Ok(number_to_string(n).intern());
         }
         if self.is_jsstring() {
-            return Ok(self.get_string().as_str().intern());
+            let string = self.get_string();
+            let key = string.as_str();
+            // "1" and 1 name the same property, `a["1"]` has to find elements of arrays.
+            return Ok(match key.parse::<u32>() {
+                Ok(index) if index.to_string() == key => Symbol::Index(index),
+                _ => string.intern(),
+            });
         }
         if self.is_null() {
             return Ok("null".intern());
@@ -821,9 +683,9 @@ impl JsValue {
             if self.is_jsstring() {
                 let str = unsafe { self.get_object().downcast_unchecked::<JsString>() };
 
-                if name == "length".intern() {
+                if name == Symbol::LENGTH {
                     slot.set_1(
-                        JsValue::new(str.len() as i32),
+                        JsValue::new(str.utf16_len() as i32),
                         string_length(),
                         Some(str.as_dyn()),
                     );
@@ -831,16 +693,12 @@ impl JsValue {
                 }
 
                 if let Symbol::Index(index) = name {
-                    if index < str.len() {
-                        let char = str
-                            .as_str()
-                            .chars()
-                            .nth(index as usize)
-                            .map(|x| {
-                                JsValue::encode_object_value(JsString::new(ctx, x.to_string()))
-                            })
-                            .unwrap_or_else(JsValue::encode_undefined_value);
-                        slot.set_1(char, string_indexed(), Some(str.as_dyn()));
+                    if let Some(char) = str.char_at(ctx, index) {
+                        slot.set_1(
+                            JsValue::encode_object_value(char),
+                            string_indexed(),
+                            Some(str.as_dyn()),
+                        );
                         return Ok(slot.value());
                     }
                 }
@@ -894,7 +752,7 @@ impl GcCell for JsValue {}
 
 pub fn print_value(x: JsValue) {
     if x.is_number() {
-        print!("{}", x.get_number())
+        print!("{}", number_to_string(x.get_number()))
     } else if x.is_bool() {
         print!("{}", x.get_bool())
     } else if x.is_undefined() {
@@ -908,6 +766,21 @@ pub fn print_value(x: JsValue) {
     }
 }
 
+/// Format `number` as `Number::toString` does: the shortest digits that read back as the same
+/// double, exponential notation once the decimal exponent leaves `-7..21`, and `0` for both zeroes.
+pub fn number_to_string(number: f64) -> String {
+    if number.is_nan() {
+        return "NaN".to_string();
+    }
+    if number.is_infinite() {
+        return if number > 0.0 { "Infinity" } else { "-Infinity" }.to_string();
+    }
+    if number as i32 as f64 == number {
+        return (number as i32).to_string();
+    }
+    ryu_js::Buffer::new().format(number).to_string()
+}
+
 macro_rules! from_primitive {
     ($($t: ty),*) => {$(
         impl From<$t> for JsValue {
@@ -1026,6 +899,21 @@ mod tests {
         assert!(val.is_number());
         assert!(val.get_number().is_nan());
     }
+
+    #[test]
+    fn test_number_to_string() {
+        assert_eq!(number_to_string(-0.0), "0");
+        assert_eq!(number_to_string(f64::NAN), "NaN");
+        assert_eq!(number_to_string(f64::NEG_INFINITY), "-Infinity");
+        assert_eq!(number_to_string(0.1 + 0.2), "0.30000000000000004");
+        assert_eq!(number_to_string(123e-20), "1.23e-18");
+        assert_eq!(number_to_string(0.000001), "0.000001");
+        assert_eq!(number_to_string(0.0000001), "1e-7");
+        assert_eq!(number_to_string(1e20), "100000000000000000000");
+        assert_eq!(number_to_string(1e21), "1e+21");
+        assert_eq!(number_to_string(-1.5e300), "-1.5e+300");
+        assert_eq!(number_to_string(5e-324), "5e-324");
+    }
 }
 
 pub struct HashValueZero(pub JsValue);
@@ -170,7 +170,8 @@ impl Test {
     ) -> Vec<TestResult> {
         let mut results = Vec::new();
         let start = Instant::now();
-        if self.flags.contains(TestFlags::STRICT) {
+        // Raw tests run exactly as written, without a `"use strict"` prologue.
+        if self.flags.contains(TestFlags::STRICT) && !self.flags.contains(TestFlags::RAW) {
             results.push(self.run_once(harness, true, verbose, vm));
         }
 
@@ -228,11 +229,10 @@ impl Test {
                         error_type: _,
                     }
                 )) {
+            js262::reset();
             let ctx = vm.new_context();
             let res = panic::catch_unwind(AssertUnwindSafe(|| match self.expected_outcome {
                 Outcome::Positive => {
-                    // TODO: implement async and add `harness/doneprintHandle.js` to the includes.
-
                     match self.set_up_env(&harness, strict, ctx) {
                         Ok(_) => {
                             let content = if strict {
@@ -251,7 +251,22 @@ impl Test {
                                 ),
                             };
 
-                            (passed, text)
+                            if passed && self.flags.contains(TestFlags::ASYNC) {
+                                // `$DONE` from `doneprintHandle.js` prints the outcome once promise jobs ran.
+                                js262::run_jobs(ctx);
+                                let output = js262::take_output();
+                                let passed = output
+                                    .iter()
+                                    .any(|line| line == "Test262:AsyncTestComplete");
+                                let text = if output.is_empty() {
+                                    "async test did not call $DONE".to_owned()
+                                } else {
+                                    output.join("\n")
+                                };
+                                (passed, text)
+                            } else {
+                                (passed, text)
+                            }
                         }
                         Err(e) => (false, e),
                     }
@@ -383,6 +398,9 @@ impl Test {
             None,
         );*/
         let _ = js262::init(context).unwrap_or_else(|_| panic!("Failed to create $262 object"));
+        if self.flags.contains(TestFlags::RAW) {
+            return Ok(());
+        }
         context
             .eval_internal(None, false, &harness.assert.as_ref(), false)
             .map_err(|e| {
@@ -400,7 +418,11 @@ impl Test {
                 )
             })?;
 
-        for include in self.includes.iter() {
+        let async_include = self
+            .flags
+            .contains(TestFlags::ASYNC)
+            .then(|| "doneprintHandle.js");
+        for include in self.includes.iter().map(|i| &**i).chain(async_include) {
             context
                 .eval_internal(
                     None,
@@ -1,4 +1,42 @@
 use starlight::{prelude::*, vm::context::Context};
+use std::{cell::RefCell, collections::VecDeque};
+
+type Job = Box<dyn FnOnce(GcPointer<Context>)>;
+
+thread_local! {
+    /// Promise jobs scheduled by the VM, run after the test script by [run_jobs].
+    static JOBS: RefCell<VecDeque<Job>> = RefCell::new(VecDeque::new());
+    /// Lines passed to `print`, async tests report completion through it.
+    static OUTPUT: RefCell<Vec<String>> = RefCell::new(Vec::new());
+}
+
+/// Async scheduler of the VM used by the runner.
+pub fn schedule(job: Job) {
+    JOBS.with(|jobs| jobs.borrow_mut().push_back(job));
+}
+
+/// Run scheduled jobs until the queue is empty.
+pub fn run_jobs(ctx: GcPointer<Context>) {
+    while let Some(job) = JOBS.with(|jobs| jobs.borrow_mut().pop_front()) {
+        job(ctx);
+    }
+}
+
+/// Drop jobs and output left by the previous test.
+pub fn reset() {
+    JOBS.with(|jobs| jobs.borrow_mut().clear());
+    OUTPUT.with(|output| output.borrow_mut().clear());
+}
+
+pub fn take_output() -> Vec<String> {
+    OUTPUT.with(|output| std::mem::take(&mut *output.borrow_mut()))
+}
+
+pub fn print(ctx: GcPointer<Context>, args: &Arguments) -> Result<JsValue, JsValue> {
+    let line = args.at(0).to_string(ctx)?;
+    OUTPUT.with(|output| output.borrow_mut().push(line));
+    Ok(JsValue::encode_undefined_value())
+}
 
 pub fn _262_create_realm(ctx: GcPointer<Context>, _: &Arguments) -> Result<JsValue, JsValue> {
     let new_ctx = ctx.vm().new_context();
@@ -30,6 +68,8 @@ pub fn init(ctx: GcPointer<Context>) -> Result<GcPointer<JsObject>, JsValue> {
     object.put(ctx, "gc".intern(), JsValue::new(gc), false)?;
     object.put(ctx, "global".intern(), JsValue::new(global_object), false)?;
     global_object.put(ctx, "$262".intern(), JsValue::new(object), false)?;
+    let print = JsNativeFunction::new(ctx, "print".intern(), print, 1);
+    global_object.put(ctx, "print".intern(), JsValue::new(print), false)?;
 
     Ok(object)
 }
@@ -462,7 +462,8 @@ fn run_test_suite(verbose: u8, test262_path: &Path, suite: &Path, output: Option
 
     let results = if suite.to_string_lossy().ends_with(".js") {
         let options = Options::default();
-        let vm = VirtualMachine::new(options, None);
+        let vm = VirtualMachine::new(options, None)
+            .with_async_scheduler(Box::new(js262::schedule));
         let test = read_test(&test262_path.join(suite)).expect("could not get the test to run");
 
         if verbose != 0 {
@@ -482,7 +483,8 @@ fn run_test_suite(verbose: u8, test262_path: &Path, suite: &Path, output: Option
             println!("Test suite loaded, starting tests...");
         }
         let options = Options::default();
-        let vm = VirtualMachine::new(options, None);
+        let vm = VirtualMachine::new(options, None)
+            .with_async_scheduler(Box::new(js262::schedule));
         suite.run_main(&harness, verbose, vm)
     };
     show_result(&results);
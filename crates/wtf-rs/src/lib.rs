@@ -1,7 +1,6 @@
 /* This Source Code Form is subject to the terms of the Mozilla Public
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
-#![feature(backtrace)]
 pub mod endian;
 pub mod pure_nan;
 pub mod segmented_vec;
@@ -21,6 +20,10 @@ pub(crate) fn thread_self() -> u64 {
     unsafe {
         libc::pthread_self() as u64
     }
+    #[cfg(target_arch = "wasm32")]
+    {
+        0
+    }
 }
 static mut SINK: usize = 0;
 /// `keep_on_stack!` internally used function. This function just does simple volatile write
@@ -126,3 +126,21 @@ impl StackBounds {
         unsafe { Self::current_thread_stack_bounds_internal() }
     }
 }
+
+#[cfg(target_arch = "wasm32")]
+impl StackBounds {
+    /// The shadow stack in linear memory lies between the end of static data and the start of the heap and
+    /// grows down. Values kept in wasm locals are not visible here.
+    pub fn current_thread_stack_bounds() -> Self {
+        extern "C" {
+            static __data_end: u8;
+            static __heap_base: u8;
+        }
+        unsafe {
+            StackBounds {
+                origin: &__heap_base as *const u8 as *mut u8,
+                bound: &__data_end as *const u8 as *mut u8,
+            }
+        }
+    }
+}